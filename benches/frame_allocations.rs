@@ -0,0 +1,159 @@
+// Counts the heap allocations culling, culling further as software occlusion does, and sorting the draws of a
+// frame make, both when every frame starts from empty buffers, as recording each frame did before it kept
+// `FrameScratch`, and when the buffers are kept from one frame to the next. Then counts filling and submitting a
+// frame's batches of command buffers the same two ways, without calling Vulkan. Only culling and submitting are
+// counted: the rest of recording a frame needs a device. Run it with `cargo bench --bench frame_allocations`.
+
+#[allow(dead_code)]
+#[path = "../src/culling.rs"]
+mod culling;
+#[path = "../src/queue_batches.rs"]
+mod queue_batches;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use ash::vk;
+use ash::vk::Handle;
+
+/// Draws in the simulated scene, about half of which are visible in any frame
+const DRAWS: usize = 10_000;
+const FRAMES: usize = 200;
+
+/// Passes allocations to the system allocator, counting them
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Whether the draw is visible in the frame, with whether it blends and its depth, moving the camera a little
+/// each frame so the visible draws change
+fn test(frame: usize, index: usize) -> Option<(bool, f32)> {
+    let hash = (index as u32 ^ (frame as u32 / 10))
+        .wrapping_mul(2_654_435_761)
+        .rotate_left(13);
+    // Half the draws are visible, an eighth of those blended
+    let visible = hash & 1 == 0;
+    let blend = hash >> 29 == 0;
+    visible.then_some((blend, (hash % 1000) as f32 + frame as f32 * 0.01))
+}
+
+/// Whether the draw is left after culling it further, like a draw software occlusion finds hidden
+fn occluded(frame: usize, index: usize) -> bool {
+    (index + frame / 10).is_multiple_of(7)
+}
+
+/// Batches for the compute, graphics and present queues like a frame graph with particles and a present family of
+/// its own makes, each chained to the last
+fn batches() -> queue_batches::Batches {
+    let mut batches = queue_batches::Batches::new((1..=3).map(vk::Queue::from_raw));
+    batches.chain(
+        0,
+        1,
+        vk::Semaphore::from_raw(1),
+        vk::PipelineStageFlags::DRAW_INDIRECT,
+    );
+    batches.chain(
+        1,
+        2,
+        vk::Semaphore::from_raw(2),
+        vk::PipelineStageFlags::ALL_COMMANDS,
+    );
+    batches
+}
+
+/// Fills the batches with a frame's command buffers, waits, signals and fence, and submits them to nothing
+fn submit(batches: &mut queue_batches::Batches, frame: usize) {
+    // Each frame in flight has its own command buffer, semaphores and fence
+    let handle = |raw: u64| raw + 10 * (frame % 2) as u64;
+    batches.reset();
+    batches.command_buffer(0, vk::CommandBuffer::from_raw(handle(1)));
+    batches.command_buffer(1, vk::CommandBuffer::from_raw(handle(2)));
+    batches.command_buffer(2, vk::CommandBuffer::from_raw(handle(3)));
+    batches.wait(
+        1,
+        vk::Semaphore::from_raw(handle(3)),
+        vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+    );
+    batches.fence(1, vk::Fence::from_raw(handle(1)));
+    batches.signal(2, vk::Semaphore::from_raw(handle(4)));
+    batches
+        .submit(|_, _, _| Ok(()))
+        .expect("Submitting to nothing");
+}
+
+/// Runs the frames, printing the allocations and time they took per frame
+fn measure(name: &str, frames: impl FnOnce()) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    frames();
+    let elapsed = start.elapsed();
+    println!(
+        "  {}: {:.2} allocations, {:.3}ms a frame",
+        name,
+        (ALLOCATIONS.load(Ordering::Relaxed) - allocations) as f32 / FRAMES as f32,
+        elapsed.as_secs_f32() * 1000.0 / FRAMES as f32
+    );
+}
+
+fn main() {
+    println!("Culling and sorting {} draws a frame", DRAWS);
+    measure("new buffers each frame", || {
+        for frame in 1..=FRAMES {
+            let mut culling = culling::Buffers::default();
+            let mut visible = Vec::new();
+            culling.test(DRAWS, |index| test(frame, index));
+            culling.retain(|index| !occluded(frame, index));
+            culling.sort(&mut visible);
+        }
+    });
+
+    let mut culling = culling::Buffers::default();
+    let mut visible = Vec::new();
+    // The first frame grows the buffers to fit the scene
+    culling.test(DRAWS, |index| test(0, index));
+    culling.retain(|index| !occluded(0, index));
+    culling.sort(&mut visible);
+    measure("buffers kept between frames", || {
+        for frame in 1..=FRAMES {
+            culling.test(DRAWS, |index| test(frame, index));
+            culling.retain(|index| !occluded(frame, index));
+            culling.sort(&mut visible);
+        }
+    });
+
+    println!("Submitting a frame's batches");
+    measure("new batches each frame", || {
+        for frame in 1..=FRAMES {
+            submit(&mut batches(), frame);
+        }
+    });
+
+    let mut kept = batches();
+    // The first frame grows the batches' lists to fit a frame
+    submit(&mut kept, 0);
+    measure("batches kept between frames", || {
+        for frame in 1..=FRAMES {
+            submit(&mut kept, frame);
+        }
+    });
+}
@@ -0,0 +1,252 @@
+// Times the phases of preparing a frame that run on the CPU, over a scene of mock draws, so that refactors of
+// them can be checked for regressions. Run it with `cargo bench --bench renderer`, and compare against a baseline
+// with criterion's `--save-baseline` and `--baseline` options. Recording writes mock commands to lists rather
+// than to Vulkan command buffers, so it measures how the renderer splits the draws between its recording jobs,
+// not the driver.
+
+#[allow(dead_code)]
+#[path = "../src/audio.rs"]
+mod audio;
+#[allow(dead_code)]
+#[path = "../src/bounds.rs"]
+mod bounds;
+#[allow(dead_code)]
+#[path = "../src/capsules.rs"]
+mod capsules;
+#[allow(dead_code)]
+#[path = "../src/clipping.rs"]
+mod clipping;
+#[allow(dead_code)]
+#[path = "../src/culling.rs"]
+mod culling;
+#[allow(dead_code)]
+#[path = "../src/ground_shadows.rs"]
+mod ground_shadows;
+// Its tests are built with the bench but not run
+#[allow(dead_code, unused_imports)]
+#[path = "../src/shadows.rs"]
+mod shadows;
+#[allow(dead_code)]
+#[path = "../src/sky.rs"]
+mod sky;
+#[allow(dead_code)]
+#[path = "../src/toon.rs"]
+mod toon;
+#[path = "../src/uniforms.rs"]
+mod uniforms;
+// Its tests are built with the bench but not run
+#[allow(dead_code, unused_imports)]
+#[path = "../src/units.rs"]
+mod units;
+
+use std::mem::MaybeUninit;
+
+use cgmath::{Deg, Matrix4, Point3, SquareMatrix, Vector3};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use rayon::prelude::*;
+
+/// Draws in the mock scene, laid out on a grid around the camera so that about a quarter of them are in view
+const DRAWS: usize = 10_000;
+const GRID: usize = 100;
+
+/// A draw as far as the CPU phases see it
+struct Draw {
+    transform: Matrix4<f32>,
+    bounds: bounds::Bounds,
+    blend: bool,
+    vertex_count: u32,
+}
+
+/// What recording a draw writes, standing in for its Vulkan commands. Only ever written, like a command buffer.
+#[allow(dead_code)]
+enum Command {
+    BindPipeline(bool),
+    PushTransform(Matrix4<f32>),
+    Draw(u32),
+}
+
+fn scene() -> Vec<Draw> {
+    let local =
+        bounds::Aabb::from_points([Point3::new(-0.5, -0.5, -0.5), Point3::new(0.5, 0.5, 0.5)])
+            .expect("Cube has corners");
+    (0..DRAWS)
+        .map(|index| {
+            let (x, z) = ((index % GRID) as f32, (index / GRID) as f32);
+            let transform = Matrix4::from_translation(Vector3::new(
+                (x - GRID as f32 / 2.0) * 2.0,
+                0.0,
+                (z - GRID as f32 / 2.0) * 2.0,
+            )) * Matrix4::from_angle_y(Deg(index as f32 * 7.0));
+            Draw {
+                transform,
+                bounds: bounds::Bounds::new(local),
+                blend: index % 8 == 0,
+                vertex_count: 36,
+            }
+        })
+        .collect()
+}
+
+fn clip() -> Matrix4<f32> {
+    let view = Matrix4::look_at_rh(
+        Point3::new(0.0, 20.0, 0.0),
+        Point3::new(40.0, 0.0, 40.0),
+        Vector3::new(0.0, 1.0, 0.0),
+    );
+    cgmath::perspective(Deg(60.0), 16.0 / 9.0, 0.1, 500.0) * view
+}
+
+/// Tests the draws against the frustum as the renderer's `visible_draws` does, with their depth for sorting
+fn cull(draws: &[Draw], clip: &Matrix4<f32>, buffers: &mut culling::Buffers) {
+    let frustum = bounds::Frustum::new(clip);
+    buffers.test(draws.len(), |index| {
+        let world = &draws[index].bounds.world;
+        if !frustum.intersects(world) {
+            return None;
+        }
+        Some((
+            draws[index].blend,
+            (clip * world.center().to_homogeneous()).w,
+        ))
+    });
+}
+
+fn transform_propagation(c: &mut Criterion) {
+    let mut draws = scene();
+    let model = Matrix4::from_angle_y(Deg(30.0));
+    let mut group = c.benchmark_group("transform propagation");
+    group.throughput(Throughput::Elements(DRAWS as u64));
+    group.bench_function("update bounds", |b| {
+        b.iter(|| {
+            draws.par_iter_mut().for_each(|draw| {
+                let transform = black_box(model) * draw.transform;
+                draw.bounds.update(&transform);
+            })
+        })
+    });
+    group.finish();
+}
+
+fn frustum_culling(c: &mut Criterion) {
+    let mut draws = scene();
+    for draw in draws.iter_mut() {
+        draw.bounds.update(&draw.transform);
+    }
+    let clip = clip();
+    let mut buffers = culling::Buffers::default();
+    let mut group = c.benchmark_group("frustum culling");
+    group.throughput(Throughput::Elements(DRAWS as u64));
+    group.bench_function("test draws", |b| {
+        b.iter(|| cull(&draws, black_box(&clip), &mut buffers))
+    });
+    group.finish();
+}
+
+fn draw_list_building(c: &mut Criterion) {
+    let mut draws = scene();
+    for draw in draws.iter_mut() {
+        draw.bounds.update(&draw.transform);
+    }
+    let clip = clip();
+    let frustum = bounds::Frustum::new(&clip);
+    // The draws' visibility and depth, so that only keeping and sorting them is timed
+    let tested: Vec<_> = draws
+        .iter()
+        .map(|draw| {
+            let world = &draw.bounds.world;
+            frustum
+                .intersects(world)
+                .then(|| (draw.blend, (clip * world.center().to_homogeneous()).w))
+        })
+        .collect();
+    let mut buffers = culling::Buffers::default();
+    let mut visible = Vec::new();
+    let mut group = c.benchmark_group("draw list building");
+    group.throughput(Throughput::Elements(DRAWS as u64));
+    group.bench_function("keep and sort", |b| {
+        b.iter(|| {
+            buffers.test(DRAWS, |index| tested[index]);
+            buffers.sort(&mut visible);
+        })
+    });
+    group.finish();
+}
+
+fn uniform_packing(c: &mut Criterion) {
+    let mut clipping = clipping::Clipping::default();
+    for axis in 0..clipping::MAX_PLANES {
+        let mut normal = Vector3::new(0.0, 0.0, 0.0);
+        normal[axis % 3] = 1.0;
+        clipping
+            .planes
+            .push(clipping::Plane::through(Point3::new(0.0, 0.0, 0.0), normal));
+    }
+    // Stands in for the uniform buffer's mapped memory
+    let mut mapped = Box::new(MaybeUninit::<uniforms::UniformBufferObject>::uninit());
+    c.bench_function("uniform packing", |b| {
+        b.iter(|| {
+            let ubo = uniforms::UniformBufferObject {
+                model: Matrix4::identity(),
+                view: black_box(Matrix4::identity()),
+                perspective: clip(),
+                audio_bands: [0.0; audio::BAND_COUNT],
+                clipping: black_box(&clipping).uniforms(),
+                light: sky::Light::default().uniforms(),
+                capsules: capsules::CapsuleShadows::default()
+                    .uniforms(std::iter::empty(), Point3::new(0.0, 0.0, 0.0)),
+                ground_shadows: ground_shadows::GroundShadows::default().uniforms(),
+                outlines: toon::Outlines::default().uniforms(1280, 720),
+                shadows: shadows::Uniforms::off(),
+            };
+            unsafe { ubo.write(mapped.as_mut_ptr().cast()) };
+        })
+    });
+}
+
+fn command_recording(c: &mut Criterion) {
+    let mut draws = scene();
+    for draw in draws.iter_mut() {
+        draw.bounds.update(&draw.transform);
+    }
+    let clip = clip();
+    let mut buffers = culling::Buffers::default();
+    let mut visible = Vec::new();
+    cull(&draws, &clip, &mut buffers);
+    buffers.sort(&mut visible);
+    // One list per recording job, as the renderer has a secondary command buffer per thread
+    let mut lists: Vec<Vec<Command>> = (0..rayon::current_num_threads())
+        .map(|_| Vec::new())
+        .collect();
+    let mut group = c.benchmark_group("command recording");
+    group.throughput(Throughput::Elements(visible.len() as u64));
+    group.bench_function("record visible draws", |b| {
+        b.iter(|| {
+            // Split as `record_command_buffer` splits the visible draws between its secondary buffers
+            let chunk_size = visible.len().div_ceil(lists.len()).max(1);
+            lists
+                .par_iter_mut()
+                .enumerate()
+                .for_each(|(chunk, commands)| {
+                    commands.clear();
+                    for &index in visible.chunks(chunk_size).nth(chunk).unwrap_or(&[]) {
+                        let draw = &draws[index];
+                        commands.push(Command::BindPipeline(draw.blend));
+                        commands.push(Command::PushTransform(draw.transform));
+                        commands.push(Command::Draw(draw.vertex_count));
+                    }
+                });
+            black_box(&lists);
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    draw_list_building,
+    frustum_culling,
+    transform_propagation,
+    uniform_packing,
+    command_recording
+);
+criterion_main!(benches);
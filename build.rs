@@ -38,12 +38,15 @@ fn main() {
             .expect("shader file name")
             .to_str()
             .expect("shader file name invalid unicode");
-        let shader_kind = if file_name.contains("vert") {
-            ShaderKind::Vertex
-        } else if file_name.contains("frag") {
-            ShaderKind::Fragment
-        } else {
-            panic!("Unrecognised shader kind {}", file_name)
+        let split_name = file_name.split(".").collect::<Vec<&str>>();
+        let base = split_name[0];
+        // The stage is the name's last word, as in `vert.glsl` or `cull_comp.glsl`, which other words of the name
+        // like `cloth_vertices_comp.glsl`'s `vertices` don't change
+        let shader_kind = match base.rsplit('_').next() {
+            Some("vert") => ShaderKind::Vertex,
+            Some("frag") => ShaderKind::Fragment,
+            Some("comp") => ShaderKind::Compute,
+            _ => panic!("Unrecognised shader kind {}", file_name),
         };
 
         let binary_result = compiler.compile_into_spirv(
@@ -53,8 +56,6 @@ fn main() {
             "main",
             Some(&options),
         );
-        let split_name = file_name.split(".").collect::<Vec<&str>>();
-        let base = split_name[0];
         match binary_result {
             Ok(artifact) => {
                 println!("Compiled {} as {:?}", file_name, shader_kind);
@@ -0,0 +1,107 @@
+// Frame update logic for `--logic`, spinning the first draw about its Z axis. Space pauses it, and Page Up and Page
+// Down change its speed. Build it with `cargo build --example spin_logic` and run the renderer with
+// `cargo run --features hot-reload -- --logic target/debug/examples/libspin_logic.so`, or `spin_logic.dll` on
+// Windows. Change it and build it again while the renderer runs to reload it, keeping its speed.
+
+#[allow(dead_code)]
+#[path = "../src/logic.rs"]
+mod logic;
+
+use std::ffi::c_void;
+use std::{mem, slice};
+
+use cgmath::{Deg, Matrix4};
+use winit::event::VirtualKeyCode;
+
+/// Degrees a second the draw spins at when the library is first loaded
+const SPEED: f32 = 90.0;
+
+struct Spin {
+    speed: f32,
+    paused: bool,
+}
+
+impl Spin {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        match *bytes {
+            [a, b, c, d, paused] => Self {
+                speed: f32::from_le_bytes([a, b, c, d]),
+                paused: paused != 0,
+            },
+            _ => Self {
+                speed: SPEED,
+                paused: false,
+            },
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.speed.to_le_bytes().to_vec();
+        bytes.push(self.paused as u8);
+        bytes
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn logic_version() -> u32 {
+    logic::VERSION
+}
+
+/// # Safety
+///
+/// `saved` must point to `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn logic_load(saved: *const u8, len: usize) -> *mut c_void {
+    let spin = Spin::from_bytes(slice::from_raw_parts(saved, len));
+    Box::into_raw(Box::new(spin)) as *mut c_void
+}
+
+/// # Safety
+///
+/// `state` must have come from `logic_load`, and `frame` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn logic_update(state: *mut c_void, frame: *mut logic::Frame) {
+    let spin = &mut *(state as *mut Spin);
+    let frame = &mut *frame;
+    for &key in slice::from_raw_parts(frame.keys, frame.key_count) {
+        if key == VirtualKeyCode::Space as u32 {
+            spin.paused = !spin.paused;
+        }
+        if key == VirtualKeyCode::PageUp as u32 {
+            spin.speed *= 2.0;
+        }
+        if key == VirtualKeyCode::PageDown as u32 {
+            spin.speed /= 2.0;
+        }
+    }
+    if spin.paused || frame.draw_count == 0 {
+        return;
+    }
+    let transform = &mut *frame.transforms;
+    let spun = Matrix4::from(*transform) * Matrix4::from_angle_z(Deg(spin.speed * frame.delta));
+    *transform = spun.into();
+}
+
+/// # Safety
+///
+/// `state` must have come from `logic_load`, and isn't used after.
+#[no_mangle]
+pub unsafe extern "C" fn logic_save(state: *mut c_void) -> logic::Saved {
+    let spin = Box::from_raw(state as *mut Spin);
+    let mut bytes = spin.to_bytes();
+    let saved = logic::Saved {
+        data: bytes.as_mut_ptr(),
+        len: bytes.len(),
+        capacity: bytes.capacity(),
+    };
+    mem::forget(bytes);
+    saved
+}
+
+/// # Safety
+///
+/// `saved` must have come from `logic_save`.
+#[no_mangle]
+pub unsafe extern "C" fn logic_free(saved: logic::Saved) {
+    drop(Vec::from_raw_parts(saved.data, saved.len, saved.capacity));
+}
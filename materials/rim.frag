@@ -0,0 +1,66 @@
+#version 450
+
+// An example shader material, loaded with `shader load rim materials/rim.frag` and applied with `shade <draw> rim`.
+// The rim's colour, width and the pattern it's tinted by are parameters, e.g. `shader rim tint 1.0 0.4 0.1 1.0`.
+
+// Matches the built in fragment shader's, set when the swapchain needs colours encoded to sRGB by hand
+layout(constant_id = 4) const bool ENCODE_SRGB = false;
+
+vec3 encodeSrgb(vec3 linear) {
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+}
+
+// Only as much of the frame's uniforms as the material reads
+layout(set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+    vec4 audioBands[4];
+    vec4 clipPlanes[4];
+    vec4 clipCapColor;
+    uvec4 clipFlags;
+    vec4 lightDirection;
+    vec4 lightColor;
+    vec4 ambientColor;
+} ubo;
+
+layout(set = 0, binding = 1) uniform sampler2D texSampler;
+
+// The material's parameters
+layout(set = 2, binding = 0) uniform Parameters {
+    vec4 tint;
+    float width;
+    float strength;
+} parameters;
+
+layout(set = 2, binding = 1) uniform sampler2D pattern;
+
+layout(location = 0) in vec3 fragColor;
+layout(location = 1) in vec2 fragTexCoord;
+layout(location = 2) in vec3 fragNormal;
+layout(location = 5) in vec3 fragScenePosition;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    vec3 normal = normalize(fragNormal);
+    vec3 position = (ubo.model * vec4(fragScenePosition, 1.0)).xyz;
+    vec3 camera = inverse(ubo.view)[3].xyz;
+    vec3 towardsCamera = normalize(camera - position);
+
+    float diffuse = max(dot(normal, normalize(ubo.lightDirection.xyz)), 0.0);
+    vec3 base = texture(texSampler, fragTexCoord).rgb;
+    vec3 lit = base * (ubo.ambientColor.rgb + ubo.lightColor.rgb * diffuse);
+
+    // The rim is where the surface turns away from the camera, zero width for none
+    float facing = 1.0 - max(dot(normal, towardsCamera), 0.0);
+    float rim = smoothstep(1.0 - max(parameters.width, 1e-4), 1.0, facing) * parameters.strength;
+    vec3 rimColor = parameters.tint.rgb * texture(pattern, fragTexCoord).rgb;
+
+    outColor = vec4(lit + rimColor * rim, 1.0);
+    if (ENCODE_SRGB) {
+        outColor.rgb = encodeSrgb(outColor.rgb);
+    }
+}
@@ -0,0 +1,14 @@
+// An example sketch for playground mode: cargo run -- --shadertoy shadertoy/plasma.glsl
+// Edit and save it while it is running to see the changes. Drag with the left mouse button to move the centre.
+
+void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    vec2 center = iMouse.z > 0.0 ? iMouse.xy : iResolution.xy * 0.5;
+    vec2 uv = (fragCoord - center) / iResolution.y;
+
+    float value = sin(uv.x * 10.0 + iTime)
+        + sin(uv.y * 10.0 + iTime * 1.3)
+        + sin(length(uv) * 12.0 - iTime * 2.0);
+    vec3 color = 0.5 + 0.5 * cos(value + vec3(0.0, 2.0, 4.0));
+
+    fragColor = vec4(color * texture(iChannel0, uv + 0.5).rgb, 1.0);
+}
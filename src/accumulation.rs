@@ -0,0 +1,565 @@
+use std::any::Any;
+use std::mem::size_of;
+use std::path::Path;
+
+use ash::vk;
+use cgmath::{Matrix4, Vector3};
+
+use crate::features::{RenderFeature, SwapchainContext};
+use crate::{framegraph, hooks, postprocess, util, HelloTriangleApplication, ImageDescription};
+
+/// Frames averaged by default before the image is left as it is
+pub const DEFAULT_SAMPLES: u32 = 256;
+
+/// What a frame was drawn with. Frames are only averaged together while they're all drawn with the same.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Key {
+    pub view: Matrix4<f32>,
+    /// Without the jitter
+    pub projection: Matrix4<f32>,
+    pub model: Matrix4<f32>,
+    pub scene: usize,
+    /// Each draw's transform
+    pub transforms: Vec<Matrix4<f32>>,
+}
+
+/// Matches the push constants in `accumulate_comp.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Blend {
+    weight: f32,
+    encoded: u32,
+}
+
+impl Blend {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+}
+
+/// What accumulation needs for each swapchain, recreated with it
+struct Resources {
+    extent: vk::Extent2D,
+    /// Whether the swapchain's colour is sRGB encoded in the frame image, rather than decoded by the blit
+    encoded: bool,
+    /// The frame is blitted out of the swapchain image into this storage image to be averaged, then back
+    frame_image: (vk::Image, vk::DeviceMemory, vk::ImageView),
+    /// The average of the frames so far in linear colour, kept in the `GENERAL` layout once it has been used
+    accumulated_image: (vk::Image, vk::DeviceMemory, vk::ImageView),
+    /// Whether the accumulated image has been moved out of the undefined layout it's created in
+    initialized: bool,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+}
+
+/// Progressive rendering of still scenes: each frame is drawn with the projection jittered by a different
+/// sub-pixel offset, and averaged before post processing into a float image with the frames drawn before it,
+/// so edges and fine detail converge to a super-sampled image over successive frames. The average is shown in
+/// place of the frame, until `max_samples` frames have been averaged, after which the converged image is shown
+/// without drawing more into it.
+///
+/// Averaging starts again from the next frame whenever what's drawn changes, see `Key`, along with anything
+/// else that calls `reset`, such as edits. Changes the key can't see, like animated materials or the time of
+/// day, blend into the average rather than starting it again.
+pub struct Accumulation {
+    pub enabled: bool,
+    /// The most frames averaged, at least 1
+    pub max_samples: u32,
+    /// Frames averaged so far
+    samples: u32,
+    /// What the averaged frames were drawn with
+    key: Option<Key>,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+    /// Created with the swapchain, if its images can be copied
+    resources: Option<Resources>,
+}
+
+impl Accumulation {
+    pub fn new(device: &ash::Device, max_samples: u32) -> Self {
+        let binding = |binding: u32| {
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding)
+                .descriptor_count(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build()
+        };
+        let bindings = [binding(0), binding(1)];
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+                    None,
+                )
+                .expect("Accumulation descriptor set layout")
+        };
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(size_of::<Blend>() as u32)
+            .build()];
+        let set_layouts = [descriptor_set_layout];
+        let layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::builder()
+                        .set_layouts(&set_layouts)
+                        .push_constant_ranges(&push_constant_ranges),
+                    None,
+                )
+                .expect("Accumulation pipeline layout")
+        };
+        let path = Path::new(env!("OUT_DIR")).join("accumulate_comp.spv");
+        let pipeline =
+            postprocess::create_compute_pipeline(device, layout, &util::read_shader_code(&path));
+
+        Self {
+            enabled: true,
+            max_samples: max_samples.max(1),
+            samples: 0,
+            key: None,
+            descriptor_set_layout,
+            layout,
+            pipeline,
+            resources: None,
+        }
+    }
+
+    /// Starts averaging again from the next frame
+    pub fn reset(&mut self) {
+        self.samples = 0;
+    }
+
+    /// Frames averaged into the image shown so far
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    pub fn converged(&self) -> bool {
+        self.samples >= self.max_samples
+    }
+
+    /// Starts averaging again if the next frame is drawn with something other than the frames before it
+    pub fn track(&mut self, key: Key) {
+        if self.key.as_ref() != Some(&key) {
+            self.reset();
+            self.key = Some(key);
+        }
+    }
+
+    /// The next frame's offset in pixels, each from -0.5 to 0.5, or `None` when no more frames are averaged.
+    /// Offsets follow the Halton sequence in bases 2 and 3, which covers the pixel evenly however many frames are
+    /// averaged, starting from its centre.
+    pub fn jitter(&self) -> Option<[f32; 2]> {
+        if !self.enabled || self.converged() {
+            return None;
+        }
+        let index = self.samples + 1;
+        Some([halton(index, 2) - 0.5, halton(index, 3) - 0.5])
+    }
+
+    fn create_resources(&mut self, context: &SwapchainContext) {
+        if !context.usage.contains(postprocess::SWAPCHAIN_USAGE) {
+            return;
+        }
+        let device = context.device;
+        let extent = context.extent;
+        let create_storage_image = |format: vk::Format, usage: vk::ImageUsageFlags| {
+            let (image, memory) = HelloTriangleApplication::create_image(
+                device,
+                &ImageDescription {
+                    width: extent.width,
+                    height: extent.height,
+                    format,
+                    tiling: vk::ImageTiling::OPTIMAL,
+                    usage: vk::ImageUsageFlags::STORAGE | usage,
+                    memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                },
+                context.device_memory_properties,
+            );
+            let view = HelloTriangleApplication::create_image_view(
+                device,
+                image,
+                format,
+                vk::ImageAspectFlags::COLOR,
+            );
+            (image, memory, view)
+        };
+        let frame_image = create_storage_image(
+            vk::Format::R16G16B16A16_SFLOAT,
+            vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST,
+        );
+        // Full floats, so that a small weight still moves the average after hundreds of frames
+        let accumulated_image = create_storage_image(
+            vk::Format::R32G32B32A32_SFLOAT,
+            vk::ImageUsageFlags::empty(),
+        );
+
+        let pool_sizes = [vk::DescriptorPoolSize::builder()
+            .ty(vk::DescriptorType::STORAGE_IMAGE)
+            .descriptor_count(2)
+            .build()];
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::builder()
+                        .pool_sizes(&pool_sizes)
+                        .max_sets(1),
+                    None,
+                )
+                .expect("Accumulation descriptor pool")
+        };
+        let set_layouts = [self.descriptor_set_layout];
+        let descriptor_set = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::builder()
+                        .descriptor_pool(descriptor_pool)
+                        .set_layouts(&set_layouts),
+                )
+                .expect("Accumulation descriptor set")[0]
+        };
+        let image_infos = [frame_image.2, accumulated_image.2].map(|view| {
+            [vk::DescriptorImageInfo::builder()
+                .image_layout(vk::ImageLayout::GENERAL)
+                .image_view(view)
+                .build()]
+        });
+        let writes: Vec<vk::WriteDescriptorSet> = image_infos
+            .iter()
+            .enumerate()
+            .map(|(binding, image_info)| {
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(binding as u32)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                    .image_info(image_info)
+                    .build()
+            })
+            .collect();
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+
+        self.resources = Some(Resources {
+            extent,
+            encoded: !util::is_srgb_format(context.format),
+            frame_image,
+            accumulated_image,
+            initialized: false,
+            descriptor_pool,
+            descriptor_set,
+        });
+    }
+
+    fn destroy_resources(&mut self, device: &ash::Device) {
+        if let Some(resources) = self.resources.take() {
+            unsafe {
+                device.destroy_descriptor_pool(resources.descriptor_pool, None);
+                for &(image, memory, view) in
+                    [resources.frame_image, resources.accumulated_image].iter()
+                {
+                    device.destroy_image_view(view, None);
+                    device.destroy_image(image, None);
+                    device.free_memory(memory, None);
+                }
+            }
+        }
+    }
+
+    /// Records averaging the frame into the accumulated image, with `weight` the frame's share of the average,
+    /// and replacing the frame with the average. The swapchain image is ready to present again afterwards.
+    fn record_blend(&self, context: &hooks::FrameContext, resources: &Resources, weight: f32) {
+        let device = context.device;
+        let command_buffer = context.command_buffer;
+        let extent = resources.extent;
+        let (frame_image, _, _) = resources.frame_image;
+        let (accumulated_image, _, _) = resources.accumulated_image;
+        let image_barrier = |image: vk::Image,
+                             old: vk::ImageLayout,
+                             new: vk::ImageLayout,
+                             src_access: vk::AccessFlags,
+                             dst_access: vk::AccessFlags| {
+            vk::ImageMemoryBarrier::builder()
+                .old_layout(old)
+                .new_layout(new)
+                .src_access_mask(src_access)
+                .dst_access_mask(dst_access)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(
+                    vk::ImageSubresourceRange::builder()
+                        .aspect_mask(vk::ImageAspectFlags::COLOR)
+                        .base_mip_level(0)
+                        .level_count(1)
+                        .base_array_layer(0)
+                        .layer_count(1)
+                        .build(),
+                )
+                .build()
+        };
+        let color_layers = vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let full_image = [
+            vk::Offset3D { x: 0, y: 0, z: 0 },
+            vk::Offset3D {
+                x: extent.width as i32,
+                y: extent.height as i32,
+                z: 1,
+            },
+        ];
+        let blit = vk::ImageBlit::builder()
+            .src_subresource(color_layers)
+            .src_offsets(full_image)
+            .dst_subresource(color_layers)
+            .dst_offsets(full_image)
+            .build();
+        let accumulated_layout = match resources.initialized {
+            true => vk::ImageLayout::GENERAL,
+            false => vk::ImageLayout::UNDEFINED,
+        };
+        let groups = |count: u32| count.div_ceil(postprocess::WORKGROUP_SIZE);
+        let blend = Blend {
+            weight,
+            encoded: resources.encoded as u32,
+        };
+
+        unsafe {
+            // The previous frame's blend may still be reading the frame image
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::COMPUTE_SHADER
+                    | vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[
+                    image_barrier(
+                        context.swapchain_image,
+                        vk::ImageLayout::PRESENT_SRC_KHR,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                        vk::AccessFlags::TRANSFER_READ,
+                    ),
+                    image_barrier(
+                        frame_image,
+                        vk::ImageLayout::UNDEFINED,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::AccessFlags::empty(),
+                        vk::AccessFlags::TRANSFER_WRITE,
+                    ),
+                ],
+            );
+            device.cmd_blit_image(
+                command_buffer,
+                context.swapchain_image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                frame_image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[blit],
+                vk::Filter::NEAREST,
+            );
+            // The previous frame's blend wrote the accumulated image this one reads
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[
+                    image_barrier(
+                        frame_image,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::ImageLayout::GENERAL,
+                        vk::AccessFlags::TRANSFER_WRITE,
+                        vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+                    ),
+                    image_barrier(
+                        accumulated_image,
+                        accumulated_layout,
+                        vk::ImageLayout::GENERAL,
+                        vk::AccessFlags::SHADER_WRITE,
+                        vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+                    ),
+                ],
+            );
+
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.layout,
+                0,
+                &[resources.descriptor_set],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                self.layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                blend.as_bytes(),
+            );
+            device.cmd_dispatch(
+                command_buffer,
+                groups(extent.width),
+                groups(extent.height),
+                1,
+            );
+
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[
+                    image_barrier(
+                        frame_image,
+                        vk::ImageLayout::GENERAL,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        vk::AccessFlags::SHADER_WRITE,
+                        vk::AccessFlags::TRANSFER_READ,
+                    ),
+                    image_barrier(
+                        context.swapchain_image,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::AccessFlags::TRANSFER_READ,
+                        vk::AccessFlags::TRANSFER_WRITE,
+                    ),
+                ],
+            );
+            device.cmd_blit_image(
+                command_buffer,
+                frame_image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                context.swapchain_image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[blit],
+                vk::Filter::NEAREST,
+            );
+            // Post processing and presentation read the frame next
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[image_barrier(
+                    context.swapchain_image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::PRESENT_SRC_KHR,
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    vk::AccessFlags::TRANSFER_READ,
+                )],
+            );
+        }
+    }
+}
+
+/// The `index`th number of the Halton sequence in `base`, from 0 to 1
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+/// The projection moved across the screen by `offset` pixels of a frame `extent` in size. Applied after any
+/// prerotation, so the offset is in the swapchain image's pixels.
+pub fn jittered(projection: Matrix4<f32>, offset: [f32; 2], extent: vk::Extent2D) -> Matrix4<f32> {
+    let [x, y] = offset;
+    let translation = Vector3::new(
+        2.0 * x / extent.width as f32,
+        2.0 * y / extent.height as f32,
+        0.0,
+    );
+    Matrix4::from_translation(translation) * projection
+}
+
+impl RenderFeature for Accumulation {
+    fn name(&self) -> &str {
+        "accumulation"
+    }
+
+    fn init(&mut self, context: &SwapchainContext) {
+        if !context.usage.contains(postprocess::SWAPCHAIN_USAGE) {
+            println!(
+                "The surface doesn't support copying swapchain images, accumulation is disabled"
+            );
+        }
+        self.create_resources(context);
+    }
+
+    fn resize(&mut self, context: &SwapchainContext) {
+        self.destroy_resources(context.device);
+        self.create_resources(context);
+        self.reset();
+    }
+
+    fn record(&mut self, context: &hooks::FrameContext) {
+        if context.stage != hooks::Stage::BeforePost || !self.enabled {
+            return;
+        }
+        let converged = self.converged();
+        // The first frame replaces whatever was averaged before, and once converged frames aren't averaged in
+        let weight = match converged {
+            true => 0.0,
+            false => 1.0 / (self.samples + 1) as f32,
+        };
+        if let Some(resources) = self.resources.as_ref() {
+            self.record_blend(context, resources, weight);
+        }
+        if let Some(resources) = self.resources.as_mut() {
+            resources.initialized = true;
+            if !converged {
+                self.samples += 1;
+            }
+        }
+    }
+
+    fn passes(&self) -> Vec<framegraph::Pass> {
+        if !self.enabled {
+            return Vec::new();
+        }
+        vec![framegraph::Pass::new(
+            "accumulate",
+            framegraph::Position::Stage(hooks::Stage::BeforePost),
+            &[framegraph::SWAPCHAIN, "accumulated frames"],
+            &[framegraph::SWAPCHAIN, "accumulated frames"],
+        )]
+    }
+
+    fn destroy(&mut self, device: &ash::Device) {
+        self.destroy_resources(device);
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
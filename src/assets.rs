@@ -0,0 +1,241 @@
+use std::sync::Arc;
+
+use ash::vk;
+
+use crate::{bounds, memory, mesh, raycast};
+
+/// GPU resources waiting to be destroyed once the frames that might still use them have finished
+#[derive(Clone, Copy, Debug)]
+pub enum Resource {
+    Buffer(vk::Buffer, vk::DeviceMemory),
+    Image(vk::Image, vk::DeviceMemory, vk::ImageView),
+}
+
+impl Resource {
+    fn destroy(self, device: &ash::Device) {
+        unsafe {
+            match self {
+                Resource::Buffer(buffer, memory) => {
+                    device.destroy_buffer(buffer, None);
+                    device.free_memory(memory, None);
+                }
+                Resource::Image(image, memory, view) => {
+                    device.destroy_image_view(view, None);
+                    device.destroy_image(image, None);
+                    device.free_memory(memory, None);
+                }
+            }
+        }
+    }
+}
+
+/// Destroys resources a number of frames after they are retired, once every frame in flight that could have
+/// recorded them has finished executing.
+pub struct DeletionQueue {
+    /// Frames in flight at once, so how many frames a retired resource must wait
+    frames_in_flight: u64,
+    frame: u64,
+    pending: Vec<(u64, Resource)>,
+}
+
+impl DeletionQueue {
+    pub fn new(frames_in_flight: usize) -> Self {
+        Self {
+            frames_in_flight: frames_in_flight as u64,
+            frame: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Queues the resource to be destroyed. Frames recorded from now on mustn't use it.
+    pub fn retire(&mut self, resource: Resource) {
+        self.pending.push((self.frame, resource));
+    }
+
+    /// Moves on to the next frame, destroying the resources that no frame in flight can be using any more. Must
+    /// be called once per frame after waiting for the frame's fence, before its commands are recorded.
+    pub fn next_frame(&mut self, device: &ash::Device) {
+        self.frame += 1;
+        let (frame, frames_in_flight) = (self.frame, self.frames_in_flight);
+        self.pending.retain(|&(retired, resource)| {
+            let finished = retired + frames_in_flight <= frame;
+            if finished {
+                resource.destroy(device);
+            }
+            !finished
+        });
+    }
+
+    /// Destroys everything still queued. The device must be idle.
+    pub fn destroy(&mut self, device: &ash::Device) {
+        for (_, resource) in self.pending.drain(..) {
+            resource.destroy(device);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct MeshData {
+    vertex_buffer: vk::Buffer,
+    index_buffer: vk::Buffer,
+    index_count: u32,
+    attributes: mesh::VertexAttributes,
+    bounds: Option<bounds::Aabb>,
+    collider: Option<Arc<raycast::Bvh>>,
+}
+
+/// A mesh uploaded with `HelloTriangleApplication::load_mesh`. Its buffers live as long as any handle to it, or
+/// draw of it, and are freed by the garbage collection pass after the last is dropped.
+#[derive(Clone, Debug)]
+pub struct Mesh(Arc<MeshData>);
+
+impl Mesh {
+    pub fn vertex_buffer(&self) -> vk::Buffer {
+        self.0.vertex_buffer
+    }
+
+    pub fn index_buffer(&self) -> vk::Buffer {
+        self.0.index_buffer
+    }
+
+    pub fn index_count(&self) -> u32 {
+        self.0.index_count
+    }
+
+    pub fn attributes(&self) -> mesh::VertexAttributes {
+        self.0.attributes
+    }
+
+    /// The mesh-local bounds of its vertices
+    pub fn bounds(&self) -> Option<bounds::Aabb> {
+        self.0.bounds
+    }
+
+    pub fn collider(&self) -> Option<Arc<raycast::Bvh>> {
+        self.0.collider.clone()
+    }
+}
+
+#[derive(Debug)]
+struct TextureData {
+    view: vk::ImageView,
+    extent: vk::Extent2D,
+}
+
+/// A texture loaded with `HelloTriangleApplication::load_texture`, in the `SHADER_READ_ONLY_OPTIMAL` layout.
+/// Its image lives as long as any handle to it, and is freed by the garbage collection pass after the last is
+/// dropped.
+#[derive(Clone, Debug)]
+pub struct Texture(Arc<TextureData>);
+
+impl Texture {
+    pub fn view(&self) -> vk::ImageView {
+        self.0.view
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.0.extent
+    }
+}
+
+/// Every mesh and texture loaded as an asset, along with the resources to free once it is no longer used.
+/// Materials aren't tracked: they hold no resources of their own, and the pipeline variants they map onto are
+/// shared by every draw and kept.
+#[derive(Default)]
+pub struct Assets {
+    meshes: Vec<(Arc<MeshData>, [Resource; 2])>,
+    /// Along with the device memory each texture takes, which is returned to the texture budget when it's freed
+    textures: Vec<(Arc<TextureData>, Resource, memory::Allocation)>,
+}
+
+impl Assets {
+    pub fn add_mesh(
+        &mut self,
+        mesh: &mesh::MeshData,
+        vertex_buffer: (vk::Buffer, vk::DeviceMemory),
+        index_buffer: (vk::Buffer, vk::DeviceMemory),
+    ) -> Mesh {
+        let positions: Vec<cgmath::Point3<f32>> = mesh
+            .positions
+            .iter()
+            .map(|&position| cgmath::Point3::from(position))
+            .collect();
+        let data = Arc::new(MeshData {
+            vertex_buffer: vertex_buffer.0,
+            index_buffer: index_buffer.0,
+            index_count: mesh.indices.len() as u32,
+            attributes: mesh.attributes(),
+            bounds: mesh.bounds(),
+            collider: raycast::Bvh::new(&positions, &mesh.indices).map(Arc::new),
+        });
+        self.meshes.push((
+            data.clone(),
+            [
+                Resource::Buffer(vertex_buffer.0, vertex_buffer.1),
+                Resource::Buffer(index_buffer.0, index_buffer.1),
+            ],
+        ));
+        Mesh(data)
+    }
+
+    pub fn add_texture(
+        &mut self,
+        image: (vk::Image, vk::DeviceMemory, vk::ImageView),
+        extent: vk::Extent2D,
+        allocation: memory::Allocation,
+    ) -> Texture {
+        let data = Arc::new(TextureData {
+            view: image.2,
+            extent,
+        });
+        self.textures.push((
+            data.clone(),
+            Resource::Image(image.0, image.1, image.2),
+            allocation,
+        ));
+        Texture(data)
+    }
+
+    /// Retires the resources of every asset whose handles have all been dropped. Returns how many meshes and
+    /// textures were collected, and the texture memory freed.
+    pub fn collect_garbage(
+        &mut self,
+        deletion_queue: &mut DeletionQueue,
+    ) -> (usize, usize, memory::Allocation) {
+        // Only the registry's own reference is left once every handle is gone
+        let mut meshes = 0;
+        self.meshes.retain(|(data, resources)| {
+            let unused = Arc::strong_count(data) == 1;
+            if unused {
+                for &resource in resources.iter() {
+                    deletion_queue.retire(resource);
+                }
+                meshes += 1;
+            }
+            !unused
+        });
+        let (mut textures, mut freed) = (0, memory::Allocation::default());
+        self.textures.retain(|(data, resource, allocation)| {
+            let unused = Arc::strong_count(data) == 1;
+            if unused {
+                deletion_queue.retire(*resource);
+                textures += 1;
+                freed += *allocation;
+            }
+            !unused
+        });
+        (meshes, textures, freed)
+    }
+
+    /// Retires every asset whether or not it's still used, when the renderer is destroyed
+    pub fn retire_all(&mut self, deletion_queue: &mut DeletionQueue) {
+        for (_, resources) in self.meshes.drain(..) {
+            for resource in resources {
+                deletion_queue.retire(resource);
+            }
+        }
+        for (_, resource, _) in self.textures.drain(..) {
+            deletion_queue.retire(resource);
+        }
+    }
+}
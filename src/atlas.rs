@@ -0,0 +1,569 @@
+use std::mem::size_of;
+use std::path::Path;
+
+use ash::vk;
+
+use crate::{pipeline, playground, util, HelloTriangleApplication};
+
+/// Width and height of each of the atlas's layers in texels
+const SIZE: u32 = 1024;
+const LAYERS: u32 = 4;
+/// Widgets encode to sRGB on write, and are decoded back to linear colour when composited
+const FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+/// Where a widget is kept in the atlas, in texels of its layer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Slot {
+    pub layer: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A row of slots along a layer, as tall as the first slot placed in it
+#[derive(Clone, Copy, Debug)]
+struct Shelf {
+    layer: u32,
+    y: u32,
+    height: u32,
+    /// Where the next slot along the shelf starts
+    x: u32,
+}
+
+/// Inputs to `atlas_frag.glsl`, laid out to match its `Placement` push constants.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Placement {
+    offset: [f32; 2],
+    size: [f32; 2],
+    /// The slot's corner and size as a fraction of its layer
+    slot: [f32; 4],
+    layer: u32,
+    encode_srgb: u32,
+}
+
+impl Placement {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+}
+
+/// Layers of a texture that overlay widgets which rarely change are drawn into, so that each frame only copies
+/// them over the scene rather than drawing them again. Widgets are given slots packed into shelves along the
+/// layers, moving on to the next layer when one fills up, and are drawn into their slot outside of the frame's
+/// render pass with the atlas's own. A widget drawn into the atlas can be composited from the next frame on.
+pub struct Atlas {
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    /// Every layer, for compositing
+    view: vk::ImageView,
+    /// Each layer on its own, for drawing widgets into
+    layer_views: Vec<vk::ImageView>,
+    framebuffers: Vec<vk::Framebuffer>,
+    render_pass: vk::RenderPass,
+    sampler: vk::Sampler,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+    layout: vk::PipelineLayout,
+    /// Created when first composited after the swapchain has been (re)created, with the frame's render pass
+    pipeline: Option<vk::Pipeline>,
+    shelves: Vec<Shelf>,
+    /// Whether the layers have been moved out of the undefined layout they are created in
+    initialized: bool,
+}
+
+impl Atlas {
+    pub fn new(
+        device: &ash::Device,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    ) -> Self {
+        let image = unsafe {
+            device
+                .create_image(
+                    &vk::ImageCreateInfo::builder()
+                        .image_type(vk::ImageType::TYPE_2D)
+                        .extent(vk::Extent3D {
+                            width: SIZE,
+                            height: SIZE,
+                            depth: 1,
+                        })
+                        .mip_levels(1)
+                        .array_layers(LAYERS)
+                        .format(FORMAT)
+                        .tiling(vk::ImageTiling::OPTIMAL)
+                        .initial_layout(vk::ImageLayout::UNDEFINED)
+                        .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED)
+                        .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                        .samples(vk::SampleCountFlags::TYPE_1),
+                    None,
+                )
+                .expect("UI atlas image")
+        };
+        let requirements = unsafe { device.get_image_memory_requirements(image) };
+        let memory = unsafe {
+            let memory = device
+                .allocate_memory(
+                    &vk::MemoryAllocateInfo::builder()
+                        .allocation_size(requirements.size)
+                        .memory_type_index(HelloTriangleApplication::find_memory_type(
+                            requirements.memory_type_bits,
+                            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                            device_memory_properties,
+                        )),
+                    None,
+                )
+                .expect("UI atlas memory");
+            device
+                .bind_image_memory(image, memory, 0)
+                .expect("Binding UI atlas memory");
+            memory
+        };
+
+        let create_view = |view_type, base_array_layer, layer_count| unsafe {
+            device
+                .create_image_view(
+                    &vk::ImageViewCreateInfo::builder()
+                        .image(image)
+                        .view_type(view_type)
+                        .format(FORMAT)
+                        .subresource_range(vk::ImageSubresourceRange {
+                            aspect_mask: vk::ImageAspectFlags::COLOR,
+                            base_mip_level: 0,
+                            level_count: 1,
+                            base_array_layer,
+                            layer_count,
+                        }),
+                    None,
+                )
+                .expect("UI atlas view")
+        };
+        let view = create_view(vk::ImageViewType::TYPE_2D_ARRAY, 0, LAYERS);
+        let layer_views: Vec<vk::ImageView> = (0..LAYERS)
+            .map(|layer| create_view(vk::ImageViewType::TYPE_2D, layer, 1))
+            .collect();
+
+        let render_pass = create_render_pass(device);
+        let framebuffers = layer_views
+            .iter()
+            .map(|&layer_view| unsafe {
+                let attachments = [layer_view];
+                device
+                    .create_framebuffer(
+                        &vk::FramebufferCreateInfo::builder()
+                            .render_pass(render_pass)
+                            .attachments(&attachments)
+                            .width(SIZE)
+                            .height(SIZE)
+                            .layers(1),
+                        None,
+                    )
+                    .expect("UI atlas framebuffer")
+            })
+            .collect();
+
+        // Widgets are composited at the size they were drawn, so each pixel reads one texel
+        let sampler = unsafe {
+            device
+                .create_sampler(
+                    &vk::SamplerCreateInfo::builder()
+                        .mag_filter(vk::Filter::NEAREST)
+                        .min_filter(vk::Filter::NEAREST)
+                        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+                    None,
+                )
+                .expect("UI atlas sampler")
+        };
+
+        let bindings = [vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build()];
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+                    None,
+                )
+                .expect("UI atlas descriptor set layout")
+        };
+        let pool_sizes = [vk::DescriptorPoolSize::builder()
+            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .build()];
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::builder()
+                        .pool_sizes(&pool_sizes)
+                        .max_sets(1),
+                    None,
+                )
+                .expect("UI atlas descriptor pool")
+        };
+        let set_layouts = [descriptor_set_layout];
+        let descriptor_set = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::builder()
+                        .descriptor_pool(descriptor_pool)
+                        .set_layouts(&set_layouts),
+                )
+                .expect("UI atlas descriptor set")[0]
+        };
+        let image_info = [vk::DescriptorImageInfo::builder()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(view)
+            .sampler(sampler)
+            .build()];
+        let writes = [vk::WriteDescriptorSet::builder()
+            .dst_set(descriptor_set)
+            .dst_binding(0)
+            .dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_info)
+            .build()];
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .offset(0)
+            .size(size_of::<Placement>() as u32)
+            .build()];
+        let layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::builder()
+                        .set_layouts(&set_layouts)
+                        .push_constant_ranges(&push_constant_ranges),
+                    None,
+                )
+                .expect("UI atlas pipeline layout")
+        };
+
+        Self {
+            image,
+            memory,
+            view,
+            layer_views,
+            framebuffers,
+            render_pass,
+            sampler,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            layout,
+            pipeline: None,
+            shelves: Vec::new(),
+            initialized: false,
+        }
+    }
+
+    /// What pipelines drawing widgets into the atlas are created for
+    pub fn target(&self) -> pipeline::Target {
+        pipeline::Target {
+            render_pass: self.render_pass,
+            samples: vk::SampleCountFlags::TYPE_1,
+            extent: vk::Extent2D {
+                width: SIZE,
+                height: SIZE,
+            },
+            encode_srgb: false,
+            color: true,
+        }
+    }
+
+    /// A slot for a widget of the size, on the first shelf with room for it, or on a new shelf below the last
+    /// one of the layer or of the next layer. Slots are kept until the atlas is destroyed.
+    pub fn allocate(&mut self, width: u32, height: u32) -> Result<Slot, String> {
+        if width > SIZE || height > SIZE {
+            return Err(format!(
+                "A {}x{} widget is larger than the {}x{} UI atlas",
+                width, height, SIZE, SIZE
+            ));
+        }
+        // Shelves taller than the widget waste the difference, so only those up to twice its height are used
+        let shelf = self.shelves.iter().position(|shelf| {
+            shelf.height >= height && shelf.height <= height * 2 && shelf.x + width <= SIZE
+        });
+        let shelf = match shelf {
+            Some(shelf) => shelf,
+            None => {
+                let (layer, y) = match self.shelves.last() {
+                    Some(last) if last.y + last.height + height <= SIZE => {
+                        (last.layer, last.y + last.height)
+                    }
+                    Some(last) => (last.layer + 1, 0),
+                    None => (0, 0),
+                };
+                if layer == LAYERS {
+                    return Err(format!(
+                        "The UI atlas has no room left for a {}x{} widget",
+                        width, height
+                    ));
+                }
+                self.shelves.push(Shelf {
+                    layer,
+                    y,
+                    height,
+                    x: 0,
+                });
+                self.shelves.len() - 1
+            }
+        };
+        let shelf = &mut self.shelves[shelf];
+        let slot = Slot {
+            layer: shelf.layer,
+            x: shelf.x,
+            y: shelf.y,
+            width,
+            height,
+        };
+        shelf.x += width;
+        Ok(slot)
+    }
+
+    /// Records drawing a widget into its slot, outside of a render pass. `draw` records into the atlas's render
+    /// pass with the slot cleared to transparent and the viewport set to it, for pipelines made for `target`.
+    pub fn record_update(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        slot: &Slot,
+        draw: impl FnOnce(vk::CommandBuffer),
+    ) {
+        if !self.initialized {
+            self.initialized = true;
+            let barrier = vk::ImageMemoryBarrier::builder()
+                .old_layout(vk::ImageLayout::UNDEFINED)
+                .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .src_access_mask(vk::AccessFlags::empty())
+                .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(self.image)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: LAYERS,
+                })
+                .build();
+            unsafe {
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[barrier],
+                );
+            }
+        }
+
+        let rect = vk::Rect2D {
+            offset: vk::Offset2D {
+                x: slot.x as i32,
+                y: slot.y as i32,
+            },
+            extent: vk::Extent2D {
+                width: slot.width,
+                height: slot.height,
+            },
+        };
+        let viewports = [vk::Viewport::builder()
+            .x(slot.x as f32)
+            .y(slot.y as f32)
+            .width(slot.width as f32)
+            .height(slot.height as f32)
+            .min_depth(0.0)
+            .max_depth(1.0)
+            .build()];
+        unsafe {
+            device.cmd_begin_render_pass(
+                command_buffer,
+                &vk::RenderPassBeginInfo::builder()
+                    .render_pass(self.render_pass)
+                    .framebuffer(self.framebuffers[slot.layer as usize])
+                    .render_area(rect),
+                vk::SubpassContents::INLINE,
+            );
+            device.cmd_clear_attachments(
+                command_buffer,
+                &[vk::ClearAttachment {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    color_attachment: 0,
+                    clear_value: vk::ClearValue {
+                        color: vk::ClearColorValue {
+                            float32: [0.0, 0.0, 0.0, 0.0],
+                        },
+                    },
+                }],
+                &[vk::ClearRect {
+                    rect,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                }],
+            );
+            device.cmd_set_viewport(command_buffer, 0, &viewports);
+        }
+        draw(command_buffer);
+        unsafe { device.cmd_end_render_pass(command_buffer) };
+    }
+
+    /// Records copying a widget's slot over the frame with its top left corner at `offset` in pixels, inside the
+    /// frame's render pass. Leaves the viewport narrowed to the widget.
+    pub fn record_composite(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        target: &pipeline::Target,
+        swapchain_format: vk::Format,
+        slot: &Slot,
+        offset: (u32, u32),
+    ) {
+        let layout = self.layout;
+        let pipeline = *self.pipeline.get_or_insert_with(|| {
+            let path = Path::new(env!("OUT_DIR")).join("atlas_frag.spv");
+            playground::create_pipeline(
+                device,
+                target,
+                layout,
+                &util::read_shader_code(&path),
+                true,
+                false,
+            )
+        });
+        let size = SIZE as f32;
+        let placement = Placement {
+            offset: [offset.0 as f32, offset.1 as f32],
+            size: [slot.width as f32, slot.height as f32],
+            slot: [
+                slot.x as f32 / size,
+                slot.y as f32 / size,
+                slot.width as f32 / size,
+                slot.height as f32 / size,
+            ],
+            layer: slot.layer,
+            encode_srgb: !util::is_srgb_format(swapchain_format) as u32,
+        };
+        // The full-screen triangle covers the viewport, which is narrowed to the widget
+        let viewports = [vk::Viewport::builder()
+            .x(placement.offset[0])
+            .y(placement.offset[1])
+            .width(placement.size[0])
+            .height(placement.size[1])
+            .min_depth(0.0)
+            .max_depth(1.0)
+            .build()];
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            device.cmd_set_viewport(command_buffer, 0, &viewports);
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                placement.as_bytes(),
+            );
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+
+    /// Drops the compositing pipeline made for the old swapchain's render pass
+    pub fn resize(&mut self, device: &ash::Device) {
+        if let Some(pipeline) = self.pipeline.take() {
+            unsafe { device.destroy_pipeline(pipeline, None) };
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        self.resize(device);
+        unsafe {
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            device.destroy_sampler(self.sampler, None);
+            for &framebuffer in self.framebuffers.iter() {
+                device.destroy_framebuffer(framebuffer, None);
+            }
+            device.destroy_render_pass(self.render_pass, None);
+            for &view in self.layer_views.iter() {
+                device.destroy_image_view(view, None);
+            }
+            device.destroy_image_view(self.view, None);
+            device.destroy_image(self.image, None);
+            device.free_memory(self.memory, None);
+        }
+    }
+}
+
+/// A render pass drawing into one layer, keeping what is outside the slot being drawn. The layer is sampled before
+/// and after.
+fn create_render_pass(device: &ash::Device) -> vk::RenderPass {
+    let attachments = [vk::AttachmentDescription::builder()
+        .format(FORMAT)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .load_op(vk::AttachmentLoadOp::LOAD)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .build()];
+    let color_attachment_refs = [vk::AttachmentReference::builder()
+        .attachment(0)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        .build()];
+    let subpasses = [vk::SubpassDescription::builder()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .color_attachments(&color_attachment_refs)
+        .build()];
+    let dependencies = [
+        // Frames composited before may still be reading the layer
+        vk::SubpassDependency::builder()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .dst_subpass(0)
+            .src_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+            .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .src_access_mask(vk::AccessFlags::SHADER_READ)
+            .dst_access_mask(
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            )
+            .build(),
+        vk::SubpassDependency::builder()
+            .src_subpass(0)
+            .dst_subpass(vk::SUBPASS_EXTERNAL)
+            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+            .build(),
+    ];
+    unsafe {
+        device
+            .create_render_pass(
+                &vk::RenderPassCreateInfo::builder()
+                    .attachments(&attachments)
+                    .subpasses(&subpasses)
+                    .dependencies(&dependencies),
+                None,
+            )
+            .expect("UI atlas render pass")
+    }
+}
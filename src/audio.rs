@@ -0,0 +1,192 @@
+#[cfg(feature = "audio")]
+use std::f32::consts::PI;
+#[cfg(feature = "audio")]
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "audio")]
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+/// Number of frequency bands of captured audio written to the frame's uniform buffer for music visualizer
+/// style effects. They are spaced logarithmically from the lowest audible frequency and read by shaders as
+/// `vec4 audioBands[4]`, lowest band first. Every band is zero without the `audio` feature.
+pub const BAND_COUNT: usize = 16;
+
+/// Samples transformed per update. Must be a power of two.
+#[cfg(feature = "audio")]
+const FFT_SIZE: usize = 1024;
+#[cfg(feature = "audio")]
+const LOWEST_FREQUENCY: f32 = 20.0;
+#[cfg(feature = "audio")]
+const HIGHEST_FREQUENCY: f32 = 20000.0;
+/// Band magnitudes are mapped from this range of decibels to 0-1
+#[cfg(feature = "audio")]
+const DECIBEL_RANGE: [f32; 2] = [-70.0, -10.0];
+/// How much of the previous update's magnitude is kept, so bands fall smoothly instead of flickering
+#[cfg(feature = "audio")]
+const SMOOTHING: f32 = 0.6;
+
+/// Captures the default input device and converts the most recent samples into band magnitudes.
+#[cfg(feature = "audio")]
+pub struct Capture {
+    // Capture stops when the stream is dropped
+    _stream: cpal::Stream,
+    sample_rate: f32,
+    /// The last `FFT_SIZE` mono samples, oldest first
+    samples: Arc<Mutex<Vec<f32>>>,
+    bands: [f32; BAND_COUNT],
+}
+
+#[cfg(feature = "audio")]
+impl Capture {
+    /// Starts capturing from the default input device.
+    pub fn new() -> Result<Self, String> {
+        let host = cpal::default_host();
+        let device = host
+            .default_input_device()
+            .ok_or_else(|| String::from("No audio input device"))?;
+        let config = device
+            .default_input_config()
+            .map_err(|e| format!("Audio input config: {}", e))?;
+        println!(
+            "Capturing audio from {} at {}Hz",
+            device
+                .name()
+                .unwrap_or_else(|_| String::from("unknown device")),
+            config.sample_rate().0
+        );
+
+        let samples = Arc::new(Mutex::new(vec![0.0; FFT_SIZE]));
+        let channels = config.channels() as usize;
+        let stream_config: cpal::StreamConfig = config.clone().into();
+        let error_callback = |e| println!("Audio capture error: {}", e);
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::F32 => device.build_input_stream(
+                &stream_config,
+                Self::receive::<f32>(samples.clone(), channels),
+                error_callback,
+            ),
+            cpal::SampleFormat::I16 => device.build_input_stream(
+                &stream_config,
+                Self::receive::<i16>(samples.clone(), channels),
+                error_callback,
+            ),
+            cpal::SampleFormat::U16 => device.build_input_stream(
+                &stream_config,
+                Self::receive::<u16>(samples.clone(), channels),
+                error_callback,
+            ),
+        }
+        .map_err(|e| format!("Audio input stream: {}", e))?;
+        stream
+            .play()
+            .map_err(|e| format!("Starting audio capture: {}", e))?;
+
+        Ok(Self {
+            _stream: stream,
+            sample_rate: config.sample_rate().0 as f32,
+            samples,
+            bands: [0.0; BAND_COUNT],
+        })
+    }
+
+    /// The stream callback. Mixes each frame of samples down to mono and appends it to the sample window.
+    fn receive<T: cpal::Sample>(
+        samples: Arc<Mutex<Vec<f32>>>,
+        channels: usize,
+    ) -> impl FnMut(&[T], &cpal::InputCallbackInfo) {
+        move |data, _| {
+            let mut samples = samples.lock().expect("audio sample lock");
+            for frame in data.chunks(channels) {
+                let mono =
+                    frame.iter().map(|sample| sample.to_f32()).sum::<f32>() / channels as f32;
+                samples.push(mono);
+            }
+            let excess = samples.len().saturating_sub(FFT_SIZE);
+            samples.drain(..excess);
+        }
+    }
+
+    /// Transforms the latest samples and returns each band's magnitude from 0 to 1.
+    pub fn update(&mut self) -> [f32; BAND_COUNT] {
+        // A Hann window stops the ends of the sample window leaking energy into every frequency
+        let mut real: Vec<f32> = {
+            let samples = self.samples.lock().expect("audio sample lock");
+            samples
+                .iter()
+                .enumerate()
+                .map(|(i, sample)| {
+                    let window = 0.5 - 0.5 * (2.0 * PI * i as f32 / (FFT_SIZE - 1) as f32).cos();
+                    sample * window
+                })
+                .collect()
+        };
+        let mut imaginary = vec![0.0; FFT_SIZE];
+        fft(&mut real, &mut imaginary);
+
+        let bin_width = self.sample_rate / FFT_SIZE as f32;
+        let highest = HIGHEST_FREQUENCY.min(self.sample_rate / 2.0);
+        for band in 0..BAND_COUNT {
+            let frequency = |edge: usize| {
+                LOWEST_FREQUENCY
+                    * (highest / LOWEST_FREQUENCY).powf(edge as f32 / BAND_COUNT as f32)
+            };
+            let first_bin = (frequency(band) / bin_width) as usize;
+            let last_bin = ((frequency(band + 1) / bin_width) as usize).max(first_bin + 1);
+
+            let magnitude = (first_bin..last_bin.min(FFT_SIZE / 2))
+                .map(|bin| (real[bin] * real[bin] + imaginary[bin] * imaginary[bin]).sqrt())
+                .fold(0.0, f32::max)
+                / (FFT_SIZE / 2) as f32;
+            let decibels = 20.0 * magnitude.max(f32::MIN_POSITIVE).log10();
+            let level = num::clamp(
+                (decibels - DECIBEL_RANGE[0]) / (DECIBEL_RANGE[1] - DECIBEL_RANGE[0]),
+                0.0,
+                1.0,
+            );
+
+            self.bands[band] = level.max(self.bands[band] * SMOOTHING);
+        }
+
+        self.bands
+    }
+}
+
+/// In place iterative radix-2 fast Fourier transform. Both slices must have the same power of two length.
+#[cfg(feature = "audio")]
+fn fft(real: &mut [f32], imaginary: &mut [f32]) {
+    let n = real.len();
+
+    // Reorder the input so the butterflies below can work in place
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            real.swap(i, j);
+            imaginary.swap(i, j);
+        }
+    }
+
+    let mut length = 2;
+    while length <= n {
+        let angle = -2.0 * PI / length as f32;
+        for start in (0..n).step_by(length) {
+            for k in 0..length / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let even = start + k;
+                let odd = even + length / 2;
+                let odd_real = real[odd] * cos - imaginary[odd] * sin;
+                let odd_imaginary = real[odd] * sin + imaginary[odd] * cos;
+                real[odd] = real[even] - odd_real;
+                imaginary[odd] = imaginary[even] - odd_imaginary;
+                real[even] += odd_real;
+                imaginary[even] += odd_imaginary;
+            }
+        }
+        length <<= 1;
+    }
+}
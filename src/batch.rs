@@ -0,0 +1,171 @@
+use cgmath::{InnerSpace, Matrix, Matrix3, Matrix4, Point3, SquareMatrix, Transform, Vector3};
+
+use crate::{bounds, material, mesh, pipeline};
+
+/// Vertices a batch can hold, the most `u16` indices can address
+const MAX_BATCH_VERTICES: usize = u16::MAX as usize + 1;
+
+/// A mesh that never moves, placed in the scene by `transform`.
+#[derive(Clone, Debug)]
+pub struct StaticMesh {
+    pub mesh: mesh::MeshData,
+    pub material: material::Material,
+    pub transform: Matrix4<f32>,
+}
+
+/// Static meshes merged into a single draw of part of a `Baked` mesh.
+#[derive(Clone, Debug)]
+pub struct Batch {
+    pub pipeline: pipeline::Config,
+    /// The batch's first index in the baked index buffer
+    pub first_index: u32,
+    pub index_count: u32,
+    /// Added to the batch's indices, which start from zero at the batch's first vertex
+    pub vertex_offset: i32,
+    /// Bounds of the batch's vertices, which are already placed in the scene
+    pub bounds: Option<bounds::Aabb>,
+}
+
+/// The batches of every static mesh with the same vertex attributes, sharing one vertex and index buffer.
+#[derive(Clone, Debug)]
+pub struct Baked {
+    pub mesh: mesh::MeshData,
+    pub batches: Vec<Batch>,
+}
+
+/// Merges static meshes that are drawn with the same pipeline into as few draws as possible. Each mesh's
+/// vertices are transformed into the scene so a batch is drawn without a transform of its own. Meshes are
+/// grouped by their vertex attributes first, since those decide the layout of the buffers, and a material
+/// gets more than one batch only when its meshes have more vertices than `u16` indices can address.
+pub fn bake(meshes: Vec<StaticMesh>) -> Vec<Baked> {
+    let mut by_attributes: Vec<(mesh::VertexAttributes, Vec<StaticMesh>)> = Vec::new();
+    for static_mesh in meshes {
+        let attributes = static_mesh.mesh.attributes();
+        match by_attributes.iter_mut().find(|(a, _)| *a == attributes) {
+            Some((_, group)) => group.push(static_mesh),
+            None => by_attributes.push((attributes, vec![static_mesh])),
+        }
+    }
+
+    by_attributes
+        .into_iter()
+        .map(|(attributes, meshes)| bake_group(attributes, meshes))
+        .collect()
+}
+
+fn bake_group(attributes: mesh::VertexAttributes, meshes: Vec<StaticMesh>) -> Baked {
+    let mut by_pipeline: Vec<(pipeline::Config, Vec<StaticMesh>)> = Vec::new();
+    for static_mesh in meshes {
+        let config = static_mesh.material.pipeline_config(attributes);
+        match by_pipeline.iter_mut().find(|(c, _)| *c == config) {
+            Some((_, group)) => group.push(static_mesh),
+            None => by_pipeline.push((config, vec![static_mesh])),
+        }
+    }
+
+    let mut baked = Baked {
+        mesh: mesh::MeshData {
+            positions: Vec::new(),
+            normals: Vec::new(),
+            colors: attributes.color.then(Vec::new),
+            tex_coords: attributes.tex_coord.then(Vec::new),
+            lightmap_coords: attributes.lightmap_coord.then(Vec::new),
+            occlusion: attributes.occlusion.then(Vec::new),
+            indices: Vec::new(),
+        },
+        batches: Vec::new(),
+    };
+    for (config, meshes) in by_pipeline {
+        let mut batch = baked.start_batch(config);
+        for static_mesh in meshes.iter() {
+            let vertices = static_mesh.mesh.positions.len();
+            let batch_vertices = baked.mesh.positions.len() - batch.vertex_offset as usize;
+            if batch_vertices > 0 && batch_vertices + vertices > MAX_BATCH_VERTICES {
+                baked.finish_batch(batch);
+                batch = baked.start_batch(config);
+            }
+            baked.append(&mut batch, static_mesh);
+        }
+        baked.finish_batch(batch);
+    }
+
+    baked
+}
+
+impl Baked {
+    fn start_batch(&self, pipeline: pipeline::Config) -> Batch {
+        Batch {
+            pipeline,
+            first_index: self.mesh.indices.len() as u32,
+            index_count: 0,
+            vertex_offset: self.mesh.positions.len() as i32,
+            bounds: None,
+        }
+    }
+
+    fn finish_batch(&mut self, batch: Batch) {
+        if batch.index_count > 0 {
+            self.batches.push(batch);
+        }
+    }
+
+    /// Appends the mesh's vertices, placed in the scene, and its indices offset to follow the batch's earlier
+    /// meshes.
+    fn append(&mut self, batch: &mut Batch, static_mesh: &StaticMesh) {
+        let source = &static_mesh.mesh;
+        let transform = static_mesh.transform;
+        let rotation = Matrix3::from_cols(
+            transform.x.truncate(),
+            transform.y.truncate(),
+            transform.z.truncate(),
+        );
+        // The inverse transpose keeps normals perpendicular to the surface under non-uniform scaling
+        let normal_transform = rotation
+            .invert()
+            .expect("Static mesh transform is invertible")
+            .transpose();
+
+        let base = (self.mesh.positions.len() - batch.vertex_offset as usize) as u16;
+        let positions: Vec<[f32; 3]> = source
+            .positions
+            .iter()
+            .map(|&position| transform.transform_point(Point3::from(position)).into())
+            .collect();
+        let bounds = bounds::Aabb::from_points(positions.iter().map(|&p| Point3::from(p)));
+        batch.bounds = match (batch.bounds, bounds) {
+            (Some(a), Some(b)) => Some(a.union(&b)),
+            (a, b) => a.or(b),
+        };
+
+        self.mesh.positions.extend(positions);
+        let normals = source.normals.iter().map(|&normal| {
+            let normal: [f32; 3] = (normal_transform * Vector3::from(normal))
+                .normalize()
+                .into();
+            normal
+        });
+        self.mesh.normals.extend(normals);
+        if let (Some(colors), Some(source_colors)) = (self.mesh.colors.as_mut(), &source.colors) {
+            colors.extend_from_slice(source_colors);
+        }
+        if let (Some(tex_coords), Some(source_tex_coords)) =
+            (self.mesh.tex_coords.as_mut(), &source.tex_coords)
+        {
+            tex_coords.extend_from_slice(source_tex_coords);
+        }
+        if let (Some(lightmap_coords), Some(source_lightmap_coords)) =
+            (self.mesh.lightmap_coords.as_mut(), &source.lightmap_coords)
+        {
+            lightmap_coords.extend_from_slice(source_lightmap_coords);
+        }
+        if let (Some(occlusion), Some(source_occlusion)) =
+            (self.mesh.occlusion.as_mut(), &source.occlusion)
+        {
+            occlusion.extend_from_slice(source_occlusion);
+        }
+        self.mesh
+            .indices
+            .extend(source.indices.iter().map(|&index| base + index));
+        batch.index_count += source.indices.len() as u32;
+    }
+}
@@ -0,0 +1,81 @@
+use std::mem::size_of;
+
+use ash::vk;
+use cgmath::Point3;
+use memoffset::offset_of;
+
+use crate::bounds;
+
+/// Number of vertices drawn per billboard. The vertex shader expands them into two triangles covering the quad.
+pub const VERTICES_PER_BILLBOARD: u32 = 6;
+
+/// How a billboard turns to face the camera.
+#[derive(Clone, Copy, Debug)]
+pub enum Orientation {
+    /// Always faces the camera head on. Suits light sprites and particles.
+    Spherical,
+    /// Only rotates around the given world space axis. Suits trees and distant-object impostors that should
+    /// stay upright.
+    AxisLocked([f32; 3]),
+}
+
+/// Per-instance data for a camera facing quad. No per-vertex data is needed, the corners of the quad are
+/// generated from `gl_VertexIndex` in `billboard_vert.glsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Billboard {
+    center: [f32; 3],
+    size: [f32; 2],
+    /// xyz is the locking axis, w is 1.0 when the billboard is axis locked and 0.0 when it is spherical.
+    axis: [f32; 4],
+}
+
+impl Billboard {
+    pub const fn new(center: [f32; 3], size: [f32; 2], orientation: Orientation) -> Self {
+        let axis = match orientation {
+            Orientation::Spherical => [0.0, 0.0, 0.0, 0.0],
+            Orientation::AxisLocked([x, y, z]) => [x, y, z, 1.0],
+        };
+
+        Self { center, size, axis }
+    }
+
+    /// Bounds that contain the billboard however it is turned
+    pub fn bounds(&self) -> bounds::Aabb {
+        let [width, height] = self.size;
+        let radius = (width * width + height * height).sqrt() / 2.0;
+        bounds::Aabb::around_sphere(Point3::from(self.center), radius)
+    }
+
+    pub fn get_binding_description() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(size_of::<Self>() as u32)
+            // Advance once per billboard rather than once per vertex
+            .input_rate(vk::VertexInputRate::INSTANCE)
+            .build()
+    }
+
+    pub fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescription; 3] {
+        let center_binding = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(0)
+            .format(vk::Format::R32G32B32_SFLOAT)
+            .offset(offset_of!(Self, center) as u32)
+            .build();
+        let size_binding = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(1)
+            .format(vk::Format::R32G32_SFLOAT)
+            .offset(offset_of!(Self, size) as u32)
+            .build();
+        let axis_binding = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(2)
+            .format(vk::Format::R32G32B32A32_SFLOAT)
+            .offset(offset_of!(Self, axis) as u32)
+            .build();
+
+        [center_binding, size_binding, axis_binding]
+    }
+}
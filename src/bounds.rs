@@ -0,0 +1,173 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix, Matrix4, Point3, Transform, Vector3, Vector4};
+
+/// An axis aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    /// The smallest box containing every point, or `None` if there are no points.
+    pub fn from_points<I: IntoIterator<Item = Point3<f32>>>(points: I) -> Option<Self> {
+        points.into_iter().fold(None, |bounds, point| {
+            Some(match bounds {
+                None => Self {
+                    min: point,
+                    max: point,
+                },
+                Some(bounds) => bounds.union(&Self {
+                    min: point,
+                    max: point,
+                }),
+            })
+        })
+    }
+
+    /// A box around a sphere
+    pub fn around_sphere(center: Point3<f32>, radius: f32) -> Self {
+        let extent = Vector3::new(radius, radius, radius);
+        Self {
+            min: center - extent,
+            max: center + extent,
+        }
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: Point3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Point3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        self.min.midpoint(self.max)
+    }
+
+    /// Radius of the sphere around `center()` that contains the box
+    pub fn radius(&self) -> f32 {
+        (self.max - self.min).magnitude() / 2.0
+    }
+
+    pub fn contains(&self, point: Point3<f32>) -> bool {
+        (self.min.x..=self.max.x).contains(&point.x)
+            && (self.min.y..=self.max.y).contains(&point.y)
+            && (self.min.z..=self.max.z).contains(&point.z)
+    }
+
+    /// Whether any of the box is on the side of the plane its normal points to. The plane's normal must be
+    /// normalised so a point's distance from it is `dot(normal, point) + w`.
+    pub fn in_front_of(&self, plane: &Vector4<f32>) -> bool {
+        // The corner furthest along the plane's normal
+        let corner = Vector3::new(
+            if plane.x >= 0.0 {
+                self.max.x
+            } else {
+                self.min.x
+            },
+            if plane.y >= 0.0 {
+                self.max.y
+            } else {
+                self.min.y
+            },
+            if plane.z >= 0.0 {
+                self.max.z
+            } else {
+                self.min.z
+            },
+        );
+        plane.truncate().dot(corner) + plane.w >= 0.0
+    }
+
+    pub fn corners(&self) -> [Point3<f32>; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            Point3::new(min.x, min.y, min.z),
+            Point3::new(max.x, min.y, min.z),
+            Point3::new(min.x, max.y, min.z),
+            Point3::new(max.x, max.y, min.z),
+            Point3::new(min.x, min.y, max.z),
+            Point3::new(max.x, min.y, max.z),
+            Point3::new(min.x, max.y, max.z),
+            Point3::new(max.x, max.y, max.z),
+        ]
+    }
+
+    /// The box containing this box once it has been transformed. It is generally larger than the transformed
+    /// geometry's own bounds when the transform rotates.
+    pub fn transformed(&self, transform: &Matrix4<f32>) -> Self {
+        Self::from_points(
+            self.corners()
+                .iter()
+                .map(|corner| transform.transform_point(*corner)),
+        )
+        .expect("box has corners")
+    }
+}
+
+/// Bounds of a mesh in its own space along with its bounds in world space under its current transform.
+#[derive(Clone, Copy, Debug)]
+pub struct Bounds {
+    pub local: Aabb,
+    pub world: Aabb,
+}
+
+impl Bounds {
+    /// Bounds for a mesh that hasn't been transformed yet
+    pub fn new(local: Aabb) -> Self {
+        Self {
+            local,
+            world: local,
+        }
+    }
+
+    /// Recomputes the world space bounds. Should be called whenever the mesh's transform changes.
+    pub fn update(&mut self, transform: &Matrix4<f32>) {
+        self.world = self.local.transformed(transform);
+    }
+}
+
+/// The volume a camera sees, as the planes bounding it.
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+    /// Normals point inwards and are normalised so a point's distance from a plane is `dot(normal, point) + w`
+    pub planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// The frustum of `clip`, a transform to clip space.
+    pub fn new(clip: &Matrix4<f32>) -> Self {
+        let row = |i: usize| clip.row(i);
+        let planes = [
+            row(3) + row(0),
+            row(3) - row(0),
+            row(3) + row(1),
+            row(3) - row(1),
+            row(3) + row(2),
+            row(3) - row(2),
+        ];
+
+        // An infinite far plane has no normal, and nothing is behind it
+        let normalize = |plane: Vector4<f32>| match plane.truncate().magnitude() {
+            magnitude if magnitude > 0.0 => plane / magnitude,
+            _ => Vector4::unit_w(),
+        };
+        Self {
+            planes: planes.map(normalize),
+        }
+    }
+
+    /// Whether any of the box may be inside the frustum. Boxes near the frustum's corners can be kept when
+    /// they are outside.
+    pub fn intersects(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| aabb.in_front_of(plane))
+    }
+}
@@ -0,0 +1,318 @@
+use std::cmp::Reverse;
+use std::time::{Duration, Instant};
+
+use ash::vk;
+
+use crate::statistics::Pass;
+
+/// Frames over budget are logged at most this often, along with how many others went over since
+const LOG_INTERVAL: Duration = Duration::from_secs(1);
+/// Passes listed in an alert's log entry
+const HEAVIEST_PASSES: usize = 5;
+
+/// How long the CPU and the GPU may each take over a frame before an alert is raised, e.g. 16.6ms to keep to
+/// 60 frames per second. Either can be left unlimited.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Budget {
+    pub cpu: Option<Duration>,
+    pub gpu: Option<Duration>,
+}
+
+impl Budget {
+    /// The same limit for both the CPU and the GPU
+    pub fn both(limit: Duration) -> Self {
+        Self {
+            cpu: Some(limit),
+            gpu: Some(limit),
+        }
+    }
+}
+
+/// Which side of the frame went over budget
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    /// Preparing and recording the frame, from acquiring its image to submitting it
+    Cpu,
+    /// Executing the frame's passes on the GPU, leaving out any gaps between them
+    Gpu,
+}
+
+/// A frame that took longer than its budget, with the passes that took longest heaviest first.
+#[derive(Clone, Debug)]
+pub struct Alert {
+    pub side: Side,
+    pub time: Duration,
+    pub budget: Duration,
+    pub heaviest: Vec<(String, Duration)>,
+}
+
+impl Alert {
+    /// One line short enough for the window title
+    pub fn summary(&self) -> String {
+        format!(
+            "{:?} frame {:.1}ms over its {:.1}ms budget",
+            self.side,
+            milliseconds(self.time),
+            milliseconds(self.budget)
+        )
+    }
+
+    /// The summary followed by a line for each of the heaviest passes
+    pub fn describe(&self) -> String {
+        let mut description = self.summary();
+        for (pass, time) in self.heaviest.iter() {
+            description.push_str(&format!("\n    {}: {:.3}ms", pass, milliseconds(*time)));
+        }
+        description
+    }
+}
+
+fn milliseconds(time: Duration) -> f64 {
+    time.as_secs_f64() * 1000.0
+}
+
+/// Timestamps written on the GPU at the start and end of each pass of the frame. Like the pipeline statistics,
+/// each swapchain image has a pool of its own, read back once the image's previous frame has finished so reading
+/// never waits on the GPU.
+pub struct GpuTimer {
+    pools: Vec<vk::QueryPool>,
+    recorded: Vec<bool>,
+    /// Nanoseconds each timestamp tick takes
+    period: f32,
+    /// Timestamps wrap around past this many bits
+    valid_bits: u32,
+}
+
+impl GpuTimer {
+    /// Whether the queue family can write timestamps
+    pub fn supported(
+        limits: &vk::PhysicalDeviceLimits,
+        queue_family: &vk::QueueFamilyProperties,
+    ) -> bool {
+        limits.timestamp_compute_and_graphics == vk::TRUE || queue_family.timestamp_valid_bits > 0
+    }
+
+    pub fn new(
+        device: &ash::Device,
+        image_count: usize,
+        limits: &vk::PhysicalDeviceLimits,
+        queue_family: &vk::QueueFamilyProperties,
+    ) -> Self {
+        let mut timer = Self {
+            pools: Vec::new(),
+            recorded: Vec::new(),
+            period: limits.timestamp_period,
+            valid_bits: queue_family.timestamp_valid_bits.clamp(1, 64),
+        };
+        timer.create_pools(device, image_count);
+        timer
+    }
+
+    /// Replaces the pools with one for each of the swapchain's images, after it has been recreated
+    pub fn recreate(&mut self, device: &ash::Device, image_count: usize) {
+        self.destroy(device);
+        self.create_pools(device, image_count);
+    }
+
+    fn create_pools(&mut self, device: &ash::Device, image_count: usize) {
+        let create_info = vk::QueryPoolCreateInfo::builder()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(2 * Pass::ALL.len() as u32);
+        self.pools = (0..image_count)
+            .map(|_| unsafe {
+                device
+                    .create_query_pool(&create_info, None)
+                    .expect("Creating timestamp query pool")
+            })
+            .collect();
+        self.recorded = vec![false; image_count];
+    }
+
+    /// How long each pass of the image's previous frame took, if it has finished and its timestamps are ready.
+    /// Must only be called once the image's previous commands have finished, and before they are recorded again.
+    pub fn collect(
+        &mut self,
+        device: &ash::Device,
+        image_index: usize,
+    ) -> Option<Vec<(Pass, Duration)>> {
+        if !self.recorded[image_index] {
+            return None;
+        }
+        let mut results = [0u64; 2 * Pass::ALL.len()];
+        unsafe {
+            device.get_query_pool_results(
+                self.pools[image_index],
+                0,
+                results.len() as u32,
+                &mut results,
+                vk::QueryResultFlags::TYPE_64,
+            )
+        }
+        .ok()?;
+        let mask = match self.valid_bits {
+            64 => u64::MAX,
+            bits => (1 << bits) - 1,
+        };
+        let period = self.period as f64;
+        let passes = Pass::ALL
+            .iter()
+            .zip(results.chunks_exact(2))
+            .map(|(&pass, times)| {
+                let ticks = times[1].wrapping_sub(times[0]) & mask;
+                (pass, Duration::from_nanos((ticks as f64 * period) as u64))
+            })
+            .collect();
+        Some(passes)
+    }
+
+    /// Resets the image's queries, ready for its passes to be recorded. Must be recorded outside a render pass.
+    pub fn begin_frame(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+    ) {
+        unsafe {
+            device.cmd_reset_query_pool(
+                command_buffer,
+                self.pools[image_index],
+                0,
+                2 * Pass::ALL.len() as u32,
+            )
+        };
+        self.recorded[image_index] = true;
+    }
+
+    pub fn begin(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+        pass: Pass,
+    ) {
+        self.write(device, command_buffer, image_index, 2 * pass.query());
+    }
+
+    pub fn end(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+        pass: Pass,
+    ) {
+        self.write(device, command_buffer, image_index, 2 * pass.query() + 1);
+    }
+
+    /// Writes the timestamp once every command before it has finished
+    fn write(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+        query: u32,
+    ) {
+        unsafe {
+            device.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                self.pools[image_index],
+                query,
+            )
+        };
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        for pool in self.pools.drain(..) {
+            unsafe { device.destroy_query_pool(pool, None) };
+        }
+    }
+}
+
+/// Checks frames against the budget, showing the latest alert in the window title and reporting the heaviest
+/// passes of frames that go over.
+pub struct Monitor {
+    pub budget: Budget,
+    /// `None` when the device can't write timestamps, leaving the GPU unchecked
+    pub timer: Option<GpuTimer>,
+    last_logged: Option<Instant>,
+    /// Frames over budget since the last one was logged
+    unlogged: u32,
+    /// Whether the last frame checked was over budget
+    over: bool,
+}
+
+impl Monitor {
+    pub fn new(budget: Budget, timer: Option<GpuTimer>) -> Self {
+        Self {
+            budget,
+            timer,
+            last_logged: None,
+            unlogged: 0,
+            over: false,
+        }
+    }
+
+    /// The alert for a frame that took `time` on the side, if it went over that side's budget. `passes` lists how
+    /// long each part of the frame took, in any order, and is only called for frames over budget.
+    pub fn check(
+        &self,
+        side: Side,
+        time: Duration,
+        passes: impl FnOnce() -> Vec<(String, Duration)>,
+    ) -> Option<Alert> {
+        let budget = match side {
+            Side::Cpu => self.budget.cpu,
+            Side::Gpu => self.budget.gpu,
+        }?;
+        if time <= budget {
+            return None;
+        }
+        let mut heaviest = passes();
+        heaviest.sort_by_key(|&(_, time)| Reverse(time));
+        heaviest.truncate(HEAVIEST_PASSES);
+        Some(Alert {
+            side,
+            time,
+            budget,
+            heaviest,
+        })
+    }
+
+    /// Reports the frame's alerts, passing each to `log` with how many frames went over budget since the last
+    /// were logged, unless some were logged too recently. Returns the window title to show: the first alert's
+    /// summary when it is logged, `Some(None)` to restore the title once frames are back within budget, or
+    /// `None` to leave the title as it is.
+    pub fn report(
+        &mut self,
+        alerts: &[Alert],
+        mut log: impl FnMut(&Alert, u32),
+    ) -> Option<Option<String>> {
+        let was_over = self.over;
+        self.over = !alerts.is_empty();
+        let first = match alerts.first() {
+            Some(first) => first,
+            None if was_over => return Some(None),
+            None => return None,
+        };
+        let due = self
+            .last_logged
+            .is_none_or(|logged| logged.elapsed() >= LOG_INTERVAL);
+        if !due {
+            self.unlogged += 1;
+            return None;
+        }
+        log(first, self.unlogged);
+        for alert in &alerts[1..] {
+            log(alert, 0);
+        }
+        self.last_logged = Some(Instant::now());
+        self.unlogged = 0;
+        Some(Some(first.summary()))
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        if let Some(timer) = self.timer.as_mut() {
+            timer.destroy(device);
+        }
+    }
+}
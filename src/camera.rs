@@ -0,0 +1,587 @@
+use std::time::Instant;
+
+use ash::vk;
+use cgmath::{
+    Basis3, Deg, InnerSpace, Matrix, Matrix4, Point3, Rad, Rotation, Rotation3, SquareMatrix,
+    Transform, Vector3,
+};
+
+use crate::{bounds, draw, pipeline, units, HelloTriangleApplication, Vertex};
+
+/// The clip planes and speeds cameras start with
+const NEAR: f32 = 0.1;
+const FAR: f32 = 10.0;
+const MOVE_SPEED: f32 = 0.1;
+const DOLLY_SPEED: f32 = 0.1;
+const SMOOTHING: f32 = 0.1;
+/// Orthographic projections can't reach infinity, so with an infinite far plane they clip this many times the
+/// target's distance away instead
+const ORTHOGRAPHIC_FAR_DISTANCES: f32 = 100.0;
+/// Lines across the grid in each direction
+const GRID_LINES: i32 = 21;
+/// Grid lines are kept at least this many logical pixels apart at the camera's target
+const MIN_GRID_PIXELS: f32 = 24.0;
+/// Length of the gizmo's axes on screen, in logical pixels
+const GIZMO_PIXELS: f32 = 60.0;
+/// How far the camera can be pitched from the preset's direction, short of turning over the top of the target
+const MAX_PITCH: Rad<f32> = Rad(1.5);
+
+/// How the view is projected onto the screen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Projection {
+    Perspective,
+    /// Parallel projection, which keeps parallel edges parallel and sizes comparable across the view, as CAD
+    /// packages show mechanical models
+    Orthographic,
+}
+
+impl Projection {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "perspective" => Some(Projection::Perspective),
+            "orthographic" => Some(Projection::Orthographic),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Projection::Perspective => "perspective",
+            Projection::Orthographic => "orthographic",
+        }
+    }
+
+    pub fn toggle(self) -> Self {
+        match self {
+            Projection::Perspective => Projection::Orthographic,
+            Projection::Orthographic => Projection::Perspective,
+        }
+    }
+}
+
+/// Standard directions to look at the scene from, described with Z up and turned to the world's up. See
+/// `units::Up::rotation`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    /// Looking along +Y
+    Front,
+    /// Looking down -Z, with +Y up the screen
+    Top,
+    /// Looking along -X
+    Right,
+    /// Looking down diagonally, equally along every axis
+    Isometric,
+}
+
+impl Preset {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "front" => Some(Preset::Front),
+            "top" => Some(Preset::Top),
+            "right" => Some(Preset::Right),
+            "isometric" => Some(Preset::Isometric),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Preset::Front => "front",
+            Preset::Top => "top",
+            Preset::Right => "right",
+            Preset::Isometric => "isometric",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Preset::Front => Preset::Top,
+            Preset::Top => Preset::Right,
+            Preset::Right => Preset::Isometric,
+            Preset::Isometric => Preset::Front,
+        }
+    }
+
+    /// From the target towards the camera
+    fn direction(&self) -> Vector3<f32> {
+        match self {
+            Preset::Front => Vector3::new(0.0, -1.0, 0.0),
+            Preset::Top => Vector3::new(0.0, 0.0, 1.0),
+            Preset::Right => Vector3::new(1.0, 0.0, 0.0),
+            Preset::Isometric => Vector3::new(1.0, 1.0, 1.0).normalize(),
+        }
+    }
+
+    fn up(&self) -> Vector3<f32> {
+        match self {
+            Preset::Top => Vector3::new(0.0, 1.0, 0.0),
+            _ => Vector3::new(0.0, 0.0, 1.0),
+        }
+    }
+}
+
+/// Where the camera looks from, how it projects what it sees and how fast it moves.
+#[derive(Clone, Copy, Debug)]
+pub struct Camera {
+    pub projection: Projection,
+    pub preset: Preset,
+    pub target: Point3<f32>,
+    /// From the target to the camera
+    pub distance: f32,
+    /// Vertical
+    pub field_of_view: Rad<f32>,
+    /// Distances of the clip planes from the camera, which the orthographic projection clips at too. The far
+    /// plane can be infinitely far away, for open worlds with no furthest distance to draw.
+    pub near: f32,
+    pub far: f32,
+    /// How far each pan moves the target, as a fraction of the distance to it
+    pub move_speed: f32,
+    /// How much closer each line scrolled with the mouse wheel dollies the camera, as a fraction of the distance
+    /// to the target
+    pub dolly_speed: f32,
+    /// Seconds scrolling takes to dolly the camera two thirds of the way, so that it eases in rather than
+    /// jumping. Zero dollies straight away.
+    pub smoothing: f32,
+    /// How far the camera has been turned about the target from the preset's direction, pitched towards the
+    /// preset's up and then yawed about the world's up
+    pub yaw: Rad<f32>,
+    pub pitch: Rad<f32>,
+    /// The world's up, which the presets are turned to and the camera orbits and walks about
+    pub up: units::Up,
+}
+
+impl Camera {
+    pub fn new(field_of_view: Rad<f32>) -> Self {
+        Self {
+            projection: Projection::Perspective,
+            preset: Preset::Isometric,
+            target: Point3::new(0.0, 0.0, 0.0),
+            distance: 1.0,
+            field_of_view,
+            near: NEAR,
+            far: FAR,
+            move_speed: MOVE_SPEED,
+            dolly_speed: DOLLY_SPEED,
+            smoothing: SMOOTHING,
+            yaw: Rad(0.0),
+            pitch: Rad(0.0),
+            up: units::Up::Z,
+        }
+    }
+
+    /// Looks at the centre of the bounds from far enough away along the preset's direction that the whole of
+    /// their bounding sphere fits in the vertical field of view
+    pub fn frame(&mut self, scene_bounds: Option<bounds::Aabb>) {
+        let (target, radius) = match scene_bounds {
+            Some(bounds) => (bounds.center(), bounds.radius()),
+            None => (Point3::new(0.0, 0.0, 0.0), 1.0),
+        };
+        self.target = target;
+        self.distance = radius / (self.field_of_view / 2.0).0.sin();
+    }
+
+    /// Where the camera looks from
+    pub fn eye(&self) -> Point3<f32> {
+        self.target + self.direction() * self.distance
+    }
+
+    pub fn view(&self) -> Matrix4<f32> {
+        Matrix4::look_at_rh(self.eye(), self.target, self.up())
+    }
+
+    /// Returns why the camera's parameters can't be used, if they can't
+    pub fn check(&self) -> Result<(), String> {
+        if let Some(parameter) = Parameter::ALL
+            .iter()
+            .find(|parameter| !parameter.valid(parameter.get(self)))
+        {
+            return Err(format!(
+                "{} can't be {}",
+                parameter.name(),
+                parameter.get(self)
+            ));
+        }
+        if self.near >= self.far {
+            return Err(String::from(
+                "The near plane has to be closer than the far plane",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Turns the camera about its target
+    pub fn orbit(&mut self, yaw: Rad<f32>, pitch: Rad<f32>) {
+        self.yaw += yaw;
+        self.pitch = Rad((self.pitch + pitch).0.clamp(-MAX_PITCH.0, MAX_PITCH.0));
+    }
+
+    /// Moves the target along the ground, to the right of the screen and away from the camera
+    #[cfg(feature = "gamepad")]
+    pub fn walk(&mut self, right: f32, forward: f32) {
+        let right_direction = self.up().cross(self.direction()).normalize();
+        // Along the ground, so looking down doesn't move the target into it
+        let forward_direction = self.up.vector().cross(right_direction);
+        self.target += right_direction * right + forward_direction * forward;
+    }
+
+    /// From the target towards the camera, turned from the preset's direction
+    fn direction(&self) -> Vector3<f32> {
+        self.up.rotation() * self.rotation().rotate_vector(self.preset.direction())
+    }
+
+    fn up(&self) -> Vector3<f32> {
+        self.up.rotation() * self.rotation().rotate_vector(self.preset.up())
+    }
+
+    /// With Z up, like the presets
+    fn rotation(&self) -> Basis3<f32> {
+        let (direction, up) = (self.preset.direction(), self.preset.up());
+        let pitch = Basis3::from_axis_angle(direction.cross(up).normalize(), self.pitch);
+        Basis3::from_angle_z(self.yaw) * pitch
+    }
+
+    /// Orthographic views show as much of the plane through the target as the perspective view does, so that
+    /// switching between them keeps the target the same size.
+    pub fn projection(&self, aspect_ratio: f32) -> Matrix4<f32> {
+        match self.projection {
+            Projection::Perspective if self.far.is_infinite() => {
+                infinite_perspective(self.field_of_view, aspect_ratio, self.near)
+            }
+            Projection::Perspective => {
+                cgmath::perspective(self.field_of_view, aspect_ratio, self.near, self.far)
+            }
+            Projection::Orthographic => {
+                let far = match self.far.is_infinite() {
+                    true => self.distance * ORTHOGRAPHIC_FAR_DISTANCES,
+                    false => self.far,
+                };
+                let half_height = self.target_half_height();
+                let half_width = half_height * aspect_ratio;
+                cgmath::ortho(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    self.near,
+                    far,
+                )
+            }
+        }
+    }
+
+    /// World units each pixel covers at the target, which is the same across the whole view when it is
+    /// orthographic
+    pub fn units_per_pixel(&self, height: u32) -> f32 {
+        2.0 * self.target_half_height() / height.max(1) as f32
+    }
+
+    fn target_half_height(&self) -> f32 {
+        self.distance * (self.field_of_view / 2.0).0.tan()
+    }
+}
+
+/// `cgmath::perspective` with its far plane taken to infinity, so that depth approaches 1 with distance and reaches
+/// it at infinity
+fn infinite_perspective(field_of_view: Rad<f32>, aspect_ratio: f32, near: f32) -> Matrix4<f32> {
+    let focal_length = 1.0 / (field_of_view / 2.0).0.tan();
+    #[rustfmt::skip]
+    let projection = Matrix4::new(
+        focal_length / aspect_ratio, 0.0, 0.0, 0.0,
+        0.0, focal_length, 0.0, 0.0,
+        0.0, 0.0, -1.0, -1.0,
+        0.0, 0.0, -2.0 * near, 0.0,
+    );
+    projection
+}
+
+/// How values in the depth buffer map to view depth, the distance from the camera along its view direction. It
+/// is read from the depth row of the projection, so it holds whether the far plane is finite or infinite, and
+/// whether depth runs from the near plane to the far or is reversed. The projection keeps OpenGL's clip space, so
+/// the depth buffer holds normalized device depth from -1 at the near plane, of which only the half from 0 is
+/// drawn.
+///
+/// Shaders are given `uniform()` and compute view depth as
+///
+/// ```glsl
+/// float viewDepth(vec4 mapping, float depth) {
+///     return mapping.z != 0.0 ? -mapping.y / (-mapping.x - depth) : (mapping.y - depth) / mapping.x;
+/// }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct DepthMapping {
+    /// Normalized device depth is `offset / view depth - scale` in perspective and `offset - scale * view depth`
+    /// in orthographic projections
+    scale: f32,
+    offset: f32,
+    perspective: bool,
+}
+
+impl DepthMapping {
+    pub fn new(projection: &Matrix4<f32>) -> Self {
+        Self {
+            scale: projection.z.z,
+            offset: projection.w.z,
+            perspective: projection.z.w != 0.0,
+        }
+    }
+
+    /// The view depth of a value in the depth buffer, which is infinite at an infinite far plane
+    pub fn view_depth(&self, depth: f32) -> f32 {
+        match self.perspective {
+            // Negated so that depth at an infinite far plane divides by positive zero
+            true => -self.offset / (-self.scale - depth),
+            false => (self.offset - depth) / self.scale,
+        }
+    }
+
+    /// The scale and offset, and whether the projection is perspective, for shaders
+    pub fn uniform(&self) -> [f32; 4] {
+        [self.scale, self.offset, self.perspective as u32 as f32, 0.0]
+    }
+}
+
+/// The camera's numeric settings, which can be changed while the renderer runs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Parameter {
+    /// In degrees
+    FieldOfView,
+    Near,
+    Far,
+    MoveSpeed,
+    DollySpeed,
+    Smoothing,
+}
+
+impl Parameter {
+    pub const ALL: [Parameter; 6] = [
+        Parameter::FieldOfView,
+        Parameter::Near,
+        Parameter::Far,
+        Parameter::MoveSpeed,
+        Parameter::DollySpeed,
+        Parameter::Smoothing,
+    ];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|parameter| parameter.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Parameter::FieldOfView => "fov",
+            Parameter::Near => "near",
+            Parameter::Far => "far",
+            Parameter::MoveSpeed => "move_speed",
+            Parameter::DollySpeed => "dolly_speed",
+            Parameter::Smoothing => "smoothing",
+        }
+    }
+
+    pub fn get(&self, camera: &Camera) -> f32 {
+        match self {
+            Parameter::FieldOfView => Deg::from(camera.field_of_view).0,
+            Parameter::Near => camera.near,
+            Parameter::Far => camera.far,
+            Parameter::MoveSpeed => camera.move_speed,
+            Parameter::DollySpeed => camera.dolly_speed,
+            Parameter::Smoothing => camera.smoothing,
+        }
+    }
+
+    /// Changes the parameter, or returns why the value can't be used, leaving the camera as it was
+    pub fn set(&self, camera: &mut Camera, value: f32) -> Result<(), String> {
+        let mut changed = *camera;
+        self.assign(&mut changed, value);
+        changed.check()?;
+        *camera = changed;
+        Ok(())
+    }
+
+    /// Changes the parameter without checking the camera can use the value, for when others are being changed
+    /// with it. See `Camera::check`.
+    pub fn assign(&self, camera: &mut Camera, value: f32) {
+        match self {
+            Parameter::FieldOfView => camera.field_of_view = Deg(value).into(),
+            Parameter::Near => camera.near = value,
+            Parameter::Far => camera.far = value,
+            Parameter::MoveSpeed => camera.move_speed = value,
+            Parameter::DollySpeed => camera.dolly_speed = value,
+            Parameter::Smoothing => camera.smoothing = value,
+        }
+    }
+
+    /// Whether the camera can use the value
+    fn valid(&self, value: f32) -> bool {
+        // Only the far plane can be infinitely far away
+        let finite = value.is_finite() || (*self == Parameter::Far && value == f32::INFINITY);
+        finite
+            && match self {
+                Parameter::FieldOfView => value > 0.0 && value < 180.0,
+                Parameter::Near | Parameter::Far | Parameter::MoveSpeed => value > 0.0,
+                Parameter::DollySpeed => value > 0.0 && value < 1.0,
+                Parameter::Smoothing => value >= 0.0,
+            }
+    }
+}
+
+/// Moves the camera towards or away from its target as the mouse wheel scrolls, easing over the camera's
+/// smoothing time
+pub struct Dolly {
+    /// The logarithm of how much the camera's distance still has to be multiplied by
+    remaining: f32,
+    updated: Instant,
+}
+
+impl Dolly {
+    pub fn new() -> Self {
+        Self {
+            remaining: 0.0,
+            updated: Instant::now(),
+        }
+    }
+
+    /// Lines are positive scrolling away from the user, which moves the camera closer
+    pub fn scroll(&mut self, camera: &Camera, lines: f32) {
+        self.remaining += lines * (1.0 - camera.dolly_speed).ln();
+    }
+
+    /// Moves the camera the part of the way it has eased since the last update. Returns whether it moved.
+    pub fn update(&mut self, camera: &mut Camera) -> bool {
+        let now = Instant::now();
+        let elapsed = (now - self.updated).as_secs_f32();
+        self.updated = now;
+        if self.remaining == 0.0 {
+            return false;
+        }
+        // Finished once too little is left to see
+        let step = match camera.smoothing > 0.0 && self.remaining.abs() > 1e-4 {
+            true => self.remaining * (1.0 - (-elapsed / camera.smoothing).exp()),
+            false => self.remaining,
+        };
+        self.remaining -= step;
+        camera.distance *= step.exp();
+        true
+    }
+}
+
+/// A grid on the ground plane through the camera's target and a gizmo of the world's axes at its centre. The
+/// ground plane is across the world's up axis, and the grid's spacing is the smallest power of ten metres that
+/// keeps its lines apart on screen. The gizmo stays the same size on screen, in either projection.
+pub struct Grid {
+    buffer: (vk::Buffer, vk::DeviceMemory),
+    /// Indices of the grid's and the gizmo's draws
+    draws: [usize; 2],
+    pub visible: bool,
+}
+
+impl Grid {
+    /// Adds the grid's draws, hidden until `visible` is set
+    pub fn new(
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        draws: &mut Vec<draw::Draw>,
+    ) -> Self {
+        let vertex = |position: [f32; 3], color: [f32; 3]| Vertex {
+            pos: position,
+            color,
+            tex_coord: [0.0, 0.0],
+            normal: [0.0, 0.0, 1.0],
+        };
+        // Lines one unit apart across Z, scaled to the spacing and turned to the world's up when drawn
+        let half = (GRID_LINES / 2) as f32;
+        let gray = [0.35, 0.35, 0.35];
+        let mut vertices = Vec::new();
+        for line in -(GRID_LINES / 2)..=GRID_LINES / 2 {
+            let offset = line as f32;
+            vertices.push(vertex([offset, -half, 0.0], gray));
+            vertices.push(vertex([offset, half, 0.0], gray));
+            vertices.push(vertex([-half, offset, 0.0], gray));
+            vertices.push(vertex([half, offset, 0.0], gray));
+        }
+        let grid_count = vertices.len();
+        for axis in 0..3 {
+            let mut end = [0.0; 3];
+            end[axis] = 1.0;
+            vertices.push(vertex([0.0; 3], end));
+            vertices.push(vertex(end, end));
+        }
+
+        let buffer = HelloTriangleApplication::create_device_local_buffer(
+            device,
+            &vertices,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            command_pool,
+            queue,
+            device_memory_properties,
+        );
+        let lines = pipeline::Config::unlit(vk::PrimitiveTopology::LINE_LIST);
+        // Neither has bounds, so they are never culled and don't change the scene's framing
+        draws.push(draw::Draw {
+            instance_count: 0,
+            ..draw::Draw::new(lines, buffer.0, grid_count as u32)
+        });
+        draws.push(draw::Draw {
+            instance_count: 0,
+            line_width: 2.0,
+            vertex_offset: grid_count as i32,
+            ..draw::Draw::new(lines, buffer.0, (vertices.len() - grid_count) as u32)
+        });
+
+        Self {
+            buffer,
+            draws: [draws.len() - 2, draws.len() - 1],
+            visible: false,
+        }
+    }
+
+    /// Moves the grid and gizmo into the scene under the camera's target and scales them for the camera's
+    /// projection. `model` is the scene's model matrix, which the draws are transformed by.
+    pub fn update(
+        &self,
+        draws: &mut [draw::Draw],
+        camera: &Camera,
+        world: &units::Convention,
+        model: &Matrix4<f32>,
+        height: u32,
+        scene: usize,
+    ) {
+        let units_per_pixel = camera.units_per_pixel(height);
+        let metres = world.metres(MIN_GRID_PIXELS * units_per_pixel);
+        let spacing = world.units(10f32.powf(metres.log10().ceil()));
+        let local_target = model.invert().map_or(camera.target, |inverse| {
+            inverse.transform_point(camera.target)
+        });
+        // Snapped to the grid's spacing across the ground, so that its lines stay put as the target moves
+        let rotation = world.up.rotation();
+        let ground = rotation.transpose() * (local_target - Point3::new(0.0, 0.0, 0.0));
+        let snap = |value: f32| (value / spacing).round() * spacing;
+        let origin = rotation * Vector3::new(snap(ground.x), snap(ground.y), ground.z);
+        let gizmo_length = GIZMO_PIXELS * units_per_pixel;
+        let transforms = [
+            Matrix4::from_translation(origin)
+                * Matrix4::from(rotation)
+                * Matrix4::from_scale(spacing),
+            Matrix4::from_translation(local_target - Point3::new(0.0, 0.0, 0.0))
+                * Matrix4::from_scale(gizmo_length),
+        ];
+        for (&index, transform) in self.draws.iter().zip(transforms) {
+            let draw = &mut draws[index];
+            draw.transform = transform;
+            draw.scene = scene;
+            // Hidden by drawing no instances, so every draw keeps its index
+            draw.instance_count = self.visible as u32;
+        }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_buffer(self.buffer.0, None);
+            device.free_memory(self.buffer.1, None);
+        }
+    }
+}
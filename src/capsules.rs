@@ -0,0 +1,146 @@
+use std::cmp::Ordering;
+
+use cgmath::{InnerSpace, Matrix4, MetricSpace, Point3, Transform, Vector3, Zero};
+
+use crate::bounds;
+
+/// The most capsules shade a frame, matching `MAX_CAPSULES` in `frag.glsl`. Those nearest the camera's target
+/// are kept when there are more.
+pub const MAX_CAPSULES: usize = 16;
+
+/// A capsule roughly filling part of a dynamic draw, such as a character's body or one of its limbs, that
+/// shadows what is around it from the light and occludes its ambient light. Given in the draw's own space, see
+/// `Draw::capsules`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Capsule {
+    pub start: Point3<f32>,
+    pub end: Point3<f32>,
+    pub radius: f32,
+}
+
+impl Capsule {
+    /// A capsule along the box's longest side, as wide as its two shorter sides on average
+    pub fn fit(aabb: &bounds::Aabb) -> Self {
+        let size = aabb.max - aabb.min;
+        let centre = aabb.min + size / 2.0;
+        let axis = (0..3)
+            .max_by(|&a, &b| size[a].partial_cmp(&size[b]).unwrap_or(Ordering::Equal))
+            .unwrap_or(0);
+        let radius = (0..3)
+            .filter(|&side| side != axis)
+            .map(|side| size[side])
+            .sum::<f32>()
+            / 4.0;
+        let mut half_length = Vector3::zero();
+        half_length[axis] = (size[axis] / 2.0 - radius).max(0.0);
+        Self {
+            start: centre - half_length,
+            end: centre + half_length,
+            radius,
+        }
+    }
+
+    /// The capsule moved by the transform. Capsules stay round, so the radius is scaled by the transform's
+    /// largest scale.
+    pub fn transformed(&self, transform: &Matrix4<f32>) -> Self {
+        let scale = [transform.x, transform.y, transform.z]
+            .iter()
+            .map(|column| column.truncate().magnitude())
+            .fold(0.0, f32::max);
+        Self {
+            start: transform.transform_point(self.start),
+            end: transform.transform_point(self.end),
+            radius: self.radius * scale,
+        }
+    }
+
+    /// How far the point is from the capsule's axis
+    fn distance_to_axis(&self, point: Point3<f32>) -> f32 {
+        let axis = self.end - self.start;
+        let along = match axis.magnitude2() {
+            length if length > 0.0 => ((point - self.start).dot(axis) / length).clamp(0.0, 1.0),
+            _ => 0.0,
+        };
+        point.distance(self.start + axis * along)
+    }
+}
+
+/// Cheap analytic shadows and ambient occlusion from the capsules of dynamic draws, to ground characters
+/// without a shadow pass. Each fragment lit by the light traces a ray towards it past every capsule, and the
+/// shadow softens with the distance the ray travels, so it is sharp where a capsule touches the ground and
+/// spreads out further away.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CapsuleShadows {
+    pub enabled: bool,
+    /// How much of the light and the ambient light a capsule blocks at most, from 0 to 1
+    pub shadow_strength: f32,
+    pub occlusion_strength: f32,
+    /// How wide a shadow's soft edge is a unit away from the capsule casting it
+    pub penumbra: f32,
+}
+
+impl Default for CapsuleShadows {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            shadow_strength: 0.8,
+            occlusion_strength: 0.6,
+            penumbra: 0.3,
+        }
+    }
+}
+
+impl CapsuleShadows {
+    /// The uniforms for shading with the capsules, which are in world space. The ones nearest the focus are
+    /// kept when there are more than `MAX_CAPSULES`.
+    pub fn uniforms<I: IntoIterator<Item = Capsule>>(
+        &self,
+        capsules: I,
+        focus: Point3<f32>,
+    ) -> Uniforms {
+        let mut uniforms = Uniforms {
+            starts: [[0.0; 4]; MAX_CAPSULES],
+            ends: [[0.0; 4]; MAX_CAPSULES],
+            flags: [0; 4],
+            shading: [
+                self.shadow_strength,
+                self.occlusion_strength,
+                // The shader divides by it
+                self.penumbra.max(0.001),
+                0.0,
+            ],
+        };
+        if !self.enabled {
+            return uniforms;
+        }
+        let mut capsules: Vec<Capsule> = capsules.into_iter().collect();
+        if capsules.len() > MAX_CAPSULES {
+            capsules.sort_by(|a, b| {
+                a.distance_to_axis(focus)
+                    .partial_cmp(&b.distance_to_axis(focus))
+                    .unwrap_or(Ordering::Equal)
+            });
+            capsules.truncate(MAX_CAPSULES);
+        }
+        for (i, capsule) in capsules.iter().enumerate() {
+            let (start, end) = (capsule.start, capsule.end);
+            uniforms.starts[i] = [start.x, start.y, start.z, capsule.radius];
+            uniforms.ends[i] = [end.x, end.y, end.z, 0.0];
+        }
+        uniforms.flags[0] = capsules.len() as u32;
+        uniforms
+    }
+}
+
+/// Matches the `capsule` members of `UniformBufferObject` in `frag.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Uniforms {
+    /// The radius is in w
+    starts: [[f32; 4]; MAX_CAPSULES],
+    ends: [[f32; 4]; MAX_CAPSULES],
+    /// x is how many capsules are used
+    flags: [u32; 4],
+    /// The shadow strength, occlusion strength and penumbra
+    shading: [f32; 4],
+}
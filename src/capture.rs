@@ -0,0 +1,336 @@
+use std::f32::consts::PI;
+use std::path::{Path, PathBuf};
+
+use ash::vk;
+use cgmath::{Deg, InnerSpace, Matrix4, Point3, Vector3};
+
+use crate::features::SwapchainContext;
+use crate::{images, pipeline, HelloTriangleApplication, ImageDescription};
+
+/// File name suffixes of the faces, in Vulkan's cubemap face order: +X, -X, +Y, -Y, +Z, -Z
+pub const FACE_NAMES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+
+const BYTES_PER_PIXEL: u32 = 4;
+
+/// The direction each face looks in and its up. The renderer's projection isn't flipped, so with these the rows
+/// of each rendered face run the way a cubemap's do.
+fn face_axes() -> [(Vector3<f32>, Vector3<f32>); 6] {
+    let (x, y, z) = (Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z());
+    [(x, -y), (-x, -y), (y, z), (-y, -z), (z, -y), (-z, -y)]
+}
+
+/// The views looking out of each face of a cube centred on `position`, in face order.
+pub fn face_views(position: Point3<f32>) -> [Matrix4<f32>; 6] {
+    face_axes().map(|(forward, up)| Matrix4::look_to_rh(position, forward, up))
+}
+
+/// A square projection covering exactly one face, with the same depth range as the renderer's own.
+pub fn face_projection() -> Matrix4<f32> {
+    cgmath::perspective(Deg(90.0), 1.0, 0.1, 10.0)
+}
+
+/// The six faces of the scene as seen from a point, each `size` pixels square.
+pub struct Cubemap {
+    pub size: u32,
+    pub faces: Vec<images::Image>,
+}
+
+impl Cubemap {
+    /// Saves each face as `<name>_<face>.png` in `directory`, or `.pam` without the `images` feature. Returns the
+    /// paths saved to.
+    pub fn save_faces(&self, directory: &Path, name: &str) -> Result<Vec<PathBuf>, String> {
+        self.faces
+            .iter()
+            .zip(FACE_NAMES.iter())
+            .map(|(face, face_name)| {
+                let path = directory.join(format!("{}_{}.{}", name, face_name, images::EXTENSION));
+                images::save(face, &path)?;
+                Ok(path)
+            })
+            .collect()
+    }
+
+    /// The colour seen in a world space direction, using the same face selection as cubemap sampling.
+    pub fn sample(&self, direction: Vector3<f32>) -> [u8; 4] {
+        let abs = Vector3::new(direction.x.abs(), direction.y.abs(), direction.z.abs());
+        let (face, s, t, major) = if abs.x >= abs.y && abs.x >= abs.z {
+            if direction.x > 0.0 {
+                (0, -direction.z, -direction.y, abs.x)
+            } else {
+                (1, direction.z, -direction.y, abs.x)
+            }
+        } else if abs.y >= abs.z {
+            if direction.y > 0.0 {
+                (2, direction.x, direction.z, abs.y)
+            } else {
+                (3, direction.x, -direction.z, abs.y)
+            }
+        } else if direction.z > 0.0 {
+            (4, direction.x, -direction.y, abs.z)
+        } else {
+            (5, -direction.x, -direction.y, abs.z)
+        };
+
+        let to_pixel = |coordinate: f32| {
+            let pixel = ((coordinate / major + 1.0) / 2.0 * self.size as f32) as u32;
+            pixel.min(self.size - 1)
+        };
+        self.faces[face].get_pixel(to_pixel(s), to_pixel(t))
+    }
+
+    /// Stitches the faces into an equirectangular panorama twice as wide as it is high. The scene's up is +Z,
+    /// so the top row of the panorama looks straight up and its centre looks along +X.
+    pub fn to_panorama(&self, width: u32) -> images::Image {
+        let height = (width / 2).max(1);
+        images::Image::from_fn(width, height, |column, row| {
+            let longitude = ((column as f32 + 0.5) / width as f32 - 0.5) * 2.0 * PI;
+            let latitude = (0.5 - (row as f32 + 0.5) / height as f32) * PI;
+            // Longitude increases to the left so the panorama isn't mirrored when viewed from inside
+            let direction = Vector3::new(
+                latitude.cos() * longitude.cos(),
+                -latitude.cos() * longitude.sin(),
+                latitude.sin(),
+            );
+            self.sample(direction.normalize())
+        })
+    }
+}
+
+/// Whether faces can be read back from targets of the format, which must be one that saves as it's stored
+pub fn check_format(format: vk::Format) -> Result<(), String> {
+    match format {
+        vk::Format::B8G8R8A8_SRGB
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::R8G8B8A8_UNORM => Ok(()),
+        format => Err(format!("Can't export captures in {:?}", format)),
+    }
+}
+
+/// A host visible buffer a face `size` pixels square can be copied into
+pub fn create_readback(context: &SwapchainContext, size: u32) -> (vk::Buffer, vk::DeviceMemory) {
+    HelloTriangleApplication::create_buffer(
+        context.device,
+        (size * size * BYTES_PER_PIXEL) as vk::DeviceSize,
+        vk::BufferUsageFlags::TRANSFER_DST,
+        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        context.device_memory_properties,
+    )
+}
+
+/// Records copying the layer of a face `size` pixels square, in the `TRANSFER_SRC_OPTIMAL` layout, into the
+/// readback buffer
+pub fn record_copy(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    layer: u32,
+    size: u32,
+    readback: vk::Buffer,
+) {
+    let region = vk::BufferImageCopy::builder()
+        .image_subresource(vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: layer,
+            layer_count: 1,
+        })
+        .image_extent(vk::Extent3D {
+            width: size,
+            height: size,
+            depth: 1,
+        })
+        .build();
+    let barrier = vk::BufferMemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(vk::AccessFlags::HOST_READ)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .buffer(readback)
+        .size(vk::WHOLE_SIZE)
+        .build();
+
+    unsafe {
+        device.cmd_copy_image_to_buffer(
+            command_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            readback,
+            &[region],
+        );
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::HOST,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[barrier],
+            &[],
+        );
+    }
+}
+
+/// The face last copied into the readback buffer's memory from a target of the format. Must only be called once
+/// the copy has finished.
+pub fn read_face(
+    device: &ash::Device,
+    memory: vk::DeviceMemory,
+    size: u32,
+    format: vk::Format,
+) -> images::Image {
+    let pixel_count = (size * size) as usize;
+    let mut bytes = vec![0u8; pixel_count * BYTES_PER_PIXEL as usize];
+    unsafe {
+        let data = device
+            .map_memory(
+                memory,
+                0,
+                bytes.len() as vk::DeviceSize,
+                vk::MemoryMapFlags::empty(),
+            )
+            .expect("Mapping capture readback memory") as *const u8;
+        data.copy_to_nonoverlapping(bytes.as_mut_ptr(), bytes.len());
+        device.unmap_memory(memory);
+    }
+
+    // Colours are stored sRGB encoded whatever the format, either by the format or by the shaders
+    let bgr = matches!(
+        format,
+        vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM
+    );
+    let pixels = bytes
+        .chunks_exact(BYTES_PER_PIXEL as usize)
+        .flat_map(|texel| {
+            if bgr {
+                [texel[2], texel[1], texel[0], 255]
+            } else {
+                [texel[0], texel[1], texel[2], 255]
+            }
+        })
+        .collect();
+    images::Image {
+        width: size,
+        height: size,
+        pixels,
+    }
+}
+
+/// A square image each face is rendered into and the host visible buffer it is copied back to.
+pub struct Target {
+    pub size: u32,
+    pub render_pass: vk::RenderPass,
+    pub framebuffer: vk::Framebuffer,
+    format: vk::Format,
+    color: (vk::Image, vk::DeviceMemory, vk::ImageView),
+    depth: (vk::Image, vk::DeviceMemory, vk::ImageView),
+    readback: (vk::Buffer, vk::DeviceMemory),
+}
+
+impl Target {
+    /// Creates a target with the swapchain's formats, so the scene's pipelines can draw into it.
+    pub fn new(context: &SwapchainContext, size: u32) -> Result<Self, String> {
+        check_format(context.format)?;
+
+        let device = context.device;
+        let render_pass = pipeline::create_offscreen_render_pass(
+            device,
+            context.format,
+            context.depth_format,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_READ,
+            &pipeline::PassConfig::default(),
+        );
+        let create_attachment = |format, usage, aspect| {
+            let (image, memory) = HelloTriangleApplication::create_image(
+                device,
+                &ImageDescription {
+                    width: size,
+                    height: size,
+                    format,
+                    tiling: vk::ImageTiling::OPTIMAL,
+                    usage,
+                    memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                },
+                context.device_memory_properties,
+            );
+            let view = HelloTriangleApplication::create_image_view(device, image, format, aspect);
+            (image, memory, view)
+        };
+        let color = create_attachment(
+            context.format,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+            vk::ImageAspectFlags::COLOR,
+        );
+        let depth = create_attachment(
+            context.depth_format,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            vk::ImageAspectFlags::DEPTH,
+        );
+
+        let attachments = [color.2, depth.2];
+        let framebuffer = unsafe {
+            device
+                .create_framebuffer(
+                    &vk::FramebufferCreateInfo::builder()
+                        .render_pass(render_pass)
+                        .attachments(&attachments)
+                        .width(size)
+                        .height(size)
+                        .layers(1),
+                    None,
+                )
+                .expect("Capture frame buffer")
+        };
+
+        let readback = create_readback(context, size);
+
+        Ok(Self {
+            size,
+            render_pass,
+            framebuffer,
+            format: context.format,
+            color,
+            depth,
+            readback,
+        })
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        vk::Extent2D {
+            width: self.size,
+            height: self.size,
+        }
+    }
+
+    /// Records copying the rendered face into the readback buffer, after the render pass has ended.
+    pub fn record_copy(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        record_copy(
+            device,
+            command_buffer,
+            self.color.0,
+            0,
+            self.size,
+            self.readback.0,
+        );
+    }
+
+    /// The face last copied into the readback buffer. Must only be called once the copy has finished.
+    pub fn read_face(&self, device: &ash::Device) -> images::Image {
+        read_face(device, self.readback.1, self.size, self.format)
+    }
+
+    pub fn destroy(self, device: &ash::Device) {
+        unsafe {
+            device.destroy_framebuffer(self.framebuffer, None);
+            for (image, memory, view) in [self.color, self.depth] {
+                device.destroy_image_view(view, None);
+                device.destroy_image(image, None);
+                device.free_memory(memory, None);
+            }
+            device.destroy_buffer(self.readback.0, None);
+            device.free_memory(self.readback.1, None);
+            device.destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
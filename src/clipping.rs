@@ -0,0 +1,108 @@
+use cgmath::{InnerSpace, Point3, Vector3};
+
+/// How many planes can clip the scene at once. Matches the size of `clipPlanes` in `frag.glsl`.
+pub const MAX_PLANES: usize = 4;
+
+/// A plane in the scene's space, before its model matrix, so that it turns with the scene. Points where
+/// `dot(normal, point) < distance` are cut away.
+#[derive(Clone, Copy, Debug)]
+pub struct Plane {
+    pub normal: Vector3<f32>,
+    pub distance: f32,
+}
+
+impl Plane {
+    /// The plane through `point` that keeps the side `normal` points towards
+    pub fn through(point: Point3<f32>, normal: Vector3<f32>) -> Self {
+        let normal = normal.normalize();
+        Self {
+            normal,
+            distance: normal.dot(point - Point3::new(0.0, 0.0, 0.0)),
+        }
+    }
+}
+
+/// Planes cutting away part of every mesh to show what is inside. Meshes are cut in their fragment shader, and
+/// when `capped` each opaque, back-face culled mesh is drawn a second time with only its back faces, moved onto
+/// the planes and shaded in `cap_color`. That closes the cut across solid meshes, though meshes with holes in them
+/// show caps where their insides show through. Lines, points and billboards aren't cut.
+#[derive(Clone, Debug)]
+pub struct Clipping {
+    /// Only the first `MAX_PLANES` are used
+    pub planes: Vec<Plane>,
+    pub capped: bool,
+    /// In linear colour
+    pub cap_color: [f32; 3],
+    /// The axis `cycle_section` last cut along
+    section: Option<usize>,
+}
+
+impl Default for Clipping {
+    fn default() -> Self {
+        Self {
+            planes: Vec::new(),
+            capped: true,
+            cap_color: [0.8, 0.25, 0.2],
+            section: None,
+        }
+    }
+}
+
+/// Matches the `clipping` members of `UniformBufferObject` in `frag.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Uniforms {
+    planes: [[f32; 4]; MAX_PLANES],
+    cap_color: [f32; 4],
+    /// x is how many planes are used
+    flags: [u32; 4],
+}
+
+impl Clipping {
+    /// Whether meshes need drawing a second time for their caps
+    pub fn caps(&self) -> bool {
+        self.capped && !self.planes.is_empty()
+    }
+
+    /// Cuts the scene in half through `center`, in the scene's space, along the next axis, after Z going back to
+    /// not cutting at all. Replaces any other planes, and returns the axis cut along.
+    pub fn cycle_section(&mut self, center: Point3<f32>) -> Option<usize> {
+        self.section = match self.section {
+            None => Some(0),
+            Some(axis) if axis < 2 => Some(axis + 1),
+            Some(_) => None,
+        };
+        self.planes.clear();
+        if let Some(axis) = self.section {
+            let mut normal = Vector3::new(0.0, 0.0, 0.0);
+            normal[axis] = 1.0;
+            self.planes.push(Plane::through(center, normal));
+        }
+        self.section
+    }
+
+    /// Moves every plane along its normal, towards the side it keeps for positive distances
+    pub fn offset(&mut self, distance: f32) {
+        for plane in self.planes.iter_mut() {
+            plane.distance += distance;
+        }
+    }
+
+    pub fn uniforms(&self) -> Uniforms {
+        let mut planes = [[0.0; 4]; MAX_PLANES];
+        for (uniform, plane) in planes.iter_mut().zip(self.planes.iter()) {
+            *uniform = [
+                plane.normal.x,
+                plane.normal.y,
+                plane.normal.z,
+                plane.distance,
+            ];
+        }
+        let [r, g, b] = self.cap_color;
+        Uniforms {
+            planes,
+            cap_color: [r, g, b, 1.0],
+            flags: [self.planes.len().min(MAX_PLANES) as u32, 0, 0, 0],
+        }
+    }
+}
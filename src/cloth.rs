@@ -0,0 +1,382 @@
+use std::mem::size_of;
+use std::path::Path;
+use std::time::Instant;
+
+use ash::vk;
+use cgmath::{Matrix4, Point3};
+
+use crate::{bounds, draw, material, mesh, postprocess, util, HelloTriangleApplication};
+
+/// Particles updated per workgroup in each direction, matching `local_size_x` and `local_size_y` in the cloth
+/// shaders
+const WORKGROUP_SIZE: u32 = 8;
+/// Steps simulated each frame. Even so that the latest state always ends up in the first state buffer.
+const STEPS_PER_FRAME: u32 = 16;
+/// The longest time simulated in a frame. Slower frames run the cloth in slow motion rather than taking steps
+/// so long the springs blow up.
+const MAX_FRAME_TIME: f32 = 1.0 / 60.0;
+/// Mass of each particle
+const PARTICLE_MASS: f32 = 0.01;
+/// Floats in each of the cloth's vertices: position, texture coordinate and normal
+const VERTEX_FLOATS: usize = 8;
+
+/// Matches `Particle` in `cloth_comp.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Particle {
+    /// w is the inverse mass, zero for pinned particles
+    position: [f32; 4],
+    previous: [f32; 4],
+}
+
+/// Matches the push constants in `cloth_comp.glsl` and `cloth_vertices_comp.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Step {
+    columns: u32,
+    rows: u32,
+    spacing: f32,
+    delta_time: f32,
+    time: f32,
+}
+
+impl Step {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+}
+
+/// A square of cloth hanging from its top edge and blowing in the wind, simulated as a grid of particles joined
+/// by springs in compute shaders. Each step reads the particles from one state buffer and writes them to the
+/// other, then a last pass writes the mesh's vertices with freshly worked out normals into a vertex buffer
+/// drawn like any other mesh. Updates are recorded into the frame's command buffer ahead of the render pass.
+///
+/// The cloth is simulated in its own space with +Z up, so its draw's transform should keep +Z up.
+pub struct Cloth {
+    columns: u32,
+    rows: u32,
+    spacing: f32,
+    state_buffers: [(vk::Buffer, vk::DeviceMemory); 2],
+    vertex_buffer: (vk::Buffer, vk::DeviceMemory),
+    index_buffer: (vk::Buffer, vk::DeviceMemory),
+    index_count: u32,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    /// Sets stepping from the first state buffer to the second and back
+    step_sets: [vk::DescriptorSet; 2],
+    vertex_set: vk::DescriptorSet,
+    layout: vk::PipelineLayout,
+    step_pipeline: vk::Pipeline,
+    vertex_pipeline: vk::Pipeline,
+    size: f32,
+    start_time: Instant,
+    last_update: Option<Instant>,
+}
+
+impl Cloth {
+    /// A `size` wide square of cloth made of `resolution` by `resolution` particles, hanging down from the
+    /// origin along +X.
+    pub fn new(
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        resolution: u32,
+        size: f32,
+    ) -> Self {
+        let (columns, rows) = (resolution.max(2), resolution.max(2));
+        let spacing = size / (columns - 1) as f32;
+        let particles: Vec<Particle> = (0..rows)
+            .flat_map(|row| {
+                (0..columns).map(move |column| {
+                    let position = [column as f32 * spacing, 0.0, -(row as f32) * spacing];
+                    let inverse_mass = if row == 0 { 0.0 } else { 1.0 / PARTICLE_MASS };
+                    Particle {
+                        position: [position[0], position[1], position[2], inverse_mass],
+                        previous: [position[0], position[1], position[2], 0.0],
+                    }
+                })
+            })
+            .collect();
+        let state_buffers = [0, 1].map(|_| {
+            HelloTriangleApplication::create_device_local_buffer(
+                device,
+                &particles,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+                command_pool,
+                queue,
+                device_memory_properties,
+            )
+        });
+        // Written by the simulation before it is first drawn
+        let vertex_buffer = HelloTriangleApplication::create_buffer(
+            device,
+            (particles.len() * VERTEX_FLOATS * size_of::<f32>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::VERTEX_BUFFER,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            device_memory_properties,
+        );
+
+        let vertex = |column: u32, row: u32| (row * columns + column) as u16;
+        let indices: Vec<u16> = (0..rows - 1)
+            .flat_map(|row| (0..columns - 1).map(move |column| (column, row)))
+            .flat_map(|(column, row)| {
+                let (a, b) = (vertex(column, row), vertex(column + 1, row));
+                let (c, d) = (vertex(column + 1, row + 1), vertex(column, row + 1));
+                vec![a, b, c, c, d, a]
+            })
+            .collect();
+        let index_buffer = HelloTriangleApplication::create_device_local_buffer(
+            device,
+            &indices,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            command_pool,
+            queue,
+            device_memory_properties,
+        );
+
+        let bindings: Vec<vk::DescriptorSetLayoutBinding> = (0..2)
+            .map(|binding| {
+                vk::DescriptorSetLayoutBinding::builder()
+                    .binding(binding)
+                    .descriptor_count(1)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                    .build()
+            })
+            .collect();
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+                    None,
+                )
+                .expect("Cloth descriptor set layout")
+        };
+        let pool_sizes = [vk::DescriptorPoolSize::builder()
+            .ty(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(6)
+            .build()];
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::builder()
+                        .pool_sizes(&pool_sizes)
+                        .max_sets(3),
+                    None,
+                )
+                .expect("Cloth descriptor pool")
+        };
+        let set_layouts = [descriptor_set_layout; 3];
+        let sets = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::builder()
+                        .descriptor_pool(descriptor_pool)
+                        .set_layouts(&set_layouts),
+                )
+                .expect("Cloth descriptor sets")
+        };
+        let (first, second) = (state_buffers[0].0, state_buffers[1].0);
+        for (&set, buffers) in
+            sets.iter()
+                .zip([[first, second], [second, first], [first, vertex_buffer.0]])
+        {
+            let buffer_infos = buffers.map(|buffer| {
+                [vk::DescriptorBufferInfo::builder()
+                    .buffer(buffer)
+                    .offset(0)
+                    .range(vk::WHOLE_SIZE)
+                    .build()]
+            });
+            let writes: Vec<vk::WriteDescriptorSet> = buffer_infos
+                .iter()
+                .enumerate()
+                .map(|(binding, buffer_info)| {
+                    vk::WriteDescriptorSet::builder()
+                        .dst_set(set)
+                        .dst_binding(binding as u32)
+                        .dst_array_element(0)
+                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                        .buffer_info(buffer_info)
+                        .build()
+                })
+                .collect();
+            unsafe { device.update_descriptor_sets(&writes, &[]) };
+        }
+
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(size_of::<Step>() as u32)
+            .build()];
+        let set_layouts = [descriptor_set_layout];
+        let layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::builder()
+                        .set_layouts(&set_layouts)
+                        .push_constant_ranges(&push_constant_ranges),
+                    None,
+                )
+                .expect("Cloth pipeline layout")
+        };
+        let create_pipeline = |name: &str| {
+            let path = Path::new(env!("OUT_DIR")).join(name);
+            postprocess::create_compute_pipeline(device, layout, &util::read_shader_code(&path))
+        };
+
+        Self {
+            columns,
+            rows,
+            spacing,
+            state_buffers,
+            vertex_buffer,
+            index_buffer,
+            index_count: indices.len() as u32,
+            descriptor_set_layout,
+            descriptor_pool,
+            step_sets: [sets[0], sets[1]],
+            vertex_set: sets[2],
+            layout,
+            step_pipeline: create_pipeline("cloth_comp.spv"),
+            vertex_pipeline: create_pipeline("cloth_vertices_comp.spv"),
+            size,
+            start_time: Instant::now(),
+            last_update: None,
+        }
+    }
+
+    /// A draw of the cloth placed by `transform`, lit and textured like other meshes and shaded on both sides
+    pub fn draw(&self, transform: Matrix4<f32>) -> draw::Draw {
+        let attributes = mesh::VertexAttributes {
+            color: false,
+            tex_coord: true,
+            lightmap_coord: false,
+            occlusion: false,
+        };
+        // However the cloth blows it stays within its size of the edge it hangs from
+        let bounds = bounds::Aabb::from_points(vec![
+            Point3::new(-self.size, -self.size, -self.size),
+            Point3::new(2.0 * self.size, self.size, 0.0),
+        ]);
+        draw::Draw {
+            transform,
+            bounds: bounds.map(bounds::Bounds::new),
+            ..draw::Draw::indexed(
+                material::Material {
+                    double_sided: true,
+                    ..material::Material::default()
+                }
+                .pipeline_config(attributes),
+                self.vertex_buffer.0,
+                self.index_buffer.0,
+                self.index_count,
+            )
+        }
+    }
+
+    /// Records the frame's steps of the simulation and writing the vertices. Must be recorded outside of a render
+    /// pass, before the cloth is drawn.
+    pub fn record_update(&mut self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        let now = Instant::now();
+        let frame_time = self
+            .last_update
+            .map_or(0.0, |last| (now - last).as_secs_f32().min(MAX_FRAME_TIME));
+        self.last_update = Some(now);
+        let step = Step {
+            columns: self.columns,
+            rows: self.rows,
+            spacing: self.spacing,
+            delta_time: frame_time / STEPS_PER_FRAME as f32,
+            time: (now - self.start_time).as_secs_f32(),
+        };
+        let groups = |count: u32| count.div_ceil(WORKGROUP_SIZE);
+
+        let barrier = |src_stage, src_access, dst_stage, dst_access| unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                src_stage,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                &[vk::MemoryBarrier::builder()
+                    .src_access_mask(src_access)
+                    .dst_access_mask(dst_access)
+                    .build()],
+                &[],
+                &[],
+            )
+        };
+        let dispatch = |pipeline, set| unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline);
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.layout,
+                0,
+                &[set],
+                &[],
+            );
+            device.cmd_dispatch(command_buffer, groups(self.columns), groups(self.rows), 1);
+        };
+
+        unsafe {
+            device.cmd_push_constants(
+                command_buffer,
+                self.layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                step.as_bytes(),
+            );
+        }
+        // The previous frame's steps wrote the state and its draw read the vertices this frame overwrites
+        barrier(
+            vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::AccessFlags::SHADER_WRITE,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+        );
+        for step in 0..STEPS_PER_FRAME {
+            if step > 0 {
+                barrier(
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::AccessFlags::SHADER_WRITE,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+                );
+            }
+            dispatch(self.step_pipeline, self.step_sets[step as usize % 2]);
+        }
+        barrier(
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_WRITE,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_READ,
+        );
+        dispatch(self.vertex_pipeline, self.vertex_set);
+        barrier(
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_WRITE,
+            vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+        );
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline(self.step_pipeline, None);
+            device.destroy_pipeline(self.vertex_pipeline, None);
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            for &(buffer, memory) in self
+                .state_buffers
+                .iter()
+                .chain([self.vertex_buffer, self.index_buffer].iter())
+            {
+                device.destroy_buffer(buffer, None);
+                device.free_memory(memory, None);
+            }
+        }
+    }
+}
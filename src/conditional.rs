@@ -0,0 +1,64 @@
+use std::ffi::CStr;
+use std::mem;
+
+use ash::vk;
+
+/// The stage and access to wait for before a buffer written on the GPU is read as a condition
+pub const STAGE: vk::PipelineStageFlags = vk::PipelineStageFlags::CONDITIONAL_RENDERING_EXT;
+pub const ACCESS: vk::AccessFlags = vk::AccessFlags::CONDITIONAL_RENDERING_READ_EXT;
+/// Buffers conditions are read from must be created with this usage
+pub const BUFFER_USAGE: vk::BufferUsageFlags = vk::BufferUsageFlags::CONDITIONAL_RENDERING_EXT;
+
+/// `VK_EXT_conditional_rendering`, which skips the draws and dispatches recorded between `begin` and `end` when a
+/// 32-bit value in a buffer is zero. The value is read on the GPU when the commands execute, so batches can be
+/// skipped on the results of culling or occlusion queries written earlier in the frame without the CPU waiting
+/// to read them back.
+#[derive(Clone)]
+pub struct ConditionalRendering {
+    conditional_rendering_fn: vk::ExtConditionalRenderingFn,
+}
+
+impl ConditionalRendering {
+    pub fn new(instance: &ash::Instance, device: &ash::Device) -> Self {
+        let conditional_rendering_fn = vk::ExtConditionalRenderingFn::load(|name| unsafe {
+            mem::transmute(instance.get_device_proc_addr(device.handle(), name.as_ptr()))
+        });
+        Self {
+            conditional_rendering_fn,
+        }
+    }
+
+    pub fn name() -> &'static CStr {
+        vk::ExtConditionalRenderingFn::name()
+    }
+
+    /// Skips the commands recorded until `end` unless the `u32` at `offset` in `buffer` is non-zero, or when
+    /// `inverted` unless it is zero. Conditions begun in a render pass must end in the same subpass.
+    pub fn begin(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        inverted: bool,
+    ) {
+        let flags = match inverted {
+            true => vk::ConditionalRenderingFlagsEXT::INVERTED,
+            false => vk::ConditionalRenderingFlagsEXT::empty(),
+        };
+        let begin_info = vk::ConditionalRenderingBeginInfoEXT::builder()
+            .buffer(buffer)
+            .offset(offset)
+            .flags(flags);
+        unsafe {
+            self.conditional_rendering_fn
+                .cmd_begin_conditional_rendering_ext(command_buffer, &*begin_info)
+        };
+    }
+
+    pub fn end(&self, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            self.conditional_rendering_fn
+                .cmd_end_conditional_rendering_ext(command_buffer)
+        };
+    }
+}
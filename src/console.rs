@@ -0,0 +1,491 @@
+use std::any::Any;
+use std::collections::VecDeque;
+
+use ash::vk;
+use winit::event::VirtualKeyCode;
+
+use crate::features::{RenderFeature, SwapchainContext};
+use crate::font;
+use crate::{framegraph, hooks, postprocess, HelloTriangleApplication};
+
+/// Lines of output shown above the input line
+const OUTPUT_LINES: usize = 10;
+/// Output kept for scrolling back through, oldest dropped first
+const MAX_OUTPUT: usize = 200;
+const MAX_HISTORY: usize = 100;
+/// Logical pixels between the panel's edge and its text
+const MARGIN: u32 = 4;
+const BACKGROUND: [u8; 3] = [24, 24, 32];
+const OUTPUT_COLOR: [u8; 3] = [190, 190, 190];
+const INPUT_COLOR: [u8; 3] = [255, 255, 255];
+const PROMPT: &str = "> ";
+
+/// A command the console runs, with the words Tab completes after it.
+#[derive(Clone, Debug)]
+pub struct Command {
+    pub name: &'static str,
+    /// Shown by `help`, e.g. `exposure <stops>`
+    pub usage: &'static str,
+    pub arguments: Vec<String>,
+}
+
+/// A host visible buffer the panel is copied to the swapchain image from, one for each image so that it can be
+/// written while the others' commands are still reading theirs.
+struct Panel {
+    buffer: vk::Buffer,
+    memory: vk::DeviceMemory,
+}
+
+/// A drop-down console drawn over the top of the frame, toggled with the key left of 1, which runs text commands
+/// the renderer handles. The renderer's hotkeys are ignored while it is open. Up and Down step through the lines
+/// run before, and Tab completes the word being typed, listing the choices when there are several.
+///
+/// The panel is drawn on the CPU in the swapchain's own format with a bitmap font and copied over the presented
+/// image, so the console only draws on swapchains with four bytes a pixel that can be copied to. The font is
+/// rasterized again at the nearest whole multiple of the window's scale factor whenever that changes.
+pub struct Console {
+    pub open: bool,
+    commands: Vec<Command>,
+    input: String,
+    history: Vec<String>,
+    /// The line of the history being shown, while stepping through it
+    browsing: Option<usize>,
+    output: VecDeque<String>,
+    /// The panel's pixels, drawn again when the console changes
+    pixels: Vec<u8>,
+    dirty: bool,
+    panels: Vec<Panel>,
+    extent: vk::Extent2D,
+    /// The scale factor the font was rasterized for
+    scale_factor: f32,
+    font: font::Font,
+    /// In physical pixels
+    margin: usize,
+    /// Whether the format stores blue first
+    bgra: bool,
+    copy: bool,
+}
+
+impl Console {
+    pub fn new(commands: Vec<Command>) -> Self {
+        Self {
+            open: false,
+            commands,
+            input: String::new(),
+            history: Vec::new(),
+            browsing: None,
+            output: VecDeque::new(),
+            pixels: Vec::new(),
+            dirty: true,
+            panels: Vec::new(),
+            extent: vk::Extent2D::default(),
+            scale_factor: 1.0,
+            font: font::Font::new(1.0),
+            margin: MARGIN as usize,
+            bgra: false,
+            copy: false,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.dirty = true;
+    }
+
+    /// Adds the text to the output, a line at a time
+    pub fn print(&mut self, text: &str) {
+        for line in text.lines() {
+            self.output.push_back(String::from(line));
+        }
+        while self.output.len() > MAX_OUTPUT {
+            self.output.pop_front();
+        }
+        self.dirty = true;
+    }
+
+    pub fn clear(&mut self) {
+        self.output.clear();
+        self.dirty = true;
+    }
+
+    /// Every command's usage, a line each
+    pub fn help(&self) -> String {
+        self.commands
+            .iter()
+            .map(|command| command.usage)
+            .collect::<Vec<&str>>()
+            .join("\n")
+    }
+
+    /// Types the character into the input line. Control characters, and the console's own toggle, are left
+    /// to `key_pressed`.
+    pub fn character(&mut self, character: char) {
+        if character.is_control() || character == '`' || character == '~' {
+            return;
+        }
+        self.input.push(character);
+        self.browsing = None;
+        self.dirty = true;
+    }
+
+    /// Edits the input line, returning it to be run when Return is pressed
+    pub fn key_pressed(&mut self, key: VirtualKeyCode) -> Option<String> {
+        self.dirty = true;
+        match key {
+            VirtualKeyCode::Return => {
+                let line = std::mem::take(&mut self.input);
+                self.browsing = None;
+                self.print(&format!("{}{}", PROMPT, line));
+                if line.trim().is_empty() {
+                    return None;
+                }
+                if self.history.last() != Some(&line) {
+                    self.history.push(line.clone());
+                    if self.history.len() > MAX_HISTORY {
+                        self.history.remove(0);
+                    }
+                }
+                return Some(line);
+            }
+            VirtualKeyCode::Back => {
+                self.input.pop();
+            }
+            VirtualKeyCode::Escape => self.open = false,
+            VirtualKeyCode::Tab => self.complete(),
+            VirtualKeyCode::Up if !self.history.is_empty() => {
+                let line = self
+                    .browsing
+                    .map_or(self.history.len() - 1, |line| line.saturating_sub(1));
+                self.browsing = Some(line);
+                self.input = self.history[line].clone();
+            }
+            VirtualKeyCode::Down => {
+                if let Some(line) = self.browsing {
+                    match self.history.get(line + 1) {
+                        Some(next) => {
+                            self.input = next.clone();
+                            self.browsing = Some(line + 1);
+                        }
+                        None => {
+                            self.input.clear();
+                            self.browsing = None;
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// Completes the last word of the input from the command names, or from the command's arguments after the
+    /// first word. Several matches are completed as far as they agree and listed.
+    fn complete(&mut self) {
+        let words: Vec<&str> = self.input.split_whitespace().collect();
+        let typing_new_word = self.input.is_empty() || self.input.ends_with(' ');
+        let (position, prefix) = match (typing_new_word, words.last()) {
+            (true, _) | (false, None) => (words.len(), ""),
+            (false, Some(word)) => (words.len() - 1, *word),
+        };
+        let choices: Vec<&str> = match position {
+            0 => self.commands.iter().map(|command| command.name).collect(),
+            _ => match self
+                .commands
+                .iter()
+                .find(|command| command.name == words[0])
+            {
+                Some(command) => command.arguments.iter().map(String::as_str).collect(),
+                None => Vec::new(),
+            },
+        };
+        let matches: Vec<&str> = choices
+            .into_iter()
+            .filter(|choice| choice.starts_with(prefix))
+            .collect();
+        let common = match matches.split_first() {
+            Some((first, rest)) => rest.iter().fold(*first, |common, choice| {
+                let length = common
+                    .char_indices()
+                    .zip(choice.chars())
+                    .take_while(|((_, a), b)| a == b)
+                    .last()
+                    .map_or(0, |((index, a), _)| index + a.len_utf8());
+                &common[..length]
+            }),
+            None => return,
+        };
+
+        let mut input = String::from(&self.input[..self.input.len() - prefix.len()]);
+        input.push_str(common);
+        if matches.len() == 1 {
+            input.push(' ');
+        } else {
+            let listed = matches.join("  ");
+            self.print(&listed);
+        }
+        self.input = input;
+    }
+
+    fn panel_height(&self) -> usize {
+        ((OUTPUT_LINES + 1) * self.font.glyph_height() + 2 * self.margin)
+            .min(self.extent.height as usize)
+    }
+
+    /// Draws the output and input lines over the background, from the bottom up
+    fn draw(&mut self) {
+        let width = self.extent.width as usize;
+        let height = self.panel_height();
+        let background = self.color(BACKGROUND);
+        self.pixels.clear();
+        for _ in 0..width * height {
+            self.pixels.extend_from_slice(&background);
+        }
+
+        let columns = width.saturating_sub(2 * self.margin) / self.font.glyph_width();
+        let input = format!("{}{}_", PROMPT, self.input);
+        // Long input scrolls so that the end being typed stays visible
+        let skipped = input.chars().count().saturating_sub(columns);
+        let input: String = input.chars().skip(skipped).collect();
+        let bottom = height.saturating_sub(self.margin + self.font.glyph_height());
+        self.draw_text(&input, bottom, self.color(INPUT_COLOR));
+
+        let output_color = self.color(OUTPUT_COLOR);
+        let lines: Vec<String> = self
+            .output
+            .iter()
+            .rev()
+            .take(OUTPUT_LINES)
+            .cloned()
+            .collect();
+        for (row, line) in lines.iter().enumerate() {
+            match bottom.checked_sub((row + 1) * self.font.glyph_height()) {
+                Some(top) => self.draw_text(line, top, output_color),
+                None => break,
+            }
+        }
+        self.dirty = false;
+    }
+
+    /// Draws the line of text from the left margin with its top at the row, cut off at the panel's edge
+    fn draw_text(&mut self, text: &str, top: usize, color: [u8; 4]) {
+        let width = self.extent.width as usize;
+        let height = self.panel_height();
+        let glyph_width = self.font.glyph_width();
+        for (column, character) in text.chars().enumerate() {
+            let left = self.margin + column * glyph_width;
+            if left + glyph_width > width.saturating_sub(self.margin) {
+                break;
+            }
+            let glyph = self.font.glyph(character);
+            for (y, row) in glyph.chunks_exact(glyph_width).enumerate() {
+                if top + y >= height {
+                    break;
+                }
+                for (x, &covered) in row.iter().enumerate() {
+                    if covered {
+                        let pixel = ((top + y) * width + left + x) * 4;
+                        self.pixels[pixel..pixel + 4].copy_from_slice(&color);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The colour's bytes in the swapchain's order
+    fn color(&self, [red, green, blue]: [u8; 3]) -> [u8; 4] {
+        match self.bgra {
+            true => [blue, green, red, 255],
+            false => [red, green, blue, 255],
+        }
+    }
+
+    fn record_copy(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+        swapchain_image: vk::Image,
+    ) {
+        let image_barrier = |old: vk::ImageLayout,
+                             new: vk::ImageLayout,
+                             src_access: vk::AccessFlags,
+                             dst_access: vk::AccessFlags| {
+            vk::ImageMemoryBarrier::builder()
+                .old_layout(old)
+                .new_layout(new)
+                .src_access_mask(src_access)
+                .dst_access_mask(dst_access)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(swapchain_image)
+                .subresource_range(postprocess::color_subresource_range())
+                .build()
+        };
+        let copy = vk::BufferImageCopy::builder()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(postprocess::color_subresource_layers())
+            .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+            .image_extent(vk::Extent3D {
+                width: self.extent.width,
+                height: self.panel_height() as u32,
+                depth: 1,
+            })
+            .build();
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::COMPUTE_SHADER
+                    | vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[image_barrier(
+                    vk::ImageLayout::PRESENT_SRC_KHR,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                        | vk::AccessFlags::TRANSFER_READ
+                        | vk::AccessFlags::TRANSFER_WRITE,
+                    vk::AccessFlags::TRANSFER_WRITE,
+                )],
+            );
+            device.cmd_copy_buffer_to_image(
+                command_buffer,
+                self.panels[image_index].buffer,
+                swapchain_image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[copy],
+            );
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[image_barrier(
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::PRESENT_SRC_KHR,
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    vk::AccessFlags::TRANSFER_READ,
+                )],
+            );
+        }
+    }
+}
+
+impl RenderFeature for Console {
+    fn name(&self) -> &str {
+        "console"
+    }
+
+    fn init(&mut self, context: &SwapchainContext) {
+        self.extent = context.extent;
+        if context.scale_factor != self.scale_factor {
+            self.scale_factor = context.scale_factor;
+            self.font = font::Font::new(context.scale_factor);
+        }
+        self.margin = context.scaled(MARGIN) as usize;
+        self.bgra = matches!(
+            context.format,
+            vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM
+        );
+        self.copy = context.usage.contains(vk::ImageUsageFlags::TRANSFER_DST)
+            && matches!(
+                context.format,
+                vk::Format::B8G8R8A8_SRGB
+                    | vk::Format::B8G8R8A8_UNORM
+                    | vk::Format::R8G8B8A8_SRGB
+                    | vk::Format::R8G8B8A8_UNORM
+            );
+        if !self.copy {
+            println!(
+                "The console can't be drawn over {:?} swapchain images",
+                context.format
+            );
+            return;
+        }
+        let size = (self.extent.width as usize * self.panel_height() * 4) as vk::DeviceSize;
+        self.panels = (0..context.image_count)
+            .map(|_| {
+                let (buffer, memory) = HelloTriangleApplication::create_buffer(
+                    context.device,
+                    size,
+                    vk::BufferUsageFlags::TRANSFER_SRC,
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                    context.device_memory_properties,
+                );
+                Panel { buffer, memory }
+            })
+            .collect();
+        self.dirty = true;
+    }
+
+    fn resize(&mut self, context: &SwapchainContext) {
+        self.destroy(context.device);
+        self.init(context);
+    }
+
+    fn prepare(&mut self, context: &SwapchainContext, image_index: usize) {
+        if !self.open || !self.copy {
+            return;
+        }
+        if self.dirty {
+            self.draw();
+        }
+        let memory = self.panels[image_index].memory;
+        unsafe {
+            let data = context
+                .device
+                .map_memory(
+                    memory,
+                    0,
+                    self.pixels.len() as vk::DeviceSize,
+                    vk::MemoryMapFlags::empty(),
+                )
+                .expect("Mapping console panel memory") as *mut u8;
+            data.copy_from_nonoverlapping(self.pixels.as_ptr(), self.pixels.len());
+            context.device.unmap_memory(memory);
+        }
+    }
+
+    fn record(&mut self, context: &hooks::FrameContext) {
+        if context.stage == hooks::Stage::AfterUi && self.open && self.copy {
+            self.record_copy(
+                context.device,
+                context.command_buffer,
+                context.image_index,
+                context.swapchain_image,
+            );
+        }
+    }
+
+    fn passes(&self) -> Vec<framegraph::Pass> {
+        if !self.open || !self.copy {
+            return Vec::new();
+        }
+        vec![framegraph::Pass::new(
+            self.name(),
+            framegraph::Position::Stage(hooks::Stage::AfterUi),
+            &[framegraph::SWAPCHAIN],
+            &[framegraph::SWAPCHAIN],
+        )]
+    }
+
+    fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            for panel in self.panels.drain(..) {
+                device.destroy_buffer(panel.buffer, None);
+                device.free_memory(panel.memory, None);
+            }
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
@@ -0,0 +1,94 @@
+use std::cmp::Ordering;
+
+use rayon::prelude::*;
+
+/// The buffers culling the draws fills in, kept from one frame to the next so that culling doesn't allocate
+/// them every frame.
+#[derive(Default)]
+pub struct Buffers {
+    /// Whether each draw blends and its depth, for the visible draws
+    tested: Vec<Option<(bool, f32)>>,
+    /// The visible draws' indices, whether they blend and their depth
+    kept: Vec<(usize, bool, f32)>,
+    /// Whether each kept draw passed the last `retain`
+    retained: Vec<bool>,
+    pub occlusion: OcclusionScratch,
+}
+
+/// What software occlusion culling fills in, kept with the rest of the buffers
+#[derive(Default)]
+pub struct OcclusionScratch {
+    /// The occluders' indices and the radii they're picked by
+    pub occluders: Vec<(usize, f32)>,
+    /// The storage of the depth buffer occluders are drawn into
+    pub depth: Vec<f32>,
+}
+
+impl Buffers {
+    /// Tests each of `count` draws in parallel, keeping those `test` finds visible with whether they blend and
+    /// their depth
+    pub fn test<F>(&mut self, count: usize, test: F)
+    where
+        F: Fn(usize) -> Option<(bool, f32)> + Sync,
+    {
+        self.tested.clear();
+        self.tested.resize(count, None);
+        self.tested
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(index, tested)| *tested = test(index));
+        self.kept.clear();
+        self.kept.extend(
+            self.tested
+                .iter()
+                .enumerate()
+                .filter_map(|(index, tested)| tested.map(|(blend, depth)| (index, blend, depth))),
+        );
+    }
+
+    /// The draws kept by the last test, with whether they blend and their depth
+    pub fn kept(&self) -> &[(usize, bool, f32)] {
+        &self.kept
+    }
+
+    /// Culls the kept draws further, keeping the draws whose indices `keep` returns true for, tested in parallel
+    pub fn retain<F>(&mut self, keep: F)
+    where
+        F: Fn(usize) -> bool + Sync,
+    {
+        self.retained.clear();
+        self.retained.resize(self.kept.len(), false);
+        let kept = &self.kept;
+        self.retained
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(position, retained)| *retained = keep(kept[position].0));
+        let mut retained = self.retained.iter();
+        self.kept
+            .retain(|_| *retained.next().expect("A result for each kept draw"));
+    }
+
+    /// Writes the kept draws' indices to `order` in the order they are drawn: opaque draws nearest first so that
+    /// they hide more of what is behind them, then blended draws furthest first so that they blend over what is
+    /// behind them. Draws at the same depth stay in index order, so the order only changes when the draws do.
+    pub fn sort(&mut self, order: &mut Vec<usize>) {
+        self.kept.par_sort_unstable_by(
+            |&(a_index, a_blend, a_depth), &(b_index, b_blend, b_depth)| {
+                a_blend
+                    .cmp(&b_blend)
+                    .then_with(|| {
+                        let nearest_first =
+                            a_depth.partial_cmp(&b_depth).unwrap_or(Ordering::Equal);
+                        if a_blend {
+                            nearest_first.reverse()
+                        } else {
+                            nearest_first
+                        }
+                    })
+                    .then(a_index.cmp(&b_index))
+            },
+        );
+        order.clear();
+        order.extend(self.kept.iter().map(|&(index, _, _)| index));
+    }
+}
@@ -13,6 +13,26 @@ pub type DebugMessengerSignature = unsafe extern "system" fn(
     p_user_data: *mut ffi::c_void,
 ) -> vk::Bool32;
 
+/// Names Vulkan objects, so that validation messages and graphics debuggers show the names rather than handles.
+/// Names are any UTF-8 text.
+pub struct Names {
+    loader: ext::DebugUtils,
+    device: vk::Device,
+}
+
+impl Names {
+    pub fn set<T: vk::Handle>(&self, object: T, name: &str) {
+        let name = util::vk_string(name);
+        let info = vk::DebugUtilsObjectNameInfoEXT::builder()
+            .object_type(T::TYPE)
+            .object_handle(object.as_raw())
+            .object_name(&name);
+        if let Err(e) = unsafe { self.loader.debug_utils_set_object_name(self.device, &info) } {
+            println!("Couldn't name {:?} {:?}: {}", T::TYPE, name, e);
+        }
+    }
+}
+
 pub struct Configuration {
     _severities: vk::DebugUtilsMessageSeverityFlagsEXT,
     _callback: DebugMessengerSignature,
@@ -46,7 +66,7 @@ impl Configuration {
                 for validation_layer in VALIDATION_LAYERS.iter() {
                     let is_present = layers
                         .iter()
-                        .map(|layer| util::read_vk_string(&layer.layer_name).unwrap())
+                        .map(|layer| util::read_vk_string(&layer.layer_name))
                         .fold(false, |acc, current| acc || current.eq(validation_layer));
 
                     if !is_present {
@@ -80,6 +100,14 @@ impl Configuration {
         instance::Extension { name, data: ci }
     }
 
+    /// Names the device's objects, once the messenger has been created
+    pub fn names(&self, device: &ash::Device) -> Option<Names> {
+        self._loader.as_ref().map(|loader| Names {
+            loader: loader.clone(),
+            device: device.handle(),
+        })
+    }
+
     /// Registers the debug messenger callback with the given vulkan instance.
     pub fn create_messenger(
         &mut self,
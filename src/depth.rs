@@ -0,0 +1,199 @@
+use ash::vk;
+
+use crate::{begin_single_time_commands, end_single_time_commands, HelloTriangleApplication};
+
+/// Bytes copied out of the depth buffer per texel, whichever depth format it has
+const TEXEL_SIZE: vk::DeviceSize = 4;
+
+/// Depth read back from the renderer, as distances from the camera along its view direction in world units.
+/// Pixels nothing was drawn to are infinitely far away when the far plane is.
+/// Values are in the swapchain image's orientation, row by row from the top left.
+#[derive(Clone, Debug)]
+pub struct DepthImage {
+    pub width: u32,
+    pub height: u32,
+    pub values: Vec<f32>,
+}
+
+impl DepthImage {
+    pub fn at(&self, x: u32, y: u32) -> Option<f32> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.values.get((y * self.width + x) as usize).copied()
+    }
+
+    /// A 16-bit greyscale image of the depth, black at the camera and white at the furthest finite value and
+    /// beyond
+    #[cfg(feature = "images")]
+    pub fn to_image(&self) -> image::ImageBuffer<image::Luma<u16>, Vec<u16>> {
+        let furthest = self
+            .values
+            .iter()
+            .copied()
+            .filter(|value| value.is_finite())
+            .fold(f32::EPSILON, f32::max);
+        let luma = self
+            .values
+            .iter()
+            .map(|value| (value / furthest * u16::MAX as f32) as u16)
+            .collect();
+        image::ImageBuffer::from_raw(self.width, self.height, luma)
+            .expect("Depth image has every pixel")
+    }
+}
+
+/// Copies the whole depth image into a host visible buffer and returns its values, from 0 at the near plane to 1
+/// at the far plane. Waits for the copy to finish, so the image must not be in use by commands still executing.
+/// The image is left ready to be drawn into again.
+pub fn read(
+    device: &ash::Device,
+    device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    command_pool: vk::CommandPool,
+    queue: vk::Queue,
+    image: vk::Image,
+    format: vk::Format,
+    extent: vk::Extent2D,
+) -> Vec<f32> {
+    let texel_count = (extent.width * extent.height) as usize;
+    let size = texel_count as vk::DeviceSize * TEXEL_SIZE;
+    let (buffer, memory) = HelloTriangleApplication::create_buffer(
+        device,
+        size,
+        vk::BufferUsageFlags::TRANSFER_DST,
+        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        device_memory_properties,
+    );
+
+    let mut aspect_mask = vk::ImageAspectFlags::DEPTH;
+    if HelloTriangleApplication::has_stencil_component(format) {
+        aspect_mask |= vk::ImageAspectFlags::STENCIL;
+    }
+    let barrier = |old: vk::ImageLayout,
+                   new: vk::ImageLayout,
+                   src_access: vk::AccessFlags,
+                   dst_access: vk::AccessFlags| {
+        vk::ImageMemoryBarrier::builder()
+            .old_layout(old)
+            .new_layout(new)
+            .src_access_mask(src_access)
+            .dst_access_mask(dst_access)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(
+                vk::ImageSubresourceRange::builder()
+                    .aspect_mask(aspect_mask)
+                    .base_mip_level(0)
+                    .level_count(1)
+                    .base_array_layer(0)
+                    .layer_count(1)
+                    .build(),
+            )
+            .build()
+    };
+    let region = vk::BufferImageCopy::builder()
+        .buffer_offset(0)
+        .buffer_row_length(0)
+        .buffer_image_height(0)
+        .image_subresource(
+            vk::ImageSubresourceLayers::builder()
+                .aspect_mask(vk::ImageAspectFlags::DEPTH)
+                .mip_level(0)
+                .base_array_layer(0)
+                .layer_count(1)
+                .build(),
+        )
+        .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+        .image_extent(vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        })
+        .build();
+    let host_barrier = vk::BufferMemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(vk::AccessFlags::HOST_READ)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .buffer(buffer)
+        .offset(0)
+        .size(vk::WHOLE_SIZE)
+        .build();
+
+    let command_buffer = begin_single_time_commands(device, command_pool);
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[barrier(
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                vk::AccessFlags::TRANSFER_READ,
+            )],
+        );
+        device.cmd_copy_image_to_buffer(
+            command_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            buffer,
+            &[region],
+        );
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::HOST,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[host_barrier],
+            &[],
+        );
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[barrier(
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                vk::AccessFlags::empty(),
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            )],
+        );
+    }
+    end_single_time_commands(device, command_pool, command_buffer, queue);
+
+    let mut bytes = vec![0u8; size as usize];
+    unsafe {
+        let data = device
+            .map_memory(memory, 0, size, vk::MemoryMapFlags::empty())
+            .expect("Mapping depth readback memory") as *const u8;
+        data.copy_to_nonoverlapping(bytes.as_mut_ptr(), bytes.len());
+        device.unmap_memory(memory);
+        device.destroy_buffer(buffer, None);
+        device.free_memory(memory, None);
+    }
+
+    bytes
+        .chunks_exact(TEXEL_SIZE as usize)
+        .map(|texel| {
+            let texel = [texel[0], texel[1], texel[2], texel[3]];
+            match format {
+                // Depth is copied out of packed depth stencil formats in the low 24 bits
+                vk::Format::D24_UNORM_S8_UINT | vk::Format::X8_D24_UNORM_PACK32 => {
+                    (u32::from_le_bytes(texel) & 0x00ff_ffff) as f32 / 0x00ff_ffff as f32
+                }
+                _ => f32::from_le_bytes(texel),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,413 @@
+use std::sync::Arc;
+
+use ash::vk;
+use cgmath::{Matrix4, SquareMatrix};
+
+use crate::{assets, bounds, capsules, indirect, pipeline, raycast, shader_materials, trails, vat};
+
+/// Everything recording a draw puts into a command buffer, so that a recorded buffer can be reused for as long as
+/// its draws would record the same
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Recorded {
+    /// The draw's pipeline and those of its caps and outline, `None` until the draw has been prepared
+    pipelines: (
+        Option<vk::Pipeline>,
+        Option<vk::Pipeline>,
+        Option<vk::Pipeline>,
+    ),
+    transform: Matrix4<f32>,
+    vertex_buffer: vk::Buffer,
+    index_buffer: Option<vk::Buffer>,
+    instances: Option<vk::Buffer>,
+    count: u32,
+    instance_count: u32,
+    first_index: u32,
+    vertex_offset: i32,
+    first_instance: u32,
+    line_width: f32,
+    point_size: f32,
+    depth_bias: Option<pipeline::DepthBias>,
+    depth_bounds: Option<pipeline::DepthBounds>,
+    animation: Option<vat::Binding>,
+}
+
+/// Geometry to draw and the pipeline state to draw it with. The buffers are owned by the application or by the
+/// draw's mesh asset, a `Draw` only records what is needed to issue the draw into a command buffer.
+#[derive(Clone, Debug)]
+pub struct Draw {
+    pub pipeline: pipeline::Config,
+    pub vertex_buffer: vk::Buffer,
+    /// Places the draw's vertices in the scene. Applied before the scene's model matrix, and ignored by
+    /// billboards which are positioned in world space.
+    pub transform: Matrix4<f32>,
+    /// Buffer of `u16` indices. When present, `count` is the number of indices rather than vertices.
+    pub index_buffer: Option<vk::Buffer>,
+    pub count: u32,
+    pub instance_count: u32,
+    /// Per-instance transforms laid out as `indirect::Object`s, bound at `indirect::INSTANCE_BINDING` for
+    /// `pipeline::Program::Indirect` draws that aren't drawn indirectly, or the `trails::Ghost`s of
+    /// `pipeline::Program::Trail` draws
+    pub instances: Option<vk::Buffer>,
+    /// Where the draw starts in buffers it shares with other draws, such as a `batch::Baked` mesh. The first
+    /// index is only used by indexed draws, and the vertex offset is added to each index or is the first vertex
+    /// of non-indexed draws.
+    pub first_index: u32,
+    pub vertex_offset: i32,
+    /// The instance drawn first, for instanced draws that share a buffer of per-instance data with other draws
+    pub first_instance: u32,
+    /// Width in pixels of lines drawn with a line topology, clamped to what the device supports
+    pub line_width: f32,
+    /// Size in pixels of points drawn with the point topology, clamped to what the device supports. Point sprites
+    /// are this size one unit from the camera, and it is the radius of surfels, see `pointcloud::Mode`.
+    pub point_size: f32,
+    /// Offset applied to the depth of the draw's polygons. Enables depth bias on the pipeline when present.
+    pub depth_bias: Option<pipeline::DepthBias>,
+    /// Drops the draw's fragments where the depth already drawn is outside the bounds. Enables the depth bounds
+    /// test on the pipeline when present, if the device supports it.
+    pub depth_bounds: Option<pipeline::DepthBounds>,
+    /// Bounds of the draw's geometry. Draws without bounds don't contribute to the scene bounds.
+    pub bounds: Option<bounds::Bounds>,
+    /// The draw's triangles in the same space as its vertices, for ray casting. Shared since draws of the same
+    /// geometry can share it.
+    pub collider: Option<Arc<raycast::Bvh>>,
+    /// The scene the draw belongs to. Only draws in the renderer's current scene are drawn, and those of the
+    /// scene being left while a transition runs.
+    pub scene: usize,
+    /// The animation texture and frame a `pipeline::Program::Vat` draw plays back
+    pub animation: Option<vat::Binding>,
+    /// The mesh asset the draw's buffers belong to, kept alive for as long as the draw is
+    pub mesh: Option<assets::Mesh>,
+    /// What the draw has been named, in any language
+    pub name: Option<String>,
+    /// Capsules roughly filling the draw, in the same space as its vertices, that shade what is around it with
+    /// `capsules::CapsuleShadows`. For dynamic draws like characters, whose shadows nothing else draws.
+    pub capsules: Vec<capsules::Capsule>,
+    /// Whether the draw casts a shadow onto the ground with `ground_shadows::GroundShadows`
+    pub casts_ground_shadow: bool,
+    /// Ghosts of where the draw has been, see `trails::Trail`
+    pub trail: Option<trails::Trail>,
+}
+
+impl Draw {
+    pub fn new(pipeline: pipeline::Config, vertex_buffer: vk::Buffer, count: u32) -> Self {
+        Self {
+            pipeline,
+            vertex_buffer,
+            transform: Matrix4::identity(),
+            index_buffer: None,
+            count,
+            instance_count: 1,
+            instances: None,
+            first_index: 0,
+            vertex_offset: 0,
+            first_instance: 0,
+            line_width: 1.0,
+            point_size: 1.0,
+            depth_bias: None,
+            depth_bounds: None,
+            bounds: None,
+            collider: None,
+            scene: 0,
+            animation: None,
+            mesh: None,
+            name: None,
+            capsules: Vec::new(),
+            casts_ground_shadow: false,
+            trail: None,
+        }
+    }
+
+    pub fn indexed(
+        pipeline: pipeline::Config,
+        vertex_buffer: vk::Buffer,
+        index_buffer: vk::Buffer,
+        count: u32,
+    ) -> Self {
+        Self {
+            index_buffer: Some(index_buffer),
+            ..Self::new(pipeline, vertex_buffer, count)
+        }
+    }
+
+    /// The transform from the draw's vertices to world space, given the scene's model matrix
+    pub fn world_transform(&self, model: &Matrix4<f32>) -> Matrix4<f32> {
+        if self.pipeline.program.uses_model_transform() {
+            model * self.transform
+        } else {
+            Matrix4::identity()
+        }
+    }
+
+    /// The pipeline variant the draw is recorded with
+    fn pipeline_config(&self) -> pipeline::Config {
+        pipeline::Config {
+            depth_bias: self.depth_bias.is_some(),
+            depth_bounds: self.depth_bounds.is_some(),
+            ..self.pipeline
+        }
+    }
+
+    /// Creates the draw's pipeline if it hasn't been used before. Must be called before the draw is recorded,
+    /// which only reads the pipelines so that draws can be recorded on several threads at once.
+    pub fn prepare(&self, device: &ash::Device, pipelines: &mut pipeline::Variants) {
+        pipelines.get(device, &self.pipeline_config());
+        if let Some(cap) = self.clip_cap_config(pipelines) {
+            pipelines.get(device, &cap);
+        }
+        if let Some(outline) = self.pipeline_config().outline() {
+            pipelines.get(device, &outline);
+        }
+    }
+
+    /// Every variant the draw may be recorded with: its own, the one capping its cross section, whether or not
+    /// clipping planes are capped yet, and those of its outline, its planar ground shadow and its trail if it has
+    /// them
+    pub fn expected_configs(&self) -> Vec<pipeline::Config> {
+        let config = self.pipeline_config();
+        std::iter::once(config)
+            .chain(config.clip_cap())
+            .chain(config.outline())
+            .chain(
+                self.ground_shadow(Matrix4::identity())
+                    .map(|shadow| shadow.pipeline_config()),
+            )
+            .chain(
+                self.trail_draw(vk::Buffer::null(), 0, 1)
+                    .map(|trail| trail.pipeline_config()),
+            )
+            .collect()
+    }
+
+    /// The draw of `count` ghosts of the draw's trail, from `first` in a buffer of `trails::Ghost`s, if it has a
+    /// trail and any ghosts. Only meshes drawn once with push constants can leave trails.
+    pub fn trail_draw(&self, ghosts: vk::Buffer, first: u32, count: u32) -> Option<Self> {
+        if self.trail.is_none()
+            || count == 0
+            || self.instances.is_some()
+            || self.instance_count != 1
+        {
+            return None;
+        }
+        Some(Self {
+            index_buffer: self.index_buffer,
+            instance_count: count,
+            instances: Some(ghosts),
+            first_index: self.first_index,
+            vertex_offset: self.vertex_offset,
+            first_instance: first,
+            scene: self.scene,
+            ..Self::new(self.pipeline.trail()?, self.vertex_buffer, self.count)
+        })
+    }
+
+    /// The draw flattened onto the ground by `transform` in place of its own, as its planar ground shadow, if it
+    /// casts one and can be flattened. See `ground_shadows::GroundShadows`.
+    pub fn ground_shadow(&self, transform: Matrix4<f32>) -> Option<Self> {
+        if !self.casts_ground_shadow {
+            return None;
+        }
+        Some(Self {
+            transform,
+            index_buffer: self.index_buffer,
+            instance_count: self.instance_count,
+            instances: self.instances,
+            first_index: self.first_index,
+            vertex_offset: self.vertex_offset,
+            first_instance: self.first_instance,
+            depth_bias: Some(pipeline::DepthBias::ground_shadow()),
+            scene: self.scene,
+            animation: self.animation,
+            ..Self::new(
+                self.pipeline.ground_shadow()?,
+                self.vertex_buffer,
+                self.count,
+            )
+        })
+    }
+
+    /// The draw into the light's shadow map, biased by `bias`, if it casts shadows. See
+    /// `shadow_map::ShadowMap`.
+    pub fn shadow_caster(&self, bias: pipeline::DepthBias) -> Option<Self> {
+        Some(Self {
+            transform: self.transform,
+            index_buffer: self.index_buffer,
+            instance_count: self.instance_count,
+            instances: self.instances,
+            first_index: self.first_index,
+            vertex_offset: self.vertex_offset,
+            first_instance: self.first_instance,
+            depth_bias: Some(bias),
+            scene: self.scene,
+            animation: self.animation,
+            ..Self::new(
+                self.pipeline.shadow_caster()?,
+                self.vertex_buffer,
+                self.count,
+            )
+        })
+    }
+
+    /// The variant that caps the draw's cross section, if clipping planes are capped and the draw can be capped
+    fn clip_cap_config(&self, pipelines: &pipeline::Variants) -> Option<pipeline::Config> {
+        if pipelines.clip_caps() {
+            self.pipeline_config().clip_cap()
+        } else {
+            None
+        }
+    }
+
+    /// What `record` would record
+    pub fn recorded(&self, pipelines: &pipeline::Variants) -> Recorded {
+        Recorded {
+            pipelines: (
+                pipelines.cached(&self.pipeline_config()),
+                self.clip_cap_config(pipelines)
+                    .and_then(|cap| pipelines.cached(&cap)),
+                self.pipeline_config()
+                    .outline()
+                    .and_then(|outline| pipelines.cached(&outline)),
+            ),
+            transform: self.transform,
+            vertex_buffer: self.vertex_buffer,
+            index_buffer: self.index_buffer,
+            instances: self.instances,
+            count: self.count,
+            instance_count: self.instance_count,
+            first_index: self.first_index,
+            vertex_offset: self.vertex_offset,
+            first_instance: self.first_instance,
+            line_width: self.line_width,
+            point_size: self.point_size,
+            depth_bias: self.depth_bias,
+            depth_bounds: self.depth_bounds,
+            animation: self.animation,
+        }
+    }
+
+    /// Records the draw into a command buffer that is inside a render pass with the frame's descriptor sets
+    /// bound, followed by its caps when clipping planes cut through it and its outline if it has one.
+    pub fn record(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        pipelines: &pipeline::Variants,
+    ) {
+        self.record_with(device, command_buffer, pipelines, &self.pipeline_config());
+        if let Some(cap) = self.clip_cap_config(pipelines) {
+            self.record_with(device, command_buffer, pipelines, &cap);
+        }
+        if let Some(outline) = self.pipeline_config().outline() {
+            self.record_with(device, command_buffer, pipelines, &outline);
+        }
+    }
+
+    fn record_with(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        pipelines: &pipeline::Variants,
+        config: &pipeline::Config,
+    ) {
+        let pipeline = pipelines
+            .cached(config)
+            .expect("Draw prepared before recording");
+        let limits = *pipelines.limits();
+        // Depth bias is dynamic on every pipeline so it has to be set even when it is disabled
+        let depth_bias = self
+            .depth_bias
+            .unwrap_or_else(|| pipeline::DepthBias::new(0.0, 0.0));
+        let push_constants = pipeline::PushConstants {
+            model: self.transform.into(),
+            // Surfels read it as a radius in world units rather than pixels, which isn't limited by the device
+            point_size: match self.pipeline.program {
+                pipeline::Program::Surfels => self.point_size,
+                _ => limits.point_size(self.point_size),
+            },
+            animation_frame: self.animation.map_or(0.0, |animation| animation.frame),
+        };
+
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            device.cmd_set_line_width(command_buffer, limits.line_width(self.line_width));
+            device.cmd_set_depth_bias(
+                command_buffer,
+                depth_bias.constant_factor,
+                limits.depth_bias_clamp(depth_bias.clamp),
+                depth_bias.slope_factor,
+            );
+            if let Some(depth_bounds) = self.depth_bounds {
+                if config.depth_bounds && limits.depth_bounds() {
+                    device.cmd_set_depth_bounds(command_buffer, depth_bounds.min, depth_bounds.max);
+                }
+            }
+            device.cmd_push_constants(
+                command_buffer,
+                pipelines.layout(),
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                push_constants.as_bytes(),
+            );
+
+            if let Some(animation) = self.animation {
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipelines.layout(),
+                    1,
+                    &[animation.descriptor_set],
+                    &[],
+                );
+            }
+            if let pipeline::Program::Custom(index) = config.program {
+                let program = pipelines
+                    .custom_program(index)
+                    .expect("Custom program registered before it is drawn");
+                if let Some(parameters) = program.parameters {
+                    device.cmd_bind_descriptor_sets(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        program.layout,
+                        shader_materials::PARAMETER_SET,
+                        &[parameters],
+                        &[],
+                    );
+                }
+            }
+
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[self.vertex_buffer], &[0]);
+            if let Some(instances) = self.instances {
+                device.cmd_bind_vertex_buffers(
+                    command_buffer,
+                    indirect::INSTANCE_BINDING,
+                    &[instances],
+                    &[0],
+                );
+            }
+            match self.index_buffer {
+                Some(index_buffer) => {
+                    device.cmd_bind_index_buffer(
+                        command_buffer,
+                        index_buffer,
+                        0,
+                        vk::IndexType::UINT16,
+                    );
+                    device.cmd_draw_indexed(
+                        command_buffer,
+                        self.count,
+                        self.instance_count,
+                        self.first_index,
+                        self.vertex_offset,
+                        self.first_instance,
+                    );
+                }
+                None => device.cmd_draw(
+                    command_buffer,
+                    self.count,
+                    self.instance_count,
+                    self.vertex_offset as u32,
+                    self.first_instance,
+                ),
+            }
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::{budget, info, memory, pipeline};
+
+/// Events kept for `Bus::recent`
+const HISTORY: usize = 64;
+
+/// What kind of asset an `Event::AssetLoaded` or `Event::LoadFailed` is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AssetKind {
+    Scene,
+    Texture,
+    ShaderMaterial,
+    /// A signed distance field atlas for text
+    Atlas,
+    Svg,
+    /// One of the cells of a streamed world
+    WorldCell,
+    Portals,
+    PointCloud,
+    Volume,
+    CameraPath,
+    Timeline,
+    Script,
+    /// A hot reloaded logic library
+    Logic,
+    GamepadBindings,
+}
+
+/// What an `Event::Exported` or `Event::ExportFailed` wrote out
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportKind {
+    /// The cubemap around the camera, as faces and a panorama
+    Capture,
+    Depth,
+    /// The frame graph as a Graphviz file
+    FrameGraph,
+    Scene,
+    CameraPath,
+}
+
+/// Something that happened inside the renderer that the host application may want to act on.
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// The renderer has been made, with its settings, before it draws its first frame
+    Started(info::RendererInfo),
+    /// The swapchain has been recreated, such as after the window was resized, with the renderer's settings
+    /// for the new swapchain
+    SwapchainRecreated(info::RendererInfo),
+    /// A pipeline variant was compiled, which stalls the frame that first draws with it unless it was prewarmed
+    PipelineCompiled {
+        program: pipeline::Program,
+        duration: Duration,
+    },
+    AssetLoaded {
+        kind: AssetKind,
+        name: String,
+    },
+    /// An asset couldn't be loaded, so the renderer carries on without it
+    LoadFailed {
+        kind: AssetKind,
+        name: String,
+        error: String,
+    },
+    Exported {
+        kind: ExportKind,
+        paths: Vec<PathBuf>,
+    },
+    ExportFailed {
+        kind: ExportKind,
+        error: String,
+    },
+    /// A feature couldn't be turned on, on this device or with the settings given, so the renderer carries on
+    /// without it. `feature` is what isn't done, such as "drawing the minimap".
+    FeatureUnavailable {
+        feature: String,
+        error: String,
+    },
+    /// Something happened to the frames streamed to remote clients, such as a client connecting or a frame
+    /// failing to send, see `remote::Remote`
+    Streaming(String),
+    /// What the renderer did in answer to input or its settings, such as a key turning the fog on, for the host
+    /// application to show
+    Status(String),
+    /// A texture's memory couldn't be allocated at its source's size, so it was loaded smaller
+    AllocationFailed(memory::Downgrade),
+    /// A frame took longer than its budget. Alerts are throttled by `budget::Monitor::report`, and `skipped` is
+    /// how many frames went over budget since the last one was sent.
+    BudgetExceeded {
+        alert: budget::Alert,
+        skipped: u32,
+    },
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Started(info) | Event::SwapchainRecreated(info) => write!(f, "{}", info),
+            Event::PipelineCompiled { program, duration } => write!(
+                f,
+                "Compiled a {:?} pipeline in {:.1}ms",
+                program,
+                duration.as_secs_f64() * 1000.0
+            ),
+            Event::AssetLoaded { kind, name } => write!(f, "Loaded {:?} {}", kind, name),
+            Event::LoadFailed { kind, name, error } => {
+                write!(f, "Not loading {:?} {}: {}", kind, name, error)
+            }
+            Event::Exported { kind, paths } => write!(f, "Exported {:?} to {:?}", kind, paths),
+            Event::ExportFailed { kind, error } => {
+                write!(f, "Couldn't export {:?}: {}", kind, error)
+            }
+            Event::FeatureUnavailable { feature, error } => {
+                write!(f, "Not {}: {}", feature, error)
+            }
+            Event::Streaming(message) | Event::Status(message) => write!(f, "{}", message),
+            Event::AllocationFailed(downgrade) => write!(f, "Warning: texture {}", downgrade),
+            Event::BudgetExceeded { alert, skipped } => {
+                if *skipped > 0 {
+                    writeln!(f, "{} more frames went over budget", skipped)?;
+                }
+                write!(f, "{}", alert.describe())
+            }
+        }
+    }
+}
+
+/// Called with each event as it's emitted, on the render thread
+pub type Subscriber = Box<dyn FnMut(&Event)>;
+
+/// Sends the renderer's events to the host application's subscribers, in the order they were subscribed, and
+/// keeps the most recent for the console. Events are printed too unless `log` is turned off, such as by a host
+/// application that shows them itself.
+pub struct Bus {
+    subscribers: Vec<Subscriber>,
+    pub log: bool,
+    history: VecDeque<Event>,
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self {
+            subscribers: Vec::new(),
+            log: true,
+            history: VecDeque::with_capacity(HISTORY),
+        }
+    }
+}
+
+impl Bus {
+    pub fn subscribe(&mut self, subscriber: Subscriber) {
+        self.subscribers.push(subscriber);
+    }
+
+    pub fn emit(&mut self, event: Event) {
+        if self.log {
+            println!("{}", event);
+        }
+        for subscriber in self.subscribers.iter_mut() {
+            subscriber(&event);
+        }
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(event);
+    }
+
+    /// Emits an `Event::Status`
+    pub fn status(&mut self, message: String) {
+        self.emit(Event::Status(message));
+    }
+
+    /// Up to the last `count` events, oldest first
+    pub fn recent(&self, count: usize) -> impl Iterator<Item = &Event> {
+        self.history
+            .iter()
+            .skip(self.history.len().saturating_sub(count))
+    }
+}
@@ -0,0 +1,142 @@
+use std::any::Any;
+use std::time::{Duration, Instant};
+
+use ash::vk;
+
+use crate::{framegraph, hooks};
+
+/// The device and swapchain a feature creates its resources for.
+pub struct SwapchainContext<'a> {
+    pub device: &'a ash::Device,
+    pub device_memory_properties: &'a vk::PhysicalDeviceMemoryProperties,
+    pub extent: vk::Extent2D,
+    pub format: vk::Format,
+    pub depth_format: vk::Format,
+    /// What the swapchain images can be used for beyond being colour attachments
+    pub usage: vk::ImageUsageFlags,
+    pub image_count: usize,
+    /// A view of each swapchain image, for features drawing into them in render passes of their own
+    pub image_views: &'a [vk::ImageView],
+    /// Physical pixels per logical pixel of the window. Overlays are sized in logical pixels and scaled by it so
+    /// they are as legible on high DPI displays as on others, whatever the swapchain's resolution.
+    pub scale_factor: f32,
+}
+
+impl SwapchainContext<'_> {
+    /// A size in logical pixels in physical pixels, at least one
+    pub fn scaled(&self, logical: u32) -> u32 {
+        ((logical as f32 * self.scale_factor).round() as u32).max(1)
+    }
+}
+
+/// A pass that records into every frame, kept separate from the renderer so passes can be added without
+/// changing it. Features are recorded at each of the frame's stages in the order they were added, before the
+/// host application's hooks for that stage.
+pub trait RenderFeature: Any {
+    fn name(&self) -> &str;
+
+    /// Creates the feature's resources for the swapchain, once when the feature is added.
+    fn init(&mut self, context: &SwapchainContext);
+
+    /// Replaces resources that depend on the swapchain after it has been recreated. The device is idle.
+    fn resize(&mut self, context: &SwapchainContext);
+
+    /// Host side work before the frame's commands are recorded, once the image's previous commands have
+    /// finished executing.
+    fn prepare(&mut self, _context: &SwapchainContext, _image_index: usize) {}
+
+    /// Records the feature's commands for `context.stage`, under the same rules as hooks.
+    fn record(&mut self, context: &hooks::FrameContext);
+
+    /// The passes the feature records with its current settings, for `framegraph::Graph`. By default a single
+    /// pass post processing the swapchain image, which features that record elsewhere should replace.
+    fn passes(&self) -> Vec<framegraph::Pass> {
+        vec![framegraph::Pass::new(
+            self.name(),
+            framegraph::Position::Stage(hooks::Stage::PostProcess),
+            &[framegraph::SWAPCHAIN],
+            &[framegraph::SWAPCHAIN],
+        )]
+    }
+
+    fn destroy(&mut self, device: &ash::Device);
+
+    /// Lets the renderer reach a feature's own API through `Features::get_mut`.
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+/// The renderer's features, in the order they record.
+#[derive(Default)]
+pub struct Features {
+    features: Vec<Box<dyn RenderFeature>>,
+    /// How long each feature last took to record at each stage
+    recording: Vec<Vec<(hooks::Stage, Duration)>>,
+}
+
+impl Features {
+    /// Initialises the feature and adds it after those already added.
+    pub fn add(&mut self, mut feature: Box<dyn RenderFeature>, context: &SwapchainContext) {
+        feature.init(context);
+        println!("Added render feature {}", feature.name());
+        self.features.push(feature);
+        self.recording.push(Vec::new());
+    }
+
+    /// The first feature of the type.
+    pub fn get_mut<T: RenderFeature>(&mut self) -> Option<&mut T> {
+        self.features
+            .iter_mut()
+            .find_map(|feature| feature.as_any_mut().downcast_mut::<T>())
+    }
+
+    pub fn resize(&mut self, context: &SwapchainContext) {
+        for feature in self.features.iter_mut() {
+            feature.resize(context);
+        }
+    }
+
+    pub fn prepare(&mut self, context: &SwapchainContext, image_index: usize) {
+        for feature in self.features.iter_mut() {
+            feature.prepare(context, image_index);
+        }
+    }
+
+    pub fn record(&mut self, context: &hooks::FrameContext) {
+        for (feature, recording) in self.features.iter_mut().zip(self.recording.iter_mut()) {
+            let start = Instant::now();
+            feature.record(context);
+            let elapsed = start.elapsed();
+            match recording
+                .iter_mut()
+                .find(|(stage, _)| *stage == context.stage)
+            {
+                Some((_, time)) => *time = elapsed,
+                None => recording.push((context.stage, elapsed)),
+            }
+        }
+    }
+
+    /// Every feature's passes, in the order they were added, with how long they last took to record
+    pub fn passes(&self) -> Vec<framegraph::Pass> {
+        let mut passes = Vec::new();
+        for (feature, recording) in self.features.iter().zip(self.recording.iter()) {
+            for mut pass in feature.passes() {
+                if let framegraph::Position::Stage(stage) = pass.position {
+                    pass.recording = recording
+                        .iter()
+                        .find(|(recorded, _)| *recorded == stage)
+                        .map(|(_, time)| *time);
+                }
+                passes.push(pass);
+            }
+        }
+        passes
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        for mut feature in self.features.drain(..) {
+            feature.destroy(device);
+        }
+        self.recording.clear();
+    }
+}
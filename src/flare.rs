@@ -0,0 +1,445 @@
+use std::any::Any;
+use std::mem::size_of;
+use std::path::Path;
+
+use ash::vk;
+use cgmath::{Matrix4, Vector3};
+
+use crate::features::{RenderFeature, SwapchainContext};
+use crate::{framegraph, hooks, pipeline, playground, util};
+
+/// How far past the frame's edge the light can be before the flare has faded out, in normalized device
+/// coordinates from the centre
+const EDGE_FADE: (f32, f32) = (0.9, 1.3);
+
+/// The shapes `flare_frag.glsl` draws, matching its `shape` push constant
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Shape {
+    /// A bright core fading out with streaks across it, drawn over the light
+    Glow,
+    /// A soft edged disc, like a reflection of the aperture between the lens's elements
+    Disc,
+    /// A thin ring around a reflection
+    Ring,
+}
+
+/// A sprite of the flare, placed along the line from the light through the centre of the frame
+struct Element {
+    /// Along the line, 1 at the light, 0 at the centre of the frame and -1 mirrored across it
+    position: f32,
+    /// Across, as a fraction of the frame's height
+    size: f32,
+    /// Linear colour, tinted by the light's
+    color: [f32; 3],
+    opacity: f32,
+    shape: Shape,
+}
+
+const ELEMENTS: [Element; 8] = [
+    Element {
+        position: 1.0,
+        size: 0.4,
+        color: [1.0, 0.95, 0.85],
+        opacity: 0.8,
+        shape: Shape::Glow,
+    },
+    Element {
+        position: 0.7,
+        size: 0.06,
+        color: [1.0, 0.6, 0.3],
+        opacity: 0.25,
+        shape: Shape::Disc,
+    },
+    Element {
+        position: 0.4,
+        size: 0.03,
+        color: [0.5, 0.9, 0.5],
+        opacity: 0.3,
+        shape: Shape::Disc,
+    },
+    Element {
+        position: 0.1,
+        size: 0.12,
+        color: [0.4, 0.5, 1.0],
+        opacity: 0.2,
+        shape: Shape::Ring,
+    },
+    Element {
+        position: -0.2,
+        size: 0.08,
+        color: [0.6, 0.4, 1.0],
+        opacity: 0.2,
+        shape: Shape::Disc,
+    },
+    Element {
+        position: -0.45,
+        size: 0.04,
+        color: [1.0, 0.5, 0.4],
+        opacity: 0.3,
+        shape: Shape::Disc,
+    },
+    Element {
+        position: -0.7,
+        size: 0.25,
+        color: [0.5, 1.0, 0.7],
+        opacity: 0.15,
+        shape: Shape::Ring,
+    },
+    Element {
+        position: -1.0,
+        size: 0.15,
+        color: [0.3, 0.5, 1.0],
+        opacity: 0.15,
+        shape: Shape::Disc,
+    },
+];
+
+/// Inputs to `flare_frag.glsl`, laid out to match its `Sprite` push constants.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Sprite {
+    offset: [f32; 2],
+    size: [f32; 2],
+    color: [f32; 4],
+    shape: u32,
+    encode_srgb: u32,
+}
+
+impl Sprite {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+}
+
+/// A bright light the flare is drawn for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Source {
+    /// Where the light is in normalized device coordinates, which can be outside the frame
+    pub position: [f32; 2],
+    /// Linear colour, whose brightest channel sets how strong the flare is
+    pub color: [f32; 3],
+    /// How much of the light can be seen, from 0 when it is hidden to 1, as `visibility::LightVisibility`
+    /// estimates it
+    pub visibility: f32,
+}
+
+/// Where a light infinitely far away in `direction` is in normalized device coordinates, if it is in front of
+/// the camera. Orthographic cameras never see a light at infinity.
+pub fn project(direction: Vector3<f32>, view_projection: &Matrix4<f32>) -> Option<[f32; 2]> {
+    let clip = view_projection * direction.extend(0.0);
+    (clip.w > 0.0).then(|| [clip.x / clip.w, clip.y / clip.w])
+}
+
+/// The render pass the sprites are drawn with, sized to the swapchain.
+struct Target {
+    extent: vk::Extent2D,
+    render_pass: vk::RenderPass,
+    /// One for each swapchain image
+    framebuffers: Vec<vk::Framebuffer>,
+    pipeline: vk::Pipeline,
+    encode_srgb: bool,
+}
+
+/// Lens flare for a bright light such as the sun: a glow over the light and ghosts of it reflected between the
+/// lens's elements, strung along the line from the light through the centre of the frame. The renderer sets the
+/// `source` each frame, and the flare fades with how much of the light is hidden and as it leaves the frame.
+///
+/// The sprites are drawn over the swapchain image after post processing, in a render pass of the flare's own,
+/// so the flare is neither fogged nor filtered and is drawn under the console.
+pub struct LensFlare {
+    pub enabled: bool,
+    /// Scales every sprite's opacity
+    pub intensity: f32,
+    pub source: Option<Source>,
+    layout: vk::PipelineLayout,
+    target: Option<Target>,
+}
+
+impl LensFlare {
+    pub fn new(device: &ash::Device, intensity: f32) -> Self {
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .offset(0)
+            .size(size_of::<Sprite>() as u32)
+            .build()];
+        let layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::builder()
+                        .push_constant_ranges(&push_constant_ranges),
+                    None,
+                )
+                .expect("Lens flare pipeline layout")
+        };
+
+        Self {
+            enabled: true,
+            intensity,
+            source: None,
+            layout,
+            target: None,
+        }
+    }
+
+    /// The sprites to draw this frame, none when the light can't be seen
+    fn sprites(&self, extent: vk::Extent2D, encode_srgb: bool) -> Vec<Sprite> {
+        let source = match self.source {
+            Some(source) if self.enabled => source,
+            _ => return Vec::new(),
+        };
+        let [x, y] = source.position;
+        let edge = smoothstep(EDGE_FADE.0, EDGE_FADE.1, x.abs().max(y.abs()));
+        let brightness = source.color.iter().cloned().fold(0.0, f32::max);
+        let strength = self.intensity * source.visibility * (1.0 - edge) * brightness.min(1.0);
+        if strength <= 0.0 {
+            return Vec::new();
+        }
+        let tint = source.color.map(|channel| channel / brightness);
+        let (width, height) = (extent.width as f32, extent.height as f32);
+
+        ELEMENTS
+            .iter()
+            .map(|element| {
+                let size = element.size * height;
+                // Normalized device Y points down the frame like the viewport's
+                let centre = [
+                    (x * element.position + 1.0) / 2.0 * width,
+                    (y * element.position + 1.0) / 2.0 * height,
+                ];
+                let [red, green, blue] = [0, 1, 2].map(|i| element.color[i] * tint[i]);
+                Sprite {
+                    offset: [centre[0] - size / 2.0, centre[1] - size / 2.0],
+                    size: [size, size],
+                    color: [red, green, blue, element.opacity * strength],
+                    shape: element.shape as u32,
+                    encode_srgb: encode_srgb as u32,
+                }
+            })
+            .filter(|sprite| {
+                let [left, top] = sprite.offset;
+                left + sprite.size[0] > 0.0
+                    && top + sprite.size[1] > 0.0
+                    && left < width
+                    && top < height
+            })
+            .collect()
+    }
+
+    fn create_target(&mut self, context: &SwapchainContext) {
+        let device = context.device;
+        let render_pass = create_render_pass(device, context.format);
+        let framebuffers = context
+            .image_views
+            .iter()
+            .map(|&view| {
+                let attachments = [view];
+                unsafe {
+                    device
+                        .create_framebuffer(
+                            &vk::FramebufferCreateInfo::builder()
+                                .render_pass(render_pass)
+                                .attachments(&attachments)
+                                .width(context.extent.width)
+                                .height(context.extent.height)
+                                .layers(1),
+                            None,
+                        )
+                        .expect("Lens flare frame buffer")
+                }
+            })
+            .collect();
+        let encode_srgb = !util::is_srgb_format(context.format);
+        let path = Path::new(env!("OUT_DIR")).join("flare_frag.spv");
+        let pipeline = playground::create_pipeline(
+            device,
+            &pipeline::Target {
+                render_pass,
+                samples: vk::SampleCountFlags::TYPE_1,
+                extent: context.extent,
+                encode_srgb,
+                color: true,
+            },
+            self.layout,
+            &util::read_shader_code(&path),
+            true,
+            false,
+        );
+
+        self.target = Some(Target {
+            extent: context.extent,
+            render_pass,
+            framebuffers,
+            pipeline,
+            encode_srgb,
+        });
+    }
+
+    fn destroy_target(&mut self, device: &ash::Device) {
+        if let Some(target) = self.target.take() {
+            unsafe {
+                device.destroy_pipeline(target.pipeline, None);
+                for framebuffer in target.framebuffers {
+                    device.destroy_framebuffer(framebuffer, None);
+                }
+                device.destroy_render_pass(target.render_pass, None);
+            }
+        }
+    }
+}
+
+impl RenderFeature for LensFlare {
+    fn name(&self) -> &str {
+        "lens flare"
+    }
+
+    fn init(&mut self, context: &SwapchainContext) {
+        self.create_target(context);
+    }
+
+    fn resize(&mut self, context: &SwapchainContext) {
+        self.destroy_target(context.device);
+        self.create_target(context);
+    }
+
+    fn record(&mut self, context: &hooks::FrameContext) {
+        if context.stage != hooks::Stage::PostProcess {
+            return;
+        }
+        let target = match self.target.as_ref() {
+            Some(target) => target,
+            None => return,
+        };
+        let sprites = self.sprites(target.extent, target.encode_srgb);
+        if sprites.is_empty() {
+            return;
+        }
+
+        let device = context.device;
+        let command_buffer = context.command_buffer;
+        let render_pass_bi = vk::RenderPassBeginInfo::builder()
+            .render_pass(target.render_pass)
+            .framebuffer(target.framebuffers[context.image_index])
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: target.extent,
+            });
+        unsafe {
+            device.cmd_begin_render_pass(
+                command_buffer,
+                &render_pass_bi,
+                vk::SubpassContents::INLINE,
+            );
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                target.pipeline,
+            );
+            for sprite in sprites.iter() {
+                // The full-screen triangle covers the viewport, which is narrowed to the sprite
+                let viewports = [vk::Viewport::builder()
+                    .x(sprite.offset[0])
+                    .y(sprite.offset[1])
+                    .width(sprite.size[0])
+                    .height(sprite.size[1])
+                    .min_depth(0.0)
+                    .max_depth(1.0)
+                    .build()];
+                device.cmd_set_viewport(command_buffer, 0, &viewports);
+                device.cmd_push_constants(
+                    command_buffer,
+                    self.layout,
+                    vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    sprite.as_bytes(),
+                );
+                device.cmd_draw(command_buffer, 3, 1, 0, 0);
+            }
+            device.cmd_end_render_pass(command_buffer);
+        }
+    }
+
+    fn passes(&self) -> Vec<framegraph::Pass> {
+        if !self.enabled || self.source.is_none() {
+            return Vec::new();
+        }
+        vec![framegraph::Pass::new(
+            self.name(),
+            framegraph::Position::Stage(hooks::Stage::PostProcess),
+            &[framegraph::SWAPCHAIN],
+            &[framegraph::SWAPCHAIN],
+        )]
+    }
+
+    fn destroy(&mut self, device: &ash::Device) {
+        self.destroy_target(device);
+        unsafe { device.destroy_pipeline_layout(self.layout, None) };
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// A render pass blending over the swapchain image, which it finds and leaves ready to present
+fn create_render_pass(device: &ash::Device, format: vk::Format) -> vk::RenderPass {
+    let attachments = [vk::AttachmentDescription::builder()
+        .format(format)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .load_op(vk::AttachmentLoadOp::LOAD)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+        .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+        .build()];
+    let color_attachment_refs = [vk::AttachmentReference::builder()
+        .attachment(0)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        .build()];
+    let subpasses = [vk::SubpassDescription::builder()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .color_attachments(&color_attachment_refs)
+        .build()];
+    let dependencies = [
+        // The frame's render pass or the post processing filters' copy back wrote the image before this
+        vk::SubpassDependency::builder()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .dst_subpass(0)
+            .src_stage_mask(
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::TRANSFER,
+            )
+            .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .src_access_mask(
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::TRANSFER_WRITE,
+            )
+            .dst_access_mask(
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            )
+            .build(),
+        // Passes after it wait on colour attachment output before copying to or from the image
+        vk::SubpassDependency::builder()
+            .src_subpass(0)
+            .dst_subpass(vk::SUBPASS_EXTERNAL)
+            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .dst_stage_mask(vk::PipelineStageFlags::BOTTOM_OF_PIPE)
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .dst_access_mask(vk::AccessFlags::empty())
+            .build(),
+    ];
+    unsafe {
+        device
+            .create_render_pass(
+                &vk::RenderPassCreateInfo::builder()
+                    .attachments(&attachments)
+                    .subpasses(&subpasses)
+                    .dependencies(&dependencies),
+                None,
+            )
+            .expect("Lens flare render pass")
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
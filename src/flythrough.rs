@@ -0,0 +1,284 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Instant;
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Vector3};
+
+use crate::units;
+
+/// The first line of every camera path file, naming the version of the format
+const HEADER: &str = "rust-renderer-vk camera path 1";
+/// Seconds between the keys of recorded paths
+pub const RECORD_STEP: f32 = 1.0 / 30.0;
+/// Seconds of the path each frame of playback advances by, whatever time the frame took
+pub const PLAYBACK_STEP: f32 = 1.0 / 60.0;
+
+/// Where the camera was at one of a path's keys.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Key {
+    pub eye: Point3<f32>,
+    pub target: Point3<f32>,
+}
+
+impl Key {
+    /// The view from the eye towards the target, with the world's up axis up unless looking along it
+    pub fn view(&self, up: units::Up) -> Matrix4<f32> {
+        let direction = self.target - self.eye;
+        let up = if direction.normalize().dot(up.vector()).abs() > 0.999 {
+            up.rotation() * Vector3::unit_y()
+        } else {
+            up.vector()
+        };
+        Matrix4::look_at_rh(self.eye, self.target, up)
+    }
+}
+
+/// A camera's trajectory as keys a fixed time step apart, played back along Catmull-Rom splines through them.
+///
+/// Camera path files are text, one setting per line:
+///
+/// ```text
+/// rust-renderer-vk camera path 1
+/// step <seconds>
+/// key <eye x> <y> <z> <target x> <y> <z>
+/// ```
+///
+/// with a `key` line for each key in order. Lines starting with `#` are ignored.
+#[derive(Clone, Debug)]
+pub struct CameraPath {
+    /// Seconds between keys
+    pub step: f32,
+    pub keys: Vec<Key>,
+}
+
+impl CameraPath {
+    pub fn new(step: f32) -> Self {
+        Self {
+            step,
+            keys: Vec::new(),
+        }
+    }
+
+    /// Seconds from the first key to the last
+    pub fn duration(&self) -> f32 {
+        self.keys.len().saturating_sub(1) as f32 * self.step
+    }
+
+    /// Where the camera is `time` seconds into the path, clamped to its ends. `None` for an empty path.
+    pub fn sample(&self, time: f32) -> Option<Key> {
+        let last = self.keys.len().checked_sub(1)?;
+        let position = (time / self.step).clamp(0.0, last as f32);
+        let index = (position.floor() as usize).min(last.saturating_sub(1));
+        let t = position - index as f32;
+        // The ends are repeated so the curve passes through the first and last keys
+        let key =
+            |offset: isize| self.keys[(index as isize + offset).clamp(0, last as isize) as usize];
+        let [k0, k1, k2, k3] = [key(-1), key(0), key(1), key(2)];
+        Some(Key {
+            eye: catmull_rom(k0.eye, k1.eye, k2.eye, k3.eye, t),
+            target: catmull_rom(k0.target, k1.target, k2.target, k3.target, t),
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text =
+            fs::read_to_string(path).map_err(|e| format!("Reading {}: {}", path.display(), e))?;
+        Self::from_text(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_text()).map_err(|e| format!("Writing {}: {}", path.display(), e))
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = format!("{}\nstep {}\n", HEADER, self.step);
+        for key in self.keys.iter() {
+            let (eye, target) = (key.eye, key.target);
+            let _ = writeln!(
+                text,
+                "key {} {} {} {} {} {}",
+                eye.x, eye.y, eye.z, target.x, target.y, target.z
+            );
+        }
+        text
+    }
+
+    fn from_text(text: &str) -> Result<Self, String> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(number, line)| (number + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+        match lines.next() {
+            Some((_, HEADER)) => {}
+            _ => return Err(format!("Camera path files start with \"{}\"", HEADER)),
+        }
+
+        let mut path = Self::new(RECORD_STEP);
+        for (number, line) in lines {
+            let mut fields = line.split_whitespace();
+            let key = fields.next().unwrap_or_default();
+            let values = fields
+                .map(|value| parse(number, value))
+                .collect::<Result<Vec<f32>, String>>()?;
+            match key {
+                "step" => match values[..] {
+                    [step] if step > 0.0 => path.step = step,
+                    _ => return Err(format!("Line {}: the step is a positive number", number)),
+                },
+                "key" => {
+                    if values.len() != 6 {
+                        return Err(format!("Line {}: keys need an eye and a target", number));
+                    }
+                    path.keys.push(Key {
+                        eye: Point3::new(values[0], values[1], values[2]),
+                        target: Point3::new(values[3], values[4], values[5]),
+                    });
+                }
+                other => return Err(format!("Line {}: unknown setting {}", number, other)),
+            }
+        }
+        Ok(path)
+    }
+}
+
+/// Records keys of the camera every `RECORD_STEP` seconds of wall time as it moves.
+pub struct Recorder {
+    pub path: CameraPath,
+    started: Instant,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self {
+            path: CameraPath::new(RECORD_STEP),
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Recorder {
+    /// Adds the camera's current key for each step that has passed since the last was added
+    pub fn update(&mut self, key: Key) {
+        let elapsed = self.started.elapsed().as_secs_f32();
+        while self.path.keys.len() as f32 * self.path.step <= elapsed {
+            self.path.keys.push(key);
+        }
+    }
+}
+
+/// Plays a path back `PLAYBACK_STEP` seconds of it per frame, so every playback shows the same frames.
+pub struct Player {
+    path: CameraPath,
+    time: f32,
+}
+
+impl Player {
+    pub fn new(path: CameraPath) -> Self {
+        Self { path, time: 0.0 }
+    }
+
+    /// Where the camera is this frame, moving on to the next. `None` once the whole path has been played.
+    pub fn advance(&mut self) -> Option<Key> {
+        if self.time > self.path.duration() {
+            return None;
+        }
+        let key = self.path.sample(self.time);
+        self.time += PLAYBACK_STEP;
+        key
+    }
+}
+
+/// The point `t` of the way from `p1` to `p2` along the uniform Catmull-Rom spline through the four points
+pub fn catmull_rom(
+    p0: Point3<f32>,
+    p1: Point3<f32>,
+    p2: Point3<f32>,
+    p3: Point3<f32>,
+    t: f32,
+) -> Point3<f32> {
+    let (p0, p1, p2, p3) = (p0.to_vec(), p1.to_vec(), p2.to_vec(), p3.to_vec());
+    let (t2, t3) = (t * t, t * t * t);
+    Point3::from_vec(
+        (p1 * 2.0
+            + (p2 - p0) * t
+            + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+            + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+            * 0.5,
+    )
+}
+
+fn parse<T: FromStr>(number: usize, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Line {}: {} isn't a valid number", number, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(x: f32) -> Key {
+        Key {
+            eye: Point3::new(x, 1.0, 2.0),
+            target: Point3::new(x, 0.0, -0.5),
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut path = CameraPath::new(0.25);
+        path.keys = vec![key(0.0), key(1.5), key(-3.25)];
+        let loaded = CameraPath::from_text(&path.to_text()).unwrap();
+        assert_eq!(loaded.step, 0.25);
+        assert_eq!(loaded.keys, path.keys);
+    }
+
+    #[test]
+    fn reports_the_line_of_errors() {
+        let error = |body: &str| CameraPath::from_text(&format!("{}\n{}", HEADER, body)).err();
+        assert!(CameraPath::from_text("step 1\n").is_err());
+        assert_eq!(
+            error("# recorded\n\nstep 0\n").as_deref(),
+            Some("Line 4: the step is a positive number")
+        );
+        assert_eq!(
+            error("key 0 0 0 1 1\n").as_deref(),
+            Some("Line 2: keys need an eye and a target")
+        );
+        assert_eq!(
+            error("key 0 0 0 1 1 up\n").as_deref(),
+            Some("Line 2: up isn't a valid number")
+        );
+        assert_eq!(
+            error("step 1\nspeed 2\n").as_deref(),
+            Some("Line 3: unknown setting speed")
+        );
+    }
+
+    #[test]
+    fn samples_pass_through_the_keys() {
+        let mut path = CameraPath::new(0.5);
+        assert!(path.sample(0.0).is_none());
+        path.keys = vec![key(0.0), key(1.0), key(4.0), key(9.0)];
+        assert_eq!(path.duration(), 1.5);
+        for (index, expected) in path.keys.iter().enumerate() {
+            let sampled = path.sample(index as f32 * 0.5).unwrap();
+            assert!((sampled.eye - expected.eye).magnitude() < 1e-5);
+            assert!((sampled.target - expected.target).magnitude() < 1e-5);
+        }
+        // Times past the ends are clamped to them
+        assert_eq!(path.sample(-1.0), Some(key(0.0)));
+        assert!((path.sample(10.0).unwrap().eye - key(9.0).eye).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn keys_along_a_line_sample_along_it() {
+        let mut path = CameraPath::new(1.0);
+        path.keys = (0..4).map(|x| key(x as f32)).collect();
+        let sampled = path.sample(1.25).unwrap();
+        assert!((sampled.eye - Point3::new(1.25, 1.0, 2.0)).magnitude() < 1e-5);
+    }
+}
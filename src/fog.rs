@@ -0,0 +1,724 @@
+use std::any::Any;
+use std::mem::size_of;
+use std::path::Path;
+
+use ash::vk;
+use cgmath::{InnerSpace, Point3, SquareMatrix, Transform};
+
+use crate::features::{RenderFeature, SwapchainContext};
+use crate::{
+    camera, framegraph, hooks, postprocess, util, HelloTriangleApplication, ImageDescription,
+};
+
+/// Froxels across, down and away from the camera. Matches `GRID` in the fog shaders.
+const GRID: [u32; 3] = [160, 90, 64];
+/// Distance the first froxel slice starts at, about the nearest depth the projection keeps
+const NEAR: f32 = 0.2;
+/// Froxels injected per workgroup in each direction, matching the local size in `fog_inject_comp.glsl`
+const INJECT_WORKGROUP_SIZE: u32 = 4;
+
+/// How the fog looks. Can be changed between frames.
+#[derive(Clone, Copy, Debug)]
+pub struct Settings {
+    /// How much of the light passing through a unit of fog is scattered, at `base_height` and below
+    pub density: f32,
+    /// From -1 to 1, how much more light is scattered forwards than back. Positive values brighten the fog
+    /// looking towards the light.
+    pub anisotropy: f32,
+    /// How quickly the fog thins out above `base_height`
+    pub height_falloff: f32,
+    pub base_height: f32,
+    /// How far from the camera the fog reaches. Everything further away, including the background, is fogged as
+    /// if it were this far away.
+    pub range: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            density: 0.3,
+            anisotropy: 0.6,
+            height_falloff: 2.0,
+            base_height: -0.75,
+            range: 10.0,
+        }
+    }
+}
+
+/// Matches `Frame` in the fog shaders
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Frame {
+    view_projection: [[f32; 4]; 4],
+    inverse_view_projection: [[f32; 4]; 4],
+    camera: [f32; 4],
+    light: [f32; 4],
+    medium: [f32; 4],
+    range: [f32; 4],
+    depth_mapping: [f32; 4],
+    extent: [u32; 4],
+    light_color: [f32; 4],
+    ambient: [f32; 4],
+}
+
+/// What the fog needs for each swapchain, recreated with it
+struct Resources {
+    extent: vk::Extent2D,
+    depth_format: vk::Format,
+    /// Whether the swapchain's colour is sRGB encoded in the frame image, rather than decoded by the blit
+    encoded: bool,
+    /// The depth buffer copied out after the render pass, one 32 bit texel per pixel
+    depth_buffer: (vk::Buffer, vk::DeviceMemory),
+    scattering_buffer: (vk::Buffer, vk::DeviceMemory),
+    integrated_buffer: (vk::Buffer, vk::DeviceMemory),
+    /// The frame is blitted out of the swapchain image into this storage image to be fogged, then back
+    frame_image: (vk::Image, vk::DeviceMemory, vk::ImageView),
+    /// One for each swapchain image, since frames in flight each write their own
+    uniform_buffers: Vec<(vk::Buffer, vk::DeviceMemory)>,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+}
+
+/// Volumetric fog lit by the renderer's fixed light, applied to the rendered frame before post processing. The
+/// view frustum is divided into a grid of froxels, frustum shaped voxels sliced exponentially away from the
+/// camera. Each frame a compute pass works out how much light each froxel scatters towards the camera, a second
+/// accumulates the froxels from the camera outwards, and a third fogs each pixel by the froxels in front of it.
+///
+/// Light is shadowed by marching towards it through the depth buffer, so objects on screen cast light shafts
+/// through the fog. Anything off screen, or hidden behind something else, casts no shadow.
+pub struct Fog {
+    pub settings: Settings,
+    pub enabled: bool,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    layout: vk::PipelineLayout,
+    inject_pipeline: vk::Pipeline,
+    integrate_pipeline: vk::Pipeline,
+    apply_pipeline: vk::Pipeline,
+    /// Created with the swapchain, if its images can be copied
+    resources: Option<Resources>,
+}
+
+impl Fog {
+    pub fn new(device: &ash::Device, settings: Settings) -> Self {
+        let binding = |binding: u32, descriptor_type: vk::DescriptorType| {
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding)
+                .descriptor_count(1)
+                .descriptor_type(descriptor_type)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build()
+        };
+        let bindings = [
+            binding(0, vk::DescriptorType::UNIFORM_BUFFER),
+            binding(1, vk::DescriptorType::STORAGE_BUFFER),
+            binding(2, vk::DescriptorType::STORAGE_BUFFER),
+            binding(3, vk::DescriptorType::STORAGE_BUFFER),
+            binding(4, vk::DescriptorType::STORAGE_IMAGE),
+        ];
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+                    None,
+                )
+                .expect("Fog descriptor set layout")
+        };
+        let set_layouts = [descriptor_set_layout];
+        let layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::builder().set_layouts(&set_layouts),
+                    None,
+                )
+                .expect("Fog pipeline layout")
+        };
+        let create_pipeline = |name: &str| {
+            let path = Path::new(env!("OUT_DIR")).join(name);
+            postprocess::create_compute_pipeline(device, layout, &util::read_shader_code(&path))
+        };
+
+        Self {
+            settings,
+            enabled: true,
+            descriptor_set_layout,
+            layout,
+            inject_pipeline: create_pipeline("fog_inject_comp.spv"),
+            integrate_pipeline: create_pipeline("fog_integrate_comp.spv"),
+            apply_pipeline: create_pipeline("fog_apply_comp.spv"),
+            resources: None,
+        }
+    }
+
+    fn create_resources(&mut self, context: &SwapchainContext) {
+        if !context.usage.contains(postprocess::SWAPCHAIN_USAGE) {
+            return;
+        }
+        let device = context.device;
+        let extent = context.extent;
+        let storage_buffer = |size: usize, usage: vk::BufferUsageFlags| {
+            HelloTriangleApplication::create_buffer(
+                device,
+                size as vk::DeviceSize,
+                vk::BufferUsageFlags::STORAGE_BUFFER | usage,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                context.device_memory_properties,
+            )
+        };
+        let froxel_count = (GRID[0] * GRID[1] * GRID[2]) as usize;
+        let depth_buffer = storage_buffer(
+            (extent.width * extent.height) as usize * size_of::<u32>(),
+            vk::BufferUsageFlags::TRANSFER_DST,
+        );
+        let scattering_buffer = storage_buffer(
+            froxel_count * size_of::<[f32; 4]>(),
+            vk::BufferUsageFlags::empty(),
+        );
+        let integrated_buffer = storage_buffer(
+            froxel_count * size_of::<[f32; 4]>(),
+            vk::BufferUsageFlags::empty(),
+        );
+
+        let format = vk::Format::R16G16B16A16_SFLOAT;
+        let (image, memory) = HelloTriangleApplication::create_image(
+            device,
+            &ImageDescription {
+                width: extent.width,
+                height: extent.height,
+                format,
+                tiling: vk::ImageTiling::OPTIMAL,
+                usage: vk::ImageUsageFlags::STORAGE
+                    | vk::ImageUsageFlags::TRANSFER_SRC
+                    | vk::ImageUsageFlags::TRANSFER_DST,
+                memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            },
+            context.device_memory_properties,
+        );
+        let view = HelloTriangleApplication::create_image_view(
+            device,
+            image,
+            format,
+            vk::ImageAspectFlags::COLOR,
+        );
+
+        let uniform_buffers: Vec<(vk::Buffer, vk::DeviceMemory)> = (0..context.image_count)
+            .map(|_| {
+                HelloTriangleApplication::create_buffer(
+                    device,
+                    size_of::<Frame>() as vk::DeviceSize,
+                    vk::BufferUsageFlags::UNIFORM_BUFFER,
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                    context.device_memory_properties,
+                )
+            })
+            .collect();
+
+        let count = context.image_count as u32;
+        let pool_size = |ty: vk::DescriptorType, per_set: u32| {
+            vk::DescriptorPoolSize::builder()
+                .ty(ty)
+                .descriptor_count(per_set * count)
+                .build()
+        };
+        let pool_sizes = [
+            pool_size(vk::DescriptorType::UNIFORM_BUFFER, 1),
+            pool_size(vk::DescriptorType::STORAGE_BUFFER, 3),
+            pool_size(vk::DescriptorType::STORAGE_IMAGE, 1),
+        ];
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::builder()
+                        .pool_sizes(&pool_sizes)
+                        .max_sets(count),
+                    None,
+                )
+                .expect("Fog descriptor pool")
+        };
+        let set_layouts = vec![self.descriptor_set_layout; context.image_count];
+        let descriptor_sets = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::builder()
+                        .descriptor_pool(descriptor_pool)
+                        .set_layouts(&set_layouts),
+                )
+                .expect("Fog descriptor sets")
+        };
+        let image_info = [vk::DescriptorImageInfo::builder()
+            .image_layout(vk::ImageLayout::GENERAL)
+            .image_view(view)
+            .build()];
+        for (&set, &(uniform_buffer, _)) in descriptor_sets.iter().zip(uniform_buffers.iter()) {
+            let buffer_infos = [
+                uniform_buffer,
+                depth_buffer.0,
+                scattering_buffer.0,
+                integrated_buffer.0,
+            ]
+            .map(|buffer| {
+                [vk::DescriptorBufferInfo::builder()
+                    .buffer(buffer)
+                    .offset(0)
+                    .range(vk::WHOLE_SIZE)
+                    .build()]
+            });
+            let mut writes: Vec<vk::WriteDescriptorSet> = buffer_infos
+                .iter()
+                .enumerate()
+                .map(|(binding, buffer_info)| {
+                    vk::WriteDescriptorSet::builder()
+                        .dst_set(set)
+                        .dst_binding(binding as u32)
+                        .dst_array_element(0)
+                        .descriptor_type(if binding == 0 {
+                            vk::DescriptorType::UNIFORM_BUFFER
+                        } else {
+                            vk::DescriptorType::STORAGE_BUFFER
+                        })
+                        .buffer_info(buffer_info)
+                        .build()
+                })
+                .collect();
+            writes.push(
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(set)
+                    .dst_binding(4)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                    .image_info(&image_info)
+                    .build(),
+            );
+            unsafe { device.update_descriptor_sets(&writes, &[]) };
+        }
+
+        self.resources = Some(Resources {
+            extent,
+            depth_format: context.depth_format,
+            encoded: !util::is_srgb_format(context.format),
+            depth_buffer,
+            scattering_buffer,
+            integrated_buffer,
+            frame_image: (image, memory, view),
+            uniform_buffers,
+            descriptor_pool,
+            descriptor_sets,
+        });
+    }
+
+    fn destroy_resources(&mut self, device: &ash::Device) {
+        if let Some(resources) = self.resources.take() {
+            unsafe {
+                device.destroy_descriptor_pool(resources.descriptor_pool, None);
+                let (image, memory, view) = resources.frame_image;
+                device.destroy_image_view(view, None);
+                device.destroy_image(image, None);
+                device.free_memory(memory, None);
+                for &(buffer, memory) in [
+                    resources.depth_buffer,
+                    resources.scattering_buffer,
+                    resources.integrated_buffer,
+                ]
+                .iter()
+                .chain(resources.uniform_buffers.iter())
+                {
+                    device.destroy_buffer(buffer, None);
+                    device.free_memory(memory, None);
+                }
+            }
+        }
+    }
+
+    /// Writes the frame's camera and the settings to the image's uniform buffer
+    fn write_frame(
+        &self,
+        device: &ash::Device,
+        resources: &Resources,
+        context: &hooks::FrameContext,
+    ) {
+        let view_projection = context.projection * context.view;
+        let inverse_view_projection = view_projection
+            .invert()
+            .expect("view projection is invertible");
+        let camera = context
+            .view
+            .invert()
+            .expect("view is invertible")
+            .transform_point(Point3::new(0.0, 0.0, 0.0));
+        let light = context.light.direction.normalize();
+        let [red, green, blue] = context.light.color;
+        let [ambient_red, ambient_green, ambient_blue] = context.light.ambient;
+        let settings = &self.settings;
+        let frame = Frame {
+            view_projection: view_projection.into(),
+            inverse_view_projection: inverse_view_projection.into(),
+            camera: [camera.x, camera.y, camera.z, 1.0],
+            light: [
+                light.x,
+                light.y,
+                light.z,
+                settings.anisotropy.clamp(-0.99, 0.99),
+            ],
+            medium: [
+                settings.density.max(0.0),
+                settings.height_falloff.max(0.0),
+                settings.base_height,
+                0.0,
+            ],
+            range: [NEAR, settings.range.max(2.0 * NEAR), 0.0, 0.0],
+            depth_mapping: camera::DepthMapping::new(&context.projection).uniform(),
+            extent: [
+                resources.extent.width,
+                resources.extent.height,
+                (resources.depth_format == vk::Format::D24_UNORM_S8_UINT) as u32,
+                resources.encoded as u32,
+            ],
+            light_color: [red, green, blue, 0.0],
+            ambient: [ambient_red, ambient_green, ambient_blue, 0.0],
+        };
+        let memory = resources.uniform_buffers[context.image_index].1;
+        unsafe {
+            let mapped = device
+                .map_memory(
+                    memory,
+                    0,
+                    size_of::<Frame>() as vk::DeviceSize,
+                    vk::MemoryMapFlags::empty(),
+                )
+                .expect("Mapping fog uniform buffer") as *mut Frame;
+            mapped.copy_from_nonoverlapping(&frame, 1);
+            device.unmap_memory(memory);
+        }
+    }
+
+    /// Records fogging the frame after the render pass. The swapchain image is ready to present again afterwards.
+    fn record_fog(&self, context: &hooks::FrameContext, resources: &Resources) {
+        let device = context.device;
+        let command_buffer = context.command_buffer;
+        let extent = resources.extent;
+        let (frame_image, _, _) = resources.frame_image;
+        let mut depth_aspect = vk::ImageAspectFlags::DEPTH;
+        if HelloTriangleApplication::has_stencil_component(resources.depth_format) {
+            depth_aspect |= vk::ImageAspectFlags::STENCIL;
+        }
+        let image_barrier = |image: vk::Image,
+                             aspect_mask: vk::ImageAspectFlags,
+                             old: vk::ImageLayout,
+                             new: vk::ImageLayout,
+                             src_access: vk::AccessFlags,
+                             dst_access: vk::AccessFlags| {
+            vk::ImageMemoryBarrier::builder()
+                .old_layout(old)
+                .new_layout(new)
+                .src_access_mask(src_access)
+                .dst_access_mask(dst_access)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(
+                    vk::ImageSubresourceRange::builder()
+                        .aspect_mask(aspect_mask)
+                        .base_mip_level(0)
+                        .level_count(1)
+                        .base_array_layer(0)
+                        .layer_count(1)
+                        .build(),
+                )
+                .build()
+        };
+        let memory_barrier = |src_access: vk::AccessFlags, dst_access: vk::AccessFlags| {
+            vk::MemoryBarrier::builder()
+                .src_access_mask(src_access)
+                .dst_access_mask(dst_access)
+                .build()
+        };
+        let color_layers = vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let full_image = [
+            vk::Offset3D { x: 0, y: 0, z: 0 },
+            vk::Offset3D {
+                x: extent.width as i32,
+                y: extent.height as i32,
+                z: 1,
+            },
+        ];
+        let blit = vk::ImageBlit::builder()
+            .src_subresource(color_layers)
+            .src_offsets(full_image)
+            .dst_subresource(color_layers)
+            .dst_offsets(full_image)
+            .build();
+        let depth_copy = vk::BufferImageCopy::builder()
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::DEPTH,
+                ..color_layers
+            })
+            .image_extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            })
+            .build();
+        let dispatch = |pipeline: vk::Pipeline, groups: [u32; 3]| unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline);
+            device.cmd_dispatch(command_buffer, groups[0], groups[1], groups[2]);
+        };
+        let compute_barrier = || unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[memory_barrier(
+                    vk::AccessFlags::SHADER_WRITE,
+                    vk::AccessFlags::SHADER_READ,
+                )],
+                &[],
+                &[],
+            )
+        };
+        let groups = |count: u32, size: u32| count.div_ceil(size);
+        let filter_groups = |count: u32| groups(count, postprocess::WORKGROUP_SIZE);
+
+        unsafe {
+            // The previous frame's fog may still be reading the depth buffer and frame image
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::COMPUTE_SHADER
+                    | vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[
+                    image_barrier(
+                        context.depth_image,
+                        depth_aspect,
+                        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                        vk::AccessFlags::TRANSFER_READ,
+                    ),
+                    image_barrier(
+                        context.swapchain_image,
+                        vk::ImageAspectFlags::COLOR,
+                        vk::ImageLayout::PRESENT_SRC_KHR,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                        vk::AccessFlags::TRANSFER_READ,
+                    ),
+                    image_barrier(
+                        frame_image,
+                        vk::ImageAspectFlags::COLOR,
+                        vk::ImageLayout::UNDEFINED,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::AccessFlags::empty(),
+                        vk::AccessFlags::TRANSFER_WRITE,
+                    ),
+                ],
+            );
+            device.cmd_copy_image_to_buffer(
+                command_buffer,
+                context.depth_image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                resources.depth_buffer.0,
+                &[depth_copy],
+            );
+            device.cmd_blit_image(
+                command_buffer,
+                context.swapchain_image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                frame_image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[blit],
+                vk::Filter::NEAREST,
+            );
+            // The next frame's depth test must not start until the copy has read the depth buffer
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::COMPUTE_SHADER
+                    | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                vk::DependencyFlags::empty(),
+                &[memory_barrier(
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    vk::AccessFlags::SHADER_READ,
+                )],
+                &[],
+                &[
+                    image_barrier(
+                        context.depth_image,
+                        depth_aspect,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                        vk::AccessFlags::empty(),
+                        vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                    ),
+                    image_barrier(
+                        frame_image,
+                        vk::ImageAspectFlags::COLOR,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::ImageLayout::GENERAL,
+                        vk::AccessFlags::TRANSFER_WRITE,
+                        vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+                    ),
+                ],
+            );
+
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.layout,
+                0,
+                &[resources.descriptor_sets[context.image_index]],
+                &[],
+            );
+        }
+        dispatch(
+            self.inject_pipeline,
+            GRID.map(|count| groups(count, INJECT_WORKGROUP_SIZE)),
+        );
+        compute_barrier();
+        dispatch(
+            self.integrate_pipeline,
+            [filter_groups(GRID[0]), filter_groups(GRID[1]), 1],
+        );
+        compute_barrier();
+        dispatch(
+            self.apply_pipeline,
+            [filter_groups(extent.width), filter_groups(extent.height), 1],
+        );
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[
+                    image_barrier(
+                        frame_image,
+                        vk::ImageAspectFlags::COLOR,
+                        vk::ImageLayout::GENERAL,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        vk::AccessFlags::SHADER_WRITE,
+                        vk::AccessFlags::TRANSFER_READ,
+                    ),
+                    image_barrier(
+                        context.swapchain_image,
+                        vk::ImageAspectFlags::COLOR,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::AccessFlags::TRANSFER_READ,
+                        vk::AccessFlags::TRANSFER_WRITE,
+                    ),
+                ],
+            );
+            device.cmd_blit_image(
+                command_buffer,
+                frame_image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                context.swapchain_image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[blit],
+                vk::Filter::NEAREST,
+            );
+            // Post processing and presentation read the frame next
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[image_barrier(
+                    context.swapchain_image,
+                    vk::ImageAspectFlags::COLOR,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::PRESENT_SRC_KHR,
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    vk::AccessFlags::TRANSFER_READ,
+                )],
+            );
+        }
+    }
+}
+
+impl RenderFeature for Fog {
+    fn name(&self) -> &str {
+        "volumetric fog"
+    }
+
+    fn init(&mut self, context: &SwapchainContext) {
+        if !context.usage.contains(postprocess::SWAPCHAIN_USAGE) {
+            println!("The surface doesn't support copying swapchain images, fog is disabled");
+        }
+        self.create_resources(context);
+    }
+
+    fn resize(&mut self, context: &SwapchainContext) {
+        self.destroy_resources(context.device);
+        self.create_resources(context);
+    }
+
+    fn record(&mut self, context: &hooks::FrameContext) {
+        if context.stage != hooks::Stage::BeforePost || !self.enabled {
+            return;
+        }
+        if let Some(resources) = self.resources.as_ref() {
+            self.write_frame(context.device, resources, context);
+            self.record_fog(context, resources);
+        }
+    }
+
+    fn passes(&self) -> Vec<framegraph::Pass> {
+        if !self.enabled {
+            return Vec::new();
+        }
+        let stage = framegraph::Position::Stage(hooks::Stage::BeforePost);
+        vec![
+            framegraph::Pass::new(
+                "fog inject",
+                stage,
+                &[framegraph::DEPTH],
+                &["fog scattering"],
+            ),
+            framegraph::Pass::new(
+                "fog integrate",
+                stage,
+                &["fog scattering"],
+                &["integrated fog"],
+            ),
+            framegraph::Pass::new(
+                "fog apply",
+                stage,
+                &[framegraph::SWAPCHAIN, framegraph::DEPTH, "integrated fog"],
+                &[framegraph::SWAPCHAIN],
+            ),
+        ]
+    }
+
+    fn destroy(&mut self, device: &ash::Device) {
+        self.destroy_resources(device);
+        unsafe {
+            device.destroy_pipeline(self.inject_pipeline, None);
+            device.destroy_pipeline(self.integrate_pipeline, None);
+            device.destroy_pipeline(self.apply_pipeline, None);
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
@@ -0,0 +1,360 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use ash::vk;
+
+/// What an image format is needed for. Candidates are in order of preference, and only those supporting the
+/// required features with the tiling are considered.
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub name: &'static str,
+    pub candidates: Vec<vk::Format>,
+    /// Either tiling is tried when this is `None`, preferring optimal
+    pub tiling: Option<vk::ImageTiling>,
+    pub required: vk::FormatFeatureFlags,
+    /// Features that are useful but not needed, each making a candidate preferable to one without it
+    pub preferred: vk::FormatFeatureFlags,
+    /// Favours candidates that use less memory per texel over those earlier in the list
+    pub prefer_small: bool,
+}
+
+impl Request {
+    /// The renderer's depth attachment
+    pub fn depth() -> Self {
+        Self {
+            name: "depth",
+            // Planar ground shadows clamp the stencil so that overlapping shadows only darken once
+            candidates: vec![
+                vk::Format::D32_SFLOAT_S8_UINT,
+                vk::Format::D32_SFLOAT,
+                vk::Format::D24_UNORM_S8_UINT,
+            ],
+            tiling: Some(vk::ImageTiling::OPTIMAL),
+            required: vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,
+            // Hi-Z culling builds its depth pyramid by sampling the attachment
+            preferred: vk::FormatFeatureFlags::SAMPLED_IMAGE,
+            prefer_small: false,
+        }
+    }
+
+    /// Shadow maps are rendered into as depth and sampled when shading, filtered if possible
+    pub fn shadow_depth() -> Self {
+        Self {
+            name: "shadow depth",
+            candidates: vec![
+                vk::Format::D16_UNORM,
+                vk::Format::D32_SFLOAT,
+                vk::Format::X8_D24_UNORM_PACK32,
+                vk::Format::D24_UNORM_S8_UINT,
+            ],
+            tiling: Some(vk::ImageTiling::OPTIMAL),
+            required: vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT
+                | vk::FormatFeatureFlags::SAMPLED_IMAGE,
+            preferred: vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR,
+            prefer_small: true,
+        }
+    }
+
+    /// Variance shadow maps' depth and depth squared, drawn into as colour and blurred by a compute shader. Depth
+    /// squared needs full precision, and storage of four 32-bit floats is always supported.
+    pub fn shadow_moments() -> Self {
+        Self {
+            name: "shadow moments",
+            candidates: vec![vk::Format::R32G32B32A32_SFLOAT],
+            tiling: Some(vk::ImageTiling::OPTIMAL),
+            required: vk::FormatFeatureFlags::COLOR_ATTACHMENT
+                | vk::FormatFeatureFlags::SAMPLED_IMAGE
+                | vk::FormatFeatureFlags::STORAGE_IMAGE,
+            preferred: vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR,
+            prefer_small: false,
+        }
+    }
+
+    /// Colour targets holding values beyond 1.0, drawn over with blending and read back by compute shaders
+    pub fn hdr_color() -> Self {
+        Self {
+            name: "hdr colour",
+            candidates: vec![
+                vk::Format::R16G16B16A16_SFLOAT,
+                vk::Format::B10G11R11_UFLOAT_PACK32,
+                vk::Format::R32G32B32A32_SFLOAT,
+            ],
+            tiling: Some(vk::ImageTiling::OPTIMAL),
+            required: vk::FormatFeatureFlags::COLOR_ATTACHMENT
+                | vk::FormatFeatureFlags::SAMPLED_IMAGE,
+            preferred: vk::FormatFeatureFlags::COLOR_ATTACHMENT_BLEND
+                | vk::FormatFeatureFlags::STORAGE_IMAGE,
+            prefer_small: false,
+        }
+    }
+
+    /// A G-buffer's normals, which need precision more than range
+    pub fn normals() -> Self {
+        Self {
+            name: "normals",
+            candidates: vec![
+                vk::Format::A2B10G10R10_UNORM_PACK32,
+                vk::Format::R16G16B16A16_SNORM,
+                vk::Format::R16G16B16A16_SFLOAT,
+                vk::Format::R8G8B8A8_SNORM,
+            ],
+            tiling: Some(vk::ImageTiling::OPTIMAL),
+            required: vk::FormatFeatureFlags::COLOR_ATTACHMENT
+                | vk::FormatFeatureFlags::SAMPLED_IMAGE,
+            preferred: vk::FormatFeatureFlags::empty(),
+            prefer_small: true,
+        }
+    }
+}
+
+/// The format chosen for a request, and how images of it should be tiled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Choice {
+    pub format: vk::Format,
+    pub tiling: vk::ImageTiling,
+}
+
+/// Picks image formats for a physical device. What each format supports is only asked of the device once, so
+/// choosing formats repeatedly, such as whenever the swapchain is recreated, is cheap.
+pub struct Formats {
+    physical_device: vk::PhysicalDevice,
+    properties: RefCell<HashMap<vk::Format, vk::FormatProperties>>,
+}
+
+impl Formats {
+    pub fn new(physical_device: vk::PhysicalDevice) -> Self {
+        Self {
+            physical_device,
+            properties: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// The best candidate supporting the request's required features, or `None` if none do. Candidates score
+    /// for each preferred feature they support, lose a little for every candidate ahead of them, and lose for
+    /// their size when the request prefers small formats.
+    pub fn select(&self, instance: &ash::Instance, request: &Request) -> Option<Choice> {
+        choose(request, |format| self.properties(instance, format))
+    }
+
+    /// Whether optimally tiled images of the format support every one of the features
+    pub fn supports(
+        &self,
+        instance: &ash::Instance,
+        format: vk::Format,
+        features: vk::FormatFeatureFlags,
+    ) -> bool {
+        self.properties(instance, format)
+            .optimal_tiling_features
+            .contains(features)
+    }
+
+    fn properties(&self, instance: &ash::Instance, format: vk::Format) -> vk::FormatProperties {
+        let physical_device = self.physical_device;
+        *self
+            .properties
+            .borrow_mut()
+            .entry(format)
+            .or_insert_with(|| unsafe {
+                instance.get_physical_device_format_properties(physical_device, format)
+            })
+    }
+}
+
+/// Scores the request's candidates like `Formats::select`, given what each format supports
+fn choose(
+    request: &Request,
+    mut properties: impl FnMut(vk::Format) -> vk::FormatProperties,
+) -> Option<Choice> {
+    let tilings = match request.tiling {
+        Some(tiling) => vec![tiling],
+        None => vec![vk::ImageTiling::OPTIMAL, vk::ImageTiling::LINEAR],
+    };
+    let mut best: Option<(i32, Choice)> = None;
+    for (rank, &format) in request.candidates.iter().enumerate() {
+        let properties = properties(format);
+        for (tiling_rank, &tiling) in tilings.iter().enumerate() {
+            let features = match tiling {
+                vk::ImageTiling::LINEAR => properties.linear_tiling_features,
+                _ => properties.optimal_tiling_features,
+            };
+            if !features.contains(request.required) {
+                continue;
+            }
+            let preferred = (features & request.preferred).as_raw().count_ones() as i32;
+            let size = if request.prefer_small {
+                bytes_per_texel(format) as i32
+            } else {
+                0
+            };
+            let score = preferred * 16 - size * 4 - rank as i32 - tiling_rank as i32 * 2;
+            if best.is_none_or(|(best_score, _)| score > best_score) {
+                best = Some((score, Choice { format, tiling }));
+            }
+        }
+    }
+    best.map(|(_, choice)| choice)
+}
+
+/// The memory a texel of the format takes, or 16 for formats this doesn't know so they are assumed expensive
+pub fn bytes_per_texel(format: vk::Format) -> u32 {
+    match format {
+        vk::Format::R8_UNORM | vk::Format::R8_SNORM | vk::Format::S8_UINT => 1,
+        vk::Format::R8G8_UNORM | vk::Format::R16_SFLOAT | vk::Format::D16_UNORM => 2,
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SNORM
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SRGB
+        | vk::Format::A2B10G10R10_UNORM_PACK32
+        | vk::Format::B10G11R11_UFLOAT_PACK32
+        | vk::Format::R16G16_SFLOAT
+        | vk::Format::R32_SFLOAT
+        | vk::Format::D32_SFLOAT
+        | vk::Format::X8_D24_UNORM_PACK32
+        | vk::Format::D24_UNORM_S8_UINT => 4,
+        vk::Format::D32_SFLOAT_S8_UINT => 5,
+        vk::Format::R16G16B16A16_SFLOAT
+        | vk::Format::R16G16B16A16_SNORM
+        | vk::Format::R32G32_SFLOAT => 8,
+        _ => 16,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Features = vk::FormatFeatureFlags;
+
+    /// Chooses for the request on a device supporting the listed features with optimal tiling and, where
+    /// given, linear tiling. Formats that aren't listed support nothing.
+    fn choose_from(
+        request: &Request,
+        supported: &[(vk::Format, Features, Features)],
+    ) -> Option<Choice> {
+        choose(request, |format| {
+            let (optimal, linear) = supported
+                .iter()
+                .find(|&&(supported, _, _)| supported == format)
+                .map_or(
+                    (Features::empty(), Features::empty()),
+                    |&(_, optimal, linear)| (optimal, linear),
+                );
+            vk::FormatProperties {
+                optimal_tiling_features: optimal,
+                linear_tiling_features: linear,
+                ..Default::default()
+            }
+        })
+    }
+
+    fn optimal(format: vk::Format) -> Choice {
+        Choice {
+            format,
+            tiling: vk::ImageTiling::OPTIMAL,
+        }
+    }
+
+    #[test]
+    fn takes_the_first_supported_candidate() {
+        let attachment = Features::DEPTH_STENCIL_ATTACHMENT;
+        let supported = [
+            (vk::Format::D32_SFLOAT, attachment, Features::empty()),
+            (vk::Format::D24_UNORM_S8_UINT, attachment, Features::empty()),
+        ];
+        assert_eq!(
+            choose_from(&Request::depth(), &supported),
+            Some(optimal(vk::Format::D32_SFLOAT))
+        );
+    }
+
+    #[test]
+    fn none_without_the_required_features() {
+        let supported = [(
+            vk::Format::D32_SFLOAT,
+            Features::SAMPLED_IMAGE,
+            Features::DEPTH_STENCIL_ATTACHMENT,
+        )];
+        // The request needs optimal tiling, so linear support doesn't count
+        assert_eq!(choose_from(&Request::depth(), &supported), None);
+    }
+
+    #[test]
+    fn preferred_features_outweigh_order() {
+        let attachment = Features::DEPTH_STENCIL_ATTACHMENT;
+        let supported = [
+            (
+                vk::Format::D32_SFLOAT_S8_UINT,
+                attachment,
+                Features::empty(),
+            ),
+            (
+                vk::Format::D24_UNORM_S8_UINT,
+                attachment | Features::SAMPLED_IMAGE,
+                Features::empty(),
+            ),
+        ];
+        assert_eq!(
+            choose_from(&Request::depth(), &supported),
+            Some(optimal(vk::Format::D24_UNORM_S8_UINT))
+        );
+    }
+
+    #[test]
+    fn small_formats_outweigh_order_when_preferred() {
+        let required = Features::COLOR_ATTACHMENT | Features::SAMPLED_IMAGE;
+        let request = Request {
+            candidates: vec![vk::Format::R16G16B16A16_SFLOAT, vk::Format::R8G8B8A8_UNORM],
+            ..Request::normals()
+        };
+        let supported = [
+            (vk::Format::R16G16B16A16_SFLOAT, required, Features::empty()),
+            (vk::Format::R8G8B8A8_UNORM, required, Features::empty()),
+        ];
+        assert_eq!(
+            choose_from(&request, &supported),
+            Some(optimal(vk::Format::R8G8B8A8_UNORM))
+        );
+        let request = Request {
+            prefer_small: false,
+            ..request
+        };
+        assert_eq!(
+            choose_from(&request, &supported),
+            Some(optimal(vk::Format::R16G16B16A16_SFLOAT))
+        );
+    }
+
+    #[test]
+    fn falls_back_to_linear_tiling() {
+        let request = Request {
+            tiling: None,
+            ..Request::hdr_color()
+        };
+        let required = Features::COLOR_ATTACHMENT | Features::SAMPLED_IMAGE;
+        let supported = [
+            (vk::Format::R16G16B16A16_SFLOAT, Features::empty(), required),
+            (
+                vk::Format::R32G32B32A32_SFLOAT,
+                required,
+                required | Features::STORAGE_IMAGE,
+            ),
+        ];
+        // A preferred feature outweighs optimal tiling
+        assert_eq!(
+            choose_from(&request, &supported),
+            Some(Choice {
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                tiling: vk::ImageTiling::LINEAR,
+            })
+        );
+        let supported = [(vk::Format::R16G16B16A16_SFLOAT, Features::empty(), required)];
+        assert_eq!(
+            choose_from(&request, &supported),
+            Some(Choice {
+                format: vk::Format::R16G16B16A16_SFLOAT,
+                tiling: vk::ImageTiling::LINEAR,
+            })
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use ash::vk;
+
+use crate::hooks;
+
+/// Images and buffers passes share, named as they appear in the graph
+pub const SWAPCHAIN: &str = "swapchain image";
+pub const DEPTH: &str = "depth";
+pub const UNIFORMS: &str = "uniforms";
+
+/// Where in the frame a pass is recorded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Position {
+    /// Compute work recorded before the render pass begins
+    BeforeRenderPass,
+    /// The render pass drawing the scene
+    RenderPass,
+    Stage(hooks::Stage),
+    /// Out of the renderer's hands, after the frame is submitted
+    Present,
+}
+
+/// Which of the renderer's queues a pass is submitted to. Compute is the graphics queue too when the device has
+/// no separate compute family, and so is present when the graphics family can present.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Queue {
+    Graphics,
+    Compute,
+    Present,
+}
+
+/// One step of the frame and the resources it reads and writes
+#[derive(Clone, Debug)]
+pub struct Pass {
+    pub name: String,
+    pub position: Position,
+    pub queue: Queue,
+    pub reads: Vec<String>,
+    pub writes: Vec<String>,
+    /// How long the pass took to record on the CPU, when it is measured
+    pub recording: Option<Duration>,
+}
+
+impl Pass {
+    /// A pass on the graphics queue
+    pub fn new(name: &str, position: Position, reads: &[&str], writes: &[&str]) -> Self {
+        Self {
+            name: String::from(name),
+            position,
+            queue: Queue::Graphics,
+            reads: reads.iter().map(|&read| String::from(read)).collect(),
+            writes: writes.iter().map(|&write| String::from(write)).collect(),
+            recording: None,
+        }
+    }
+
+    pub fn on(self, queue: Queue) -> Self {
+        Self { queue, ..self }
+    }
+}
+
+/// A pass on one queue reading what a pass on another wrote, so that the reading queue's submission has to
+/// wait on a semaphore the writing queue's submission signals
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dependency {
+    pub from: Queue,
+    pub to: Queue,
+    /// Where the reading queue waits, the earliest stage the reading pass could touch the resource in
+    pub stage: vk::PipelineStageFlags,
+}
+
+/// The passes of a frame in the order they run, built by `HelloTriangleApplication::frame_graph` from the
+/// renderer's current configuration. The renderer records its passes directly rather than scheduling them from
+/// a graph, so this describes what is recorded rather than deciding it.
+pub struct Graph {
+    pub passes: Vec<Pass>,
+}
+
+impl Position {
+    /// Where the position comes in the frame
+    fn order(&self) -> u8 {
+        match self {
+            Position::BeforeRenderPass => 0,
+            Position::RenderPass => 1,
+            Position::Stage(hooks::Stage::AfterOpaque) => 2,
+            Position::Stage(hooks::Stage::BeforePost) => 3,
+            Position::Stage(hooks::Stage::PostProcess) => 4,
+            Position::Stage(hooks::Stage::AfterUi) => 5,
+            Position::Present => 6,
+        }
+    }
+
+    /// The earliest stages a pass at the position can read in. Waits cover the stages logically later too, so
+    /// indirect command reading covers the rest of both compute and graphics work.
+    fn first_stage(&self) -> vk::PipelineStageFlags {
+        match self {
+            Position::BeforeRenderPass => {
+                vk::PipelineStageFlags::DRAW_INDIRECT | vk::PipelineStageFlags::TRANSFER
+            }
+            Position::RenderPass => vk::PipelineStageFlags::DRAW_INDIRECT,
+            Position::Stage(_) | Position::Present => vk::PipelineStageFlags::ALL_COMMANDS,
+        }
+    }
+}
+
+impl Graph {
+    /// Puts the passes in the order they run. Passes at the same position keep the order they were given in.
+    pub fn new(mut passes: Vec<Pass>) -> Self {
+        passes.sort_by_key(|pass| pass.position.order());
+        Self { passes }
+    }
+
+    /// Where each queue's work has to wait for another's. A pass reading a resource depends on the last pass
+    /// before it that wrote the resource, when that pass is on another queue. Each pair of queues has one
+    /// dependency, at the earliest stage any of its reads needs.
+    pub fn dependencies(&self) -> Vec<Dependency> {
+        let mut dependencies: Vec<Dependency> = Vec::new();
+        for (index, pass) in self.passes.iter().enumerate() {
+            for read in pass.reads.iter() {
+                let writer = self.passes[..index]
+                    .iter()
+                    .rev()
+                    .find(|earlier| earlier.writes.contains(read));
+                let from = match writer {
+                    Some(writer) if writer.queue != pass.queue => writer.queue,
+                    _ => continue,
+                };
+                let stage = pass.position.first_stage();
+                match dependencies
+                    .iter_mut()
+                    .find(|dependency| dependency.from == from && dependency.to == pass.queue)
+                {
+                    Some(dependency) => dependency.stage |= stage,
+                    None => dependencies.push(Dependency {
+                        from,
+                        to: pass.queue,
+                        stage,
+                    }),
+                }
+            }
+        }
+        dependencies
+    }
+
+    /// The queues the passes are on, in the order of the first pass on each
+    pub fn queues(&self) -> Vec<Queue> {
+        let mut queues = Vec::new();
+        for pass in self.passes.iter() {
+            if !queues.contains(&pass.queue) {
+                queues.push(pass.queue);
+            }
+        }
+        queues
+    }
+
+    /// A Graphviz digraph of the passes as boxes and the resources as ellipses. Each write makes a new version of
+    /// the resource, so the graph stays acyclic when passes read and write the same image.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        // Writing to a string can't fail
+        let _ = writeln!(dot, "digraph frame {{");
+        let _ = writeln!(dot, "    rankdir=LR;");
+        let _ = writeln!(dot, "    node [fontname=\"sans-serif\"];");
+        let mut versions: HashMap<&str, usize> = HashMap::new();
+        let resource_node = |name: &str, version: usize| format!("\"{} #{}\"", name, version);
+        let mut declared = Vec::new();
+        for (index, pass) in self.passes.iter().enumerate() {
+            let label = match pass.recording {
+                Some(time) => format!(
+                    "{}\\n{}\\n{:.3}ms",
+                    pass.name,
+                    position_name(pass.position),
+                    time.as_secs_f64() * 1000.0
+                ),
+                None => format!("{}\\n{}", pass.name, position_name(pass.position)),
+            };
+            let _ = writeln!(dot, "    pass{} [shape=box, label=\"{}\"];", index, label);
+            for read in pass.reads.iter() {
+                let version = *versions.entry(read).or_insert(0);
+                let node = resource_node(read, version);
+                if !declared.contains(&node) {
+                    let _ = writeln!(dot, "    {} [shape=ellipse, label=\"{}\"];", node, read);
+                    declared.push(node.clone());
+                }
+                let _ = writeln!(dot, "    {} -> pass{};", node, index);
+            }
+            for write in pass.writes.iter() {
+                let version = versions.entry(write).or_insert(0);
+                *version += 1;
+                let node = resource_node(write, *version);
+                if !declared.contains(&node) {
+                    let _ = writeln!(dot, "    {} [shape=ellipse, label=\"{}\"];", node, write);
+                    declared.push(node.clone());
+                }
+                let _ = writeln!(dot, "    pass{} -> {};", index, node);
+            }
+        }
+        let _ = writeln!(dot, "}}");
+        dot
+    }
+
+    /// One line per pass with where it runs, what it reads and writes, and how long it took to record
+    pub fn describe(&self) -> String {
+        let mut description = String::new();
+        for pass in self.passes.iter() {
+            let _ = write!(
+                description,
+                "{} ({}, {:?} queue): reads [{}] writes [{}]",
+                pass.name,
+                position_name(pass.position),
+                pass.queue,
+                pass.reads.join(", "),
+                pass.writes.join(", ")
+            );
+            if let Some(time) = pass.recording {
+                let _ = write!(
+                    description,
+                    ", recorded in {:.3}ms",
+                    time.as_secs_f64() * 1000.0
+                );
+            }
+            description.push('\n');
+        }
+        description
+    }
+}
+
+fn position_name(position: Position) -> String {
+    match position {
+        Position::BeforeRenderPass => String::from("before the render pass"),
+        Position::RenderPass => String::from("render pass"),
+        Position::Stage(stage) => format!("{:?}", stage),
+        Position::Present => String::from("present"),
+    }
+}
@@ -0,0 +1,323 @@
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use cgmath::Rad;
+use gilrs::{Axis, Button, EventType, GamepadId, Gilrs};
+use winit::event::VirtualKeyCode;
+
+/// How fast the look stick turns the camera about its target when pushed all the way, in radians a second
+const LOOK_SPEED: f32 = 2.0;
+/// How fast the move stick moves the target when pushed all the way, in pans a second. See
+/// `camera::Camera::move_speed`.
+const PANS_PER_SECOND: f32 = 10.0;
+/// What the move speed is multiplied by with the faster or slower trigger pulled all the way
+const FASTEST: f32 = 4.0;
+const SLOWEST: f32 = 0.25;
+/// Sticks are read as centred until pushed this far, so that they don't drift
+const DEAD_ZONE: f32 = 0.15;
+
+/// Button names in bindings files
+const BUTTONS: [(&str, Button); 17] = [
+    ("south", Button::South),
+    ("east", Button::East),
+    ("north", Button::North),
+    ("west", Button::West),
+    ("left_bumper", Button::LeftTrigger),
+    ("left_trigger", Button::LeftTrigger2),
+    ("right_bumper", Button::RightTrigger),
+    ("right_trigger", Button::RightTrigger2),
+    ("select", Button::Select),
+    ("start", Button::Start),
+    ("mode", Button::Mode),
+    ("left_thumb", Button::LeftThumb),
+    ("right_thumb", Button::RightThumb),
+    ("dpad_up", Button::DPadUp),
+    ("dpad_down", Button::DPadDown),
+    ("dpad_left", Button::DPadLeft),
+    ("dpad_right", Button::DPadRight),
+];
+
+/// Names of the keys buttons can be bound to, which are the ones the renderer and its console respond to
+const KEYS: [(&str, VirtualKeyCode); 46] = [
+    ("grave", VirtualKeyCode::Grave),
+    ("return", VirtualKeyCode::Return),
+    ("escape", VirtualKeyCode::Escape),
+    ("tab", VirtualKeyCode::Tab),
+    ("back", VirtualKeyCode::Back),
+    ("up", VirtualKeyCode::Up),
+    ("down", VirtualKeyCode::Down),
+    ("left", VirtualKeyCode::Left),
+    ("right", VirtualKeyCode::Right),
+    ("page_up", VirtualKeyCode::PageUp),
+    ("page_down", VirtualKeyCode::PageDown),
+    ("a", VirtualKeyCode::A),
+    ("b", VirtualKeyCode::B),
+    ("c", VirtualKeyCode::C),
+    ("d", VirtualKeyCode::D),
+    ("e", VirtualKeyCode::E),
+    ("f", VirtualKeyCode::F),
+    ("g", VirtualKeyCode::G),
+    ("h", VirtualKeyCode::H),
+    ("i", VirtualKeyCode::I),
+    ("j", VirtualKeyCode::J),
+    ("k", VirtualKeyCode::K),
+    ("l", VirtualKeyCode::L),
+    ("m", VirtualKeyCode::M),
+    ("n", VirtualKeyCode::N),
+    ("o", VirtualKeyCode::O),
+    ("p", VirtualKeyCode::P),
+    ("q", VirtualKeyCode::Q),
+    ("r", VirtualKeyCode::R),
+    ("s", VirtualKeyCode::S),
+    ("t", VirtualKeyCode::T),
+    ("u", VirtualKeyCode::U),
+    ("v", VirtualKeyCode::V),
+    ("w", VirtualKeyCode::W),
+    ("x", VirtualKeyCode::X),
+    ("y", VirtualKeyCode::Y),
+    ("z", VirtualKeyCode::Z),
+    ("1", VirtualKeyCode::Key1),
+    ("2", VirtualKeyCode::Key2),
+    ("3", VirtualKeyCode::Key3),
+    ("4", VirtualKeyCode::Key4),
+    ("5", VirtualKeyCode::Key5),
+    ("6", VirtualKeyCode::Key6),
+    ("7", VirtualKeyCode::Key7),
+    ("8", VirtualKeyCode::Key8),
+    ("9", VirtualKeyCode::Key9),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stick {
+    Left,
+    Right,
+}
+
+impl Stick {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "left" => Some(Stick::Left),
+            "right" => Some(Stick::Right),
+            _ => None,
+        }
+    }
+
+    fn axes(&self) -> (Axis, Axis) {
+        match self {
+            Stick::Left => (Axis::LeftStickX, Axis::LeftStickY),
+            Stick::Right => (Axis::RightStickX, Axis::RightStickY),
+        }
+    }
+}
+
+/// What the gamepad's sticks, triggers and buttons do. Buttons are bound to keys, which they press as the key
+/// would be pressed on the keyboard, so that they reach the console and every hotkey without bindings of their
+/// own.
+///
+/// Bindings files are text, one binding per line, each changing one of the defaults:
+///
+/// ```text
+/// look <left|right>
+/// move <left|right>
+/// faster <button>
+/// slower <button>
+/// button <button> <key|none>
+/// ```
+///
+/// Buttons are named `south`, `east`, `north` and `west` for the face buttons, `left_bumper`, `left_trigger`,
+/// `right_bumper`, `right_trigger`, `select`, `start`, `mode`, `left_thumb`, `right_thumb` and `dpad_up`,
+/// `dpad_down`, `dpad_left` and `dpad_right`. Keys are named by their letter or digit, or `grave`, `return`,
+/// `escape`, `tab`, `back`, `up`, `down`, `left`, `right`, `page_up` and `page_down`. Lines starting with `#` are
+/// ignored.
+#[derive(Clone, Debug)]
+pub struct Bindings {
+    /// Turns the camera about its target, pushing up raising it
+    pub look: Stick,
+    /// Moves the camera's target along the ground
+    pub movement: Stick,
+    /// Speed the movement up or slow it down by how far they are pulled
+    pub faster: Button,
+    pub slower: Button,
+    /// At most one key for each button
+    pub keys: Vec<(Button, VirtualKeyCode)>,
+}
+
+impl Default for Bindings {
+    /// The right stick looks and the left moves. Start opens the console, the D-pad browses its history, south
+    /// runs the line, west completes it and east closes it. North changes the view and select the projection.
+    fn default() -> Self {
+        Self {
+            look: Stick::Right,
+            movement: Stick::Left,
+            faster: Button::RightTrigger2,
+            slower: Button::LeftTrigger2,
+            keys: vec![
+                (Button::Start, VirtualKeyCode::Grave),
+                (Button::DPadUp, VirtualKeyCode::Up),
+                (Button::DPadDown, VirtualKeyCode::Down),
+                (Button::South, VirtualKeyCode::Return),
+                (Button::West, VirtualKeyCode::Tab),
+                (Button::East, VirtualKeyCode::Escape),
+                (Button::North, VirtualKeyCode::C),
+                (Button::Select, VirtualKeyCode::O),
+            ],
+        }
+    }
+}
+
+impl Bindings {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut bindings = Self::default();
+        let lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+        for (number, line) in lines {
+            let mut fields = line.split_whitespace();
+            let binding = fields.next().unwrap_or_default();
+            let mut field = |name: &str| {
+                fields
+                    .next()
+                    .ok_or_else(|| format!("Line {} is missing the {}", number, name))
+            };
+            let stick = |name: &str| {
+                Stick::parse(name).ok_or_else(|| format!("Line {}: unknown stick {}", number, name))
+            };
+            let button = |name: &str| {
+                BUTTONS
+                    .iter()
+                    .find(|(button_name, _)| *button_name == name)
+                    .map(|&(_, button)| button)
+                    .ok_or_else(|| format!("Line {}: unknown button {}", number, name))
+            };
+            match binding {
+                "look" => bindings.look = stick(field("stick")?)?,
+                "move" => bindings.movement = stick(field("stick")?)?,
+                "faster" => bindings.faster = button(field("button")?)?,
+                "slower" => bindings.slower = button(field("button")?)?,
+                "button" => {
+                    let button = button(field("button")?)?;
+                    let key = match field("key")? {
+                        "none" => None,
+                        name => Some(
+                            KEYS.iter()
+                                .find(|(key_name, _)| *key_name == name)
+                                .map(|&(_, key)| key)
+                                .ok_or_else(|| format!("Line {}: unknown key {}", number, name))?,
+                        ),
+                    };
+                    bindings.keys.retain(|&(bound, _)| bound != button);
+                    bindings.keys.extend(key.map(|key| (button, key)));
+                }
+                other => return Err(format!("Line {}: unknown binding {}", number, other)),
+            }
+        }
+        Ok(bindings)
+    }
+}
+
+/// What the gamepad did since it was last updated
+pub struct Motion {
+    pub yaw: Rad<f32>,
+    pub pitch: Rad<f32>,
+    /// How far to move the target along the ground, in pans
+    pub right: f32,
+    pub forward: f32,
+    /// Keys bound to the buttons pressed, in the order they were pressed
+    pub keys: Vec<VirtualKeyCode>,
+}
+
+impl Motion {
+    pub fn moved(&self) -> bool {
+        self.yaw != Rad(0.0) || self.pitch != Rad(0.0) || self.right != 0.0 || self.forward != 0.0
+    }
+}
+
+/// Reads the gamepads with gilrs. The sticks and triggers are read from whichever gamepad was used last, and
+/// buttons pressed on any of them.
+pub struct Gamepad {
+    gilrs: Gilrs,
+    pub bindings: Bindings,
+    active: Option<GamepadId>,
+    updated: Instant,
+}
+
+impl Gamepad {
+    pub fn new() -> Result<Self, String> {
+        let gilrs = Gilrs::new().map_err(|e| e.to_string())?;
+        let active = gilrs.gamepads().next().map(|(id, gamepad)| {
+            println!("Gamepad: {}", gamepad.name());
+            id
+        });
+        Ok(Self {
+            gilrs,
+            bindings: Bindings::default(),
+            active,
+            updated: Instant::now(),
+        })
+    }
+
+    /// Reads the gamepads' events since the last update, and how far the sticks have moved the camera over the
+    /// time since
+    pub fn update(&mut self) -> Motion {
+        let now = Instant::now();
+        let delta = (now - self.updated).as_secs_f32();
+        self.updated = now;
+
+        let mut motion = Motion {
+            yaw: Rad(0.0),
+            pitch: Rad(0.0),
+            right: 0.0,
+            forward: 0.0,
+            keys: Vec::new(),
+        };
+        while let Some(event) = self.gilrs.next_event() {
+            self.active = Some(event.id);
+            match event.event {
+                EventType::ButtonPressed(button, _) => motion.keys.extend(
+                    self.bindings
+                        .keys
+                        .iter()
+                        .filter(|&&(bound, _)| bound == button)
+                        .map(|&(_, key)| key),
+                ),
+                EventType::Connected => {
+                    println!("Gamepad: {}", self.gilrs.gamepad(event.id).name())
+                }
+                EventType::Disconnected => self.active = None,
+                _ => {}
+            }
+        }
+
+        let gamepad = match self.active {
+            Some(id) => self.gilrs.gamepad(id),
+            None => return motion,
+        };
+        let stick = |stick: Stick| {
+            let (x, y) = stick.axes();
+            let (x, y) = (gamepad.value(x), gamepad.value(y));
+            match (x * x + y * y).sqrt() < DEAD_ZONE {
+                true => (0.0, 0.0),
+                false => (x, y),
+            }
+        };
+        let pulled = |button: Button| gamepad.button_data(button).map_or(0.0, |data| data.value());
+
+        let (look_x, look_y) = stick(self.bindings.look);
+        motion.yaw = Rad(look_x * LOOK_SPEED * delta);
+        motion.pitch = Rad(look_y * LOOK_SPEED * delta);
+        let speed = (1.0 + (FASTEST - 1.0) * pulled(self.bindings.faster))
+            * (1.0 - (1.0 - SLOWEST) * pulled(self.bindings.slower));
+        let (move_x, move_y) = stick(self.bindings.movement);
+        motion.right = move_x * PANS_PER_SECOND * speed * delta;
+        motion.forward = move_y * PANS_PER_SECOND * speed * delta;
+        motion
+    }
+}
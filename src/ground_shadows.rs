@@ -0,0 +1,125 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Vector3, Vector4, Zero};
+
+use crate::{bounds, units};
+
+/// Triangles around the blob disc
+pub const DISC_SEGMENTS: usize = 24;
+/// Light this close to the horizon, as the sine of its elevation, casts no planar shadows, which would stretch
+/// out to infinity as it sets
+const MIN_ELEVATION: f32 = 0.05;
+
+/// How draws that cast ground shadows are shadowed, see `GroundShadows`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Off,
+    /// A soft disc on the ground under each caster, whichever way the light comes from
+    Blob,
+    /// Each caster flattened onto the ground along the light
+    Planar,
+}
+
+impl Mode {
+    pub const ALL: [Self; 3] = [Self::Off, Self::Blob, Self::Planar];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|mode| mode.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Off => "off",
+            Mode::Blob => "blob",
+            Mode::Planar => "planar",
+        }
+    }
+}
+
+/// Shadows cast onto a flat ground plane by the draws that cast them, see `Draw::casts_ground_shadow`, for
+/// devices too weak for anything better. Planar shadows draw each caster again flattened onto the ground along
+/// the light and blended over it, with the stencil clamped so that the ground is only darkened once where the
+/// flattened triangles overlap. That needs a depth format with stencil, and overlapping shadows darken more
+/// without one. Blob shadows draw a soft disc under each caster's bounds instead, which works for casters of any
+/// program. Nothing but the ground is shadowed, and shadows are drawn over transparent draws.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GroundShadows {
+    pub mode: Mode,
+    /// Where the ground is along the world's up axis
+    pub height: f32,
+    /// How much light a shadow blocks, from 0 to 1
+    pub opacity: f32,
+}
+
+impl Default for GroundShadows {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Planar,
+            height: 0.0,
+            opacity: 0.6,
+        }
+    }
+}
+
+impl GroundShadows {
+    pub fn uniforms(&self) -> Uniforms {
+        Uniforms {
+            shadow: [self.opacity.clamp(0.0, 1.0), 0.0, 0.0, 0.0],
+        }
+    }
+
+    /// The world space transform that flattens points onto the ground along `towards_light`, or `None` if the
+    /// light is too low to cast planar shadows
+    pub fn projection(&self, up: units::Up, towards_light: Vector3<f32>) -> Option<Matrix4<f32>> {
+        let up = up.vector();
+        let towards_light = towards_light.normalize();
+        let elevation = up.dot(towards_light);
+        if elevation < MIN_ELEVATION {
+            return None;
+        }
+        // Points move along the light by how far above the ground they are
+        let along = towards_light / elevation;
+        let column = |axis: usize| {
+            let mut column = Vector4::zero();
+            column[axis] = 1.0;
+            column - (along * up[axis]).extend(0.0)
+        };
+        Some(Matrix4::from_cols(
+            column(0),
+            column(1),
+            column(2),
+            (along * self.height).extend(1.0),
+        ))
+    }
+
+    /// The world space transform placing the disc on the ground under the bounds, wide enough to cover them seen
+    /// from above
+    pub fn blob(&self, up: units::Up, aabb: &bounds::Aabb) -> Matrix4<f32> {
+        let axis = up.vector();
+        let centre = aabb.center().to_vec();
+        let half = (aabb.max - aabb.min) / 2.0;
+        let radius = (half.magnitude2() - half.dot(axis).powi(2)).max(0.0).sqrt();
+        let ground = centre - axis * (axis.dot(centre) - self.height);
+        Matrix4::from_translation(ground)
+            * Matrix4::from(up.rotation())
+            * Matrix4::from_scale(radius)
+    }
+}
+
+/// The triangles of a unit disc across Z for blob shadows, as x, y and how much of the shadow covers each
+/// vertex, which is all of it at the centre fading to none at the edge
+pub fn disc() -> Vec<[f32; 3]> {
+    let rim = |segment: usize| {
+        let angle = segment as f32 / DISC_SEGMENTS as f32 * std::f32::consts::TAU;
+        [angle.cos(), angle.sin(), 0.0]
+    };
+    (0..DISC_SEGMENTS)
+        .flat_map(|segment| [[0.0, 0.0, 1.0], rim(segment), rim(segment + 1)])
+        .collect()
+}
+
+/// Matches the `groundShadow` member of `UniformBufferObject` in `ground_shadow_frag.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Uniforms {
+    /// x is the opacity
+    shadow: [f32; 4],
+}
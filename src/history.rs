@@ -0,0 +1,185 @@
+use cgmath::Matrix4;
+
+use crate::{camera, clipping, pipeline};
+
+/// How many edits can be undone before the oldest are forgotten
+const LIMIT: usize = 256;
+
+/// A change to the scene that can be undone, holding the state on both sides of it. The renderer applies an edit
+/// by setting everything to the `after` state.
+#[derive(Clone, Debug)]
+pub enum Edit {
+    /// Moves the draw at an index in the renderer's draws
+    Transform {
+        draw: usize,
+        before: Matrix4<f32>,
+        after: Matrix4<f32>,
+    },
+    /// Changes the pipeline state a draw's material maps to, see `material::Material::pipeline_config`
+    Material {
+        draw: usize,
+        before: pipeline::Config,
+        after: pipeline::Config,
+    },
+    Camera {
+        before: camera::Camera,
+        after: camera::Camera,
+    },
+    Clipping {
+        before: clipping::Clipping,
+        after: clipping::Clipping,
+    },
+    /// Several edits applied in order, undone together
+    Batch(Vec<Edit>),
+}
+
+impl Edit {
+    /// The edit that takes the scene back from after this one to before it
+    pub fn inverse(&self) -> Self {
+        match self {
+            Edit::Transform {
+                draw,
+                before,
+                after,
+            } => Edit::Transform {
+                draw: *draw,
+                before: *after,
+                after: *before,
+            },
+            Edit::Material {
+                draw,
+                before,
+                after,
+            } => Edit::Material {
+                draw: *draw,
+                before: *after,
+                after: *before,
+            },
+            Edit::Camera { before, after } => Edit::Camera {
+                before: *after,
+                after: *before,
+            },
+            Edit::Clipping { before, after } => Edit::Clipping {
+                before: after.clone(),
+                after: before.clone(),
+            },
+            Edit::Batch(edits) => Edit::Batch(edits.iter().rev().map(Edit::inverse).collect()),
+        }
+    }
+}
+
+/// The edits made to the scene, most recent last, and those undone since, most recently undone last
+#[derive(Default)]
+pub struct History {
+    done: Vec<Edit>,
+    undone: Vec<Edit>,
+}
+
+impl History {
+    /// Remembers an edit that has just been applied. Edits that were undone can no longer be redone.
+    pub fn record(&mut self, edit: Edit) {
+        self.undone.clear();
+        self.done.push(edit);
+        if self.done.len() > LIMIT {
+            self.done.remove(0);
+        }
+    }
+
+    /// The edit to apply to undo the most recent edit, if there is one
+    pub fn undo(&mut self) -> Option<Edit> {
+        let edit = self.done.pop()?;
+        let inverse = edit.inverse();
+        self.undone.push(edit);
+        Some(inverse)
+    }
+
+    /// The edit to apply to redo the most recently undone edit, if there is one
+    pub fn redo(&mut self) -> Option<Edit> {
+        let edit = self.undone.pop()?;
+        self.done.push(edit.clone());
+        Some(edit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Scales draw 0 from one size to another, so edits can be told apart by their sizes
+    fn scale(before: f32, after: f32) -> Edit {
+        Edit::Transform {
+            draw: 0,
+            before: Matrix4::from_scale(before),
+            after: Matrix4::from_scale(after),
+        }
+    }
+
+    /// The sizes an edit scales from and to
+    fn sizes(edit: &Edit) -> (f32, f32) {
+        match edit {
+            Edit::Transform { before, after, .. } => (before.x.x, after.x.x),
+            other => panic!("Expected a transform, not {:?}", other),
+        }
+    }
+
+    #[test]
+    fn undoes_and_redoes_in_order() {
+        let mut history = History::default();
+        history.record(scale(1.0, 2.0));
+        history.record(scale(2.0, 3.0));
+        history.record(scale(3.0, 4.0));
+
+        assert_eq!(history.undo().as_ref().map(sizes), Some((4.0, 3.0)));
+        assert_eq!(history.undo().as_ref().map(sizes), Some((3.0, 2.0)));
+        assert_eq!(history.redo().as_ref().map(sizes), Some((2.0, 3.0)));
+        assert_eq!(history.undo().as_ref().map(sizes), Some((3.0, 2.0)));
+        assert_eq!(history.undo().as_ref().map(sizes), Some((2.0, 1.0)));
+        assert!(history.undo().is_none());
+        assert_eq!(history.redo().as_ref().map(sizes), Some((1.0, 2.0)));
+        assert_eq!(history.redo().as_ref().map(sizes), Some((2.0, 3.0)));
+        assert_eq!(history.redo().as_ref().map(sizes), Some((3.0, 4.0)));
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn recording_forgets_what_was_undone() {
+        let mut history = History::default();
+        history.record(scale(1.0, 2.0));
+        history.record(scale(2.0, 3.0));
+        history.undo();
+        history.record(scale(2.0, 5.0));
+
+        assert!(history.redo().is_none());
+        assert_eq!(history.undo().as_ref().map(sizes), Some((5.0, 2.0)));
+        assert_eq!(history.undo().as_ref().map(sizes), Some((2.0, 1.0)));
+    }
+
+    #[test]
+    fn forgets_the_oldest_edits_past_the_limit() {
+        let mut history = History::default();
+        for size in 0..LIMIT + 10 {
+            history.record(scale(size as f32, size as f32 + 1.0));
+        }
+        let mut undone = 0;
+        let mut last = None;
+        while let Some(edit) = history.undo() {
+            undone += 1;
+            last = Some(sizes(&edit));
+        }
+        assert_eq!(undone, LIMIT);
+        assert_eq!(last, Some((11.0, 10.0)));
+    }
+
+    #[test]
+    fn batches_undo_in_reverse() {
+        let mut history = History::default();
+        history.record(Edit::Batch(vec![scale(1.0, 2.0), scale(2.0, 3.0)]));
+        match history.undo() {
+            Some(Edit::Batch(edits)) => {
+                let undone: Vec<(f32, f32)> = edits.iter().map(sizes).collect();
+                assert_eq!(undone, vec![(3.0, 2.0), (2.0, 1.0)]);
+            }
+            other => panic!("Expected a batch, not {:?}", other),
+        }
+    }
+}
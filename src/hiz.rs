@@ -0,0 +1,463 @@
+use std::path::Path;
+
+use ash::vk;
+
+use crate::{
+    begin_single_time_commands, end_single_time_commands, postprocess, util,
+    HelloTriangleApplication,
+};
+
+/// Texels built per workgroup in each direction, matching the local size in `hiz_comp.glsl`
+const WORKGROUP_SIZE: u32 = 8;
+const FORMAT: vk::Format = vk::Format::R32_SFLOAT;
+
+/// The depth attachment a pyramid is built from. Its image must have been created with sampled usage.
+#[derive(Clone, Copy, Debug)]
+pub struct Depth {
+    pub image: vk::Image,
+    /// A view of the depth aspect alone
+    pub view: vk::ImageView,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+}
+
+/// A hierarchical depth buffer: a mip chain where each texel holds the farthest depth of the texels it covers in
+/// the depth attachment, so a few reads tell whether a box's nearest point is behind everything drawn over it.
+/// Level zero is the largest power of two size that fits in the attachment, so every level after it halves the
+/// last exactly.
+///
+/// The pyramid is built from the depth the previous frame left, since the current frame's depth isn't known
+/// until it has been drawn. An object coming out from behind an occluder can be missing for a frame.
+pub struct Pyramid {
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    /// Every level, for culling
+    view: vk::ImageView,
+    /// Each level on its own, for building the next from
+    level_views: Vec<vk::ImageView>,
+    sampler: vk::Sampler,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    /// One per level, reading the level before it or the depth attachment and writing the level
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+    /// Level zero's width and height
+    extent: (u32, u32),
+    /// `None` when the pyramid is only a placeholder that is never built
+    depth: Option<Depth>,
+}
+
+impl Pyramid {
+    /// A pyramid for the depth attachment, or a single texel placeholder that is never built when `depth` is
+    /// `None`. The pyramid stays in the `GENERAL` layout.
+    pub fn new(
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        depth: Option<Depth>,
+    ) -> Self {
+        let floor_power_of_two = |size: u32| -> u32 { 1 << (31 - size.max(1).leading_zeros()) };
+        let (width, height) = depth.map_or((1, 1), |depth| {
+            (
+                floor_power_of_two(depth.extent.width),
+                floor_power_of_two(depth.extent.height),
+            )
+        });
+        let levels = 32 - width.max(height).leading_zeros();
+
+        let image = unsafe {
+            device
+                .create_image(
+                    &vk::ImageCreateInfo::builder()
+                        .image_type(vk::ImageType::TYPE_2D)
+                        .extent(vk::Extent3D {
+                            width,
+                            height,
+                            depth: 1,
+                        })
+                        .mip_levels(levels)
+                        .array_layers(1)
+                        .format(FORMAT)
+                        .tiling(vk::ImageTiling::OPTIMAL)
+                        .initial_layout(vk::ImageLayout::UNDEFINED)
+                        .usage(vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED)
+                        .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                        .samples(vk::SampleCountFlags::TYPE_1),
+                    None,
+                )
+                .expect("Depth pyramid image")
+        };
+        let requirements = unsafe { device.get_image_memory_requirements(image) };
+        let memory = unsafe {
+            let memory = device
+                .allocate_memory(
+                    &vk::MemoryAllocateInfo::builder()
+                        .allocation_size(requirements.size)
+                        .memory_type_index(HelloTriangleApplication::find_memory_type(
+                            requirements.memory_type_bits,
+                            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                            device_memory_properties,
+                        )),
+                    None,
+                )
+                .expect("Depth pyramid memory");
+            device
+                .bind_image_memory(image, memory, 0)
+                .expect("Binding depth pyramid memory");
+            memory
+        };
+
+        let create_view = |base_mip_level: u32, level_count: u32| unsafe {
+            device
+                .create_image_view(
+                    &vk::ImageViewCreateInfo::builder()
+                        .image(image)
+                        .view_type(vk::ImageViewType::TYPE_2D)
+                        .format(FORMAT)
+                        .subresource_range(vk::ImageSubresourceRange {
+                            aspect_mask: vk::ImageAspectFlags::COLOR,
+                            base_mip_level,
+                            level_count,
+                            base_array_layer: 0,
+                            layer_count: 1,
+                        }),
+                    None,
+                )
+                .expect("Depth pyramid view")
+        };
+        let view = create_view(0, levels);
+        let level_views: Vec<vk::ImageView> =
+            (0..levels).map(|level| create_view(level, 1)).collect();
+        // Levels are only read with texelFetch, which ignores filtering
+        let sampler = unsafe {
+            device
+                .create_sampler(
+                    &vk::SamplerCreateInfo::builder()
+                        .mag_filter(vk::Filter::NEAREST)
+                        .min_filter(vk::Filter::NEAREST)
+                        .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+                        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                        .max_lod(levels as f32),
+                    None,
+                )
+                .expect("Depth pyramid sampler")
+        };
+
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_count(1)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build(),
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(1)
+                .descriptor_count(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build(),
+        ];
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+                    None,
+                )
+                .expect("Depth pyramid descriptor set layout")
+        };
+        let pool_sizes = [
+            vk::DescriptorPoolSize::builder()
+                .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(levels)
+                .build(),
+            vk::DescriptorPoolSize::builder()
+                .ty(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(levels)
+                .build(),
+        ];
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::builder()
+                        .pool_sizes(&pool_sizes)
+                        .max_sets(levels),
+                    None,
+                )
+                .expect("Depth pyramid descriptor pool")
+        };
+        let descriptor_sets = match depth {
+            Some(depth) => {
+                let set_layouts = vec![descriptor_set_layout; levels as usize];
+                let descriptor_sets = unsafe {
+                    device
+                        .allocate_descriptor_sets(
+                            &vk::DescriptorSetAllocateInfo::builder()
+                                .descriptor_pool(descriptor_pool)
+                                .set_layouts(&set_layouts),
+                        )
+                        .expect("Depth pyramid descriptor sets")
+                };
+                for (level, &descriptor_set) in descriptor_sets.iter().enumerate() {
+                    let source = match level {
+                        0 => (depth.view, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+                        _ => (level_views[level - 1], vk::ImageLayout::GENERAL),
+                    };
+                    let source_info = [vk::DescriptorImageInfo::builder()
+                        .sampler(sampler)
+                        .image_view(source.0)
+                        .image_layout(source.1)
+                        .build()];
+                    let destination_info = [vk::DescriptorImageInfo::builder()
+                        .image_view(level_views[level])
+                        .image_layout(vk::ImageLayout::GENERAL)
+                        .build()];
+                    let writes = [
+                        vk::WriteDescriptorSet::builder()
+                            .dst_set(descriptor_set)
+                            .dst_binding(0)
+                            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                            .image_info(&source_info)
+                            .build(),
+                        vk::WriteDescriptorSet::builder()
+                            .dst_set(descriptor_set)
+                            .dst_binding(1)
+                            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                            .image_info(&destination_info)
+                            .build(),
+                    ];
+                    unsafe { device.update_descriptor_sets(&writes, &[]) };
+                }
+                descriptor_sets
+            }
+            None => Vec::new(),
+        };
+
+        let set_layouts = [descriptor_set_layout];
+        let layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::builder().set_layouts(&set_layouts),
+                    None,
+                )
+                .expect("Depth pyramid pipeline layout")
+        };
+        let shader_path = Path::new(env!("OUT_DIR")).join("hiz_comp.spv");
+        let pipeline = postprocess::create_compute_pipeline(
+            device,
+            layout,
+            &util::read_shader_code(&shader_path),
+        );
+
+        let command_buffer = begin_single_time_commands(device, command_pool);
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[level_barrier(
+                    image,
+                    0,
+                    levels,
+                    vk::ImageLayout::UNDEFINED,
+                    vk::AccessFlags::empty(),
+                )],
+            );
+        }
+        end_single_time_commands(device, command_pool, command_buffer, queue);
+
+        Self {
+            image,
+            memory,
+            view,
+            level_views,
+            sampler,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_sets,
+            layout,
+            pipeline,
+            extent: (width, height),
+            depth,
+        }
+    }
+
+    /// Whether the pyramid is built from a depth attachment, rather than being a placeholder
+    pub fn can_build(&self) -> bool {
+        self.depth.is_some()
+    }
+
+    /// The image info for sampling every level of the pyramid
+    pub fn descriptor_info(&self) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo::builder()
+            .sampler(self.sampler)
+            .image_view(self.view)
+            .image_layout(vk::ImageLayout::GENERAL)
+            .build()
+    }
+
+    /// Records building every level from the depth the previous frame drew, outside of a render pass. The
+    /// pyramid can be read by compute shaders afterwards and the depth attachment is left as it was found, in the
+    /// `DEPTH_STENCIL_ATTACHMENT_OPTIMAL` layout.
+    pub fn record_build(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        let depth = match self.depth {
+            Some(depth) => depth,
+            None => return,
+        };
+        let aspect_mask = if HelloTriangleApplication::has_stencil_component(depth.format) {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        } else {
+            vk::ImageAspectFlags::DEPTH
+        };
+        let depth_barrier = |old_layout, new_layout, src_access_mask, dst_access_mask| {
+            vk::ImageMemoryBarrier::builder()
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .src_access_mask(src_access_mask)
+                .dst_access_mask(dst_access_mask)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(depth.image)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                })
+                .build()
+        };
+
+        unsafe {
+            // The previous frame's culling may still be reading the pyramid
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[depth_barrier(
+                    vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                    vk::AccessFlags::SHADER_READ,
+                )],
+            );
+
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline,
+            );
+            for (level, &descriptor_set) in self.descriptor_sets.iter().enumerate() {
+                let level = level as u32;
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    self.layout,
+                    0,
+                    &[descriptor_set],
+                    &[],
+                );
+                let (width, height) = self.level_extent(level);
+                device.cmd_dispatch(
+                    command_buffer,
+                    width.div_ceil(WORKGROUP_SIZE),
+                    height.div_ceil(WORKGROUP_SIZE),
+                    1,
+                );
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[level_barrier(
+                        self.image,
+                        level,
+                        1,
+                        vk::ImageLayout::GENERAL,
+                        vk::AccessFlags::SHADER_WRITE,
+                    )],
+                );
+            }
+
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[depth_barrier(
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                    vk::AccessFlags::SHADER_READ,
+                    vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                )],
+            );
+        }
+    }
+
+    /// The size of a level in texels, which stops halving along an axis once it is one texel across
+    fn level_extent(&self, level: u32) -> (u32, u32) {
+        (
+            (self.extent.0 >> level).max(1),
+            (self.extent.1 >> level).max(1),
+        )
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            device.destroy_sampler(self.sampler, None);
+            for &view in self.level_views.iter() {
+                device.destroy_image_view(view, None);
+            }
+            device.destroy_image_view(self.view, None);
+            device.destroy_image(self.image, None);
+            device.free_memory(self.memory, None);
+        }
+    }
+}
+
+/// Makes shader writes to the levels visible to later shader reads, moving them to the `GENERAL` layout
+fn level_barrier(
+    image: vk::Image,
+    base_mip_level: u32,
+    level_count: u32,
+    old_layout: vk::ImageLayout,
+    src_access_mask: vk::AccessFlags,
+) -> vk::ImageMemoryBarrier {
+    vk::ImageMemoryBarrier::builder()
+        .old_layout(old_layout)
+        .new_layout(vk::ImageLayout::GENERAL)
+        .src_access_mask(src_access_mask)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level,
+            level_count,
+            base_array_layer: 0,
+            layer_count: 1,
+        })
+        .build()
+}
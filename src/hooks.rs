@@ -0,0 +1,87 @@
+use ash::vk;
+use cgmath::Matrix4;
+
+use crate::{framegraph, pipeline, sky};
+
+/// Points in a frame where the host application can record its own commands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// Inside the render pass after every draw, with the viewport set and the scene's descriptor set bound
+    /// with the shared pipeline layout. Commands can draw into the colour and depth attachments. The command
+    /// buffer is a secondary command buffer continuing the render pass.
+    AfterOpaque,
+    /// After the render pass ends and before post processing. The swapchain image holds the rendered scene in
+    /// the `PRESENT_SRC_KHR` layout.
+    BeforePost,
+    /// Where post processing filters the frame, with the swapchain image in the `PRESENT_SRC_KHR` layout.
+    PostProcess,
+    /// After post processing and the colour scopes, last before the frame is submitted. The swapchain image
+    /// holds the final frame in the `PRESENT_SRC_KHR` layout.
+    AfterUi,
+}
+
+/// Everything a hook needs to record into the frame.
+// Fields are for host applications' hooks, the renderer itself doesn't read all of them
+#[allow(dead_code)]
+pub struct FrameContext<'a> {
+    pub stage: Stage,
+    pub device: &'a ash::Device,
+    pub command_buffer: vk::CommandBuffer,
+    pub image_index: usize,
+    pub swapchain_image: vk::Image,
+    pub swapchain_format: vk::Format,
+    /// The depth attachment, in the `DEPTH_STENCIL_ATTACHMENT_OPTIMAL` layout
+    pub depth_image: vk::Image,
+    /// The render pass and extent, for creating pipelines compatible with `Stage::AfterOpaque`
+    pub target: pipeline::Target,
+    /// The descriptor set holding the frame's uniform buffer and texture
+    pub descriptor_set: vk::DescriptorSet,
+    pub pipeline_layout: vk::PipelineLayout,
+    /// What the device can rasterize, such as whether pipelines can test depth bounds
+    pub limits: pipeline::RasterLimits,
+    pub view: Matrix4<f32>,
+    pub projection: Matrix4<f32>,
+    /// Seconds since the renderer started
+    pub time: f32,
+    /// What the scene is lit by
+    pub light: sky::Light,
+}
+
+/// Records commands into the frame. Hooks must leave the frame as they found it: inside the render pass for
+/// `Stage::AfterOpaque`, and with the swapchain image in `PRESENT_SRC_KHR` for the other stages.
+pub type Hook = Box<dyn FnMut(&FrameContext)>;
+
+/// Callbacks registered by the host application, run in the order they were added at their stage.
+#[derive(Default)]
+pub struct Hooks {
+    hooks: Vec<(Stage, String, Hook)>,
+}
+
+impl Hooks {
+    pub fn add(&mut self, stage: Stage, name: &str, hook: Hook) {
+        self.hooks.push((stage, String::from(name), hook));
+    }
+
+    /// A pass for each hook. Hooks can record anything, so they are assumed to draw into the swapchain image.
+    pub fn passes(&self) -> Vec<framegraph::Pass> {
+        self.hooks
+            .iter()
+            .map(|(stage, name, _)| {
+                framegraph::Pass::new(
+                    name,
+                    framegraph::Position::Stage(*stage),
+                    &[framegraph::SWAPCHAIN],
+                    &[framegraph::SWAPCHAIN],
+                )
+            })
+            .collect()
+    }
+
+    pub fn run(&mut self, context: &FrameContext) {
+        for (stage, _, hook) in self.hooks.iter_mut() {
+            if *stage == context.stage {
+                hook(context);
+            }
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::alloc::{self, Layout};
+use std::ffi::c_void;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ash::vk;
+
+/// The alignment given to the header in front of every allocation. Allocations are aligned to at least this.
+const HEADER_ALIGNMENT: usize = 16;
+
+/// The Vulkan implementation's host memory, counted as it is allocated through `callbacks`
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+/// Memory the implementation allocates itself and only tells the callbacks about, such as for executable code
+static INTERNAL: AtomicUsize = AtomicUsize::new(0);
+
+/// What is written in front of each allocation, so that it can be freed and reallocated with the layout it was
+/// allocated with
+#[derive(Clone, Copy)]
+struct Header {
+    size: usize,
+    alignment: usize,
+}
+
+/// How much host memory the Vulkan implementation is using through the renderer's allocation callbacks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub allocated: usize,
+    /// The most that has been allocated at once
+    pub peak: usize,
+    pub allocations: usize,
+    pub internal: usize,
+}
+
+pub fn usage() -> Usage {
+    Usage {
+        allocated: ALLOCATED.load(Ordering::Relaxed),
+        peak: PEAK.load(Ordering::Relaxed),
+        allocations: ALLOCATIONS.load(Ordering::Relaxed),
+        internal: INTERNAL.load(Ordering::Relaxed),
+    }
+}
+
+/// Allocation callbacks that route the Vulkan implementation's host allocations through Rust's global allocator
+/// and count them in `usage`. Every call returns callbacks compatible with the others', so an object can be
+/// destroyed with callbacks from another call than the ones it was created with.
+pub fn callbacks() -> vk::AllocationCallbacks {
+    vk::AllocationCallbacks::builder()
+        .pfn_allocation(Some(allocation))
+        .pfn_reallocation(Some(reallocation))
+        .pfn_free(Some(free))
+        .pfn_internal_allocation(Some(internal_allocation))
+        .pfn_internal_free(Some(internal_free))
+        .build()
+}
+
+/// The layout of a block holding `size` bytes aligned to `alignment` after the header, and how far into the block
+/// they start
+fn layout(size: usize, alignment: usize) -> Option<(Layout, usize)> {
+    let alignment = alignment.max(HEADER_ALIGNMENT);
+    // The header goes in the bytes just before the allocation, so the offset has room for it in any alignment
+    let offset = alignment.max(std::mem::size_of::<Header>());
+    let layout = Layout::from_size_align(size.checked_add(offset)?, alignment).ok()?;
+    Some((layout, offset))
+}
+
+unsafe fn header(memory: *mut c_void) -> *mut Header {
+    (memory as *mut u8).sub(std::mem::size_of::<Header>()) as *mut Header
+}
+
+unsafe extern "system" fn allocation(
+    _: *mut c_void,
+    size: usize,
+    alignment: usize,
+    _: vk::SystemAllocationScope,
+) -> *mut c_void {
+    let (layout, offset) = match layout(size, alignment) {
+        Some(layout) if size > 0 => layout,
+        _ => return ptr::null_mut(),
+    };
+    let block = alloc::alloc(layout);
+    if block.is_null() {
+        return ptr::null_mut();
+    }
+    let memory = block.add(offset) as *mut c_void;
+    header(memory).write_unaligned(Header { size, alignment });
+    let allocated = ALLOCATED.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(allocated, Ordering::Relaxed);
+    ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    memory
+}
+
+unsafe extern "system" fn reallocation(
+    user_data: *mut c_void,
+    original: *mut c_void,
+    size: usize,
+    alignment: usize,
+    scope: vk::SystemAllocationScope,
+) -> *mut c_void {
+    if original.is_null() {
+        return allocation(user_data, size, alignment, scope);
+    }
+    if size == 0 {
+        free(user_data, original);
+        return ptr::null_mut();
+    }
+    let old = header(original).read_unaligned();
+    let memory = allocation(user_data, size, alignment, scope);
+    // The original is left as it was when the new allocation fails
+    if !memory.is_null() {
+        ptr::copy_nonoverlapping(original as *const u8, memory as *mut u8, old.size.min(size));
+        free(user_data, original);
+    }
+    memory
+}
+
+unsafe extern "system" fn free(_: *mut c_void, memory: *mut c_void) {
+    if memory.is_null() {
+        return;
+    }
+    let Header { size, alignment } = header(memory).read_unaligned();
+    let (layout, offset) = layout(size, alignment).expect("Freed allocations had a valid layout");
+    alloc::dealloc((memory as *mut u8).sub(offset), layout);
+    ALLOCATED.fetch_sub(size, Ordering::Relaxed);
+    ALLOCATIONS.fetch_sub(1, Ordering::Relaxed);
+}
+
+unsafe extern "system" fn internal_allocation(
+    _: *mut c_void,
+    size: usize,
+    _: vk::InternalAllocationType,
+    _: vk::SystemAllocationScope,
+) {
+    INTERNAL.fetch_add(size, Ordering::Relaxed);
+}
+
+unsafe extern "system" fn internal_free(
+    _: *mut c_void,
+    size: usize,
+    _: vk::InternalAllocationType,
+    _: vk::SystemAllocationScope,
+) {
+    INTERNAL.fetch_sub(size, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCOPE: vk::SystemAllocationScope = vk::SystemAllocationScope::OBJECT;
+
+    #[test]
+    fn layouts_leave_room_for_the_header_and_keep_the_alignment() {
+        for alignment in [1, 2, 4, 8, 16, 32, 64, 256, 4096] {
+            for size in [1, 3, 16, 1000] {
+                let (layout, offset) = layout(size, alignment).unwrap();
+                assert!(offset >= std::mem::size_of::<Header>());
+                assert_eq!(offset % alignment, 0);
+                assert_eq!(layout.align() % alignment, 0);
+                assert!(layout.align() >= HEADER_ALIGNMENT);
+                assert_eq!(layout.size(), size + offset);
+            }
+        }
+    }
+
+    #[test]
+    fn layouts_that_overflow_are_refused() {
+        assert!(layout(usize::MAX, 16).is_none());
+        // Not a power of two
+        assert!(layout(16, 48).is_none());
+    }
+
+    #[test]
+    fn allocations_are_aligned_counted_and_moved_when_reallocated() {
+        let before = usage();
+        unsafe {
+            let memory = allocation(ptr::null_mut(), 24, 64, SCOPE);
+            assert!(!memory.is_null());
+            assert_eq!(memory as usize % 64, 0);
+            let header = header(memory).read_unaligned();
+            assert_eq!((header.size, header.alignment), (24, 64));
+            assert_eq!(usage().allocated, before.allocated + 24);
+            assert_eq!(usage().allocations, before.allocations + 1);
+
+            let bytes: Vec<u8> = (0..24).collect();
+            ptr::copy_nonoverlapping(bytes.as_ptr(), memory as *mut u8, bytes.len());
+            let grown = reallocation(ptr::null_mut(), memory, 100, 8, SCOPE);
+            assert!(!grown.is_null());
+            assert_eq!(grown as usize % HEADER_ALIGNMENT, 0);
+            assert_eq!(
+                std::slice::from_raw_parts(grown as *const u8, 24),
+                &bytes[..]
+            );
+            assert_eq!(usage().allocated, before.allocated + 100);
+            assert_eq!(usage().allocations, before.allocations + 1);
+            assert!(usage().peak >= before.allocated + 124);
+
+            // Reallocating to nothing frees
+            assert!(reallocation(ptr::null_mut(), grown, 0, 8, SCOPE).is_null());
+            free(ptr::null_mut(), ptr::null_mut());
+            assert!(allocation(ptr::null_mut(), 0, 8, SCOPE).is_null());
+        }
+        assert_eq!(usage().allocated, before.allocated);
+        assert_eq!(usage().allocations, before.allocations);
+    }
+}
@@ -0,0 +1,153 @@
+use std::ffi::c_void;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use std::{env, fs, mem, process, ptr, slice};
+
+use libloading::Library;
+
+use crate::logic;
+
+/// Frame update logic in a dynamic library, reloaded whenever the library is rebuilt so gameplay built on the
+/// renderer can be changed without restarting it and loading its assets again. See `logic` for what the library
+/// exports, and `examples/spin_logic.rs` for a library spinning a draw.
+///
+/// The library is copied before it is loaded, so that it can be rebuilt while its copy is in use. When it changes
+/// the new build is loaded alongside the old, and only once it has loaded does the old build save its state and
+/// hand it to the new one. A build that fails to load leaves the old one running.
+pub struct Logic {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    loaded: Loaded,
+    /// Builds loaded so far, numbering the copies
+    builds: u32,
+}
+
+/// A copy of the library, loaded, and its state
+struct Loaded {
+    /// Taken when dropped, to unload the copy before deleting it
+    library: Option<Library>,
+    copy: PathBuf,
+    /// Null until the library is started, and once it has saved its state
+    state: *mut c_void,
+    load: logic::LoadFn,
+    update: logic::UpdateFn,
+    save: logic::SaveFn,
+    free: logic::FreeFn,
+}
+
+impl Logic {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let modified = modified(path);
+        let mut loaded = Loaded::open(path, 0)?;
+        loaded.start(&[]);
+        Ok(Self {
+            path: path.to_path_buf(),
+            modified,
+            loaded,
+            builds: 1,
+        })
+    }
+
+    /// Reloads the library if it has been rebuilt, then runs the frame's update
+    pub fn update(&mut self, frame: &mut logic::Frame) {
+        self.reload_if_changed();
+        unsafe { (self.loaded.update)(self.loaded.state, frame) };
+    }
+
+    fn reload_if_changed(&mut self) {
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return;
+        }
+        self.modified = modified;
+        let mut old = match Loaded::open(&self.path, self.builds) {
+            Ok(loaded) => loaded,
+            Err(e) => return println!("Not reloading {}: {}", self.path.display(), e),
+        };
+        self.builds += 1;
+        mem::swap(&mut self.loaded, &mut old);
+        let saved = old.close();
+        drop(old);
+        self.loaded.start(&saved);
+        println!(
+            "Reloaded {}, handing over {} bytes of state",
+            self.path.display(),
+            saved.len()
+        );
+    }
+}
+
+impl Loaded {
+    /// Copies the library and loads the copy
+    fn open(path: &Path, build: u32) -> Result<Self, String> {
+        let name = path
+            .file_name()
+            .ok_or_else(|| format!("{} isn't a file", path.display()))?;
+        let copy = env::temp_dir().join(format!(
+            "{}-{}-{}",
+            process::id(),
+            build,
+            name.to_string_lossy()
+        ));
+        fs::copy(path, &copy).map_err(|e| format!("Copying {}: {}", path.display(), e))?;
+        let error = |e: libloading::Error| format!("{}: {}", path.display(), e);
+        unsafe {
+            let library = Library::new(&copy).map_err(error)?;
+            let version = *library
+                .get::<logic::VersionFn>(logic::VERSION_SYMBOL)
+                .map_err(error)?;
+            if version() != logic::VERSION {
+                return Err(format!(
+                    "{} was built for version {} of the logic interface, not {}",
+                    path.display(),
+                    version(),
+                    logic::VERSION
+                ));
+            }
+            Ok(Self {
+                load: *library.get(logic::LOAD_SYMBOL).map_err(error)?,
+                update: *library.get(logic::UPDATE_SYMBOL).map_err(error)?,
+                save: *library.get(logic::SAVE_SYMBOL).map_err(error)?,
+                free: *library.get(logic::FREE_SYMBOL).map_err(error)?,
+                library: Some(library),
+                copy,
+                state: ptr::null_mut(),
+            })
+        }
+    }
+
+    /// Creates the library's state from what its previous build saved
+    fn start(&mut self, saved: &[u8]) {
+        self.state = unsafe { (self.load)(saved.as_ptr(), saved.len()) };
+    }
+
+    /// Has the library save and drop its state, returning what it saved
+    fn close(&mut self) -> Vec<u8> {
+        if self.state.is_null() {
+            return Vec::new();
+        }
+        unsafe {
+            let saved = (self.save)(mem::replace(&mut self.state, ptr::null_mut()));
+            let bytes = match saved.data.is_null() {
+                true => Vec::new(),
+                false => slice::from_raw_parts(saved.data, saved.len).to_vec(),
+            };
+            (self.free)(saved);
+            bytes
+        }
+    }
+}
+
+impl Drop for Loaded {
+    fn drop(&mut self) {
+        self.close();
+        self.library = None;
+        let _ = fs::remove_file(&self.copy);
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
@@ -0,0 +1,334 @@
+use std::fs;
+use std::path::Path;
+
+use ash::vk;
+
+/// The extension images the renderer saves are given when no path is asked for: PNG when the `images` feature
+/// can encode it, otherwise PAM, which `save` writes itself.
+#[cfg(feature = "images")]
+pub const EXTENSION: &str = "png";
+#[cfg(not(feature = "images"))]
+pub const EXTENSION: &str = "pam";
+
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xab, b'K', b'T', b'X', b' ', b'2', b'0', 0xbb, b'\r', b'\n', 0x1a, b'\n',
+];
+
+/// An image of red, green, blue and alpha bytes, row by row from the top
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    /// A transparent black image
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; (width * height * 4) as usize],
+        }
+    }
+
+    pub fn from_fn(width: u32, height: u32, pixel: impl Fn(u32, u32) -> [u8; 4]) -> Self {
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .flat_map(|(x, y)| pixel(x, y))
+            .collect();
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Squares of two colours, each `square` pixels across
+    #[cfg(not(feature = "images"))]
+    pub fn checkerboard(size: u32, square: u32, colors: [[u8; 4]; 2]) -> Self {
+        Self::from_fn(size, size, |x, y| {
+            colors[((x / square + y / square) % 2) as usize]
+        })
+    }
+
+    pub fn get_pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let start = ((y * self.width + x) * 4) as usize;
+        let mut pixel = [0; 4];
+        pixel.copy_from_slice(&self.pixels[start..start + 4]);
+        pixel
+    }
+
+    pub fn put_pixel(&mut self, x: u32, y: u32, pixel: [u8; 4]) {
+        let start = ((y * self.width + x) * 4) as usize;
+        self.pixels[start..start + 4].copy_from_slice(&pixel);
+    }
+
+    pub fn flip_vertical(&mut self) {
+        let row = (self.width * 4) as usize;
+        for y in 0..self.height as usize / 2 {
+            let (top, bottom) = self
+                .pixels
+                .split_at_mut((self.height as usize - 1 - y) * row);
+            top[y * row..(y + 1) * row].swap_with_slice(&mut bottom[..row]);
+        }
+    }
+
+    /// The image at half its width and height, rounded down, each pixel the average of the two by two pixels it
+    /// covers
+    pub fn halved(&self) -> Self {
+        let (width, height) = ((self.width / 2).max(1), (self.height / 2).max(1));
+        Self::from_fn(width, height, |x, y| {
+            let mut sum = [0u32; 4];
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let pixel = self.get_pixel(
+                    (x * 2 + dx).min(self.width - 1),
+                    (y * 2 + dy).min(self.height - 1),
+                );
+                for (total, channel) in sum.iter_mut().zip(pixel) {
+                    *total += channel as u32;
+                }
+            }
+            sum.map(|total| ((total + 2) / 4) as u8)
+        })
+    }
+}
+
+/// Reads an image from a file. KTX 2 files holding uncompressed 8 bit sRGB colour and binary Netpbm files
+/// (`.ppm`, `.pgm` and `.pam`) are always read, other formats with the `image` crate when the `images` feature
+/// is enabled. Only the first level, layer and face of a KTX file is read.
+pub fn load(path: &Path) -> Result<Image, String> {
+    let loaded = match extension(path).as_str() {
+        "ktx2" => fs::read(path)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| read_ktx2(&bytes)),
+        "ppm" | "pgm" | "pam" => fs::read(path)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| read_netpbm(&bytes)),
+        _ => decode(path),
+    };
+    loaded.map_err(|e| format!("Loading {}: {}", path.display(), e))
+}
+
+/// Writes the image to a file, as a Netpbm file for `.ppm` and `.pam` paths, and with the `image` crate in the
+/// format of the path's extension otherwise when the `images` feature is enabled. `.ppm` files leave out alpha.
+pub fn save(image: &Image, path: &Path) -> Result<(), String> {
+    let saved = match extension(path).as_str() {
+        "ppm" => {
+            let mut bytes = format!("P6\n{} {}\n255\n", image.width, image.height).into_bytes();
+            bytes.extend(
+                image
+                    .pixels
+                    .chunks_exact(4)
+                    .flat_map(|pixel| pixel[..3].iter().copied()),
+            );
+            fs::write(path, bytes).map_err(|e| e.to_string())
+        }
+        "pam" => {
+            let mut bytes = format!(
+                "P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n",
+                image.width, image.height
+            )
+            .into_bytes();
+            bytes.extend_from_slice(&image.pixels);
+            fs::write(path, bytes).map_err(|e| e.to_string())
+        }
+        _ => encode(image, path),
+    };
+    saved.map_err(|e| format!("Saving {}: {}", path.display(), e))
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+#[cfg(feature = "images")]
+fn decode(path: &Path) -> Result<Image, String> {
+    let image = image::open(path).map_err(|e| e.to_string())?.into_rgba8();
+    Ok(Image {
+        width: image.width(),
+        height: image.height(),
+        pixels: image.into_raw(),
+    })
+}
+
+#[cfg(not(feature = "images"))]
+fn decode(_path: &Path) -> Result<Image, String> {
+    Err(String::from(
+        "only KTX 2 and Netpbm images can be read without the `images` feature",
+    ))
+}
+
+#[cfg(feature = "images")]
+fn encode(image: &Image, path: &Path) -> Result<(), String> {
+    image::save_buffer(
+        path,
+        &image.pixels,
+        image.width,
+        image.height,
+        image::ColorType::Rgba8,
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "images"))]
+fn encode(_image: &Image, _path: &Path) -> Result<(), String> {
+    Err(String::from(
+        "only .ppm and .pam images can be written without the `images` feature",
+    ))
+}
+
+fn read_ktx2(bytes: &[u8]) -> Result<Image, String> {
+    if !bytes.starts_with(&KTX2_IDENTIFIER) {
+        return Err(String::from("not a KTX 2 file"));
+    }
+    let u32_at = |offset: usize| -> Result<u32, String> {
+        let field = bytes
+            .get(offset..offset + 4)
+            .ok_or_else(|| String::from("header is cut short"))?;
+        Ok(u32::from_le_bytes([field[0], field[1], field[2], field[3]]))
+    };
+    let u64_at = |offset: usize| -> Result<usize, String> {
+        Ok((u32_at(offset)? as u64 | (u32_at(offset + 4)? as u64) << 32) as usize)
+    };
+
+    let format = vk::Format::from_raw(u32_at(12)? as i32);
+    let channels = match format {
+        vk::Format::R8G8B8A8_SRGB => 4,
+        vk::Format::R8G8B8_SRGB => 3,
+        format => {
+            return Err(format!(
+                "holds {:?}, only R8G8B8A8_SRGB and R8G8B8_SRGB can be read",
+                format
+            ))
+        }
+    };
+    let (width, height) = (u32_at(20)?, u32_at(24)?);
+    let (depth, layers, faces) = (u32_at(28)?, u32_at(32)?, u32_at(36)?);
+    if depth > 1 || layers > 1 || faces > 1 {
+        return Err(String::from("only single 2D images can be read"));
+    }
+    if u32_at(44)? != 0 {
+        return Err(String::from("supercompressed files can't be read"));
+    }
+
+    // The first entry of the level index, which follows the header, is the full size level
+    let (offset, length) = (u64_at(80)?, u64_at(88)?);
+    let expected = width as usize * height as usize * channels;
+    let level = offset
+        .checked_add(length)
+        .and_then(|end| bytes.get(offset..end))
+        .filter(|level| level.len() >= expected)
+        .ok_or_else(|| String::from("first level is cut short"))?;
+    Ok(to_rgba(width, height, channels, &level[..expected]))
+}
+
+/// Reads a binary Netpbm image with 8 bit channels
+fn read_netpbm(bytes: &[u8]) -> Result<Image, String> {
+    let mut header = NetpbmHeader { bytes, position: 0 };
+    let magic = header.field()?;
+    let (width, height, channels) = match magic {
+        "P5" | "P6" => {
+            let channels = if magic == "P5" { 1 } else { 3 };
+            let (width, height) = (header.number()?, header.number()?);
+            header.maxval()?;
+            (width, height, channels)
+        }
+        "P7" => {
+            let (mut width, mut height, mut channels) = (0, 0, 0);
+            loop {
+                match header.field()? {
+                    "WIDTH" => width = header.number()?,
+                    "HEIGHT" => height = header.number()?,
+                    "DEPTH" => channels = header.number()? as usize,
+                    "MAXVAL" => header.maxval()?,
+                    "TUPLTYPE" => {
+                        header.field()?;
+                    }
+                    "ENDHDR" => break,
+                    field => return Err(format!("unknown header field {}", field)),
+                }
+            }
+            if !(1..=4).contains(&channels) {
+                return Err(format!("has {} channels, at most 4 can be read", channels));
+            }
+            (width, height, channels)
+        }
+        _ => return Err(String::from("not a binary Netpbm file")),
+    };
+    // A single whitespace character separates the header from the pixels
+    let expected = width as usize * height as usize * channels;
+    let pixels = bytes
+        .get(header.position + 1..)
+        .filter(|pixels| pixels.len() >= expected)
+        .ok_or_else(|| String::from("pixels are cut short"))?;
+    Ok(to_rgba(width, height, channels, &pixels[..expected]))
+}
+
+/// The fields of a Netpbm header, which are separated by whitespace and can have comments between them
+struct NetpbmHeader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> NetpbmHeader<'a> {
+    /// The next field, leaving `position` just after it
+    fn field(&mut self) -> Result<&'a str, String> {
+        loop {
+            match self.bytes.get(self.position) {
+                Some(b'#') => {
+                    while !matches!(self.bytes.get(self.position), Some(b'\n') | None) {
+                        self.position += 1;
+                    }
+                }
+                Some(byte) if byte.is_ascii_whitespace() => self.position += 1,
+                Some(_) => break,
+                None => return Err(String::from("header is cut short")),
+            }
+        }
+        let start = self.position;
+        while matches!(self.bytes.get(self.position), Some(byte) if !byte.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.position])
+            .map_err(|_| String::from("header isn't text"))
+    }
+
+    fn number(&mut self) -> Result<u32, String> {
+        let field = self.field()?;
+        field
+            .parse()
+            .map_err(|_| format!("{} in the header isn't a number", field))
+    }
+
+    fn maxval(&mut self) -> Result<(), String> {
+        match self.number()? {
+            255 => Ok(()),
+            maxval => Err(format!(
+                "has a maximum value of {}, only 8 bit channels can be read",
+                maxval
+            )),
+        }
+    }
+}
+
+/// Expands grey, grey and alpha, or RGB pixels to RGBA
+fn to_rgba(width: u32, height: u32, channels: usize, pixels: &[u8]) -> Image {
+    let pixels = pixels
+        .chunks_exact(channels)
+        .flat_map(|pixel| match *pixel {
+            [grey] => [grey, grey, grey, 255],
+            [grey, alpha] => [grey, grey, grey, alpha],
+            [r, g, b] => [r, g, b, 255],
+            [r, g, b, a] => [r, g, b, a],
+            _ => unreachable!("Pixels have at most 4 channels"),
+        })
+        .collect();
+    Image {
+        width,
+        height,
+        pixels,
+    }
+}
@@ -0,0 +1,126 @@
+use cgmath::Matrix4;
+
+use crate::{assets, camera, material, mesh};
+
+/// Runs once a frame to say what the frame draws, see `HelloTriangleApplication::set_immediate`
+pub type Update = Box<dyn FnMut(&mut Renderer)>;
+
+/// One mesh drawn for one frame
+pub struct Command {
+    pub mesh: assets::Mesh,
+    pub transform: Matrix4<f32>,
+    pub material: material::Material,
+}
+
+/// What an immediate mode update draws with. Everything drawn only lasts the frame, so the update draws all of
+/// it again every frame, without adding, moving or removing draws in the scene.
+pub struct Renderer<'a> {
+    commands: &'a mut Vec<Command>,
+    quad: &'a assets::Mesh,
+    camera: camera::Camera,
+    camera_set: bool,
+    /// Seconds since the renderer started, or into the timeline while one plays
+    pub time: f32,
+    /// Seconds since the last update
+    pub delta: f32,
+}
+
+impl<'a> Renderer<'a> {
+    /// Draws a mesh loaded with `HelloTriangleApplication::load_mesh` this frame
+    pub fn draw_mesh(
+        &mut self,
+        mesh: &assets::Mesh,
+        transform: Matrix4<f32>,
+        material: material::Material,
+    ) {
+        self.commands.push(Command {
+            mesh: mesh.clone(),
+            transform,
+            material,
+        });
+    }
+
+    /// Draws a square one unit across this frame, centred on the origin in the XY plane and facing +Z before
+    /// the transform, textured across its face
+    pub fn draw_quad(&mut self, transform: Matrix4<f32>, material: material::Material) {
+        let quad = self.quad.clone();
+        self.draw_mesh(&quad, transform, material);
+    }
+
+    /// The camera the frame is drawn from, with any change made by `set_camera`
+    pub fn camera(&self) -> &camera::Camera {
+        &self.camera
+    }
+
+    /// Draws the frame from the camera, which stays where it is left for later frames and the camera controls
+    pub fn set_camera(&mut self, camera: camera::Camera) {
+        self.camera = camera;
+        self.camera_set = true;
+    }
+}
+
+/// An immediate mode facade over the retained draw list, for putting things on screen without keeping track of
+/// draws. The update's commands are matched up with draws kept for it from frame to frame in order, so a draw is
+/// only rebuilt when its command draws another mesh or needs another pipeline than last frame, and otherwise
+/// just moved. Draws left over from frames that drew more are emptied rather than removed, keeping every other
+/// draw's index.
+pub struct Immediate {
+    update: Update,
+    quad: assets::Mesh,
+    commands: Vec<Command>,
+    /// Indices of the draws kept for the commands
+    pub draws: Vec<usize>,
+    updated: Option<f32>,
+}
+
+impl Immediate {
+    /// `quad` is `quad()` loaded onto the GPU
+    pub fn new(update: Update, quad: assets::Mesh) -> Self {
+        Self {
+            update,
+            quad,
+            commands: Vec::new(),
+            draws: Vec::new(),
+            updated: None,
+        }
+    }
+
+    /// Runs the update, returning the camera if it set it
+    pub fn run(&mut self, camera: camera::Camera, time: f32) -> Option<camera::Camera> {
+        self.commands.clear();
+        let mut renderer = Renderer {
+            commands: &mut self.commands,
+            quad: &self.quad,
+            camera,
+            camera_set: false,
+            time,
+            delta: time - self.updated.unwrap_or(time),
+        };
+        (self.update)(&mut renderer);
+        self.updated = Some(time);
+        renderer.camera_set.then_some(renderer.camera)
+    }
+
+    /// What the last update drew, in the order it drew it
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+}
+
+/// The geometry of `Renderer::draw_quad`, wound clockwise like the renderer's other built in geometry
+pub fn quad() -> mesh::MeshData {
+    mesh::MeshData {
+        positions: vec![
+            [-0.5, -0.5, 0.0],
+            [0.5, -0.5, 0.0],
+            [0.5, 0.5, 0.0],
+            [-0.5, 0.5, 0.0],
+        ],
+        normals: vec![[0.0, 0.0, 1.0]; 4],
+        colors: None,
+        tex_coords: Some(vec![[0.0, 1.0], [1.0, 1.0], [1.0, 0.0], [0.0, 0.0]]),
+        lightmap_coords: None,
+        occlusion: None,
+        indices: vec![0, 1, 2, 2, 3, 0],
+    }
+}
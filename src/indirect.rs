@@ -0,0 +1,538 @@
+use std::mem::size_of;
+use std::path::Path;
+
+use ash::vk;
+use cgmath::Matrix4;
+use memoffset::offset_of;
+
+use crate::{bounds, conditional, hiz, pipeline, postprocess, util, HelloTriangleApplication};
+
+/// Binding the objects are read from as per-instance vertex attributes, after the mesh bindings
+pub const INSTANCE_BINDING: u32 = 2;
+/// Objects culled per workgroup, matching `local_size_x` in `cull_comp.glsl`
+const WORKGROUP_SIZE: u32 = 64;
+
+/// One object of the scene. Matches `Object` in `cull_comp.glsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Object {
+    /// Places the object's vertices in the scene, applied before the scene's model matrix
+    transform: [[f32; 4]; 4],
+    /// xyz is the centre of the object's bounding sphere in the scene, w its radius
+    sphere: [f32; 4],
+    first_index: u32,
+    index_count: u32,
+    vertex_offset: i32,
+    _padding: u32,
+}
+
+impl Object {
+    /// An object drawing `index_count` indices from `first_index` of the scene's index buffer. `bounds`
+    /// contains the drawn vertices before they are transformed.
+    pub fn new(
+        transform: Matrix4<f32>,
+        bounds: &bounds::Aabb,
+        first_index: u32,
+        index_count: u32,
+        vertex_offset: i32,
+    ) -> Self {
+        let placed = bounds.transformed(&transform);
+        let center = placed.center();
+        Self {
+            transform: transform.into(),
+            sphere: [center.x, center.y, center.z, placed.radius()],
+            first_index,
+            index_count,
+            vertex_offset,
+            _padding: 0,
+        }
+    }
+
+    pub fn get_binding_description() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::builder()
+            .binding(INSTANCE_BINDING)
+            .stride(size_of::<Self>() as u32)
+            .input_rate(vk::VertexInputRate::INSTANCE)
+            .build()
+    }
+
+    /// The transform's columns at locations 4 to 7, after the mesh attributes.
+    pub fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescription; 4] {
+        let column = size_of::<[f32; 4]>() as u32;
+        [0, 1, 2, 3].map(|i| {
+            vk::VertexInputAttributeDescription::builder()
+                .binding(INSTANCE_BINDING)
+                .location(4 + i)
+                .format(vk::Format::R32G32B32A32_SFLOAT)
+                .offset(offset_of!(Self, transform) as u32 + i * column)
+                .build()
+        })
+    }
+}
+
+/// Matches the push constants in `cull_comp.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Cull {
+    clip: [[f32; 4]; 4],
+    object_count: u32,
+    compact: u32,
+    hi_z: u32,
+}
+
+impl Cull {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+}
+
+/// A scene of objects sharing one vertex and index buffer whose draw stream is built entirely on the GPU. A
+/// compute shader frustum culls every object each frame and writes the draw commands for those that are
+/// visible, which are drawn with a single indirect draw so the CPU cost doesn't grow with the object count.
+///
+/// When `VK_KHR_draw_indirect_count` is available the visible commands are packed together and the draw count
+/// is read from the GPU too. Otherwise every object keeps a command, with culled objects drawing no
+/// instances. When `VK_EXT_conditional_rendering` is available the whole draw is skipped on the GPU once every
+/// object has been culled, using the count of visible objects as its condition.
+///
+/// With a depth attachment to build a `hiz::Pyramid` from, objects inside the frustum are also culled when they
+/// are behind what the previous frame drew.
+pub struct Scene {
+    object_count: u32,
+    objects_buffer: vk::Buffer,
+    objects_buffer_memory: vk::DeviceMemory,
+    commands_buffer: vk::Buffer,
+    commands_buffer_memory: vk::DeviceMemory,
+    count_buffer: vk::Buffer,
+    count_buffer_memory: vk::DeviceMemory,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+    layout: vk::PipelineLayout,
+    cull_pipeline: vk::Pipeline,
+    pyramid: hiz::Pyramid,
+    /// Whether a frame has been culled, and so drawn, since the pyramid was made, leaving depth to build it from
+    drawn_since_pyramid: bool,
+    /// Owned by the application
+    vertex_buffer: vk::Buffer,
+    index_buffer: vk::Buffer,
+    config: pipeline::Config,
+    draw_indirect_count: Option<ash::extensions::khr::DrawIndirectCount>,
+    conditional_rendering: Option<conditional::ConditionalRendering>,
+}
+
+impl Scene {
+    /// Whether the device can draw every object with one indirect draw, each selecting its transform with
+    /// the draw's first instance.
+    pub fn supported(features: &vk::PhysicalDeviceFeatures) -> bool {
+        features.multi_draw_indirect == vk::TRUE
+            && features.draw_indirect_first_instance == vk::TRUE
+    }
+
+    /// `config` is the pipeline objects are drawn with, and its program is replaced with
+    /// `pipeline::Program::Indirect`. `vertex_buffer` holds vertices with the config's attributes and
+    /// `index_buffer` `u16` indices. Objects are only tested against the depth pyramid when given `depth`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+        submit_queue: vk::Queue,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        objects: &[Object],
+        vertex_buffer: vk::Buffer,
+        index_buffer: vk::Buffer,
+        config: pipeline::Config,
+        draw_indirect_count: Option<ash::extensions::khr::DrawIndirectCount>,
+        conditional_rendering: Option<conditional::ConditionalRendering>,
+        depth: Option<hiz::Depth>,
+    ) -> Self {
+        let (objects_buffer, objects_buffer_memory) =
+            HelloTriangleApplication::create_device_local_buffer(
+                device,
+                objects,
+                vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::VERTEX_BUFFER,
+                command_pool,
+                submit_queue,
+                device_memory_properties,
+            );
+        let (commands_buffer, commands_buffer_memory) = HelloTriangleApplication::create_buffer(
+            device,
+            (objects.len() * size_of::<vk::DrawIndexedIndirectCommand>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            device_memory_properties,
+        );
+        let condition_usage = match conditional_rendering {
+            Some(_) => conditional::BUFFER_USAGE,
+            None => vk::BufferUsageFlags::empty(),
+        };
+        let (count_buffer, count_buffer_memory) = HelloTriangleApplication::create_buffer(
+            device,
+            size_of::<u32>() as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::INDIRECT_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_DST
+                | condition_usage,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            device_memory_properties,
+        );
+
+        let bindings: Vec<vk::DescriptorSetLayoutBinding> = (0..4)
+            .map(|binding| {
+                vk::DescriptorSetLayoutBinding::builder()
+                    .binding(binding)
+                    .descriptor_count(1)
+                    .descriptor_type(match binding {
+                        3 => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                        _ => vk::DescriptorType::STORAGE_BUFFER,
+                    })
+                    .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                    .build()
+            })
+            .collect();
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+                    None,
+                )
+                .expect("Cull descriptor set layout")
+        };
+        let pool_sizes = [
+            vk::DescriptorPoolSize::builder()
+                .ty(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(3)
+                .build(),
+            vk::DescriptorPoolSize::builder()
+                .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .build(),
+        ];
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::builder()
+                        .pool_sizes(&pool_sizes)
+                        .max_sets(1),
+                    None,
+                )
+                .expect("Cull descriptor pool")
+        };
+        let set_layouts = [descriptor_set_layout];
+        let descriptor_set = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::builder()
+                        .descriptor_pool(descriptor_pool)
+                        .set_layouts(&set_layouts),
+                )
+                .expect("Cull descriptor set")[0]
+        };
+        let buffer_infos = [objects_buffer, commands_buffer, count_buffer].map(|buffer| {
+            [vk::DescriptorBufferInfo::builder()
+                .buffer(buffer)
+                .offset(0)
+                .range(vk::WHOLE_SIZE)
+                .build()]
+        });
+        let writes: Vec<vk::WriteDescriptorSet> = buffer_infos
+            .iter()
+            .enumerate()
+            .map(|(binding, buffer_info)| {
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(binding as u32)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(buffer_info)
+                    .build()
+            })
+            .collect();
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(size_of::<Cull>() as u32)
+            .build()];
+        let layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::builder()
+                        .set_layouts(&set_layouts)
+                        .push_constant_ranges(&push_constant_ranges),
+                    None,
+                )
+                .expect("Cull pipeline layout")
+        };
+        let shader_path = Path::new(env!("OUT_DIR")).join("cull_comp.spv");
+        let cull_pipeline = postprocess::create_compute_pipeline(
+            device,
+            layout,
+            &util::read_shader_code(&shader_path),
+        );
+
+        let scene = Self {
+            object_count: objects.len() as u32,
+            objects_buffer,
+            objects_buffer_memory,
+            commands_buffer,
+            commands_buffer_memory,
+            count_buffer,
+            count_buffer_memory,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            layout,
+            cull_pipeline,
+            pyramid: hiz::Pyramid::new(
+                device,
+                command_pool,
+                submit_queue,
+                device_memory_properties,
+                depth,
+            ),
+            drawn_since_pyramid: false,
+            vertex_buffer,
+            index_buffer,
+            config: pipeline::Config {
+                program: pipeline::Program::Indirect,
+                ..config
+            },
+            draw_indirect_count,
+            conditional_rendering,
+        };
+        scene.write_pyramid_descriptor(device);
+        scene
+    }
+
+    fn write_pyramid_descriptor(&self, device: &ash::Device) {
+        let image_info = [self.pyramid.descriptor_info()];
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(self.descriptor_set)
+            .dst_binding(3)
+            .dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_info)
+            .build();
+        unsafe { device.update_descriptor_sets(&[write], &[]) };
+    }
+
+    /// Remakes the depth pyramid for a new depth attachment, or stops testing objects against it when `depth`
+    /// is `None`. The device must be idle.
+    pub fn set_depth(
+        &mut self,
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+        submit_queue: vk::Queue,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        depth: Option<hiz::Depth>,
+    ) {
+        self.pyramid.destroy(device);
+        self.pyramid = hiz::Pyramid::new(
+            device,
+            command_pool,
+            submit_queue,
+            device_memory_properties,
+            depth,
+        );
+        self.drawn_since_pyramid = false;
+        self.write_pyramid_descriptor(device);
+    }
+
+    /// The stages and accesses after culling that read the draw commands and count
+    fn draw_reads(&self) -> (vk::PipelineStageFlags, vk::AccessFlags) {
+        match self.conditional_rendering {
+            Some(_) => (
+                vk::PipelineStageFlags::DRAW_INDIRECT | conditional::STAGE,
+                vk::AccessFlags::INDIRECT_COMMAND_READ | conditional::ACCESS,
+            ),
+            None => (
+                vk::PipelineStageFlags::DRAW_INDIRECT,
+                vk::AccessFlags::INDIRECT_COMMAND_READ,
+            ),
+        }
+    }
+
+    /// Records culling the objects against the frustum of `clip`, the transform from the scene to clip space,
+    /// and against the depth pyramid once there is a previous frame to build it from. Must be recorded outside of
+    /// a render pass, before `record_draw`.
+    pub fn record_cull(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        clip: &Matrix4<f32>,
+    ) {
+        let hi_z = self.pyramid.can_build() && self.drawn_since_pyramid;
+        if hi_z {
+            self.pyramid.record_build(device, command_buffer);
+        }
+        self.drawn_since_pyramid = true;
+        let cull = Cull {
+            clip: (*clip).into(),
+            object_count: self.object_count,
+            compact: self.draw_indirect_count.is_some() as u32,
+            hi_z: hi_z as u32,
+        };
+        let buffer_barrier =
+            |buffer: vk::Buffer, src_access: vk::AccessFlags, dst_access: vk::AccessFlags| {
+                vk::BufferMemoryBarrier::builder()
+                    .src_access_mask(src_access)
+                    .dst_access_mask(dst_access)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .buffer(buffer)
+                    .offset(0)
+                    .size(vk::WHOLE_SIZE)
+                    .build()
+            };
+        let (read_stages, read_access) = self.draw_reads();
+
+        unsafe {
+            // The previous frame's draw may still be reading the commands and count
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                read_stages,
+                vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[],
+            );
+            device.cmd_fill_buffer(command_buffer, self.count_buffer, 0, vk::WHOLE_SIZE, 0);
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[buffer_barrier(
+                    self.count_buffer,
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+                )],
+                &[],
+            );
+
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.cull_pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                self.layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                cull.as_bytes(),
+            );
+            device.cmd_dispatch(
+                command_buffer,
+                self.object_count.div_ceil(WORKGROUP_SIZE),
+                1,
+                1,
+            );
+
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                read_stages,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[self.commands_buffer, self.count_buffer].map(|buffer| {
+                    buffer_barrier(buffer, vk::AccessFlags::SHADER_WRITE, read_access)
+                }),
+                &[],
+            );
+        }
+    }
+
+    /// The pipeline the objects are drawn with
+    pub fn config(&self) -> pipeline::Config {
+        self.config
+    }
+
+    /// Records drawing the objects left visible by `record_cull`, inside the render pass with the frame's
+    /// descriptor sets bound. The draw is skipped on the GPU when conditional rendering is available and no
+    /// objects are visible.
+    pub fn record_draw(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        pipelines: &mut pipeline::Variants,
+    ) {
+        let pipeline = pipelines.get(device, &self.config);
+        let stride = size_of::<vk::DrawIndexedIndirectCommand>() as u32;
+
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            // Every pipeline has dynamic line width and depth bias, neither of which objects use
+            device.cmd_set_line_width(command_buffer, 1.0);
+            device.cmd_set_depth_bias(command_buffer, 0.0, 0.0, 0.0);
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[self.vertex_buffer], &[0]);
+            device.cmd_bind_vertex_buffers(
+                command_buffer,
+                INSTANCE_BINDING,
+                &[self.objects_buffer],
+                &[0],
+            );
+            device.cmd_bind_index_buffer(
+                command_buffer,
+                self.index_buffer,
+                0,
+                vk::IndexType::UINT16,
+            );
+
+            if let Some(conditional_rendering) = &self.conditional_rendering {
+                conditional_rendering.begin(command_buffer, self.count_buffer, 0, false);
+            }
+            match &self.draw_indirect_count {
+                Some(draw_indirect_count) => draw_indirect_count.cmd_draw_indexed_indirect_count(
+                    command_buffer,
+                    self.commands_buffer,
+                    0,
+                    self.count_buffer,
+                    0,
+                    self.object_count,
+                    stride,
+                ),
+                None => device.cmd_draw_indexed_indirect(
+                    command_buffer,
+                    self.commands_buffer,
+                    0,
+                    self.object_count,
+                    stride,
+                ),
+            }
+            if let Some(conditional_rendering) = &self.conditional_rendering {
+                conditional_rendering.end(command_buffer);
+            }
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            self.pyramid.destroy(device);
+            device.destroy_pipeline(self.cull_pipeline, None);
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            for (buffer, memory) in [
+                (self.objects_buffer, self.objects_buffer_memory),
+                (self.commands_buffer, self.commands_buffer_memory),
+                (self.count_buffer, self.count_buffer_memory),
+            ] {
+                device.destroy_buffer(buffer, None);
+                device.free_memory(memory, None);
+            }
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::fmt;
+
+use ash::vk;
+
+/// A snapshot of the choices the renderer made for the current device and swapchain. Useful for logging and
+/// for diagnosing output that looks wrong on a particular machine.
+#[derive(Clone, Debug)]
+pub struct RendererInfo {
+    pub device_name: String,
+    pub surface_format: vk::SurfaceFormatKHR,
+    pub present_mode: vk::PresentModeKHR,
+    /// Whether the surface format and present mode were chosen with `surface::Overrides` rather than by the
+    /// renderer
+    pub forced_surface_format: bool,
+    pub forced_present_mode: bool,
+    pub extent: vk::Extent2D,
+    /// True when the surface format doesn't encode to sRGB on write so the shaders do it themselves
+    pub manual_srgb_encoding: bool,
+    /// How the frame is rotated before it is presented, see `prerotation::choose_transform`
+    pub transform: vk::SurfaceTransformFlagsKHR,
+    pub depth_format: vk::Format,
+    /// The formats the device would use for other kinds of targets, or `None` where it supports none of the
+    /// candidates
+    pub formats: Vec<(&'static str, Option<vk::Format>)>,
+    /// Textures loaded at a lower resolution than their source to fit in device memory
+    pub texture_downgrades: Vec<String>,
+}
+
+impl fmt::Display for RendererInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Rendering on [{}] to {}x{} {:?} {:?} surface with {:?} presentation",
+            self.device_name,
+            self.extent.width,
+            self.extent.height,
+            self.surface_format.format,
+            self.surface_format.color_space,
+            self.present_mode,
+        )?;
+        match (self.forced_surface_format, self.forced_present_mode) {
+            (true, true) => write!(f, " (format and present mode forced)")?,
+            (true, false) => write!(f, " (format forced)")?,
+            (false, true) => write!(f, " (present mode forced)")?,
+            (false, false) => (),
+        }
+        if self.manual_srgb_encoding {
+            write!(f, " (sRGB encoded in shaders)")?;
+        }
+        if self.transform != vk::SurfaceTransformFlagsKHR::IDENTITY {
+            write!(f, " pre-rotated {:?}", self.transform)?;
+        }
+        write!(f, ", {:?} depth", self.depth_format)?;
+        for (name, format) in self.formats.iter() {
+            match format {
+                Some(format) => write!(f, ", {:?} {}", format, name)?,
+                None => write!(f, ", no {} format", name)?,
+            }
+        }
+        for downgrade in self.texture_downgrades.iter() {
+            write!(f, "\n    Texture {}", downgrade)?;
+        }
+        Ok(())
+    }
+}
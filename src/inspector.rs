@@ -0,0 +1,333 @@
+use std::any::Any;
+
+use ash::vk;
+
+use crate::features::{RenderFeature, SwapchainContext};
+use crate::{framegraph, hooks, HelloTriangleApplication};
+
+/// Bytes read back per pixel: the colour texel followed by the depth texel
+const SAMPLE_SIZE: vk::DeviceSize = 8;
+
+/// The values of one pixel of a presented frame.
+#[derive(Clone, Copy, Debug)]
+pub struct Sample {
+    /// Pixel from the top left of the window
+    pub pixel: (u32, u32),
+    /// Red, green, blue and alpha as stored in the swapchain image, or `None` if the swapchain image can't be
+    /// copied from
+    pub color: Option<[u8; 4]>,
+    /// Value in the depth buffer, from 0 at the near plane to 1 at the far plane
+    pub depth: f32,
+}
+
+/// A host visible buffer a pixel is copied into by one swapchain image's command buffer.
+struct Readback {
+    buffer: vk::Buffer,
+    memory: vk::DeviceMemory,
+    /// The pixel copied by the commands last recorded for the image
+    pending: Option<(u32, u32)>,
+}
+
+/// Reads back a pixel from the presented colour and the depth buffer, after everything but the host's
+/// `AfterUi` hooks has drawn. Each swapchain image copies into its own buffer, which is read the next time
+/// the image's commands are recorded, once they have finished executing, so samples lag a few frames behind.
+#[derive(Default)]
+pub struct Inspector {
+    /// The pixel to read back from frames recorded from now on, from the top left of the window
+    pub pixel: Option<(u32, u32)>,
+    /// The last pixel read back
+    latest: Option<Sample>,
+    readbacks: Vec<Readback>,
+    color_format: vk::Format,
+    depth_format: vk::Format,
+    /// Whether the swapchain images were created with transfer source usage
+    copy_color: bool,
+}
+
+impl Inspector {
+    /// The last sample read back, if there has been one since it was last taken.
+    pub fn take_latest(&mut self) -> Option<Sample> {
+        self.latest.take()
+    }
+
+    /// The pixel copied by the image's previous commands. Must only be called once they have finished.
+    fn take_sample(&mut self, device: &ash::Device, image_index: usize) -> Option<Sample> {
+        let readback = &mut self.readbacks[image_index];
+        let pixel = readback.pending.take()?;
+
+        let mut bytes = [0u8; SAMPLE_SIZE as usize];
+        unsafe {
+            let data = device
+                .map_memory(readback.memory, 0, SAMPLE_SIZE, vk::MemoryMapFlags::empty())
+                .expect("Mapping pixel readback memory") as *const u8;
+            data.copy_to_nonoverlapping(bytes.as_mut_ptr(), bytes.len());
+            device.unmap_memory(readback.memory);
+        }
+
+        let color = if self.copy_color {
+            let [a, b, c, d] = [bytes[0], bytes[1], bytes[2], bytes[3]];
+            Some(match self.color_format {
+                vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM => [c, b, a, d],
+                _ => [a, b, c, d],
+            })
+        } else {
+            None
+        };
+        let depth_bytes = [bytes[4], bytes[5], bytes[6], bytes[7]];
+        let depth = match self.depth_format {
+            // Depth is copied out of packed depth stencil formats in the low 24 bits
+            vk::Format::D24_UNORM_S8_UINT => {
+                (u32::from_le_bytes(depth_bytes) & 0x00ff_ffff) as f32 / 0x00ff_ffff as f32
+            }
+            _ => f32::from_le_bytes(depth_bytes),
+        };
+
+        Some(Sample {
+            pixel,
+            color,
+            depth,
+        })
+    }
+
+    /// Records copying the pixel from the presented image and the depth buffer. The swapchain image is ready
+    /// to present again afterwards.
+    fn record_copy(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+        pixel: (u32, u32),
+        swapchain_image: vk::Image,
+        depth_image: vk::Image,
+    ) {
+        let readback = &mut self.readbacks[image_index];
+        readback.pending = Some(pixel);
+
+        let mut depth_aspect = vk::ImageAspectFlags::DEPTH;
+        if HelloTriangleApplication::has_stencil_component(self.depth_format) {
+            depth_aspect |= vk::ImageAspectFlags::STENCIL;
+        }
+        let barrier = |image: vk::Image,
+                       aspect_mask: vk::ImageAspectFlags,
+                       old: vk::ImageLayout,
+                       new: vk::ImageLayout,
+                       src_access: vk::AccessFlags,
+                       dst_access: vk::AccessFlags| {
+            vk::ImageMemoryBarrier::builder()
+                .old_layout(old)
+                .new_layout(new)
+                .src_access_mask(src_access)
+                .dst_access_mask(dst_access)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(
+                    vk::ImageSubresourceRange::builder()
+                        .aspect_mask(aspect_mask)
+                        .base_mip_level(0)
+                        .level_count(1)
+                        .base_array_layer(0)
+                        .layer_count(1)
+                        .build(),
+                )
+                .build()
+        };
+        let copy = |aspect_mask: vk::ImageAspectFlags, offset: vk::DeviceSize| {
+            vk::BufferImageCopy::builder()
+                .buffer_offset(offset)
+                .buffer_row_length(0)
+                .buffer_image_height(0)
+                .image_subresource(
+                    vk::ImageSubresourceLayers::builder()
+                        .aspect_mask(aspect_mask)
+                        .mip_level(0)
+                        .base_array_layer(0)
+                        .layer_count(1)
+                        .build(),
+                )
+                .image_offset(vk::Offset3D {
+                    x: pixel.0 as i32,
+                    y: pixel.1 as i32,
+                    z: 0,
+                })
+                .image_extent(vk::Extent3D {
+                    width: 1,
+                    height: 1,
+                    depth: 1,
+                })
+                .build()
+        };
+
+        let mut to_transfer = vec![barrier(
+            depth_image,
+            depth_aspect,
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            vk::AccessFlags::TRANSFER_READ,
+        )];
+        // The next frame's depth test must not start until the copy has read the depth buffer
+        let mut from_transfer = vec![barrier(
+            depth_image,
+            depth_aspect,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            vk::AccessFlags::empty(),
+            vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        )];
+        if self.copy_color {
+            to_transfer.push(barrier(
+                swapchain_image,
+                vk::ImageAspectFlags::COLOR,
+                vk::ImageLayout::PRESENT_SRC_KHR,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::TRANSFER_READ,
+            ));
+            from_transfer.push(barrier(
+                swapchain_image,
+                vk::ImageAspectFlags::COLOR,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::ImageLayout::PRESENT_SRC_KHR,
+                vk::AccessFlags::empty(),
+                vk::AccessFlags::empty(),
+            ));
+        }
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &to_transfer,
+            );
+            device.cmd_copy_image_to_buffer(
+                command_buffer,
+                depth_image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                readback.buffer,
+                &[copy(vk::ImageAspectFlags::DEPTH, 4)],
+            );
+            if self.copy_color {
+                device.cmd_copy_image_to_buffer(
+                    command_buffer,
+                    swapchain_image,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    readback.buffer,
+                    &[copy(vk::ImageAspectFlags::COLOR, 0)],
+                );
+            }
+            let host_barrier = vk::BufferMemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::HOST_READ)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .buffer(readback.buffer)
+                .offset(0)
+                .size(vk::WHOLE_SIZE)
+                .build();
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[host_barrier],
+                &[],
+            );
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &from_transfer,
+            );
+        }
+    }
+}
+
+impl RenderFeature for Inspector {
+    fn name(&self) -> &str {
+        "pixel inspector"
+    }
+
+    fn init(&mut self, context: &SwapchainContext) {
+        self.readbacks = (0..context.image_count)
+            .map(|_| {
+                let (buffer, memory) = HelloTriangleApplication::create_buffer(
+                    context.device,
+                    SAMPLE_SIZE,
+                    vk::BufferUsageFlags::TRANSFER_DST,
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                    context.device_memory_properties,
+                );
+                Readback {
+                    buffer,
+                    memory,
+                    pending: None,
+                }
+            })
+            .collect();
+        self.color_format = context.format;
+        self.depth_format = context.depth_format;
+        self.copy_color = context.usage.contains(vk::ImageUsageFlags::TRANSFER_SRC);
+    }
+
+    fn resize(&mut self, context: &SwapchainContext) {
+        self.destroy(context.device);
+        self.init(context);
+    }
+
+    fn prepare(&mut self, context: &SwapchainContext, image_index: usize) {
+        if let Some(sample) = self.take_sample(context.device, image_index) {
+            self.latest = Some(sample);
+        }
+    }
+
+    fn record(&mut self, context: &hooks::FrameContext) {
+        if let (hooks::Stage::AfterUi, Some(pixel)) = (context.stage, self.pixel) {
+            self.record_copy(
+                context.device,
+                context.command_buffer,
+                context.image_index,
+                pixel,
+                context.swapchain_image,
+                context.depth_image,
+            );
+        }
+    }
+
+    fn passes(&self) -> Vec<framegraph::Pass> {
+        if self.pixel.is_none() {
+            return Vec::new();
+        }
+        vec![framegraph::Pass::new(
+            "inspector",
+            framegraph::Position::Stage(hooks::Stage::AfterUi),
+            &[framegraph::SWAPCHAIN, framegraph::DEPTH],
+            &["inspector readback"],
+        )]
+    }
+
+    fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            for readback in self.readbacks.drain(..) {
+                device.destroy_buffer(readback.buffer, None);
+                device.free_memory(readback.memory, None);
+            }
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
@@ -16,6 +16,7 @@ pub fn new<T>(
     layers: &[CString],
     extensions: &[CString],
     extension_data: &mut [T],
+    allocator: Option<&vk::AllocationCallbacks>,
 ) -> Result<ash::Instance, String>
 where
     T: vk::ExtendsInstanceCreateInfo,
@@ -53,7 +54,7 @@ where
 
     unsafe {
         entry
-            .create_instance(&builder, None)
+            .create_instance(&builder, allocator)
             .map_err(|e| format!("{}", e))
     }
 }
@@ -68,7 +69,7 @@ fn validate_extensions(entry: &ash::Entry, extensions: &[CString]) -> Result<(),
         for required_extension in extensions.iter().map(|e| String::from(e.to_str().unwrap())) {
             let is_present = available_extension_properties
                 .iter()
-                .map(|p| util::read_vk_string(&p.extension_name).unwrap())
+                .map(|p| util::read_vk_string(&p.extension_name))
                 .fold(false, |acc, current| acc || current.eq(&required_extension));
 
             if !is_present {
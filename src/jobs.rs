@@ -0,0 +1,250 @@
+use std::time::{Duration, Instant};
+
+use ash::vk;
+
+/// Time spent in each of the systems that prepare a frame, averaged over the frames since they were last
+/// taken.
+#[derive(Default)]
+pub struct Timings {
+    systems: Vec<(&'static str, Duration)>,
+    frames: u32,
+    /// The systems' times in the frame being prepared, and in the last one to end
+    frame: Vec<(&'static str, Duration)>,
+    last_frame: Vec<(&'static str, Duration)>,
+}
+
+impl Timings {
+    /// Runs the system, adding how long it took to the frame's timings.
+    pub fn time<T>(&mut self, system: &'static str, run: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = run();
+        let elapsed = start.elapsed();
+        for times in [&mut self.systems, &mut self.frame] {
+            match times.iter_mut().find(|(name, _)| *name == system) {
+                Some((_, total)) => *total += elapsed,
+                None => times.push((system, elapsed)),
+            }
+        }
+        result
+    }
+
+    pub fn end_frame(&mut self) {
+        self.frames += 1;
+        // Swapped rather than taken, so the next frame's timings reuse the last's allocation
+        std::mem::swap(&mut self.last_frame, &mut self.frame);
+        self.frame.clear();
+    }
+
+    /// How long each system took in the last frame to end
+    pub fn last_frame(&self) -> &[(&'static str, Duration)] {
+        &self.last_frame
+    }
+
+    /// The average time each system took per frame since the timings were last taken, in the order they first
+    /// ran.
+    pub fn take_averages(&mut self) -> Vec<(&'static str, Duration)> {
+        let frames = self.frames.max(1);
+        self.frames = 0;
+        self.systems
+            .drain(..)
+            .map(|(name, total)| (name, total / frames))
+            .collect()
+    }
+}
+
+/// Secondary command buffers that continue a swapchain image's render pass, so that its draws can be
+/// recorded by jobs running on rayon's threads. Every buffer has a pool of its own since command pools can
+/// only be used from one thread at a time.
+pub struct SecondaryBuffers {
+    /// For each swapchain image, the pools its draws are recorded from and the buffers they are recorded into
+    images: Vec<(Vec<vk::CommandPool>, Vec<vk::CommandBuffer>)>,
+}
+
+impl SecondaryBuffers {
+    pub fn new(
+        device: &ash::Device,
+        queue_family: u32,
+        image_count: usize,
+        buffers_per_image: usize,
+    ) -> Self {
+        let images = (0..image_count)
+            .map(|_| {
+                (0..buffers_per_image)
+                    .map(|_| unsafe {
+                        let pool = device
+                            .create_command_pool(
+                                &vk::CommandPoolCreateInfo::builder()
+                                    .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+                                    .queue_family_index(queue_family),
+                                None,
+                            )
+                            .expect("Secondary command pool");
+                        let buffer = device
+                            .allocate_command_buffers(
+                                &vk::CommandBufferAllocateInfo::builder()
+                                    .command_pool(pool)
+                                    .level(vk::CommandBufferLevel::SECONDARY)
+                                    .command_buffer_count(1),
+                            )
+                            .expect("Secondary command buffer")[0];
+                        (pool, buffer)
+                    })
+                    .unzip()
+            })
+            .collect();
+
+        Self { images }
+    }
+
+    /// The image's buffers, reset ready to record again. Must only be called once the image's previous
+    /// commands have finished.
+    pub fn reset(&self, device: &ash::Device, image_index: usize) -> &[vk::CommandBuffer] {
+        let (pools, buffers) = &self.images[image_index];
+        for &pool in pools.iter() {
+            unsafe {
+                device
+                    .reset_command_pool(pool, vk::CommandPoolResetFlags::empty())
+                    .expect("Resetting secondary command pool")
+            };
+        }
+        buffers
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        for pool in self.images.drain(..).flat_map(|(pools, _)| pools) {
+            unsafe { device.destroy_command_pool(pool, None) };
+        }
+    }
+}
+
+/// A secondary command buffer per swapchain image that is kept from frame to frame, along with the key it was
+/// recorded for. It is only recorded again when a frame asks for it with a different key.
+pub struct BakedBuffers<K> {
+    images: Vec<(vk::CommandPool, vk::CommandBuffer, Option<K>)>,
+}
+
+impl<K: PartialEq> BakedBuffers<K> {
+    pub fn new(device: &ash::Device, queue_family: u32, image_count: usize) -> Self {
+        let images = (0..image_count)
+            .map(|_| unsafe {
+                let pool = device
+                    .create_command_pool(
+                        &vk::CommandPoolCreateInfo::builder().queue_family_index(queue_family),
+                        None,
+                    )
+                    .expect("Baked command pool");
+                let buffer = device
+                    .allocate_command_buffers(
+                        &vk::CommandBufferAllocateInfo::builder()
+                            .command_pool(pool)
+                            .level(vk::CommandBufferLevel::SECONDARY)
+                            .command_buffer_count(1),
+                    )
+                    .expect("Baked command buffer")[0];
+                (pool, buffer, None)
+            })
+            .collect();
+
+        Self { images }
+    }
+
+    /// The image's buffer, and whether it has to be recorded because it was last recorded for another key, in
+    /// which case it has been reset. Must only be called once the image's previous commands have finished. Also
+    /// returns whichever key is no longer kept, the one given or the one it replaced, so that what it holds can
+    /// be reused for the next.
+    pub fn get(
+        &mut self,
+        device: &ash::Device,
+        image_index: usize,
+        key: K,
+    ) -> (vk::CommandBuffer, bool, Option<K>) {
+        let (pool, buffer, recorded) = &mut self.images[image_index];
+        if recorded.as_ref() == Some(&key) {
+            return (*buffer, false, Some(key));
+        }
+        unsafe {
+            device
+                .reset_command_pool(*pool, vk::CommandPoolResetFlags::empty())
+                .expect("Resetting baked command pool")
+        };
+        let replaced = recorded.replace(key);
+        (*buffer, true, replaced)
+    }
+
+    /// Makes every buffer be recorded again, for when something they use has changed in a way their keys don't
+    /// show, such as the descriptor sets they bind being updated
+    pub fn invalidate(&mut self) {
+        for (_, _, recorded) in self.images.iter_mut() {
+            *recorded = None;
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        for (pool, _, _) in self.images.drain(..) {
+            unsafe { device.destroy_command_pool(pool, None) };
+        }
+    }
+}
+
+/// Begins recording a secondary command buffer that continues the first subpass of the render pass. The pipeline
+/// statistics are those counted by any query active in the primary command buffer it is executed from.
+pub fn begin_secondary(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    render_pass: vk::RenderPass,
+    framebuffer: vk::Framebuffer,
+    pipeline_statistics: vk::QueryPipelineStatisticFlags,
+) {
+    begin(
+        device,
+        command_buffer,
+        render_pass,
+        framebuffer,
+        pipeline_statistics,
+        vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+    );
+}
+
+/// Begins recording a secondary command buffer like `begin_secondary`, but one that can be submitted again in
+/// later frames
+pub fn begin_baked_secondary(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    render_pass: vk::RenderPass,
+    framebuffer: vk::Framebuffer,
+    pipeline_statistics: vk::QueryPipelineStatisticFlags,
+) {
+    begin(
+        device,
+        command_buffer,
+        render_pass,
+        framebuffer,
+        pipeline_statistics,
+        vk::CommandBufferUsageFlags::empty(),
+    );
+}
+
+fn begin(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    render_pass: vk::RenderPass,
+    framebuffer: vk::Framebuffer,
+    pipeline_statistics: vk::QueryPipelineStatisticFlags,
+    usage: vk::CommandBufferUsageFlags,
+) {
+    let inheritance = vk::CommandBufferInheritanceInfo::builder()
+        .render_pass(render_pass)
+        .subpass(0)
+        .framebuffer(framebuffer)
+        .pipeline_statistics(pipeline_statistics);
+    unsafe {
+        device
+            .begin_command_buffer(
+                command_buffer,
+                &vk::CommandBufferBeginInfo::builder()
+                    .flags(usage | vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE)
+                    .inheritance_info(&inheritance),
+            )
+            .expect("Recording secondary command buffer")
+    };
+}
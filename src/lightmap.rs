@@ -0,0 +1,382 @@
+use std::f32::consts::PI;
+
+use cgmath::{
+    EuclideanSpace, InnerSpace, Matrix, Matrix3, Point3, SquareMatrix, Transform, Vector3,
+};
+use rayon::prelude::*;
+
+use crate::{batch, images, raycast, sky, util};
+
+/// Baked with the renderer's fixed light, so lightmapped surfaces lit by nothing but the light match those that
+/// aren't lightmapped while the scene has no time of day
+const AMBIENT: f32 = sky::FIXED_AMBIENT;
+/// How much light surfaces reflect onto each other. Baking doesn't know the surfaces' colours, so every surface
+/// reflects the same grey.
+const BOUNCE_ALBEDO: f32 = 0.5;
+/// Rays start this far off the surface so they don't hit the triangle they leave from
+pub const RAY_OFFSET: f32 = 1e-3;
+/// Texels left around each chart so filtering doesn't blend in its neighbours
+const CHART_PADDING: f32 = 1.5;
+
+/// How a lightmap is baked.
+#[derive(Clone, Copy, Debug)]
+pub struct Settings {
+    /// Width and height of the lightmap in texels
+    pub size: u32,
+    /// Rays cast over the hemisphere above each texel for indirect lighting
+    pub samples: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            size: 512,
+            samples: 64,
+        }
+    }
+}
+
+/// Gives every triangle of the meshes its own square of the lightmap, so that triangles never overlap and
+/// their lighting doesn't bleed between them. Writes the lightmap coordinates into the meshes, splitting
+/// their vertices so that no two triangles share one. Fails if the lightmap is too small for every triangle
+/// to have a few texels.
+pub fn generate_coords(meshes: &mut [&mut batch::StaticMesh], size: u32) -> Result<(), String> {
+    let triangle_count: usize = meshes
+        .iter()
+        .map(|static_mesh| static_mesh.mesh.indices.len() / 3)
+        .sum();
+    let columns = (triangle_count as f32).sqrt().ceil() as u32;
+    let chart_size = size / columns.max(1);
+    if chart_size < 4 {
+        return Err(format!(
+            "A {} texel lightmap is too small for {} triangles",
+            size, triangle_count
+        ));
+    }
+
+    let mut triangle = 0;
+    for static_mesh in meshes.iter_mut() {
+        let source = &static_mesh.mesh;
+        let corner = |index: usize| source.indices[index] as usize;
+        let split = |attribute: &Vec<[f32; 3]>| -> Vec<[f32; 3]> {
+            (0..source.indices.len())
+                .map(|index| attribute[corner(index)])
+                .collect()
+        };
+        let positions = split(&source.positions);
+        let normals = split(&source.normals);
+        let colors = source.colors.as_ref().map(split);
+        let tex_coords = source.tex_coords.as_ref().map(|tex_coords| {
+            (0..source.indices.len())
+                .map(|index| tex_coords[corner(index)])
+                .collect()
+        });
+        let occlusion = source.occlusion.as_ref().map(|occlusion| {
+            (0..source.indices.len())
+                .map(|index| occlusion[corner(index)])
+                .collect()
+        });
+        if positions.len() > u16::MAX as usize + 1 {
+            return Err(String::from(
+                "A lightmapped mesh has too many triangles for u16 indices",
+            ));
+        }
+
+        let mut lightmap_coords = Vec::with_capacity(positions.len());
+        for _ in 0..source.indices.len() / 3 {
+            lightmap_coords.extend(
+                chart_corners(triangle, columns, chart_size)
+                    .iter()
+                    .map(|&[x, y]| [x / size as f32, y / size as f32]),
+            );
+            triangle += 1;
+        }
+
+        let mesh = &mut static_mesh.mesh;
+        mesh.indices = (0..positions.len() as u32)
+            .map(|index| index as u16)
+            .collect();
+        mesh.positions = positions;
+        mesh.normals = normals;
+        mesh.colors = colors;
+        mesh.tex_coords = tex_coords;
+        mesh.occlusion = occlusion;
+        mesh.lightmap_coords = Some(lightmap_coords);
+    }
+
+    Ok(())
+}
+
+/// Where the corners of the `triangle`th triangle given coordinates by `generate_coords` are in the lightmap, in
+/// texels. Each triangle covers half of a square `size` texels wide, inset by the padding.
+fn chart_corners(triangle: usize, columns: u32, size: u32) -> [[f32; 2]; 3] {
+    let x = ((triangle as u32 % columns) * size) as f32;
+    let y = ((triangle as u32 / columns) * size) as f32;
+    let (low, high) = (CHART_PADDING, size as f32 - CHART_PADDING);
+    [[x + low, y + low], [x + high, y + low], [x + low, y + high]]
+}
+
+/// A static mesh's vertices placed in the scene
+pub struct Placed {
+    pub positions: Vec<Point3<f32>>,
+    pub normals: Vec<Vector3<f32>>,
+}
+
+impl Placed {
+    pub fn new(static_mesh: &batch::StaticMesh) -> Self {
+        let transform = static_mesh.transform;
+        let rotation = Matrix3::from_cols(
+            transform.x.truncate(),
+            transform.y.truncate(),
+            transform.z.truncate(),
+        );
+        let normal_transform = rotation
+            .invert()
+            .expect("Static mesh transform is invertible")
+            .transpose();
+        let positions: Vec<Point3<f32>> = static_mesh
+            .mesh
+            .positions
+            .iter()
+            .map(|&position| transform.transform_point(Point3::from(position)))
+            .collect();
+        let normals = static_mesh
+            .mesh
+            .normals
+            .iter()
+            .map(|&normal| (normal_transform * Vector3::from(normal)).normalize())
+            .collect();
+
+        Self { positions, normals }
+    }
+
+    fn collider(&self, static_mesh: &batch::StaticMesh) -> Option<raycast::Bvh> {
+        raycast::Bvh::new(&self.positions, &static_mesh.mesh.indices)
+    }
+}
+
+/// The surfaces light is traced against
+pub struct Scene {
+    colliders: Vec<raycast::Bvh>,
+    light_direction: Vector3<f32>,
+}
+
+impl Scene {
+    /// The placed meshes, which are `meshes` placed by `Placed::new`, and the occluders
+    pub fn new(
+        placed: &[Placed],
+        meshes: &[batch::StaticMesh],
+        occluders: &[batch::StaticMesh],
+    ) -> Self {
+        let colliders = placed
+            .iter()
+            .zip(meshes)
+            .filter_map(|(mesh, static_mesh)| mesh.collider(static_mesh))
+            .chain(
+                occluders
+                    .iter()
+                    .filter_map(|static_mesh| Placed::new(static_mesh).collider(static_mesh)),
+            )
+            .collect();
+        Self {
+            colliders,
+            light_direction: Vector3::from(sky::FIXED_DIRECTION).normalize(),
+        }
+    }
+
+    /// The nearest surface the ray hits
+    pub fn hit(&self, ray: &raycast::Ray) -> Option<raycast::Hit> {
+        self.colliders
+            .iter()
+            .filter_map(|collider| collider.raycast(ray))
+            .min_by(|a, b| {
+                a.distance
+                    .partial_cmp(&b.distance)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+    }
+
+    /// Light arriving straight from the light, unless something is in the way
+    fn direct(&self, point: Point3<f32>, normal: Vector3<f32>) -> f32 {
+        let diffuse = normal.dot(self.light_direction);
+        if diffuse <= 0.0 {
+            return 0.0;
+        }
+        let shadow_ray = raycast::Ray::new(point + normal * RAY_OFFSET, self.light_direction);
+        match self.hit(&shadow_ray) {
+            Some(_) => 0.0,
+            None => diffuse,
+        }
+    }
+
+    /// All the light arriving at a point: the direct light, the ambient light from the parts of the sky that
+    /// can be seen and the direct light reflected once off the surfaces that can be seen.
+    fn irradiance(&self, point: Point3<f32>, normal: Vector3<f32>, samples: u32, seed: u32) -> f32 {
+        let origin = point + normal * RAY_OFFSET;
+
+        let mut sky = 0.0;
+        let mut bounce = 0.0;
+        for direction in cosine_directions(normal, samples, seed) {
+            match self.hit(&raycast::Ray::new(origin, direction)) {
+                None => sky += 1.0,
+                Some(hit) => {
+                    let hit_normal = if hit.normal.dot(direction) > 0.0 {
+                        -hit.normal
+                    } else {
+                        hit.normal
+                    };
+                    bounce += BOUNCE_ALBEDO * self.direct(hit.point, hit_normal);
+                }
+            }
+        }
+        let samples = samples.max(1) as f32;
+
+        AMBIENT * sky / samples + (1.0 - AMBIENT) * (self.direct(point, normal) + bounce / samples)
+    }
+}
+
+/// Bakes the light falling on the meshes into their charts in `lightmap`, which is `Settings::size` texels
+/// square. The meshes must have had coordinates generated by `generate_coords` with the same size, and light is
+/// traced against them and `occluders`. Baking is done on the CPU across every core, and can take a while.
+pub fn bake(
+    lightmap: &mut images::Image,
+    meshes: &[batch::StaticMesh],
+    occluders: &[batch::StaticMesh],
+    settings: &Settings,
+) {
+    let placed: Vec<Placed> = meshes.iter().map(Placed::new).collect();
+    let scene = Scene::new(&placed, meshes, occluders);
+
+    let size = settings.size;
+    let mut triangles = Vec::new();
+    for (mesh, static_mesh) in placed.iter().zip(meshes) {
+        let coords = static_mesh
+            .mesh
+            .lightmap_coords
+            .as_ref()
+            .expect("Lightmap coordinates generated before baking");
+        for corner in (0..coords.len()).step_by(3) {
+            triangles.push((mesh, corner, coords));
+        }
+    }
+
+    let texels: Vec<(u32, u32, f32)> = triangles
+        .par_iter()
+        .enumerate()
+        .flat_map_iter(|(triangle, &(mesh, corner, coords))| {
+            let texel_corners = [0, 1, 2].map(|i| {
+                let [u, v] = coords[corner + i];
+                [u * size as f32, v * size as f32]
+            });
+            // The chart's square is found from where `chart_corners` put the triangle's corners in it
+            let chart_size =
+                (texel_corners[1][0] - texel_corners[0][0] + 2.0 * CHART_PADDING).round() as u32;
+            let origin = [
+                (texel_corners[0][0] - CHART_PADDING).round() as u32,
+                (texel_corners[0][1] - CHART_PADDING).round() as u32,
+            ];
+            let scene = &scene;
+            (0..chart_size * chart_size).map(move |texel| {
+                let (x, y) = (
+                    origin[0] + texel % chart_size,
+                    origin[1] + texel / chart_size,
+                );
+                // Texels outside the triangle take the lighting of the nearest point on it, filling the padding
+                // so the chart's edges don't filter in black
+                let weights = barycentric([x as f32 + 0.5, y as f32 + 0.5], texel_corners);
+                let point = Point3::from_vec(
+                    (0..3)
+                        .map(|i| mesh.positions[corner + i].to_vec() * weights[i])
+                        .sum(),
+                );
+                let normal = (0..3)
+                    .map(|i| mesh.normals[corner + i] * weights[i])
+                    .sum::<Vector3<f32>>()
+                    .normalize();
+                let seed = (triangle as u32).wrapping_mul(7919) ^ texel;
+                (
+                    x,
+                    y,
+                    scene.irradiance(point, normal, settings.samples, seed),
+                )
+            })
+        })
+        .collect();
+
+    for (x, y, irradiance) in texels {
+        if x < size && y < size {
+            let encoded = (util::encode_srgb(irradiance.min(1.0)) * 255.0).round() as u8;
+            lightmap.put_pixel(x, y, [encoded, encoded, encoded, 255]);
+        }
+    }
+}
+
+/// Barycentric weights of the point in the triangle, clamped to the triangle's edges for points outside it.
+fn barycentric(point: [f32; 2], corners: [[f32; 2]; 3]) -> [f32; 3] {
+    let [a, b, c] = corners;
+    let v0 = [b[0] - a[0], b[1] - a[1]];
+    let v1 = [c[0] - a[0], c[1] - a[1]];
+    let v2 = [point[0] - a[0], point[1] - a[1]];
+    let denominator = v0[0] * v1[1] - v1[0] * v0[1];
+    let v = (v2[0] * v1[1] - v1[0] * v2[1]) / denominator;
+    let w = (v0[0] * v2[1] - v2[0] * v0[1]) / denominator;
+
+    let (v, w) = (v.max(0.0), w.max(0.0));
+    let total = v + w;
+    let (v, w) = if total > 1.0 {
+        (v / total, w / total)
+    } else {
+        (v, w)
+    };
+    [1.0 - v - w, v, w]
+}
+
+/// Directions over the hemisphere above the normal, cosine weighted so that each counts equally towards the light
+/// arriving from them. Each seed turns the directions differently, so neighbouring points don't all sample the
+/// same ones.
+pub fn cosine_directions(
+    normal: Vector3<f32>,
+    samples: u32,
+    seed: u32,
+) -> impl Iterator<Item = Vector3<f32>> {
+    let (tangent, bitangent) = tangent_frame(normal);
+    let rotation = [random(seed), random(seed.wrapping_add(0x9e37_79b9))];
+    (0..samples).map(move |sample| {
+        let [u, v] = hammersley(sample, samples, rotation);
+        let radius = u.sqrt();
+        let angle = 2.0 * PI * v;
+        tangent * (radius * angle.cos())
+            + bitangent * (radius * angle.sin())
+            + normal * (1.0 - u).max(0.0).sqrt()
+    })
+}
+
+/// Two unit vectors perpendicular to the normal and each other
+fn tangent_frame(normal: Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let helper = if normal.x.abs() < 0.9 {
+        Vector3::unit_x()
+    } else {
+        Vector3::unit_y()
+    };
+    let tangent = helper.cross(normal).normalize();
+    (tangent, normal.cross(tangent))
+}
+
+/// The `index`th of `count` points evenly spread over the unit square, offset by `rotation` so neighbouring
+/// texels don't sample the same directions.
+fn hammersley(index: u32, count: u32, rotation: [f32; 2]) -> [f32; 2] {
+    let u = (index as f32 + 0.5) / count.max(1) as f32;
+    let v = index.reverse_bits() as f32 / 4_294_967_296.0;
+    [(u + rotation[0]).fract(), (v + rotation[1]).fract()]
+}
+
+/// A number in [0, 1) that looks random for each seed
+pub fn random(seed: u32) -> f32 {
+    let mut x = seed;
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x as f32 / 4_294_967_296.0
+}
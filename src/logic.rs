@@ -0,0 +1,51 @@
+use std::ffi::c_void;
+
+/// Changed whenever `Frame` or the functions a logic library exports change, so that a library built against
+/// another version is refused rather than misread
+pub const VERSION: u32 = 1;
+
+/// `extern "C" fn logic_version() -> u32`, returning the `VERSION` the library was built against
+pub const VERSION_SYMBOL: &[u8] = b"logic_version\0";
+/// `unsafe extern "C" fn logic_load(saved: *const u8, len: usize) -> *mut c_void`, creating the library's state
+/// from the bytes its previous version saved, which are empty the first time it is loaded
+pub const LOAD_SYMBOL: &[u8] = b"logic_load\0";
+/// `unsafe extern "C" fn logic_update(state: *mut c_void, frame: *mut Frame)`, called once a frame
+pub const UPDATE_SYMBOL: &[u8] = b"logic_update\0";
+/// `unsafe extern "C" fn logic_save(state: *mut c_void) -> Saved`, dropping the state and returning what the
+/// next version of the library needs to carry on from it
+pub const SAVE_SYMBOL: &[u8] = b"logic_save\0";
+/// `unsafe extern "C" fn logic_free(saved: Saved)`, freeing what `logic_save` returned
+pub const FREE_SYMBOL: &[u8] = b"logic_free\0";
+
+pub type VersionFn = extern "C" fn() -> u32;
+pub type LoadFn = unsafe extern "C" fn(*const u8, usize) -> *mut c_void;
+pub type UpdateFn = unsafe extern "C" fn(*mut c_void, *mut Frame);
+pub type SaveFn = unsafe extern "C" fn(*mut c_void) -> Saved;
+pub type FreeFn = unsafe extern "C" fn(Saved);
+
+/// What a logic library sees of the renderer each frame. Only the transforms and the scene are read back.
+#[repr(C)]
+pub struct Frame {
+    /// Seconds since the renderer started, or into the timeline while one plays
+    pub time: f32,
+    /// Seconds since the last frame
+    pub delta: f32,
+    /// Each draw's transform by its index, in column major order, `draw_count` of them
+    pub transforms: *mut [[f32; 4]; 4],
+    pub draw_count: usize,
+    /// The scene drawn. Setting it to another scene below `scene_count` switches to that scene.
+    pub scene: usize,
+    pub scene_count: usize,
+    /// Keys pressed since the last frame as winit's `VirtualKeyCode`s cast to `u32`, `key_count` of them
+    pub keys: *const u32,
+    pub key_count: usize,
+}
+
+/// Bytes allocated by a logic library, handed back to it to be freed. Libraries written in Rust build it from a
+/// `Vec<u8>` they forget and rebuild the `Vec` to free it.
+#[repr(C)]
+pub struct Saved {
+    pub data: *mut u8,
+    pub len: usize,
+    pub capacity: usize,
+}
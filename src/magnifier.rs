@@ -0,0 +1,308 @@
+use std::any::Any;
+
+use ash::vk;
+
+use crate::features::{RenderFeature, SwapchainContext};
+use crate::{framegraph, hooks, postprocess, HelloTriangleApplication, ImageDescription};
+
+/// Width and height of the inset in logical pixels
+const INSET_SIZE: u32 = 256;
+/// Gap between the inset and the bottom right corner of the frame, in logical pixels
+const MARGIN: u32 = 16;
+/// How many times larger pixels can be shown, each showing a region a fraction of the inset's size across
+const ZOOMS: [u32; 4] = [2, 4, 8, 16];
+
+/// A debug lens showing the final frame around the cursor magnified in the bottom right corner, after
+/// everything else has drawn. Each pixel of the region around the cursor fills a square of the inset, as
+/// nearest filtering keeps pixels sharp, so edges, aliasing and other artifacts can be seen pixel by pixel.
+///
+/// The region is copied out of the swapchain image before it is scaled up into the inset, so the lens works
+/// even when the cursor is over the inset. It needs swapchain images that can be copied from and to, and shows
+/// nothing otherwise.
+pub struct Magnifier {
+    pub enabled: bool,
+    /// The pixel to magnify around, from the top left of the window
+    pub cursor: Option<(u32, u32)>,
+    /// Index into `ZOOMS`
+    zoom: usize,
+    /// Holds the region around the cursor, big enough for the lowest zoom
+    region: Option<(vk::Image, vk::DeviceMemory)>,
+    extent: vk::Extent2D,
+    /// `INSET_SIZE` and `MARGIN` in physical pixels, the inset kept a multiple of every zoom
+    inset_size: u32,
+    margin: u32,
+}
+
+impl Default for Magnifier {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cursor: None,
+            zoom: 2,
+            region: None,
+            extent: vk::Extent2D::default(),
+            inset_size: INSET_SIZE,
+            margin: MARGIN,
+        }
+    }
+}
+
+impl Magnifier {
+    /// Switches to the next zoom, back to the lowest after the highest. Returns the new zoom.
+    pub fn cycle_zoom(&mut self) -> u32 {
+        self.zoom = (self.zoom + 1) % ZOOMS.len();
+        ZOOMS[self.zoom]
+    }
+
+    /// The region around the cursor that the inset shows, as its top left pixel and size, kept inside the
+    /// frame. `None` when the frame is too small for the inset.
+    fn region(&self) -> Option<((u32, u32), u32)> {
+        let cursor = self.cursor?;
+        let extent = self.extent;
+        let (inset_size, margin) = (self.inset_size, self.margin);
+        if inset_size + margin > extent.width || inset_size + margin > extent.height {
+            return None;
+        }
+        let size = inset_size / ZOOMS[self.zoom];
+        let corner = |cursor: u32, limit: u32| cursor.saturating_sub(size / 2).min(limit - size);
+        Some((
+            (
+                corner(cursor.0, extent.width),
+                corner(cursor.1, extent.height),
+            ),
+            size,
+        ))
+    }
+
+    /// Records copying the region out of the swapchain image and scaling it up into the inset. The swapchain
+    /// image is ready to present again afterwards.
+    fn record_lens(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        swapchain_image: vk::Image,
+        region_image: vk::Image,
+        (corner, size): ((u32, u32), u32),
+    ) {
+        let image_barrier = |image: vk::Image,
+                             old: vk::ImageLayout,
+                             new: vk::ImageLayout,
+                             src_access: vk::AccessFlags,
+                             dst_access: vk::AccessFlags| {
+            vk::ImageMemoryBarrier::builder()
+                .old_layout(old)
+                .new_layout(new)
+                .src_access_mask(src_access)
+                .dst_access_mask(dst_access)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(postprocess::color_subresource_range())
+                .build()
+        };
+        let copy = vk::ImageCopy::builder()
+            .src_subresource(postprocess::color_subresource_layers())
+            .src_offset(vk::Offset3D {
+                x: corner.0 as i32,
+                y: corner.1 as i32,
+                z: 0,
+            })
+            .dst_subresource(postprocess::color_subresource_layers())
+            .dst_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+            .extent(vk::Extent3D {
+                width: size,
+                height: size,
+                depth: 1,
+            })
+            .build();
+        let inset = (
+            (self.extent.width - self.margin - self.inset_size) as i32,
+            (self.extent.height - self.margin - self.inset_size) as i32,
+        );
+        let blit = vk::ImageBlit::builder()
+            .src_subresource(postprocess::color_subresource_layers())
+            .src_offsets([
+                vk::Offset3D { x: 0, y: 0, z: 0 },
+                vk::Offset3D {
+                    x: size as i32,
+                    y: size as i32,
+                    z: 1,
+                },
+            ])
+            .dst_subresource(postprocess::color_subresource_layers())
+            .dst_offsets([
+                vk::Offset3D {
+                    x: inset.0,
+                    y: inset.1,
+                    z: 0,
+                },
+                vk::Offset3D {
+                    x: inset.0 + self.inset_size as i32,
+                    y: inset.1 + self.inset_size as i32,
+                    z: 1,
+                },
+            ])
+            .build();
+
+        unsafe {
+            // The previous frame's lens may still be reading the region, which the copy overwrites
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::COMPUTE_SHADER
+                    | vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[
+                    image_barrier(
+                        swapchain_image,
+                        vk::ImageLayout::PRESENT_SRC_KHR,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::TRANSFER_WRITE,
+                        vk::AccessFlags::TRANSFER_READ,
+                    ),
+                    image_barrier(
+                        region_image,
+                        vk::ImageLayout::UNDEFINED,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::AccessFlags::empty(),
+                        vk::AccessFlags::TRANSFER_WRITE,
+                    ),
+                ],
+            );
+            device.cmd_copy_image(
+                command_buffer,
+                swapchain_image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                region_image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[copy],
+            );
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[
+                    image_barrier(
+                        region_image,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        vk::AccessFlags::TRANSFER_WRITE,
+                        vk::AccessFlags::TRANSFER_READ,
+                    ),
+                    image_barrier(
+                        swapchain_image,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::AccessFlags::TRANSFER_READ,
+                        vk::AccessFlags::TRANSFER_WRITE,
+                    ),
+                ],
+            );
+            device.cmd_blit_image(
+                command_buffer,
+                region_image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                swapchain_image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[blit],
+                vk::Filter::NEAREST,
+            );
+            // Presentation waits on the frame's semaphore, so no destination access is needed
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[image_barrier(
+                    swapchain_image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::PRESENT_SRC_KHR,
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    vk::AccessFlags::empty(),
+                )],
+            );
+        }
+    }
+}
+
+impl RenderFeature for Magnifier {
+    fn name(&self) -> &str {
+        "magnifier"
+    }
+
+    fn init(&mut self, context: &SwapchainContext) {
+        self.extent = context.extent;
+        let highest = ZOOMS[ZOOMS.len() - 1];
+        self.inset_size = (context.scaled(INSET_SIZE) / highest).max(1) * highest;
+        self.margin = context.scaled(MARGIN);
+        if !context.usage.contains(postprocess::SWAPCHAIN_USAGE) {
+            return;
+        }
+        let size = self.inset_size / ZOOMS[0];
+        self.region = Some(HelloTriangleApplication::create_image(
+            context.device,
+            &ImageDescription {
+                width: size,
+                height: size,
+                format: context.format,
+                tiling: vk::ImageTiling::OPTIMAL,
+                usage: vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST,
+                memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            },
+            context.device_memory_properties,
+        ));
+    }
+
+    fn resize(&mut self, context: &SwapchainContext) {
+        self.destroy(context.device);
+        self.init(context);
+    }
+
+    fn record(&mut self, context: &hooks::FrameContext) {
+        if context.stage != hooks::Stage::AfterUi || !self.enabled {
+            return;
+        }
+        if let (Some((image, _)), Some(region)) = (self.region, self.region()) {
+            self.record_lens(
+                context.device,
+                context.command_buffer,
+                context.swapchain_image,
+                image,
+                region,
+            );
+        }
+    }
+
+    fn passes(&self) -> Vec<framegraph::Pass> {
+        if !self.enabled || self.region.is_none() || self.region().is_none() {
+            return Vec::new();
+        }
+        vec![framegraph::Pass::new(
+            self.name(),
+            framegraph::Position::Stage(hooks::Stage::AfterUi),
+            &[framegraph::SWAPCHAIN],
+            &[framegraph::SWAPCHAIN],
+        )]
+    }
+
+    fn destroy(&mut self, device: &ash::Device) {
+        if let Some((image, memory)) = self.region.take() {
+            unsafe {
+                device.destroy_image(image, None);
+                device.free_memory(memory, None);
+            }
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
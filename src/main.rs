@@ -1,30 +1,153 @@
-use cgmath::{Deg, Euler, Matrix4, Point3, Rad, Vector3};
+use cgmath::{
+    Angle, Deg, ElementWise, EuclideanSpace, Euler, InnerSpace, Matrix, Matrix4, Point3, Rad,
+    SquareMatrix, Transform, Vector3,
+};
 use core::panic;
 use memoffset::offset_of;
 use num::{self, range};
+use std::cmp::Ordering;
 use std::convert::TryInto;
 use std::ffi::{c_void, CStr, CString};
+use std::fs;
 use std::mem::{self, size_of};
 use std::ops::{BitAndAssign, BitOr, BitOrAssign, Deref, Not};
 use std::os::raw::c_char;
-use std::path::Path;
-use std::time::Instant;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rayon::prelude::*;
+use std::time::{Duration, Instant};
+mod accumulation;
+mod assets;
+mod atlas;
+mod audio;
+mod batch;
+mod billboard;
+mod bounds;
+mod budget;
+mod camera;
+mod capsules;
+mod capture;
+mod clipping;
+mod cloth;
+mod conditional;
+mod console;
+mod culling;
 mod debug;
+mod depth;
+mod draw;
+mod events;
+mod features;
+mod flare;
+mod flythrough;
+mod fog;
+mod font;
+mod formats;
+mod framegraph;
+#[cfg(feature = "gamepad")]
+mod gamepad;
+mod ground_shadows;
+mod history;
+mod hiz;
+mod hooks;
+mod host_memory;
+#[cfg(feature = "hot-reload")]
+mod hot_reload;
+mod images;
+mod immediate;
+mod indirect;
+mod info;
+mod inspector;
 mod instance;
+mod jobs;
+mod lightmap;
+#[cfg(feature = "hot-reload")]
+mod logic;
+mod magnifier;
+mod material;
+mod measure;
+mod memory;
+mod mesh;
+mod minimap;
+mod noise;
+mod occluders;
+mod occlusion;
+mod ownership;
+mod particles;
+#[cfg(feature = "physics")]
+mod physics;
+mod pipeline;
+mod playground;
+mod pointcloud;
+mod portals;
+mod postprocess;
+mod prerotation;
+mod present_thread;
+mod probes;
+mod quality;
+mod queue_batches;
+mod raycast;
+mod readback;
+mod remote;
+mod render_thread;
+mod residency;
+mod schedule;
+mod scope;
+mod screenshot;
+#[cfg(feature = "scripting")]
+mod scripting;
+mod sdf;
+mod shader_materials;
+mod shadow_map;
+mod shadows;
+mod sky;
+mod snapshot;
+mod spirv;
+mod statistics;
+mod stereo;
+mod streaming;
+mod submission;
+mod surface;
+mod swarm;
+mod thumbnails;
+mod timeline;
+mod toon;
+mod trails;
+mod transition;
+mod uniforms;
+mod units;
 mod util;
+mod validation;
+mod vat;
+#[cfg(feature = "vector")]
+mod vector;
+mod visibility;
+mod volume;
 
 use ash::extensions::khr::{Surface, Win32Surface};
 use ash::vk::{self, DeviceQueueCreateInfo, MemoryMapFlags};
-use winit::event::{Event, WindowEvent};
+use uniforms::UniformBufferObject;
+use winit::dpi::PhysicalPosition;
+use winit::event::{Event, ModifiersState, VirtualKeyCode, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 
 const APP_TITLE: &str = "Rust Renderer VK";
+const SCENE_TEXTURE: &str = "src/textures/texture.jpg";
 const WINDOW_WIDTH: u32 = 800;
 const WINDOW_HEIGHT: u32 = 600;
 
 const VALIDATION_LAYERS: [&str; 1] = ["VK_LAYER_KHRONOS_validation"];
 const MAX_FRAMES_IN_FLIGHT: usize = 2;
 
+// Swapchain formats in order of preference. sRGB formats come first so the hardware encodes our linear output,
+// UNORM formats are only used when the surface offers no sRGB format and the shaders then do the encoding.
+const PREFERRED_SURFACE_FORMATS: [vk::Format; 4] = [
+    vk::Format::B8G8R8A8_SRGB,
+    vk::Format::R8G8B8A8_SRGB,
+    vk::Format::B8G8R8A8_UNORM,
+    vk::Format::R8G8B8A8_UNORM,
+];
+
 // Debug utils callback
 unsafe extern "system" fn vulkan_debug_utils_callback(
     message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
@@ -46,25 +169,39 @@ unsafe extern "system" fn vulkan_debug_utils_callback(
         _ => "???",
     };
 
-    let message = CStr::from_ptr((*p_callback_data).p_message);
-    eprintln!("[VK DEBUG][{}][{}]: {:?}", severity, kind, message);
+    let data = &*p_callback_data;
+    let message = util::utf8_lossless(CStr::from_ptr(data.p_message).to_bytes());
+    eprintln!("[VK DEBUG][{}][{}]: {}", severity, kind, message);
+    validation::record(
+        message_severity,
+        &format!("[{}][{}]: {}", severity, kind, message),
+    );
+    // The objects the message is about that have been named, see `debug::Names`
+    let named: Vec<String> = (0..data.object_count as usize)
+        .map(|i| &*data.p_objects.add(i))
+        .filter(|object| !object.p_object_name.is_null())
+        .map(|object| {
+            let name = util::utf8_lossless(CStr::from_ptr(object.p_object_name).to_bytes());
+            format!("{:?} \"{}\"", object.object_type, name)
+        })
+        .collect();
+    if !named.is_empty() {
+        eprintln!("    named objects: {}", named.join(", "));
+    }
 
     // Return false to indicate that validation should not cause a crash
     vk::FALSE
 }
 
+/// A vertex with every attribute. `repr(C)` keeps its layout the same as
+/// `mesh::VertexAttributes::ALL`.
 #[repr(C)]
-#[derive(Clone, Debug, Copy)]
-struct UniformBufferObject {
-    model: Matrix4<f32>,
-    view: Matrix4<f32>,
-    perspective: Matrix4<f32>,
-}
-
+#[derive(Clone, Copy)]
 struct Vertex {
     pos: [f32; 3],
     color: [f32; 3],
     tex_coord: [f32; 2],
+    normal: [f32; 3],
 }
 
 impl Vertex {
@@ -76,7 +213,7 @@ impl Vertex {
             .build()
     }
 
-    fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescription; 3] {
+    fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescription; 4] {
         let position_binding = vk::VertexInputAttributeDescription::builder()
             .binding(0)
             .location(0)
@@ -95,8 +232,19 @@ impl Vertex {
             .format(vk::Format::R32G32_SFLOAT)
             .offset(offset_of!(Self, tex_coord) as u32)
             .build();
+        let normal_binding = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(3)
+            .format(vk::Format::R32G32B32_SFLOAT)
+            .offset(offset_of!(Self, normal) as u32)
+            .build();
 
-        [position_binding, color_binding, tex_coord_binding]
+        [
+            position_binding,
+            color_binding,
+            tex_coord_binding,
+            normal_binding,
+        ]
     }
 }
 
@@ -106,42 +254,50 @@ const QUAD_VERTICES: [Vertex; 8] = [
         pos: [-0.5, -0.5, 0.0],
         color: [1.0, 0.0, 0.0],
         tex_coord: [1.0, 0.0],
+        normal: [0.0, 0.0, 1.0],
     },
     Vertex {
         pos: [0.5, -0.5, 0.0],
         color: [0.0, 1.0, 0.0],
         tex_coord: [0.0, 0.0],
+        normal: [0.0, 0.0, 1.0],
     },
     Vertex {
         pos: [0.5, 0.5, 0.0],
         color: [0.0, 0.0, 1.0],
         tex_coord: [0.0, 1.0],
+        normal: [0.0, 0.0, 1.0],
     },
     Vertex {
         pos: [-0.5, 0.5, 0.0],
         color: [1.0, 1.0, 1.0],
         tex_coord: [1.0, 1.0],
+        normal: [0.0, 0.0, 1.0],
     },
     // Second quad
     Vertex {
         pos: [-0.5, -0.5, -0.5],
         color: [1.0, 0.0, 0.0],
         tex_coord: [1.0, 0.0],
+        normal: [0.0, 0.0, 1.0],
     },
     Vertex {
         pos: [0.5, -0.5, -0.5],
         color: [0.0, 1.0, 0.0],
         tex_coord: [0.0, 0.0],
+        normal: [0.0, 0.0, 1.0],
     },
     Vertex {
         pos: [0.5, 0.5, -0.5],
         color: [0.0, 0.0, 1.0],
         tex_coord: [0.0, 1.0],
+        normal: [0.0, 0.0, 1.0],
     },
     Vertex {
         pos: [-0.5, 0.5, -0.5],
         color: [1.0, 1.0, 1.0],
         tex_coord: [1.0, 1.0],
+        normal: [0.0, 0.0, 1.0],
     },
 ];
 
@@ -150,9 +306,66 @@ const QUAD_INDICES: [u16; 12] = [
     4, 5, 6, 6, 7, 4, // Second Quad
 ];
 
+// X, Y and Z axes drawn as a line list off to the side of the quads
+const AXIS_VERTICES: [Vertex; 6] = [
+    Vertex {
+        pos: [-1.0, -1.0, 0.0],
+        color: [1.0, 0.0, 0.0],
+        tex_coord: [0.0, 0.0],
+        normal: [0.0, 0.0, 1.0],
+    },
+    Vertex {
+        pos: [-0.5, -1.0, 0.0],
+        color: [1.0, 0.0, 0.0],
+        tex_coord: [0.0, 0.0],
+        normal: [0.0, 0.0, 1.0],
+    },
+    Vertex {
+        pos: [-1.0, -1.0, 0.0],
+        color: [0.0, 1.0, 0.0],
+        tex_coord: [0.0, 0.0],
+        normal: [0.0, 0.0, 1.0],
+    },
+    Vertex {
+        pos: [-1.0, -0.5, 0.0],
+        color: [0.0, 1.0, 0.0],
+        tex_coord: [0.0, 0.0],
+        normal: [0.0, 0.0, 1.0],
+    },
+    Vertex {
+        pos: [-1.0, -1.0, 0.0],
+        color: [0.0, 0.0, 1.0],
+        tex_coord: [0.0, 0.0],
+        normal: [0.0, 0.0, 1.0],
+    },
+    Vertex {
+        pos: [-1.0, -1.0, 0.5],
+        color: [0.0, 0.0, 1.0],
+        tex_coord: [0.0, 0.0],
+        normal: [0.0, 0.0, 1.0],
+    },
+];
+
+const BILLBOARDS: [billboard::Billboard; 2] = [
+    // A sprite floating above the quads
+    billboard::Billboard::new(
+        [0.0, 0.0, 1.0],
+        [0.5, 0.5],
+        billboard::Orientation::Spherical,
+    ),
+    // An upright impostor beside the quads
+    billboard::Billboard::new(
+        [1.0, -1.0, 0.0],
+        [0.5, 1.0],
+        billboard::Orientation::AxisLocked([0.0, 0.0, 1.0]),
+    ),
+];
+
 struct QueueFamilyIndices {
     graphics_family: Option<u32>,
     present_family: Option<u32>,
+    /// A family that supports compute but not graphics, so compute work can run alongside rendering
+    compute_family: Option<u32>,
 }
 
 impl QueueFamilyIndices {
@@ -172,7 +385,105 @@ struct SwapChainData {
     swapchain: vk::SwapchainKHR,
     images: Vec<vk::Image>,
     format: vk::Format,
+    color_space: vk::ColorSpaceKHR,
+    present_mode: vk::PresentModeKHR,
     extent: vk::Extent2D,
+    usage: vk::ImageUsageFlags,
+    /// How the frame is rotated to match the surface, see `prerotation::choose_transform`
+    transform: vk::SurfaceTransformFlagsKHR,
+}
+
+/// What every secondary command buffer of the render pass sets before recording draws.
+#[derive(Clone, Copy, PartialEq)]
+struct SceneState {
+    /// The part of the frame the scene is drawn into
+    area: vk::Rect2D,
+    pipeline_layout: vk::PipelineLayout,
+    descriptor_set: vk::DescriptorSet,
+    default_attribute_buffer: vk::Buffer,
+}
+
+/// What an image's baked scene draws were recorded from, see `set_baked_draws`
+#[derive(PartialEq)]
+struct BakedScene {
+    state: SceneState,
+    pipeline_statistics: vk::QueryPipelineStatisticFlags,
+    draws: Vec<draw::Recorded>,
+}
+
+/// What recording a frame fills in, kept from one frame to the next so that recording doesn't allocate it every
+/// frame once the buffers have grown to fit the scene
+#[derive(Default)]
+struct FrameScratch {
+    culling: culling::Buffers,
+    visible: Vec<usize>,
+    /// The draws of the scene a transition is leaving
+    outgoing: Vec<usize>,
+    /// The draws the left eye sees, when drawing in stereo
+    left_visible: Vec<usize>,
+    /// The draws the minimap sees, when it's drawn in the frame
+    minimap_visible: Vec<usize>,
+    /// The draws the light sees, when there's a shadow map
+    shadow_visible: Vec<usize>,
+    /// The draws of those that cast shadows into the shadow map, see `draw::Draw::shadow_caster`
+    shadow_casters: Vec<draw::Draw>,
+    /// The draws each reflection probe's face drawn in the frame sees
+    probe_visible: Vec<Vec<usize>>,
+    /// The draws of a baked scene's key, reused from a key that is no longer needed
+    baked: Vec<draw::Recorded>,
+    /// The secondary command buffers the render pass executes
+    executed: Vec<vk::CommandBuffer>,
+    /// The draws of the ground shadows, see `HelloTriangleApplication::ground_shadow_draws`
+    ground_shadows: Vec<draw::Draw>,
+    /// The draws of the trails' ghosts, see `trails::Ghosts`
+    trails: Vec<draw::Draw>,
+}
+
+impl SceneState {
+    fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            let viewports = [vk::Viewport::builder()
+                .x(self.area.offset.x as f32)
+                .y(self.area.offset.y as f32)
+                .width(self.area.extent.width as f32)
+                .height(self.area.extent.height as f32)
+                .min_depth(0.0)
+                .max_depth(1.0)
+                .build()];
+            device.cmd_set_viewport(command_buffer, 0, &viewports);
+            let scissors = [self.area];
+            device.cmd_set_scissor(command_buffer, 0, &scissors);
+
+            // Every pipeline shares a layout so the descriptor sets stay bound between draws
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+
+            // Draws only bind their own vertex buffer, so the defaults for missing attributes stay bound
+            device.cmd_bind_vertex_buffers(
+                command_buffer,
+                mesh::DEFAULT_ATTRIBUTE_BINDING,
+                &[self.default_attribute_buffer],
+                &[0],
+            );
+        }
+    }
+}
+
+/// A 2D image with one mip level and one layer for `create_image` to make, and the memory it goes in
+#[derive(Clone, Copy)]
+struct ImageDescription {
+    width: u32,
+    height: u32,
+    format: vk::Format,
+    tiling: vk::ImageTiling,
+    usage: vk::ImageUsageFlags,
+    memory_properties: vk::MemoryPropertyFlags,
 }
 
 struct HelloTriangleApplication {
@@ -183,12 +494,18 @@ struct HelloTriangleApplication {
     surface: vk::SurfaceKHR,
     surface_loader: ash::extensions::khr::Surface,
     debug_config: Option<debug::Configuration>,
+    /// Names objects in validation messages, when the debug layers are enabled
+    names: Option<debug::Names>,
     physical_device: ash::vk::PhysicalDevice,
     physical_device_memory_properties: vk::PhysicalDeviceMemoryProperties,
     queue_families: QueueFamilyIndices,
     logical_device: ash::Device,
     graphics_queue: vk::Queue,
     present_queue: vk::Queue,
+    /// Hands each frame's swapchain image to the present family when it isn't the graphics family
+    present_acquire: Option<ownership::PresentAcquire>,
+    /// Presents the frames when presenting is moved off the render thread
+    present_thread: Option<present_thread::PresentThread>,
 
     swapchain_data: SwapChainData,
     swapchain_image_views: Vec<vk::ImageView>,
@@ -196,24 +513,49 @@ struct HelloTriangleApplication {
     descriptor_pool: vk::DescriptorPool,
     descriptor_sets: Vec<vk::DescriptorSet>,
     descriptor_set_layout: vk::DescriptorSetLayout,
+    /// Layout of the vertex animation textures' descriptor sets, bound as set 1
+    animation_set_layout: vk::DescriptorSetLayout,
 
     render_pass: vk::RenderPass,
-    pipeline_layout: vk::PipelineLayout,
-    graphics_pipeline: vk::Pipeline,
+    /// Clears what `render_pass` preserves, for the first frame drawn into each swapchain image
+    first_render_pass: Option<vk::RenderPass>,
+    /// Whether each swapchain image has been drawn into since the swapchain was made
+    images_drawn: Vec<bool>,
+    /// How each render target's passes load, store and clear it
+    passes: pipeline::PassConfigs,
+    pipelines: pipeline::Variants,
+    /// Every pipeline variant is created through it, so variants rebuilt with the swapchain compile quickly
+    pipeline_cache: vk::PipelineCache,
+    draws: Vec<draw::Draw>,
+    /// The scene whose draws are drawn, see `switch_scene`
+    scene: usize,
 
     swap_chain_frame_buffers: Vec<vk::Framebuffer>,
 
     command_pool: vk::CommandPool,
     command_buffers: Vec<vk::CommandBuffer>,
+    /// The render pass of each image, recorded in parallel
+    secondary_buffers: jobs::SecondaryBuffers,
+    /// The scene's draws for each image, kept between frames when baking them, see `set_baked_draws`
+    baked_draws: Option<jobs::BakedBuffers<BakedScene>>,
+    frame_scratch: FrameScratch,
+    /// How long preparing frames takes
+    timings: jobs::Timings,
 
     image_available_semaphores: Vec<vk::Semaphore>,
     render_complete_semaphores: Vec<vk::Semaphore>,
+    /// Chain the frame's submissions to its queues
+    submission_semaphores: submission::Semaphores,
+    /// Each frame in flight's batches, laid out from the frame graph with the swapchain, see `plan_submissions`
+    submissions: Vec<submission::Submission>,
     frame_fences: Vec<vk::Fence>,
     image_fences: Vec<vk::Fence>,
 
     current_frame: usize,
 
     frame_buffer_resized: bool,
+    /// The window's scale factor, see `features::SwapchainContext::scale_factor`
+    scale_factor: f32,
 
     vertex_buffer: vk::Buffer,
     vertex_buffer_memory: vk::DeviceMemory,
@@ -221,35 +563,218 @@ struct HelloTriangleApplication {
     index_buffer: vk::Buffer,
     index_buffer_memory: vk::DeviceMemory,
 
+    billboard_buffer: vk::Buffer,
+    billboard_buffer_memory: vk::DeviceMemory,
+
+    axis_buffer: vk::Buffer,
+    axis_buffer_memory: vk::DeviceMemory,
+
+    /// The disc blob ground shadows are drawn with, see `ground_shadows::disc`
+    disc_buffer: vk::Buffer,
+    disc_buffer_memory: vk::DeviceMemory,
+
+    floor_vertex_buffer: vk::Buffer,
+    floor_vertex_buffer_memory: vk::DeviceMemory,
+    floor_index_buffer: vk::Buffer,
+    floor_index_buffer_memory: vk::DeviceMemory,
+
+    default_attribute_buffer: vk::Buffer,
+    default_attribute_buffer_memory: vk::DeviceMemory,
+
+    /// Vertex and index buffers of the baked static geometry
+    static_buffers: Vec<(vk::Buffer, vk::DeviceMemory)>,
+    /// Vertex buffers of the point clouds added with `add_point_cloud`
+    point_cloud_buffers: Vec<(vk::Buffer, vk::DeviceMemory)>,
+    /// The cloth demo, simulated ahead of each frame's render pass
+    cloth: Option<cloth::Cloth>,
+    /// The swarm demo, simulated ahead of each frame's render pass
+    swarm: Option<swarm::Swarm>,
+    /// Draws played back from vertex animation textures, advanced each frame
+    animations: Vec<vat::Animated>,
+    animations_updated: Instant,
+
     uniform_buffers: Vec<vk::Buffer>,
     uniform_buffers_memory: Vec<vk::DeviceMemory>,
 
     start_time: Instant,
+    model: Matrix4<f32>,
+    /// Always `camera.view()`, kept up to date whenever the camera moves
+    view: Matrix4<f32>,
+    camera: camera::Camera,
+    /// Where the mouse wheel is moving the camera
+    dolly: camera::Dolly,
+    /// A grid and axis gizmo under the camera's target, toggled with G
+    grid: camera::Grid,
+    /// The world's up axis and unit, which the camera, the grid, measurements and imported assets follow
+    units: units::Convention,
+    /// Distances or angles between points clicked on while measuring, cycled with M
+    measurement: measure::Measurement,
+    measuring: bool,
+    /// The draw last picked, which the arrow keys, Page Up and Page Down move and B makes double-sided
+    picked: Option<usize>,
+    /// Edits to undo with Ctrl+Z and redo with Ctrl+Y
+    history: history::History,
+    /// Where the scene is saved on exit and with Ctrl+S
+    scene_file: Option<PathBuf>,
+    /// Where camera paths are recorded to with R and played back from with Shift+R
+    camera_path_file: Option<PathBuf>,
+    camera_recorder: Option<flythrough::Recorder>,
+    /// Moves the view along a camera path in place of the camera while it plays
+    camera_player: Option<flythrough::Player>,
+    /// Drives the camera, draws and settings from a timeline while it plays, in place of the clock
+    timeline: Option<timeline::Playback>,
+    /// Section planes cutting into the meshes, cycled with K and moved with [ and ]
+    clipping: clipping::Clipping,
+    /// Last known position of the mouse cursor over the window, used for picking
+    cursor_position: Option<PhysicalPosition<f64>>,
+
+    /// Drawn instead of the scene when running a sketch
+    playground: Option<playground::Playground>,
+    /// Objects whose draws are generated on the GPU, see `add_object_grid`
+    indirect_scene: Option<indirect::Scene>,
+    /// Whether the GPU culled objects are tested against a depth pyramid, see `set_hi_z_culling`
+    hi_z_culling: bool,
+    /// Counts of the work each pass does on the GPU, see `enable_pipeline_statistics`
+    statistics: Option<statistics::PipelineStatistics>,
+    /// Alerts when frames take longer than they should, see `set_frame_budget`
+    frame_budget: Option<budget::Monitor>,
+    /// Fountain simulated on the compute queue
+    particles: particles::Particles,
+    /// Index of the particles' draw in `draws`
+    particle_draw: usize,
+    /// Passes recorded into every frame, such as post processing
+    features: features::Features,
+
+    /// Host application callbacks recorded into each frame
+    hooks: hooks::Hooks,
+    /// Sends what happens inside the renderer to the host application
+    events: events::Bus,
+
+    /// Whether the pixel under the cursor is being read back, while alt is held
+    inspecting: bool,
+    modifiers: ModifiersState,
+
+    #[cfg(feature = "physics")]
+    physics: physics::Demo,
+    /// Audio input for the uniform buffer's audio bands, if an input device could be opened
+    #[cfg(feature = "audio")]
+    audio: Option<audio::Capture>,
+    /// Gamepad input for the camera and the console, if gilrs could be started
+    #[cfg(feature = "gamepad")]
+    gamepad: Option<gamepad::Gamepad>,
+    /// The scene script run each frame, and the input it hasn't seen yet
+    #[cfg(feature = "scripting")]
+    script: Option<scripting::Script>,
+    #[cfg(feature = "scripting")]
+    script_input: scripting::Input,
+    /// Frame update logic reloaded from a dynamic library, the keys pressed since it last ran, and when it did
+    #[cfg(feature = "hot-reload")]
+    logic: Option<(hot_reload::Logic, Vec<VirtualKeyCode>, f32)>,
+    /// Draws what an immediate mode update asks for each frame
+    immediate: Option<immediate::Immediate>,
+    /// Moves the sun over the day, and the clock time it was last moved on at
+    time_of_day: Option<(sky::TimeOfDay, f32)>,
+    /// The quality preset last applied, saved in scene files. None until one is.
+    quality: Option<quality::Preset>,
+    /// What the scene is lit by and drawn over, in linear colour
+    light: sky::Light,
+    /// Shades what is around draws with capsules, see `Draw::capsules`
+    capsule_shadows: capsules::CapsuleShadows,
+    /// Shadows cast onto the ground by draws, see `Draw::casts_ground_shadow`
+    ground_shadows: ground_shadows::GroundShadows,
+    /// The ghosts of the draws' trails, see `Draw::trail`
+    trail_ghosts: trails::Ghosts,
+    /// How toon shaded draws are outlined, see `material::Toon`
+    outlines: toon::Outlines,
+    /// The user's shaders drawn as materials, see `material::Material::shader`
+    shader_materials: shader_materials::Library,
+    /// How the console's `text` and `shape` commands style what they add
+    sdf_style: sdf::Style,
+    background: [f32; 3],
     image: vk::Image,
     image_memory: vk::DeviceMemory,
     texture_image_view: vk::ImageView,
     texture_sampler: vk::Sampler,
+    /// The static meshes' baked lighting, when they are lightmapped
+    lightmap: Option<(vk::Image, vk::DeviceMemory, vk::ImageView)>,
+    /// The built in atlas of `toon::RAMPS` that toon shaded materials are lit through
+    toon_ramps: (vk::Image, vk::DeviceMemory, vk::ImageView),
+    /// The atlas given by `load_toon_ramps`, used instead of the built in one
+    loaded_toon_ramps: Option<assets::Texture>,
+    /// Textures generated by `generate_noise`
+    noise_textures: Vec<noise::Texture>,
+    /// The noise texture drawn in place of the scene's texture, if any
+    scene_noise_texture: Option<usize>,
 
     depth_image: vk::Image,
     depth_image_memory: vk::DeviceMemory,
     depth_image_view: vk::ImageView,
+    depth_format: vk::Format,
+    /// Chooses image formats for the physical device, see `select_format`
+    formats: formats::Formats,
+    /// The memory textures may take, and the textures loaded smaller to fit in it
+    texture_budget: memory::TextureBudget,
+    /// Meshes and textures loaded at runtime, freed through the deletion queue once no longer used
+    assets: assets::Assets,
+    deletion_queue: assets::DeletionQueue,
+    /// Streamed in around the camera's target, see `stream_world`
+    world: Option<streaming::World>,
+    /// Counts the bytes of meshes and textures loaded while the renderer runs, for the residency overlay
+    uploads: residency::Uploads,
+    /// Whether the instance and device were created with `host_memory::callbacks`
+    track_host_memory: bool,
+    /// Used instead of the chosen surface format and present mode whenever the swapchain is created
+    surface_overrides: surface::Overrides,
+    /// Culls the draws of indoor cells the camera can't see through a portal, see `set_portals`
+    portals: Option<portals::Portals>,
+    /// Culls draws hidden behind the largest draws on the CPU, see `set_software_occlusion`
+    software_occlusion: Option<occluders::Settings>,
+    /// Draws the scene for two eyes, see `set_stereo`
+    stereo: Option<stereo::Stereo>,
+    /// Draws a top-down map of the scene into a corner of the frame, see `set_minimap`
+    minimap: Option<minimap::Minimap>,
+    /// Shadows the light with a map of the scene's depth from it, see `set_shadows`
+    shadow_map: Option<shadow_map::ShadowMap>,
+    /// Draws the scene into the faces of cubemaps around points, see `add_probe`
+    probes: Vec<probes::Probe>,
+    /// Spreads the minimap's and probes' renders across frames, see `set_schedule_budget`
+    schedule: schedule::Scheduler,
 }
 
 impl HelloTriangleApplication {
+    /// Vertical field of view of the camera
+    const FIELD_OF_VIEW: Rad<f32> = Rad(std::f32::consts::FRAC_PI_4);
+
+    /// Renders the demo scene into the window, or the sketch instead when one is given. The static meshes are
+    /// lightmapped when given a lightmap path, which is baked first if it doesn't exist yet. Textures are loaded
+    /// at lower resolutions when they would take more than `texture_budget` bytes of device memory between them.
+    /// With `track_host_memory` the instance and device allocate their host memory through
+    /// `host_memory::callbacks`, so it can be reported. The swapchain uses the surface overrides the surface
+    /// supports.
+    #[allow(clippy::too_many_arguments)]
     pub fn initialize(
-        event_loop: &EventLoop<()>,
+        window: winit::window::Window,
         debug_config: Option<debug::Configuration>,
+        sketch: Option<playground::Sketch>,
+        filters: Vec<PathBuf>,
+        lightmap_path: Option<PathBuf>,
+        texture_budget: Option<vk::DeviceSize>,
+        track_host_memory: bool,
+        surface_overrides: surface::Overrides,
     ) -> Self {
-        let window = Self::init_window(&event_loop);
-
+        let mut surface_overrides = surface_overrides;
         let mut debug_config = debug_config;
         let entry = unsafe { ash::Entry::new().unwrap() };
+        let host_allocator = Some(host_memory::callbacks()).filter(|_| track_host_memory);
+        let mut events = events::Bus::default();
 
-        let instance = Self::create_instance(&entry, &debug_config);
+        let instance = Self::create_instance(&entry, &debug_config, host_allocator.as_ref());
         for config in debug_config.iter_mut() {
-            let result = config.create_messenger(&entry, &instance);
-            if result.is_err() {
-                println!("error creating debug messenger: {}", result.unwrap_err())
+            if let Err(e) = config.create_messenger(&entry, &instance) {
+                events.emit(events::Event::FeatureUnavailable {
+                    feature: String::from("reporting validation messages"),
+                    error: e.to_string(),
+                });
             }
         }
 
@@ -274,6 +799,7 @@ impl HelloTriangleApplication {
             &physical_device,
             &queue_families,
             debug_config.is_some(),
+            host_allocator.as_ref(),
         );
 
         let graphics_queue = Self::get_device_queue(
@@ -288,6 +814,11 @@ impl HelloTriangleApplication {
                 .present_family
                 .expect("Present queue family index"),
         );
+        // The dedicated compute queue when the device has one, otherwise the graphics queue
+        let compute_queue = match queue_families.compute_family {
+            Some(family) => Self::get_device_queue(&logical_device, family),
+            None => graphics_queue,
+        };
 
         let swapchain_data = Self::create_swap_chain(
             &instance,
@@ -296,26 +827,49 @@ impl HelloTriangleApplication {
             &physical_device,
             &surface,
             &window,
-            &queue_families,
+            &mut surface_overrides,
         );
+        let present_acquire = ownership::Transfer::new(
+            queue_families.graphics_family.unwrap(),
+            queue_families.present_family.unwrap(),
+        )
+        .map(|transfer| {
+            ownership::PresentAcquire::new(&logical_device, transfer, &swapchain_data.images)
+        });
 
         let swapchain_image_views =
             Self::create_swapchain_image_views(&logical_device, &swapchain_data);
 
+        let formats = formats::Formats::new(physical_device);
+        let depth_format = formats
+            .select(&instance, &formats::Request::depth())
+            .expect("getting depth format")
+            .format;
+        let passes = pipeline::PassConfigs::default();
         let render_pass = Self::create_render_pass(
-            &instance,
-            physical_device,
             &logical_device,
             swapchain_data.format,
+            depth_format,
+            &passes.scene,
         );
 
         let descriptor_set_layout = Self::create_descriptor_set_layout(&logical_device);
+        let animation_set_layout = vat::create_descriptor_set_layout(&logical_device);
 
-        let (graphics_pipeline, pipeline_layout) = Self::create_graphics_pipeline(
+        let physical_device_properties =
+            unsafe { instance.get_physical_device_properties(physical_device) };
+        let raster_limits = pipeline::RasterLimits::new(
+            &Self::get_device_features(&instance, &physical_device),
+            &physical_device_properties.limits,
+        );
+        let pipeline_cache = pipeline::create_cache(&logical_device);
+        let pipelines = pipeline::Variants::new(
             &logical_device,
-            swapchain_data.extent,
-            render_pass,
             descriptor_set_layout,
+            animation_set_layout,
+            Self::pipeline_target(&swapchain_data, render_pass),
+            raster_limits,
+            pipeline_cache,
         );
 
         let command_pool = Self::create_command_pool(&logical_device, &queue_families);
@@ -324,13 +878,17 @@ impl HelloTriangleApplication {
             unsafe { instance.get_physical_device_memory_properties(physical_device) };
 
         let (depth_image, depth_image_memory, depth_image_view) = Self::create_depth_resources(
-            &instance,
-            physical_device,
             &physical_device_memory_properties,
             &logical_device,
             graphics_queue,
             command_pool,
             swapchain_data.extent,
+            depth_format,
+            formats.supports(
+                &instance,
+                depth_format,
+                vk::FormatFeatureFlags::SAMPLED_IMAGE,
+            ),
         );
 
         let swap_chain_frame_buffers = Self::create_frame_buffers(
@@ -350,12 +908,16 @@ impl HelloTriangleApplication {
             physical_device_memory_properties,
         );
 
-        let (image, image_memory) = Self::create_texture_image(
+        let mut texture_budget =
+            memory::TextureBudget::new(&physical_device_memory_properties, texture_budget);
+        let (image, image_memory, _, _) = Self::create_texture_from_pixels(
             &logical_device,
             command_pool,
             graphics_queue,
             &physical_device_memory_properties,
-            "src/textures/texture.jpg".into(),
+            SCENE_TEXTURE.into(),
+            Self::scene_texture(),
+            &mut texture_budget,
         );
 
         let texture_image_view = Self::create_texture_image_view(&logical_device, image);
@@ -369,10 +931,343 @@ impl HelloTriangleApplication {
             physical_device_memory_properties,
         );
 
-        let physical_device_properties =
-            unsafe { instance.get_physical_device_properties(physical_device) };
-        let texture_sampler =
-            Self::create_texture_sampler(&logical_device, physical_device_properties);
+        let (billboard_buffer, billboard_buffer_memory) = Self::create_device_local_buffer(
+            &logical_device,
+            &BILLBOARDS,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            command_pool,
+            graphics_queue,
+            &physical_device_memory_properties,
+        );
+
+        let (axis_buffer, axis_buffer_memory) = Self::create_device_local_buffer(
+            &logical_device,
+            &AXIS_VERTICES,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            command_pool,
+            graphics_queue,
+            &physical_device_memory_properties,
+        );
+
+        let disc: Vec<Vertex> = ground_shadows::disc()
+            .iter()
+            .map(|&[x, y, coverage]| Vertex {
+                pos: [x, y, 0.0],
+                color: [coverage, 0.0, 0.0],
+                tex_coord: [0.0, 0.0],
+                normal: [0.0, 0.0, 1.0],
+            })
+            .collect();
+        let (disc_buffer, disc_buffer_memory) = Self::create_device_local_buffer(
+            &logical_device,
+            &disc,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            command_pool,
+            graphics_queue,
+            &physical_device_memory_properties,
+        );
+
+        // The floor only has vertex colours, its texture coordinates come from the default attributes
+        let floor = Self::floor_mesh();
+        let (floor_vertex_buffer, floor_vertex_buffer_memory) = Self::create_device_local_buffer(
+            &logical_device,
+            &floor.interleave(),
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            command_pool,
+            graphics_queue,
+            &physical_device_memory_properties,
+        );
+        let (floor_index_buffer, floor_index_buffer_memory) = Self::create_device_local_buffer(
+            &logical_device,
+            &floor.indices,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            command_pool,
+            graphics_queue,
+            &physical_device_memory_properties,
+        );
+
+        // Posts around the edge of the floor and the second scene's tower, baked into one draw per material
+        let mut static_buffers = Vec::new();
+        let mut static_draws = Vec::new();
+        // Light baked into the static meshes is blocked by the floor as well as the scene's own meshes
+        let mut static_scenes = vec![(0, Self::post_meshes()), (1, Self::tower_meshes())];
+        let floor_occluder = batch::StaticMesh {
+            mesh: floor.clone(),
+            material: material::Material::default(),
+            transform: Matrix4::identity(),
+        };
+        let lightmap = lightmap_path.map(|path| {
+            Self::prepare_lightmap(&mut static_scenes, &floor_occluder, &path, &mut events)
+                .unwrap_or_else(|e| panic!("Preparing lightmap {}: {}", path.display(), e));
+            let (image, memory) = Self::create_texture_image(
+                &logical_device,
+                command_pool,
+                graphics_queue,
+                &physical_device_memory_properties,
+                path.to_string_lossy().into_owned(),
+                &mut texture_budget,
+            );
+            let view = Self::create_texture_image_view(&logical_device, image);
+            (image, memory, view)
+        });
+        let lightmap_image_view = lightmap.map_or(texture_image_view, |(_, _, view)| view);
+        let (toon_ramps_image, toon_ramps_memory, _, _) = Self::create_texture_from_pixels(
+            &logical_device,
+            command_pool,
+            graphics_queue,
+            &physical_device_memory_properties,
+            "toon ramps".into(),
+            Self::toon_ramp_atlas(),
+            &mut texture_budget,
+        );
+        let toon_ramps = (
+            toon_ramps_image,
+            toon_ramps_memory,
+            Self::create_texture_image_view(&logical_device, toon_ramps_image),
+        );
+        // Lightmaps include ambient occlusion already
+        if lightmap.is_none() {
+            for (_, meshes) in static_scenes.iter_mut() {
+                occlusion::bake(
+                    meshes,
+                    std::slice::from_ref(&floor_occluder),
+                    &occlusion::Settings::default(),
+                );
+            }
+        }
+        for (scene, baked) in static_scenes.into_iter().flat_map(|(scene, meshes)| {
+            batch::bake(meshes)
+                .into_iter()
+                .map(move |baked| (scene, baked))
+        }) {
+            let (vertex_buffer, vertex_buffer_memory) = Self::create_device_local_buffer(
+                &logical_device,
+                &baked.mesh.interleave(),
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+                command_pool,
+                graphics_queue,
+                &physical_device_memory_properties,
+            );
+            let (index_buffer, index_buffer_memory) = Self::create_device_local_buffer(
+                &logical_device,
+                &baked.mesh.indices,
+                vk::BufferUsageFlags::INDEX_BUFFER,
+                command_pool,
+                graphics_queue,
+                &physical_device_memory_properties,
+            );
+            for batch in baked.batches.iter() {
+                let positions: Vec<Point3<f32>> = baked.mesh.positions
+                    [batch.vertex_offset as usize..]
+                    .iter()
+                    .map(|&position| Point3::from(position))
+                    .collect();
+                let indices =
+                    &baked.mesh.indices[batch.first_index as usize..][..batch.index_count as usize];
+                static_draws.push(draw::Draw {
+                    first_index: batch.first_index,
+                    vertex_offset: batch.vertex_offset,
+                    bounds: batch.bounds.map(bounds::Bounds::new),
+                    collider: raycast::Bvh::new(&positions, indices).map(Arc::new),
+                    scene,
+                    ..draw::Draw::indexed(
+                        batch.pipeline,
+                        vertex_buffer,
+                        index_buffer,
+                        batch.index_count,
+                    )
+                });
+            }
+            static_buffers.push((vertex_buffer, vertex_buffer_memory));
+            static_buffers.push((index_buffer, index_buffer_memory));
+        }
+        events.status(format!(
+            "Baked the static meshes into {} draws",
+            static_draws.len()
+        ));
+
+        let (default_attribute_buffer, default_attribute_buffer_memory) =
+            Self::create_device_local_buffer(
+                &logical_device,
+                &mesh::DEFAULT_ATTRIBUTES,
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+                command_pool,
+                graphics_queue,
+                &physical_device_memory_properties,
+            );
+
+        let vertex_bounds = |vertices: &[Vertex]| {
+            bounds::Aabb::from_points(vertices.iter().map(|vertex| Point3::from(vertex.pos)))
+                .map(bounds::Bounds::new)
+        };
+        let billboard_bounds = BILLBOARDS
+            .iter()
+            .map(billboard::Billboard::bounds)
+            .reduce(|a, b| a.union(&b))
+            .map(bounds::Bounds::new);
+
+        let quad_positions: Vec<Point3<f32>> = QUAD_VERTICES
+            .iter()
+            .map(|vertex| Point3::from(vertex.pos))
+            .collect();
+        let floor_positions: Vec<Point3<f32>> = floor
+            .positions
+            .iter()
+            .map(|&position| Point3::from(position))
+            .collect();
+
+        let mut draws = vec![
+            // The quads are set up like foliage: flat so they should look right from either side, and cut out
+            // where the texture is transparent
+            draw::Draw {
+                bounds: vertex_bounds(&QUAD_VERTICES),
+                collider: raycast::Bvh::new(&quad_positions, &QUAD_INDICES).map(Arc::new),
+                ..draw::Draw::indexed(
+                    material::Material {
+                        double_sided: true,
+                        blend_mode: material::BlendMode::Masked { cutoff: 0.5 },
+                        ..material::Material::default()
+                    }
+                    .pipeline_config(mesh::VertexAttributes::ALL),
+                    vertex_buffer,
+                    index_buffer,
+                    QUAD_INDICES.len() as u32,
+                )
+            },
+            draw::Draw {
+                bounds: floor.bounds().map(bounds::Bounds::new),
+                collider: raycast::Bvh::new(&floor_positions, &floor.indices).map(Arc::new),
+                ..draw::Draw::indexed(
+                    material::Material {
+                        vertex_color: material::VertexColorMode::Multiply,
+                        ..material::Material::default()
+                    }
+                    .pipeline_config(floor.attributes()),
+                    floor_vertex_buffer,
+                    floor_index_buffer,
+                    floor.indices.len() as u32,
+                )
+            },
+            draw::Draw {
+                line_width: 2.0,
+                bounds: vertex_bounds(&AXIS_VERTICES),
+                ..draw::Draw::new(
+                    pipeline::Config::unlit(vk::PrimitiveTopology::LINE_LIST),
+                    axis_buffer,
+                    AXIS_VERTICES.len() as u32,
+                )
+            },
+            // Mark the ends of the axes
+            draw::Draw {
+                point_size: 6.0,
+                ..draw::Draw::new(
+                    pipeline::Config::unlit(vk::PrimitiveTopology::POINT_LIST),
+                    axis_buffer,
+                    AXIS_VERTICES.len() as u32,
+                )
+            },
+            // Billboards are blended so they are drawn after the opaque geometry
+            draw::Draw {
+                instance_count: BILLBOARDS.len() as u32,
+                bounds: billboard_bounds,
+                ..draw::Draw::new(
+                    pipeline::Config::billboard(),
+                    billboard_buffer,
+                    billboard::VERTICES_PER_BILLBOARD,
+                )
+            },
+        ];
+        // Static geometry is opaque, so it goes in before the blended billboards
+        draws.splice(0..0, static_draws);
+        // The second scene stands on the same floor
+        let second_floor = draw::Draw {
+            scene: 1,
+            ..draws
+                .iter()
+                .find(|draw| draw.vertex_buffer == floor_vertex_buffer)
+                .expect("Floor draw")
+                .clone()
+        };
+        draws.push(second_floor);
+        let grid = camera::Grid::new(
+            &logical_device,
+            command_pool,
+            graphics_queue,
+            &physical_device_memory_properties,
+            &mut draws,
+        );
+        let measurement = measure::Measurement::new(
+            &logical_device,
+            command_pool,
+            graphics_queue,
+            &physical_device_memory_properties,
+            &mut draws,
+        );
+
+        let compute_family = queue_families
+            .compute_family
+            .or(queue_families.graphics_family)
+            .expect("Compute queue family index");
+        let particles = particles::Particles::new(
+            &logical_device,
+            &physical_device_memory_properties,
+            compute_queue,
+            compute_family,
+            queue_families.graphics_family.unwrap(),
+            MAX_FRAMES_IN_FLIGHT,
+        );
+        let particle_draw = draws.len();
+        // Each frame draws the billboards its own update wrote, see `draw_frame`
+        draws.push(draw::Draw {
+            instance_count: particles::PARTICLE_COUNT,
+            ..draw::Draw::new(
+                pipeline::Config::billboard(),
+                particles.billboard_buffer(0),
+                billboard::VERTICES_PER_BILLBOARD,
+            )
+        });
+
+        #[cfg(feature = "physics")]
+        let physics = physics::Demo::new(
+            &logical_device,
+            command_pool,
+            graphics_queue,
+            &physical_device_memory_properties,
+            &mut draws,
+            &floor.bounds().expect("floor has vertices"),
+        );
+
+        let texture_sampler = Self::create_texture_sampler(
+            &logical_device,
+            physical_device_properties.limits.max_sampler_anisotropy,
+        );
+
+        let mut post_process =
+            postprocess::PostProcess::new(&logical_device, &physical_device_memory_properties);
+        for name in ["sharpen", "posterize", "edge_detect"] {
+            let path = Path::new(env!("OUT_DIR")).join(format!("{}_comp.spv", name));
+            post_process.register(&logical_device, name, &util::read_shader_code(&path), false);
+        }
+        for path in filters.iter() {
+            let name = path.file_stem().map_or(String::from("filter"), |stem| {
+                stem.to_string_lossy().into_owned()
+            });
+            post_process.register(&logical_device, &name, &util::read_shader_code(path), true);
+        }
+        let playground = sketch.map(|sketch| {
+            playground::Playground::new(
+                &logical_device,
+                command_pool,
+                graphics_queue,
+                &physical_device_memory_properties,
+                &sketch,
+                playground::ChannelSampling {
+                    default_texture: texture_image_view,
+                    sampler: texture_sampler,
+                },
+                &mut texture_budget,
+            )
+        });
 
         let (uniform_buffers, uniform_buffers_memory) = Self::create_uniform_buffers(
             &logical_device,
@@ -393,21 +1288,19 @@ impl HelloTriangleApplication {
             &descriptor_sets,
             &uniform_buffers,
             texture_image_view,
+            lightmap_image_view,
+            toon_ramps.2,
+            shadow_map::Descriptors::none(texture_image_view, texture_sampler),
             texture_sampler,
             swapchain_image_views.len(),
         );
 
-        let command_buffers = Self::create_command_buffers(
+        let command_buffers =
+            Self::create_command_buffers(&logical_device, command_pool, &swap_chain_frame_buffers);
+        let secondary_buffers = Self::create_secondary_buffers(
             &logical_device,
-            command_pool,
-            render_pass,
-            &swap_chain_frame_buffers,
-            swapchain_data.extent,
-            graphics_pipeline,
-            vertex_buffer,
-            index_buffer,
-            pipeline_layout,
-            &descriptor_sets,
+            &queue_families,
+            swapchain_data.images.len(),
         );
 
         // TODO: Handle image in flight fences
@@ -418,9 +1311,31 @@ impl HelloTriangleApplication {
             .map(|_| vk::Fence::null())
             .collect();
 
-        Self {
+        let names = debug_config
+            .as_ref()
+            .and_then(|config| config.names(&logical_device));
+        #[cfg(feature = "audio")]
+        let audio = audio::Capture::new()
+            .map_err(|e| {
+                events.emit(events::Event::FeatureUnavailable {
+                    feature: String::from("capturing audio"),
+                    error: e.to_string(),
+                })
+            })
+            .ok();
+        #[cfg(feature = "gamepad")]
+        let gamepad = gamepad::Gamepad::new()
+            .map_err(|e| {
+                events.emit(events::Event::FeatureUnavailable {
+                    feature: String::from("reading gamepad input"),
+                    error: e.to_string(),
+                })
+            })
+            .ok();
+        let mut app = Self {
             _entry: entry,
             debug_config,
+            names,
             instance,
             surface,
             surface_loader,
@@ -430,47 +1345,237 @@ impl HelloTriangleApplication {
             logical_device,
             graphics_queue,
             present_queue,
+            present_acquire,
+            present_thread: None,
             swapchain_data,
+            images_drawn: vec![false; swapchain_image_views.len()],
             swapchain_image_views,
             render_pass,
+            first_render_pass: None,
+            passes,
             descriptor_pool,
             descriptor_sets,
             descriptor_set_layout,
-            pipeline_layout,
-            graphics_pipeline,
+            animation_set_layout,
+            pipelines,
+            pipeline_cache,
+            draws,
+            scene: 0,
             swap_chain_frame_buffers,
             command_pool,
             command_buffers,
+            secondary_buffers,
+            baked_draws: None,
+            frame_scratch: FrameScratch::default(),
+            timings: jobs::Timings::default(),
             image_available_semaphores,
             render_complete_semaphores,
+            submission_semaphores: submission::Semaphores::new(MAX_FRAMES_IN_FLIGHT),
+            submissions: Vec::new(),
             frame_fences,
             image_fences,
             current_frame: 0,
+            scale_factor: window.scale_factor() as f32,
             window,
             frame_buffer_resized: false,
             vertex_buffer,
             vertex_buffer_memory,
             index_buffer,
             index_buffer_memory,
+            billboard_buffer,
+            billboard_buffer_memory,
+            axis_buffer,
+            axis_buffer_memory,
+            disc_buffer,
+            disc_buffer_memory,
+            floor_vertex_buffer,
+            floor_vertex_buffer_memory,
+            floor_index_buffer,
+            floor_index_buffer_memory,
+            default_attribute_buffer,
+            default_attribute_buffer_memory,
+            static_buffers,
+            point_cloud_buffers: Vec::new(),
+            cloth: None,
+            swarm: None,
+            animations: Vec::new(),
+            animations_updated: Instant::now(),
             uniform_buffers,
             uniform_buffers_memory,
             image,
             image_memory,
             texture_image_view,
             texture_sampler,
+            lightmap,
+            toon_ramps,
+            loaded_toon_ramps: None,
+            noise_textures: Vec::new(),
+            scene_noise_texture: None,
             start_time: Instant::now(),
+            model: Matrix4::identity(),
+            view: Matrix4::identity(),
+            camera: camera::Camera::new(Self::FIELD_OF_VIEW),
+            dolly: camera::Dolly::new(),
+            grid,
+            units: units::Convention::default(),
+            measurement,
+            measuring: false,
+            picked: None,
+            history: history::History::default(),
+            scene_file: None,
+            camera_path_file: None,
+            camera_recorder: None,
+            camera_player: None,
+            timeline: None,
+            clipping: clipping::Clipping::default(),
+            cursor_position: None,
+            playground,
+            indirect_scene: None,
+            hi_z_culling: false,
+            statistics: None,
+            frame_budget: None,
+            particles,
+            particle_draw,
+            features: features::Features::default(),
+            hooks: hooks::Hooks::default(),
+            events,
+            inspecting: false,
+            modifiers: ModifiersState::empty(),
+            #[cfg(feature = "physics")]
+            physics,
+            #[cfg(feature = "audio")]
+            audio,
+            #[cfg(feature = "gamepad")]
+            gamepad,
+            #[cfg(feature = "scripting")]
+            script: None,
+            #[cfg(feature = "scripting")]
+            script_input: scripting::Input::default(),
+            #[cfg(feature = "hot-reload")]
+            logic: None,
+            immediate: None,
+            time_of_day: None,
+            quality: None,
+            light: sky::Light::default(),
+            capsule_shadows: capsules::CapsuleShadows::default(),
+            ground_shadows: ground_shadows::GroundShadows::default(),
+            trail_ghosts: trails::Ghosts::default(),
+            outlines: toon::Outlines::default(),
+            shader_materials: shader_materials::Library::default(),
+            sdf_style: sdf::Style::default(),
+            background: [0.0, 0.0, 0.0],
             depth_image,
             depth_image_memory,
             depth_image_view,
+            depth_format,
+            formats,
+            texture_budget,
+            assets: assets::Assets::default(),
+            deletion_queue: assets::DeletionQueue::new(MAX_FRAMES_IN_FLIGHT),
+            world: None,
+            uploads: residency::Uploads::default(),
+            track_host_memory,
+            surface_overrides,
+            portals: None,
+            software_occlusion: None,
+            stereo: None,
+            minimap: None,
+            shadow_map: None,
+            probes: Vec::new(),
+            schedule: schedule::Scheduler::default(),
+        };
+        // The scene hasn't moved yet so its bounds are its untransformed bounds
+        app.frame_scene();
+        app.add_feature(Box::new(post_process));
+        app.add_feature(Box::new(inspector::Inspector::default()));
+        let transitions = transition::Transitions::new(&app.logical_device);
+        app.add_feature(Box::new(transitions));
+        let mut thumbnails = thumbnails::Thumbnails::new(&app.logical_device);
+        let mut sources = vec![("texture", app.texture_image_view)];
+        if let Some((_, _, view)) = app.lightmap {
+            sources.push(("lightmap", view));
+        }
+        for (name, view) in sources {
+            thumbnails
+                .add_source(&app.logical_device, name, view)
+                .expect("The renderer's own thumbnails fit");
+        }
+        app.add_feature(Box::new(thumbnails));
+        let atlas = sdf::Atlas::from_font();
+        let texture = app.create_sdf_texture(&atlas);
+        app.add_feature(Box::new(sdf::Layer::new(
+            &app.logical_device,
+            atlas,
+            texture,
+        )));
+        app.add_feature(Box::new(magnifier::Magnifier::default()));
+        // Before the console, so that screenshots are taken without it
+        app.add_feature(Box::new(screenshot::Screenshot::default()));
+        app.add_feature(Box::new(residency::Overlay::default()));
+        let console = console::Console::new(app.console_commands());
+        app.add_feature(Box::new(console));
+        app.plan_submissions();
+        let info = app.info();
+        app.events.emit(events::Event::Started(info));
+
+        app
+    }
+
+    /// Describes the device and swapchain configuration currently in use.
+    pub fn info(&self) -> info::RendererInfo {
+        let properties = unsafe {
+            self.instance
+                .get_physical_device_properties(self.physical_device)
+        };
+
+        info::RendererInfo {
+            device_name: util::read_vk_string(&properties.device_name[..]),
+            surface_format: vk::SurfaceFormatKHR {
+                format: self.swapchain_data.format,
+                color_space: self.swapchain_data.color_space,
+            },
+            present_mode: self.swapchain_data.present_mode,
+            forced_surface_format: self.surface_overrides.format.is_some()
+                || self.surface_overrides.color_space.is_some(),
+            forced_present_mode: self.surface_overrides.present_mode.is_some(),
+            extent: self.swapchain_data.extent,
+            manual_srgb_encoding: !util::is_srgb_format(self.swapchain_data.format),
+            transform: self.swapchain_data.transform,
+            depth_format: self.depth_format,
+            texture_downgrades: self
+                .texture_budget
+                .downgrades
+                .iter()
+                .map(|downgrade| downgrade.to_string())
+                .collect(),
+            formats: [
+                formats::Request::hdr_color(),
+                formats::Request::normals(),
+                formats::Request::shadow_depth(),
+                formats::Request::shadow_moments(),
+            ]
+            .iter()
+            .map(|request| {
+                let choice = self.select_format(request);
+                (request.name, choice.map(|choice| choice.format))
+            })
+            .collect(),
         }
     }
 
+    /// The best format the device supports for the request. What the device supports for each format is cached,
+    /// so this can be called whenever a target is created.
+    pub fn select_format(&self, request: &formats::Request) -> Option<formats::Choice> {
+        self.formats.select(&self.instance, request)
+    }
+
     /**
     Instance creation
     */
     fn create_instance(
         entry: &ash::Entry,
         debug_config: &Option<debug::Configuration>,
+        allocator: Option<&vk::AllocationCallbacks>,
     ) -> ash::Instance {
         let mut layers: Vec<CString> = Vec::new();
         let mut extensions = vec![Surface::name().to_owned(), Win32Surface::name().to_owned()];
@@ -481,12 +1586,23 @@ impl HelloTriangleApplication {
             extensions.push(name);
             extension_inputs.push(data);
 
-            if let Ok(mut validation_layers) = configuration.instance_validation_layers(entry) {
-                layers.append(&mut validation_layers)
+            match configuration.instance_validation_layers(entry) {
+                Ok(mut validation_layers) => layers.append(&mut validation_layers),
+                Err(e) => validation::record_failure(&format!(
+                    "The validation layers couldn't be loaded: {}",
+                    e
+                )),
             }
         }
 
-        instance::new(entry, &layers, &extensions, &mut extension_inputs).unwrap()
+        instance::new(
+            entry,
+            &layers,
+            &extensions,
+            &mut extension_inputs,
+            allocator,
+        )
+        .unwrap()
     }
 
     /**
@@ -504,7 +1620,6 @@ impl HelloTriangleApplication {
                 if devices.len() == 0 {
                     None
                 } else {
-                    println!("Found {} devices", devices.len());
                     // TODO confirm device name in use
                     if let Some(device) = devices.iter().find(|&device| {
                         Self::is_device_suitable(instance, device, surface_loader, surface)
@@ -535,11 +1650,6 @@ impl HelloTriangleApplication {
         let required_device_extensions_supported =
             Self::check_device_extension_support(&instance, device, required_device_extensions);
 
-        println!(
-            "Evaluating suitability of device [{}]",
-            util::read_vk_string(&properties.device_name[..]).unwrap()
-        );
-
         let supports_required_families =
             Self::find_queue_families(instance, device, surface_loader, surface).is_complete();
 
@@ -570,14 +1680,9 @@ impl HelloTriangleApplication {
             unsafe { instance.enumerate_device_extension_properties(*device) }
                 .expect("Reading device extensions")
                 .iter()
-                .map(|extension| {
-                    util::read_vk_string(&extension.extension_name[..])
-                        .expect("Reading device extension name")
-                })
+                .map(|extension| util::read_vk_string(&extension.extension_name[..]))
                 .collect();
 
-        println!("Found {:?} device extensions", available_extensions);
-
         let mut all_extensions_present = true;
         for required_extension in required_extensions.iter() {
             all_extensions_present =
@@ -600,6 +1705,7 @@ impl HelloTriangleApplication {
         let mut indices = QueueFamilyIndices {
             graphics_family: None,
             present_family: None,
+            compute_family: None,
         };
 
         let properties = unsafe { instance.get_physical_device_queue_family_properties(*device) };
@@ -624,6 +1730,15 @@ impl HelloTriangleApplication {
             }
         }
 
+        indices.compute_family = properties
+            .iter()
+            .position(|family| {
+                family.queue_count > 0
+                    && family.queue_flags.contains(vk::QueueFlags::COMPUTE)
+                    && !family.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+            })
+            .map(|i| i as u32);
+
         indices
     }
 
@@ -634,11 +1749,51 @@ impl HelloTriangleApplication {
         vec![ash::extensions::khr::Swapchain::name()]
     }
 
+    /// Extensions enabled when the physical device supports them
+    fn get_optional_device_extensions() -> Vec<&'static CStr> {
+        vec![
+            ash::extensions::khr::DrawIndirectCount::name(),
+            conditional::ConditionalRendering::name(),
+        ]
+    }
+
+    fn is_device_extension_supported(
+        instance: &ash::Instance,
+        device: &vk::PhysicalDevice,
+        name: &CStr,
+    ) -> bool {
+        unsafe { instance.enumerate_device_extension_properties(*device) }
+            .expect("Reading device extensions")
+            .iter()
+            .any(|extension| unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) } == name)
+    }
+
+    /// The features to enable on the logical device. Optional features are only requested when the physical
+    /// device supports them.
+    fn get_device_features(
+        instance: &ash::Instance,
+        physical_device: &vk::PhysicalDevice,
+    ) -> vk::PhysicalDeviceFeatures {
+        let supported = unsafe { instance.get_physical_device_features(*physical_device) };
+
+        vk::PhysicalDeviceFeatures::builder()
+            .sampler_anisotropy(true)
+            .wide_lines(supported.wide_lines == vk::TRUE)
+            .large_points(supported.large_points == vk::TRUE)
+            .depth_bias_clamp(supported.depth_bias_clamp == vk::TRUE)
+            .multi_draw_indirect(supported.multi_draw_indirect == vk::TRUE)
+            .draw_indirect_first_instance(supported.draw_indirect_first_instance == vk::TRUE)
+            .pipeline_statistics_query(supported.pipeline_statistics_query == vk::TRUE)
+            .depth_bounds(supported.depth_bounds == vk::TRUE)
+            .build()
+    }
+
     fn create_logical_device(
         instance: &ash::Instance,
         physical_device: &vk::PhysicalDevice,
         queue_indices: &QueueFamilyIndices,
         debug: bool,
+        allocator: Option<&vk::AllocationCallbacks>,
     ) -> ash::Device {
         let mut queue_create_infos: Vec<DeviceQueueCreateInfo> = vec![];
 
@@ -647,6 +1802,9 @@ impl HelloTriangleApplication {
         let mut unique_queue_families = HashSet::new();
         unique_queue_families.insert(queue_indices.graphics_family.unwrap());
         unique_queue_families.insert(queue_indices.present_family.unwrap());
+        if let Some(compute_family) = queue_indices.compute_family {
+            unique_queue_families.insert(compute_family);
+        }
 
         for index in unique_queue_families.iter() {
             queue_create_infos.push(
@@ -656,9 +1814,7 @@ impl HelloTriangleApplication {
                     .build(),
             )
         }
-        let device_features = vk::PhysicalDeviceFeatures::builder()
-            .sampler_anisotropy(true)
-            .build();
+        let device_features = Self::get_device_features(instance, physical_device);
 
         let create_infos = &queue_create_infos[..];
         let required_validation_layer_raw_names: Vec<CString> = VALIDATION_LAYERS
@@ -669,9 +1825,13 @@ impl HelloTriangleApplication {
             .iter()
             .map(|layer_name| layer_name.as_ptr())
             .collect();
+        let optional_extensions = Self::get_optional_device_extensions()
+            .into_iter()
+            .filter(|name| Self::is_device_extension_supported(instance, physical_device, name));
         let enabled_extension_names: Vec<*const c_char> = Self::get_device_extensions()
-            .iter()
-            .map(|&name| name.as_ptr())
+            .into_iter()
+            .chain(optional_extensions)
+            .map(|name| name.as_ptr())
             .collect();
         let device_create_info = if debug {
             vk::DeviceCreateInfo::builder()
@@ -683,10 +1843,24 @@ impl HelloTriangleApplication {
             vk::DeviceCreateInfo::builder()
                 .queue_create_infos(create_infos)
                 .enabled_features(&device_features)
+                .enabled_extension_names(&enabled_extension_names[..])
+        };
+        // Devices supporting the extension must support the feature
+        let mut conditional_rendering_features =
+            vk::PhysicalDeviceConditionalRenderingFeaturesEXT::builder()
+                .conditional_rendering(true);
+        let device_create_info = if Self::is_device_extension_supported(
+            instance,
+            physical_device,
+            conditional::ConditionalRendering::name(),
+        ) {
+            device_create_info.push_next(&mut conditional_rendering_features)
+        } else {
+            device_create_info
         };
 
         unsafe {
-            match instance.create_device(*physical_device, &device_create_info, None) {
+            match instance.create_device(*physical_device, &device_create_info, allocator) {
                 Ok(device) => device,
                 _ => panic!("Logical device creation"),
             }
@@ -757,13 +1931,22 @@ impl HelloTriangleApplication {
     fn choose_swap_surface_format(
         available_formats: &Vec<ash::vk::SurfaceFormatKHR>,
     ) -> ash::vk::SurfaceFormatKHR {
-        available_formats
+        // A single undefined format means the surface has no preferred format and we can pick any
+        if available_formats.len() == 1 && available_formats[0].format == vk::Format::UNDEFINED {
+            return vk::SurfaceFormatKHR {
+                format: PREFERRED_SURFACE_FORMATS[0],
+                color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+            };
+        }
+
+        PREFERRED_SURFACE_FORMATS
             .iter()
-            .filter(|&format| {
-                format.format == ash::vk::Format::B8G8R8A8_SRGB
-                    && format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+            .find_map(|&preferred| {
+                available_formats.iter().find(|format| {
+                    format.format == preferred
+                        && format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+                })
             })
-            .next()
             .unwrap_or(&available_formats[0])
             .to_owned()
     }
@@ -803,13 +1986,18 @@ impl HelloTriangleApplication {
         physical_device: &ash::vk::PhysicalDevice,
         surface: &vk::SurfaceKHR,
         window: &winit::window::Window,
-        indicies: &QueueFamilyIndices,
+        overrides: &mut surface::Overrides,
     ) -> SwapChainData {
         let swap_chain_support =
             unsafe { Self::query_swap_chain_support(surface_loader, physical_device, surface) };
-        let format = Self::choose_swap_surface_format(&swap_chain_support.formats);
-        let present_mode = Self::choose_swap_present_mode(&swap_chain_support.present_modes);
+        let format = overrides
+            .surface_format(&swap_chain_support.formats)
+            .unwrap_or_else(|| Self::choose_swap_surface_format(&swap_chain_support.formats));
+        let present_mode = overrides
+            .present_mode(&swap_chain_support.present_modes)
+            .unwrap_or_else(|| Self::choose_swap_present_mode(&swap_chain_support.present_modes));
         let extent = Self::choose_swap_extent(&swap_chain_support.capabilities, window);
+        let transform = prerotation::choose_transform(&swap_chain_support.capabilities);
 
         // Minimum images plus one so we always have an image to draw to while driver is working
         let preferred_image_count = swap_chain_support.capabilities.min_image_count + 1;
@@ -822,23 +2010,10 @@ impl HelloTriangleApplication {
             preferred_image_count
         };
 
-        let (image_sharing_mode, families) = if indicies.graphics_family != indicies.present_family
-        {
-            // Both the graphics and the present family need to access swap chain images. If these queue families are not the
-            // same queue, then use concurent sharing mode. This is worse performance but allows us to share images without
-            // explicitly managing image ownership.
-            (
-                vk::SharingMode::CONCURRENT,
-                vec![
-                    indicies.graphics_family.unwrap(),
-                    indicies.present_family.unwrap(),
-                ],
-            )
-        } else {
-            // If the queue families are the same queue then the queue has exclusive use of swap chain images so we don't need to
-            // manage ownership anyway
-            (vk::SharingMode::EXCLUSIVE, vec![])
-        };
+        // Post processing copies the frame out of the image and back when the surface allows it
+        let usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+            | (swap_chain_support.capabilities.supported_usage_flags
+                & postprocess::SWAPCHAIN_USAGE);
 
         // See https://www.khronos.org/registry/vulkan/specs/1.2-extensions/man/html/VkSwapchainCreateInfoKHR.html for reference on all options
         let create_info = vk::SwapchainCreateInfoKHR::builder()
@@ -848,14 +2023,15 @@ impl HelloTriangleApplication {
             .image_color_space(format.color_space)
             .image_extent(extent)
             .image_array_layers(1)
-            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
-            .pre_transform(swap_chain_support.capabilities.current_transform)
+            .image_usage(usage)
+            .pre_transform(transform)
             // Alpha blending between other windows in window system
             .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
             .present_mode(present_mode)
             .clipped(true)
-            .image_sharing_mode(image_sharing_mode)
-            .queue_family_indices(&families[..]);
+            // Images belong to one family at a time, and are handed to a separate present family with
+            // `ownership::PresentAcquire`
+            .image_sharing_mode(vk::SharingMode::EXCLUSIVE);
 
         let swapchain_loader = ash::extensions::khr::Swapchain::new(instance, logical_device);
         let swapchain =
@@ -868,13 +2044,30 @@ impl HelloTriangleApplication {
             loader: swapchain_loader,
             swapchain: swapchain,
             format: format.format,
+            color_space: format.color_space,
+            present_mode,
             extent: extent,
+            usage,
+            transform,
             images,
         }
     }
 
-    fn create_swapchain_image_views(
-        device: &ash::Device,
+    fn pipeline_target(
+        swapchain_data: &SwapChainData,
+        render_pass: vk::RenderPass,
+    ) -> pipeline::Target {
+        pipeline::Target {
+            render_pass,
+            samples: vk::SampleCountFlags::TYPE_1,
+            extent: swapchain_data.extent,
+            encode_srgb: !util::is_srgb_format(swapchain_data.format),
+            color: true,
+        }
+    }
+
+    fn create_swapchain_image_views(
+        device: &ash::Device,
         swapchain_data: &SwapChainData,
     ) -> Vec<vk::ImageView> {
         swapchain_data
@@ -891,20 +2084,25 @@ impl HelloTriangleApplication {
             .collect()
     }
 
+    /// The frame's render pass, loading and storing the swapchain image and depth buffer as `config` says
     fn create_render_pass(
-        instance: &ash::Instance,
-        physical_device: vk::PhysicalDevice,
         device: &ash::Device,
         swap_chain_format: vk::Format,
+        depth_format: vk::Format,
+        config: &pipeline::PassConfig,
     ) -> vk::RenderPass {
         let color_attachment = vk::AttachmentDescription::builder()
             .format(swap_chain_format)
             .samples(vk::SampleCountFlags::TYPE_1)
-            .load_op(vk::AttachmentLoadOp::CLEAR)
-            .store_op(vk::AttachmentStoreOp::STORE)
+            .load_op(config.color.load.op())
+            .store_op(config.color.store_op())
             .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
             .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .initial_layout(
+                config
+                    .color
+                    .initial_layout(vk::ImageLayout::PRESENT_SRC_KHR),
+            )
             .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
             .build();
 
@@ -914,13 +2112,18 @@ impl HelloTriangleApplication {
             .build();
 
         let depth_attachment = vk::AttachmentDescription::builder()
-            .format(Self::find_depth_format(instance, physical_device, device))
+            .format(depth_format)
             .samples(vk::SampleCountFlags::TYPE_1)
-            .load_op(vk::AttachmentLoadOp::CLEAR)
-            .store_op(vk::AttachmentStoreOp::DONT_CARE)
-            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .load_op(config.depth.load.op())
+            .store_op(config.depth.store_op())
+            // Planar ground shadows only darken where the stencil is clear
+            .stencil_load_op(vk::AttachmentLoadOp::CLEAR)
             .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .initial_layout(
+                config
+                    .depth
+                    .initial_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL),
+            )
             .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
             .build();
 
@@ -935,6 +2138,17 @@ impl HelloTriangleApplication {
             .depth_stencil_attachment(&depth_attachment_ref)
             .build();
 
+        // What the previous frame drew has to be written before it's preserved
+        let (preserved_stages, preserved_writes, preserved_reads) = match config.preserves() {
+            true => (
+                vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                vk::AccessFlags::COLOR_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ,
+            ),
+            false => Default::default(),
+        };
         // Declare subpass dependencies
         let dependency = vk::SubpassDependency::builder()
             // Implicit subpass that always takes place
@@ -944,17 +2158,19 @@ impl HelloTriangleApplication {
             // Operation to wait on
             .src_stage_mask(
                 vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
-                    | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                    | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | preserved_stages,
             )
             // Stage that the operation occurs in
-            .src_access_mask(vk::AccessFlags::empty())
+            .src_access_mask(preserved_writes)
             .dst_stage_mask(
                 vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
                     | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
             )
             .dst_access_mask(
                 vk::AccessFlags::COLOR_ATTACHMENT_WRITE
-                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+                    | preserved_reads,
             )
             .build();
         let subpass_dependencies = [dependency];
@@ -978,16 +2194,43 @@ impl HelloTriangleApplication {
             .binding(0)
             .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
             .descriptor_count(1)
-            .stage_flags(vk::ShaderStageFlags::VERTEX);
+            .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT);
         let tex_sampler_layout_binding = vk::DescriptorSetLayoutBinding::builder()
             .binding(1)
             .descriptor_count(1)
             .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
             .stage_flags(vk::ShaderStageFlags::FRAGMENT);
 
+        let lightmap_sampler_layout_binding = vk::DescriptorSetLayoutBinding::builder()
+            .binding(2)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT);
+
+        // The shadow map's depth and VSM's moments, see `shadow_map::Descriptors`
+        let shadow_depth_layout_binding = vk::DescriptorSetLayoutBinding::builder()
+            .binding(3)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT);
+        let shadow_moments_layout_binding = vk::DescriptorSetLayoutBinding::builder()
+            .binding(4)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT);
+        let toon_ramps_layout_binding = vk::DescriptorSetLayoutBinding::builder()
+            .binding(5)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT);
+
         let bindings = [
             ubo_layout_binding.build(),
             tex_sampler_layout_binding.build(),
+            lightmap_sampler_layout_binding.build(),
+            shadow_depth_layout_binding.build(),
+            shadow_moments_layout_binding.build(),
+            toon_ramps_layout_binding.build(),
         ];
         let ci = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
         unsafe {
@@ -997,158 +2240,6 @@ impl HelloTriangleApplication {
         }
     }
 
-    fn create_graphics_pipeline(
-        device: &ash::Device,
-        swap_chain_extents: vk::Extent2D,
-        render_pass: vk::RenderPass,
-        descriptor_set_layout: vk::DescriptorSetLayout,
-    ) -> (vk::Pipeline, vk::PipelineLayout) {
-        let vert_path = Path::new(env!("OUT_DIR")).join("vert.spv");
-        println!(
-            "Reading vertex shader from {}",
-            vert_path.to_str().expect("vertex shader path")
-        );
-        let vert_shader_code = util::read_shader_code(vert_path.as_path());
-        let frag_path = Path::new(env!("OUT_DIR")).join("frag.spv");
-        println!(
-            "Reading frag shader from {}",
-            frag_path.to_str().expect("frag shader path")
-        );
-        let frag_shader_code = util::read_shader_code(frag_path.as_path());
-
-        let vert_shader_module = Self::create_shader_module(device, &vert_shader_code);
-        let frag_shader_module = Self::create_shader_module(device, &frag_shader_code);
-
-        let main_fn_name = CString::new("main").unwrap();
-        let vert_stage_builder = vk::PipelineShaderStageCreateInfo::builder()
-            .stage(vk::ShaderStageFlags::VERTEX)
-            .module(vert_shader_module)
-            .name(main_fn_name.as_c_str());
-        let frag_stage_builder = vk::PipelineShaderStageCreateInfo::builder()
-            .stage(vk::ShaderStageFlags::FRAGMENT)
-            .module(frag_shader_module)
-            .name(main_fn_name.as_c_str());
-        let shader_stages = vec![vert_stage_builder.build(), frag_stage_builder.build()];
-
-        let binding_description = [Vertex::get_binding_desription()];
-        let attribute_descriptions = Vertex::get_attribute_descriptions();
-        // Describe our vertex layout, the input for the vertex shader
-        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
-            .vertex_binding_descriptions(&binding_description)
-            .vertex_attribute_descriptions(&attribute_descriptions);
-
-        // Describe the primitives we are drawing with our vertices
-        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
-            .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
-            .primitive_restart_enable(false);
-
-        // Describe the region of the framebuffer that we want to render to
-        let viewport = vk::Viewport::builder()
-            .x(0.0)
-            .y(0.0)
-            .min_depth(0.0)
-            .max_depth(1.0)
-            .width(swap_chain_extents.width as f32)
-            .height(swap_chain_extents.height as f32);
-
-        // Clipping filter for frame buffer. We don't want to clip the frame buffer with this pipeline so we do the entire frame buffer.
-        let scissor = vk::Rect2D::builder()
-            .offset(vk::Offset2D { x: 0, y: 0 })
-            .extent(swap_chain_extents);
-
-        let viewports = [viewport.build()];
-        let scissors = [scissor.build()];
-        let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
-            .viewports(&viewports)
-            .scissors(&scissors);
-
-        // Set up a rasterizer
-        let rasterizer = vk::PipelineRasterizationStateCreateInfo::builder()
-            .depth_clamp_enable(false) // Clip beyond near and far planes
-            .rasterizer_discard_enable(false) // Don't skip rasterization
-            .polygon_mode(vk::PolygonMode::FILL) // Rasterize entire polygon
-            .line_width(1.0) // Rasterization line width
-            .cull_mode(vk::CullModeFlags::BACK) // Face culling
-            .front_face(vk::FrontFace::CLOCKWISE) // Vertex direction to determine if face is front or back
-            .depth_bias_enable(false); // Don't alter depth values with bias
-
-        // MSAA config. Ignored for now.
-        let multisampling = vk::PipelineMultisampleStateCreateInfo::builder()
-            .sample_shading_enable(false)
-            .rasterization_samples(vk::SampleCountFlags::TYPE_1)
-            .min_sample_shading(1.0)
-            .alpha_to_coverage_enable(false)
-            .alpha_to_one_enable(false);
-
-        // TODO Set up alpha blending
-        let color_blend_attachment = vk::PipelineColorBlendAttachmentState::builder()
-            .color_write_mask(vk::ColorComponentFlags::all())
-            .blend_enable(false)
-            .build();
-        let color_blend_attachments = [color_blend_attachment];
-        let global_blend = vk::PipelineColorBlendStateCreateInfo::builder()
-            .logic_op_enable(false)
-            .attachments(&color_blend_attachments);
-
-        let depth_stencil_attachment = vk::PipelineDepthStencilStateCreateInfo::builder()
-            .depth_test_enable(true)
-            .depth_write_enable(true)
-            .depth_compare_op(vk::CompareOp::LESS)
-            .depth_bounds_test_enable(false)
-            .min_depth_bounds(0.0)
-            .max_depth_bounds(0.0)
-            .stencil_test_enable(false);
-
-        let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::LINE_WIDTH];
-        let dynamic_state =
-            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(dynamic_states);
-
-        let set_layouts = [descriptor_set_layout];
-        let pipeline_layout_info =
-            vk::PipelineLayoutCreateInfo::builder().set_layouts(&set_layouts);
-        let pipeline_layout = unsafe {
-            device
-                .create_pipeline_layout(&pipeline_layout_info, None)
-                .expect("pipeline layout")
-        };
-
-        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
-            .stages(&shader_stages[..])
-            .vertex_input_state(&vertex_input_info)
-            .input_assembly_state(&input_assembly_info)
-            .viewport_state(&viewport_state)
-            .rasterization_state(&rasterizer)
-            .multisample_state(&multisampling)
-            .color_blend_state(&global_blend)
-            .depth_stencil_state(&depth_stencil_attachment)
-            .layout(pipeline_layout)
-            .render_pass(render_pass);
-
-        let pipelines = unsafe {
-            device
-                .create_graphics_pipelines(
-                    vk::PipelineCache::null(),
-                    &[pipeline_info.build()],
-                    None,
-                )
-                .expect("graphics pipeline")
-        };
-
-        unsafe { device.destroy_shader_module(vert_shader_module, None) };
-        unsafe { device.destroy_shader_module(frag_shader_module, None) };
-
-        (pipelines[0], pipeline_layout)
-    }
-
-    fn create_shader_module(device: &ash::Device, code: &[u32]) -> vk::ShaderModule {
-        let builder = vk::ShaderModuleCreateInfo::builder().code(code);
-        unsafe {
-            device
-                .create_shader_module(&builder, None)
-                .expect("Shader module")
-        }
-    }
-
     fn create_frame_buffers(
         device: &ash::Device,
         swapchain_image_views: &Vec<vk::ImageView>,
@@ -1187,6 +2278,8 @@ impl HelloTriangleApplication {
         queue_indices: &QueueFamilyIndices,
     ) -> vk::CommandPool {
         let ci = vk::CommandPoolCreateInfo::builder()
+            // Command buffers are re-recorded every frame so they have to be reset individually
+            .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
             // Which queue will this command pool create command buffers for
             .queue_family_index(
                 queue_indices
@@ -1317,6 +2410,217 @@ impl HelloTriangleApplication {
         (index_buffer, index_buffer_memory)
     }
 
+    /// A vertex coloured plane under the quads. It has no texture coordinates so it is drawn untextured.
+    fn floor_mesh() -> mesh::MeshData {
+        mesh::MeshData {
+            positions: vec![
+                [-1.0, -1.0, -0.75],
+                [1.0, -1.0, -0.75],
+                [1.0, 1.0, -0.75],
+                [-1.0, 1.0, -0.75],
+            ],
+            normals: vec![[0.0, 0.0, 1.0]; 4],
+            colors: Some(vec![
+                [0.2, 0.2, 0.3],
+                [0.3, 0.2, 0.2],
+                [0.2, 0.3, 0.2],
+                [0.3, 0.3, 0.3],
+            ]),
+            tex_coords: None,
+            lightmap_coords: None,
+            occlusion: None,
+            indices: vec![0, 1, 2, 2, 3, 0],
+        }
+    }
+
+    /// A ring of posts standing on the floor. Every other post is vertex coloured, the rest are textured.
+    fn post_meshes() -> Vec<batch::StaticMesh> {
+        let count = 16;
+        (0..count)
+            .map(|i| {
+                let angle = Rad::full_turn() * (i as f32 / count as f32);
+                let colored = i % 2 == 0;
+                let mut mesh = Self::cuboid_mesh([0.08, 0.08, 0.3]);
+                if colored {
+                    mesh.tex_coords = None;
+                } else {
+                    mesh.colors = None;
+                }
+                batch::StaticMesh {
+                    mesh,
+                    material: material::Material {
+                        vertex_color: if colored {
+                            material::VertexColorMode::Multiply
+                        } else {
+                            material::VertexColorMode::Ignore
+                        },
+                        ..material::Material::default()
+                    },
+                    transform: Matrix4::from_translation(Vector3::new(
+                        0.9 * angle.cos(),
+                        0.9 * angle.sin(),
+                        -0.6,
+                    )) * Matrix4::from_angle_z(angle),
+                }
+            })
+            .collect()
+    }
+
+    /// Gives the static meshes lightmap coordinates, and bakes their lightmap to `path` if it isn't there
+    /// already. Coordinates are generated the same way every time, so a lightmap baked before still fits. Every
+    /// scene shares the lightmap, but each is baked with only its own meshes and the floor in the way of the light.
+    fn prepare_lightmap(
+        static_scenes: &mut [(usize, Vec<batch::StaticMesh>)],
+        floor: &batch::StaticMesh,
+        path: &Path,
+        events: &mut events::Bus,
+    ) -> Result<(), String> {
+        let settings = lightmap::Settings::default();
+        let mut meshes: Vec<&mut batch::StaticMesh> = static_scenes
+            .iter_mut()
+            .flat_map(|(_, meshes)| meshes.iter_mut())
+            .collect();
+        lightmap::generate_coords(&mut meshes, settings.size)?;
+        if path.exists() {
+            return Ok(());
+        }
+
+        events.status(format!("Baking lightmap {}", path.display()));
+        let mut image = images::Image::new(settings.size, settings.size);
+        for (_, meshes) in static_scenes.iter() {
+            lightmap::bake(&mut image, meshes, std::slice::from_ref(floor), &settings);
+        }
+        // Textures are flipped as they are loaded
+        image.flip_vertical();
+        images::save(&image, path)
+    }
+
+    /// A stack of slabs, each smaller than the one below it and turned a little further, for the second scene.
+    fn tower_meshes() -> Vec<batch::StaticMesh> {
+        let count = 6;
+        let height = 0.12;
+        (0..count)
+            .map(|i| {
+                let width = 0.6 - 0.08 * i as f32;
+                batch::StaticMesh {
+                    mesh: Self::cuboid_mesh([width, width, height]),
+                    material: material::Material {
+                        vertex_color: material::VertexColorMode::Multiply,
+                        ..material::Material::default()
+                    },
+                    transform: Matrix4::from_translation(Vector3::new(
+                        0.0,
+                        0.0,
+                        -0.75 + height * (i as f32 + 0.5),
+                    )) * Matrix4::from_angle_z(Deg(15.0 * i as f32)),
+                }
+            })
+            .collect()
+    }
+
+    /// A box of the given size centred on the origin, with each face wound clockwise like the other built in
+    /// geometry.
+    fn cuboid_mesh(size: [f32; 3]) -> mesh::MeshData {
+        let half = Vector3::from(size) / 2.0;
+        let (x, y, z) = (Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z());
+        // Each face's normal followed by two axes across it, ordered so their cross product is the normal
+        let faces = [
+            (x, y, z),
+            (-x, z, y),
+            (y, z, x),
+            (-y, x, z),
+            (z, x, y),
+            (-z, y, x),
+        ];
+
+        let mut mesh = mesh::MeshData {
+            positions: Vec::new(),
+            normals: Vec::new(),
+            colors: Some(Vec::new()),
+            tex_coords: Some(Vec::new()),
+            lightmap_coords: None,
+            occlusion: None,
+            indices: Vec::new(),
+        };
+        for (normal, u, v) in faces {
+            let base = mesh.positions.len() as u16;
+            for (s, t) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                let position = (normal + u * s + v * t).mul_element_wise(half);
+                mesh.positions.push(position.into());
+                mesh.normals.push(normal.into());
+                mesh.tex_coords
+                    .as_mut()
+                    .unwrap()
+                    .push([(s + 1.0) / 2.0, (t + 1.0) / 2.0]);
+                // Lighter towards the top
+                let shade = 0.4 + 0.3 * (position.z / half.z + 1.0);
+                mesh.colors
+                    .as_mut()
+                    .unwrap()
+                    .push([shade, shade * 0.8, 0.5]);
+            }
+            mesh.indices
+                .extend([0, 1, 2, 2, 3, 0].iter().map(|&index| base + index));
+        }
+
+        mesh
+    }
+
+    /// Uploads `data` to a new device local buffer with the given usage by way of a host visible staging buffer.
+    fn create_device_local_buffer<T: Copy>(
+        device: &ash::Device,
+        data: &[T],
+        usage: vk::BufferUsageFlags,
+        command_pool: vk::CommandPool,
+        submit_queue: vk::Queue,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    ) -> (vk::Buffer, vk::DeviceMemory) {
+        if data.is_empty() {
+            panic!("Empty buffer data")
+        }
+        let size = mem::size_of_val(data) as vk::DeviceSize;
+
+        let (staging_buffer, staging_buffer_memory) = Self::create_buffer(
+            device,
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            device_memory_properties,
+        );
+
+        unsafe {
+            let data_ptr = device
+                .map_memory(staging_buffer_memory, 0, size, vk::MemoryMapFlags::empty())
+                .expect("Failed to Map staging buffer Memory") as *mut T;
+
+            data_ptr.copy_from_nonoverlapping(data.as_ptr(), data.len());
+
+            device.unmap_memory(staging_buffer_memory);
+        }
+
+        let (buffer, buffer_memory) = Self::create_buffer(
+            device,
+            size,
+            usage | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            device_memory_properties,
+        );
+
+        Self::copy_buffer(
+            device,
+            submit_queue,
+            command_pool,
+            staging_buffer,
+            buffer,
+            size,
+        );
+
+        unsafe { device.destroy_buffer(staging_buffer, None) };
+        unsafe { device.free_memory(staging_buffer_memory, None) };
+
+        (buffer, buffer_memory)
+    }
+
     fn create_uniform_buffers(
         device: &ash::Device,
         device_memory_properties: vk::PhysicalDeviceMemoryProperties,
@@ -1429,9 +2733,10 @@ impl HelloTriangleApplication {
                 .ty(vk::DescriptorType::UNIFORM_BUFFER)
                 .descriptor_count(size as u32)
                 .build(),
+            // The texture, the lightmap, the toon ramps and the shadow map's depth and moments
             vk::DescriptorPoolSize::builder()
                 .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                .descriptor_count(size as u32)
+                .descriptor_count(5 * size as u32)
                 .build(),
         ];
 
@@ -1475,6 +2780,9 @@ impl HelloTriangleApplication {
         descriptor_sets: &Vec<vk::DescriptorSet>,
         uniform_buffers: &Vec<vk::Buffer>,
         texture_image_view: vk::ImageView,
+        lightmap_image_view: vk::ImageView,
+        toon_ramps_image_view: vk::ImageView,
+        shadow_map: shadow_map::Descriptors,
         texture_sampler: vk::Sampler,
         size: usize,
     ) {
@@ -1490,6 +2798,26 @@ impl HelloTriangleApplication {
                 .image_view(texture_image_view)
                 .sampler(texture_sampler)
                 .build()];
+            let lightmap_info = [vk::DescriptorImageInfo::builder()
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .image_view(lightmap_image_view)
+                .sampler(texture_sampler)
+                .build()];
+            let shadow_depth_info = [vk::DescriptorImageInfo::builder()
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .image_view(shadow_map.depth.0)
+                .sampler(shadow_map.depth.1)
+                .build()];
+            let shadow_moments_info = [vk::DescriptorImageInfo::builder()
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .image_view(shadow_map.moments.0)
+                .sampler(shadow_map.moments.1)
+                .build()];
+            let toon_ramps_info = [vk::DescriptorImageInfo::builder()
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .image_view(toon_ramps_image_view)
+                .sampler(texture_sampler)
+                .build()];
 
             let write = [
                 vk::WriteDescriptorSet::builder()
@@ -1506,536 +2834,5772 @@ impl HelloTriangleApplication {
                     .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
                     .image_info(&image_info)
                     .build(),
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_sets[i])
+                    .dst_binding(2)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&lightmap_info)
+                    .build(),
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_sets[i])
+                    .dst_binding(3)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&shadow_depth_info)
+                    .build(),
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_sets[i])
+                    .dst_binding(4)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&shadow_moments_info)
+                    .build(),
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_sets[i])
+                    .dst_binding(5)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&toon_ramps_info)
+                    .build(),
             ];
 
             unsafe { device.update_descriptor_sets(&write, &[]) };
         }
     }
 
-    /// Allocates `num_buffers` command buffers to the given command pool on the given device. Records all commands required to render a frame from
-    /// the vertex and index data.
+    /// Allocates one command buffer per frame buffer from the given command pool. They are recorded each frame
+    /// by `record_command_buffer`.
     fn create_command_buffers(
         device: &ash::Device,
         command_pool: vk::CommandPool,
-        render_pass: vk::RenderPass,
         frame_buffers: &Vec<vk::Framebuffer>,
-        swap_chain_extent: vk::Extent2D,
-        graphics_pipeline: vk::Pipeline,
-        vertex_buffer: vk::Buffer,
-        index_buffer: vk::Buffer,
-        pipeline_layout: vk::PipelineLayout,
-        descriptor_sets: &Vec<vk::DescriptorSet>,
     ) -> Vec<vk::CommandBuffer> {
-        let num_buffers = frame_buffers.len();
-        if frame_buffers.len() != num_buffers {
-            panic!("Must have same number of command buffers as frame buffers")
-        }
-
         let ci = vk::CommandBufferAllocateInfo::builder()
             .command_pool(command_pool)
             // Primary command buffer is submitted directly to queue, cannot be called from other command buffers.
             .level(vk::CommandBufferLevel::PRIMARY)
-            .command_buffer_count(num_buffers as u32);
+            .command_buffer_count(frame_buffers.len() as u32);
 
-        let buffers = unsafe {
+        unsafe {
             device
                 .allocate_command_buffers(&ci)
                 .expect("Command buffers")
-        };
+        }
+    }
 
-        for i in range(0, num_buffers) {
-            let index = i as usize;
-            let buffer = buffers[index];
-            let frame_buffer = frame_buffers[index];
+    fn create_baked_draws(
+        device: &ash::Device,
+        queue_families: &QueueFamilyIndices,
+        image_count: usize,
+    ) -> jobs::BakedBuffers<BakedScene> {
+        jobs::BakedBuffers::new(device, queue_families.graphics_family.unwrap(), image_count)
+    }
 
-            let bi = vk::CommandBufferBeginInfo::builder();
+    /// A secondary command buffer for each of rayon's threads per image, and one more for the render thread.
+    fn create_secondary_buffers(
+        device: &ash::Device,
+        queue_families: &QueueFamilyIndices,
+        image_count: usize,
+    ) -> jobs::SecondaryBuffers {
+        jobs::SecondaryBuffers::new(
+            device,
+            queue_families.graphics_family.unwrap(),
+            image_count,
+            rayon::current_num_threads() + 1,
+        )
+    }
 
-            unsafe {
-                device
-                    .begin_command_buffer(buffer, &bi)
-                    .expect("Recording command buffer")
-            };
+    /// Records all commands required to render a frame from the current draws into the image's command buffer.
+    /// Draws can move from one frame to the next so this happens every frame, once the commands previously
+    /// recorded for the image have finished executing.
+    ///
+    /// The render pass is recorded into secondary command buffers. The visible draws are split between all but
+    /// the last of them and recorded in parallel. The last is recorded on this thread with everything else in
+    /// the render pass, since hooks and features aren't shared between threads.
+    fn record_command_buffer(&mut self, image_index: usize) {
+        let mut timings = mem::take(&mut self.timings);
+        let mut scratch = mem::take(&mut self.frame_scratch);
+        let FrameScratch {
+            culling,
+            visible,
+            outgoing,
+            left_visible,
+            minimap_visible,
+            shadow_visible,
+            shadow_casters,
+            probe_visible,
+            baked,
+            executed,
+            ground_shadows,
+            trails,
+        } = &mut scratch;
+        visible.clear();
+        outgoing.clear();
+        left_visible.clear();
+        minimap_visible.clear();
+        shadow_visible.clear();
+        shadow_casters.clear();
+        probe_visible.resize_with(self.probes.len(), Vec::new);
+        probe_visible.iter_mut().for_each(Vec::clear);
+        // Drawn without the scene while the playground replaces it, like the probes
+        let minimap_due = self
+            .minimap
+            .iter()
+            .any(|minimap| self.schedule.scheduled(minimap.task).is_some());
+        // The playground replaces the scene
+        match self.playground {
+            Some(_) => {}
+            None => {
+                let outgoing_scene = self
+                    .features
+                    .get_mut::<transition::Transitions>()
+                    .and_then(|transitions| transitions.outgoing_scene());
+                let (view, projection) = self.eye_view_projection(stereo::Eye::Right);
+                let clip = projection * view;
+                // Portals are looked through from a point, which an orthographic camera doesn't have
+                let eye = match self.camera.projection {
+                    camera::Projection::Perspective => Some(self.camera.eye()),
+                    camera::Projection::Orthographic => None,
+                };
+                // The left eye is culled on its own, since its frustum reaches past the right eye's on its side
+                if self.stereo.is_some() {
+                    let (view, projection) = self.eye_view_projection(stereo::Eye::Left);
+                    let clip = projection * view;
+                    self.visible_draws(self.scene, &clip, eye, &mut timings, culling, left_visible);
+                }
+                self.visible_draws(self.scene, &clip, eye, &mut timings, culling, visible);
+                if let Some(scene) = outgoing_scene {
+                    self.visible_draws(scene, &clip, eye, &mut timings, culling, outgoing);
+                }
+                if minimap_due {
+                    let (view, projection) = self.minimap_view_projection();
+                    let clip = projection * view;
+                    self.visible_draws(
+                        self.scene,
+                        &clip,
+                        None,
+                        &mut timings,
+                        culling,
+                        minimap_visible,
+                    );
+                }
+                if let Some(light) = self.shadow_light_view() {
+                    let clip = light.projection * light.view;
+                    self.visible_draws(
+                        self.scene,
+                        &clip,
+                        None,
+                        &mut timings,
+                        culling,
+                        shadow_visible,
+                    );
+                }
+                for (probe, visible) in self.probes.iter().zip(probe_visible.iter_mut()) {
+                    if let Some(face) = self.schedule.scheduled(probe.task) {
+                        let (view, projection) = probe.face_view_projection(face);
+                        let clip = projection * view;
+                        let eye = Some(probe.position);
+                        self.visible_draws(self.scene, &clip, eye, &mut timings, culling, visible);
+                    }
+                }
+            }
+        }
+        self.pipelines.set_clip_caps(self.clipping.caps());
+        for &index in visible
+            .iter()
+            .chain(outgoing.iter())
+            .chain(left_visible.iter())
+            .chain(minimap_visible.iter())
+            .chain(probe_visible.iter().flatten())
+        {
+            self.draws[index].prepare(&self.logical_device, &mut self.pipelines);
+        }
+        self.ground_shadow_draws(ground_shadows);
+        // Drawn from the same eye as the ground shadows
+        trails.clear();
+        if self.stereo.is_none() && self.playground.is_none() {
+            let scene = self.scene;
+            self.trail_ghosts.write(
+                &self.logical_device,
+                &self.physical_device_memory_properties,
+                image_index,
+                self.draws.iter().filter(|draw| draw.scene == scene),
+                &self.model,
+                trails,
+            );
+        }
+        for extra in ground_shadows.iter().chain(trails.iter()) {
+            extra.prepare(&self.logical_device, &mut self.pipelines);
+        }
+        if let Some(shadow_map) = self.shadow_map.as_mut() {
+            let (draws, bias) = (&self.draws, shadow_map.depth_bias());
+            shadow_casters.extend(
+                shadow_visible
+                    .iter()
+                    .filter_map(|&index| draws[index].shadow_caster(bias)),
+            );
+            for caster in shadow_casters.iter() {
+                caster.prepare(&self.logical_device, &mut shadow_map.pipelines);
+            }
+        }
 
-            let clear_values = [
-                vk::ClearValue {
-                    color: vk::ClearColorValue {
-                        float32: [0.0, 0.0, 0.0, 1.0],
-                    },
-                },
-                vk::ClearValue {
-                    depth_stencil: vk::ClearDepthStencilValue {
-                        depth: 1.0,
-                        stencil: 0,
-                    },
-                },
-            ];
+        let device = &self.logical_device;
+        let buffer = self.command_buffers[image_index];
+        let frame_buffer = self.swap_chain_frame_buffers[image_index];
+        let swap_chain_extent = self.swapchain_data.extent;
 
-            let render_pass_bi = vk::RenderPassBeginInfo::builder()
-                .render_pass(render_pass)
-                .framebuffer(frame_buffer)
-                .render_area(vk::Rect2D {
-                    offset: vk::Offset2D { x: 0, y: 0 },
-                    extent: swap_chain_extent,
-                })
-                .clear_values(&clear_values);
+        // The command pool allows buffers to be reset individually, beginning the buffer resets it
+        let bi = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
 
-            unsafe {
-                // Inline means render pass commands will be in primary command buffer as opposed to SECONDARY_COMMAND_BUFFERS
-                // where render pass commands are in secondary buffer
-                device.cmd_begin_render_pass(buffer, &render_pass_bi, vk::SubpassContents::INLINE);
-                device.cmd_bind_pipeline(
-                    buffer,
-                    vk::PipelineBindPoint::GRAPHICS,
-                    graphics_pipeline,
-                );
+        unsafe {
+            device
+                .begin_command_buffer(buffer, &bi)
+                .expect("Recording command buffer")
+        };
+        self.particles.acquire(device, buffer, self.current_frame);
+
+        // Taken so the passes can be counted and timed while the rest of the renderer records them
+        let mut statistics = self.statistics.take();
+        let mut frame_budget = self.frame_budget.take();
+        let inherited_statistics = match statistics {
+            Some(_) => statistics::FLAGS,
+            None => vk::QueryPipelineStatisticFlags::empty(),
+        };
+        // Each pass's queries are ended as the next begins
+        let mut count_pass = {
+            let mut counting = None;
+            let statistics = statistics.as_mut().map(|statistics| {
+                statistics.begin_frame(device, buffer, image_index);
+                &*statistics
+            });
+            let timer = frame_budget
+                .as_mut()
+                .and_then(|monitor| monitor.timer.as_mut())
+                .map(|timer| {
+                    timer.begin_frame(device, buffer, image_index);
+                    &*timer
+                });
+            move |pass: Option<statistics::Pass>| {
+                if let Some(previous) = counting {
+                    if let Some(statistics) = statistics {
+                        statistics.end(device, buffer, image_index, previous);
+                    }
+                    if let Some(timer) = timer {
+                        timer.end(device, buffer, image_index, previous);
+                    }
+                }
+                if let Some(pass) = pass {
+                    if let Some(statistics) = statistics {
+                        statistics.begin(device, buffer, image_index, pass);
+                    }
+                    if let Some(timer) = timer {
+                        timer.begin(device, buffer, image_index, pass);
+                    }
+                }
+                counting = pass;
+            }
+        };
 
-                let buffers = [vertex_buffer];
-                let offsets = [0];
-                device.cmd_bind_vertex_buffers(buffer, 0, &buffers, &offsets);
-                device.cmd_bind_index_buffer(buffer, index_buffer, 0, vk::IndexType::UINT16);
+        count_pass(Some(statistics::Pass::Compute));
+        if self.playground.is_none() {
+            let (view, projection) = self.eye_view_projection(stereo::Eye::Right);
+            let clip = projection * view * self.model;
+            if let Some(scene) = self.indirect_scene.as_mut() {
+                scene.record_cull(device, buffer, &clip);
+            }
+            if let Some(cloth) = self.cloth.as_mut() {
+                cloth.record_update(device, buffer);
+            }
+            if let Some(swarm) = self.swarm.as_mut() {
+                swarm.record_update(device, buffer);
+            }
+        }
+        count_pass(None);
+
+        let full_frame = vk::Rect2D::builder().extent(swap_chain_extent).build();
+        let scene_state = SceneState {
+            area: self.stereo.as_ref().map_or(full_frame, |stereo| {
+                stereo.settings.area(stereo::Eye::Right, swap_chain_extent)
+            }),
+            pipeline_layout: self.pipelines.layout(),
+            descriptor_set: self.descriptor_sets[image_index],
+            default_attribute_buffer: self.default_attribute_buffer,
+        };
+        // Drawn every frame before anything shades with it, without casters while the playground replaces the
+        // scene, so that it's always been drawn when it's sampled
+        if let Some(shadow_map) = self.shadow_map.as_ref() {
+            let light_state = SceneState {
+                area: vk::Rect2D::builder().extent(shadow_map.extent()).build(),
+                pipeline_layout: shadow_map.pipelines.layout(),
+                descriptor_set: shadow_map.view.descriptor_sets[image_index],
+                ..scene_state
+            };
+            shadow_map.record(device, buffer, |command_buffer| {
+                light_state.record(device, command_buffer);
+                for caster in shadow_casters.iter() {
+                    caster.record(device, command_buffer, &shadow_map.pipelines);
+                }
+            });
+        }
+        if !outgoing.is_empty() {
+            if let Some(transitions) = self.features.get_mut::<transition::Transitions>() {
+                let (draws, pipelines) = (&self.draws, &self.pipelines);
+                transitions.record_outgoing(device, buffer, |command_buffer| {
+                    scene_state.record(device, command_buffer);
+                    for &index in outgoing.iter() {
+                        draws[index].record(device, command_buffer, pipelines);
+                    }
+                });
+            }
+        }
+        if let (Some(stereo), None) = (self.stereo.as_ref(), self.playground.as_ref()) {
+            let left_state = SceneState {
+                area: stereo.settings.area(stereo::Eye::Left, swap_chain_extent),
+                descriptor_set: stereo.left.descriptor_sets[image_index],
+                ..scene_state
+            };
+            let (draws, pipelines) = (&self.draws, &self.pipelines);
+            let clear_values = self.clear_values(pipeline::RenderTarget::Scene);
+            stereo.record_left(device, buffer, &clear_values, |command_buffer| {
+                left_state.record(device, command_buffer);
+                for &index in left_visible.iter() {
+                    draws[index].record(device, command_buffer, pipelines);
+                }
+            });
+        }
+        // Cleared the first time, so they can be read before they've been drawn
+        let (minimap_color, probes_color) = (
+            self.clear_color(pipeline::RenderTarget::Minimap),
+            self.clear_color(pipeline::RenderTarget::Probes),
+        );
+        for minimap in self.minimap.iter_mut() {
+            minimap.record_clear(device, buffer, &minimap_color);
+        }
+        for probe in self.probes.iter_mut() {
+            probe.record_clear(device, buffer, &probes_color);
+        }
+        let (minimap_clear, probes_clear) = (
+            self.clear_values(pipeline::RenderTarget::Minimap),
+            self.clear_values(pipeline::RenderTarget::Probes),
+        );
+        // Drawn before the frame's render pass, where the 2D layer shows it
+        if let (Some(minimap), true) = (self.minimap.as_mut(), minimap_due) {
+            let map_state = SceneState {
+                area: vk::Rect2D::builder().extent(minimap.extent()).build(),
+                descriptor_set: minimap.view.descriptor_sets[image_index],
+                ..scene_state
+            };
+            let (draws, pipelines) = (&self.draws, &self.pipelines);
+            minimap.record(device, buffer, &minimap_clear, |command_buffer| {
+                map_state.record(device, command_buffer);
+                for &index in minimap_visible.iter() {
+                    draws[index].record(device, command_buffer, pipelines);
+                }
+            });
+        }
+        for (probe, visible) in self.probes.iter_mut().zip(probe_visible.iter()) {
+            let face = match self.schedule.scheduled(probe.task) {
+                Some(face) => face,
+                None => continue,
+            };
+            let face_state = SceneState {
+                area: vk::Rect2D::builder().extent(probe.extent()).build(),
+                descriptor_set: probe.view.descriptor_sets[image_index],
+                ..scene_state
+            };
+            let (draws, pipelines) = (&self.draws, &self.pipelines);
+            probe.record_face(device, buffer, face, &probes_clear, |command_buffer| {
+                face_state.record(device, command_buffer);
+                for &index in visible.iter() {
+                    draws[index].record(device, command_buffer, pipelines);
+                }
+            });
+        }
 
-                let sets = [descriptor_sets[i]];
-                device.cmd_bind_descriptor_sets(
-                    buffer,
-                    vk::PipelineBindPoint::GRAPHICS,
-                    pipeline_layout,
-                    0,
-                    &sets,
-                    &[],
+        let secondary_buffers = self.secondary_buffers.reset(device, image_index);
+        let (&main_secondary, draw_secondaries) = secondary_buffers
+            .split_last()
+            .expect("Secondary command buffers");
+        let render_pass = self.render_pass;
+        let (draws, pipelines) = (&self.draws, &self.pipelines);
+        executed.clear();
+        match self.baked_draws.as_mut() {
+            Some(baked_draws) => {
+                let mut key = mem::take(baked);
+                key.clear();
+                key.extend(
+                    visible
+                        .iter()
+                        .map(|&index| draws[index].recorded(pipelines)),
                 );
-
-                device.cmd_draw_indexed(buffer, QUAD_INDICES.len() as u32, 1, 0, 0, 0);
-
-                device.cmd_end_render_pass(buffer);
-
-                device
-                    .end_command_buffer(buffer)
-                    .expect("Ending command buffer")
+                let baked_scene = BakedScene {
+                    state: scene_state,
+                    pipeline_statistics: inherited_statistics,
+                    draws: key,
+                };
+                let (secondary, stale, unused) = baked_draws.get(device, image_index, baked_scene);
+                if let Some(unused) = unused {
+                    *baked = unused.draws;
+                }
+                if stale {
+                    timings.time("recording", || {
+                        jobs::begin_baked_secondary(
+                            device,
+                            secondary,
+                            render_pass,
+                            frame_buffer,
+                            inherited_statistics,
+                        );
+                        scene_state.record(device, secondary);
+                        for &index in visible.iter() {
+                            draws[index].record(device, secondary, pipelines);
+                        }
+                        unsafe {
+                            device
+                                .end_command_buffer(secondary)
+                                .expect("Ending baked command buffer")
+                        };
+                    });
+                }
+                executed.push(secondary);
+            }
+            None => {
+                timings.time("recording", || {
+                    let chunk_size = visible.len().div_ceil(draw_secondaries.len()).max(1);
+                    draw_secondaries
+                        .par_iter()
+                        .enumerate()
+                        .for_each(|(chunk, &secondary)| {
+                            jobs::begin_secondary(
+                                device,
+                                secondary,
+                                render_pass,
+                                frame_buffer,
+                                inherited_statistics,
+                            );
+                            scene_state.record(device, secondary);
+                            for &index in visible.chunks(chunk_size).nth(chunk).unwrap_or(&[]) {
+                                draws[index].record(device, secondary, pipelines);
+                            }
+                            unsafe {
+                                device
+                                    .end_command_buffer(secondary)
+                                    .expect("Ending secondary command buffer")
+                            };
+                        });
+                });
+                executed.extend_from_slice(draw_secondaries);
             }
         }
+        executed.push(main_secondary);
 
-        buffers
-    }
-
-    fn create_synchronisation_primitives(
-        device: &ash::Device,
-    ) -> (Vec<vk::Semaphore>, Vec<vk::Semaphore>, Vec<vk::Fence>) {
-        let mut image_available_semaphores: Vec<vk::Semaphore> = Vec::new();
-        let mut render_complete_semaphores: Vec<vk::Semaphore> = Vec::new();
-        let mut in_flight_fences: Vec<vk::Fence> = Vec::new();
-
-        for _ in num::range(0, MAX_FRAMES_IN_FLIGHT) {
-            let (image_semaphore, render_semaphore, frame_fence) = unsafe {
-                (
-                    device
-                        .create_semaphore(&vk::SemaphoreCreateInfo::builder(), None)
-                        .expect("Image Semaphore"),
-                    device
-                        .create_semaphore(&vk::SemaphoreCreateInfo::builder(), None)
-                        .expect("Render Semaphore"),
-                    device
-                        .create_fence(
-                            &vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED),
-                            None,
-                        )
-                        .expect("Frame fence"),
-                )
-            };
-            image_available_semaphores.push(image_semaphore);
-            render_complete_semaphores.push(render_semaphore);
-            in_flight_fences.push(frame_fence);
+        jobs::begin_secondary(
+            device,
+            main_secondary,
+            render_pass,
+            frame_buffer,
+            inherited_statistics,
+        );
+        scene_state.record(device, main_secondary);
+        match self.playground.as_mut() {
+            Some(playground) => playground.record(device, main_secondary, swap_chain_extent),
+            None => {
+                if let Some(scene) = self.indirect_scene.as_ref() {
+                    scene.record_draw(device, main_secondary, &mut self.pipelines);
+                }
+                // The other secondary buffers are executed first, so the shadows are blended over the ground
+                // and the trails over both
+                for extra in ground_shadows.iter().chain(trails.iter()) {
+                    extra.record(device, main_secondary, &self.pipelines);
+                }
+                if let Some(stereo) = self.stereo.as_ref() {
+                    stereo.record_composite(device, main_secondary);
+                    // What draws after the scene covers the whole frame
+                    SceneState {
+                        area: full_frame,
+                        ..scene_state
+                    }
+                    .record(device, main_secondary);
+                }
+            }
         }
 
-        (
-            image_available_semaphores,
-            render_complete_semaphores,
-            in_flight_fences,
-        )
-    }
+        // A compatible pass of its own clears images that have nothing to preserve yet
+        let scene_render_pass = match (self.images_drawn[image_index], self.first_render_pass) {
+            (false, Some(first)) => first,
+            _ => self.render_pass,
+        };
+        self.images_drawn[image_index] = true;
+
+        // Taken so they can be given a context borrowing the rest of the renderer
+        let mut features = mem::take(&mut self.features);
+        let mut hooks = mem::take(&mut self.hooks);
+        let mut run_stage = |stage: hooks::Stage, command_buffer: vk::CommandBuffer| {
+            let context = self.frame_context(stage, command_buffer, image_index);
+            features.record(&context);
+            hooks.run(&context);
+        };
 
-    /**
-    Main loop
-    */
-    fn init_window(event_loop: &EventLoop<()>) -> winit::window::Window {
-        winit::window::WindowBuilder::new()
-            .with_title(APP_TITLE)
-            .with_inner_size(winit::dpi::LogicalSize::new(WINDOW_WIDTH, WINDOW_HEIGHT))
-            .build(event_loop)
-            .expect("Failed to create window.")
-    }
+        run_stage(hooks::Stage::AfterOpaque, main_secondary);
+        unsafe {
+            self.logical_device
+                .end_command_buffer(main_secondary)
+                .expect("Ending secondary command buffer")
+        };
 
-    /**
-     * recreate_swapchain re-creates the swapchain and all structures that are dependent on it.
-     */
-    fn recreate_swapchain(&mut self) {
+        let clear_values = self.clear_values(pipeline::RenderTarget::Scene);
+        let render_pass_bi = vk::RenderPassBeginInfo::builder()
+            .render_pass(scene_render_pass)
+            .framebuffer(frame_buffer)
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: swap_chain_extent,
+            })
+            .clear_values(&clear_values);
+
+        count_pass(Some(statistics::Pass::Scene));
+        unsafe {
+            // The render pass's commands are all in the secondary command buffers
+            self.logical_device.cmd_begin_render_pass(
+                buffer,
+                &render_pass_bi,
+                vk::SubpassContents::SECONDARY_COMMAND_BUFFERS,
+            );
+            self.logical_device.cmd_execute_commands(buffer, executed);
+            self.logical_device.cmd_end_render_pass(buffer);
+        }
+        count_pass(Some(statistics::Pass::BeforePost));
+        run_stage(hooks::Stage::BeforePost, buffer);
+        count_pass(Some(statistics::Pass::PostProcess));
+        run_stage(hooks::Stage::PostProcess, buffer);
+        count_pass(Some(statistics::Pass::Ui));
+        run_stage(hooks::Stage::AfterUi, buffer);
+        count_pass(None);
+        self.statistics = statistics;
+        self.frame_budget = frame_budget;
+
+        self.features = features;
+        self.hooks = hooks;
+        timings.end_frame();
+        self.timings = timings;
+        self.frame_scratch = scratch;
+
+        if let Some(acquire) = self.present_acquire.as_ref() {
+            acquire.release(
+                &self.logical_device,
+                buffer,
+                self.swapchain_data.images[image_index],
+            );
+        }
         unsafe {
             self.logical_device
-                .device_wait_idle()
-                .expect("Waiting for device to be idle")
+                .end_command_buffer(buffer)
+                .expect("Ending command buffer")
+        }
+    }
+
+    /// The colour and depth the target is cleared to
+    fn clear_values(&self, target: pipeline::RenderTarget) -> [vk::ClearValue; 2] {
+        [
+            vk::ClearValue {
+                color: self.clear_color(target),
+            },
+            vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            },
+        ]
+    }
+
+    /// The target's clear colour, the background unless its pass config has one of its own, as it's cleared to in
+    /// targets with the swapchain's format
+    fn clear_color(&self, target: pipeline::RenderTarget) -> vk::ClearColorValue {
+        let color = self
+            .passes
+            .get(target)
+            .clear_color
+            .unwrap_or(self.background);
+        // Cleared colours aren't encoded by UNORM swapchains, like the colours shaders write
+        let [red, green, blue] = match util::is_srgb_format(self.swapchain_data.format) {
+            true => color,
+            false => color.map(util::encode_srgb),
         };
+        vk::ClearColorValue {
+            float32: [red, green, blue, 1.0],
+        }
+    }
 
-        self.cleanup_swapchain();
+    /// Writes the scene's draws that may be in view of `clip` to `visible`, in the order they should be drawn,
+    /// culling them in the buffers given. Opaque draws go first, nearest first so that hidden fragments fail the
+    /// depth test early, and blended draws follow furthest first so they
+    /// blend over what is behind them. Draws without bounds are always drawn. When there are portals and the
+    /// camera looks from `eye`, draws in cells it can't see through them are culled too.
+    fn visible_draws(
+        &self,
+        scene: usize,
+        clip: &Matrix4<f32>,
+        eye: Option<Point3<f32>>,
+        timings: &mut jobs::Timings,
+        buffers: &mut culling::Buffers,
+        visible: &mut Vec<usize>,
+    ) {
+        let frustum = bounds::Frustum::new(clip);
+        let draws = &self.draws;
+        let visibility = self.portals.as_ref().and_then(|portals| {
+            timings.time("portals", || {
+                portals.visibility(&self.model, eye?, &frustum)
+            })
+        });
 
-        let swapchain_data = Self::create_swap_chain(
-            &self.instance,
-            &self.logical_device,
-            &self.surface_loader,
-            &self.physical_device,
-            &self.surface,
-            &self.window,
-            &self.queue_families,
+        timings.time("culling", || {
+            buffers.test(draws.len(), |index| {
+                let draw = &draws[index];
+                // Removed draws are left in place as empty draws so the indices of the others don't change
+                if draw.scene != scene || draw.count == 0 || draw.instance_count == 0 {
+                    return None;
+                }
+                let depth = match draw.bounds {
+                    Some(bounds) if !frustum.intersects(&bounds.world) => return None,
+                    Some(bounds)
+                        if !visibility
+                            .as_ref()
+                            .is_none_or(|visibility| visibility.may_see(&bounds.world)) =>
+                    {
+                        return None
+                    }
+                    Some(bounds) => (clip * bounds.world.center().to_homogeneous()).w,
+                    None => 0.0,
+                };
+                Some((draw.pipeline.alpha_blend, depth))
+            })
+        });
+        if let Some(settings) = self.software_occlusion.as_ref() {
+            timings.time("occlusion", || self.cull_occluded(buffers, clip, settings));
+        }
+        timings.time("sorting", || buffers.sort(visible));
+    }
+
+    /// Leaves out the visible draws that are hidden behind the largest of them, by drawing those into a depth
+    /// buffer on the CPU and testing the bounds of the rest against it. See `occluders::DepthBuffer`. The
+    /// occluders and depth buffer are kept in the culling buffers between frames.
+    fn cull_occluded(
+        &self,
+        buffers: &mut culling::Buffers,
+        clip: &Matrix4<f32>,
+        settings: &occluders::Settings,
+    ) {
+        let min_radius =
+            self.scene_bounds().map_or(0.0, |bounds| bounds.radius()) * settings.min_size;
+        let mut scratch = mem::take(&mut buffers.occlusion);
+        // Occluders must be solid and drawn where their collider is
+        scratch.occluders.clear();
+        scratch
+            .occluders
+            .extend(buffers.kept().iter().filter_map(|&(index, blend, _)| {
+                let draw = &self.draws[index];
+                let radius = draw.bounds?.world.radius();
+                let solid = !blend && draw.pipeline.alpha_cutoff.is_none();
+                let placed = draw.instance_count == 1 && draw.animation.is_none();
+                (solid && placed && draw.collider.is_some() && radius >= min_radius)
+                    .then_some((index, radius))
+            }));
+        let occluders = &mut scratch.occluders;
+        occluders.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+        occluders.truncate(settings.max_occluders);
+
+        let depth = occluders::DepthBuffer::rasterize(
+            clip,
+            occluders.iter().filter_map(|&(index, _)| {
+                let draw = &self.draws[index];
+                Some((draw.collider.as_deref()?, draw.world_transform(&self.model)))
+            }),
+            mem::take(&mut scratch.depth),
         );
-        self.swapchain_data = swapchain_data;
+        let draws = &self.draws;
+        buffers.retain(|index| {
+            occluders.iter().any(|&(occluder, _)| occluder == index)
+                || draws[index]
+                    .bounds
+                    .is_none_or(|bounds| !depth.occludes(clip, &bounds.world))
+        });
+        scratch.depth = depth.into_storage();
+        buffers.occlusion = scratch;
+    }
 
-        self.swapchain_image_views =
-            Self::create_swapchain_image_views(&self.logical_device, &self.swapchain_data);
+    /// Draws the scene for two eyes and puts them together into the frame, as an anaglyph or side by side, see
+    /// `stereo::Stereo`. `None` draws the camera's view alone again.
+    pub fn set_stereo(&mut self, settings: Option<stereo::Settings>) {
+        present_thread::device_wait_idle(&self.logical_device)
+            .expect("Waiting for device to be idle");
+        if let Some(mut stereo) = self.stereo.take() {
+            stereo.destroy(&self.logical_device);
+        }
+        self.stereo = settings.map(|settings| self.create_stereo(settings));
+    }
 
-        self.render_pass = Self::create_render_pass(
-            &self.instance,
-            self.physical_device,
+    /// The left eye's target and its own uniform buffers and descriptor sets for each swapchain image
+    fn create_stereo(&self, settings: stereo::Settings) -> stereo::Stereo {
+        let image_count = self.swapchain_image_views.len();
+        let (uniform_buffers, uniform_buffers_memory) = Self::create_uniform_buffers(
             &self.logical_device,
-            self.swapchain_data.format,
+            self.physical_device_memory_properties,
+            image_count,
         );
-
-        let (graphics_pipeline, pipeline_layout) = Self::create_graphics_pipeline(
+        let descriptor_pool = Self::create_descriptor_pool(&self.logical_device, image_count);
+        let descriptor_sets = Self::create_descriptor_sets(
             &self.logical_device,
-            self.swapchain_data.extent,
-            self.render_pass,
+            descriptor_pool,
             self.descriptor_set_layout,
+            image_count,
         );
-        self.graphics_pipeline = graphics_pipeline;
-        self.pipeline_layout = pipeline_layout;
-
-        (
-            self.depth_image,
-            self.depth_image_memory,
-            self.depth_image_view,
-        ) = Self::create_depth_resources(
-            &self.instance,
-            self.physical_device,
-            &self.physical_device_memory_properties,
+        Self::populate_descriptor_sets(
             &self.logical_device,
-            self.graphics_queue,
-            self.command_pool,
-            self.swapchain_data.extent,
+            &descriptor_sets,
+            &uniform_buffers,
+            self.scene_texture_view(),
+            self.lightmap_image_view(),
+            self.toon_ramps_image_view(),
+            self.shadow_descriptors(),
+            self.texture_sampler,
+            image_count,
         );
+        stereo::Stereo::new(
+            &self.swapchain_context(),
+            &Self::pipeline_target(&self.swapchain_data, self.render_pass),
+            settings,
+            stereo::LeftEye {
+                uniform_buffers,
+                uniform_buffers_memory,
+                descriptor_pool,
+                descriptor_sets,
+            },
+        )
+    }
 
-        self.swap_chain_frame_buffers = Self::create_frame_buffers(
-            &self.logical_device,
-            &self.swapchain_image_views,
-            self.depth_image_view,
-            self.swapchain_data.extent,
-            self.render_pass,
-        );
+    /// Draws a top-down map of the scene around the camera's target into the top right corner of the frame, see
+    /// `minimap::Minimap`. `None` stops drawing it. Needs the text and shapes feature, which shows the map.
+    pub fn set_minimap(&mut self, settings: Option<minimap::Settings>) -> Result<(), String> {
+        present_thread::device_wait_idle(&self.logical_device)
+            .expect("Waiting for device to be idle");
+        if let Some(mut minimap) = self.minimap.take() {
+            if let (Some(layer), Some(image)) =
+                (self.features.get_mut::<sdf::Layer>(), minimap.image)
+            {
+                layer.remove_image(&self.logical_device, image);
+            }
+            self.schedule.remove(minimap.task);
+            minimap.destroy(&self.logical_device);
+        }
+        if let Some(settings) = settings {
+            let task = self.schedule.add("minimap", 1, settings.interval);
+            self.minimap = Some(self.create_minimap(settings, task));
+            if let Err(error) = self.show_minimap(None) {
+                if let Some(mut minimap) = self.minimap.take() {
+                    self.schedule.remove(task);
+                    minimap.destroy(&self.logical_device);
+                }
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    /// The minimap's target and its own uniform buffers and descriptor sets for each swapchain image
+    fn create_minimap(&self, settings: minimap::Settings, task: usize) -> minimap::Minimap {
+        minimap::Minimap::new(
+            &self.swapchain_context(),
+            settings,
+            self.create_scheduled_view(),
+            task,
+            &self.passes.minimap,
+        )
+    }
+
+    /// Makes the minimap and the probes again, to be drawn again from the start, after the swapchain or their
+    /// pass configs have changed. Their old targets must have been destroyed, or be no longer in use.
+    fn recreate_scheduled_renders(&mut self) {
+        // Made again at the new scale factor, and moved to the frame's new corner
+        if let Some((settings, task, image)) = self
+            .minimap
+            .as_ref()
+            .map(|minimap| (minimap.settings, minimap.task, minimap.image))
+        {
+            self.minimap = Some(self.create_minimap(settings, task));
+            self.schedule.invalidate(task);
+            self.show_minimap(image).expect("Showing the minimap");
+        }
+        // Made again in case the formats have changed
+        for index in 0..self.probes.len() {
+            let probe = &self.probes[index];
+            let (position, size, interval, task) =
+                (probe.position, probe.size, probe.interval, probe.task);
+            self.probes[index] = probes::Probe::new(
+                &self.swapchain_context(),
+                position,
+                size,
+                interval,
+                self.create_scheduled_view(),
+                task,
+                &self.passes.probes,
+            );
+            self.schedule.invalidate(task);
+        }
+    }
 
+    /// Uniform buffers and descriptor sets for each swapchain image of a render the scheduler draws, pointing at
+    /// the scene's textures like the renderer's own
+    fn create_scheduled_view(&self) -> schedule::View {
+        self.create_view(self.shadow_descriptors())
+    }
+
+    /// Uniform buffers and descriptor sets for each swapchain image, pointing at the scene's textures and
+    /// `shadow_map`
+    fn create_view(&self, shadow_map: shadow_map::Descriptors) -> schedule::View {
+        let image_count = self.swapchain_image_views.len();
         let (uniform_buffers, uniform_buffers_memory) = Self::create_uniform_buffers(
             &self.logical_device,
             self.physical_device_memory_properties,
-            self.swapchain_image_views.len(),
+            image_count,
         );
-        self.uniform_buffers = uniform_buffers;
-        self.uniform_buffers_memory = uniform_buffers_memory;
-
-        self.descriptor_pool =
-            Self::create_descriptor_pool(&self.logical_device, self.swapchain_image_views.len());
-        self.descriptor_sets = Self::create_descriptor_sets(
+        let descriptor_pool = Self::create_descriptor_pool(&self.logical_device, image_count);
+        let descriptor_sets = Self::create_descriptor_sets(
             &self.logical_device,
-            self.descriptor_pool,
+            descriptor_pool,
             self.descriptor_set_layout,
-            self.swapchain_image_views.len(),
+            image_count,
         );
         Self::populate_descriptor_sets(
             &self.logical_device,
-            &self.descriptor_sets,
-            &self.uniform_buffers,
-            self.texture_image_view,
+            &descriptor_sets,
+            &uniform_buffers,
+            self.scene_texture_view(),
+            self.lightmap_image_view(),
+            self.toon_ramps_image_view(),
+            shadow_map,
             self.texture_sampler,
-            self.swapchain_image_views.len(),
+            image_count,
         );
+        schedule::View {
+            uniform_buffers,
+            uniform_buffers_memory,
+            descriptor_pool,
+            descriptor_sets,
+        }
+    }
 
-        self.command_buffers = Self::create_command_buffers(
+    /// Adds the minimap's texture and the item showing it to the 2D layer, in place of the layer's `previous`
+    /// image from before the minimap was made again. The device must be idle.
+    fn show_minimap(&mut self, previous: Option<usize>) -> Result<(), String> {
+        let width = self.swapchain_data.extent.width as f32 / self.scale_factor;
+        let encoded = !util::is_srgb_format(self.swapchain_data.format);
+        let (layer, minimap) = match (self.features.get_mut::<sdf::Layer>(), self.minimap.as_mut())
+        {
+            (Some(layer), Some(minimap)) => (layer, minimap),
+            (None, _) => {
+                return Err(String::from(
+                    "The minimap can't be shown without the text and shapes feature",
+                ))
+            }
+            (_, None) => return Ok(()),
+        };
+        if let Some(previous) = previous {
+            layer.remove_image(&self.logical_device, previous);
+        }
+        let image = layer.add_image(
             &self.logical_device,
-            self.command_pool,
-            self.render_pass,
-            &self.swap_chain_frame_buffers,
-            self.swapchain_data.extent,
-            self.graphics_pipeline,
-            self.vertex_buffer,
-            self.index_buffer,
-            self.pipeline_layout,
-            &self.descriptor_sets,
-        );
+            "minimap",
+            minimap.image_view(),
+            encoded,
+        )?;
+        layer.add(minimap.settings.item(image, width));
+        minimap.image = Some(image);
+        Ok(())
     }
 
-    fn cleanup_swapchain(&mut self) {
-        unsafe {
-            for &frame_buffer in self.swap_chain_frame_buffers.iter() {
-                self.logical_device.destroy_framebuffer(frame_buffer, None)
-            }
+    /// The view and projection of the minimap, looking down onto the camera's target
+    fn minimap_view_projection(&self) -> (Matrix4<f32>, Matrix4<f32>) {
+        match self.minimap.as_ref() {
+            Some(minimap) => minimap.settings.view_projection(
+                self.camera.target,
+                self.camera.target - self.camera.eye(),
+                self.units.up,
+                self.scene_bounds(),
+            ),
+            None => (self.view, self.projection()),
+        }
+    }
 
-            for &buffer in self.uniform_buffers.iter() {
-                self.logical_device.destroy_buffer(buffer, None)
+    /// Shadows the scene's light with a shadow map covering the scene around the camera's target, filtered as the
+    /// settings say, see `shadow_map::ShadowMap`. `None` stops drawing it. Errs when the device has no formats the
+    /// map can be made with.
+    pub fn set_shadows(&mut self, settings: Option<shadows::Settings>) -> Result<(), String> {
+        let limit = unsafe {
+            self.instance
+                .get_physical_device_properties(self.physical_device)
+        }
+        .limits
+        .max_image_dimension2_d;
+        if let Some(resolution) = settings.map(|settings| settings.resolution) {
+            if resolution == 0 || resolution > limit {
+                return Err(format!(
+                    "Shadow maps are 1 to {} texels across on this device, not {}",
+                    limit, resolution
+                ));
             }
+        }
+        present_thread::device_wait_idle(&self.logical_device)
+            .expect("Waiting for device to be idle");
+        if let Some(mut shadow_map) = self.shadow_map.take() {
+            shadow_map.destroy(&self.logical_device);
+        }
+        let result = match settings {
+            Some(settings) => self
+                .create_shadow_map(settings)
+                .map(|shadow_map| self.shadow_map = Some(shadow_map)),
+            None => Ok(()),
+        };
+        // Drawn with the new map, or without one
+        self.write_texture_descriptors();
+        result
+    }
 
-            for &buffer_memory in self.uniform_buffers_memory.iter() {
-                self.logical_device.free_memory(buffer_memory, None)
-            }
+    /// The shadow map's targets, pipelines, and uniform buffers and descriptor sets for each swapchain image
+    fn create_shadow_map(
+        &self,
+        settings: shadows::Settings,
+    ) -> Result<shadow_map::ShadowMap, String> {
+        let depth = self
+            .select_format(&formats::Request::shadow_depth())
+            .ok_or_else(|| String::from("The device has no depth format for shadow maps"))?;
+        let moments = match settings.filter.uses_moments() {
+            true => Some(
+                self.select_format(&formats::Request::shadow_moments())
+                    .ok_or_else(|| String::from("The device has no format for VSM's moments"))?,
+            ),
+            false => None,
+        };
+        let formats = shadow_map::Formats {
+            depth: depth.format,
+            moments: moments.map(|choice| choice.format),
+            filter_moments: moments.is_some_and(|choice| {
+                self.formats.supports(
+                    &self.instance,
+                    choice.format,
+                    vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR,
+                )
+            }),
+        };
+        Ok(shadow_map::ShadowMap::new(
+            &self.swapchain_context(),
+            settings,
+            // The map can't sample itself while it's drawn into
+            self.create_view(self.no_shadow_map()),
+            formats,
+            |target| {
+                pipeline::Variants::new(
+                    &self.logical_device,
+                    self.descriptor_set_layout,
+                    self.animation_set_layout,
+                    target,
+                    *self.pipelines.limits(),
+                    self.pipeline_cache,
+                )
+            },
+        ))
+    }
 
-            self.logical_device
-                .destroy_image_view(self.depth_image_view, None);
-            self.logical_device.destroy_image(self.depth_image, None);
-            self.logical_device
-                .free_memory(self.depth_image_memory, None);
+    /// What the scene's descriptor sets sample the shadow map through, or stand-ins without one
+    fn shadow_descriptors(&self) -> shadow_map::Descriptors {
+        match self.shadow_map.as_ref() {
+            Some(shadow_map) => shadow_map.descriptors(),
+            None => self.no_shadow_map(),
+        }
+    }
 
-            self.logical_device
-                .destroy_descriptor_pool(self.descriptor_pool, None);
+    fn no_shadow_map(&self) -> shadow_map::Descriptors {
+        shadow_map::Descriptors::none(self.scene_texture_view(), self.texture_sampler)
+    }
 
-            self.logical_device
-                .free_command_buffers(self.command_pool, &self.command_buffers);
+    /// What the light sees the shadow map through, following the camera's target
+    fn shadow_light_view(&self) -> Option<shadows::LightView> {
+        self.shadow_map.as_ref().map(|shadow_map| {
+            shadow_map.settings.light_view(
+                self.light.direction,
+                self.camera.target,
+                self.scene_bounds(),
+            )
+        })
+    }
 
-            self.logical_device
-                .destroy_pipeline(self.graphics_pipeline, None);
-            self.logical_device
-                .destroy_pipeline_layout(self.pipeline_layout, None);
-            self.logical_device
-                .destroy_render_pass(self.render_pass, None);
+    /// Adds a reflection probe drawing the scene around `position` into a cubemap with faces `size` texels
+    /// across, each drawn every `interval` frames when the scheduler gets round to it, and returns its index. See
+    /// `probes::Probe`.
+    pub fn add_probe(&mut self, position: Point3<f32>, size: u32, interval: u32) -> usize {
+        let task = self
+            .schedule
+            .add("reflection probe", capture::FACE_NAMES.len(), interval);
+        let probe = probes::Probe::new(
+            &self.swapchain_context(),
+            position,
+            size.max(1),
+            interval,
+            self.create_scheduled_view(),
+            task,
+            &self.passes.probes,
+        );
+        self.probes.push(probe);
+        self.probes.len() - 1
+    }
 
-            for &image_view in self.swapchain_image_views.iter() {
-                self.logical_device.destroy_image_view(image_view, None)
-            }
-            self.swapchain_data
-                .loader
-                .destroy_swapchain(self.swapchain_data.swapchain, None);
+    /// Removes every reflection probe, returning how many there were
+    pub fn clear_probes(&mut self) -> usize {
+        present_thread::device_wait_idle(&self.logical_device)
+            .expect("Waiting for device to be idle");
+        let count = self.probes.len();
+        for mut probe in self.probes.drain(..) {
+            self.schedule.remove(probe.task);
+            probe.destroy(&self.logical_device);
         }
+        count
     }
 
-    // TODO: Semaphores not in consistent state when re-creating swapchain when frame buffer is suboptimal
-    fn draw_frame(&mut self) {
-        // TODO: Wait for fences
-        let current_frame_fences = [self.frame_fences[self.current_frame]];
+    /// Saves the probe's cubemap to the working directory, both as faces and as a panorama, as the probe last
+    /// drew them. Faces the probe hasn't drawn yet are the background. Returns the paths saved to.
+    pub fn save_probe(&mut self, index: usize) -> Result<Vec<PathBuf>, String> {
+        let probe = self
+            .probes
+            .get(index)
+            .ok_or_else(|| format!("There's no probe {}", index))?;
+        capture::check_format(probe.format())?;
+        if !probe.cleared() {
+            return Err(format!("Probe {} hasn't been drawn yet", index));
+        }
+        present_thread::device_wait_idle(&self.logical_device)
+            .expect("Waiting for device to be idle");
+        let device = &self.logical_device;
+        let readback = capture::create_readback(&self.swapchain_context(), probe.size);
+        let faces = (0..capture::FACE_NAMES.len())
+            .map(|face| {
+                let command_buffer = begin_single_time_commands(device, self.command_pool);
+                probe.record_copy(device, command_buffer, face, readback.0);
+                end_single_time_commands(
+                    device,
+                    self.command_pool,
+                    command_buffer,
+                    self.graphics_queue,
+                );
+                capture::read_face(device, readback.1, probe.size, probe.format())
+            })
+            .collect();
         unsafe {
-            self.logical_device
-                .wait_for_fences(&current_frame_fences, true, u64::MAX)
-                .expect("Waiting for frame fence");
-        };
+            device.destroy_buffer(readback.0, None);
+            device.free_memory(readback.1, None);
+        }
 
-        // Request an image from the swap chain. It will signal the given semaphore when the image is ready
-        let (image_index, recreated) = unsafe {
-            match self.swapchain_data.loader.acquire_next_image(
-                self.swapchain_data.swapchain,
-                u64::MAX,
-                self.image_available_semaphores[self.current_frame],
-                vk::Fence::null(),
-            ) {
-                Ok((idx, _)) => (idx as usize, false),
-                Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
-                    self.recreate_swapchain();
-                    (0 as usize, true)
+        let cubemap = capture::Cubemap {
+            size: probe.size,
+            faces,
+        };
+        let name = format!("probe{}", index);
+        let directory = Path::new(".");
+        let mut paths = cubemap.save_faces(directory, &name)?;
+        let panorama_path = directory.join(format!("{}_panorama.{}", name, images::EXTENSION));
+        images::save(&cubemap.to_panorama(probe.size * 4), &panorama_path)?;
+        paths.push(panorama_path);
+        Ok(paths)
+    }
+
+    /// Sets the most renders of the minimap and probes' faces drawn in a frame, at least 1, see
+    /// `schedule::Scheduler`
+    pub fn set_schedule_budget(&mut self, budget: usize) {
+        self.schedule.budget = budget.max(1);
+    }
+
+    pub fn pass_config(&self, target: pipeline::RenderTarget) -> pipeline::PassConfig {
+        *self.passes.get(target)
+    }
+
+    /// Changes how the target's passes load, store and clear it, see `pipeline::PassConfig`. A new clear colour
+    /// applies from the next frame. New load and store policies make the target's render passes again: the
+    /// scene's along with the swapchain before the next frame, and the minimap and probes straight away, drawn
+    /// again from the start. Errs when the scene's colour would be preserved while a separate present family
+    /// presents the swapchain images, since they come back from it without their contents, see
+    /// `ownership::PresentAcquire`.
+    pub fn set_pass_config(
+        &mut self,
+        target: pipeline::RenderTarget,
+        config: pipeline::PassConfig,
+    ) -> Result<(), String> {
+        if target == pipeline::RenderTarget::Scene
+            && config.color.load == pipeline::Load::Preserve
+            && self.present_acquire.is_some()
+        {
+            return Err(String::from(
+                "The scene's colour can't be preserved when the present family isn't the graphics family",
+            ));
+        }
+        let previous = mem::replace(self.passes.get_mut(target), config);
+        if previous.color == config.color && previous.depth == config.depth {
+            return Ok(());
+        }
+        match target {
+            pipeline::RenderTarget::Scene => self.frame_buffer_resized = true,
+            pipeline::RenderTarget::Minimap | pipeline::RenderTarget::Probes => {
+                present_thread::device_wait_idle(&self.logical_device)
+                    .expect("Waiting for device to be idle");
+                if let Some(minimap) = self.minimap.as_mut() {
+                    minimap.destroy(&self.logical_device);
                 }
-                Err(_) => panic!("Failed to acquire swapchain image"),
+                for probe in self.probes.iter_mut() {
+                    probe.destroy(&self.logical_device);
+                }
+                self.recreate_scheduled_renders();
             }
+        }
+        Ok(())
+    }
+
+    /// Streams every frame to a client connecting to the address, and handles the input it sends back, see
+    /// `remote::Remote`. Only one stream can be started.
+    pub fn stream_frames(
+        &mut self,
+        address: &str,
+        encoding: remote::Encoding,
+    ) -> Result<(), String> {
+        if self.features.get_mut::<remote::Remote>().is_some() {
+            return Err(String::from("Frames are already being streamed"));
+        }
+        let remote = remote::Remote::new(address, encoding)?;
+        self.add_feature(Box::new(remote));
+        Ok(())
+    }
+
+    /// Handles the input streaming clients have sent since the last frame
+    fn update_remote(&mut self) {
+        let inputs = match self.features.get_mut::<remote::Remote>() {
+            Some(remote) => remote.take_inputs(),
+            None => return,
+        };
+        for input in inputs {
+            self.handle_input(input);
+        }
+    }
+
+    /// Shows the final frame around the cursor magnified in the bottom right corner, see `magnifier::Magnifier`.
+    /// Shows the texture memory resident against what was requested, the world cells waiting to stream in and
+    /// the upload bandwidth in the top right corner, see `residency::Overlay`
+    pub fn set_residency_overlay(&mut self, visible: bool) {
+        if let Some(overlay) = self.features.get_mut::<residency::Overlay>() {
+            overlay.visible = visible;
+        }
+    }
+
+    /// The callbacks the instance and device were created with, which they must be destroyed with
+    fn host_allocator(&self) -> Option<vk::AllocationCallbacks> {
+        Some(host_memory::callbacks()).filter(|_| self.track_host_memory)
+    }
+
+    fn update_residency(&mut self) {
+        let texture_memory = self.texture_budget.used();
+        let metrics = residency::Metrics {
+            texture_resident: texture_memory.size,
+            texture_requested: texture_memory.requested,
+            texture_budget: self.texture_budget.limit(),
+            queued_cells: self.world.as_ref().map(streaming::World::queued),
+            upload_bandwidth: self.uploads.bandwidth(),
+            host_memory: Some(host_memory::usage()).filter(|_| self.track_host_memory),
         };
+        if let Some(overlay) = self.features.get_mut::<residency::Overlay>() {
+            overlay.set_metrics(metrics);
+        }
+    }
 
-        // If the swapchain had to be re-created, exit early and draw again in the next tick.
-        if recreated {
+    pub fn set_magnifier(&mut self, enabled: bool) {
+        if let Some(magnifier) = self.features.get_mut::<magnifier::Magnifier>() {
+            magnifier.enabled = enabled;
+        }
+    }
+
+    /// Keeps the thumbnails in a UI atlas, drawing each into it once when shown and copying it from there every
+    /// frame after, rather than drawing every thumbnail from its source each frame.
+    pub fn set_ui_atlas(&mut self, enabled: bool) -> Result<(), String> {
+        present_thread::device_wait_idle(&self.logical_device)
+            .expect("Waiting for device to be idle");
+        match self.features.get_mut::<thumbnails::Thumbnails>() {
+            Some(thumbnails) => thumbnails.set_cached(
+                &self.logical_device,
+                &self.physical_device_memory_properties,
+                enabled,
+            ),
+            None => Err(String::from("The thumbnails feature hasn't been added")),
+        }
+    }
+
+    /// Keeps the scene's draws recorded from one frame to the next, recording them again only when the visible
+    /// draws or anything they record changes. Saves recording every draw each frame when the scene and camera are
+    /// still, but records on one thread so frames that change cost more.
+    pub fn set_baked_draws(&mut self, enabled: bool) {
+        if enabled == self.baked_draws.is_some() {
             return;
         }
+        self.baked_draws = match enabled {
+            true => Some(Self::create_baked_draws(
+                &self.logical_device,
+                &self.queue_families,
+                self.swapchain_data.images.len(),
+            )),
+            false => {
+                present_thread::device_wait_idle(&self.logical_device)
+                    .expect("Waiting for device to be idle");
+                if let Some(mut baked_draws) = self.baked_draws.take() {
+                    baked_draws.destroy(&self.logical_device);
+                }
+                None
+            }
+        };
+    }
 
-        self.update_uniform_buffer(image_index);
+    /// Culls draws hidden behind the largest opaque draws in view, found by drawing those into a small depth
+    /// buffer on the CPU each frame. `None` turns it off.
+    pub fn set_software_occlusion(&mut self, settings: Option<occluders::Settings>) {
+        self.software_occlusion = settings;
+    }
 
-        // Make sure we don't reference a swapchain image that is already being presented
-        if self.image_fences[image_index] != vk::Fence::null() {
-            let active_image_in_flight_fences = [self.image_fences[image_index]];
-            unsafe {
-                self.logical_device
-                    .wait_for_fences(&active_image_in_flight_fences, true, u64::MAX)
-                    .expect("Image in flight fence");
+    /// The average time each frame preparation system has taken since this was last called.
+    pub fn take_timings(&mut self) -> Vec<(&'static str, Duration)> {
+        self.timings.take_averages()
+    }
+
+    /// The average counts of each pass's work per frame since this was last called, if pipeline statistics are
+    /// enabled
+    pub fn take_pipeline_statistics(&mut self) -> Vec<(&'static str, statistics::Counts)> {
+        self.statistics
+            .as_mut()
+            .map_or_else(Vec::new, |statistics| statistics.take_averages())
+    }
+
+    /// Counts the vertices, primitives and shader invocations of each pass with pipeline statistics queries,
+    /// reported with the frame rate. Does nothing if the device doesn't support the queries.
+    pub fn enable_pipeline_statistics(&mut self) {
+        let features = unsafe {
+            self.instance
+                .get_physical_device_features(self.physical_device)
+        };
+        if !statistics::PipelineStatistics::supported(&features) {
+            self.events.emit(events::Event::FeatureUnavailable {
+                feature: String::from("counting pipeline statistics"),
+                error: String::from("The device doesn't support pipeline statistics queries"),
+            });
+            return;
+        }
+        if self.statistics.is_none() {
+            self.statistics = Some(statistics::PipelineStatistics::new(
+                &self.logical_device,
+                self.swapchain_data.images.len(),
+            ));
+        }
+    }
+
+    /// Alerts when the CPU or the GPU takes longer than the budget over a frame, showing the alert in the window
+    /// title and logging the frame's heaviest passes. The GPU is left unchecked if the device can't time it.
+    pub fn set_frame_budget(&mut self, budget: budget::Budget) {
+        if let Some(monitor) = self.frame_budget.as_mut() {
+            monitor.budget = budget;
+            return;
+        }
+        let limits = unsafe {
+            self.instance
+                .get_physical_device_properties(self.physical_device)
+        }
+        .limits;
+        let queue_families = unsafe {
+            self.instance
+                .get_physical_device_queue_family_properties(self.physical_device)
+        };
+        let queue_family = &queue_families[self
+            .queue_families
+            .graphics_family
+            .expect("The renderer has a graphics queue")
+            as usize];
+        let timer = if budget::GpuTimer::supported(&limits, queue_family) {
+            Some(budget::GpuTimer::new(
+                &self.logical_device,
+                self.swapchain_data.images.len(),
+                &limits,
+                queue_family,
+            ))
+        } else {
+            self.events.emit(events::Event::FeatureUnavailable {
+                feature: String::from("checking the GPU's frame budget"),
+                error: String::from("The device can't time frames on the GPU"),
+            });
+            None
+        };
+        self.frame_budget = Some(budget::Monitor::new(budget, timer));
+    }
+
+    /// Raises alerts for the frames that went over budget, for the CPU as it has just finished the frame and for
+    /// the GPU as its timestamps are read back
+    fn check_frame_budget(
+        &mut self,
+        cpu_time: Duration,
+        gpu_passes: Option<Vec<(statistics::Pass, Duration)>>,
+    ) {
+        let monitor = match self.frame_budget.as_ref() {
+            Some(monitor) => monitor,
+            None => return,
+        };
+        let mut alerts = Vec::new();
+        let cpu_passes = || {
+            let mut passes: Vec<(String, Duration)> = self
+                .timings
+                .last_frame()
+                .iter()
+                .map(|&(system, time)| (String::from(system), time))
+                .collect();
+            passes.extend(
+                self.frame_graph()
+                    .passes
+                    .into_iter()
+                    .filter_map(|pass| pass.recording.map(|time| (pass.name, time))),
+            );
+            passes
+        };
+        alerts.extend(monitor.check(budget::Side::Cpu, cpu_time, cpu_passes));
+        if let Some(passes) = gpu_passes {
+            let gpu_time = passes.iter().map(|(_, time)| *time).sum();
+            let named = || {
+                passes
+                    .iter()
+                    .map(|(pass, time)| (String::from(pass.name()), *time))
+                    .collect()
             };
+            alerts.extend(monitor.check(budget::Side::Gpu, gpu_time, named));
+        }
+        let events = &mut self.events;
+        let title = self.frame_budget.as_mut().and_then(|monitor| {
+            monitor.report(&alerts, |alert, skipped| {
+                events.emit(events::Event::BudgetExceeded {
+                    alert: alert.clone(),
+                    skipped,
+                })
+            })
+        });
+        match title {
+            Some(Some(summary)) => self
+                .window
+                .set_title(&format!("{} - {}", APP_TITLE, summary)),
+            Some(None) => self.window.set_title(APP_TITLE),
+            None => {}
+        }
+    }
+
+    fn swapchain_context(&self) -> features::SwapchainContext<'_> {
+        features::SwapchainContext {
+            device: &self.logical_device,
+            device_memory_properties: &self.physical_device_memory_properties,
+            extent: self.swapchain_data.extent,
+            format: self.swapchain_data.format,
+            depth_format: self.depth_format,
+            usage: self.swapchain_data.usage,
+            image_count: self.swapchain_data.images.len(),
+            image_views: &self.swapchain_image_views,
+            scale_factor: self.scale_factor,
+        }
+    }
+
+    /// Sizes the overlays for the window's new scale factor, once the swapchain has been recreated
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor as f32;
+        self.frame_buffer_resized = true;
+    }
+
+    /// Adds a pass recorded into every frame after the renderer's own features.
+    pub fn add_feature(&mut self, feature: Box<dyn features::RenderFeature>) {
+        let mut features = mem::take(&mut self.features);
+        features.add(feature, &self.swapchain_context());
+        self.features = features;
+    }
+
+    fn frame_context(
+        &self,
+        stage: hooks::Stage,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+    ) -> hooks::FrameContext<'_> {
+        hooks::FrameContext {
+            stage,
+            device: &self.logical_device,
+            command_buffer,
+            image_index,
+            swapchain_image: self.swapchain_data.images[image_index],
+            swapchain_format: self.swapchain_data.format,
+            depth_image: self.depth_image,
+            target: Self::pipeline_target(&self.swapchain_data, self.render_pass),
+            descriptor_set: self.descriptor_sets[image_index],
+            pipeline_layout: self.pipelines.layout(),
+            limits: *self.pipelines.limits(),
+            view: self.view,
+            projection: self.projection(),
+            time: self.start_time.elapsed().as_secs_f32(),
+            light: self.light,
+        }
+    }
+
+    /// Adds a `size` by `size` grid of the demo's quads beneath the floor, culled and drawn from a draw stream
+    /// generated on the GPU. The grid is left out if the device can't draw it indirectly.
+    pub fn add_object_grid(&mut self, size: u32) {
+        let features = unsafe {
+            self.instance
+                .get_physical_device_features(self.physical_device)
         };
-        self.image_fences[image_index] = self.frame_fences[self.current_frame];
+        if !indirect::Scene::supported(&features) {
+            self.events.emit(events::Event::FeatureUnavailable {
+                feature: String::from("adding the object grid"),
+                error: String::from("The device doesn't support multi-draw indirect"),
+            });
+            return;
+        }
+        let draw_indirect_count = Self::is_device_extension_supported(
+            &self.instance,
+            &self.physical_device,
+            ash::extensions::khr::DrawIndirectCount::name(),
+        )
+        .then(|| {
+            ash::extensions::khr::DrawIndirectCount::new(&self.instance, &self.logical_device)
+        });
+        let conditional_rendering = Self::is_device_extension_supported(
+            &self.instance,
+            &self.physical_device,
+            conditional::ConditionalRendering::name(),
+        )
+        .then(|| conditional::ConditionalRendering::new(&self.instance, &self.logical_device));
+
+        let spacing = 0.125;
+        let quad_bounds = bounds::Aabb::from_points(
+            QUAD_VERTICES[..4]
+                .iter()
+                .map(|vertex| Point3::from(vertex.pos)),
+        )
+        .expect("quad has vertices");
+        let objects: Vec<indirect::Object> = (0..size * size)
+            .map(|i| {
+                let (column, row) = (i % size, i / size);
+                let offset = |index: u32| (index as f32 - (size - 1) as f32 / 2.0) * spacing;
+                let transform =
+                    Matrix4::from_translation(Vector3::new(offset(column), offset(row), -1.0))
+                        * Matrix4::from_scale(spacing * 0.8);
+                // Alternate between the demo's two quads to show objects drawing their own index ranges
+                let quad = i % 2;
+                indirect::Object::new(transform, &quad_bounds, quad * 6, 6, 0)
+            })
+            .collect();
+
+        let pipeline = material::Material {
+            double_sided: true,
+            blend_mode: material::BlendMode::Masked { cutoff: 0.5 },
+            ..material::Material::default()
+        }
+        .pipeline_config(mesh::VertexAttributes::ALL);
+        let scene = indirect::Scene::new(
+            &self.logical_device,
+            self.command_pool,
+            self.graphics_queue,
+            &self.physical_device_memory_properties,
+            &objects,
+            self.vertex_buffer,
+            self.index_buffer,
+            pipeline,
+            draw_indirect_count,
+            conditional_rendering,
+            self.hi_z_depth(),
+        );
+        if let Some(mut previous) = self.indirect_scene.replace(scene) {
+            previous.destroy(&self.logical_device);
+        }
+        self.events
+            .status(format!("Added {} GPU culled objects", objects.len()));
+    }
 
-        let render_wait_semaphores = [self.image_available_semaphores[self.current_frame]];
-        let render_signal_semaphores = [self.render_complete_semaphores[self.current_frame]];
-        let wait_stage = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
-        let command_buffers = [self.command_buffers[image_index]];
+    /// Whether the depth attachment can be sampled, which building a depth pyramid needs
+    fn depth_sampled(&self) -> bool {
+        self.formats.supports(
+            &self.instance,
+            self.depth_format,
+            vk::FormatFeatureFlags::SAMPLED_IMAGE,
+        )
+    }
 
-        // Submit info is data representing a request to a queue and how to synchronise it with other requests
-        // Tells vulkan to wait at the "color attachment" point until the image_available_semaphore has signaled,
-        // then run the command buffer. Once the commands are complete, signal the "render_complete_semaphore".
-        let submit_info = vk::SubmitInfo::builder()
-            .wait_semaphores(&render_wait_semaphores)
-            .wait_dst_stage_mask(&wait_stage)
-            .command_buffers(&command_buffers)
-            .signal_semaphores(&render_signal_semaphores);
+    /// The depth attachment for the GPU culled objects to build their depth pyramid from, when Hi-Z culling is on
+    fn hi_z_depth(&self) -> Option<hiz::Depth> {
+        (self.hi_z_culling && self.depth_sampled()).then_some(hiz::Depth {
+            image: self.depth_image,
+            view: self.depth_image_view,
+            format: self.depth_format,
+            extent: self.swapchain_data.extent,
+        })
+    }
 
-        let queue_submissions = [submit_info.build()];
+    /// Also culls the objects added with `add_object_grid` that are hidden behind what the previous frame drew,
+    /// testing their bounds against a depth pyramid built on the GPU. Needs a depth format that can be sampled.
+    pub fn set_hi_z_culling(&mut self, enabled: bool) {
+        self.hi_z_culling = enabled;
+        if enabled && !self.depth_sampled() {
+            self.events.emit(events::Event::FeatureUnavailable {
+                feature: String::from("culling with a depth pyramid"),
+                error: String::from("The depth format can't be sampled"),
+            });
+        }
+        let depth = self.hi_z_depth();
+        if let Some(scene) = self.indirect_scene.as_mut() {
+            present_thread::device_wait_idle(&self.logical_device)
+                .expect("Waiting for device to be idle");
+            scene.set_depth(
+                &self.logical_device,
+                self.command_pool,
+                self.graphics_queue,
+                &self.physical_device_memory_properties,
+                depth,
+            );
+        }
+    }
 
-        unsafe {
-            self.logical_device
-                .reset_fences(&current_frame_fences)
-                .expect("Resetting current frame fence");
-            self.logical_device
-                .queue_submit(
-                    self.graphics_queue,
-                    &queue_submissions,
-                    self.frame_fences[self.current_frame],
-                )
-                .expect("Graphics queue submit")
+    /// Uploads a mesh to the GPU. Its buffers stay loaded until every handle to it, and every draw added with
+    /// `add_mesh`, has been dropped or removed.
+    pub fn load_mesh(&mut self, mesh: &mesh::MeshData) -> assets::Mesh {
+        let vertices = mesh.interleave();
+        let vertex_buffer = Self::create_device_local_buffer(
+            &self.logical_device,
+            &vertices,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            self.command_pool,
+            self.graphics_queue,
+            &self.physical_device_memory_properties,
+        );
+        let index_buffer = Self::create_device_local_buffer(
+            &self.logical_device,
+            &mesh.indices,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            self.command_pool,
+            self.graphics_queue,
+            &self.physical_device_memory_properties,
+        );
+        self.uploads.add(
+            (mem::size_of_val(&vertices[..]) + mem::size_of_val(&mesh.indices[..]))
+                as vk::DeviceSize,
+        );
+        self.assets.add_mesh(mesh, vertex_buffer, index_buffer)
+    }
+
+    /// Hands what is drawn over to an update run once a frame, which draws meshes and quads and moves the camera
+    /// through an immediate mode `immediate::Renderer` instead of adding and keeping track of draws. Replaces
+    /// any update set before, whose draws are emptied.
+    pub fn set_immediate(&mut self, update: immediate::Update) {
+        let draws = match self.immediate.take() {
+            Some(immediate) => immediate.draws,
+            None => Vec::new(),
         };
+        for &draw in draws.iter() {
+            self.remove_draw(draw);
+        }
+        let quad = self.load_mesh(&immediate::quad());
+        let mut immediate = immediate::Immediate::new(update, quad);
+        immediate.draws = draws;
+        self.immediate = Some(immediate);
+    }
 
-        let present_wait_semaphores = render_signal_semaphores;
-        let swapchains = [self.swapchain_data.swapchain];
-        let image_indices = [image_index as u32];
-        let present_info = vk::PresentInfoKHR::builder()
-            .wait_semaphores(&present_wait_semaphores)
-            .swapchains(&swapchains)
-            .image_indices(&image_indices);
+    /// Draws a loaded mesh with the material in the current scene, returning the new draw's index
+    pub fn add_mesh(
+        &mut self,
+        mesh: &assets::Mesh,
+        material: material::Material,
+        transform: Matrix4<f32>,
+    ) -> usize {
+        let draw = Self::mesh_draw(mesh, material, transform, self.scene);
+        self.draws.push(draw);
+        self.draws.len() - 1
+    }
 
-        let present_result = unsafe {
-            self.swapchain_data
-                .loader
-                .queue_present(self.present_queue, &present_info.build())
+    fn mesh_draw(
+        mesh: &assets::Mesh,
+        material: material::Material,
+        transform: Matrix4<f32>,
+        scene: usize,
+    ) -> draw::Draw {
+        draw::Draw {
+            transform,
+            bounds: mesh.bounds().map(bounds::Bounds::new),
+            collider: mesh.collider(),
+            scene,
+            mesh: Some(mesh.clone()),
+            ..draw::Draw::indexed(
+                material.pipeline_config(mesh.attributes()),
+                mesh.vertex_buffer(),
+                mesh.index_buffer(),
+                mesh.index_count(),
+            )
+        }
+    }
+
+    /// Stops drawing the draw, returning the mesh it was drawing if it was added with `add_mesh`. The mesh is
+    /// freed once it's dropped unless something else still holds it. The draw is left as an empty draw so that
+    /// the indices of the draws after it stay the same.
+    pub fn remove_draw(&mut self, draw: usize) -> Option<assets::Mesh> {
+        let empty = draw::Draw {
+            instance_count: 0,
+            scene: self.draws[draw].scene,
+            ..draw::Draw::new(self.draws[draw].pipeline, vk::Buffer::null(), 0)
+        };
+        let removed = mem::replace(&mut self.draws[draw], empty);
+        if self.picked == Some(draw) {
+            self.picked = None;
+        }
+        removed.mesh
+    }
+
+    /// Names the draw, and its buffers after it in validation messages and graphics debuggers when the debug
+    /// layers are enabled. Buffers shared by several draws keep the name of the last named.
+    pub fn set_draw_name(&mut self, draw: usize, name: &str) -> Result<(), String> {
+        let named = self
+            .draws
+            .get_mut(draw)
+            .ok_or_else(|| format!("There's no draw {}", draw))?;
+        named.name = Some(String::from(name));
+        if let Some(names) = self.names.as_ref() {
+            let buffers = [
+                (Some(named.vertex_buffer), "vertices"),
+                (named.index_buffer, "indices"),
+                (named.instances, "instances"),
+            ];
+            for (buffer, contents) in buffers {
+                if let Some(buffer) = buffer.filter(|buffer| *buffer != vk::Buffer::null()) {
+                    names.set(buffer, &format!("{} {}", name, contents));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Gives the draw a trail of ghosts, or takes its trail away with `None`
+    pub fn set_draw_trail(
+        &mut self,
+        draw: usize,
+        trail: Option<trails::Trail>,
+    ) -> Result<(), String> {
+        self.draws
+            .get_mut(draw)
+            .ok_or_else(|| format!("There's no draw {}", draw))?
+            .trail = trail;
+        Ok(())
+    }
+
+    /// Sets whether the draw casts a shadow onto the ground, see `ground_shadows::GroundShadows`
+    pub fn set_draw_casts_ground_shadow(&mut self, draw: usize, casts: bool) -> Result<(), String> {
+        self.draws
+            .get_mut(draw)
+            .ok_or_else(|| format!("There's no draw {}", draw))?
+            .casts_ground_shadow = casts;
+        Ok(())
+    }
+
+    /// Replaces the capsules the draw shades what is around it with, see `Draw::capsules`
+    pub fn set_draw_capsules(
+        &mut self,
+        draw: usize,
+        capsules: Vec<capsules::Capsule>,
+    ) -> Result<(), String> {
+        self.draws
+            .get_mut(draw)
+            .ok_or_else(|| format!("There's no draw {}", draw))?
+            .capsules = capsules;
+        Ok(())
+    }
+
+    /// Culls draws inside the portals' cells that the camera can't see through a chain of portals from the cell
+    /// it is in, as well as those outside its frustum. `None` goes back to frustum culling alone.
+    pub fn set_portals(&mut self, portals: Option<portals::Portals>) {
+        if let Some(portals) = portals.as_ref() {
+            self.events.status(format!(
+                "Culling {} cells through {} portals",
+                portals.cells.len(),
+                portals.portals.len()
+            ));
+        }
+        self.portals = portals;
+    }
+
+    /// Streams a world too large to load at once into the current scene, loading the cells within `load_radius`
+    /// of the camera's target and unloading them once it moves away. See `streaming::World`.
+    pub fn stream_world(&mut self, path: &Path, load_radius: f32) -> Result<(), String> {
+        if self.world.is_some() {
+            return Err(String::from("A world is already being streamed"));
+        }
+        let world = streaming::World::open(path, load_radius, self.scene)?;
+        self.events.status(format!(
+            "Streaming the {} cells of {}",
+            world.cell_counts().1,
+            path.display()
+        ));
+        self.world = Some(world);
+        Ok(())
+    }
+
+    /// Requests the cells of the world around the camera's target, adds the cells that have finished loading and
+    /// removes those that have got too far away
+    fn update_streaming(&mut self) {
+        let mut world = match self.world.take() {
+            Some(world) => world,
+            None => return,
+        };
+        // The world is placed in the scene by the model transform like the rest of the draws
+        let center = self.model.invert().map_or(self.camera.target, |inverse| {
+            inverse.transform_point(self.camera.target)
+        });
+        let unloaded = world.update(center);
+        let changed = !unloaded.is_empty();
+        for draw in unloaded {
+            self.remove_draw(draw);
+        }
+        let finished = world.finished();
+        let changed = changed || !finished.is_empty();
+        for (cell, meshes) in finished {
+            let meshes = meshes.unwrap_or_else(|e| {
+                self.events.emit(events::Event::LoadFailed {
+                    kind: events::AssetKind::WorldCell,
+                    name: format!("{:?}", cell),
+                    error: e,
+                });
+                Vec::new()
+            });
+            let draws = meshes
+                .iter()
+                .map(|mesh| {
+                    let mesh = self.load_mesh(mesh);
+                    let draw = Self::mesh_draw(
+                        &mesh,
+                        streaming::material(),
+                        Matrix4::identity(),
+                        world.scene,
+                    );
+                    match world.take_free_draw() {
+                        Some(index) => {
+                            self.draws[index] = draw;
+                            index
+                        }
+                        None => {
+                            self.draws.push(draw);
+                            self.draws.len() - 1
+                        }
+                    }
+                })
+                .collect();
+            // Cells that fail are left empty rather than loaded again
+            world.placed(cell, draws);
+        }
+        if changed {
+            let (loaded, cells) = world.cell_counts();
+            self.events
+                .status(format!("{} of {} world cells loaded", loaded, cells));
+        }
+        self.world = Some(world);
+    }
+
+    /// Frees the meshes and textures that are no longer used, once the frames that may have recorded them have
+    /// finished. Called at the start of each frame, after waiting for its fence.
+    fn collect_garbage(&mut self) {
+        let (meshes, textures, freed) = self.assets.collect_garbage(&mut self.deletion_queue);
+        self.texture_budget.release(freed);
+        if meshes + textures > 0 {
+            self.events.status(format!(
+                "Freeing {} unused meshes and {} unused textures",
+                meshes, textures
+            ));
+        }
+        self.deletion_queue.next_frame(&self.logical_device);
+    }
+
+    /// Registers a callback that records its own commands into every frame at the stage.
+    /// How many scenes there are to switch between. Scenes are numbered from zero by their draws.
+    pub fn scene_count(&self) -> usize {
+        self.draws
+            .iter()
+            .map(|draw| draw.scene + 1)
+            .max()
+            .unwrap_or(1)
+    }
+
+    /// Makes `scene` the scene that is drawn, transitioning to it from the current scene.
+    pub fn switch_scene(&mut self, scene: usize) {
+        if scene == self.scene {
+            return;
+        }
+        if let Some(transitions) = self.features.get_mut::<transition::Transitions>() {
+            transitions.start(self.scene);
+        }
+        self.scene = scene;
+        self.events.status(format!("Switched to scene {}", scene));
+    }
+
+    /// Averages successive frames drawn with jittered projections into a super-sampled image of a still scene,
+    /// up to `max_samples` of them, see `accumulation::Accumulation`. `None` turns accumulation off.
+    pub fn set_accumulation(&mut self, max_samples: Option<u32>) {
+        match (
+            self.features.get_mut::<accumulation::Accumulation>(),
+            max_samples,
+        ) {
+            (Some(accumulation), Some(max_samples)) => {
+                accumulation.enabled = true;
+                accumulation.max_samples = max_samples.max(1);
+                accumulation.reset();
+            }
+            (Some(accumulation), None) => {
+                accumulation.enabled = false;
+                accumulation.reset();
+            }
+            (None, Some(max_samples)) => {
+                let accumulation =
+                    accumulation::Accumulation::new(&self.logical_device, max_samples);
+                self.add_feature(Box::new(accumulation));
+            }
+            (None, None) => {}
+        }
+    }
+
+    /// Frames averaged into the image shown so far and the most that will be, while accumulating
+    pub fn accumulated_samples(&mut self) -> Option<(u32, u32)> {
+        self.features
+            .get_mut::<accumulation::Accumulation>()
+            .filter(|accumulation| accumulation.enabled)
+            .map(|accumulation| (accumulation.samples(), accumulation.max_samples))
+    }
+
+    /// Starts averaging frames again, for changes accumulation can't see for itself
+    fn reset_accumulation(&mut self) {
+        if let Some(accumulation) = self.features.get_mut::<accumulation::Accumulation>() {
+            accumulation.reset();
+        }
+    }
+
+    /// The frame's sub-pixel offset while accumulating, starting the average again first if the frame is drawn
+    /// with anything other than the frames before it
+    fn accumulation_jitter(&mut self) -> Option<[f32; 2]> {
+        let enabled = self
+            .features
+            .get_mut::<accumulation::Accumulation>()
+            .is_some_and(|accumulation| accumulation.enabled);
+        if !enabled {
+            return None;
+        }
+        let key = accumulation::Key {
+            view: self.view,
+            projection: self.projection(),
+            model: self.model,
+            scene: self.scene,
+            transforms: self.draws.iter().map(|draw| draw.transform).collect(),
         };
+        let accumulation = self.features.get_mut::<accumulation::Accumulation>()?;
+        accumulation.track(key);
+        accumulation.jitter()
+    }
+
+    /// Hangs a square of cloth simulated on the GPU between two of the posts in the first scene, blowing in the
+    /// wind. Only one cloth can be added.
+    pub fn add_cloth(&mut self) {
+        if self.cloth.is_some() {
+            return;
+        }
+        let cloth = cloth::Cloth::new(
+            &self.logical_device,
+            self.command_pool,
+            self.graphics_queue,
+            &self.physical_device_memory_properties,
+            32,
+            0.4,
+        );
+        self.draws.push(draw::Draw {
+            scene: 0,
+            ..cloth.draw(Matrix4::from_translation(Vector3::new(-0.2, -0.55, 0.0)))
+        });
+        self.cloth = Some(cloth);
+    }
+
+    /// Adds a scene of `count` agents moved by a compute shader and switches to it. Only one swarm can be added.
+    pub fn add_swarm(&mut self, behaviour: swarm::Behaviour, count: u32) {
+        if self.swarm.is_some() {
+            return;
+        }
+        let swarm = swarm::Swarm::new(
+            &self.logical_device,
+            self.command_pool,
+            self.graphics_queue,
+            &self.physical_device_memory_properties,
+            behaviour,
+            count,
+            Point3::new(0.0, 0.0, 0.0),
+            0.6,
+        );
+        let scene = self.scene_count();
+        self.draws.push(draw::Draw {
+            scene,
+            ..swarm.draw()
+        });
+        self.swarm = Some(swarm);
+        self.switch_scene(scene);
+        self.events
+            .status(format!("Added a swarm of {} agents", count));
+    }
 
-        match unsafe { self.logical_device.queue_wait_idle(self.present_queue) } {
-            Ok(_) => {}
-            Err(result) => {
-                println!("Error waiting for present queue: {}", result)
+    /// Adds a scene of the point cloud, scaled to fit the camera's view and turned to the world's up, and switches
+    /// to it. Returns the scene.
+    /// Each of the cloud's chunks is a separate draw, so only the chunks in view are drawn.
+    pub fn add_point_cloud(
+        &mut self,
+        cloud: &pointcloud::PointCloud,
+        mode: pointcloud::Mode,
+    ) -> usize {
+        let (vertex_buffer, vertex_buffer_memory) = Self::create_device_local_buffer(
+            &self.logical_device,
+            &cloud.points,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            self.command_pool,
+            self.graphics_queue,
+            &self.physical_device_memory_properties,
+        );
+        self.point_cloud_buffers
+            .push((vertex_buffer, vertex_buffer_memory));
+
+        let scene = self.scene_count();
+        self.draws.extend(
+            cloud
+                .draws(
+                    vertex_buffer,
+                    mode,
+                    self.units.up.convert(cloud.up) * cloud.fit_transform(),
+                )
+                .into_iter()
+                .map(|draw| draw::Draw { scene, ..draw }),
+        );
+        self.switch_scene(scene);
+        self.events.status(format!(
+            "Added a cloud of {} points in {} chunks",
+            cloud.points.len(),
+            cloud.chunks.len()
+        ));
+        scene
+    }
+
+    /// Adds a draw of `mesh` to the current scene with its positions and normals played back from `animation`,
+    /// and returns the index of its `vertex_animation`. The mesh's other attributes are drawn as usual, and its own
+    /// positions only decide which vertices make up each triangle. Fails if the animation doesn't move every one
+    /// of the mesh's vertices.
+    pub fn add_vertex_animation(
+        &mut self,
+        mesh: &mesh::MeshData,
+        animation: &vat::Animation,
+        material: material::Material,
+        transform: Matrix4<f32>,
+    ) -> Result<usize, String> {
+        if animation.vertex_count() != mesh.positions.len() {
+            return Err(format!(
+                "The animation moves {} vertices but the mesh has {}",
+                animation.vertex_count(),
+                mesh.positions.len()
+            ));
+        }
+
+        let (vertex_buffer, vertex_buffer_memory) = Self::create_device_local_buffer(
+            &self.logical_device,
+            &mesh.interleave(),
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            self.command_pool,
+            self.graphics_queue,
+            &self.physical_device_memory_properties,
+        );
+        let (index_buffer, index_buffer_memory) = Self::create_device_local_buffer(
+            &self.logical_device,
+            &mesh.indices,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            self.command_pool,
+            self.graphics_queue,
+            &self.physical_device_memory_properties,
+        );
+        let texture = vat::Texture::new(
+            &self.logical_device,
+            self.command_pool,
+            self.graphics_queue,
+            &self.physical_device_memory_properties,
+            self.animation_set_layout,
+            animation,
+        );
+        let playback = vat::Playback::new(animation);
+
+        self.draws.push(draw::Draw {
+            transform,
+            bounds: animation.bounds().map(bounds::Bounds::new),
+            scene: self.scene,
+            animation: Some(texture.binding(&playback)),
+            ..draw::Draw::indexed(
+                pipeline::Config {
+                    program: pipeline::Program::Vat,
+                    ..material.pipeline_config(mesh.attributes())
+                },
+                vertex_buffer,
+                index_buffer,
+                mesh.indices.len() as u32,
+            )
+        });
+        self.animations.push(vat::Animated {
+            draw: self.draws.len() - 1,
+            texture,
+            playback,
+            buffers: vec![
+                (vertex_buffer, vertex_buffer_memory),
+                (index_buffer, index_buffer_memory),
+            ],
+        });
+        Ok(self.animations.len() - 1)
+    }
+
+    /// The playback of a draw added by `add_vertex_animation`, to play, pause or seek it
+    pub fn vertex_animation(&mut self, index: usize) -> &mut vat::Playback {
+        &mut self.animations[index].playback
+    }
+
+    /// Pauses every vertex animation, or plays them again if they are paused. With shift held they start over
+    /// instead.
+    fn toggle_vertex_animations(&mut self) {
+        let restart = self.modifiers.shift();
+        let paused = self
+            .animations
+            .iter()
+            .all(|animated| !animated.playback.playing);
+        for index in 0..self.animations.len() {
+            let playback = self.vertex_animation(index);
+            if restart {
+                playback.seek(0.0);
+            } else if paused {
+                playback.play();
+            } else {
+                playback.pause();
+            }
+        }
+    }
+
+    /// Advances every vertex animation by the time since the last frame, or by a fixed step while a timeline plays
+    fn update_animations(&mut self) {
+        let now = Instant::now();
+        let elapsed = match self.timeline {
+            Some(_) => Duration::from_secs_f32(flythrough::PLAYBACK_STEP),
+            None => now - self.animations_updated,
+        };
+        self.animations_updated = now;
+        for animated in self.animations.iter_mut() {
+            animated.playback.advance(elapsed);
+            self.draws[animated.draw].animation =
+                Some(animated.texture.binding(&animated.playback));
+        }
+    }
+
+    /// Sets how later scene switches transition
+    pub fn set_transition(&mut self, effect: transition::Effect, duration: Duration) {
+        if let Some(transitions) = self.features.get_mut::<transition::Transitions>() {
+            transitions.effect = effect;
+            transitions.duration = duration;
+        }
+    }
+
+    /// Fogs every frame from now on. Press F to toggle it.
+    pub fn add_fog(&mut self, settings: fog::Settings) {
+        let fog = fog::Fog::new(&self.logical_device, settings);
+        self.add_feature(Box::new(fog));
+    }
+
+    /// Draws a lens flare over the frame for the sun, or the fixed light without a time of day, whenever it is in
+    /// view. `intensity` scales how opaque the flare is.
+    pub fn add_lens_flare(&mut self, intensity: f32) {
+        let flare = flare::LensFlare::new(&self.logical_device, intensity);
+        self.add_feature(Box::new(flare));
+        self.add_light_visibility();
+    }
+
+    /// Streaks light out from the sun through the gaps in the scene in post processing, as strongly as
+    /// `strength`. Zero turns the god rays off.
+    pub fn set_god_rays(&mut self, strength: f32) -> Result<(), String> {
+        let post_process = self
+            .features
+            .get_mut::<postprocess::PostProcess>()
+            .ok_or_else(|| String::from("post processing hasn't been added"))?;
+        post_process.god_rays = strength.max(0.0);
+        if strength > 0.0 {
+            self.add_light_visibility();
+        }
+        Ok(())
+    }
+
+    /// Applies a quality preset while the renderer runs, filtering textures and turning effects that have been
+    /// added on or off to match, see `quality::Preset`
+    pub fn set_quality(&mut self, preset: quality::Preset) {
+        let settings = preset.settings();
+        let limit = unsafe {
+            self.instance
+                .get_physical_device_properties(self.physical_device)
+        }
+        .limits
+        .max_sampler_anisotropy;
+        // Frames in flight may still be sampling with the old sampler
+        present_thread::device_wait_idle(&self.logical_device)
+            .expect("Waiting for the device to finish before changing the texture sampler");
+        unsafe {
+            self.logical_device
+                .destroy_sampler(self.texture_sampler, None);
+        }
+        self.texture_sampler =
+            Self::create_texture_sampler(&self.logical_device, settings.anisotropy.min(limit));
+        self.write_texture_descriptors();
+
+        if let Some(fog) = self.features.get_mut::<fog::Fog>() {
+            fog.enabled = settings.fog;
+        }
+        if let Some(flare) = self.features.get_mut::<flare::LensFlare>() {
+            flare.enabled = settings.lens_flare;
+        }
+        if let Some(post_process) = self.features.get_mut::<postprocess::PostProcess>() {
+            post_process.god_rays_enabled = settings.god_rays;
+        }
+        if let Some(volume) = self.features.get_mut::<volume::Volume>() {
+            volume.samples_per_voxel = settings.volume_samples_per_voxel;
+        }
+        self.capsule_shadows.enabled = settings.capsule_shadows;
+        self.ground_shadows.mode = settings.ground_shadows;
+        if let Some(current) = self
+            .shadow_map
+            .as_ref()
+            .map(|shadow_map| shadow_map.settings)
+        {
+            let shadows = shadows::Settings {
+                filter: settings.shadow_filter,
+                resolution: settings.shadow_resolution,
+                ..current
+            };
+            // The presets don't use VSM, so the map can be made with the formats it was made with before
+            if shadows != current {
+                self.set_shadows(Some(shadows))
+                    .expect("Changing the shadow map's quality");
+            }
+        }
+        self.quality = Some(preset);
+    }
+
+    /// Applies the preset `quality::recommend` guesses the device can keep up with, printing why it was chosen
+    pub fn set_recommended_quality(&mut self) {
+        let properties = unsafe {
+            self.instance
+                .get_physical_device_properties(self.physical_device)
+        };
+        let (preset, reason) =
+            quality::recommend(&properties, &self.physical_device_memory_properties);
+        self.events.status(format!(
+            "Starting at {} quality for {}, pass --quality to choose another",
+            preset.name(),
+            reason
+        ));
+        self.set_quality(preset);
+    }
+
+    /// Creates the pipeline variants every draw in every scene may be recorded with, so they don't stall the
+    /// frame that first draws with them. `progress` is called with how many have been created out of how many
+    /// were missing.
+    pub fn prewarm_pipelines(&mut self, progress: impl FnMut(usize, usize)) {
+        let configs: Vec<pipeline::Config> = self
+            .draws
+            .iter()
+            .flat_map(draw::Draw::expected_configs)
+            .chain(self.indirect_scene.as_ref().map(indirect::Scene::config))
+            .collect();
+        self.pipelines
+            .prewarm(&self.logical_device, &configs, progress);
+    }
+
+    /// Starts estimating how much of the light can be seen, for the lens flare and god rays
+    fn add_light_visibility(&mut self) {
+        if self
+            .features
+            .get_mut::<visibility::LightVisibility>()
+            .is_none()
+        {
+            self.add_feature(Box::<visibility::LightVisibility>::default());
+        }
+    }
+
+    /// Ray marches the dataset over every frame from now on, coloured by the transfer function. The volume fills
+    /// a box centred on the origin turned to the world's up, see `volume::Dataset::default_transform`. Press V to
+    /// slice through it.
+    pub fn add_volume(
+        &mut self,
+        dataset: &volume::Dataset,
+        transfer_function: volume::TransferFunction,
+    ) {
+        let mut volume = volume::Volume::new(
+            &self.logical_device,
+            self.command_pool,
+            self.graphics_queue,
+            &self.physical_device_memory_properties,
+            dataset,
+            transfer_function,
+        );
+        volume.transform = self.units.up.convert(dataset.up) * volume.transform;
+        self.add_feature(Box::new(volume));
+    }
+
+    /// Renders the current scene's draws from `position` into each face of a cubemap `size` pixels square, for
+    /// authoring environment maps or checking what can be seen from a point. Renders between frames, waiting
+    /// for the device to be idle first.
+    pub fn capture_cubemap(
+        &mut self,
+        position: Point3<f32>,
+        size: u32,
+    ) -> Result<capture::Cubemap, String> {
+        present_thread::device_wait_idle(&self.logical_device)
+            .expect("Waiting for device to be idle");
+        let target = capture::Target::new(&self.swapchain_context(), size)?;
+        let face_count = capture::FACE_NAMES.len();
+        let (uniform_buffers, uniform_buffers_memory) = Self::create_uniform_buffers(
+            &self.logical_device,
+            self.physical_device_memory_properties,
+            face_count,
+        );
+        let descriptor_pool = Self::create_descriptor_pool(&self.logical_device, face_count);
+        let descriptor_sets = Self::create_descriptor_sets(
+            &self.logical_device,
+            descriptor_pool,
+            self.descriptor_set_layout,
+            face_count,
+        );
+        Self::populate_descriptor_sets(
+            &self.logical_device,
+            &descriptor_sets,
+            &uniform_buffers,
+            self.scene_texture_view(),
+            self.lightmap_image_view(),
+            self.toon_ramps_image_view(),
+            self.shadow_descriptors(),
+            self.texture_sampler,
+            face_count,
+        );
+
+        let projection = capture::face_projection();
+        // Captures are timed apart from frames
+        let mut timings = jobs::Timings::default();
+        let clear_values = self.clear_values(pipeline::RenderTarget::Scene);
+        let mut faces = Vec::new();
+        for (face, view) in capture::face_views(position).iter().enumerate() {
+            self.write_uniforms(
+                uniform_buffers_memory[face],
+                *view,
+                projection,
+                [0.0; audio::BAND_COUNT],
+            );
+            let mut visible = Vec::new();
+            self.visible_draws(
+                self.scene,
+                &(projection * view),
+                Some(position),
+                &mut timings,
+                &mut culling::Buffers::default(),
+                &mut visible,
+            );
+            for &index in visible.iter() {
+                self.draws[index].prepare(&self.logical_device, &mut self.pipelines);
+            }
+
+            let device = &self.logical_device;
+            let command_buffer = begin_single_time_commands(device, self.command_pool);
+            let render_pass_bi = vk::RenderPassBeginInfo::builder()
+                .render_pass(target.render_pass)
+                .framebuffer(target.framebuffer)
+                .render_area(vk::Rect2D {
+                    offset: vk::Offset2D { x: 0, y: 0 },
+                    extent: target.extent(),
+                })
+                .clear_values(&clear_values);
+            unsafe {
+                device.cmd_begin_render_pass(
+                    command_buffer,
+                    &render_pass_bi,
+                    vk::SubpassContents::INLINE,
+                )
+            };
+            SceneState {
+                area: vk::Rect2D::builder().extent(target.extent()).build(),
+                pipeline_layout: self.pipelines.layout(),
+                descriptor_set: descriptor_sets[face],
+                default_attribute_buffer: self.default_attribute_buffer,
+            }
+            .record(device, command_buffer);
+            for &index in visible.iter() {
+                self.draws[index].record(device, command_buffer, &self.pipelines);
+            }
+            unsafe { device.cmd_end_render_pass(command_buffer) };
+            target.record_copy(device, command_buffer);
+            end_single_time_commands(
+                device,
+                self.command_pool,
+                command_buffer,
+                self.graphics_queue,
+            );
+
+            faces.push(target.read_face(device));
+        }
+
+        unsafe {
+            self.logical_device
+                .destroy_descriptor_pool(descriptor_pool, None);
+            for (buffer, memory) in uniform_buffers.into_iter().zip(uniform_buffers_memory) {
+                self.logical_device.destroy_buffer(buffer, None);
+                self.logical_device.free_memory(memory, None);
+            }
+        }
+        target.destroy(&self.logical_device);
+
+        Ok(capture::Cubemap { size, faces })
+    }
+
+    /// Captures the scene around the camera and saves it to the working directory, both as cubemap faces and as
+    /// a panorama.
+    fn export_capture(&mut self) {
+        let eye = self
+            .view
+            .invert()
+            .expect("view is invertible")
+            .transform_point(Point3::new(0.0, 0.0, 0.0));
+        let result = self.capture_cubemap(eye, 512).and_then(|cubemap| {
+            let directory = Path::new(".");
+            let mut paths = cubemap.save_faces(directory, "capture")?;
+            let panorama_path = directory.join(format!("capture_panorama.{}", images::EXTENSION));
+            images::save(&cubemap.to_panorama(2048), &panorama_path)?;
+            paths.push(panorama_path);
+            Ok(paths)
+        });
+        let kind = events::ExportKind::Capture;
+        self.events.emit(match result {
+            Ok(paths) => events::Event::Exported { kind, paths },
+            Err(error) => events::Event::ExportFailed { kind, error },
+        });
+    }
+
+    /// The passes each frame records with the renderer's current configuration, and what they read and write
+    pub fn frame_graph(&self) -> framegraph::Graph {
+        let before = framegraph::Position::BeforeRenderPass;
+        // Submitted to the compute queue ahead of the frame. The frame's command buffer acquires the billboards
+        // even when the playground replaces the scene.
+        let mut passes = vec![framegraph::Pass::new(
+            "particles",
+            before,
+            &["particles"],
+            &["particles", "particle billboards"],
+        )
+        .on(framegraph::Queue::Compute)];
+        let mut scene_reads = vec![framegraph::UNIFORMS, "textures", "particle billboards"];
+        if self.playground.is_none() {
+            if self.indirect_scene.is_some() {
+                let mut cull_reads = vec![framegraph::UNIFORMS, "objects"];
+                if self.hi_z_depth().is_some() {
+                    // From the depth the previous frame left
+                    passes.push(framegraph::Pass::new(
+                        "build depth pyramid",
+                        before,
+                        &[framegraph::DEPTH],
+                        &["depth pyramid"],
+                    ));
+                    cull_reads.push("depth pyramid");
+                }
+                passes.push(framegraph::Pass::new(
+                    "cull objects",
+                    before,
+                    &cull_reads,
+                    &["draw commands"],
+                ));
+                scene_reads.push("draw commands");
+            }
+            if self.cloth.is_some() {
+                passes.push(framegraph::Pass::new(
+                    "cloth simulation",
+                    before,
+                    &["cloth particles"],
+                    &["cloth particles", "cloth vertices"],
+                ));
+                scene_reads.push("cloth vertices");
+            }
+            if self.swarm.is_some() {
+                passes.push(framegraph::Pass::new(
+                    "swarm simulation",
+                    before,
+                    &["swarm agents"],
+                    &["swarm agents"],
+                ));
+                scene_reads.push("swarm agents");
+            }
+            if self.minimap.is_some() {
+                // Shown by the 2D layer, one of the features drawn after the scene
+                passes.push(framegraph::Pass::new(
+                    "minimap",
+                    before,
+                    &scene_reads,
+                    &["minimap"],
+                ));
+            }
+            if !self.probes.is_empty() {
+                // A face of each probe at most, as the scheduler picks them
+                passes.push(framegraph::Pass::new(
+                    "reflection probes",
+                    before,
+                    &scene_reads,
+                    &["reflection probes"],
+                ));
+            }
+            if self.stereo.is_some() {
+                // Copied over the frame after the scene's draws
+                passes.push(framegraph::Pass::new(
+                    "left eye",
+                    before,
+                    &scene_reads,
+                    &["left eye"],
+                ));
+                scene_reads.push("left eye");
+            }
+        }
+        passes.push(framegraph::Pass::new(
+            match self.playground {
+                Some(_) => "sketch",
+                None => "scene",
+            },
+            framegraph::Position::RenderPass,
+            &scene_reads,
+            &[framegraph::SWAPCHAIN, framegraph::DEPTH],
+        ));
+        passes.extend(self.features.passes());
+        passes.extend(self.hooks.passes());
+        passes.push(
+            framegraph::Pass::new(
+                "present",
+                framegraph::Position::Present,
+                &[framegraph::SWAPCHAIN],
+                &[],
+            )
+            .on(self.present_pass_queue()),
+        );
+        framegraph::Graph::new(passes)
+    }
+
+    /// Lays out each frame in flight's batches from the frame graph. How they're laid out only depends on the
+    /// queues the passes are on and where they read each other's writes, which the present family decides, so
+    /// the batches are kept until the swapchain is made again rather than laid out every frame.
+    fn plan_submissions(&mut self) {
+        let plan = submission::Plan::new(&self.frame_graph());
+        let (graphics_queue, compute_queue, present_queue) = (
+            self.graphics_queue,
+            self.particles.queue(),
+            self.present_queue,
+        );
+        let handle = |queue| match queue {
+            framegraph::Queue::Graphics => graphics_queue,
+            framegraph::Queue::Compute => compute_queue,
+            framegraph::Queue::Present => present_queue,
+        };
+        let mut submissions = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
+        for frame in 0..MAX_FRAMES_IN_FLIGHT {
+            submissions.push(submission::Submission::new(
+                &self.logical_device,
+                &plan,
+                handle,
+                &mut self.submission_semaphores,
+                frame,
+            ));
+        }
+        self.submissions = submissions;
+    }
+
+    /// The present queue acquires each swapchain image the graphics queue releases when they are different
+    /// families, and otherwise the graphics queue presents what it drew
+    fn present_pass_queue(&self) -> framegraph::Queue {
+        match self.present_acquire {
+            Some(_) => framegraph::Queue::Present,
+            None => framegraph::Queue::Graphics,
+        }
+    }
+
+    /// The depth buffer as the last frame left it, linearized to distances from the camera. Waits for the device
+    /// to be idle first.
+    pub fn read_depth(&self) -> depth::DepthImage {
+        present_thread::device_wait_idle(&self.logical_device)
+            .expect("Waiting for device to be idle");
+        let extent = self.swapchain_data.extent;
+        let values = depth::read(
+            &self.logical_device,
+            &self.physical_device_memory_properties,
+            self.command_pool,
+            self.graphics_queue,
+            self.depth_image,
+            self.depth_format,
+            extent,
+        );
+        let mapping = camera::DepthMapping::new(&self.projection());
+        depth::DepthImage {
+            width: extent.width,
+            height: extent.height,
+            values: values
+                .into_iter()
+                .map(|value| mapping.view_depth(value))
+                .collect(),
+        }
+    }
+
+    /// Writes the depth buffer to `depth.png` in the working directory
+    fn export_depth(&mut self) {
+        let path = Path::new("depth.png");
+        let depth = self.read_depth();
+        let centre = depth.at(depth.width / 2, depth.height / 2).unwrap_or(0.0);
+        #[cfg(feature = "images")]
+        let saved = depth.to_image().save(path).map_err(|e| e.to_string());
+        #[cfg(not(feature = "images"))]
+        let saved = Err("16 bit images can't be written without the `images` feature");
+        let kind = events::ExportKind::Depth;
+        match saved {
+            Ok(()) => {
+                self.events.emit(events::Event::Exported {
+                    kind,
+                    paths: vec![path.to_path_buf()],
+                });
+                self.events
+                    .status(format!("The depth is {:.3} at the centre", centre));
+            }
+            Err(e) => self.events.emit(events::Event::ExportFailed {
+                kind,
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    /// Writes the frame graph to `frame_graph.dot` in the working directory and lists its passes
+    fn export_frame_graph(&mut self) {
+        let graph = self.frame_graph();
+        self.events.status(graph.describe());
+        let path = Path::new("frame_graph.dot");
+        let kind = events::ExportKind::FrameGraph;
+        self.events.emit(match fs::write(path, graph.to_dot()) {
+            Ok(()) => events::Event::Exported {
+                kind,
+                paths: vec![path.to_path_buf()],
+            },
+            Err(e) => events::Event::ExportFailed {
+                kind,
+                error: e.to_string(),
+            },
+        });
+    }
+
+    pub fn add_hook(&mut self, stage: hooks::Stage, name: &str, hook: hooks::Hook) {
+        self.hooks.add(stage, name, hook);
+    }
+
+    /// Calls `subscriber` with every event the renderer emits from now on, see `events::Event`
+    pub fn subscribe(&mut self, subscriber: events::Subscriber) {
+        self.events.subscribe(subscriber);
+    }
+
+    /// Whether events are printed as well as sent to subscribers, on to start with
+    pub fn set_event_logging(&mut self, log: bool) {
+        self.events.log = log;
+    }
+
+    /// Emits the events noted by the renderer's parts since the last call: the pipelines compiled, the textures
+    /// that didn't fit in memory and what happened to the frame stream
+    fn emit_pending_events(&mut self) {
+        for (program, duration) in self.pipelines.take_compiled() {
+            self.events
+                .emit(events::Event::PipelineCompiled { program, duration });
+        }
+        for downgrade in self.texture_budget.unreported() {
+            self.events.emit(events::Event::AllocationFailed(downgrade));
+        }
+        let reports = match self.features.get_mut::<remote::Remote>() {
+            Some(remote) => remote.take_reports(),
+            None => Vec::new(),
+        };
+        for report in reports {
+            self.events.emit(events::Event::Streaming(report));
+        }
+    }
+
+    fn create_synchronisation_primitives(
+        device: &ash::Device,
+    ) -> (Vec<vk::Semaphore>, Vec<vk::Semaphore>, Vec<vk::Fence>) {
+        let mut image_available_semaphores: Vec<vk::Semaphore> = Vec::new();
+        let mut render_complete_semaphores: Vec<vk::Semaphore> = Vec::new();
+        let mut in_flight_fences: Vec<vk::Fence> = Vec::new();
+
+        for _ in num::range(0, MAX_FRAMES_IN_FLIGHT) {
+            let (image_semaphore, render_semaphore, frame_fence) = unsafe {
+                (
+                    device
+                        .create_semaphore(&vk::SemaphoreCreateInfo::builder(), None)
+                        .expect("Image Semaphore"),
+                    device
+                        .create_semaphore(&vk::SemaphoreCreateInfo::builder(), None)
+                        .expect("Render Semaphore"),
+                    device
+                        .create_fence(
+                            &vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED),
+                            None,
+                        )
+                        .expect("Frame fence"),
+                )
+            };
+            image_available_semaphores.push(image_semaphore);
+            render_complete_semaphores.push(render_semaphore);
+            in_flight_fences.push(frame_fence);
+        }
+
+        (
+            image_available_semaphores,
+            render_complete_semaphores,
+            in_flight_fences,
+        )
+    }
+
+    /**
+    Main loop
+    */
+    fn init_window(event_loop: &EventLoop<render_thread::Notification>) -> winit::window::Window {
+        winit::window::WindowBuilder::new()
+            .with_title(APP_TITLE)
+            .with_inner_size(winit::dpi::LogicalSize::new(WINDOW_WIDTH, WINDOW_HEIGHT))
+            .build(event_loop)
+            .expect("Failed to create window.")
+    }
+
+    /**
+     * recreate_swapchain re-creates the swapchain and all structures that are dependent on it.
+     */
+    fn recreate_swapchain(&mut self) {
+        if let Some(thread) = self.present_thread.as_mut() {
+            thread.wait_idle();
+        }
+        present_thread::device_wait_idle(&self.logical_device)
+            .expect("Waiting for device to be idle");
+
+        self.cleanup_swapchain();
+
+        let swapchain_data = Self::create_swap_chain(
+            &self.instance,
+            &self.logical_device,
+            &self.surface_loader,
+            &self.physical_device,
+            &self.surface,
+            &self.window,
+            &mut self.surface_overrides,
+        );
+        self.swapchain_data = swapchain_data;
+        if let Some(acquire) = self.present_acquire.as_mut() {
+            acquire.record(&self.logical_device, &self.swapchain_data.images);
+        }
+
+        self.swapchain_image_views =
+            Self::create_swapchain_image_views(&self.logical_device, &self.swapchain_data);
+
+        // Images the present family gave back aren't handed back to the graphics family, so what they held can't be
+        // kept, see `set_pass_config`
+        debug_assert!(
+            self.present_acquire.is_none()
+                || self.passes.scene.color.load != pipeline::Load::Preserve,
+            "Preserving the scene's colour with a separate present family"
+        );
+        self.render_pass = Self::create_render_pass(
+            &self.logical_device,
+            self.swapchain_data.format,
+            self.depth_format,
+            &self.passes.scene,
+        );
+        self.first_render_pass = match self.passes.scene.preserves() {
+            true => Some(Self::create_render_pass(
+                &self.logical_device,
+                self.swapchain_data.format,
+                self.depth_format,
+                &self.passes.scene.first(),
+            )),
+            false => None,
+        };
+        self.images_drawn = vec![false; self.swapchain_image_views.len()];
+
+        self.pipelines = pipeline::Variants::new(
+            &self.logical_device,
+            self.descriptor_set_layout,
+            self.animation_set_layout,
+            Self::pipeline_target(&self.swapchain_data, self.render_pass),
+            *self.pipelines.limits(),
+            self.pipeline_cache,
+        );
+        self.pipelines
+            .set_custom_programs(self.shader_materials.programs());
+        // Made again for the new swapchain images, before the descriptor sets that sample it
+        if let Some(settings) = self
+            .shadow_map
+            .as_ref()
+            .map(|shadow_map| shadow_map.settings)
+        {
+            self.shadow_map = Some(
+                self.create_shadow_map(settings)
+                    .expect("Making the shadow map again"),
+            );
+        }
+
+        (
+            self.depth_image,
+            self.depth_image_memory,
+            self.depth_image_view,
+        ) = Self::create_depth_resources(
+            &self.physical_device_memory_properties,
+            &self.logical_device,
+            self.graphics_queue,
+            self.command_pool,
+            self.swapchain_data.extent,
+            self.depth_format,
+            self.depth_sampled(),
+        );
+        let depth = self.hi_z_depth();
+        if let Some(scene) = self.indirect_scene.as_mut() {
+            scene.set_depth(
+                &self.logical_device,
+                self.command_pool,
+                self.graphics_queue,
+                &self.physical_device_memory_properties,
+                depth,
+            );
+        }
+
+        self.swap_chain_frame_buffers = Self::create_frame_buffers(
+            &self.logical_device,
+            &self.swapchain_image_views,
+            self.depth_image_view,
+            self.swapchain_data.extent,
+            self.render_pass,
+        );
+
+        let (uniform_buffers, uniform_buffers_memory) = Self::create_uniform_buffers(
+            &self.logical_device,
+            self.physical_device_memory_properties,
+            self.swapchain_image_views.len(),
+        );
+        self.uniform_buffers = uniform_buffers;
+        self.uniform_buffers_memory = uniform_buffers_memory;
+
+        self.descriptor_pool =
+            Self::create_descriptor_pool(&self.logical_device, self.swapchain_image_views.len());
+        self.descriptor_sets = Self::create_descriptor_sets(
+            &self.logical_device,
+            self.descriptor_pool,
+            self.descriptor_set_layout,
+            self.swapchain_image_views.len(),
+        );
+        Self::populate_descriptor_sets(
+            &self.logical_device,
+            &self.descriptor_sets,
+            &self.uniform_buffers,
+            self.scene_texture_view(),
+            self.lightmap_image_view(),
+            self.toon_ramps_image_view(),
+            self.shadow_descriptors(),
+            self.texture_sampler,
+            self.swapchain_image_views.len(),
+        );
+
+        self.command_buffers = Self::create_command_buffers(
+            &self.logical_device,
+            self.command_pool,
+            &self.swap_chain_frame_buffers,
+        );
+        self.secondary_buffers = Self::create_secondary_buffers(
+            &self.logical_device,
+            &self.queue_families,
+            self.swapchain_data.images.len(),
+        );
+        if self.baked_draws.is_some() {
+            self.baked_draws = Some(Self::create_baked_draws(
+                &self.logical_device,
+                &self.queue_families,
+                self.swapchain_data.images.len(),
+            ));
+        }
+        if let Some(settings) = self.stereo.as_ref().map(|stereo| stereo.settings) {
+            self.stereo = Some(self.create_stereo(settings));
+        }
+        if let Some(statistics) = self.statistics.as_mut() {
+            // The averages so far are lost along with the old pools
+            statistics.destroy(&self.logical_device);
+            *statistics = statistics::PipelineStatistics::new(
+                &self.logical_device,
+                self.swapchain_data.images.len(),
+            );
+        }
+        if let Some(timer) = self
+            .frame_budget
+            .as_mut()
+            .and_then(|monitor| monitor.timer.as_mut())
+        {
+            timer.recreate(&self.logical_device, self.swapchain_data.images.len());
+        }
+
+        let mut features = mem::take(&mut self.features);
+        features.resize(&self.swapchain_context());
+        self.features = features;
+        self.recreate_scheduled_renders();
+        self.plan_submissions();
+        // Quick with the pipeline cache, and saves resizing from hitching again as each variant comes into view
+        self.prewarm_pipelines(|_, _| {});
+
+        self.emit_pending_events();
+        let info = self.info();
+        self.events.emit(events::Event::SwapchainRecreated(info));
+    }
+
+    fn cleanup_swapchain(&mut self) {
+        unsafe {
+            for &frame_buffer in self.swap_chain_frame_buffers.iter() {
+                self.logical_device.destroy_framebuffer(frame_buffer, None)
+            }
+
+            for &buffer in self.uniform_buffers.iter() {
+                self.logical_device.destroy_buffer(buffer, None)
+            }
+
+            for &buffer_memory in self.uniform_buffers_memory.iter() {
+                self.logical_device.free_memory(buffer_memory, None)
+            }
+
+            self.logical_device
+                .destroy_image_view(self.depth_image_view, None);
+            self.logical_device.destroy_image(self.depth_image, None);
+            self.logical_device
+                .free_memory(self.depth_image_memory, None);
+
+            self.logical_device
+                .destroy_descriptor_pool(self.descriptor_pool, None);
+
+            self.logical_device
+                .free_command_buffers(self.command_pool, &self.command_buffers);
+            self.secondary_buffers.destroy(&self.logical_device);
+            if let Some(baked_draws) = self.baked_draws.as_mut() {
+                baked_draws.destroy(&self.logical_device);
+            }
+            if let Some(stereo) = self.stereo.as_mut() {
+                stereo.destroy(&self.logical_device);
+            }
+            if let Some(minimap) = self.minimap.as_mut() {
+                minimap.destroy(&self.logical_device);
+            }
+            for probe in self.probes.iter_mut() {
+                probe.destroy(&self.logical_device);
+            }
+            if let Some(shadow_map) = self.shadow_map.as_mut() {
+                shadow_map.destroy(&self.logical_device);
+            }
+
+            self.pipelines.destroy(&self.logical_device);
+            if let Some(playground) = self.playground.as_mut() {
+                playground.destroy_pipeline(&self.logical_device);
+            }
+            self.logical_device
+                .destroy_render_pass(self.render_pass, None);
+            if let Some(first) = self.first_render_pass.take() {
+                self.logical_device.destroy_render_pass(first, None);
+            }
+
+            for &image_view in self.swapchain_image_views.iter() {
+                self.logical_device.destroy_image_view(image_view, None)
+            }
+            self.swapchain_data
+                .loader
+                .destroy_swapchain(self.swapchain_data.swapchain, None);
+        }
+    }
+
+    // TODO: Semaphores not in consistent state when re-creating swapchain when frame buffer is suboptimal
+    fn draw_frame(&mut self) {
+        // TODO: Wait for fences
+        let current_frame_fences = [self.frame_fences[self.current_frame]];
+        unsafe {
+            self.logical_device
+                .wait_for_fences(&current_frame_fences, true, u64::MAX)
+                .expect("Waiting for frame fence");
+        };
+        self.collect_garbage();
+        self.emit_pending_events();
+
+        // The swapchain can't be used by the render thread while the present thread is presenting to it
+        if let Some(result) = self
+            .present_thread
+            .as_mut()
+            .and_then(|thread| thread.wait_presented())
+        {
+            self.check_present(result);
+        }
+
+        // Request an image from the swap chain. It will signal the given semaphore when the image is ready
+        let (image_index, recreated) = unsafe {
+            match self.swapchain_data.loader.acquire_next_image(
+                self.swapchain_data.swapchain,
+                u64::MAX,
+                self.image_available_semaphores[self.current_frame],
+                vk::Fence::null(),
+            ) {
+                Ok((idx, _)) => (idx as usize, false),
+                Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                    self.recreate_swapchain();
+                    (0 as usize, true)
+                }
+                Err(_) => panic!("Failed to acquire swapchain image"),
+            }
+        };
+
+        // If the swapchain had to be re-created, exit early and draw again in the next tick.
+        if recreated {
+            return;
+        }
+        let cpu_start = Instant::now();
+        let mut cpu_waiting = Duration::ZERO;
+
+        self.update_remote();
+        self.update_gamepad();
+        self.update_dolly();
+        self.update_timeline();
+        self.model = self.model_transform();
+        #[cfg(feature = "physics")]
+        self.physics.update(&mut self.draws);
+        self.update_animations();
+        self.update_flythrough();
+        self.update_script();
+        self.update_logic();
+        self.update_immediate();
+        self.update_time_of_day();
+        self.update_glare();
+        self.update_streaming();
+        self.update_residency();
+        self.update_overlays();
+        self.update_bounds();
+        self.update_trails();
+        let audio_bands = self.update_audio();
+        // Picked before the uniforms are written, since only the renders drawn in the frame write theirs
+        self.schedule.next_frame();
+        let jitter = self.accumulation_jitter();
+        self.update_uniform_buffer(image_index, audio_bands, jitter);
+
+        // Make sure we don't reference a swapchain image that is already being presented
+        if self.image_fences[image_index] != vk::Fence::null() {
+            let active_image_in_flight_fences = [self.image_fences[image_index]];
+            let waiting = Instant::now();
+            unsafe {
+                self.logical_device
+                    .wait_for_fences(&active_image_in_flight_fences, true, u64::MAX)
+                    .expect("Image in flight fence");
+            };
+            // Waiting on the GPU isn't the CPU's work
+            cpu_waiting = waiting.elapsed();
+        };
+        self.image_fences[image_index] = self.frame_fences[self.current_frame];
+        if let Some(statistics) = self.statistics.as_mut() {
+            statistics.collect(&self.logical_device, image_index);
+        }
+        let device = &self.logical_device;
+        let gpu_passes = self
+            .frame_budget
+            .as_mut()
+            .and_then(|monitor| monitor.timer.as_mut())
+            .and_then(|timer| timer.collect(device, image_index));
+        if let Some(playground) = self.playground.as_mut() {
+            let target = Self::pipeline_target(&self.swapchain_data, self.render_pass);
+            playground.prepare(&self.logical_device, &target);
+        }
+        let mut features = mem::take(&mut self.features);
+        features.prepare(&self.swapchain_context(), image_index);
+        self.features = features;
+        self.update_inspector();
+        self.update_magnifier();
+        // The frame fence guarantees the frame's previous draw of the particles has finished
+        let particles_update = self
+            .particles
+            .update(&self.logical_device, self.current_frame);
+        self.draws[self.particle_draw].vertex_buffer =
+            self.particles.billboard_buffer(self.current_frame);
+        self.record_command_buffer(image_index);
+
+        // The frame graph's queues each get a batch, chained where one reads what another wrote. The graphics
+        // batch waits at the "color attachment" point until the image_available_semaphore has signaled, and the
+        // batch with the present pass signals the "render_complete_semaphore" once the image is ready to present.
+        let present_pass_queue = self.present_pass_queue();
+        let submission = &mut self.submissions[self.current_frame];
+        submission.reset();
+        submission.command_buffer(framegraph::Queue::Compute, particles_update);
+        submission.command_buffer(
+            framegraph::Queue::Graphics,
+            self.command_buffers[image_index],
+        );
+        submission.wait(
+            framegraph::Queue::Graphics,
+            self.image_available_semaphores[self.current_frame],
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        );
+        submission.fence(
+            framegraph::Queue::Graphics,
+            self.frame_fences[self.current_frame],
+        );
+        if let Some(acquire) = self.present_acquire.as_ref() {
+            submission.command_buffer(
+                framegraph::Queue::Present,
+                acquire.command_buffer(image_index),
+            );
+        }
+        let present_wait_semaphores = [self.render_complete_semaphores[self.current_frame]];
+        submission.signal(present_pass_queue, present_wait_semaphores[0]);
+
+        unsafe {
+            self.logical_device
+                .reset_fences(&current_frame_fences)
+                .expect("Resetting current frame fence");
+        }
+        submission
+            .submit(&self.logical_device)
+            .expect("Queue submit");
+        self.check_frame_budget(cpu_start.elapsed() - cpu_waiting, gpu_passes);
+
+        match self.present_thread.as_mut() {
+            Some(thread) => thread.present(
+                self.swapchain_data.swapchain,
+                image_index as u32,
+                present_wait_semaphores[0],
+            ),
+            None => {
+                let swapchains = [self.swapchain_data.swapchain];
+                let image_indices = [image_index as u32];
+                let present_info = vk::PresentInfoKHR::builder()
+                    .wait_semaphores(&present_wait_semaphores)
+                    .swapchains(&swapchains)
+                    .image_indices(&image_indices);
+
+                let present_result = unsafe {
+                    self.swapchain_data
+                        .loader
+                        .queue_present(self.present_queue, &present_info.build())
+                };
+
+                match unsafe { self.logical_device.queue_wait_idle(self.present_queue) } {
+                    Ok(_) => {}
+                    Err(result) => self
+                        .events
+                        .status(format!("Error waiting for present queue: {}", result)),
+                };
+                self.check_present(present_result);
+            }
+        }
+
+        self.current_frame = (self.current_frame + 1) % MAX_FRAMES_IN_FLIGHT;
+    }
+
+    /// Handles what presenting a frame did, which the present thread reports as the next frame starts
+    fn check_present(&mut self, present_result: Result<bool, vk::Result>) {
+        match (present_result, self.frame_buffer_resized) {
+            (_, true) => {
+                // self.recreate_swapchain();
+                self.frame_buffer_resized = false;
+            }
+            (Ok(_), _) => (),
+            // (Ok(false), _) | (Err(vk::Result::ERROR_OUT_OF_DATE_KHR), _) => {
+            //     self.recreate_swapchain();
+            //     return;
+            // }
+            (Err(_), _) => panic!("Failed to present swapchain image"),
+        }
+    }
+
+    /// Presents from a thread of its own, so the render thread can start on the next frame while the present
+    /// queue finishes the last one, or from the render thread after each frame
+    pub fn set_present_thread(&mut self, enabled: bool) {
+        if let Some(result) = self
+            .present_thread
+            .take()
+            .and_then(|mut thread| thread.wait_idle())
+        {
+            self.check_present(result);
+        }
+        if enabled {
+            self.present_thread = Some(present_thread::PresentThread::spawn(
+                self.logical_device.clone(),
+                self.swapchain_data.loader.clone(),
+                self.present_queue,
+            ));
+        }
+    }
+
+    /// The model matrix applied to the scene's meshes, which spin around the Z axis over time
+    fn model_transform(&self) -> Matrix4<f32> {
+        Matrix4::from(Euler {
+            x: Deg(0f32),
+            y: Deg(0f32),
+            z: Deg(45f32) * self.clock(),
+        })
+    }
+
+    /// Seconds since the renderer started, or into the timeline while one plays
+    fn clock(&self) -> f32 {
+        match self.timeline.as_ref() {
+            Some(playback) => playback.time(),
+            None => (Instant::now() - self.start_time).as_secs_f32(),
+        }
+    }
+
+    /// Replaces `shadows` with the draws of the ground shadows of the current scene's casters. Shadows are only
+    /// drawn from one eye, so there are none in stereo or while the playground replaces the scene.
+    fn ground_shadow_draws(&self, shadows: &mut Vec<draw::Draw>) {
+        shadows.clear();
+        if self.stereo.is_some() || self.playground.is_some() {
+            return;
+        }
+        // Shadows are placed in world space, and their draws are transformed by the model matrix after that
+        let from_world = match self.model.invert() {
+            Some(inverse) => inverse,
+            None => return,
+        };
+        let casters = self.draws.iter().filter(|draw| {
+            draw.scene == self.scene
+                && draw.casts_ground_shadow
+                && draw.count > 0
+                && draw.instance_count > 0
+        });
+        let settings = &self.ground_shadows;
+        match settings.mode {
+            ground_shadows::Mode::Off => {}
+            ground_shadows::Mode::Planar => {
+                if let Some(projection) = settings.projection(self.units.up, self.light.direction) {
+                    let flatten = from_world * projection * self.model;
+                    shadows.extend(
+                        casters
+                            .filter_map(|caster| caster.ground_shadow(flatten * caster.transform)),
+                    );
+                }
+            }
+            ground_shadows::Mode::Blob => {
+                let count = (ground_shadows::DISC_SEGMENTS * 3) as u32;
+                shadows.extend(casters.filter_map(|caster| {
+                    let bounds = caster.bounds?;
+                    Some(draw::Draw {
+                        transform: from_world * settings.blob(self.units.up, &bounds.world),
+                        depth_bias: Some(pipeline::DepthBias::ground_shadow()),
+                        scene: self.scene,
+                        ..draw::Draw::new(pipeline::Config::blob_shadow(), self.disc_buffer, count)
+                    })
+                }));
+            }
+        }
+    }
+
+    /// Samples where each draw with a trail is for its ghosts
+    fn update_trails(&mut self) {
+        let model = self.model;
+        for draw in self.draws.iter_mut() {
+            let transform = draw.world_transform(&model);
+            if let Some(trail) = draw.trail.as_mut() {
+                trail.update(transform);
+            }
+        }
+    }
+
+    /// Moves the world space bounds of every draw to match its current transform
+    fn update_bounds(&mut self) {
+        let (draws, model) = (&mut self.draws, self.model);
+        self.timings.time("transforms", || {
+            draws.par_iter_mut().for_each(|draw| {
+                let transform = draw.world_transform(&model);
+                if let Some(bounds) = draw.bounds.as_mut() {
+                    bounds.update(&transform);
+                }
+            })
+        });
+    }
+
+    /// The world space bounds of everything drawn, or `None` if nothing drawn has bounds. Kept up to date with
+    /// the model transform each frame.
+    pub fn scene_bounds(&self) -> Option<bounds::Aabb> {
+        self.draws
+            .iter()
+            .filter(|draw| draw.scene == self.scene)
+            .filter_map(|draw| draw.bounds.map(|bounds| bounds.world))
+            .reduce(|a, b| a.union(&b))
+    }
+
+    /// Points the camera at the current scene from far enough away that the whole of its bounding sphere fits in
+    /// the vertical field of view
+    fn frame_scene(&mut self) {
+        self.camera.frame(self.scene_bounds());
+        self.view = self.camera.view();
+    }
+
+    /// Switches between perspective and orthographic projection, keeping the camera's target the same size
+    pub fn set_projection(&mut self, projection: camera::Projection) {
+        let after = camera::Camera {
+            projection,
+            ..self.camera
+        };
+        self.edit(history::Edit::Camera {
+            before: self.camera,
+            after,
+        });
+        self.events.status(format!("{:?} projection", projection));
+    }
+
+    /// Looks at the current scene from one of the standard directions, framing it again
+    pub fn set_view_preset(&mut self, preset: camera::Preset) {
+        let mut after = camera::Camera {
+            preset,
+            yaw: Rad(0.0),
+            pitch: Rad(0.0),
+            ..self.camera
+        };
+        after.frame(self.scene_bounds());
+        self.edit(history::Edit::Camera {
+            before: self.camera,
+            after,
+        });
+        self.events.status(format!("{:?} view", preset));
+    }
+
+    /// Moves the camera's target along a direction by the camera's move speed, undoably
+    fn pan_camera(&mut self, direction: Vector3<f32>) {
+        let after = camera::Camera {
+            target: self.camera.target + direction * self.camera.distance * self.camera.move_speed,
+            ..self.camera
+        };
+        self.edit(history::Edit::Camera {
+            before: self.camera,
+            after,
+        });
+    }
+
+    /// Changes one of the camera's parameters, undoably, for the projection and view from the next frame on
+    pub fn set_camera_parameter(
+        &mut self,
+        parameter: camera::Parameter,
+        value: f32,
+    ) -> Result<(), String> {
+        let mut after = self.camera;
+        parameter.set(&mut after, value)?;
+        self.edit(history::Edit::Camera {
+            before: self.camera,
+            after,
+        });
+        Ok(())
+    }
+
+    /// Eases the camera towards the distance the mouse wheel has scrolled it to. Moved directly rather than as an
+    /// edit, which would add one to the history every frame.
+    fn update_dolly(&mut self) {
+        if self.dolly.update(&mut self.camera) {
+            self.view = self.camera.view();
+        }
+    }
+
+    /// Places a draw in the scene, undoably
+    pub fn set_draw_transform(&mut self, draw: usize, transform: Matrix4<f32>) {
+        self.edit(history::Edit::Transform {
+            draw,
+            before: self.draws[draw].transform,
+            after: transform,
+        });
+    }
+
+    /// The material a draw's pipeline state was made from, see `material::Material::from_config`
+    fn draw_material(&self, draw: usize) -> Result<material::Material, String> {
+        self.draws
+            .get(draw)
+            .map(|draw| material::Material::from_config(&draw.pipeline))
+            .ok_or_else(|| format!("There's no draw {}", draw))
+    }
+
+    /// Changes a mesh draw's material, undoably. The draw keeps its program and topology, other than switching
+    /// between `pipeline::Program::Mesh` and a shader material's program when the material's shader changes.
+    pub fn set_draw_material(&mut self, draw: usize, material: material::Material) {
+        let before = self.draws[draw].pipeline;
+        let after = pipeline::Config {
+            program: match (material.shader, before.program) {
+                (Some(shader), _) => pipeline::Program::Custom(shader),
+                (None, pipeline::Program::Custom(_)) => pipeline::Program::Mesh,
+                (None, program) => program,
+            },
+            topology: before.topology,
+            ..material.pipeline_config(before.vertex_attributes)
+        };
+        self.edit(history::Edit::Material {
+            draw,
+            before,
+            after,
+        });
+    }
+
+    /// Applies an edit to the scene and remembers it so that it can be undone
+    fn edit(&mut self, edit: history::Edit) {
+        self.apply_edit(&edit);
+        self.history.record(edit);
+    }
+
+    fn apply_edit(&mut self, edit: &history::Edit) {
+        self.reset_accumulation();
+        match edit {
+            history::Edit::Transform { draw, after, .. } => self.draws[*draw].transform = *after,
+            history::Edit::Material { draw, after, .. } => self.draws[*draw].pipeline = *after,
+            history::Edit::Camera { after, .. } => {
+                self.camera = *after;
+                self.view = self.camera.view();
+            }
+            history::Edit::Clipping { after, .. } => self.clipping = after.clone(),
+            history::Edit::Batch(edits) => {
+                for edit in edits {
+                    self.apply_edit(edit);
+                }
+            }
+        }
+    }
+
+    pub fn undo(&mut self) {
+        match self.history.undo() {
+            Some(edit) => self.apply_edit(&edit),
+            None => self.events.status(String::from("Nothing to undo")),
+        }
+    }
+
+    pub fn redo(&mut self) {
+        match self.history.redo() {
+            Some(edit) => self.apply_edit(&edit),
+            None => self.events.status(String::from("Nothing to redo")),
+        }
+    }
+
+    /// Changes the clipping planes undoably
+    fn edit_clipping<F: FnOnce(&mut clipping::Clipping)>(&mut self, change: F) {
+        let before = self.clipping.clone();
+        let mut after = before.clone();
+        change(&mut after);
+        self.edit(history::Edit::Clipping { before, after });
+    }
+
+    /// Saves the current scene, camera, clipping planes, light, and each draw's transform and material to a scene
+    /// file, see `snapshot::Snapshot`
+    pub fn save_scene(&self, path: &Path) -> Result<(), String> {
+        snapshot::Snapshot {
+            scene: self.scene,
+            camera: self.camera,
+            grid_visible: self.grid.visible,
+            clipping: self.clipping.clone(),
+            draws: self.draws.iter().map(snapshot::DrawState::of).collect(),
+            quality: self.quality,
+            light: self.light,
+            time_of_day: self.time_of_day.map(|(time_of_day, _)| time_of_day),
+        }
+        .save(path)
+    }
+
+    /// Restores a scene file saved by `save_scene`. Fails without changing anything if the file doesn't have a
+    /// state for each of the renderer's draws, which is the case when it was saved with different arguments.
+    /// Loading can be undone, apart from the scene shown, the grid, the quality preset and the light.
+    pub fn load_scene(&mut self, path: &Path) -> Result<(), String> {
+        let snapshot = snapshot::Snapshot::load(path)?;
+        if snapshot.draws.len() != self.draws.len() {
+            return Err(format!(
+                "{} has {} draws but the renderer has {}",
+                path.display(),
+                snapshot.draws.len(),
+                self.draws.len()
+            ));
+        }
+        if snapshot.scene >= self.scene_count() {
+            return Err(format!(
+                "{} shows a scene that doesn't exist",
+                path.display()
+            ));
+        }
+        let mut edits = Vec::new();
+        for (index, (state, draw)) in snapshot.draws.iter().zip(self.draws.iter()).enumerate() {
+            let mut loaded = draw.clone();
+            state.apply(&mut loaded);
+            edits.push(history::Edit::Transform {
+                draw: index,
+                before: draw.transform,
+                after: loaded.transform,
+            });
+            edits.push(history::Edit::Material {
+                draw: index,
+                before: draw.pipeline,
+                after: loaded.pipeline,
+            });
+        }
+        // Scene files don't keep the world's up, which comes from the arguments
+        edits.push(history::Edit::Camera {
+            before: self.camera,
+            after: camera::Camera {
+                up: self.camera.up,
+                ..snapshot.camera
+            },
+        });
+        edits.push(history::Edit::Clipping {
+            before: self.clipping.clone(),
+            after: snapshot.clipping,
+        });
+        self.edit(history::Edit::Batch(edits));
+        self.switch_scene(snapshot.scene);
+        self.grid.visible = snapshot.grid_visible;
+        if let Some(preset) = snapshot.quality {
+            self.set_quality(preset);
+        }
+        // The time of day sets the light itself
+        self.set_time_of_day(snapshot.time_of_day);
+        if snapshot.time_of_day.is_none() {
+            self.light = snapshot.light;
+        }
+        self.events.emit(events::Event::AssetLoaded {
+            kind: events::AssetKind::Scene,
+            name: path.display().to_string(),
+        });
+        Ok(())
+    }
+
+    /// Loads the scene file if it exists, and saves to it on exit and when Ctrl+S is pressed
+    pub fn set_scene_file(&mut self, path: PathBuf) {
+        if path.exists() {
+            if let Err(error) = self.load_scene(&path) {
+                self.events.emit(events::Event::LoadFailed {
+                    kind: events::AssetKind::Scene,
+                    name: path.display().to_string(),
+                    error,
+                });
+            }
+        }
+        self.scene_file = Some(path);
+    }
+
+    fn save_scene_file(&mut self) {
+        if let Some(path) = self.scene_file.as_ref() {
+            let kind = events::ExportKind::Scene;
+            let event = match self.save_scene(path) {
+                Ok(()) => events::Event::Exported {
+                    kind,
+                    paths: vec![path.clone()],
+                },
+                Err(error) => events::Event::ExportFailed { kind, error },
+            };
+            self.events.emit(event);
+        }
+    }
+
+    /// Called before the renderer is destroyed when the window closes
+    pub fn close(&mut self) {
+        self.save_scene_file();
+        if self.camera_recorder.is_some() {
+            self.stop_camera_recording();
+        }
+    }
+
+    /// Sets where camera paths are recorded to and played back from
+    pub fn set_camera_path_file(&mut self, path: PathBuf) {
+        self.camera_path_file = Some(path);
+    }
+
+    /// Records the camera's trajectory from now on, until `stop_camera_recording`
+    pub fn start_camera_recording(&mut self) {
+        self.camera_recorder = Some(flythrough::Recorder::default());
+        self.events
+            .status(String::from("Recording the camera's path"));
+    }
+
+    /// Saves the path recorded since `start_camera_recording` to the camera path file
+    pub fn stop_camera_recording(&mut self) {
+        let recorder = match self.camera_recorder.take() {
+            Some(recorder) => recorder,
+            None => return,
+        };
+        let path = match self.camera_path_file.as_ref() {
+            Some(path) => path,
+            None => {
+                return self.events.emit(events::Event::ExportFailed {
+                    kind: events::ExportKind::CameraPath,
+                    error: String::from("There's no camera path file"),
+                })
+            }
+        };
+        let kind = events::ExportKind::CameraPath;
+        let event = match recorder.path.save(path) {
+            Ok(()) => events::Event::Exported {
+                kind,
+                paths: vec![path.clone()],
+            },
+            Err(error) => events::Event::ExportFailed { kind, error },
+        };
+        self.events.emit(event);
+        self.events.status(format!(
+            "Recorded {:.1} seconds of camera path",
+            recorder.path.duration()
+        ));
+    }
+
+    /// Moves the view along the path in place of the camera, one fixed step of it each frame, then returns the view
+    /// to the camera once it has all been played. See `flythrough::Player`.
+    pub fn play_camera_path(&mut self, path: flythrough::CameraPath) {
+        self.events.status(format!(
+            "Playing {:.1} seconds of camera path",
+            path.duration()
+        ));
+        self.camera_player = Some(flythrough::Player::new(path));
+    }
+
+    /// Plays the camera path file back
+    fn play_camera_path_file(&mut self) {
+        let path = match self.camera_path_file.as_ref() {
+            Some(path) => path,
+            None => {
+                return self
+                    .events
+                    .status(String::from("There's no camera path file to play"))
+            }
+        };
+        match flythrough::CameraPath::load(path) {
+            Ok(camera_path) => self.play_camera_path(camera_path),
+            Err(error) => {
+                let name = path.display().to_string();
+                self.events.emit(events::Event::LoadFailed {
+                    kind: events::AssetKind::CameraPath,
+                    name,
+                    error,
+                })
+            }
+        }
+    }
+
+    /// Records the camera's key for this frame, or moves the view to the playing path's
+    fn update_flythrough(&mut self) {
+        if let Some(recorder) = self.camera_recorder.as_mut() {
+            recorder.update(flythrough::Key {
+                eye: self.camera.eye(),
+                target: self.camera.target,
+            });
+        }
+        if let Some(player) = self.camera_player.as_mut() {
+            match player.advance() {
+                Some(key) => self.view = key.view(self.units.up),
+                None => {
+                    self.camera_player = None;
+                    self.view = self.camera.view();
+                    self.events
+                        .status(String::from("Finished playing the camera path"));
+                }
+            }
+        }
+    }
+
+    /// Plays the timeline from its start, a fixed step of it each frame, so that every playback renders the same
+    /// frames whatever the frame rate. See `timeline::Timeline`. The view returns to the camera at its end, and
+    /// draws and settings stay as it left them.
+    pub fn play_timeline(&mut self, timeline: timeline::Timeline) -> Result<(), String> {
+        if let Some(track) = timeline
+            .tracks
+            .iter()
+            .find(|track| track.draw >= self.draws.len())
+        {
+            return Err(format!(
+                "There are {} draws, so there's no draw {} to move",
+                self.draws.len(),
+                track.draw
+            ));
+        }
+        let scenes = self.scene_count();
+        for (_, action) in timeline.actions.iter() {
+            if let timeline::Action::Scene(scene) = action {
+                if *scene >= scenes {
+                    return Err(format!(
+                        "There are {} scenes, so there's no scene {} to switch to",
+                        scenes, scene
+                    ));
+                }
+            }
+        }
+        self.events
+            .status(format!("Playing {:.1} seconds of timeline", timeline.end));
+        self.timeline = Some(timeline::Playback::new(timeline));
+        Ok(())
+    }
+
+    /// Moves the timeline on to this frame, applying its keys and doing the actions that have come due
+    fn update_timeline(&mut self) {
+        let playback = match self.timeline.as_mut() {
+            Some(playback) => playback,
+            None => return,
+        };
+        let frame = match playback.advance() {
+            Some(frame) => frame,
+            None => {
+                self.timeline = None;
+                self.view = self.camera.view();
+                self.events
+                    .status(String::from("Finished playing the timeline"));
+                return;
+            }
+        };
+        for (draw, transform) in frame.transforms {
+            self.draws[draw].transform = transform;
+        }
+        for action in frame.actions {
+            self.apply_timeline_action(action);
+        }
+        // After the actions, since setting the projection moves the view back to the camera
+        if let Some(key) = frame.camera {
+            self.view = key.view(self.units.up);
+        }
+    }
+
+    fn apply_timeline_action(&mut self, action: timeline::Action) {
+        match action {
+            timeline::Action::Set(setting, on) => match setting {
+                timeline::Setting::Grid => self.set_grid_visible(on),
+                timeline::Setting::Fog => match self.features.get_mut::<fog::Fog>() {
+                    Some(fog) => fog.enabled = on,
+                    None => self.events.emit(events::Event::FeatureUnavailable {
+                        feature: String::from("fogging the scene"),
+                        error: String::from("Fog hasn't been added"),
+                    }),
+                },
+                timeline::Setting::Magnifier => self.set_magnifier(on),
+                timeline::Setting::Orthographic => self.set_projection(match on {
+                    true => camera::Projection::Orthographic,
+                    false => camera::Projection::Perspective,
+                }),
+                timeline::Setting::Baked => self.set_baked_draws(on),
+            },
+            timeline::Action::Scene(scene) => self.switch_scene(scene),
+        }
+    }
+
+    /// Cuts away the part of every mesh on the back of the plane, see `clipping::Clipping`. Planes beyond
+    /// `clipping::MAX_PLANES` are ignored.
+    pub fn add_clip_plane(&mut self, plane: clipping::Plane) {
+        self.edit_clipping(|clipping| clipping.planes.push(plane));
+    }
+
+    /// Whether meshes' cross sections with the clipping planes are filled in
+    pub fn set_clip_caps(&mut self, capped: bool) {
+        self.edit_clipping(|clipping| clipping.capped = capped);
+    }
+
+    /// Cuts the scene in half along the next axis through the centre of its bounds
+    fn cycle_section(&mut self) {
+        let center = self
+            .scene_bounds()
+            .map_or(Point3::new(0.0, 0.0, 0.0), |bounds| bounds.center());
+        // The planes are in the scene's space, which the bounds have been moved out of
+        let center = self
+            .model
+            .invert()
+            .map_or(center, |inverse| inverse.transform_point(center));
+        let mut section = None;
+        self.edit_clipping(|clipping| section = clipping.cycle_section(center));
+        self.events.status(match section {
+            Some(axis) => format!("Cutting the scene along axis {}", axis),
+            None => String::from("Not cutting the scene"),
+        });
+    }
+
+    /// Moves the clipping planes by a fiftieth of the scene's size
+    fn move_section(&mut self, direction: f32) {
+        let radius = self.scene_bounds().map_or(1.0, |bounds| bounds.radius());
+        self.edit_clipping(|clipping| clipping.offset(direction * radius / 50.0));
+    }
+
+    /// Changes the world's up axis and unit, turning the camera and grid to the new up. Assets already added keep
+    /// the up they were turned to.
+    pub fn set_units(&mut self, units: units::Convention) {
+        self.units = units;
+        self.camera.up = units.up;
+        self.view = self.camera.view();
+    }
+
+    pub fn set_grid_visible(&mut self, visible: bool) {
+        self.grid.visible = visible;
+    }
+
+    /// Keeps the grid under the camera's target and scaled for its projection, and the measurement's markers on
+    /// the points measured
+    fn update_overlays(&mut self) {
+        // The grid's spacing and the gizmo are sized in logical pixels, like the other overlays
+        let height = (self.display_extent().height as f32 / self.scale_factor) as u32;
+        self.grid.update(
+            &mut self.draws,
+            &self.camera,
+            &self.units,
+            &self.model,
+            height,
+            self.scene,
+        );
+        self.measurement.update(&mut self.draws, self.scene);
+    }
+
+    /// Steps from picking to measuring distances, then angles, then back to picking
+    fn cycle_measurement(&mut self) {
+        if !self.measuring {
+            self.measuring = true;
+            self.measurement.mode = measure::Mode::Distance;
+        } else if self.measurement.mode == measure::Mode::Angle {
+            self.measuring = false;
+        } else {
+            self.measurement.mode = self.measurement.mode.next();
+        }
+        self.measurement.clear();
+        self.events.status(match self.measuring {
+            true => format!("Measuring {:?}", self.measurement.mode),
+            false => String::from("Not measuring"),
+        });
+    }
+
+    /// Adds the surface under the cursor to the measurement, showing the result in the window title once there
+    /// are enough points
+    fn measure(&mut self) {
+        let position = match self.cursor_position {
+            Some(position) => position,
+            None => return,
+        };
+        let ray = self.cursor_ray(position);
+        let point = match self.raycast(ray.origin, ray.direction) {
+            Some(scene_hit) => scene_hit.hit.point,
+            None => return,
+        };
+        // The measurement is in the scene's space, so it turns with the scene
+        let point = self
+            .model
+            .invert()
+            .map_or(point, |inverse| inverse.transform_point(point));
+        if let Some(result) = self.measurement.add(point, &self.units) {
+            self.events.status(format!("Measured {}", result));
+            self.window
+                .set_title(&format!("{} - {}", APP_TITLE, result));
+        }
+    }
+
+    /// Casts a world space ray against every draw in the current scene that has a collider and returns the
+    /// closest hit.
+    pub fn raycast(
+        &self,
+        origin: Point3<f32>,
+        direction: Vector3<f32>,
+    ) -> Option<raycast::SceneHit> {
+        let world_ray = raycast::Ray::new(origin, direction);
+
+        self.draws
+            .iter()
+            .enumerate()
+            .filter(|(_, draw)| draw.scene == self.scene)
+            .filter_map(|(index, draw)| {
+                let collider = draw.collider.as_ref()?;
+                // Colliders are built in the space of the draw's vertices. Distances along the ray are the same
+                // in both spaces since the transform is affine.
+                let inverse = draw
+                    .world_transform(&self.model)
+                    .invert()
+                    .expect("draw transform is invertible");
+                let local_ray = raycast::Ray::new(
+                    inverse.transform_point(origin),
+                    inverse.transform_vector(direction),
+                );
+                let hit = collider.raycast(&local_ray)?;
+                let hit = raycast::Hit {
+                    point: world_ray.at(hit.distance),
+                    normal: inverse.transpose().transform_vector(hit.normal).normalize(),
+                    ..hit
+                };
+                Some(raycast::SceneHit { draw: index, hit })
+            })
+            .min_by(|a, b| {
+                a.hit
+                    .distance
+                    .partial_cmp(&b.hit.distance)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+    }
+
+    /// The ray from the camera through a position in the window
+    fn cursor_ray(&self, position: PhysicalPosition<f64>) -> raycast::Ray {
+        let extent = self.display_extent();
+        // The projection isn't flipped so normalized device Y points down the window like the cursor's
+        let x = (2.0 * position.x / extent.width as f64 - 1.0) as f32;
+        let y = (2.0 * position.y / extent.height as f64 - 1.0) as f32;
+        // The window isn't rotated, so neither is the cursor
+        let aspect_ratio = extent.width as f32 / extent.height as f32;
+        let inverse_view_projection = (self.camera.projection(aspect_ratio) * self.view)
+            .invert()
+            .expect("view projection is invertible");
+
+        let near = inverse_view_projection.transform_point(Point3::new(x, y, 0.0));
+        let far = inverse_view_projection.transform_point(Point3::new(x, y, 1.0));
+        raycast::Ray::new(near, far - near)
+    }
+
+    fn cursor_moved(&mut self, position: PhysicalPosition<f64>) {
+        self.cursor_position = Some(position);
+        let height = self.swapchain_data.extent.height as f32;
+        if let Some(playground) = self.playground.as_mut() {
+            playground.cursor_moved(position.x as f32, position.y as f32, height);
+        }
+    }
+
+    /// Picks in the scene when the button is pressed, or passes the button through to a running sketch
+    fn left_mouse_button(&mut self, pressed: bool) {
+        let height = self.swapchain_data.extent.height as f32;
+        match (self.playground.as_mut(), self.cursor_position) {
+            (Some(playground), Some(position)) => {
+                playground.mouse_button(pressed, position.x as f32, position.y as f32, height)
+            }
+            (Some(_), None) => (),
+            (None, _) if pressed && self.measuring => self.measure(),
+            (None, _) if pressed => self.pick(),
+            (None, _) => (),
+        }
+    }
+
+    /// Centres the magnifier on the cursor for this frame.
+    fn update_magnifier(&mut self) {
+        let cursor = self
+            .cursor_position
+            .filter(|position| position.x >= 0.0 && position.y >= 0.0)
+            .map(|position| (position.x as u32, position.y as u32));
+        if let Some(magnifier) = self.features.get_mut::<magnifier::Magnifier>() {
+            magnifier.cursor = cursor;
+        }
+    }
+
+    /// Shows the last pixel read back in the window title, and picks the pixel to read back from this frame.
+    fn update_inspector(&mut self) {
+        let extent = self.swapchain_data.extent;
+        let pixel = match self.cursor_position {
+            Some(position)
+                if self.modifiers.alt()
+                    && position.x >= 0.0
+                    && position.y >= 0.0
+                    && (position.x as u32) < extent.width
+                    && (position.y as u32) < extent.height =>
+            {
+                Some((position.x as u32, position.y as u32))
+            }
+            _ => None,
+        };
+
+        let inspector = match self.features.get_mut::<inspector::Inspector>() {
+            Some(inspector) => inspector,
+            None => return,
+        };
+        inspector.pixel = pixel;
+        let sample = inspector.take_latest();
+
+        if let Some(sample) = sample {
+            let title = format!("{} - {}", APP_TITLE, self.describe_sample(&sample));
+            self.window.set_title(&title);
+        }
+        if pixel.is_none() && self.inspecting {
+            self.window.set_title(APP_TITLE);
+        }
+        self.inspecting = pixel.is_some();
+    }
+
+    /// The sample's colour and depth, along with where the depth puts the pixel in the scene.
+    fn describe_sample(&self, sample: &inspector::Sample) -> String {
+        let (x, y) = sample.pixel;
+        let color = match sample.color {
+            Some([r, g, b, a]) => {
+                let mut description =
+                    format!("rgba {} {} {} {} #{:02x}{:02x}{:02x}", r, g, b, a, r, g, b);
+                if util::is_srgb_format(self.swapchain_data.format) {
+                    let linear = |encoded: u8| {
+                        let value = encoded as f32 / 255.0;
+                        if value <= 0.04045 {
+                            value / 12.92
+                        } else {
+                            ((value + 0.055) / 1.055).powf(2.4)
+                        }
+                    };
+                    description +=
+                        &format!(" linear {:.3} {:.3} {:.3}", linear(r), linear(g), linear(b));
+                }
+                description
+            }
+            None => String::from("colour unreadable"),
+        };
+
+        let depth = if sample.depth >= 1.0 {
+            format!("depth {:.5} (background)", sample.depth)
+        } else {
+            let extent = self.swapchain_data.extent;
+            let ndc = Point3::new(
+                2.0 * (x as f32 + 0.5) / extent.width as f32 - 1.0,
+                2.0 * (y as f32 + 0.5) / extent.height as f32 - 1.0,
+                sample.depth,
+            );
+            let inverse_projection = self
+                .projection()
+                .invert()
+                .expect("projection is invertible");
+            let inverse_view = self.view.invert().expect("view is invertible");
+            let view_position = inverse_projection.transform_point(ndc);
+            let world = inverse_view.transform_point(view_position);
+            format!(
+                "depth {:.5} distance {:.3} world ({:.2}, {:.2}, {:.2})",
+                sample.depth,
+                view_position.to_vec().magnitude(),
+                world.x,
+                world.y,
+                world.z
+            )
+        };
+
+        format!("pixel ({}, {}) {} {}", x, y, color, depth)
+    }
+
+    fn key_pressed(&mut self, key: VirtualKeyCode) {
+        let filter_keys = [
+            VirtualKeyCode::Key1,
+            VirtualKeyCode::Key2,
+            VirtualKeyCode::Key3,
+            VirtualKeyCode::Key4,
+            VirtualKeyCode::Key5,
+            VirtualKeyCode::Key6,
+            VirtualKeyCode::Key7,
+            VirtualKeyCode::Key8,
+            VirtualKeyCode::Key9,
+        ];
+
+        if key == VirtualKeyCode::E {
+            self.export_capture();
+        }
+        if key == VirtualKeyCode::P {
+            self.toggle_vertex_animations();
+        }
+        if key == VirtualKeyCode::N {
+            self.switch_scene((self.scene + 1) % self.scene_count());
+        }
+        if key == VirtualKeyCode::T {
+            if let Some(transitions) = self.features.get_mut::<transition::Transitions>() {
+                transitions.effect = transitions.effect.next();
+                self.events
+                    .status(format!("Scene transition: {:?}", transitions.effect));
+            }
+        }
+        if key == VirtualKeyCode::F {
+            if let Some(fog) = self.features.get_mut::<fog::Fog>() {
+                fog.enabled = !fog.enabled;
+                self.events
+                    .status(format!("Fog {}", if fog.enabled { "on" } else { "off" }));
+            }
+        }
+        if key == VirtualKeyCode::O {
+            self.set_projection(self.camera.projection.toggle());
+        }
+        if key == VirtualKeyCode::C {
+            self.set_view_preset(self.camera.preset.next());
+        }
+        if key == VirtualKeyCode::G {
+            self.set_grid_visible(!self.grid.visible);
+        }
+        if key == VirtualKeyCode::Z && self.modifiers.ctrl() {
+            match self.modifiers.shift() {
+                true => self.redo(),
+                false => self.undo(),
+            }
+        }
+        if key == VirtualKeyCode::Y && self.modifiers.ctrl() {
+            self.redo();
+        }
+        if key == VirtualKeyCode::G && self.modifiers.ctrl() {
+            self.export_frame_graph();
+            return;
+        }
+        if key == VirtualKeyCode::D && self.modifiers.ctrl() {
+            self.export_depth();
+        }
+        if key == VirtualKeyCode::S && self.modifiers.ctrl() {
+            self.save_scene_file();
+        }
+        // Along the axes with Z up, like the camera's presets, turned to the world's up
+        let nudges = [
+            (VirtualKeyCode::Right, Vector3::unit_x()),
+            (VirtualKeyCode::Left, -Vector3::unit_x()),
+            (VirtualKeyCode::Up, Vector3::unit_y()),
+            (VirtualKeyCode::Down, -Vector3::unit_y()),
+            (VirtualKeyCode::PageUp, Vector3::unit_z()),
+            (VirtualKeyCode::PageDown, -Vector3::unit_z()),
+        ];
+        if let Some(&(_, direction)) = nudges.iter().find(|(nudge_key, _)| *nudge_key == key) {
+            self.nudge_picked(self.units.up.rotation() * direction);
+        }
+        if key == VirtualKeyCode::B {
+            self.toggle_picked_double_sided();
+        }
+        let pans = [
+            (VirtualKeyCode::W, Vector3::unit_y()),
+            (VirtualKeyCode::S, -Vector3::unit_y()),
+            (VirtualKeyCode::D, Vector3::unit_x()),
+            (VirtualKeyCode::A, -Vector3::unit_x()),
+        ];
+        if let Some(&(_, direction)) = pans.iter().find(|(pan_key, _)| *pan_key == key) {
+            if !self.modifiers.ctrl() {
+                self.pan_camera(self.units.up.rotation() * direction);
+            }
+        }
+        if key == VirtualKeyCode::Insert {
+            self.add_crate();
+        }
+        if key == VirtualKeyCode::Delete {
+            if let Some(draw) = self.picked {
+                self.remove_draw(draw);
+                self.events.status(format!("Removed draw {}", draw));
+            }
+        }
+        if key == VirtualKeyCode::M {
+            self.cycle_measurement();
+        }
+        if key == VirtualKeyCode::K {
+            self.cycle_section();
+        }
+        if key == VirtualKeyCode::LBracket {
+            self.move_section(-1.0);
+        }
+        if key == VirtualKeyCode::RBracket {
+            self.move_section(1.0);
+        }
+        if key == VirtualKeyCode::R {
+            match (self.modifiers.shift(), self.camera_recorder.is_some()) {
+                (true, _) => self.play_camera_path_file(),
+                (false, true) => self.stop_camera_recording(),
+                (false, false) => self.start_camera_recording(),
+            }
+        }
+        if key == VirtualKeyCode::L {
+            if let Some(magnifier) = self.features.get_mut::<magnifier::Magnifier>() {
+                let status = if self.modifiers.shift() {
+                    format!("Magnifier zoom {}x", magnifier.cycle_zoom())
+                } else {
+                    magnifier.enabled = !magnifier.enabled;
+                    format!("Magnifier {}", if magnifier.enabled { "on" } else { "off" })
+                };
+                self.events.status(status);
+            }
+        }
+        if key == VirtualKeyCode::V {
+            if let Some(volume) = self.features.get_mut::<volume::Volume>() {
+                self.events.status(match volume.cycle_slice() {
+                    Some(axis) => format!("Slicing the volume along axis {}", axis),
+                    None => String::from("Not slicing the volume"),
+                });
+            }
+        }
+
+        // With Ctrl the number keys show and hide thumbnails instead of filters
+        if self.modifiers.ctrl() {
+            if let Some(index) = filter_keys.iter().position(|&filter_key| filter_key == key) {
+                if let Some(thumbnails) = self.features.get_mut::<thumbnails::Thumbnails>() {
+                    self.events.status(match thumbnails.toggle(index) {
+                        Some((name, visible)) => {
+                            format!("{} thumbnail {}", name, if visible { "on" } else { "off" })
+                        }
+                        None => format!(
+                            "No thumbnail {}, there are [{}]",
+                            index + 1,
+                            thumbnails.source_names().join(", ")
+                        ),
+                    });
+                }
+                return;
+            }
+        }
+
+        let post_process = match self.features.get_mut::<postprocess::PostProcess>() {
+            Some(post_process) => post_process,
+            None => return,
+        };
+
+        if key == VirtualKeyCode::H {
+            post_process.scope = scope::Mode::next(post_process.scope);
+            self.events
+                .status(format!("Colour scope: {:?}", post_process.scope));
+        }
+
+        // The number keys toggle post processing filters in the order they were registered
+        if let Some(index) = filter_keys.iter().position(|&filter_key| filter_key == key) {
+            if let Some(filter) = post_process.filters_mut().get_mut(index) {
+                filter.enabled = !filter.enabled;
+                self.events.status(format!(
+                    "{} filter {}",
+                    filter.name,
+                    if filter.enabled { "on" } else { "off" }
+                ));
+            }
+        }
+    }
+
+    /// Reports what is under the cursor
+    fn pick(&mut self) {
+        let position = match self.cursor_position {
+            Some(position) => position,
+            None => return,
+        };
+        let ray = self.cursor_ray(position);
+        let scene_hit = self.raycast(ray.origin, ray.direction);
+        let status = match scene_hit.as_ref() {
+            Some(scene_hit) => format!(
+                "Picked triangle {} of draw {}{} at {:?} with normal {:?}",
+                scene_hit.hit.triangle,
+                scene_hit.draw,
+                self.draws[scene_hit.draw]
+                    .name
+                    .as_ref()
+                    .map_or(String::new(), |name| format!(" \"{}\"", name)),
+                scene_hit.hit.point,
+                scene_hit.hit.normal
+            ),
+            None => String::from("Picked nothing"),
+        };
+        self.events.status(status);
+        self.picked = scene_hit.map(|scene_hit| scene_hit.draw);
+    }
+
+    /// Moves the picked draw by a fiftieth of the scene's size along an axis of the scene
+    fn nudge_picked(&mut self, direction: Vector3<f32>) {
+        if let Some(draw) = self.picked {
+            let radius = self.scene_bounds().map_or(1.0, |bounds| bounds.radius());
+            let offset = Matrix4::from_translation(direction * radius / 50.0);
+            self.set_draw_transform(draw, offset * self.draws[draw].transform);
+        }
+    }
+
+    /// Drops a crate into the current scene from its own mesh, which is freed once the crate is removed
+    fn add_crate(&mut self) {
+        let radius = self.scene_bounds().map_or(1.0, |bounds| bounds.radius());
+        let size = radius / 10.0;
+        let mesh = self.load_mesh(&Self::cuboid_mesh([size, size, size]));
+        let material = material::Material {
+            vertex_color: material::VertexColorMode::Multiply,
+            ..material::Material::default()
+        };
+        let draw = self.add_mesh(
+            &mesh,
+            material,
+            Matrix4::from_translation(Vector3::unit_z() * size),
+        );
+        self.events.status(format!("Added crate draw {}", draw));
+    }
+
+    fn toggle_picked_double_sided(&mut self) {
+        if let Some(draw) = self.picked {
+            let mut material = material::Material::from_config(&self.draws[draw].pipeline);
+            material.double_sided = !material.double_sided;
+            self.set_draw_material(draw, material);
+            self.events.status(format!(
+                "Draw {} is {}",
+                draw,
+                if material.double_sided {
+                    "double-sided"
+                } else {
+                    "single-sided"
+                }
+            ));
+        }
+    }
+
+    /// The camera's projection, rotated to match the surface when it is turned
+    fn projection(&self) -> Matrix4<f32> {
+        let extent = self.display_extent();
+        let aspect_ratio = extent.width as f32 / extent.height as f32;
+        prerotation::matrix(self.swapchain_data.transform) * self.camera.projection(aspect_ratio)
+    }
+
+    /// The size of the frame the way the user sees it, which is the swapchain's size turned with the surface
+    fn display_extent(&self) -> vk::Extent2D {
+        prerotation::display_extent(self.swapchain_data.extent, self.swapchain_data.transform)
+    }
+
+    /// Binds the gamepad's sticks, triggers and buttons as the file says, see `gamepad::Bindings`. Needs the
+    /// gamepad feature.
+    pub fn set_gamepad_bindings(&mut self, path: &Path) -> Result<(), String> {
+        #[cfg(feature = "gamepad")]
+        {
+            let bindings = gamepad::Bindings::load(path)?;
+            match self.gamepad.as_mut() {
+                Some(gamepad) => {
+                    gamepad.bindings = bindings;
+                    Ok(())
+                }
+                None => Err(String::from("gamepad input is unavailable")),
+            }
+        }
+        #[cfg(not(feature = "gamepad"))]
+        Err(format!(
+            "{} can't be used without the gamepad feature",
+            path.display()
+        ))
+    }
+
+    /// Turns and moves the camera with the gamepad's sticks, and presses the keys bound to the buttons pressed.
+    /// Does nothing without the gamepad feature or a gamepad.
+    fn update_gamepad(&mut self) {
+        #[cfg(feature = "gamepad")]
+        {
+            let motion = match self.gamepad.as_mut() {
+                Some(gamepad) => gamepad.update(),
+                None => return,
+            };
+            // Moved directly rather than as an edit, which would add one to the history every frame
+            if motion.moved() {
+                let pan = self.camera.distance * self.camera.move_speed;
+                self.camera.orbit(motion.yaw, motion.pitch);
+                self.camera.walk(motion.right * pan, motion.forward * pan);
+                self.view = self.camera.view();
+            }
+            for key in motion.keys {
+                self.handle_input(render_thread::Input::KeyPressed(key));
+            }
+        }
+    }
+
+    /// Runs the scene script each frame from now on, see `scripting::Script`. Needs the scripting feature.
+    pub fn run_script(&mut self, path: &Path) -> Result<(), String> {
+        #[cfg(feature = "scripting")]
+        {
+            self.script = Some(scripting::Script::load(path)?);
+            self.events.status(format!("Running {}", path.display()));
+            Ok(())
+        }
+        #[cfg(not(feature = "scripting"))]
+        Err(format!(
+            "{} can't be run without the scripting feature",
+            path.display()
+        ))
+    }
+
+    /// Runs the scene script over the draws, scene, fog and input, then applies what it changed. Does nothing
+    /// without the scripting feature or a script.
+    fn update_script(&mut self) {
+        #[cfg(feature = "scripting")]
+        {
+            if self.script.is_none() {
+                return;
+            }
+            let state = scripting::State {
+                time: self.clock(),
+                draws: self
+                    .draws
+                    .iter()
+                    .map(|draw| (draw.transform, draw.pipeline))
+                    .collect(),
+                scene: self.scene,
+                scene_count: self.scene_count(),
+                fog: self
+                    .features
+                    .get_mut::<fog::Fog>()
+                    .map(|fog| (fog.enabled, fog.settings)),
+                input: self.script_input.clone(),
+                errors: Vec::new(),
+            };
+            self.script_input.keys.clear();
+            let state = match self.script.as_mut().map(|script| script.run(state)) {
+                Some(Ok(state)) => state,
+                Some(Err(e)) => return self.events.status(format!("Stopped the script: {}", e)),
+                None => return,
+            };
+            for (draw, (transform, pipeline)) in self.draws.iter_mut().zip(state.draws) {
+                draw.transform = transform;
+                draw.pipeline = pipeline;
+            }
+            if let (Some(fog), Some((enabled, settings))) =
+                (self.features.get_mut::<fog::Fog>(), state.fog)
+            {
+                fog.enabled = enabled;
+                fog.settings = settings;
+            }
+            self.switch_scene(state.scene);
+            for error in state.errors {
+                self.events.status(format!("Script: {}", error));
+            }
+        }
+    }
+
+    /// Runs the frame update logic in the dynamic library each frame from now on, reloading it whenever it is
+    /// rebuilt, see `hot_reload::Logic`. Needs the hot-reload feature.
+    pub fn run_logic(&mut self, path: &Path) -> Result<(), String> {
+        #[cfg(feature = "hot-reload")]
+        {
+            let logic = hot_reload::Logic::load(path)?;
+            self.logic = Some((logic, Vec::new(), self.clock()));
+            self.events.status(format!("Running {}", path.display()));
+            Ok(())
+        }
+        #[cfg(not(feature = "hot-reload"))]
+        Err(format!(
+            "{} can't be run without the hot-reload feature",
+            path.display()
+        ))
+    }
+
+    /// Runs the frame update logic over the draws' transforms and the scene. Does nothing without the hot-reload
+    /// feature or a library.
+    fn update_logic(&mut self) {
+        #[cfg(feature = "hot-reload")]
+        {
+            let time = self.clock();
+            let scene_count = self.scene_count();
+            let (logic, keys, updated) = match self.logic.as_mut() {
+                Some(logic) => logic,
+                None => return,
+            };
+            let mut transforms: Vec<[[f32; 4]; 4]> = self
+                .draws
+                .iter()
+                .map(|draw| draw.transform.into())
+                .collect();
+            let codes: Vec<u32> = keys.drain(..).map(|key| key as u32).collect();
+            let mut frame = logic::Frame {
+                time,
+                delta: time - *updated,
+                transforms: transforms.as_mut_ptr(),
+                draw_count: transforms.len(),
+                scene: self.scene,
+                scene_count,
+                keys: codes.as_ptr(),
+                key_count: codes.len(),
+            };
+            *updated = time;
+            logic.update(&mut frame);
+            let scene = frame.scene;
+            for (draw, transform) in self.draws.iter_mut().zip(transforms) {
+                draw.transform = transform.into();
+            }
+            if scene < scene_count {
+                self.switch_scene(scene);
+            }
+        }
+    }
+
+    /// Moves the sun on, and the light and sky with it
+    fn update_time_of_day(&mut self) {
+        let time = self.clock();
+        let (time_of_day, updated) = match self.time_of_day.as_mut() {
+            Some(time_of_day) => time_of_day,
+            None => return,
+        };
+        time_of_day.update(time - *updated);
+        *updated = time;
+        self.light = time_of_day.light(self.units.up);
+        self.background = time_of_day.sky().background();
+    }
+
+    /// Lights the scene with a sun that moves over the day, and draws it over the sky. `None` goes back to the
+    /// fixed light over black.
+    pub fn set_time_of_day(&mut self, time_of_day: Option<sky::TimeOfDay>) {
+        match time_of_day {
+            Some(time_of_day) => {
+                self.time_of_day = Some((time_of_day, self.clock()));
+                self.update_time_of_day();
+            }
+            None => {
+                self.time_of_day = None;
+                self.light = sky::Light::default();
+                self.background = [0.0, 0.0, 0.0];
+            }
+        }
+    }
+
+    /// Points the lens flare, the god rays and the light's visibility estimate at the light, and scales the flare
+    /// and god rays by how much of it the depth buffer showed
+    fn update_glare(&mut self) {
+        let view_projection = self.projection() * self.view;
+        let position = match self.playground {
+            Some(_) => None,
+            None => flare::project(self.light.direction, &view_projection),
+        };
+        let visibility = match self.features.get_mut::<visibility::LightVisibility>() {
+            Some(visibility) => {
+                visibility.position = position;
+                visibility.visibility()
+            }
+            None => return,
+        };
+        let color = self.light.color;
+        if let Some(flare) = self.features.get_mut::<flare::LensFlare>() {
+            flare.source = position.map(|position| flare::Source {
+                position,
+                color,
+                visibility,
+            });
+        }
+        if let Some(post_process) = self.features.get_mut::<postprocess::PostProcess>() {
+            post_process.light = match position {
+                Some([x, y]) => postprocess::Light {
+                    position: [(x + 1.0) / 2.0, (y + 1.0) / 2.0],
+                    strength: color.iter().cloned().fold(0.0, f32::max) * visibility,
+                },
+                None => postprocess::Light::default(),
+            };
+        }
+    }
+
+    /// Runs the immediate mode update and brings the draws kept for it up to date with what it drew
+    fn update_immediate(&mut self) {
+        let mut immediate = match self.immediate.take() {
+            Some(immediate) => immediate,
+            None => return,
+        };
+        if let Some(camera) = immediate.run(self.camera, self.clock()) {
+            self.camera = camera;
+            self.view = self.camera.view();
+        }
+        let mut draws = mem::take(&mut immediate.draws);
+        let commands = immediate.commands();
+        for (index, command) in commands.iter().enumerate() {
+            let pipeline = command.material.pipeline_config(command.mesh.attributes());
+            match draws.get(index) {
+                Some(&draw)
+                    if self.draws[draw].pipeline == pipeline
+                        && self.draws[draw].vertex_buffer == command.mesh.vertex_buffer() =>
+                {
+                    let kept = &mut self.draws[draw];
+                    kept.transform = command.transform;
+                    kept.scene = self.scene;
+                }
+                Some(&draw) => {
+                    self.draws[draw] = Self::mesh_draw(
+                        &command.mesh,
+                        command.material,
+                        command.transform,
+                        self.scene,
+                    )
+                }
+                None => {
+                    let draw = self.add_mesh(&command.mesh, command.material, command.transform);
+                    draws.push(draw);
+                }
+            }
+        }
+        for &draw in draws[commands.len()..].iter() {
+            if self.draws[draw].mesh.is_some() {
+                self.remove_draw(draw);
+            }
+        }
+        immediate.draws = draws;
+        self.immediate = Some(immediate);
+    }
+
+    /// Band magnitudes of the latest captured audio. Silent without the audio feature or an input device.
+    fn update_audio(&mut self) -> [f32; audio::BAND_COUNT] {
+        #[cfg(feature = "audio")]
+        if let Some(capture) = self.audio.as_mut() {
+            return capture.update();
+        }
+        [0.0; audio::BAND_COUNT]
+    }
+
+    /// Writes the frame's uniforms, with the eyes' projections moved by `jitter` pixels while accumulating
+    fn update_uniform_buffer(
+        &self,
+        current_image: usize,
+        audio_bands: [f32; audio::BAND_COUNT],
+        jitter: Option<[f32; 2]>,
+    ) {
+        let eye_view_projection = |eye| {
+            let (view, projection) = self.eye_view_projection(eye);
+            match jitter {
+                Some(offset) => (
+                    view,
+                    accumulation::jittered(projection, offset, self.swapchain_data.extent),
+                ),
+                None => (view, projection),
+            }
+        };
+        let (view, projection) = eye_view_projection(stereo::Eye::Right);
+        self.write_uniforms(
+            self.uniform_buffers_memory[current_image],
+            view,
+            projection,
+            audio_bands,
+        );
+        if let Some(stereo) = self.stereo.as_ref() {
+            let (view, projection) = eye_view_projection(stereo::Eye::Left);
+            self.write_uniforms(
+                stereo.left.uniform_buffers_memory[current_image],
+                view,
+                projection,
+                audio_bands,
+            );
+        }
+        if let (Some(shadow_map), Some(light)) =
+            (self.shadow_map.as_ref(), self.shadow_light_view())
+        {
+            self.write_uniforms(
+                shadow_map.view.uniform_buffers_memory[current_image],
+                light.view,
+                light.projection,
+                audio_bands,
+            );
+        }
+        // Only the renders drawn in the frame, which are drawn at most once in it
+        if let Some(minimap) = self.minimap.as_ref() {
+            if self.schedule.scheduled(minimap.task).is_some() {
+                let (view, projection) = self.minimap_view_projection();
+                self.write_uniforms(
+                    minimap.view.uniform_buffers_memory[current_image],
+                    view,
+                    projection,
+                    audio_bands,
+                );
+            }
+        }
+        for probe in self.probes.iter() {
+            if let Some(face) = self.schedule.scheduled(probe.task) {
+                let (view, projection) = probe.face_view_projection(face);
+                self.write_uniforms(
+                    probe.view.uniform_buffers_memory[current_image],
+                    view,
+                    projection,
+                    audio_bands,
+                );
+            }
+        }
+    }
+
+    /// The view and projection the eye sees the scene through, which are the camera's own without stereo output
+    fn eye_view_projection(&self, eye: stereo::Eye) -> (Matrix4<f32>, Matrix4<f32>) {
+        let settings = match self.stereo.as_ref() {
+            Some(stereo) => stereo.settings,
+            None => return (self.view, self.projection()),
+        };
+        let extent = self.display_extent();
+        let aspect_ratio = extent.width as f32 / extent.height as f32 * settings.aspect_scale();
+        let (view, projection) = settings.eye(
+            eye,
+            self.view,
+            self.camera.projection(aspect_ratio),
+            self.camera.distance,
+            matches!(self.camera.projection, camera::Projection::Perspective),
+        );
+        (
+            view,
+            prerotation::matrix(self.swapchain_data.transform) * projection,
+        )
+    }
+
+    /// Writes the scene's uniforms as seen through `view` and `perspective` into a uniform buffer's memory
+    fn write_uniforms(
+        &self,
+        memory: vk::DeviceMemory,
+        view: Matrix4<f32>,
+        perspective: Matrix4<f32>,
+        audio_bands: [f32; audio::BAND_COUNT],
+    ) {
+        let ubo = UniformBufferObject {
+            model: self.model,
+            view,
+            perspective,
+            audio_bands,
+            clipping: self.clipping.uniforms(),
+            light: self.light.uniforms(),
+            capsules: self.capsule_shadows.uniforms(
+                self.draws
+                    .iter()
+                    .filter(|draw| draw.scene == self.scene && draw.count > 0)
+                    .flat_map(|draw| {
+                        let transform = draw.world_transform(&self.model);
+                        draw.capsules
+                            .iter()
+                            .map(move |capsule| capsule.transformed(&transform))
+                    }),
+                self.camera.target,
+            ),
+            ground_shadows: self.ground_shadows.uniforms(),
+            outlines: self.outlines.uniforms(
+                self.swapchain_data.extent.width,
+                self.swapchain_data.extent.height,
+            ),
+            shadows: match (self.shadow_map.as_ref(), self.shadow_light_view()) {
+                (Some(shadow_map), Some(light)) => shadow_map.settings.uniforms(&light),
+                _ => shadows::Uniforms::off(),
+            },
+        };
+
+        let buffer_size = mem::size_of::<UniformBufferObject>() as u64;
+
+        unsafe {
+            let data_ptr = self
+                .logical_device
+                .map_memory(memory, 0, buffer_size, vk::MemoryMapFlags::empty())
+                .expect("Failed to Map Memory");
+
+            ubo.write(data_ptr);
+
+            self.logical_device.unmap_memory(memory);
+        }
+    }
+
+    /// The commands the console runs with `run_console_command`
+    fn console_commands(&mut self) -> Vec<console::Command> {
+        let mut toggles: Vec<String> = [
+            "fog",
+            "flare",
+            "grid",
+            "magnifier",
+            "residency",
+            "baked",
+            "capsules",
+        ]
+        .iter()
+        .map(|&name| String::from(name))
+        .collect();
+        if let Some(post_process) = self.features.get_mut::<postprocess::PostProcess>() {
+            toggles.extend(
+                post_process
+                    .filters_mut()
+                    .iter()
+                    .map(|filter| filter.name.clone()),
+            );
+        }
+        let command = |name, usage, arguments: &[&str]| console::Command {
+            name,
+            usage,
+            arguments: arguments
+                .iter()
+                .map(|&argument| String::from(argument))
+                .collect(),
+        };
+        vec![
+            command("help", "help", &[]),
+            command(
+                "load",
+                "load <scene file> | load <cloud.ply|cloud.las> [sprites|surfels]",
+                &["scene"],
+            ),
+            command("exposure", "exposure <stops>", &[]),
+            command("godrays", "godrays <strength>", &[]),
+            command(
+                "quality",
+                "quality [low|medium|high|ultra]",
+                &quality::Preset::ALL.map(|preset| preset.name()),
+            ),
+            command(
+                "camera",
+                "camera [<parameter> [value]]",
+                &camera::Parameter::ALL.map(|parameter| parameter.name()),
+            ),
+            console::Command {
+                name: "toggle",
+                usage: "toggle <fog|flare|grid|magnifier|residency|baked|capsules|filter>",
+                arguments: toggles,
+            },
+            command("screenshot", "screenshot [path.png]", &[]),
+            command("scene", "scene <number>", &[]),
+            command("name", "name <draw> <name>", &[]),
+            command(
+                "capsules",
+                "capsules [<draw> fit|clear | shadow|occlusion|penumbra <value>]",
+                &["shadow", "occlusion", "penumbra"],
+            ),
+            command(
+                "groundshadows",
+                "groundshadows [off|blob|planar | opacity|height <value>]",
+                &["off", "blob", "planar", "opacity", "height"],
+            ),
+            command("groundshadow", "groundshadow <draw>", &[]),
+            command(
+                "shadows",
+                "shadows [off | hard | pcf [<kernel>] | pcss [<light size>] | vsm [<blur>] | resolution|extent|strength <value>]",
+                &[
+                    "off",
+                    "hard",
+                    "pcf",
+                    "pcss",
+                    "vsm",
+                    "resolution",
+                    "extent",
+                    "strength",
+                ],
+            ),
+            command("castshadows", "castshadows <draw>", &[]),
+            command(
+                "trail",
+                "trail <draw> [off | <length> [<interval> [<opacity>]]]",
+                &["off"],
+            ),
+            command(
+                "toon",
+                "toon <draw> [off | <ramp> [<outline pixels>]]",
+                &toon::RAMPS.map(|ramp| ramp.name),
+            ),
+            command("outlines", "outlines <red> <green> <blue>", &[]),
+            command(
+                "shader",
+                "shader load <name> <fragment> [<vertex>] | <name> [<parameter> <values or image>]",
+                &["load"],
+            ),
+            command("shade", "shade <draw> <shader material | off>", &["off"]),
+            command("text", "text clear | <x> <y> <size> <text...>", &["clear"]),
+            command(
+                "shape",
+                "shape rect <x> <y> <width> <height> [<radius>] | circle <x> <y> <radius> | line <x> <y> <x> <y> <width>",
+                &["rect", "circle", "line"],
+            ),
+            command("icon", "icon <x> <y> <size> <path.svg>", &[]),
+            command(
+                "minimap",
+                "minimap [off | <extent> [<interval> [<size>]]]",
+                &["off"],
+            ),
+            command(
+                "probe",
+                "probe add <x> <y> <z> [<size> [<interval>]] | save <probe> | clear",
+                &["add", "save", "clear"],
+            ),
+            command("schedule", "schedule [<budget>]", &[]),
+            command("events", "events [<count> | on | off]", &["on", "off"]),
+            command(
+                "accumulate",
+                "accumulate [on | off | reset | <samples>]",
+                &["on", "off", "reset"],
+            ),
+            command(
+                "pass",
+                "pass <target> [color <red> <green> <blue> | color background | load|store color|depth <policy>]",
+                &pipeline::RenderTarget::ALL.map(|target| target.name()),
+            ),
+            command(
+                "style",
+                "style fill | outline | glow <red> <green> <blue> <alpha> [<width>]",
+                &["fill", "outline", "glow"],
+            ),
+            command("clear", "clear", &[]),
+            command(
+                "time",
+                "time [off | <hour> | speed <hours a second> | latitude <degrees> | day <day of year>]",
+                &["off", "speed", "latitude", "day"],
+            ),
+        ]
+    }
+
+    /// Passes key presses and typed text to the console while it is open, running the lines entered. Returns
+    /// whether the console took the input, which the renderer's hotkeys then ignore.
+    fn console_input(&mut self, input: &render_thread::Input) -> bool {
+        let console = match self.features.get_mut::<console::Console>() {
+            Some(console) => console,
+            None => return false,
+        };
+        match *input {
+            render_thread::Input::KeyPressed(VirtualKeyCode::Grave) => console.toggle(),
+            render_thread::Input::KeyPressed(key) if console.open => {
+                if let Some(line) = console.key_pressed(key) {
+                    self.run_console_command(&line);
+                }
+            }
+            render_thread::Input::Character(character) if console.open => {
+                console.character(character)
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Runs a line entered in the console, printing what it did or why it couldn't there
+    fn run_console_command(&mut self, line: &str) {
+        let result = self.console_command(line);
+        if let Some(console) = self.features.get_mut::<console::Console>() {
+            match result {
+                Ok(message) => console.print(&message),
+                Err(e) => console.print(&format!("Error: {}", e)),
+            }
+        }
+    }
+
+    fn console_command(&mut self, line: &str) -> Result<String, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            ["help"] => match self.features.get_mut::<console::Console>() {
+                Some(console) => Ok(console.help()),
+                None => Ok(String::new()),
+            },
+            ["clear"] => {
+                if let Some(console) = self.features.get_mut::<console::Console>() {
+                    console.clear();
+                }
+                Ok(String::new())
+            }
+            ["load", "scene", path] => {
+                self.load_scene(Path::new(path))?;
+                Ok(format!("Loaded the scene from {}", path))
+            }
+            ["load", path] | ["load", path, _] => {
+                let cloud = pointcloud::PointCloud::load(Path::new(path))?;
+                let mode = words.get(2).copied().unwrap_or("sprites");
+                let mode = pointcloud::Mode::parse(mode, &cloud)
+                    .ok_or_else(|| format!("{} isn't sprites or surfels", mode))?;
+                let scene = self.add_point_cloud(&cloud, mode);
+                Ok(format!("Loaded {} as scene {}", path, scene))
+            }
+            ["exposure", stops] => {
+                let stops: f32 = stops
+                    .parse()
+                    .map_err(|_| format!("{} isn't a number of stops", stops))?;
+                let post_process = self
+                    .features
+                    .get_mut::<postprocess::PostProcess>()
+                    .ok_or_else(|| String::from("Post processing hasn't been added"))?;
+                post_process.exposure = stops;
+                Ok(format!("Exposure {:+} stops", stops))
+            }
+            ["godrays", strength] => {
+                let strength: f32 = strength
+                    .parse()
+                    .map_err(|_| format!("{} isn't a strength", strength))?;
+                self.set_god_rays(strength)?;
+                Ok(format!("God rays at {}", strength.max(0.0)))
+            }
+            ["quality"] => Ok(match self.quality {
+                Some(preset) => format!("Quality {}", preset.name()),
+                None => String::from("No quality preset has been applied"),
+            }),
+            ["quality", name] => {
+                let preset = quality::Preset::parse(name)
+                    .ok_or_else(|| format!("There's no {} quality preset", name))?;
+                self.set_quality(preset);
+                Ok(format!("Quality {}", preset.name()))
+            }
+            ["camera"] => Ok(camera::Parameter::ALL
+                .iter()
+                .map(|parameter| format!("{} {}", parameter.name(), parameter.get(&self.camera)))
+                .collect::<Vec<String>>()
+                .join("\n")),
+            ["camera", name] | ["camera", name, _] => {
+                let parameter = camera::Parameter::parse(name)
+                    .ok_or_else(|| format!("The camera has no {} parameter", name))?;
+                if let Some(value) = words.get(2) {
+                    let value: f32 = value
+                        .parse()
+                        .map_err(|_| format!("{} isn't a number", value))?;
+                    self.set_camera_parameter(parameter, value)?;
+                }
+                Ok(format!("{} {}", name, parameter.get(&self.camera)))
+            }
+            ["toggle", name] => self.toggle_setting(name),
+            ["time"] => Ok(match self.time_of_day.as_ref() {
+                Some((time_of_day, _)) => time_of_day.describe(),
+                None => String::from("The scene has no time of day"),
+            }),
+            ["time", "off"] => {
+                self.set_time_of_day(None);
+                Ok(String::from("Lit by the fixed light"))
+            }
+            ["time", ..] if words.len() <= 3 => {
+                let mut time_of_day = self
+                    .time_of_day
+                    .map_or_else(sky::TimeOfDay::default, |(time_of_day, _)| time_of_day);
+                let value = words[words.len() - 1];
+                let value: f32 = value
+                    .parse()
+                    .map_err(|_| format!("{} isn't a number", value))?;
+                match words[1..] {
+                    [_] if (0.0..24.0).contains(&value) => time_of_day.hour = value,
+                    [_] => return Err(format!("{} isn't an hour from 0 to 24", value)),
+                    ["speed", _] => time_of_day.speed = value,
+                    ["latitude", _] if (-90.0..=90.0).contains(&value) => {
+                        time_of_day.latitude = Deg(value)
+                    }
+                    ["latitude", _] => return Err(format!("{} isn't a latitude", value)),
+                    ["day", _] => time_of_day.day = value.rem_euclid(365.0),
+                    _ => return Err(String::from("Usage: time [off | <hour> | speed <hours a second> | latitude <degrees> | day <day of year>]")),
+                }
+                self.set_time_of_day(Some(time_of_day));
+                Ok(time_of_day.describe())
+            }
+            ["screenshot"] | ["screenshot", _] => {
+                let path = match words.get(1) {
+                    Some(path) => PathBuf::from(path),
+                    None => PathBuf::from(format!("screenshot.{}", images::EXTENSION)),
+                };
+                let screenshot = self
+                    .features
+                    .get_mut::<screenshot::Screenshot>()
+                    .ok_or_else(|| String::from("Screenshots haven't been added"))?;
+                let message = format!("Saving the next frame to {}", path.display());
+                screenshot.request(path)?;
+                Ok(message)
+            }
+            ["name", draw, ..] if words.len() > 2 => {
+                let draw: usize = draw
+                    .parse()
+                    .map_err(|_| format!("{} isn't a draw number", draw))?;
+                // The rest of the line, spaces and all
+                let name = line.trim_start()["name".len()..]
+                    .trim_start()
+                    .split_once(char::is_whitespace)
+                    .map_or("", |(_, name)| name.trim());
+                self.set_draw_name(draw, name)?;
+                Ok(format!("Named draw {} \"{}\"", draw, name))
+            }
+            ["capsules"] => {
+                let settings = self.capsule_shadows;
+                let count: usize = self.draws.iter().map(|draw| draw.capsules.len()).sum();
+                Ok(format!(
+                    "Capsule shadows {}, {} capsules, shadow {}, occlusion {}, penumbra {}",
+                    if settings.enabled { "on" } else { "off" },
+                    count,
+                    settings.shadow_strength,
+                    settings.occlusion_strength,
+                    settings.penumbra
+                ))
+            }
+            ["capsules", setting @ ("shadow" | "occlusion" | "penumbra"), value] => {
+                let value: f32 = value
+                    .parse()
+                    .map_err(|_| format!("{} isn't a number", value))?;
+                let settings = &mut self.capsule_shadows;
+                match setting {
+                    "shadow" => settings.shadow_strength = value.clamp(0.0, 1.0),
+                    "occlusion" => settings.occlusion_strength = value.clamp(0.0, 1.0),
+                    _ => settings.penumbra = value.max(0.0),
+                }
+                Ok(format!("Capsule {} {}", setting, value))
+            }
+            ["capsules", draw, action @ ("fit" | "clear")] => {
+                let draw: usize = draw
+                    .parse()
+                    .map_err(|_| format!("{} isn't a draw number", draw))?;
+                let capsules = match action {
+                    "fit" => vec![capsules::Capsule::fit(
+                        &self
+                            .draws
+                            .get(draw)
+                            .and_then(|draw| draw.bounds)
+                            .ok_or_else(|| format!("Draw {} has no bounds to fit to", draw))?
+                            .local,
+                    )],
+                    _ => Vec::new(),
+                };
+                let count = capsules.len();
+                self.set_draw_capsules(draw, capsules)?;
+                Ok(format!("Draw {} has {} capsules", draw, count))
+            }
+            ["groundshadows"] => {
+                let shadows = &self.ground_shadows;
+                let casters = self
+                    .draws
+                    .iter()
+                    .filter(|draw| draw.casts_ground_shadow)
+                    .count();
+                Ok(format!(
+                    "Ground shadows {}, {} casters, opacity {}, height {}",
+                    shadows.mode.name(),
+                    casters,
+                    shadows.opacity,
+                    shadows.height
+                ))
+            }
+            ["groundshadows", mode] => {
+                self.ground_shadows.mode = ground_shadows::Mode::parse(mode)
+                    .ok_or_else(|| format!("{} isn't off, blob or planar", mode))?;
+                Ok(format!("Ground shadows {}", mode))
+            }
+            ["groundshadows", setting @ ("opacity" | "height"), value] => {
+                let value: f32 = value
+                    .parse()
+                    .map_err(|_| format!("{} isn't a number", value))?;
+                match setting {
+                    "opacity" => self.ground_shadows.opacity = value.clamp(0.0, 1.0),
+                    _ => self.ground_shadows.height = value,
+                }
+                Ok(format!("Ground shadow {} {}", setting, value))
+            }
+            ["trail", draw, "off"] => {
+                let draw: usize = draw
+                    .parse()
+                    .map_err(|_| format!("{} isn't a draw number", draw))?;
+                self.set_draw_trail(draw, None)?;
+                Ok(format!("Draw {} has no trail", draw))
+            }
+            ["trail", draw, ..] if words.len() <= 5 => {
+                let draw: usize = draw
+                    .parse()
+                    .map_err(|_| format!("{} isn't a draw number", draw))?;
+                let number = |index: usize, default: &str| {
+                    let word = words.get(index).copied().unwrap_or(default);
+                    word.parse::<f32>()
+                        .map_err(|_| format!("{} isn't a number", word))
+                };
+                let length = number(2, "8")?.max(0.0) as usize;
+                let interval = number(3, "2")?.max(1.0) as u32;
+                let opacity = number(4, "0.5")?.clamp(0.0, 1.0);
+                self.set_draw_trail(draw, Some(trails::Trail::new(length, interval, opacity)))?;
+                Ok(format!(
+                    "Draw {} leaves {} ghosts {} frames apart, from {} opacity",
+                    draw, length, interval, opacity
+                ))
+            }
+            ["toon", draw, "off"] => {
+                let draw: usize = draw
+                    .parse()
+                    .map_err(|_| format!("{} isn't a draw number", draw))?;
+                let mut material = self.draw_material(draw)?;
+                material.toon = None;
+                self.set_draw_material(draw, material);
+                Ok(format!("Draw {} is lit smoothly", draw))
+            }
+            ["toon", draw, ramp, ..] if words.len() <= 4 => {
+                let draw: usize = draw
+                    .parse()
+                    .map_err(|_| format!("{} isn't a draw number", draw))?;
+                let row = toon::ramp(ramp)
+                    .or_else(|| ramp.parse().ok())
+                    .filter(|&row| row <= u8::MAX as usize)
+                    .ok_or_else(|| format!("{} isn't a ramp name or row", ramp))?;
+                let outline = match words.get(3) {
+                    Some(width) => width
+                        .parse::<f32>()
+                        .map_err(|_| format!("{} isn't a number", width))?,
+                    None => 0.0,
+                };
+                let mut material = self.draw_material(draw)?;
+                material.toon = Some(material::Toon {
+                    ramp: row as u8,
+                    outline,
+                });
+                self.set_draw_material(draw, material);
+                Ok(format!(
+                    "Draw {} is toon shaded with ramp {} and a {} pixel outline",
+                    draw, ramp, outline
+                ))
+            }
+            ["outlines", red, green, blue] => {
+                let mut color = [0.0; 3];
+                for (channel, word) in color.iter_mut().zip([red, green, blue].iter()) {
+                    *channel = word
+                        .parse::<f32>()
+                        .map_err(|_| format!("{} isn't a number", word))?
+                        .max(0.0);
+                }
+                self.outlines.color = color;
+                Ok(format!("Outlines are {} {} {}", red, green, blue))
+            }
+            ["shader", "load", name, fragment, ..] if words.len() <= 5 => {
+                let vertex = words.get(4).map(Path::new);
+                let index = self.register_shader_material(name, vertex, Path::new(fragment))?;
+                let parameters = self
+                    .shader_materials
+                    .get(index)
+                    .map(shader_materials::ShaderMaterial::parameters)
+                    .unwrap_or_default();
+                Ok(format!(
+                    "Loaded shader material {} with parameters: {}",
+                    name,
+                    parameters.join(", ")
+                ))
+            }
+            ["shader", name] => {
+                let material = self
+                    .shader_materials
+                    .find(name)
+                    .and_then(|index| self.shader_materials.get(index))
+                    .ok_or_else(|| format!("There's no shader material {}", name))?;
+                Ok(format!(
+                    "{} has parameters: {}",
+                    name,
+                    material.parameters().join(", ")
+                ))
+            }
+            ["shader", name, parameter, ..] if words.len() > 3 => {
+                let values = &words[3..];
+                let index = self
+                    .shader_materials
+                    .find(name)
+                    .ok_or_else(|| format!("There's no shader material {}", name))?;
+                self.set_shader_parameter(index, parameter, values)?;
+                Ok(format!(
+                    "Set {} of {} to {}",
+                    parameter,
+                    name,
+                    values.join(" ")
+                ))
+            }
+            ["shade", draw, name] => {
+                let draw: usize = draw
+                    .parse()
+                    .map_err(|_| format!("{} isn't a draw number", draw))?;
+                let shader = match name {
+                    "off" => None,
+                    name => Some(
+                        self.shader_materials
+                            .find(name)
+                            .ok_or_else(|| format!("There's no shader material {}", name))?,
+                    ),
+                };
+                let mut material = self.draw_material(draw)?;
+                if !matches!(
+                    self.draws[draw].pipeline.program,
+                    pipeline::Program::Mesh | pipeline::Program::Custom(_)
+                ) {
+                    return Err(format!("Draw {} isn't a mesh", draw));
+                }
+                material.shader = shader;
+                self.set_draw_material(draw, material);
+                Ok(format!("Draw {} is shaded by {}", draw, name))
+            }
+            ["text", "clear"] => match self.features.get_mut::<sdf::Layer>() {
+                Some(layer) => {
+                    let cleared = layer.len();
+                    layer.clear();
+                    Ok(format!("Removed {} texts, shapes and icons", cleared))
+                }
+                None => Err(String::from(
+                    "The text and shapes feature hasn't been added",
+                )),
+            },
+            ["text", x, y, size, ..] if words.len() > 4 => {
+                let number = |word: &str| {
+                    word.parse::<f32>()
+                        .map_err(|_| format!("{} isn't a number", word))
+                };
+                let text = sdf::Text {
+                    text: words[4..].join(" "),
+                    position: [number(x)?, number(y)?],
+                    size: number(size)?.max(1.0),
+                    style: self.sdf_style,
+                };
+                let index = self.add_sdf_item(sdf::Item::Text(text))?;
+                Ok(format!("Added text {}", index))
+            }
+            ["icon", x, y, size, path] => {
+                let number = |word: &str| {
+                    word.parse::<f32>()
+                        .map_err(|_| format!("{} isn't a number", word))
+                };
+                let size = number(size)?;
+                let index = self.add_svg(Path::new(path), [number(x)?, number(y)?], [size; 2])?;
+                Ok(format!("Added icon {}", index))
+            }
+            ["probe", "add", x, y, z, ..] if words.len() <= 7 => {
+                let number = |word: &str| {
+                    word.parse::<f32>()
+                        .map_err(|_| format!("{} isn't a number", word))
+                };
+                let whole = |index: usize, default: u32| match words.get(index) {
+                    Some(word) => word
+                        .parse::<u32>()
+                        .map_err(|_| format!("{} isn't a whole number", word)),
+                    None => Ok(default),
+                };
+                let position = Point3::new(number(x)?, number(y)?, number(z)?);
+                let size = whole(5, probes::DEFAULT_SIZE)?;
+                let interval = whole(6, probes::DEFAULT_INTERVAL)?;
+                let index = self.add_probe(position, size, interval);
+                Ok(format!(
+                    "Added probe {}, {} texels across, drawn every {} frames",
+                    index, size, interval
+                ))
+            }
+            ["probe", "save", probe] => {
+                let probe: usize = probe
+                    .parse()
+                    .map_err(|_| format!("{} isn't a probe number", probe))?;
+                let paths = self.save_probe(probe)?;
+                Ok(format!("Saved probe {} to {:?}", probe, paths))
+            }
+            ["probe", "clear"] => Ok(format!("Removed {} probes", self.clear_probes())),
+            ["schedule"] => {
+                let report = self.schedule.report();
+                Ok(match report.is_empty() {
+                    true => format!(
+                        "Nothing is scheduled, within {} renders a frame",
+                        self.schedule.budget
+                    ),
+                    false => format!(
+                        "Within {} renders a frame:\n{}",
+                        self.schedule.budget,
+                        report.join("\n")
+                    ),
+                })
+            }
+            ["schedule", budget] => {
+                let budget: usize = budget
+                    .parse()
+                    .map_err(|_| format!("{} isn't a number of renders", budget))?;
+                self.set_schedule_budget(budget);
+                Ok(format!(
+                    "Drawing {} scheduled renders a frame",
+                    self.schedule.budget
+                ))
+            }
+            ["pass", target, ..] => {
+                let target = pipeline::RenderTarget::parse(target)
+                    .ok_or_else(|| format!("{} isn't a render target", target))?;
+                let mut config = self.pass_config(target);
+                match &words[2..] {
+                    [] => {}
+                    ["color", "background"] => config.clear_color = None,
+                    ["color", red, green, blue] => {
+                        let mut color = [0.0; 3];
+                        for (channel, word) in color.iter_mut().zip([red, green, blue].iter()) {
+                            *channel = word
+                                .parse::<f32>()
+                                .map_err(|_| format!("{} isn't a number", word))?
+                                .max(0.0);
+                        }
+                        config.clear_color = Some(color);
+                    }
+                    [operation @ ("load" | "store"), attachment, policy] => {
+                        let attachment = match *attachment {
+                            "color" => &mut config.color,
+                            "depth" => &mut config.depth,
+                            _ => return Err(format!("{} isn't color or depth", attachment)),
+                        };
+                        match (*operation, *policy) {
+                            ("store", "on") => attachment.store = true,
+                            ("store", "off") => attachment.store = false,
+                            ("store", _) => return Err(format!("{} isn't on or off", policy)),
+                            _ => {
+                                attachment.load =
+                                    pipeline::Load::parse(policy).ok_or_else(|| {
+                                        format!("{} isn't clear, preserve or dontcare", policy)
+                                    })?
+                            }
+                        }
+                    }
+                    _ => return Err(String::from("Not a pass config")),
+                }
+                self.set_pass_config(target, config)?;
+                Ok(format!("{}: {}", target.name(), config.describe()))
+            }
+            ["accumulate"] => Ok(match self.accumulated_samples() {
+                Some((samples, max_samples)) => {
+                    format!("Accumulated {} of {} frames", samples, max_samples)
+                }
+                None => String::from("Not accumulating"),
+            }),
+            ["accumulate", "on"] => {
+                self.set_accumulation(Some(accumulation::DEFAULT_SAMPLES));
+                Ok(format!(
+                    "Accumulating up to {} frames",
+                    accumulation::DEFAULT_SAMPLES
+                ))
+            }
+            ["accumulate", "off"] => {
+                self.set_accumulation(None);
+                Ok(String::from("Accumulation off"))
+            }
+            ["accumulate", "reset"] => {
+                self.reset_accumulation();
+                Ok(String::from("Accumulating from the next frame"))
+            }
+            ["accumulate", samples] => {
+                let samples: u32 = samples
+                    .parse()
+                    .map_err(|_| format!("{} isn't a number of frames", samples))?;
+                self.set_accumulation(Some(samples));
+                Ok(format!("Accumulating up to {} frames", samples.max(1)))
+            }
+            ["events", log @ ("on" | "off")] => {
+                self.set_event_logging(log == "on");
+                Ok(format!("Printing events {}", log))
+            }
+            ["events"] | ["events", _] => {
+                let count = match words.get(1) {
+                    Some(count) => count
+                        .parse()
+                        .map_err(|_| format!("{} isn't a number of events", count))?,
+                    None => 10,
+                };
+                let recent: Vec<String> =
+                    self.events.recent(count).map(ToString::to_string).collect();
+                Ok(match recent.is_empty() {
+                    true => String::from("Nothing has happened yet"),
+                    false => recent.join("\n"),
+                })
+            }
+            ["minimap", "off"] => {
+                self.set_minimap(None)?;
+                Ok(String::from("Minimap off"))
+            }
+            ["minimap", ..] if words.len() <= 4 => {
+                let defaults = minimap::Settings::default();
+                let number = |index: usize, default: f32| match words.get(index) {
+                    Some(word) => word
+                        .parse::<f32>()
+                        .map_err(|_| format!("{} isn't a number", word)),
+                    None => Ok(default),
+                };
+                let settings = minimap::Settings {
+                    extent: number(1, defaults.extent)?.max(f32::EPSILON),
+                    interval: number(2, defaults.interval as f32)?.max(1.0) as u32,
+                    size: number(3, defaults.size)?.max(1.0),
+                    ..defaults
+                };
+                self.set_minimap(Some(settings))?;
+                Ok(format!(
+                    "Minimap {} units across, drawn every {} frames",
+                    settings.extent, settings.interval
+                ))
+            }
+            ["shape", kind, ..] => {
+                let numbers = words[2..]
+                    .iter()
+                    .map(|word| {
+                        word.parse::<f32>()
+                            .map_err(|_| format!("{} isn't a number", word))
+                    })
+                    .collect::<Result<Vec<f32>, String>>()?;
+                let shape = match (kind, numbers.as_slice()) {
+                    ("rect", [x, y, width, height]) => sdf::Shape::Rectangle {
+                        position: [*x, *y],
+                        size: [*width, *height],
+                        radius: 0.0,
+                    },
+                    ("rect", [x, y, width, height, radius]) => sdf::Shape::Rectangle {
+                        position: [*x, *y],
+                        size: [*width, *height],
+                        radius: *radius,
+                    },
+                    ("circle", [x, y, radius]) => sdf::Shape::Circle {
+                        centre: [*x, *y],
+                        radius: *radius,
+                    },
+                    ("line", [x0, y0, x1, y1, width]) => sdf::Shape::Line {
+                        from: [*x0, *y0],
+                        to: [*x1, *y1],
+                        width: *width,
+                    },
+                    _ => return Err(String::from("Usage: shape rect <x> <y> <width> <height> [<radius>] | circle <x> <y> <radius> | line <x> <y> <x> <y> <width>")),
+                };
+                let index = self.add_sdf_item(sdf::Item::Shape(shape, self.sdf_style))?;
+                Ok(format!("Added shape {}", index))
+            }
+            ["style", layer, red, green, blue, alpha, ..] if words.len() <= 7 => {
+                let number = |word: &str| {
+                    word.parse::<f32>()
+                        .map_err(|_| format!("{} isn't a number", word))
+                };
+                let color = [number(red)?, number(green)?, number(blue)?, number(alpha)?];
+                let width = words.get(6).map(|word| number(word)).transpose()?;
+                let style = &mut self.sdf_style;
+                match (layer, width) {
+                    ("fill", None) => style.fill = color,
+                    ("outline", width) => {
+                        style.outline = color;
+                        style.outline_width = width.unwrap_or(style.outline_width).max(0.0);
+                    }
+                    ("glow", width) => {
+                        style.glow = color;
+                        style.glow_width = width.unwrap_or(style.glow_width).max(0.0);
+                    }
+                    _ => return Err(String::from(
+                        "Usage: style fill | outline | glow <red> <green> <blue> <alpha> [<width>]",
+                    )),
+                }
+                Ok(format!(
+                    "Texts and shapes added next have a {} of {}",
+                    layer,
+                    words[2..].join(" ")
+                ))
+            }
+            ["groundshadow", draw] => {
+                let draw: usize = draw
+                    .parse()
+                    .map_err(|_| format!("{} isn't a draw number", draw))?;
+                let casts = !self
+                    .draws
+                    .get(draw)
+                    .ok_or_else(|| format!("There's no draw {}", draw))?
+                    .casts_ground_shadow;
+                self.set_draw_casts_ground_shadow(draw, casts)?;
+                Ok(format!(
+                    "Draw {} {} a ground shadow",
+                    draw,
+                    if casts { "casts" } else { "doesn't cast" }
+                ))
+            }
+            ["shadows"] => Ok(match self.shadow_map.as_ref() {
+                Some(shadow_map) => {
+                    let settings = shadow_map.settings;
+                    format!(
+                        "Shadow map {}, {} texels across {} units, strength {}",
+                        settings.filter.describe(),
+                        settings.resolution,
+                        settings.extent,
+                        settings.strength
+                    )
+                }
+                None => String::from("Shadow map off"),
+            }),
+            ["shadows", "off"] => {
+                self.set_shadows(None)?;
+                Ok(String::from("Shadow map off"))
+            }
+            ["shadows", setting @ ("resolution" | "extent" | "strength"), value] => {
+                let mut settings = self
+                    .shadow_map
+                    .as_ref()
+                    .map(|shadow_map| shadow_map.settings)
+                    .ok_or_else(|| String::from("There's no shadow map, pick a filter first"))?;
+                let value: f32 = value
+                    .parse()
+                    .map_err(|_| format!("{} isn't a number", value))?;
+                match setting {
+                    "resolution" => settings.resolution = value.max(1.0) as u32,
+                    "extent" => settings.extent = value.max(0.01),
+                    _ => settings.strength = value.clamp(0.0, 1.0),
+                }
+                self.set_shadows(Some(settings))?;
+                Ok(format!("Shadow map {} {}", setting, value))
+            }
+            ["shadows", filter, ..] if words.len() <= 3 => {
+                let filter = shadows::Filter::parse(filter, words.get(2).copied())?;
+                let settings = shadows::Settings {
+                    filter,
+                    ..self
+                        .shadow_map
+                        .as_ref()
+                        .map_or_else(shadows::Settings::default, |shadow_map| shadow_map.settings)
+                };
+                self.set_shadows(Some(settings))?;
+                Ok(format!("Shadow map {}", filter.describe()))
+            }
+            ["castshadows", draw] => {
+                let draw: usize = draw
+                    .parse()
+                    .map_err(|_| format!("{} isn't a draw number", draw))?;
+                let mut material = self.draw_material(draw)?;
+                material.casts_shadows = !material.casts_shadows;
+                self.set_draw_material(draw, material);
+                Ok(format!(
+                    "Draw {} {} shadows",
+                    draw,
+                    if material.casts_shadows {
+                        "casts"
+                    } else {
+                        "doesn't cast"
+                    }
+                ))
             }
-        };
-
-        match (present_result, self.frame_buffer_resized) {
-            (_, true) => {
-                // self.recreate_swapchain();
-                self.frame_buffer_resized = false;
+            ["scene", scene] => {
+                let scene: usize = scene
+                    .parse()
+                    .map_err(|_| format!("{} isn't a scene number", scene))?;
+                if scene >= self.scene_count() {
+                    return Err(format!("There are {} scenes", self.scene_count()));
+                }
+                self.switch_scene(scene);
+                Ok(format!("Switched to scene {}", scene))
             }
-            (Ok(_), _) => (),
-            // (Ok(false), _) | (Err(vk::Result::ERROR_OUT_OF_DATE_KHR), _) => {
-            //     self.recreate_swapchain();
-            //     return;
-            // }
-            (Err(_), _) => panic!("Failed to present swapchain image"),
+            _ => Err(format!("Unknown command \"{}\", try help", line.trim())),
         }
-
-        self.current_frame = (self.current_frame + 1) % MAX_FRAMES_IN_FLIGHT;
     }
 
-    fn update_uniform_buffer(&self, current_image: usize) {
-        let current_time = Instant::now();
-        let time = current_time - self.start_time;
-
-        let rot = Matrix4::from(Euler {
-            x: Deg(0f32),
-            y: Deg(0f32),
-            z: Deg(45f32) * time.as_secs_f32(),
-        });
-        let view = Matrix4::<f32>::look_at_rh(
-            Point3::new(2.0, 2.0, 2.0),
-            Point3::new(0.0, 0.0, 0.0),
-            Vector3::new(0.0, 0.0, 1.0),
-        );
-        let extent = self.swapchain_data.extent;
-        let aspect_ratio = extent.width as f32 / extent.height as f32;
-        let proj = cgmath::perspective(Deg(45.0), aspect_ratio, 0.1, 10.0);
-
-        // We put them in an array so we can get a raw pointer to this data.
-        let ubos = [UniformBufferObject {
-            model: rot,
-            view,
-            perspective: proj,
-        }];
-
-        let buffer_size = (std::mem::size_of::<UniformBufferObject>() * ubos.len()) as u64;
-
-        unsafe {
-            let data_ptr =
-                self.logical_device
-                    .map_memory(
-                        self.uniform_buffers_memory[current_image],
-                        0,
-                        buffer_size,
-                        vk::MemoryMapFlags::empty(),
-                    )
-                    .expect("Failed to Map Memory") as *mut UniformBufferObject;
-
-            data_ptr.copy_from_nonoverlapping(ubos.as_ptr(), ubos.len());
+    /// Turns a setting or post processing filter on or off from the console
+    fn toggle_setting(&mut self, name: &str) -> Result<String, String> {
+        let on = match name {
+            "fog" => {
+                let fog = self
+                    .features
+                    .get_mut::<fog::Fog>()
+                    .ok_or_else(|| String::from("Fog hasn't been added"))?;
+                fog.enabled = !fog.enabled;
+                fog.enabled
+            }
+            "flare" => {
+                let flare = self
+                    .features
+                    .get_mut::<flare::LensFlare>()
+                    .ok_or_else(|| String::from("The lens flare hasn't been added"))?;
+                flare.enabled = !flare.enabled;
+                flare.enabled
+            }
+            "grid" => {
+                self.set_grid_visible(!self.grid.visible);
+                self.grid.visible
+            }
+            "magnifier" => {
+                let magnifier = self
+                    .features
+                    .get_mut::<magnifier::Magnifier>()
+                    .ok_or_else(|| String::from("The magnifier hasn't been added"))?;
+                magnifier.enabled = !magnifier.enabled;
+                magnifier.enabled
+            }
+            "residency" => {
+                let overlay = self
+                    .features
+                    .get_mut::<residency::Overlay>()
+                    .ok_or_else(|| String::from("The residency overlay hasn't been added"))?;
+                overlay.visible = !overlay.visible;
+                overlay.visible
+            }
+            "baked" => {
+                self.set_baked_draws(self.baked_draws.is_none());
+                self.baked_draws.is_some()
+            }
+            "capsules" => {
+                self.capsule_shadows.enabled = !self.capsule_shadows.enabled;
+                self.capsule_shadows.enabled
+            }
+            _ => {
+                let filter = self
+                    .features
+                    .get_mut::<postprocess::PostProcess>()
+                    .and_then(|post_process| {
+                        post_process
+                            .filters_mut()
+                            .iter_mut()
+                            .find(|filter| filter.name == name)
+                    })
+                    .ok_or_else(|| format!("Nothing called {} to toggle", name))?;
+                filter.enabled = !filter.enabled;
+                filter.enabled
+            }
+        };
+        Ok(format!("{} {}", name, if on { "on" } else { "off" }))
+    }
 
-            self.logical_device
-                .unmap_memory(self.uniform_buffers_memory[current_image]);
+    /// Responds to input from the window's event thread.
+    fn handle_input(&mut self, input: render_thread::Input) {
+        if self.console_input(&input) {
+            return;
+        }
+        #[cfg(feature = "scripting")]
+        if self.script.is_some() {
+            self.script_input.handle(&input);
+        }
+        #[cfg(feature = "hot-reload")]
+        if let (Some((_, keys, _)), render_thread::Input::KeyPressed(key)) =
+            (self.logic.as_mut(), &input)
+        {
+            keys.push(*key);
+        }
+        match input {
+            render_thread::Input::CursorMoved(position) => self.cursor_moved(position),
+            render_thread::Input::CursorLeft => self.cursor_position = None,
+            render_thread::Input::Modifiers(modifiers) => self.modifiers = modifiers,
+            render_thread::Input::LeftMouseButton(pressed) => self.left_mouse_button(pressed),
+            render_thread::Input::MouseWheel(lines) => self.dolly.scroll(&self.camera, lines),
+            render_thread::Input::KeyPressed(key) => self.key_pressed(key),
+            render_thread::Input::Character(_) => {}
         }
     }
 
-    fn main_loop(mut self, event_loop: EventLoop<()>) {
-        event_loop.run(move |event, _, control_flow| {
-            *control_flow = ControlFlow::Poll;
-
-            match event {
-                Event::WindowEvent {
-                    event: WindowEvent::CloseRequested,
-                    ..
-                } => {
-                    println!("The close button was pressed; stopping");
-                    *control_flow = ControlFlow::Exit
-                }
-                Event::WindowEvent {
-                    event: WindowEvent::Resized(_),
-                    ..
-                } => self.frame_buffer_resized = true,
-                Event::MainEventsCleared => {
-                    // Application update code.
-                    // Queue a RedrawRequested event.
-                    //
-                    // You only need to call this if you've determined that you need to redraw, in
-                    // applications which do not always need to. Applications that redraw continuously
-                    // can just render here instead.
-
-                    self.window.request_redraw()
-                }
-                Event::RedrawRequested(_) => {
-                    // Redraw the application.
-                    //
-                    // It's preferable for applications that do not render continuously to render in
-                    // this event rather than in MainEventsCleared, since rendering in here allows
-                    // the program to gracefully handle redraws requested by the OS.
+    fn create_texture_image(
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        image_path: String,
+        budget: &mut memory::TextureBudget,
+    ) -> (vk::Image, vk::DeviceMemory) {
+        let (image, memory, _, _) = Self::load_texture_image(
+            device,
+            command_pool,
+            queue,
+            device_memory_properties,
+            image_path,
+            budget,
+        );
+        (image, memory)
+    }
 
-                    // NOTE: This function does nothing, however if we don't reference `self` in this loop,
-                    // Drop will never be called for our application.
-                    self.draw_frame();
-                }
-                _ => (),
-            }
-        });
+    /// The texture the scene's meshes are drawn with
+    #[cfg(feature = "images")]
+    fn scene_texture() -> images::Image {
+        images::load(Path::new(SCENE_TEXTURE)).unwrap_or_else(|e| panic!("{}", e))
     }
 
-    fn run(self, event_loop: EventLoop<()>) {
-        self.main_loop(event_loop);
+    /// Builds without the `images` feature can't decode the scene's JPEG texture, so they draw a checkerboard
+    #[cfg(not(feature = "images"))]
+    fn scene_texture() -> images::Image {
+        images::Image::checkerboard(512, 64, [[230, 230, 230, 255], [60, 60, 60, 255]])
     }
 
-    fn create_texture_image(
+    /// Creates a texture from the image at the path like `create_texture_image`, also returning the size it was
+    /// loaded at and the device memory it takes
+    fn load_texture_image(
         device: &ash::Device,
         command_pool: vk::CommandPool,
         queue: vk::Queue,
         device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
         image_path: String,
-    ) -> (vk::Image, vk::DeviceMemory) {
-        let mut image_object = image::open(image_path).unwrap(); // this function is slow in debug mode.
+        budget: &mut memory::TextureBudget,
+    ) -> (
+        vk::Image,
+        vk::DeviceMemory,
+        vk::Extent2D,
+        memory::Allocation,
+    ) {
+        // Decoding is slow in debug mode
+        let pixels = images::load(Path::new(&image_path)).unwrap_or_else(|e| panic!("{}", e));
+        Self::create_texture_from_pixels(
+            device,
+            command_pool,
+            queue,
+            device_memory_properties,
+            image_path,
+            pixels,
+            budget,
+        )
+    }
 
-        // Why flipv?
-        image_object = image_object.flipv();
+    /// Creates a texture holding data rather than colour from pixels in memory, with a UNORM format so that it
+    /// is sampled and filtered as it is stored. Unlike colour textures it isn't flipped, so its first row is at
+    /// the top, and it is left out of the texture budget.
+    fn create_data_texture(
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        pixels: &images::Image,
+    ) -> (vk::Image, vk::DeviceMemory, vk::ImageView) {
+        const FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+        let (image, memory) = Self::create_image(
+            device,
+            &ImageDescription {
+                width: pixels.width,
+                height: pixels.height,
+                format: FORMAT,
+                tiling: vk::ImageTiling::OPTIMAL,
+                usage: vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+                memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            },
+            device_memory_properties,
+        );
+        let size = pixels.pixels.len() as vk::DeviceSize;
+        let (staging_buffer, staging_mem) = Self::create_buffer(
+            device,
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            device_memory_properties,
+        );
+        unsafe {
+            let data = device
+                .map_memory(staging_mem, 0, size, MemoryMapFlags::empty())
+                .expect("Map memory for image staging buffer") as *mut u8;
+            data.copy_from_nonoverlapping(pixels.pixels.as_ptr(), pixels.pixels.len());
+            device.unmap_memory(staging_mem);
+        }
+        Self::transition_image_layout(
+            device,
+            queue,
+            command_pool,
+            image,
+            FORMAT,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        );
+        Self::copy_buffer_to_image(
+            device,
+            command_pool,
+            queue,
+            staging_buffer,
+            image,
+            pixels.width,
+            pixels.height,
+        );
+        Self::transition_image_layout(
+            device,
+            queue,
+            command_pool,
+            image,
+            FORMAT,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+        unsafe {
+            device.destroy_buffer(staging_buffer, None);
+            device.free_memory(staging_mem, None);
+        }
+        let view = Self::create_image_view(device, image, FORMAT, vk::ImageAspectFlags::COLOR);
+        (image, memory, view)
+    }
 
-        let (image_width, image_height) = (image_object.width(), image_object.height());
-        let image_size =
-            (std::mem::size_of::<u8>() as u32 * image_width * image_height * 4) as vk::DeviceSize;
-        let image_data = match &image_object {
-            image::DynamicImage::ImageLuma8(_) | image::DynamicImage::ImageRgb8(_) => {
-                image_object.to_rgba8().into_raw()
-            }
-            image::DynamicImage::ImageLumaA8(_) | image::DynamicImage::ImageRgba8(_) => {
-                image_object.to_rgba8().into_raw()
+    /// Creates a texture from pixels in memory like `load_texture_image`. `name` is the texture's name in the
+    /// budget's downgrades.
+    fn create_texture_from_pixels(
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        name: String,
+        mut pixels: images::Image,
+        budget: &mut memory::TextureBudget,
+    ) -> (
+        vk::Image,
+        vk::DeviceMemory,
+        vk::Extent2D,
+        memory::Allocation,
+    ) {
+        // Why flipv?
+        pixels.flip_vertical();
+
+        // Textures that don't fit are halved until they do, rather than failing to load
+        let source_size = (pixels.width, pixels.height);
+        let mut downgrade_reason = None;
+        let (image, image_memory, memory_size) = loop {
+            let (width, height) = (pixels.width, pixels.height);
+            let created = Self::try_create_image(
+                device,
+                &ImageDescription {
+                    width,
+                    height,
+                    format: vk::Format::R8G8B8A8_SRGB,
+                    tiling: vk::ImageTiling::OPTIMAL,
+                    usage: vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+                    memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                },
+                device_memory_properties,
+                |size| budget.fits(size),
+            );
+            match created {
+                Ok(created) => break created,
+                Err(reason)
+                    if width / 2 < memory::MIN_TEXTURE_SIZE
+                        || height / 2 < memory::MIN_TEXTURE_SIZE =>
+                {
+                    panic!("Loading texture {}: {}", name, reason)
+                }
+                Err(reason) => {
+                    pixels = pixels.halved();
+                    downgrade_reason = Some(reason);
+                }
             }
-            image_type => panic!("Unsupported image type: {:?}", image_type),
         };
+        // What the texture would have taken at the source's size, in proportion to the texels it has
+        let source_texels = source_size.0 as f64 * source_size.1 as f64;
+        let texels = pixels.width as f64 * pixels.height as f64;
+        let allocation = memory::Allocation {
+            size: memory_size,
+            requested: (memory_size as f64 * source_texels / texels) as vk::DeviceSize,
+        };
+        budget.allocate(allocation);
+        if let Some(reason) = downgrade_reason {
+            budget.downgrade(memory::Downgrade {
+                name,
+                from: source_size,
+                to: (pixels.width, pixels.height),
+                reason,
+            });
+        }
 
+        let (image_width, image_height) = (pixels.width, pixels.height);
+        let image_data = pixels.pixels;
+        let image_size = image_data.len() as vk::DeviceSize;
         if image_size <= 0 {
             panic!("Failed to load texture image!")
         }
@@ -2057,17 +8621,6 @@ impl HelloTriangleApplication {
             device.unmap_memory(staging_mem);
         }
 
-        let (image, image_memory) = Self::create_image(
-            device,
-            image_width,
-            image_height,
-            vk::Format::R8G8B8A8_SRGB,
-            vk::ImageTiling::OPTIMAL,
-            vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL,
-            device_memory_properties,
-        );
-
         Self::transition_image_layout(
             device,
             queue,
@@ -2102,19 +8655,40 @@ impl HelloTriangleApplication {
             device.free_memory(staging_mem, None);
         }
 
-        (image, image_memory)
+        let extent = vk::Extent2D {
+            width: image_width,
+            height: image_height,
+        };
+        (image, image_memory, extent, allocation)
     }
 
     fn create_image(
         device: &ash::Device,
-        width: u32,
-        height: u32,
-        format: vk::Format,
-        tiling: vk::ImageTiling,
-        usage: vk::ImageUsageFlags,
-        memory_properties: vk::MemoryPropertyFlags,
+        description: &ImageDescription,
         device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
     ) -> (vk::Image, vk::DeviceMemory) {
+        let (image, memory, _) =
+            Self::try_create_image(device, description, device_memory_properties, |_| true)
+                .unwrap_or_else(|e| panic!("Allocating image memory: {}", e));
+        (image, memory)
+    }
+
+    /// Creates an image like `create_image`, but fails instead when its memory runs out or `fits` rejects the
+    /// size of memory it needs, destroying the image again. Returns the size allocated along with the image.
+    fn try_create_image(
+        device: &ash::Device,
+        description: &ImageDescription,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        fits: impl FnOnce(vk::DeviceSize) -> bool,
+    ) -> Result<(vk::Image, vk::DeviceMemory, vk::DeviceSize), String> {
+        let &ImageDescription {
+            width,
+            height,
+            format,
+            tiling,
+            usage,
+            memory_properties,
+        } = description;
         let image_ci = vk::ImageCreateInfo::builder()
             .image_type(vk::ImageType::TYPE_2D)
             .extent(
@@ -2141,6 +8715,13 @@ impl HelloTriangleApplication {
         };
 
         let memory_requirements = unsafe { device.get_image_memory_requirements(image) };
+        if !fits(memory_requirements.size) {
+            unsafe { device.destroy_image(image, None) };
+            return Err(format!(
+                "its {} bytes would go over the texture budget",
+                memory_requirements.size
+            ));
+        }
 
         let image_ai = vk::MemoryAllocateInfo::builder()
             .allocation_size(memory_requirements.size)
@@ -2150,16 +8731,21 @@ impl HelloTriangleApplication {
                 device_memory_properties,
             ));
         let image_mem = unsafe {
-            let mem = device
-                .allocate_memory(&image_ai, None)
-                .expect("Allocating image memory");
+            let mem = match device.allocate_memory(&image_ai, None) {
+                Ok(mem) => mem,
+                Err(e) if memory::is_out_of_memory(e) => {
+                    device.destroy_image(image, None);
+                    return Err(format!("allocating its memory failed with {}", e));
+                }
+                Err(e) => panic!("Allocating image memory: {}", e),
+            };
             device
                 .bind_image_memory(image, mem, 0)
                 .expect("Binding image memory");
             mem
         };
 
-        (image, image_mem)
+        Ok((image, image_mem, memory_requirements.size))
     }
 
     fn transition_image_layout(
@@ -2317,8 +8903,341 @@ impl HelloTriangleApplication {
                 &[region.build()],
             );
         }
-
-        end_single_time_commands(device, command_pool, command_buffer, queue);
+
+        end_single_time_commands(device, command_pool, command_buffer, queue);
+    }
+
+    /// The lightmap's view, or the texture's when nothing is lightmapped so the descriptor is still valid.
+    fn lightmap_image_view(&self) -> vk::ImageView {
+        self.lightmap
+            .map_or(self.texture_image_view, |(_, _, view)| view)
+    }
+
+    /// The view of the toon ramp atlas toon shaded materials are lit through
+    fn toon_ramps_image_view(&self) -> vk::ImageView {
+        self.loaded_toon_ramps
+            .as_ref()
+            .map_or(self.toon_ramps.2, assets::Texture::view)
+    }
+
+    fn create_sdf_texture(
+        &self,
+        atlas: &sdf::Atlas,
+    ) -> (vk::Image, vk::DeviceMemory, vk::ImageView) {
+        Self::create_data_texture(
+            &self.logical_device,
+            self.command_pool,
+            self.graphics_queue,
+            &self.physical_device_memory_properties,
+            &atlas.image(),
+        )
+    }
+
+    /// Draws text with the signed distance field atlas in the image, whose distances reach `spread` texels
+    /// either side of the glyphs' edges, instead of the atlas made from the bitmap font. See `sdf::Atlas`.
+    pub fn load_sdf_atlas(&mut self, path: &Path, spread: f32) -> Result<(), String> {
+        let atlas = sdf::Atlas::from_image(&images::load(path)?, spread)?;
+        let texture = self.create_sdf_texture(&atlas);
+        present_thread::device_wait_idle(&self.logical_device)
+            .map_err(|e| format!("Waiting for the device to change the atlas: {}", e))?;
+        let device = &self.logical_device;
+        match self.features.get_mut::<sdf::Layer>() {
+            Some(layer) => {
+                layer.set_atlas(device, atlas, texture);
+                self.events.emit(events::Event::AssetLoaded {
+                    kind: events::AssetKind::Atlas,
+                    name: path.display().to_string(),
+                });
+                Ok(())
+            }
+            None => Err(String::from(
+                "The text and shapes feature hasn't been added",
+            )),
+        }
+    }
+
+    /// Draws text or a shape over the frame, over those added before it, and returns its index in the layer.
+    /// See `sdf::Layer`.
+    pub fn add_sdf_item(&mut self, item: sdf::Item) -> Result<usize, String> {
+        self.features
+            .get_mut::<sdf::Layer>()
+            .map(|layer| layer.add(item))
+            .ok_or_else(|| String::from("The text and shapes feature hasn't been added"))
+    }
+
+    /// Draws the SVG icon over the frame, fitted into the rectangle with its top left corner at `position` in
+    /// logical pixels, and returns its index in the layer. Needs the vector feature, see `vector::Graphic`.
+    pub fn add_svg(
+        &mut self,
+        path: &Path,
+        position: [f32; 2],
+        size: [f32; 2],
+    ) -> Result<usize, String> {
+        #[cfg(feature = "vector")]
+        {
+            let graphic = vector::Graphic::load(path)?;
+            self.add_sdf_item(sdf::Item::Vector {
+                graphic: std::sync::Arc::new(graphic),
+                position,
+                size,
+            })
+        }
+        #[cfg(not(feature = "vector"))]
+        {
+            let _ = (position, size);
+            Err(format!(
+                "{} can't be drawn without the vector feature",
+                path.display()
+            ))
+        }
+    }
+
+    /// The atlas of the built in `toon::RAMPS`, one to a row
+    fn toon_ramp_atlas() -> images::Image {
+        images::Image::from_fn(toon::RAMP_WIDTH, toon::RAMPS.len() as u32, |x, y| {
+            let [r, g, b] = toon::RAMPS[y as usize].texel(x);
+            let encode = |linear: f32| (util::encode_srgb(linear) * 255.0).round() as u8;
+            [encode(r), encode(g), encode(b), 255]
+        })
+    }
+
+    /// Lights toon shaded materials through the rows of the image instead of the built in `toon::RAMPS`. Each
+    /// row is a ramp from the top, lit from facing away from the light on the left to facing it on the right.
+    pub fn load_toon_ramps(&mut self, path: &Path) {
+        let ramps = self.load_texture(path);
+        self.loaded_toon_ramps = Some(ramps);
+        self.write_texture_descriptors();
+    }
+
+    /// Loads the shaders as a shader material called `name` and returns its index for `material::Material::shader`.
+    /// Without a vertex shader, vertices are transformed as for the scene's other meshes. See
+    /// `shader_materials::Library::load`.
+    pub fn register_shader_material(
+        &mut self,
+        name: &str,
+        vertex: Option<&Path>,
+        fragment: &Path,
+    ) -> Result<u16, String> {
+        let texture = self.scene_texture_view();
+        let index = self.shader_materials.load(
+            &self.logical_device,
+            &self.physical_device_memory_properties,
+            name,
+            vertex,
+            fragment,
+            self.descriptor_set_layout,
+            self.animation_set_layout,
+            texture,
+            self.texture_sampler,
+        )?;
+        self.pipelines
+            .set_custom_programs(self.shader_materials.programs());
+        self.events.emit(events::Event::AssetLoaded {
+            kind: events::AssetKind::ShaderMaterial,
+            name: String::from(name),
+        });
+        Ok(index)
+    }
+
+    /// Sets a parameter of a shader material. Sampler parameters are given the path of an image to load, and
+    /// anything else is given its numbers.
+    pub fn set_shader_parameter(
+        &mut self,
+        material: u16,
+        parameter: &str,
+        values: &[&str],
+    ) -> Result<(), String> {
+        if self.shader_materials.has_sampler(material, parameter) {
+            let path = match values {
+                [path] => Path::new(path),
+                _ => return Err(format!("{} takes the path of an image", parameter)),
+            };
+            let texture = self.load_texture(path);
+            self.shader_materials.set_texture(
+                &self.logical_device,
+                material,
+                parameter,
+                texture,
+                self.texture_sampler,
+            )?;
+            // The baked draws bind the parameters, and updating them invalidates the buffers
+            if let Some(baked_draws) = self.baked_draws.as_mut() {
+                baked_draws.invalidate();
+            }
+            return Ok(());
+        }
+        let numbers = values
+            .iter()
+            .map(|value| {
+                value
+                    .parse::<f32>()
+                    .map_err(|_| format!("{} isn't a number", value))
+            })
+            .collect::<Result<Vec<f32>, String>>()?;
+        self.shader_materials
+            .set_parameter(&self.logical_device, material, parameter, &numbers)
+    }
+
+    /// The view textured meshes sample, which is a noise texture once `use_noise_texture` has been called
+    fn scene_texture_view(&self) -> vk::ImageView {
+        self.scene_noise_texture
+            .map_or(self.texture_image_view, |index| {
+                self.noise_textures[index].view
+            })
+    }
+
+    /// Generates a noise texture on the GPU and returns its index for `noise_texture`. The texture lives as long
+    /// as the renderer.
+    pub fn generate_noise(&mut self, settings: &noise::Settings) -> Result<usize, String> {
+        let texture = noise::Texture::generate(
+            &self.logical_device,
+            self.command_pool,
+            self.graphics_queue,
+            &self.physical_device_memory_properties,
+            settings,
+        )?;
+        let index = self.noise_textures.len();
+        // Volume noise can't be shown as a flat thumbnail
+        if texture.extent.depth == 1 {
+            if let Err(e) = self.add_thumbnail(&format!("noise {}", index), texture.view) {
+                self.events.emit(events::Event::FeatureUnavailable {
+                    feature: format!("showing noise texture {}'s thumbnail", index),
+                    error: e,
+                });
+            }
+        }
+        self.noise_textures.push(texture);
+        Ok(index)
+    }
+
+    /// Makes an image available to show as a thumbnail, toggled with Ctrl and the number key after those of the
+    /// images added before it. See `thumbnails::Thumbnails::add_source`.
+    pub fn add_thumbnail(&mut self, name: &str, view: vk::ImageView) -> Result<(), String> {
+        match self.features.get_mut::<thumbnails::Thumbnails>() {
+            Some(thumbnails) => thumbnails
+                .add_source(&self.logical_device, name, view)
+                .map(|_| ()),
+            None => Err(String::from("Thumbnails aren't drawn")),
+        }
+    }
+
+    /// Makes a loaded texture available to show as a thumbnail like `add_thumbnail`, keeping it loaded for as
+    /// long as the thumbnails are drawn
+    pub fn add_texture_thumbnail(
+        &mut self,
+        name: &str,
+        texture: &assets::Texture,
+    ) -> Result<(), String> {
+        match self.features.get_mut::<thumbnails::Thumbnails>() {
+            Some(thumbnails) => thumbnails
+                .add_texture(&self.logical_device, name, texture)
+                .map(|_| ()),
+            None => Err(String::from("Thumbnails aren't drawn")),
+        }
+    }
+
+    /// Loads an image as a texture, shrunk to fit the texture budget like the scene's texture. It stays loaded
+    /// until every handle to it has been dropped.
+    pub fn load_texture(&mut self, path: &Path) -> assets::Texture {
+        let (image, memory, extent, allocation) = Self::load_texture_image(
+            &self.logical_device,
+            self.command_pool,
+            self.graphics_queue,
+            &self.physical_device_memory_properties,
+            path.to_string_lossy().into_owned(),
+            &mut self.texture_budget,
+        );
+        let view = Self::create_texture_image_view(&self.logical_device, image);
+        self.uploads.add(allocation.size);
+        self.events.emit(events::Event::AssetLoaded {
+            kind: events::AssetKind::Texture,
+            name: path.display().to_string(),
+        });
+        self.assets
+            .add_texture((image, memory, view), extent, allocation)
+    }
+
+    pub fn noise_texture(&self, index: usize) -> &noise::Texture {
+        &self.noise_textures[index]
+    }
+
+    /// Textures every textured mesh with a 2D noise texture from `generate_noise` instead of the scene's texture.
+    pub fn use_noise_texture(&mut self, index: usize) -> Result<(), String> {
+        if self.noise_texture(index).is_3d() {
+            return Err("Meshes can only be textured with 2D noise".to_string());
+        }
+        self.scene_noise_texture = Some(index);
+        self.write_texture_descriptors();
+        Ok(())
+    }
+
+    /// Points the descriptor sets meshes are drawn with at the scene's texture, lightmap, toon ramps, shadow map
+    /// and sampler again after one of them changes
+    fn write_texture_descriptors(&mut self) {
+        // Frames in flight may still be reading the descriptor sets
+        present_thread::device_wait_idle(&self.logical_device).expect(
+            "Waiting for the device to finish before changing the textures meshes are drawn with",
+        );
+        Self::populate_descriptor_sets(
+            &self.logical_device,
+            &self.descriptor_sets,
+            &self.uniform_buffers,
+            self.scene_texture_view(),
+            self.lightmap_image_view(),
+            self.toon_ramps_image_view(),
+            self.shadow_descriptors(),
+            self.texture_sampler,
+            self.swapchain_image_views.len(),
+        );
+        if let Some(stereo) = self.stereo.as_ref() {
+            Self::populate_descriptor_sets(
+                &self.logical_device,
+                &stereo.left.descriptor_sets,
+                &stereo.left.uniform_buffers,
+                self.scene_texture_view(),
+                self.lightmap_image_view(),
+                self.toon_ramps_image_view(),
+                self.shadow_descriptors(),
+                self.texture_sampler,
+                self.swapchain_image_views.len(),
+            );
+        }
+        let scheduled_views = self
+            .minimap
+            .iter()
+            .map(|minimap| &minimap.view)
+            .chain(self.probes.iter().map(|probe| &probe.view));
+        for view in scheduled_views {
+            Self::populate_descriptor_sets(
+                &self.logical_device,
+                &view.descriptor_sets,
+                &view.uniform_buffers,
+                self.scene_texture_view(),
+                self.lightmap_image_view(),
+                self.toon_ramps_image_view(),
+                self.shadow_descriptors(),
+                self.texture_sampler,
+                self.swapchain_image_views.len(),
+            );
+        }
+        // The shadow map can't sample itself while it's drawn into
+        if let Some(shadow_map) = self.shadow_map.as_ref() {
+            Self::populate_descriptor_sets(
+                &self.logical_device,
+                &shadow_map.view.descriptor_sets,
+                &shadow_map.view.uniform_buffers,
+                self.scene_texture_view(),
+                self.lightmap_image_view(),
+                self.toon_ramps_image_view(),
+                self.no_shadow_map(),
+                self.texture_sampler,
+                self.swapchain_image_views.len(),
+            );
+        }
+        // The baked draws bind the descriptor sets, and updating them invalidates the buffers
+        if let Some(baked_draws) = self.baked_draws.as_mut() {
+            baked_draws.invalidate();
+        }
     }
 
     fn create_texture_image_view(device: &ash::Device, image: vk::Image) -> vk::ImageView {
@@ -2330,18 +9249,17 @@ impl HelloTriangleApplication {
         )
     }
 
-    fn create_texture_sampler(
-        device: &ash::Device,
-        physical_device_properties: vk::PhysicalDeviceProperties,
-    ) -> vk::Sampler {
+    /// Filters anisotropically over at most `max_anisotropy` texels, which must be within the device's limit, or
+    /// not at all when it is 1
+    fn create_texture_sampler(device: &ash::Device, max_anisotropy: f32) -> vk::Sampler {
         let create_info = vk::SamplerCreateInfo::builder()
             .mag_filter(vk::Filter::LINEAR)
             .min_filter(vk::Filter::LINEAR)
             .address_mode_u(vk::SamplerAddressMode::REPEAT)
             .address_mode_v(vk::SamplerAddressMode::REPEAT)
             .address_mode_w(vk::SamplerAddressMode::REPEAT)
-            .anisotropy_enable(true)
-            .max_anisotropy(physical_device_properties.limits.max_sampler_anisotropy)
+            .anisotropy_enable(max_anisotropy > 1.0)
+            .max_anisotropy(max_anisotropy.max(1.0))
             .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
             .unnormalized_coordinates(false)
             .compare_enable(false)
@@ -2359,24 +9277,30 @@ impl HelloTriangleApplication {
     }
 
     fn create_depth_resources(
-        instance: &ash::Instance,
-        physical_device: vk::PhysicalDevice,
         physical_device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
         logical_device: &ash::Device,
         queue: vk::Queue,
         command_pool: vk::CommandPool,
         extent: vk::Extent2D,
+        format: vk::Format,
+        sampled: bool,
     ) -> (vk::Image, vk::DeviceMemory, vk::ImageView) {
-        let format = Self::find_depth_format(instance, physical_device, logical_device);
-
+        let sampled_usage = match sampled {
+            true => vk::ImageUsageFlags::SAMPLED,
+            false => vk::ImageUsageFlags::empty(),
+        };
         let (image, image_memory) = Self::create_image(
             logical_device,
-            extent.width,
-            extent.height,
-            format,
-            vk::ImageTiling::OPTIMAL,
-            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            &ImageDescription {
+                width: extent.width,
+                height: extent.height,
+                format,
+                tiling: vk::ImageTiling::OPTIMAL,
+                usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+                    | vk::ImageUsageFlags::TRANSFER_SRC
+                    | sampled_usage,
+                memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            },
             physical_device_memory_properties,
         );
 
@@ -2396,53 +9320,6 @@ impl HelloTriangleApplication {
         (image, image_memory, image_view)
     }
 
-    fn find_depth_format(
-        instance: &ash::Instance,
-        physical_device: vk::PhysicalDevice,
-        logical_device: &ash::Device,
-    ) -> vk::Format {
-        Self::find_supported_format(
-            instance,
-            physical_device,
-            logical_device,
-            vec![
-                vk::Format::D32_SFLOAT,
-                vk::Format::D32_SFLOAT_S8_UINT,
-                vk::Format::D24_UNORM_S8_UINT,
-            ]
-            .iter(),
-            vk::ImageTiling::OPTIMAL,
-            vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,
-        )
-        .expect("getting depth format")
-    }
-
-    fn find_supported_format(
-        instance: &ash::Instance,
-        physical_device: vk::PhysicalDevice,
-        logical_device: &ash::Device,
-        candidates: impl Iterator<Item = impl Deref<Target = vk::Format>>,
-        tiling: vk::ImageTiling,
-        features: vk::FormatFeatureFlags,
-    ) -> Option<vk::Format> {
-        for format in candidates {
-            let props = unsafe {
-                instance.get_physical_device_format_properties(physical_device, format.clone())
-            };
-            if tiling.eq(&vk::ImageTiling::LINEAR)
-                && props.linear_tiling_features.contains(features)
-            {
-                return Some(format.clone());
-            } else if tiling.eq(&vk::ImageTiling::OPTIMAL)
-                && props.optimal_tiling_features.contains(features)
-            {
-                return Some(format.clone());
-            }
-        }
-
-        None
-    }
-
     fn has_stencil_component(format: vk::Format) -> bool {
         format.eq(&vk::Format::D32_SFLOAT_S8_UINT) || format.eq(&vk::Format::D24_UNORM_S8_UINT)
     }
@@ -2488,6 +9365,7 @@ fn end_single_time_commands(
     let buffers = [command_buffer];
     let submit_infos = [vk::SubmitInfo::builder().command_buffers(&buffers).build()];
 
+    let _queues = present_thread::lock_queues();
     unsafe {
         device
             .queue_submit(queue, &submit_infos, vk::Fence::null())
@@ -2502,26 +9380,109 @@ fn end_single_time_commands(
 
 impl Drop for HelloTriangleApplication {
     fn drop(&mut self) {
+        // Before the swapchain the thread may still be presenting to is destroyed
+        self.present_thread = None;
         self.cleanup_swapchain();
 
         // This forces the debug config to be dropped
         self.debug_config = None;
 
         unsafe {
+            self.logical_device
+                .destroy_pipeline_cache(self.pipeline_cache, None);
             self.logical_device
                 .destroy_sampler(self.texture_sampler, None);
             self.logical_device
                 .destroy_image_view(self.texture_image_view, None);
             self.logical_device.destroy_image(self.image, None);
             self.logical_device.free_memory(self.image_memory, None);
+            if let Some((image, memory, view)) = self.lightmap.take() {
+                self.logical_device.destroy_image_view(view, None);
+                self.logical_device.destroy_image(image, None);
+                self.logical_device.free_memory(memory, None);
+            }
+            let (image, memory, view) = self.toon_ramps;
+            self.logical_device.destroy_image_view(view, None);
+            self.logical_device.destroy_image(image, None);
+            self.logical_device.free_memory(memory, None);
+            for texture in self.noise_textures.drain(..) {
+                texture.destroy(&self.logical_device);
+            }
             self.logical_device
                 .destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            self.logical_device
+                .destroy_descriptor_set_layout(self.animation_set_layout, None);
             self.logical_device.destroy_buffer(self.vertex_buffer, None);
             self.logical_device
                 .free_memory(self.vertex_buffer_memory, None);
             self.logical_device.destroy_buffer(self.index_buffer, None);
             self.logical_device
                 .free_memory(self.index_buffer_memory, None);
+            self.logical_device
+                .destroy_buffer(self.billboard_buffer, None);
+            self.logical_device
+                .free_memory(self.billboard_buffer_memory, None);
+            self.logical_device.destroy_buffer(self.axis_buffer, None);
+            self.logical_device
+                .free_memory(self.axis_buffer_memory, None);
+            self.logical_device.destroy_buffer(self.disc_buffer, None);
+            self.logical_device
+                .free_memory(self.disc_buffer_memory, None);
+            self.trail_ghosts.destroy(&self.logical_device);
+            self.shader_materials.destroy(&self.logical_device);
+            self.logical_device
+                .destroy_buffer(self.floor_vertex_buffer, None);
+            self.logical_device
+                .free_memory(self.floor_vertex_buffer_memory, None);
+            self.logical_device
+                .destroy_buffer(self.floor_index_buffer, None);
+            self.logical_device
+                .free_memory(self.floor_index_buffer_memory, None);
+            #[cfg(feature = "physics")]
+            self.physics.destroy(&self.logical_device);
+            self.grid.destroy(&self.logical_device);
+            self.measurement.destroy(&self.logical_device);
+            for &(buffer, memory) in self
+                .static_buffers
+                .iter()
+                .chain(self.point_cloud_buffers.iter())
+            {
+                self.logical_device.destroy_buffer(buffer, None);
+                self.logical_device.free_memory(memory, None);
+            }
+            if let Some(cloth) = self.cloth.as_mut() {
+                cloth.destroy(&self.logical_device);
+            }
+            if let Some(swarm) = self.swarm.as_mut() {
+                swarm.destroy(&self.logical_device);
+            }
+            for animated in self.animations.drain(..) {
+                animated.destroy(&self.logical_device);
+            }
+            self.particles.destroy(&self.logical_device);
+            if let Some(acquire) = self.present_acquire.as_ref() {
+                acquire.destroy(&self.logical_device);
+            }
+            if let Some(scene) = self.indirect_scene.as_mut() {
+                scene.destroy(&self.logical_device);
+            }
+            if let Some(statistics) = self.statistics.as_mut() {
+                statistics.destroy(&self.logical_device);
+            }
+            if let Some(monitor) = self.frame_budget.as_mut() {
+                monitor.destroy(&self.logical_device);
+            }
+            if let Some(playground) = self.playground.as_mut() {
+                playground.destroy(&self.logical_device);
+            }
+            self.features.destroy(&self.logical_device);
+            self.world = None;
+            self.assets.retire_all(&mut self.deletion_queue);
+            self.deletion_queue.destroy(&self.logical_device);
+            self.logical_device
+                .destroy_buffer(self.default_attribute_buffer, None);
+            self.logical_device
+                .free_memory(self.default_attribute_buffer_memory, None);
 
             for &semaphore in self.image_available_semaphores.iter() {
                 self.logical_device.destroy_semaphore(semaphore, None);
@@ -2529,6 +9490,7 @@ impl Drop for HelloTriangleApplication {
             for &semaphore in self.render_complete_semaphores.iter() {
                 self.logical_device.destroy_semaphore(semaphore, None);
             }
+            self.submission_semaphores.destroy(&self.logical_device);
 
             for &fence in self.frame_fences.iter() {
                 self.logical_device.destroy_fence(fence, None);
@@ -2538,16 +9500,34 @@ impl Drop for HelloTriangleApplication {
                 .destroy_command_pool(self.command_pool, None);
 
             self.surface_loader.destroy_surface(self.surface, None);
-            self.logical_device.destroy_device(None);
-            self.instance.destroy_instance(None);
+            let host_allocator = self.host_allocator();
+            self.logical_device.destroy_device(host_allocator.as_ref());
+            self.instance.destroy_instance(host_allocator.as_ref());
         }
     }
 }
 
+/// Exits with a usage error rather than panicking when a command line argument is missing or malformed
+trait OrUsage<T> {
+    fn or_usage(self, message: &str) -> T;
+}
+
+impl<T> OrUsage<T> for Option<T> {
+    fn or_usage(self, message: &str) -> T {
+        self.unwrap_or_else(|| usage_error(message))
+    }
+}
+
+/// Prints what's wrong with the command line and exits with a non-zero status
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(2);
+}
+
 fn main() {
     let debug_layers = true;
 
-    let event_loop = EventLoop::new();
+    let event_loop = EventLoop::with_user_event();
 
     let debug_config = if debug_layers {
         let mut severities = vk::DebugUtilsMessageSeverityFlagsEXT::all();
@@ -2559,6 +9539,1131 @@ fn main() {
     } else {
         None
     };
-    let app = HelloTriangleApplication::initialize(&event_loop, debug_config);
-    app.run(event_loop);
+    // `--filter <compute.spv>` adds a post processing filter, and may be repeated.
+    // `--gpu-driven <size>` adds a size by size grid of objects drawn from a draw stream built on the GPU.
+    // `--hi-z` also culls those objects against a depth pyramid of the previous frame.
+    // `--cloth` hangs a square of cloth simulated in compute shaders in the first scene.
+    // `--swarm <flocking|gravity> <count>` adds a scene of agents moved by a compute shader and drawn instanced.
+    // `--vat-example <path.exr>` adds a flag waving in the wind, played back from a vertex animation texture. The
+    // animation is loaded from the path, or baked to it if it doesn't exist yet.
+    // `--noise <perlin|simplex|worley>` textures the meshes with noise generated on the GPU.
+    // `--texture-budget <MiB>` loads textures at lower resolutions when they would take more device memory than
+    // that between them.
+    // `--track-host-memory` allocates the Vulkan implementation's host memory through the renderer's own callbacks
+    // and shows how much it uses in the residency overlay.
+    // `--surface-format <format>`, `--color-space <space>` and `--present-mode <mode>` create the swapchain with
+    // those instead of the renderer's choices when the surface supports them, see `surface::Overrides`.
+    // `--software-occlusion` culls draws hidden behind the largest draws with a depth buffer drawn on the CPU.
+    // `--baked` keeps the scene's draws recorded between frames, recording them again only when they change.
+    // `--portals <path>` culls indoor cells the camera can't see through their portals, see `portals::Portals`.
+    // `--world <path> <load radius>` streams a world of boxes in around the camera's target, moved with W, A, S
+    // and D. A city is generated and written to the path if it doesn't exist.
+    // `--thumbnail <path.png>` loads an image to show as a thumbnail, and may be repeated.
+    // `--ui-atlas` draws thumbnails into a texture atlas once when shown and copies them from there each frame.
+    // `--stereo <anaglyph|side-by-side> <separation>` draws the scene for two eyes that far apart in world units.
+    // `--camera-path <path>` records the camera's path to the file with R, and plays it back with Shift+R.
+    // `--play-camera` plays the camera path back as soon as the renderer starts, for benchmarks and demos.
+    // `--timeline <path>` plays a timeline of camera keys, draw transforms, setting toggles and scene switches back
+    // deterministically once everything else has been set up, see `timeline::Timeline`.
+    // `--script <path.rhai>` runs a Rhai script each frame to move draws, change materials and the fog and switch
+    // scenes, recompiling it when the file changes. Needs the scripting feature, see `scripting::Script`.
+    // `--logic <library>` runs frame update logic from a dynamic library, reloading it whenever it is rebuilt.
+    // Needs the hot-reload feature, see `hot_reload::Logic` and `examples/spin_logic.rs`.
+    // `--gamepad-bindings <path>` rebinds the gamepad's sticks, triggers and buttons. Needs the gamepad feature,
+    // see `gamepad::Bindings`.
+    // `--stream <address> <raw|jpeg>` streams every frame over TCP to a client connecting to the address, such as
+    // 127.0.0.1:7878, and takes mouse and keyboard input from it, see `remote::Remote`. Clients aren't
+    // authenticated, so only listen on other interfaces on a trusted network.
+    // `--magnifier` shows the frame around the cursor magnified in a corner, toggled with L and zoomed with Shift+L.
+    // `--fail-on-validation <frames>` draws that many frames and exits, printing the validation warnings and errors
+    // that were reported and failing if there were any, see `validation::Report`.
+    // `--residency` shows texture memory, queued world cells and upload bandwidth, toggled with `toggle residency`.
+    // `--lightmap <path.png>` lightmaps the static meshes, baking the lightmap to the path if it doesn't exist.
+    // `--toon-ramps <path.png>` lights toon shaded materials through the image's rows instead of the built in ramps.
+    // `--sdf-atlas <path.png> <spread>` draws text with a signed distance field atlas whose distances reach that
+    // many texels either side of its glyphs' edges, see `sdf::Atlas`.
+    // `--svg <path.svg> <x> <y> <size>` draws an SVG icon over the frame, `size` logical pixels across, and may be
+    // repeated. Needs the vector feature, see `vector::Graphic`.
+    // `--minimap <extent>` draws a top-down map of the scene that many world units across around the camera's
+    // target into the top right corner of the frame, see `minimap::Minimap`.
+    // `--probe <x> <y> <z>` adds a reflection probe drawing the scene around the point into a cubemap a face at a
+    // time, and may be repeated, see `probes::Probe`.
+    // `--schedule-budget <renders>` sets the most minimap and probe renders drawn in a frame, see
+    // `schedule::Scheduler`.
+    // `--shader-material <name> <vertex|default> <fragment>` loads GLSL or SPIR-V shaders as a material for the
+    // `shade` console command, and may be repeated, see `shader_materials::Library`.
+    // `--fog <density> <anisotropy>` fills the scene with volumetric fog lit by the light, toggled with F.
+    // `--lens-flare <intensity>` draws a lens flare for the light when it is in view, toggled with `toggle flare`.
+    // `--god-rays <strength>` streaks the light out through gaps in the scene in post processing.
+    // `--quality <low|medium|high|ultra>` applies a quality preset once everything else has been added, instead of
+    // the one guessed from the device.
+    // `--volume <path.nrrd> <grayscale|fire|bone>` ray marches a volume with a transfer function preset.
+    // `--raw-volume <path> <width>x<height>x<depth> <u8|u16|f32|...> <grayscale|fire|bone>` does the same for a
+    // headerless volume of little-endian samples.
+    // `--point-cloud <path.ply|path.las> <sprites|surfels>` adds a scene of a point cloud.
+    // `--orthographic` starts with an orthographic projection, toggled with O.
+    // `--infinite-far` moves the far plane to infinity, for open worlds with no furthest distance to draw.
+    // `--up <y|z>` sets the world's up axis, which the camera, the grid and imported assets follow, and
+    // `--unit <metres>` the length of its unit, so that the grid's spacing and measurements are in metres.
+    // `--import-up <y|z>` overrides the up axis the point cloud or volume is taken to have, see `units::Up`.
+    // `--view <front|top|right|isometric>` starts looking from one of the standard views, cycled with C.
+    // `--grid` shows a grid and axis gizmo that scale with the view, toggled with G.
+    // `--clip <x> <y> <z> <distance>` cuts away the meshes where the dot product of their position and the normal
+    // is less than the distance, and may be repeated. `--no-clip-caps` leaves the cut open.
+    // `--scene-file <path>` restores the scene from the file if it exists, and saves it there on exit and with
+    // Ctrl+S. Only load files saved with the same arguments.
+    // `--pipeline-statistics` reports the vertices, primitives and shader invocations of each pass with the frame
+    // rate.
+    // `--frame-budget <milliseconds>` alerts when the CPU or the GPU takes longer than that over a frame.
+    // `--accumulate <samples>` averages up to that many jittered frames of a still scene into a super-sampled
+    // image, see `accumulation::Accumulation`.
+    // `--transition <fade|crossfade|wipe> <seconds>` sets how switching scenes with N transitions.
+    // `--shadertoy <sketch> [channel images...]` runs a Shadertoy style sketch instead of the demo scene, and
+    // takes the rest of the arguments.
+    let mut args = std::env::args().skip(1);
+    let mut sketch = None;
+    let mut filters = Vec::new();
+    let mut hook_example = false;
+    let mut events_example = false;
+    let mut clear_color = None;
+    let mut accumulate = None;
+    let mut immediate_example = false;
+    let mut vat_example = None;
+    let mut cloth = false;
+    let mut swarm = None;
+    let mut object_grid = None;
+    let mut transition = None;
+    let mut lightmap = None;
+    let mut toon_ramps = None;
+    let mut shader_materials = Vec::new();
+    let mut sdf_atlas = None;
+    let mut svgs = Vec::new();
+    let mut minimap = None;
+    let mut shadow_map = None;
+    let mut probe_positions = Vec::new();
+    let mut schedule_budget = None;
+    let mut texture_budget = None;
+    let mut track_host_memory = false;
+    let mut surface_overrides = surface::Overrides::default();
+    let mut fail_on_validation = None;
+    let mut thumbnails = Vec::new();
+    let mut world = None;
+    let mut portals = None;
+    let mut software_occlusion = false;
+    let mut hi_z = false;
+    let mut baked = false;
+    let mut ui_atlas = false;
+    let mut magnifier = false;
+    let mut residency = false;
+    let mut camera_path = None;
+    let mut play_camera = false;
+    let mut timeline = None;
+    let mut time_of_day: Option<sky::TimeOfDay> = None;
+    let mut script = None;
+    let mut logic = None;
+    let mut gamepad_bindings = None;
+    let mut stream = None;
+    let mut stereo = None;
+    let mut noise = None;
+    let mut fog = None;
+    let mut lens_flare = None;
+    let mut god_rays = None;
+    let mut quality = None;
+    let mut volume = None;
+    let mut point_cloud = None;
+    let mut orthographic = false;
+    let mut infinite_far = false;
+    let mut present_thread = false;
+    let mut units = units::Convention::default();
+    let mut import_up = None;
+    let mut view_preset = None;
+    let mut grid = false;
+    let mut pipeline_statistics = false;
+    let mut frame_budget = None;
+    let mut clip_planes = Vec::new();
+    let mut clip_caps = true;
+    let mut scene_file = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--hook-example" => hook_example = true,
+            "--events-example" => events_example = true,
+            "--immediate-example" => immediate_example = true,
+            "--cloth" => cloth = true,
+            "--orthographic" => orthographic = true,
+            "--infinite-far" => infinite_far = true,
+            "--present-thread" => present_thread = true,
+            "--up" => {
+                units.up = args
+                    .next()
+                    .and_then(|name| units::Up::parse(&name))
+                    .or_usage("--up needs y or z");
+            }
+            "--unit" => {
+                units.unit = args
+                    .next()
+                    .and_then(|metres| metres.parse().ok())
+                    .filter(|&metres: &f32| metres > 0.0)
+                    .or_usage("--unit needs a length in metres");
+            }
+            "--import-up" => {
+                import_up = Some(
+                    args.next()
+                        .and_then(|name| units::Up::parse(&name))
+                        .or_usage("--import-up needs y or z"),
+                );
+            }
+            "--grid" => grid = true,
+            "--pipeline-statistics" => pipeline_statistics = true,
+            "--software-occlusion" => software_occlusion = true,
+            "--hi-z" => hi_z = true,
+            "--baked" => baked = true,
+            "--ui-atlas" => ui_atlas = true,
+            "--magnifier" => magnifier = true,
+            "--residency" => residency = true,
+            "--camera-path" => {
+                camera_path = Some(PathBuf::from(
+                    args.next()
+                        .or_usage("--camera-path needs a camera path file"),
+                ))
+            }
+            "--play-camera" => play_camera = true,
+            "--stream" => {
+                let address = args
+                    .next()
+                    .or_usage("--stream needs an address to listen on");
+                let encoding = args
+                    .next()
+                    .and_then(|encoding| remote::Encoding::parse(&encoding))
+                    .or_usage("--stream needs raw or jpeg");
+                stream = Some((address, encoding));
+            }
+            "--logic" => {
+                logic = Some(PathBuf::from(
+                    args.next().or_usage("--logic needs a dynamic library"),
+                ))
+            }
+            "--gamepad-bindings" => {
+                gamepad_bindings = Some(PathBuf::from(
+                    args.next()
+                        .or_usage("--gamepad-bindings needs a bindings file"),
+                ))
+            }
+            "--script" => {
+                script = Some(PathBuf::from(
+                    args.next().or_usage("--script needs a script file"),
+                ))
+            }
+            "--time-of-day" => {
+                let hour: f32 = args
+                    .next()
+                    .and_then(|hour| hour.parse().ok())
+                    .filter(|hour| (0.0..24.0).contains(hour))
+                    .or_usage("--time-of-day needs an hour from 0 to 24");
+                let speed: f32 = args
+                    .next()
+                    .and_then(|speed| speed.parse().ok())
+                    .or_usage("--time-of-day needs the hours that pass each second");
+                time_of_day = Some(sky::TimeOfDay {
+                    hour,
+                    speed,
+                    ..time_of_day.unwrap_or_default()
+                });
+            }
+            "--latitude" => {
+                let latitude: f32 = args
+                    .next()
+                    .and_then(|latitude| latitude.parse().ok())
+                    .filter(|latitude| (-90.0..=90.0).contains(latitude))
+                    .or_usage("--latitude needs degrees from -90 to 90");
+                time_of_day = Some(sky::TimeOfDay {
+                    latitude: Deg(latitude),
+                    ..time_of_day.unwrap_or_default()
+                });
+            }
+            "--timeline" => {
+                timeline = Some(PathBuf::from(
+                    args.next().or_usage("--timeline needs a timeline file"),
+                ))
+            }
+            "--stereo" => {
+                let mode = args
+                    .next()
+                    .and_then(|mode| stereo::Mode::parse(&mode))
+                    .or_usage("--stereo needs anaglyph or side-by-side");
+                let separation: f32 = args
+                    .next()
+                    .and_then(|separation| separation.parse().ok())
+                    .or_usage("--stereo needs an eye separation");
+                stereo = Some(stereo::Settings { mode, separation });
+            }
+            "--no-clip-caps" => clip_caps = false,
+            "--scene-file" => {
+                scene_file = Some(PathBuf::from(
+                    args.next().or_usage("--scene-file needs a path"),
+                ))
+            }
+            "--clip" => {
+                let mut values = [0.0; 4];
+                for value in values.iter_mut() {
+                    *value = args
+                        .next()
+                        .and_then(|value| value.parse().ok())
+                        .or_usage("--clip needs a normal and a distance");
+                }
+                let [x, y, z, distance] = values;
+                clip_planes.push(clipping::Plane {
+                    normal: Vector3::new(x, y, z).normalize(),
+                    distance,
+                });
+            }
+            "--view" => {
+                view_preset = Some(
+                    args.next()
+                        .and_then(|preset| camera::Preset::parse(&preset))
+                        .or_usage("--view needs front, top, right or isometric"),
+                )
+            }
+            "--swarm" => {
+                let behaviour = args
+                    .next()
+                    .and_then(|behaviour| swarm::Behaviour::parse(&behaviour))
+                    .or_usage("--swarm needs flocking or gravity");
+                let count: u32 = args
+                    .next()
+                    .and_then(|count| count.parse().ok())
+                    .or_usage("--swarm needs an agent count");
+                swarm = Some((behaviour, count));
+            }
+            "--vat-example" => {
+                vat_example = Some(PathBuf::from(
+                    args.next().or_usage("--vat-example needs an EXR path"),
+                ))
+            }
+            "--gpu-driven" => {
+                object_grid = Some(
+                    args.next()
+                        .and_then(|size| size.parse().ok())
+                        .or_usage("--gpu-driven needs a grid size"),
+                )
+            }
+            "--clear-color" => {
+                let mut color = [0.0f32; 3];
+                for channel in color.iter_mut() {
+                    *channel = args
+                        .next()
+                        .and_then(|channel| channel.parse().ok())
+                        .or_usage("--clear-color needs a red, green and blue");
+                }
+                clear_color = Some(color);
+            }
+            "--accumulate" => {
+                accumulate = Some(
+                    args.next()
+                        .and_then(|samples| samples.parse::<u32>().ok())
+                        .or_usage("--accumulate needs a number of frames"),
+                )
+            }
+            "--frame-budget" => {
+                let milliseconds: f32 = args
+                    .next()
+                    .and_then(|milliseconds| milliseconds.parse().ok())
+                    .or_usage("--frame-budget needs a time in milliseconds");
+                frame_budget = Some(budget::Budget::both(Duration::from_secs_f32(
+                    milliseconds / 1000.0,
+                )));
+            }
+            "--transition" => {
+                let effect = args
+                    .next()
+                    .and_then(|effect| transition::Effect::parse(&effect))
+                    .or_usage("--transition needs fade, crossfade or wipe");
+                let seconds: f32 = args
+                    .next()
+                    .and_then(|seconds| seconds.parse().ok())
+                    .or_usage("--transition needs a duration in seconds");
+                transition = Some((effect, Duration::from_secs_f32(seconds)));
+            }
+            "--lens-flare" => {
+                lens_flare = Some(
+                    args.next()
+                        .and_then(|intensity| intensity.parse().ok())
+                        .or_usage("--lens-flare needs an intensity"),
+                );
+            }
+            "--god-rays" => {
+                god_rays = Some(
+                    args.next()
+                        .and_then(|strength| strength.parse().ok())
+                        .or_usage("--god-rays needs a strength"),
+                );
+            }
+            "--quality" => {
+                quality = Some(
+                    args.next()
+                        .and_then(|name| quality::Preset::parse(&name))
+                        .or_usage("--quality needs one of low, medium, high or ultra"),
+                );
+            }
+            "--fog" => {
+                let density: f32 = args
+                    .next()
+                    .and_then(|density| density.parse().ok())
+                    .or_usage("--fog needs a density");
+                let anisotropy: f32 = args
+                    .next()
+                    .and_then(|anisotropy| anisotropy.parse().ok())
+                    .or_usage("--fog needs an anisotropy from -1 to 1");
+                fog = Some(fog::Settings {
+                    density,
+                    anisotropy,
+                    ..fog::Settings::default()
+                });
+            }
+            "--volume" => {
+                let path = PathBuf::from(args.next().or_usage("--volume needs a NRRD path"));
+                let transfer_function = args
+                    .next()
+                    .and_then(|name| volume::TransferFunction::preset(&name))
+                    .or_usage("--volume needs grayscale, fire or bone");
+                volume = Some((volume::Dataset::load_nrrd(&path), transfer_function, path));
+            }
+            "--raw-volume" => {
+                let path = PathBuf::from(args.next().or_usage("--raw-volume needs a path"));
+                let size: Vec<u32> = args
+                    .next()
+                    .map(|size| size.split('x').filter_map(|n| n.parse().ok()).collect())
+                    .filter(|size: &Vec<u32>| size.len() == 3)
+                    .or_usage("--raw-volume needs a size like 256x256x128");
+                let sample_type = args
+                    .next()
+                    .and_then(|name| volume::SampleType::parse(&name))
+                    .or_usage("--raw-volume needs a sample type such as u8, u16 or f32");
+                let transfer_function = args
+                    .next()
+                    .and_then(|name| volume::TransferFunction::preset(&name))
+                    .or_usage("--raw-volume needs grayscale, fire or bone");
+                volume = Some((
+                    volume::Dataset::load_raw(&path, [size[0], size[1], size[2]], sample_type),
+                    transfer_function,
+                    path,
+                ));
+            }
+            "--point-cloud" => {
+                let path = PathBuf::from(
+                    args.next()
+                        .or_usage("--point-cloud needs a PLY or LAS path"),
+                );
+                let mode = args
+                    .next()
+                    .or_usage("--point-cloud needs sprites or surfels");
+                point_cloud = Some((pointcloud::PointCloud::load(&path), mode, path));
+            }
+            "--noise" => {
+                noise = Some(
+                    args.next()
+                        .and_then(|kind| noise::Kind::parse(&kind))
+                        .or_usage("--noise needs perlin, simplex or worley"),
+                )
+            }
+            "--toon-ramps" => {
+                toon_ramps = Some(PathBuf::from(
+                    args.next().or_usage("--toon-ramps needs a PNG path"),
+                ))
+            }
+            "--shader-material" => {
+                let mut next = || {
+                    args.next()
+                        .or_usage("--shader-material needs a name and two shader paths")
+                };
+                let name = next();
+                let vertex = next();
+                let fragment = PathBuf::from(next());
+                let vertex = match vertex.as_str() {
+                    "default" => None,
+                    _ => Some(PathBuf::from(vertex)),
+                };
+                shader_materials.push((name, vertex, fragment));
+            }
+            "--sdf-atlas" => {
+                let path = PathBuf::from(args.next().or_usage("--sdf-atlas needs a PNG path"));
+                let spread = args
+                    .next()
+                    .and_then(|spread| spread.parse::<f32>().ok())
+                    .or_usage("--sdf-atlas needs the texels its distances spread over");
+                sdf_atlas = Some((path, spread));
+            }
+            "--svg" => {
+                let path = PathBuf::from(args.next().or_usage("--svg needs an SVG path"));
+                let mut number = || {
+                    args.next()
+                        .and_then(|number| number.parse::<f32>().ok())
+                        .or_usage("--svg needs the x, y and size of the icon")
+                };
+                let (x, y, size) = (number(), number(), number());
+                svgs.push((path, [x, y], size));
+            }
+            "--probe" => {
+                let mut number = || {
+                    args.next()
+                        .and_then(|number| number.parse::<f32>().ok())
+                        .or_usage("--probe needs the x, y and z of the probe")
+                };
+                probe_positions.push(Point3::new(number(), number(), number()));
+            }
+            "--schedule-budget" => {
+                schedule_budget = Some(
+                    args.next()
+                        .and_then(|budget| budget.parse::<usize>().ok())
+                        .or_usage("--schedule-budget needs a number of renders"),
+                )
+            }
+            "--minimap" => {
+                let extent = args
+                    .next()
+                    .and_then(|extent| extent.parse::<f32>().ok())
+                    .filter(|&extent| extent > 0.0)
+                    .or_usage("--minimap needs how many world units across the map shows");
+                minimap = Some(minimap::Settings {
+                    extent,
+                    ..minimap::Settings::default()
+                });
+            }
+            "--shadows" => {
+                let filter = args.next().or_usage("--shadows needs a filter");
+                shadow_map = Some(shadows::Settings {
+                    filter: shadows::Filter::parse(&filter, None)
+                        .unwrap_or_else(|e| usage_error(&e)),
+                    ..shadows::Settings::default()
+                });
+            }
+            "--lightmap" => {
+                lightmap = Some(PathBuf::from(
+                    args.next().or_usage("--lightmap needs a PNG path"),
+                ))
+            }
+            "--track-host-memory" => track_host_memory = true,
+            "--surface-format" => {
+                surface_overrides.format = Some(
+                    args.next()
+                        .or_usage("--surface-format needs a format, like B8G8R8A8_UNORM"),
+                )
+            }
+            "--color-space" => {
+                surface_overrides.color_space = Some(
+                    args.next()
+                        .or_usage("--color-space needs a colour space, like SRGB_NONLINEAR"),
+                )
+            }
+            "--present-mode" => {
+                surface_overrides.present_mode = Some(args.next().or_usage(
+                    "--present-mode needs a present mode, like IMMEDIATE, MAILBOX or FIFO",
+                ))
+            }
+            "--fail-on-validation" => {
+                fail_on_validation = Some(
+                    args.next()
+                        .and_then(|frames| frames.parse::<u32>().ok())
+                        .filter(|&frames| frames > 0)
+                        .or_usage("--fail-on-validation needs a number of frames to draw"),
+                );
+            }
+            "--texture-budget" => {
+                let mebibytes: vk::DeviceSize = args
+                    .next()
+                    .and_then(|mebibytes| mebibytes.parse().ok())
+                    .or_usage("--texture-budget needs a size in MiB");
+                texture_budget = Some(mebibytes << 20);
+            }
+            "--portals" => {
+                portals = Some(PathBuf::from(
+                    args.next().or_usage("--portals needs a portal file path"),
+                ))
+            }
+            "--world" => {
+                let path = PathBuf::from(args.next().or_usage("--world needs a path"));
+                let load_radius: f32 = args
+                    .next()
+                    .and_then(|radius| radius.parse().ok())
+                    .or_usage("--world needs a load radius");
+                world = Some((path, load_radius));
+            }
+            "--thumbnail" => thumbnails.push(PathBuf::from(
+                args.next().or_usage("--thumbnail needs an image path"),
+            )),
+            "--filter" => filters.push(PathBuf::from(
+                args.next().or_usage("--filter needs a SPIR-V path"),
+            )),
+            "--shadertoy" => {
+                sketch = Some(playground::Sketch {
+                    source: args
+                        .next()
+                        .or_usage("--shadertoy needs a sketch path")
+                        .into(),
+                    channels: args.by_ref().collect(),
+                })
+            }
+            _ => usage_error(&format!("Unrecognised argument {}", arg)),
+        }
+    }
+
+    // The window has to be created on the event thread, the renderer is created on its own thread
+    let window = HelloTriangleApplication::init_window(&event_loop);
+    let create = move || {
+        let mut app = HelloTriangleApplication::initialize(
+            window,
+            debug_config,
+            sketch,
+            filters,
+            lightmap,
+            texture_budget,
+            track_host_memory,
+            surface_overrides,
+        );
+        app.set_units(units);
+        if let Some(path) = toon_ramps {
+            app.load_toon_ramps(&path);
+        }
+        if let Some((path, spread)) = sdf_atlas {
+            if let Err(e) = app.load_sdf_atlas(&path, spread) {
+                app.events.emit(events::Event::LoadFailed {
+                    kind: events::AssetKind::Atlas,
+                    name: path.display().to_string(),
+                    error: e,
+                });
+            }
+        }
+        for (path, position, size) in svgs {
+            if let Err(e) = app.add_svg(&path, position, [size; 2]) {
+                app.events.emit(events::Event::LoadFailed {
+                    kind: events::AssetKind::Svg,
+                    name: path.display().to_string(),
+                    error: e,
+                });
+            }
+        }
+        if let Some(budget) = schedule_budget {
+            app.set_schedule_budget(budget);
+        }
+        for position in probe_positions {
+            app.add_probe(position, probes::DEFAULT_SIZE, probes::DEFAULT_INTERVAL);
+        }
+        if minimap.is_some() {
+            if let Err(e) = app.set_minimap(minimap) {
+                app.events.emit(events::Event::FeatureUnavailable {
+                    feature: String::from("drawing the minimap"),
+                    error: e,
+                });
+            }
+        }
+        if shadow_map.is_some() {
+            if let Err(e) = app.set_shadows(shadow_map) {
+                app.events.emit(events::Event::FeatureUnavailable {
+                    feature: String::from("drawing the shadow map"),
+                    error: e,
+                });
+            }
+        }
+        for (name, vertex, fragment) in shader_materials {
+            if let Err(e) = app.register_shader_material(&name, vertex.as_deref(), &fragment) {
+                app.events.emit(events::Event::LoadFailed {
+                    kind: events::AssetKind::ShaderMaterial,
+                    name: name,
+                    error: e,
+                });
+            }
+        }
+        if hi_z {
+            app.set_hi_z_culling(true);
+        }
+        if baked {
+            app.set_baked_draws(true);
+        }
+        if magnifier {
+            app.set_magnifier(true);
+        }
+        if residency {
+            app.set_residency_overlay(true);
+        }
+        if let Some(path) = camera_path {
+            app.set_camera_path_file(path);
+        }
+        if play_camera {
+            app.play_camera_path_file();
+        }
+        if stereo.is_some() {
+            app.set_stereo(stereo);
+        }
+        if ui_atlas {
+            if let Err(e) = app.set_ui_atlas(true) {
+                app.events.emit(events::Event::FeatureUnavailable {
+                    feature: String::from("caching the UI in an atlas"),
+                    error: e,
+                });
+            }
+        }
+        if let Some(size) = object_grid {
+            app.add_object_grid(size);
+        }
+        if software_occlusion {
+            app.set_software_occlusion(Some(occluders::Settings::default()));
+        }
+        if let Some(path) = portals {
+            match portals::Portals::load(&path) {
+                Ok(portals) => app.set_portals(Some(portals)),
+                Err(e) => app.events.emit(events::Event::LoadFailed {
+                    kind: events::AssetKind::Portals,
+                    name: path.display().to_string(),
+                    error: e,
+                }),
+            }
+        }
+        if let Some((path, load_radius)) = world {
+            let streamed = match path.exists() {
+                true => Ok(()),
+                false => streaming::write_example(&path, 64, 1.0, 0.25),
+            }
+            .and_then(|_| app.stream_world(&path, load_radius));
+            if let Err(e) = streamed {
+                app.events.emit(events::Event::FeatureUnavailable {
+                    feature: String::from("streaming the world"),
+                    error: e,
+                });
+            }
+        }
+        for path in thumbnails {
+            let texture = app.load_texture(&path);
+            let extent = texture.extent();
+            let name = path.file_stem().map_or(String::from("image"), |stem| {
+                stem.to_string_lossy().into_owned()
+            });
+            let event = match app.add_texture_thumbnail(&name, &texture) {
+                Ok(()) => events::Event::Status(format!(
+                    "Showing {} at {}x{}",
+                    name, extent.width, extent.height
+                )),
+                Err(error) => events::Event::FeatureUnavailable {
+                    feature: format!("showing {}", name),
+                    error,
+                },
+            };
+            app.events.emit(event);
+        }
+        if let Some(kind) = noise {
+            let settings = noise::Settings {
+                kind,
+                ..noise::Settings::default()
+            };
+            if let Err(e) = app
+                .generate_noise(&settings)
+                .and_then(|texture| app.use_noise_texture(texture))
+            {
+                app.events.emit(events::Event::FeatureUnavailable {
+                    feature: String::from("texturing the scene with noise"),
+                    error: e,
+                });
+            }
+        }
+        if let Some(settings) = fog {
+            app.add_fog(settings);
+        }
+        if let Some(intensity) = lens_flare {
+            app.add_lens_flare(intensity);
+        }
+        if let Some(strength) = god_rays {
+            if let Err(e) = app.set_god_rays(strength) {
+                app.events.emit(events::Event::FeatureUnavailable {
+                    feature: String::from("adding god rays"),
+                    error: e,
+                });
+            }
+        }
+        match point_cloud {
+            Some((Ok(mut cloud), mode, _)) => {
+                cloud.up = import_up.unwrap_or(cloud.up);
+                let mode = pointcloud::Mode::parse(&mode, &cloud)
+                    .expect("--point-cloud needs sprites or surfels");
+                app.add_point_cloud(&cloud, mode);
+            }
+            Some((Err(e), _, path)) => app.events.emit(events::Event::LoadFailed {
+                kind: events::AssetKind::PointCloud,
+                name: path.display().to_string(),
+                error: e,
+            }),
+            None => {}
+        }
+        match volume {
+            Some((Ok(mut dataset), transfer_function, _)) => {
+                dataset.up = import_up.unwrap_or(dataset.up);
+                app.add_volume(&dataset, transfer_function)
+            }
+            Some((Err(e), _, path)) => app.events.emit(events::Event::LoadFailed {
+                kind: events::AssetKind::Volume,
+                name: path.display().to_string(),
+                error: e,
+            }),
+            None => {}
+        }
+        if orthographic {
+            app.set_projection(camera::Projection::Orthographic);
+        }
+        if present_thread {
+            app.set_present_thread(true);
+        }
+        if infinite_far {
+            app.set_camera_parameter(camera::Parameter::Far, f32::INFINITY)
+                .expect("An infinite far plane is always allowed");
+        }
+        if let Some(preset) = view_preset {
+            app.set_view_preset(preset);
+        }
+        app.set_grid_visible(grid);
+        if pipeline_statistics {
+            app.enable_pipeline_statistics();
+        }
+        if let Some(budget) = frame_budget {
+            app.set_frame_budget(budget);
+        }
+        if clear_color.is_some() {
+            let scene = pipeline::RenderTarget::Scene;
+            app.set_pass_config(
+                scene,
+                pipeline::PassConfig {
+                    clear_color,
+                    ..app.pass_config(scene)
+                },
+            )
+            .expect("The clear colour is always allowed");
+        }
+        if accumulate.is_some() {
+            app.set_accumulation(accumulate);
+        }
+        for plane in clip_planes {
+            app.add_clip_plane(plane);
+        }
+        app.set_clip_caps(clip_caps);
+        if let Some((effect, duration)) = transition {
+            app.set_transition(effect, duration);
+        }
+        if hook_example {
+            add_example_hook(&mut app);
+        }
+        if events_example {
+            add_example_subscriber(&mut app);
+        }
+        if immediate_example {
+            add_example_immediate(&mut app);
+        }
+        if cloth {
+            app.add_cloth();
+        }
+        if let Some((behaviour, count)) = swarm {
+            app.add_swarm(behaviour, count);
+        }
+        if let Some(path) = vat_example {
+            add_example_vertex_animation(&mut app, &path);
+        }
+        // After the effects it turns on and off, and before the scene file, which may have a preset of its own
+        match quality {
+            Some(preset) => app.set_quality(preset),
+            None => app.set_recommended_quality(),
+        }
+        // Last, so that everything the file was saved with has been added
+        if let Some(path) = scene_file {
+            app.set_scene_file(path);
+        }
+        // After the scene file, which may add the draws the script and timeline move
+        if let Some(path) = script {
+            if let Err(e) = app.run_script(&path) {
+                app.events.emit(events::Event::LoadFailed {
+                    kind: events::AssetKind::Script,
+                    name: path.display().to_string(),
+                    error: e,
+                });
+            }
+        }
+        if let Some(path) = logic {
+            if let Err(e) = app.run_logic(&path) {
+                app.events.emit(events::Event::LoadFailed {
+                    kind: events::AssetKind::Logic,
+                    name: path.display().to_string(),
+                    error: e,
+                });
+            }
+        }
+        if let Some(path) = gamepad_bindings {
+            if let Err(e) = app.set_gamepad_bindings(&path) {
+                app.events.emit(events::Event::LoadFailed {
+                    kind: events::AssetKind::GamepadBindings,
+                    name: path.display().to_string(),
+                    error: e,
+                });
+            }
+        }
+        // Once every draw has been added
+        let mut prewarmed = (0, 0);
+        app.prewarm_pipelines(|created, total| prewarmed = (created, total));
+        if prewarmed.0 > 0 {
+            app.events.status(format!(
+                "Compiled {} of {} pipelines",
+                prewarmed.0, prewarmed.1
+            ));
+        }
+        // Last, so that it streams the frame every other feature has drawn
+        if let Some((address, encoding)) = stream {
+            if let Err(e) = app.stream_frames(&address, encoding) {
+                app.events.emit(events::Event::FeatureUnavailable {
+                    feature: String::from("streaming frames"),
+                    error: e,
+                });
+            }
+        }
+        if time_of_day.is_some() {
+            app.set_time_of_day(time_of_day);
+        }
+        if let Some(path) = timeline {
+            if let Err(e) =
+                timeline::Timeline::load(&path).and_then(|timeline| app.play_timeline(timeline))
+            {
+                app.events.emit(events::Event::LoadFailed {
+                    kind: events::AssetKind::Timeline,
+                    name: path.display().to_string(),
+                    error: e,
+                });
+            }
+        }
+        app
+    };
+    // Before the renderer is created, so that what creating the instance and device reports is collected too
+    if fail_on_validation.is_some() {
+        validation::fail_on_messages();
+    }
+    let mut renderer =
+        render_thread::RenderThread::spawn(create, fail_on_validation, event_loop.create_proxy());
+
+    event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::Wait;
+
+        match event {
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => {
+                println!("The close button was pressed; stopping");
+                renderer.close();
+                *control_flow = ControlFlow::Exit
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Resized(_),
+                ..
+            } => renderer.send(render_thread::Message::Resized),
+            Event::WindowEvent {
+                event: WindowEvent::ScaleFactorChanged { scale_factor, .. },
+                ..
+            } => renderer.send(render_thread::Message::ScaleFactorChanged(scale_factor)),
+            Event::WindowEvent { event, .. } => {
+                if let Some(input) = render_thread::Input::from_event(&event) {
+                    renderer.send(render_thread::Message::Input(input));
+                }
+            }
+            Event::UserEvent(render_thread::Notification::Stats(stats)) => {
+                let systems: Vec<String> = stats
+                    .systems
+                    .iter()
+                    .map(|(system, time)| {
+                        format!("{} {:.2}ms", system, time.as_secs_f64() * 1000.0)
+                    })
+                    .collect();
+                println!(
+                    "{:.1} frames per second ({})",
+                    stats.frames_per_second(),
+                    systems.join(", ")
+                );
+                for (pass, counts) in stats.pipeline.iter() {
+                    println!("    {}: {}", pass, counts.describe());
+                }
+            }
+            Event::UserEvent(render_thread::Notification::Stopped) => {
+                renderer.close();
+                if let Some(frames) = fail_on_validation {
+                    let report = validation::report(frames);
+                    println!("{}", report);
+                    if !report.passed() {
+                        std::process::exit(1);
+                    }
+                }
+                *control_flow = ControlFlow::Exit
+            }
+            _ => (),
+        }
+    });
+}
+
+/// Adds a flag above the floor rippling in the wind. The ripples are worked out ahead of time and baked into a
+/// vertex animation saved to `path`, the way a cloth simulation's output would be, and loaded from it later.
+fn add_example_vertex_animation(app: &mut HelloTriangleApplication, path: &Path) {
+    let (columns, rows) = (24, 12);
+    let (width, height) = (0.6, 0.3);
+    let (frame_count, fps) = (48, 24.0);
+
+    let mut mesh = mesh::MeshData {
+        positions: Vec::new(),
+        normals: Vec::new(),
+        colors: Some(Vec::new()),
+        tex_coords: Some(Vec::new()),
+        lightmap_coords: None,
+        occlusion: None,
+        indices: Vec::new(),
+    };
+    for row in 0..=rows {
+        for column in 0..=columns {
+            let (u, v) = (column as f32 / columns as f32, row as f32 / rows as f32);
+            mesh.positions.push([u * width, 0.0, v * height]);
+            mesh.normals.push([0.0, -1.0, 0.0]);
+            mesh.tex_coords.as_mut().unwrap().push([u, 1.0 - v]);
+            // Striped so the ripples are easy to follow
+            let stripe = if (row / 3) % 2 == 0 { 0.9 } else { 0.3 };
+            mesh.colors.as_mut().unwrap().push([0.9, stripe, 0.2]);
+        }
+    }
+    let vertex = |column: u16, row: u16| row * (columns + 1) + column;
+    for row in 0..rows {
+        for column in 0..columns {
+            let (a, b) = (vertex(column, row), vertex(column + 1, row));
+            let (c, d) = (vertex(column + 1, row + 1), vertex(column, row + 1));
+            mesh.indices.extend([a, b, c, c, d, a]);
+        }
+    }
+
+    // Waves run away from the edge the flag hangs from, growing as they go. Whole waves pass in the animation
+    // so it loops.
+    let simulate = || {
+        let positions = (0..frame_count)
+            .map(|frame| {
+                let phase = frame as f32 / frame_count as f32 * 2.0 * std::f32::consts::PI;
+                mesh.positions
+                    .iter()
+                    .map(|&[x, _, z]| {
+                        let along = x / width;
+                        let ripple = (phase - along * 3.0 * std::f32::consts::PI).sin();
+                        let droop = 0.04 * along * along;
+                        [x, 0.05 * along * ripple, z - droop]
+                    })
+                    .collect()
+            })
+            .collect();
+        let animation = vat::Animation::from_positions(positions, &mesh.indices, fps)?;
+        animation.save(path)?;
+        Ok(animation)
+    };
+    let animation = if path.exists() {
+        vat::Animation::load(path, fps)
+    } else {
+        simulate()
+    };
+    let result = animation.and_then(|animation| {
+        app.add_vertex_animation(
+            &mesh,
+            &animation,
+            material::Material {
+                double_sided: true,
+                vertex_color: material::VertexColorMode::Multiply,
+                ..material::Material::default()
+            },
+            Matrix4::from_translation(Vector3::new(-0.3, 0.0, -0.2)),
+        )
+    });
+    if let Err(e) = result {
+        app.events.emit(events::Event::FeatureUnavailable {
+            feature: String::from("adding the example vertex animation"),
+            error: e,
+        });
+    }
+}
+
+/// Spins a ring of quads above the scene with an immediate mode update, which also turns the camera slowly
+/// around it
+fn add_example_immediate(app: &mut HelloTriangleApplication) {
+    let count = 8;
+    app.set_immediate(Box::new(move |renderer: &mut immediate::Renderer| {
+        for index in 0..count {
+            let angle = Rad(2.0 * std::f32::consts::PI * index as f32 / count as f32);
+            let transform = Matrix4::from_angle_z(angle + Rad(0.5 * renderer.time))
+                * Matrix4::from_translation(Vector3::new(0.8, 0.0, 0.6))
+                * Matrix4::from_angle_y(Rad(renderer.time * 2.0))
+                * Matrix4::from_scale(0.2);
+            renderer.draw_quad(
+                transform,
+                material::Material {
+                    double_sided: true,
+                    ..material::Material::default()
+                },
+            );
+        }
+        let mut camera = *renderer.camera();
+        camera.orbit(Rad(0.1 * renderer.delta), Rad(0.0));
+        renderer.set_camera(camera);
+    }));
+}
+
+/// Keeps a tally of the time spent compiling pipelines from outside the renderer, printing it each time
+/// another is compiled and when a frame goes over budget
+fn add_example_subscriber(app: &mut HelloTriangleApplication) {
+    let mut compiled = 0;
+    let mut compiling = Duration::ZERO;
+    app.subscribe(Box::new(move |event: &events::Event| match event {
+        events::Event::PipelineCompiled { duration, .. } => {
+            compiled += 1;
+            compiling += *duration;
+            println!(
+                "Example subscriber: {} pipelines compiled in {:.1}ms",
+                compiled,
+                compiling.as_secs_f64() * 1000.0
+            );
+        }
+        events::Event::BudgetExceeded { alert, .. } => println!(
+            "Example subscriber: {:?} frame over budget after {} pipelines compiled",
+            alert.side, compiled
+        ),
+        _ => {}
+    }));
+}
+
+/// Draws a pulsing square in the top right corner from outside the renderer
+fn add_example_hook(app: &mut HelloTriangleApplication) {
+    app.add_hook(
+        hooks::Stage::AfterOpaque,
+        "example",
+        Box::new(|context: &hooks::FrameContext| {
+            let size = 32;
+            let rect = vk::ClearRect::builder()
+                .rect(vk::Rect2D {
+                    offset: vk::Offset2D {
+                        x: context.target.extent.width as i32 - 2 * size,
+                        y: size,
+                    },
+                    extent: vk::Extent2D {
+                        width: size as u32,
+                        height: size as u32,
+                    },
+                })
+                .base_array_layer(0)
+                .layer_count(1)
+                .build();
+            let pulse = 0.5 + 0.5 * (context.time * 3.0).sin();
+            let attachment = vk::ClearAttachment::builder()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .color_attachment(0)
+                .clear_value(vk::ClearValue {
+                    color: vk::ClearColorValue {
+                        float32: [pulse, 0.2, 1.0 - pulse, 1.0],
+                    },
+                })
+                .build();
+            unsafe {
+                context
+                    .device
+                    .cmd_clear_attachments(context.command_buffer, &[attachment], &[rect])
+            };
+        }),
+    );
 }
@@ -0,0 +1,146 @@
+use ash::vk;
+
+use crate::{mesh, pipeline};
+
+/// How a material's alpha is used.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlendMode {
+    /// Alpha is ignored
+    Opaque,
+    /// Cutout surfaces such as foliage and fences. Fragments with alpha below `cutoff` are masked out, using
+    /// alpha-to-coverage for smooth edges when multisampling and discarding them otherwise.
+    Masked { cutoff: f32 },
+}
+
+/// How a mesh's vertex colours are combined with the material's texture. The values match the fragment
+/// shader's `VERTEX_COLOR_MODE` specialization constant.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum VertexColorMode {
+    /// Vertex colours are ignored and only the texture is shown
+    Ignore = 0,
+    /// Vertex colours tint the texture. Meshes without texture coordinates are only vertex coloured.
+    Multiply = 1,
+}
+
+/// Stylized cel shading, lighting the mesh in flat bands from a ramp rather than smoothly, with an optional outline
+/// drawn around it. Lightmapped meshes keep their baked lighting but can still be outlined.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Toon {
+    /// The row of the toon ramp atlas the light is looked up in, which is one of `toon::RAMPS` unless the
+    /// renderer was given its own atlas
+    pub ramp: u8,
+    /// How many pixels wide the outline is, 0 for none. Rounded to whole pixels.
+    pub outline: f32,
+}
+
+/// Describes how the surface of a mesh is drawn. Each distinct material maps onto a pipeline variant.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Material {
+    /// Which faces are discarded before rasterization
+    pub cull_mode: vk::CullModeFlags,
+    /// The winding order of front facing triangles. The geometry built into this renderer winds its front faces
+    /// clockwise, imported glTF content winds them counter-clockwise.
+    pub front_face: vk::FrontFace,
+    /// Draws both faces of thin geometry such as leaves, cloth and paper. Culling is disabled regardless of
+    /// `cull_mode` and back faces are shaded with their normals flipped so they are lit like the front.
+    pub double_sided: bool,
+    pub blend_mode: BlendMode,
+    pub vertex_color: VertexColorMode,
+    /// How much the mesh's baked ambient occlusion darkens it, from 0 to 1. Only meshes baked by
+    /// `occlusion::bake` have any.
+    pub occlusion_strength: f32,
+    /// Toon shades the mesh instead of lighting it smoothly when present
+    pub toon: Option<Toon>,
+    /// The index of the shader material drawing the mesh instead of the built in shading, see
+    /// `shader_materials::Library`. Shader materials have no clip caps, outlines or trails, and cast no shadows
+    /// into the shadow map.
+    pub shader: Option<u16>,
+    /// Whether the mesh is drawn into the light's shadow map, shadowing what's behind it. See
+    /// `shadow_map::ShadowMap`.
+    pub casts_shadows: bool,
+}
+
+impl Material {
+    /// The pipeline state for drawing a mesh with this material. Attributes the mesh doesn't have take default
+    /// values: white vertex colours, and the texture isn't sampled without texture coordinates. Meshes with
+    /// lightmap coordinates are lit by the lightmap instead of the fixed light.
+    pub fn pipeline_config(&self, attributes: mesh::VertexAttributes) -> pipeline::Config {
+        pipeline::Config {
+            cull_mode: if self.double_sided {
+                vk::CullModeFlags::NONE
+            } else {
+                self.cull_mode
+            },
+            front_face: self.front_face,
+            double_sided: self.double_sided,
+            alpha_cutoff: match self.blend_mode {
+                BlendMode::Opaque => None,
+                BlendMode::Masked { cutoff } => {
+                    Some((num::clamp(cutoff, 0.0, 1.0) * 255.0).round() as u8)
+                }
+            },
+            vertex_attributes: attributes,
+            vertex_color: self.vertex_color,
+            occlusion_strength: (num::clamp(self.occlusion_strength, 0.0, 1.0) * 255.0).round()
+                as u8,
+            toon_ramp: self.toon.map(|toon| toon.ramp),
+            outline_width: self.toon.map_or(0, |toon| {
+                toon.outline.clamp(0.0, u8::MAX as f32).round() as u8
+            }),
+            program: self
+                .shader
+                .map_or(pipeline::Program::Mesh, pipeline::Program::Custom),
+            casts_shadows: self.casts_shadows,
+            ..pipeline::Config::mesh()
+        }
+    }
+
+    /// The material a mesh's pipeline state was made from. Double-sided materials don't keep their cull mode, so
+    /// they are given back-face culling.
+    pub fn from_config(config: &pipeline::Config) -> Self {
+        Self {
+            cull_mode: if config.double_sided {
+                vk::CullModeFlags::BACK
+            } else {
+                config.cull_mode
+            },
+            front_face: config.front_face,
+            double_sided: config.double_sided,
+            blend_mode: match config.alpha_cutoff {
+                None => BlendMode::Opaque,
+                Some(cutoff) => BlendMode::Masked {
+                    cutoff: cutoff as f32 / 255.0,
+                },
+            },
+            vertex_color: config.vertex_color,
+            occlusion_strength: config.occlusion_strength as f32 / 255.0,
+            toon: config.toon_ramp.map(|ramp| Toon {
+                ramp,
+                outline: config.outline_width as f32,
+            }),
+            shader: match config.program {
+                pipeline::Program::Custom(index) => Some(index),
+                _ => None,
+            },
+            casts_shadows: config.casts_shadows,
+        }
+    }
+}
+
+impl Default for Material {
+    /// Back-face culled with clockwise front faces, matching the renderer's built in geometry, textured without
+    /// vertex colours, fully occluded by any baked occlusion and casting shadows.
+    fn default() -> Self {
+        Self {
+            cull_mode: vk::CullModeFlags::BACK,
+            front_face: vk::FrontFace::CLOCKWISE,
+            double_sided: false,
+            blend_mode: BlendMode::Opaque,
+            vertex_color: VertexColorMode::Ignore,
+            occlusion_strength: 1.0,
+            toon: None,
+            shader: None,
+            casts_shadows: true,
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use ash::vk;
+use cgmath::{Deg, InnerSpace, Matrix4, Point3, Vector3, Vector4};
+
+use crate::{draw, pipeline, units, HelloTriangleApplication, Vertex};
+
+const COLOR: [f32; 3] = [1.0, 0.85, 0.1];
+
+/// What clicking on the scene measures
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Between two points
+    Distance,
+    /// At the second of three points, between the lines to the other two
+    Angle,
+}
+
+impl Mode {
+    pub fn next(self) -> Self {
+        match self {
+            Mode::Distance => Mode::Angle,
+            Mode::Angle => Mode::Distance,
+        }
+    }
+
+    fn point_count(&self) -> usize {
+        match self {
+            Mode::Distance => 2,
+            Mode::Angle => 3,
+        }
+    }
+}
+
+/// Points picked on the scene's surfaces, drawn as markers joined by lines. The points are kept in the scene's
+/// space, before its model matrix, so they stay on the surfaces they were picked on as the scene turns.
+pub struct Measurement {
+    pub mode: Mode,
+    points: Vec<Point3<f32>>,
+    /// A line from the origin to one along X, stretched between points by each segment's transform
+    buffer: (vk::Buffer, vk::DeviceMemory),
+    /// Indices of the draws of the two segments, then the three markers
+    segments: [usize; 2],
+    markers: [usize; 3],
+}
+
+impl Measurement {
+    /// Adds the measurement's draws, hidden until points are picked
+    pub fn new(
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        draws: &mut Vec<draw::Draw>,
+    ) -> Self {
+        let vertex = |x: f32| Vertex {
+            pos: [x, 0.0, 0.0],
+            color: COLOR,
+            tex_coord: [0.0, 0.0],
+            normal: [0.0, 0.0, 1.0],
+        };
+        let vertices = [vertex(0.0), vertex(1.0)];
+        let buffer = HelloTriangleApplication::create_device_local_buffer(
+            device,
+            &vertices,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            command_pool,
+            queue,
+            device_memory_properties,
+        );
+
+        let first = draws.len();
+        for _ in 0..2 {
+            draws.push(draw::Draw {
+                instance_count: 0,
+                line_width: 2.0,
+                ..draw::Draw::new(
+                    pipeline::Config::unlit(vk::PrimitiveTopology::LINE_LIST),
+                    buffer.0,
+                    2,
+                )
+            });
+        }
+        for _ in 0..3 {
+            draws.push(draw::Draw {
+                instance_count: 0,
+                point_size: 8.0,
+                ..draw::Draw::new(
+                    pipeline::Config::unlit(vk::PrimitiveTopology::POINT_LIST),
+                    buffer.0,
+                    1,
+                )
+            });
+        }
+
+        Self {
+            mode: Mode::Distance,
+            points: Vec::new(),
+            buffer,
+            segments: [first, first + 1],
+            markers: [first + 2, first + 3, first + 4],
+        }
+    }
+
+    /// Adds a point in the scene's space, starting over once the last measurement is complete. Returns the
+    /// measurement in metres when this point completes it.
+    pub fn add(&mut self, point: Point3<f32>, world: &units::Convention) -> Option<String> {
+        if self.points.len() >= self.mode.point_count() {
+            self.points.clear();
+        }
+        self.points.push(point);
+        self.describe(world)
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    /// The distance or angle between the points, once there are enough of them
+    pub fn describe(&self, world: &units::Convention) -> Option<String> {
+        match (self.mode, self.points.as_slice()) {
+            (Mode::Distance, &[a, b]) => {
+                let offset = (b - a) * world.unit;
+                Some(format!(
+                    "distance {:.4}m (dx {:.4} dy {:.4} dz {:.4})",
+                    offset.magnitude(),
+                    offset.x,
+                    offset.y,
+                    offset.z
+                ))
+            }
+            (Mode::Angle, &[a, vertex, b]) => {
+                let angle: Deg<f32> = (a - vertex).angle(b - vertex).into();
+                Some(format!("angle {:.2} degrees", angle.0))
+            }
+            _ => None,
+        }
+    }
+
+    /// Places the markers and segments between the points picked so far, in the current scene
+    pub fn update(&self, draws: &mut [draw::Draw], scene: usize) {
+        for (index, &draw) in self.markers.iter().enumerate() {
+            let point = self.points.get(index);
+            let draw = &mut draws[draw];
+            draw.scene = scene;
+            draw.instance_count = point.is_some() as u32;
+            if let Some(&point) = point {
+                draw.transform = Matrix4::from_translation(point - Point3::new(0.0, 0.0, 0.0));
+            }
+        }
+        for (index, &draw) in self.segments.iter().enumerate() {
+            let ends = self.points.get(index).zip(self.points.get(index + 1));
+            let draw = &mut draws[draw];
+            draw.scene = scene;
+            draw.instance_count = ends.is_some() as u32;
+            if let Some((&start, &end)) = ends {
+                // Only the X axis is used, so the rest of the basis doesn't matter
+                let along = end - start;
+                draw.transform = Matrix4::from_cols(
+                    along.extend(0.0),
+                    Vector4::unit_y(),
+                    Vector4::unit_z(),
+                    Vector3::new(start.x, start.y, start.z).extend(1.0),
+                );
+            }
+        }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_buffer(self.buffer.0, None);
+            device.free_memory(self.buffer.1, None);
+        }
+    }
+}
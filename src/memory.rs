@@ -0,0 +1,115 @@
+use std::fmt;
+
+use ash::vk;
+
+/// Textures aren't shrunk below this many texels across, failing to load instead
+pub const MIN_TEXTURE_SIZE: u32 = 16;
+
+/// A texture loaded smaller than its source because it didn't fit in device memory.
+#[derive(Clone, Debug)]
+pub struct Downgrade {
+    pub name: String,
+    pub from: (u32, u32),
+    pub to: (u32, u32),
+    pub reason: String,
+}
+
+impl fmt::Display for Downgrade {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} loaded at {}x{} instead of {}x{}: {}",
+            self.name, self.to.0, self.to.1, self.from.0, self.from.1, self.reason
+        )
+    }
+}
+
+/// The device memory a texture takes, and what it would have taken at its source's size had it not been shrunk
+/// to fit the budget
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Allocation {
+    pub size: vk::DeviceSize,
+    pub requested: vk::DeviceSize,
+}
+
+impl std::ops::AddAssign for Allocation {
+    fn add_assign(&mut self, other: Self) {
+        self.size += other.size;
+        self.requested += other.requested;
+    }
+}
+
+/// The device memory the renderer's textures may take. The limit is the largest device local heap, or less when
+/// configured, and textures that would go over it or fail to allocate are loaded at lower resolutions instead.
+pub struct TextureBudget {
+    limit: vk::DeviceSize,
+    used: Allocation,
+    pub downgrades: Vec<Downgrade>,
+    /// Downgrades already returned by `unreported`
+    reported: usize,
+}
+
+impl TextureBudget {
+    pub fn new(
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        limit: Option<vk::DeviceSize>,
+    ) -> Self {
+        let heaps = &device_memory_properties.memory_heaps
+            [..device_memory_properties.memory_heap_count as usize];
+        let device_local = heaps
+            .iter()
+            .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .max()
+            .unwrap_or(vk::DeviceSize::MAX);
+        Self {
+            limit: limit.map_or(device_local, |limit| limit.min(device_local)),
+            used: Allocation::default(),
+            downgrades: Vec::new(),
+            reported: 0,
+        }
+    }
+
+    /// Whether a texture taking `size` bytes fits in what is left of the budget
+    pub fn fits(&self, size: vk::DeviceSize) -> bool {
+        self.used.size.saturating_add(size) <= self.limit
+    }
+
+    pub fn allocate(&mut self, allocation: Allocation) {
+        self.used += allocation;
+    }
+
+    /// Returns the memory of a texture that has been freed
+    pub fn release(&mut self, allocation: Allocation) {
+        self.used.size = self.used.size.saturating_sub(allocation.size);
+        self.used.requested = self.used.requested.saturating_sub(allocation.requested);
+    }
+
+    /// The memory the textures loaded take between them, and would take at their sources' sizes
+    pub fn used(&self) -> Allocation {
+        self.used
+    }
+
+    pub fn limit(&self) -> vk::DeviceSize {
+        self.limit
+    }
+
+    pub fn downgrade(&mut self, downgrade: Downgrade) {
+        self.downgrades.push(downgrade);
+    }
+
+    /// The downgrades recorded since the last call, for warning about them
+    pub fn unreported(&mut self) -> Vec<Downgrade> {
+        let unreported = self.downgrades[self.reported..].to_vec();
+        self.reported = self.downgrades.len();
+        unreported
+    }
+}
+
+/// Whether allocating failed because the memory has run out, which can be recovered from by allocating less
+pub fn is_out_of_memory(result: vk::Result) -> bool {
+    matches!(
+        result,
+        vk::Result::ERROR_OUT_OF_DEVICE_MEMORY | vk::Result::ERROR_OUT_OF_HOST_MEMORY
+    )
+}
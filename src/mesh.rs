@@ -0,0 +1,219 @@
+use std::mem::size_of;
+
+use ash::vk;
+use cgmath::Point3;
+
+use crate::bounds;
+
+/// Binding that attributes missing from a mesh are read from. It is bound to `DEFAULT_ATTRIBUTES` with a stride
+/// of zero so every vertex reads the same value.
+pub const DEFAULT_ATTRIBUTE_BINDING: u32 = 1;
+
+/// Values used for attributes a mesh doesn't have: white vertex colour followed by a zero texture coordinate,
+/// which is also used for missing lightmap coordinates. Meshes without baked occlusion read the colour's first
+/// component, so they are unoccluded.
+pub const DEFAULT_ATTRIBUTES: [f32; 5] = [1.0, 1.0, 1.0, 0.0, 0.0];
+const DEFAULT_COLOR_OFFSET: u32 = 0;
+const DEFAULT_TEX_COORD_OFFSET: u32 = 3 * size_of::<f32>() as u32;
+
+/// Location of the lightmap coordinate attribute. It follows the locations `Program::Indirect` uses for its
+/// per-instance transforms.
+const LIGHTMAP_COORD_LOCATION: u32 = 8;
+const OCCLUSION_LOCATION: u32 = 9;
+
+/// Which optional attributes a mesh's vertices carry. Every vertex has a position and a normal.
+///
+/// Attributes are interleaved in the order position, colour, texture coordinate, normal, lightmap coordinate,
+/// occlusion with absent attributes left out, so a mesh with the attributes in `ALL` has the same layout as `Vertex`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VertexAttributes {
+    pub color: bool,
+    pub tex_coord: bool,
+    /// Where the vertex is in the scene's lightmap, see `lightmap`
+    pub lightmap_coord: bool,
+    /// How much of the ambient light reaches the vertex, see `occlusion`
+    pub occlusion: bool,
+}
+
+impl VertexAttributes {
+    /// The attributes of `Vertex`
+    pub const ALL: Self = Self {
+        color: true,
+        tex_coord: true,
+        lightmap_coord: false,
+        occlusion: false,
+    };
+
+    /// Size in bytes of one interleaved vertex
+    pub fn stride(&self) -> u32 {
+        let floats = 3
+            + 3
+            + if self.color { 3 } else { 0 }
+            + if self.tex_coord { 2 } else { 0 }
+            + if self.lightmap_coord { 2 } else { 0 }
+            + if self.occlusion { 1 } else { 0 };
+        (floats * size_of::<f32>()) as u32
+    }
+
+    pub fn binding_descriptions(&self) -> Vec<vk::VertexInputBindingDescription> {
+        let mut bindings = vec![vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(self.stride())
+            .input_rate(vk::VertexInputRate::VERTEX)
+            .build()];
+
+        if !(self.color && self.tex_coord && self.lightmap_coord && self.occlusion) {
+            bindings.push(
+                vk::VertexInputBindingDescription::builder()
+                    .binding(DEFAULT_ATTRIBUTE_BINDING)
+                    .stride(0)
+                    .input_rate(vk::VertexInputRate::VERTEX)
+                    .build(),
+            );
+        }
+
+        bindings
+    }
+
+    /// Attribute locations are the same for every layout so one vertex shader handles them all. Attributes the
+    /// mesh doesn't have are sourced from the default attribute binding instead.
+    pub fn attribute_descriptions(&self) -> Vec<vk::VertexInputAttributeDescription> {
+        let attribute = |location: u32, binding: u32, format: vk::Format, offset: u32| {
+            vk::VertexInputAttributeDescription::builder()
+                .binding(binding)
+                .location(location)
+                .format(format)
+                .offset(offset)
+                .build()
+        };
+        let vec2 = 2 * size_of::<f32>() as u32;
+        let vec3 = 3 * size_of::<f32>() as u32;
+
+        let mut offset = 0;
+        let position = attribute(0, 0, vk::Format::R32G32B32_SFLOAT, offset);
+        offset += vec3;
+
+        let color = if self.color {
+            offset += vec3;
+            attribute(1, 0, vk::Format::R32G32B32_SFLOAT, offset - vec3)
+        } else {
+            attribute(
+                1,
+                DEFAULT_ATTRIBUTE_BINDING,
+                vk::Format::R32G32B32_SFLOAT,
+                DEFAULT_COLOR_OFFSET,
+            )
+        };
+
+        let tex_coord = if self.tex_coord {
+            offset += vec2;
+            attribute(2, 0, vk::Format::R32G32_SFLOAT, offset - vec2)
+        } else {
+            attribute(
+                2,
+                DEFAULT_ATTRIBUTE_BINDING,
+                vk::Format::R32G32_SFLOAT,
+                DEFAULT_TEX_COORD_OFFSET,
+            )
+        };
+
+        let normal = attribute(3, 0, vk::Format::R32G32B32_SFLOAT, offset);
+        offset += vec3;
+
+        let lightmap_coord = if self.lightmap_coord {
+            offset += vec2;
+            attribute(
+                LIGHTMAP_COORD_LOCATION,
+                0,
+                vk::Format::R32G32_SFLOAT,
+                offset - vec2,
+            )
+        } else {
+            attribute(
+                LIGHTMAP_COORD_LOCATION,
+                DEFAULT_ATTRIBUTE_BINDING,
+                vk::Format::R32G32_SFLOAT,
+                DEFAULT_TEX_COORD_OFFSET,
+            )
+        };
+
+        let occlusion = if self.occlusion {
+            attribute(OCCLUSION_LOCATION, 0, vk::Format::R32_SFLOAT, offset)
+        } else {
+            attribute(
+                OCCLUSION_LOCATION,
+                DEFAULT_ATTRIBUTE_BINDING,
+                vk::Format::R32_SFLOAT,
+                DEFAULT_COLOR_OFFSET,
+            )
+        };
+
+        vec![
+            position,
+            color,
+            tex_coord,
+            normal,
+            lightmap_coord,
+            occlusion,
+        ]
+    }
+}
+
+/// Vertex data stored as one array per attribute, the way it is usually loaded from model files. Optional
+/// attributes that are `None` are filled in with defaults when drawn.
+#[derive(Clone, Debug)]
+pub struct MeshData {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub colors: Option<Vec<[f32; 3]>>,
+    pub tex_coords: Option<Vec<[f32; 2]>>,
+    pub lightmap_coords: Option<Vec<[f32; 2]>>,
+    pub occlusion: Option<Vec<f32>>,
+    pub indices: Vec<u16>,
+}
+
+impl MeshData {
+    pub fn attributes(&self) -> VertexAttributes {
+        VertexAttributes {
+            color: self.colors.is_some(),
+            tex_coord: self.tex_coords.is_some(),
+            lightmap_coord: self.lightmap_coords.is_some(),
+            occlusion: self.occlusion.is_some(),
+        }
+    }
+
+    /// The mesh-local bounds of the vertex positions
+    pub fn bounds(&self) -> Option<bounds::Aabb> {
+        bounds::Aabb::from_points(
+            self.positions
+                .iter()
+                .map(|&position| Point3::from(position)),
+        )
+    }
+
+    /// Interleaves the attributes the mesh has into the contents of a vertex buffer laid out as described by
+    /// `attributes()`.
+    pub fn interleave(&self) -> Vec<f32> {
+        let floats_per_vertex = self.attributes().stride() as usize / size_of::<f32>();
+        let mut data = Vec::with_capacity(self.positions.len() * floats_per_vertex);
+
+        for (i, position) in self.positions.iter().enumerate() {
+            data.extend_from_slice(position);
+            if let Some(colors) = &self.colors {
+                data.extend_from_slice(&colors[i]);
+            }
+            if let Some(tex_coords) = &self.tex_coords {
+                data.extend_from_slice(&tex_coords[i]);
+            }
+            data.extend_from_slice(&self.normals[i]);
+            if let Some(lightmap_coords) = &self.lightmap_coords {
+                data.extend_from_slice(&lightmap_coords[i]);
+            }
+            if let Some(occlusion) = &self.occlusion {
+                data.push(occlusion[i]);
+            }
+        }
+
+        data
+    }
+}
@@ -0,0 +1,297 @@
+use ash::vk;
+use cgmath::{InnerSpace, Matrix4, Point3, Vector3};
+
+use crate::features::SwapchainContext;
+use crate::{bounds, pipeline, schedule, sdf, units, HelloTriangleApplication, ImageDescription};
+
+/// Gap between the minimap and the top right corner of the frame, in logical pixels
+const MARGIN: f32 = 16.0;
+/// How far above the highest point and below the lowest the map's clip planes are, in world units
+const CLEARANCE: f32 = 1.0;
+/// How rounded the map's corners are, as a fraction of its size
+const CORNER_RADIUS: f32 = 0.08;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Settings {
+    /// How wide and tall the map is on screen, in logical pixels
+    pub size: f32,
+    /// How much of the world the map shows across, in world units
+    pub extent: f32,
+    /// Frames between one drawing of the map and the next, 1 to draw it every frame. The scheduler may leave it
+    /// longer when other renders are due too.
+    pub interval: u32,
+    /// Whether the map turns with the camera so that the way it looks is up, or keeps the world's north up
+    pub rotate: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            size: 192.0,
+            extent: 20.0,
+            interval: 2,
+            rotate: true,
+        }
+    }
+}
+
+impl Settings {
+    /// The map's view and projection. It looks straight down the world's up onto `target` from above everything
+    /// in `bounds`, with `forward` up the map when it rotates.
+    pub fn view_projection(
+        &self,
+        target: Point3<f32>,
+        forward: Vector3<f32>,
+        up: units::Up,
+        bounds: Option<bounds::Aabb>,
+    ) -> (Matrix4<f32>, Matrix4<f32>) {
+        let up_vector = up.vector();
+        // The scene's highest and lowest points above the target, or as far as the map is wide either way
+        let (above, below) = match bounds {
+            Some(bounds) => {
+                let heights = corners(&bounds).map(|corner| (corner - target).dot(up_vector));
+                heights
+                    .iter()
+                    .fold((f32::MIN, f32::MAX), |(above, below), &height| {
+                        (above.max(height), below.min(height))
+                    })
+            }
+            None => (self.extent, -self.extent),
+        };
+        // North is the axis the presets' front view looks along, flattened onto the ground
+        let north = up.rotation() * Vector3::unit_y();
+        let flattened = forward - up_vector * forward.dot(up_vector);
+        let map_up = match self.rotate && flattened.magnitude2() > f32::EPSILON {
+            true => flattened.normalize(),
+            false => north,
+        };
+        let height = above.max(0.0) + CLEARANCE;
+        let eye = target + up_vector * height;
+        let view = Matrix4::look_to_rh(eye, -up_vector, map_up);
+        let half = self.extent / 2.0;
+        let far = height - below.min(0.0) + CLEARANCE;
+        let projection = cgmath::ortho(-half, half, -half, half, 0.0, far);
+        (view, projection)
+    }
+
+    /// The 2D layer's item showing the map, the layer's `image`, in the top right corner of a frame `width`
+    /// logical pixels wide
+    pub fn item(&self, image: usize, width: f32) -> sdf::Item {
+        sdf::Item::Image {
+            image,
+            position: [width - MARGIN - self.size, MARGIN],
+            size: [self.size; 2],
+            radius: self.size * CORNER_RADIUS,
+            style: sdf::Style {
+                outline: [0.0, 0.0, 0.0, 0.75],
+                outline_width: 2.0,
+                ..sdf::Style::default()
+            },
+        }
+    }
+}
+
+fn corners(bounds: &bounds::Aabb) -> [Point3<f32>; 8] {
+    let (min, max) = (bounds.min, bounds.max);
+    [
+        Point3::new(min.x, min.y, min.z),
+        Point3::new(max.x, min.y, min.z),
+        Point3::new(min.x, max.y, min.z),
+        Point3::new(max.x, max.y, min.z),
+        Point3::new(min.x, min.y, max.z),
+        Point3::new(max.x, min.y, max.z),
+        Point3::new(min.x, max.y, max.z),
+        Point3::new(max.x, max.y, max.z),
+    ]
+}
+
+/// A top-down map of the scene around the camera's target, drawn through an orthographic projection looking
+/// down the world's up into a texture of its own with a render pass compatible with the renderer's, so the
+/// scene's pipelines draw into it unchanged. It's drawn before the frame's render pass when the scheduler gets
+/// round to it, no more often than every `interval` frames, see `schedule::Scheduler`. The texture is shown in
+/// the top right corner of the frame by the 2D layer, `sdf::Layer`, in between.
+///
+/// Like the left eye in stereo, the map is the scene's draws only: GPU driven objects, the playground and the
+/// renderer's features aren't drawn into it.
+pub struct Minimap {
+    pub settings: Settings,
+    pub view: schedule::View,
+    /// The map's task in the scheduler
+    pub task: usize,
+    /// Texels across the texture, the map's size on screen in physical pixels
+    pub resolution: u32,
+    render_pass: vk::RenderPass,
+    /// Clears what `render_pass` preserves, for the first time the map is drawn
+    first_render_pass: Option<vk::RenderPass>,
+    color: (vk::Image, vk::DeviceMemory, vk::ImageView),
+    depth: (vk::Image, vk::DeviceMemory, vk::ImageView),
+    framebuffer: vk::Framebuffer,
+    /// Whether the texture has been cleared out of the undefined layout it's created in
+    cleared: bool,
+    drawn: bool,
+    /// The texture's index in the 2D layer, once it has been added to it
+    pub image: Option<usize>,
+}
+
+impl Minimap {
+    pub fn new(
+        context: &SwapchainContext,
+        settings: Settings,
+        view: schedule::View,
+        task: usize,
+        passes: &pipeline::PassConfig,
+    ) -> Self {
+        let device = context.device;
+        let resolution = ((settings.size * context.scale_factor).round() as u32).max(1);
+        let create_render_pass = |config: &pipeline::PassConfig| {
+            pipeline::create_offscreen_render_pass(
+                device,
+                context.format,
+                context.depth_format,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                // The 2D layer samples the texture in the frame's render pass
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::AccessFlags::SHADER_READ,
+                config,
+            )
+        };
+        let render_pass = create_render_pass(passes);
+        let first_render_pass = match passes.preserves() {
+            true => Some(create_render_pass(&passes.first())),
+            false => None,
+        };
+        let create_attachment = |format, usage, aspect| {
+            let (image, memory) = HelloTriangleApplication::create_image(
+                device,
+                &ImageDescription {
+                    width: resolution,
+                    height: resolution,
+                    format,
+                    tiling: vk::ImageTiling::OPTIMAL,
+                    usage,
+                    memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                },
+                context.device_memory_properties,
+            );
+            let view = HelloTriangleApplication::create_image_view(device, image, format, aspect);
+            (image, memory, view)
+        };
+        let color = create_attachment(
+            context.format,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::SAMPLED
+                | vk::ImageUsageFlags::TRANSFER_DST,
+            vk::ImageAspectFlags::COLOR,
+        );
+        let depth = create_attachment(
+            context.depth_format,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            vk::ImageAspectFlags::DEPTH,
+        );
+        let attachments = [color.2, depth.2];
+        let framebuffer = unsafe {
+            device
+                .create_framebuffer(
+                    &vk::FramebufferCreateInfo::builder()
+                        .render_pass(render_pass)
+                        .attachments(&attachments)
+                        .width(resolution)
+                        .height(resolution)
+                        .layers(1),
+                    None,
+                )
+                .expect("Minimap frame buffer")
+        };
+
+        Self {
+            settings,
+            view,
+            task,
+            resolution,
+            render_pass,
+            first_render_pass,
+            color,
+            depth,
+            framebuffer,
+            cleared: false,
+            drawn: false,
+            image: None,
+        }
+    }
+
+    /// The texture the map is drawn into, in the `SHADER_READ_ONLY_OPTIMAL` layout once it has been drawn
+    pub fn image_view(&self) -> vk::ImageView {
+        self.color.2
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        vk::Extent2D {
+            width: self.resolution,
+            height: self.resolution,
+        }
+    }
+
+    /// Records clearing the texture to `color` the first time it's called, so the 2D layer has something to show
+    /// before the map has been drawn. Must be called outside a render pass, before the frame's render pass.
+    pub fn record_clear(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        color: &vk::ClearColorValue,
+    ) {
+        if !self.cleared {
+            self.cleared = true;
+            schedule::record_clear(device, command_buffer, self.color.0, 1, color);
+        }
+    }
+
+    /// Records a render pass that draws the map into its texture. `record` is given the command buffer inside
+    /// the render pass and records the scene's draws. Must be called outside a render pass, before the frame's
+    /// render pass, once the texture has been cleared.
+    pub fn record(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        clear_values: &[vk::ClearValue],
+        record: impl FnOnce(vk::CommandBuffer),
+    ) {
+        let render_pass = match (self.drawn, self.first_render_pass) {
+            (false, Some(first)) => first,
+            _ => self.render_pass,
+        };
+        self.drawn = true;
+        let render_pass_bi = vk::RenderPassBeginInfo::builder()
+            .render_pass(render_pass)
+            .framebuffer(self.framebuffer)
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: self.extent(),
+            })
+            .clear_values(clear_values);
+        unsafe {
+            device.cmd_begin_render_pass(
+                command_buffer,
+                &render_pass_bi,
+                vk::SubpassContents::INLINE,
+            )
+        };
+        record(command_buffer);
+        unsafe { device.cmd_end_render_pass(command_buffer) };
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            device.destroy_framebuffer(self.framebuffer, None);
+            for &(image, memory, view) in [self.color, self.depth].iter() {
+                device.destroy_image_view(view, None);
+                device.destroy_image(image, None);
+                device.free_memory(memory, None);
+            }
+            device.destroy_render_pass(self.render_pass, None);
+            if let Some(first) = self.first_render_pass {
+                device.destroy_render_pass(first, None);
+            }
+        }
+        self.view.destroy(device);
+    }
+}
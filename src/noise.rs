@@ -0,0 +1,415 @@
+use std::mem::size_of;
+use std::path::Path;
+
+use ash::vk;
+
+use crate::{
+    begin_single_time_commands, end_single_time_commands, postprocess, util,
+    HelloTriangleApplication,
+};
+
+/// Texels generated per workgroup in each direction, matching the local size in `noise_comp.glsl`
+const WORKGROUP_SIZE: u32 = 4;
+/// Linear filtering and transfers are supported for it on every device
+const FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+/// The kind of noise to generate. The values match the constants in `noise_comp.glsl`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// Smooth gradient noise on the cube grid
+    Perlin = 0,
+    /// Gradient noise summed from the corners of the simplex around each point, with fewer grid-aligned features
+    /// than Perlin noise
+    Simplex = 1,
+    /// Bright spots at a random point in each cell, the usual base for clouds and stone
+    Worley = 2,
+}
+
+impl Kind {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "perlin" => Some(Kind::Perlin),
+            "simplex" => Some(Kind::Simplex),
+            "worley" => Some(Kind::Worley),
+            _ => None,
+        }
+    }
+}
+
+/// What `Texture::generate` generates
+#[derive(Clone, Copy, Debug)]
+pub struct Settings {
+    pub kind: Kind,
+    /// Width and height in texels
+    pub size: u32,
+    /// Depth in texels. Textures with a depth of one are 2D, deeper ones 3D.
+    pub depth: u32,
+    /// Cells across the texture in the first octave. The texture tiles since it is a whole number.
+    pub frequency: u32,
+    /// Layers of noise summed together, each at twice the frequency of the one before
+    pub octaves: u32,
+    /// How much each octave's amplitude is scaled by relative to the one before
+    pub persistence: f32,
+    /// Different seeds give different noise with the same look
+    pub seed: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            kind: Kind::Perlin,
+            size: 256,
+            depth: 1,
+            frequency: 4,
+            octaves: 4,
+            persistence: 0.5,
+            seed: 0,
+        }
+    }
+}
+
+/// Matches the push constants in `noise_comp.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Generate {
+    size: [u32; 3],
+    kind: u32,
+    frequency: u32,
+    octaves: u32,
+    persistence: f32,
+    seed: u32,
+}
+
+impl Generate {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+}
+
+/// A tileable noise texture generated by a compute shader. Each channel holds the noise starting an octave
+/// higher than the one before, so the red channel has the broadest features and alpha the finest. Read by
+/// shaders at `SHADER_READ_ONLY_OPTIMAL` through `view`, as a `sampler2D` or a `sampler3D` depending on its depth.
+pub struct Texture {
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    pub view: vk::ImageView,
+    pub extent: vk::Extent3D,
+}
+
+impl Texture {
+    /// Generates the texture, waiting for the GPU to finish. Fails if any of the settings' sizes are zero, or the
+    /// frequency of the finest octave overflows.
+    pub fn generate(
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        settings: &Settings,
+    ) -> Result<Self, String> {
+        if settings.size == 0 || settings.depth == 0 {
+            return Err("Noise textures need at least one texel".to_string());
+        }
+        if settings.frequency == 0 || settings.octaves == 0 {
+            return Err("Noise needs a frequency and at least one octave".to_string());
+        }
+        // The alpha channel starts three octaves above the red
+        let finest = settings.octaves + 2;
+        if finest > settings.frequency.leading_zeros() {
+            return Err(format!(
+                "{} octaves of noise overflow from a frequency of {}",
+                settings.octaves, settings.frequency
+            ));
+        }
+
+        let extent = vk::Extent3D {
+            width: settings.size,
+            height: settings.size,
+            depth: settings.depth,
+        };
+        let texel_count = (extent.width * extent.height * extent.depth) as vk::DeviceSize;
+        let (texel_buffer, texel_memory) = HelloTriangleApplication::create_buffer(
+            device,
+            texel_count * size_of::<u32>() as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            device_memory_properties,
+        );
+        let (image, memory) = Self::create_image(device, extent, device_memory_properties);
+
+        let bindings = [vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .build()];
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+                    None,
+                )
+                .expect("Noise descriptor set layout")
+        };
+        let pool_sizes = [vk::DescriptorPoolSize::builder()
+            .ty(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(1)
+            .build()];
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::builder()
+                        .pool_sizes(&pool_sizes)
+                        .max_sets(1),
+                    None,
+                )
+                .expect("Noise descriptor pool")
+        };
+        let set_layouts = [descriptor_set_layout];
+        let descriptor_set = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::builder()
+                        .descriptor_pool(descriptor_pool)
+                        .set_layouts(&set_layouts),
+                )
+                .expect("Noise descriptor set")[0]
+        };
+        let buffer_info = [vk::DescriptorBufferInfo::builder()
+            .buffer(texel_buffer)
+            .offset(0)
+            .range(vk::WHOLE_SIZE)
+            .build()];
+        let writes = [vk::WriteDescriptorSet::builder()
+            .dst_set(descriptor_set)
+            .dst_binding(0)
+            .dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .buffer_info(&buffer_info)
+            .build()];
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(size_of::<Generate>() as u32)
+            .build()];
+        let layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::builder()
+                        .set_layouts(&set_layouts)
+                        .push_constant_ranges(&push_constant_ranges),
+                    None,
+                )
+                .expect("Noise pipeline layout")
+        };
+        let shader_path = Path::new(env!("OUT_DIR")).join("noise_comp.spv");
+        let pipeline = postprocess::create_compute_pipeline(
+            device,
+            layout,
+            &util::read_shader_code(&shader_path),
+        );
+
+        let generate = Generate {
+            size: [extent.width, extent.height, extent.depth],
+            kind: settings.kind as u32,
+            frequency: settings.frequency,
+            octaves: settings.octaves,
+            persistence: settings.persistence,
+            seed: settings.seed,
+        };
+        let groups = |count: u32| count.div_ceil(WORKGROUP_SIZE);
+        let range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let image_barrier = |old, new, src_access, dst_access| {
+            vk::ImageMemoryBarrier::builder()
+                .old_layout(old)
+                .new_layout(new)
+                .src_access_mask(src_access)
+                .dst_access_mask(dst_access)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(range)
+                .build()
+        };
+        let region = vk::BufferImageCopy::builder()
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .image_extent(extent)
+            .build();
+
+        let command_buffer = begin_single_time_commands(device, command_pool);
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline);
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                generate.as_bytes(),
+            );
+            device.cmd_dispatch(
+                command_buffer,
+                groups(extent.width),
+                groups(extent.height),
+                groups(extent.depth),
+            );
+
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[vk::MemoryBarrier::builder()
+                    .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                    .build()],
+                &[],
+                &[image_barrier(
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::AccessFlags::empty(),
+                    vk::AccessFlags::TRANSFER_WRITE,
+                )],
+            );
+            device.cmd_copy_buffer_to_image(
+                command_buffer,
+                texel_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region],
+            );
+            // Noise may be read by any shader, not only materials' fragment shaders
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::ALL_GRAPHICS | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[image_barrier(
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    vk::AccessFlags::SHADER_READ,
+                )],
+            );
+        }
+        end_single_time_commands(device, command_pool, command_buffer, queue);
+
+        unsafe {
+            device.destroy_pipeline(pipeline, None);
+            device.destroy_pipeline_layout(layout, None);
+            device.destroy_descriptor_pool(descriptor_pool, None);
+            device.destroy_descriptor_set_layout(descriptor_set_layout, None);
+            device.destroy_buffer(texel_buffer, None);
+            device.free_memory(texel_memory, None);
+        }
+
+        let view_type = if extent.depth > 1 {
+            vk::ImageViewType::TYPE_3D
+        } else {
+            vk::ImageViewType::TYPE_2D
+        };
+        let view = unsafe {
+            device
+                .create_image_view(
+                    &vk::ImageViewCreateInfo::builder()
+                        .image(image)
+                        .view_type(view_type)
+                        .format(FORMAT)
+                        .subresource_range(range),
+                    None,
+                )
+                .expect("Noise image view")
+        };
+
+        Ok(Self {
+            image,
+            memory,
+            view,
+            extent,
+        })
+    }
+
+    /// Whether the texture is read as a `sampler3D` rather than a `sampler2D`
+    pub fn is_3d(&self) -> bool {
+        self.extent.depth > 1
+    }
+
+    fn create_image(
+        device: &ash::Device,
+        extent: vk::Extent3D,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    ) -> (vk::Image, vk::DeviceMemory) {
+        let image_type = if extent.depth > 1 {
+            vk::ImageType::TYPE_3D
+        } else {
+            vk::ImageType::TYPE_2D
+        };
+        let image = unsafe {
+            device
+                .create_image(
+                    &vk::ImageCreateInfo::builder()
+                        .image_type(image_type)
+                        .extent(extent)
+                        .mip_levels(1)
+                        .array_layers(1)
+                        .format(FORMAT)
+                        .tiling(vk::ImageTiling::OPTIMAL)
+                        .initial_layout(vk::ImageLayout::UNDEFINED)
+                        .usage(vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED)
+                        .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                        .samples(vk::SampleCountFlags::TYPE_1),
+                    None,
+                )
+                .expect("Noise image")
+        };
+        let requirements = unsafe { device.get_image_memory_requirements(image) };
+        let memory = unsafe {
+            let memory = device
+                .allocate_memory(
+                    &vk::MemoryAllocateInfo::builder()
+                        .allocation_size(requirements.size)
+                        .memory_type_index(HelloTriangleApplication::find_memory_type(
+                            requirements.memory_type_bits,
+                            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                            device_memory_properties,
+                        )),
+                    None,
+                )
+                .expect("Noise image memory");
+            device
+                .bind_image_memory(image, memory, 0)
+                .expect("Binding noise image memory");
+            memory
+        };
+        (image, memory)
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_image_view(self.view, None);
+            device.destroy_image(self.image, None);
+            device.free_memory(self.memory, None);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use cgmath::{Matrix4, Point3, Vector4};
+
+use crate::{bounds, raycast};
+
+/// Size of the depth buffer occluders are drawn into. It covers the whole view whatever its aspect ratio.
+const WIDTH: usize = 256;
+const HEIGHT: usize = 144;
+
+/// How software occlusion culling picks its occluders.
+#[derive(Clone, Copy, Debug)]
+pub struct Settings {
+    /// Draws whose bounding sphere is at least this fraction of the scene's can occlude others
+    pub min_size: f32,
+    /// Only the largest occluders are drawn, since small ones rarely hide much
+    pub max_occluders: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            min_size: 0.1,
+            max_occluders: 16,
+        }
+    }
+}
+
+/// A coarse depth buffer drawn on the CPU from the triangles of the scene's largest opaque draws, which the
+/// bounds of the rest are tested against before they are recorded. Only pixels whose centre an occluder covers
+/// are written, and a box is hidden only when every pixel it covers has a nearer occluder, so draws are never
+/// wrongly culled, only sometimes drawn when they are hidden.
+///
+/// Depth is normalized device depth, which is linear across the screen in either projection.
+pub struct DepthBuffer {
+    depth: Vec<f32>,
+}
+
+impl DepthBuffer {
+    /// Draws the occluders' colliders, each placed in world space by its transform, as seen through `clip`.
+    /// The depth is drawn into `storage`, such as the last frame's from `into_storage`, so that it isn't
+    /// allocated every frame.
+    pub fn rasterize<'a>(
+        clip: &Matrix4<f32>,
+        occluders: impl IntoIterator<Item = (&'a raycast::Bvh, Matrix4<f32>)>,
+        mut storage: Vec<f32>,
+    ) -> Self {
+        storage.clear();
+        storage.resize(WIDTH * HEIGHT, f32::INFINITY);
+        let mut buffer = Self { depth: storage };
+        for (collider, transform) in occluders {
+            let clip = clip * transform;
+            for triangle in collider.triangles() {
+                let [a, b, c] = triangle.vertices.map(|vertex| to_screen(&clip, vertex));
+                // Triangles crossing the near plane are left out rather than clipped
+                if let (Some(a), Some(b), Some(c)) = (a, b, c) {
+                    buffer.draw_triangle(a, b, c);
+                }
+            }
+        }
+        buffer
+    }
+
+    /// The depth's storage, for drawing the next frame's into
+    pub fn into_storage(self) -> Vec<f32> {
+        self.depth
+    }
+
+    /// Writes the triangle's depth, given in pixels and normalized device depth, to the pixels whose centres
+    /// are inside it
+    fn draw_triangle(&mut self, a: Point3<f32>, b: Point3<f32>, c: Point3<f32>) {
+        let area = edge(a, b, c);
+        if area.abs() < f32::EPSILON {
+            return;
+        }
+        let (min_x, max_x) = pixel_range(a.x.min(b.x).min(c.x), a.x.max(b.x).max(c.x), WIDTH);
+        let (min_y, max_y) = pixel_range(a.y.min(b.y).min(c.y), a.y.max(b.y).max(c.y), HEIGHT);
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let p = Point3::new(x as f32 + 0.5, y as f32 + 0.5, 0.0);
+                // Barycentric weights, all the same sign as the area inside the triangle whichever way it winds
+                let (wa, wb, wc) = (
+                    edge(b, c, p) / area,
+                    edge(c, a, p) / area,
+                    edge(a, b, p) / area,
+                );
+                if wa < 0.0 || wb < 0.0 || wc < 0.0 {
+                    continue;
+                }
+                let depth = wa * a.z + wb * b.z + wc * c.z;
+                let pixel = &mut self.depth[y * WIDTH + x];
+                *pixel = pixel.min(depth);
+            }
+        }
+    }
+
+    /// Whether the box, in world space, is certainly behind the occluders seen through `clip`
+    pub fn occludes(&self, clip: &Matrix4<f32>, aabb: &bounds::Aabb) -> bool {
+        let mut corners = Vec::with_capacity(8);
+        for corner in aabb.corners() {
+            match to_screen(clip, corner) {
+                Some(corner) => corners.push(corner),
+                // Boxes reaching past the near plane could cover the whole view
+                None => return false,
+            }
+        }
+        let nearest = corners
+            .iter()
+            .map(|corner| corner.z)
+            .fold(f32::INFINITY, f32::min);
+        let (min_x, max_x) = pixel_range(
+            corners
+                .iter()
+                .map(|corner| corner.x)
+                .fold(f32::INFINITY, f32::min)
+                - 0.5,
+            corners
+                .iter()
+                .map(|corner| corner.x)
+                .fold(f32::NEG_INFINITY, f32::max)
+                + 0.5,
+            WIDTH,
+        );
+        let (min_y, max_y) = pixel_range(
+            corners
+                .iter()
+                .map(|corner| corner.y)
+                .fold(f32::INFINITY, f32::min)
+                - 0.5,
+            corners
+                .iter()
+                .map(|corner| corner.y)
+                .fold(f32::NEG_INFINITY, f32::max)
+                + 0.5,
+            HEIGHT,
+        );
+        if min_x >= max_x || min_y >= max_y {
+            return false;
+        }
+        (min_y..max_y).all(|y| (min_x..max_x).all(|x| self.depth[y * WIDTH + x] < nearest))
+    }
+}
+
+/// The point in the depth buffer's pixels, with its normalized device depth, or `None` if it is behind the near
+/// plane
+fn to_screen(clip: &Matrix4<f32>, point: Point3<f32>) -> Option<Point3<f32>> {
+    let clip_point: Vector4<f32> = clip * point.to_homogeneous();
+    if clip_point.w <= 0.0 || clip_point.z < -clip_point.w {
+        return None;
+    }
+    let ndc = clip_point.truncate() / clip_point.w;
+    Some(Point3::new(
+        (ndc.x * 0.5 + 0.5) * WIDTH as f32,
+        (ndc.y * 0.5 + 0.5) * HEIGHT as f32,
+        ndc.z,
+    ))
+}
+
+/// Twice the signed area of the triangle from `a` to `b` to `p`, in the plane of the screen
+fn edge(a: Point3<f32>, b: Point3<f32>, p: Point3<f32>) -> f32 {
+    (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+}
+
+/// The pixels whose centres may lie between `min` and `max`, clamped to the buffer
+fn pixel_range(min: f32, max: f32, size: usize) -> (usize, usize) {
+    let min = (min - 0.5).ceil().clamp(0.0, size as f32) as usize;
+    let max = ((max - 0.5).floor() + 1.0).clamp(0.0, size as f32) as usize;
+    (min, max)
+}
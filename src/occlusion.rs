@@ -0,0 +1,66 @@
+use rayon::prelude::*;
+
+use crate::lightmap::{self, Placed, Scene};
+use crate::{batch, raycast};
+
+/// How ambient occlusion is baked.
+#[derive(Clone, Copy, Debug)]
+pub struct Settings {
+    /// Rays cast over the hemisphere above each vertex
+    pub samples: u32,
+    /// Surfaces further away than this don't occlude. Lower values only darken creases and contact points,
+    /// higher ones darken whole areas near other surfaces.
+    pub radius: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            samples: 128,
+            radius: 0.25,
+        }
+    }
+}
+
+/// Bakes how much of the ambient light reaches each vertex of the meshes into their occlusion attribute, which
+/// the material's `occlusion_strength` then darkens them with. Rays are traced against all of the meshes and
+/// `occluders`. Vertices only record occlusion at their own position, so meshes need enough vertices for the
+/// occlusion to show where it changes. Baking is done on the CPU across every core.
+pub fn bake(
+    meshes: &mut [batch::StaticMesh],
+    occluders: &[batch::StaticMesh],
+    settings: &Settings,
+) {
+    let placed: Vec<Placed> = meshes.iter().map(Placed::new).collect();
+    let scene = Scene::new(&placed, meshes, occluders);
+
+    let occlusion: Vec<Vec<f32>> = placed
+        .par_iter()
+        .enumerate()
+        .map(|(mesh, placed)| {
+            placed
+                .positions
+                .iter()
+                .zip(placed.normals.iter())
+                .enumerate()
+                .map(|(vertex, (&position, &normal))| {
+                    let origin = position + normal * lightmap::RAY_OFFSET;
+                    let seed = (mesh as u32).wrapping_mul(7919) ^ vertex as u32;
+                    let open = lightmap::cosine_directions(normal, settings.samples, seed)
+                        .filter(|&direction| {
+                            let ray = raycast::Ray::new(origin, direction);
+                            scene
+                                .hit(&ray)
+                                .is_none_or(|hit| hit.distance > settings.radius)
+                        })
+                        .count();
+                    open as f32 / settings.samples.max(1) as f32
+                })
+                .collect()
+        })
+        .collect();
+
+    for (static_mesh, occlusion) in meshes.iter_mut().zip(occlusion) {
+        static_mesh.mesh.occlusion = Some(occlusion);
+    }
+}
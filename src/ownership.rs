@@ -0,0 +1,232 @@
+use ash::vk;
+
+/// Moves a resource with exclusive sharing from one queue family to another, so that it needn't be created with
+/// concurrent sharing, which keeps drivers from compressing images and caching buffers for just one family. The
+/// family giving the resource up records a release barrier, the family taking it records the matching acquire,
+/// and a semaphore orders the acquire's submission after the release's. Layout changes made by the transfer have
+/// to be the same in both barriers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Transfer {
+    pub source: u32,
+    pub destination: u32,
+}
+
+impl Transfer {
+    /// None when the families are the same, since a family has nothing to transfer to itself
+    pub fn new(source: u32, destination: u32) -> Option<Self> {
+        (source != destination).then_some(Self {
+            source,
+            destination,
+        })
+    }
+
+    /// Releases the buffer after the source family's `access` to it
+    pub fn release_buffer(
+        &self,
+        buffer: vk::Buffer,
+        access: vk::AccessFlags,
+    ) -> vk::BufferMemoryBarrier {
+        self.buffer_barrier(buffer).src_access_mask(access).build()
+    }
+
+    /// Acquires the buffer before the destination family's `access` to it
+    pub fn acquire_buffer(
+        &self,
+        buffer: vk::Buffer,
+        access: vk::AccessFlags,
+    ) -> vk::BufferMemoryBarrier {
+        self.buffer_barrier(buffer).dst_access_mask(access).build()
+    }
+
+    /// Releases the first level and layer of a colour image after the source family's `access` to it
+    pub fn release_image(
+        &self,
+        image: vk::Image,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+        access: vk::AccessFlags,
+    ) -> vk::ImageMemoryBarrier {
+        self.image_barrier(image, old_layout, new_layout)
+            .src_access_mask(access)
+            .build()
+    }
+
+    /// Acquires the first level and layer of a colour image before the destination family's `access` to it
+    pub fn acquire_image(
+        &self,
+        image: vk::Image,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+        access: vk::AccessFlags,
+    ) -> vk::ImageMemoryBarrier {
+        self.image_barrier(image, old_layout, new_layout)
+            .dst_access_mask(access)
+            .build()
+    }
+
+    fn buffer_barrier(&self, buffer: vk::Buffer) -> vk::BufferMemoryBarrierBuilder<'static> {
+        vk::BufferMemoryBarrier::builder()
+            .src_queue_family_index(self.source)
+            .dst_queue_family_index(self.destination)
+            .buffer(buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE)
+    }
+
+    fn image_barrier(
+        &self,
+        image: vk::Image,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+    ) -> vk::ImageMemoryBarrierBuilder<'static> {
+        vk::ImageMemoryBarrier::builder()
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_queue_family_index(self.source)
+            .dst_queue_family_index(self.destination)
+            .image(image)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+    }
+}
+
+/// Records the barriers between the stages
+pub fn record(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    src_stage: vk::PipelineStageFlags,
+    dst_stage: vk::PipelineStageFlags,
+    buffer_barriers: &[vk::BufferMemoryBarrier],
+    image_barriers: &[vk::ImageMemoryBarrier],
+) {
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            src_stage,
+            dst_stage,
+            vk::DependencyFlags::empty(),
+            &[],
+            buffer_barriers,
+            image_barriers,
+        )
+    };
+}
+
+/// Hands swapchain images from the graphics family to a separate present family. The frame's command buffer
+/// releases its image once everything has drawn to it, and a command buffer submitted to the present queue
+/// after the frame's graphics work acquires it before it is presented. Images come back from the presentation
+/// engine still owned by the present family, and without a release from it and an acquire by the graphics family
+/// their contents are undefined to the graphics family. The scene's render pass discards them instead, starting
+/// from an undefined layout, which needs no transfer back, so the scene's colour can't be preserved with a
+/// separate present family.
+pub struct PresentAcquire {
+    transfer: Transfer,
+    command_pool: vk::CommandPool,
+    /// One for each swapchain image, recorded once since each only ever acquires its image
+    command_buffers: Vec<vk::CommandBuffer>,
+}
+
+impl PresentAcquire {
+    /// `transfer` is from the graphics family to the present family
+    pub fn new(device: &ash::Device, transfer: Transfer, images: &[vk::Image]) -> Self {
+        let command_pool = unsafe {
+            device
+                .create_command_pool(
+                    &vk::CommandPoolCreateInfo::builder().queue_family_index(transfer.destination),
+                    None,
+                )
+                .expect("Present command pool")
+        };
+        let mut acquire = Self {
+            transfer,
+            command_pool,
+            command_buffers: Vec::new(),
+        };
+        acquire.record(device, images);
+        acquire
+    }
+
+    /// Records the acquires again for a recreated swapchain's images. Must only be called once the present
+    /// queue has finished with the old ones.
+    pub fn record(&mut self, device: &ash::Device, images: &[vk::Image]) {
+        unsafe {
+            if !self.command_buffers.is_empty() {
+                device.free_command_buffers(self.command_pool, &self.command_buffers);
+            }
+            self.command_buffers = device
+                .allocate_command_buffers(
+                    &vk::CommandBufferAllocateInfo::builder()
+                        .command_pool(self.command_pool)
+                        .level(vk::CommandBufferLevel::PRIMARY)
+                        .command_buffer_count(images.len() as u32),
+                )
+                .expect("Present command buffers");
+        }
+        for (&command_buffer, &image) in self.command_buffers.iter().zip(images) {
+            unsafe {
+                device
+                    .begin_command_buffer(command_buffer, &vk::CommandBufferBeginInfo::builder())
+                    .expect("Recording present acquire");
+            }
+            // From the stages the submission waits for the graphics work in. Presenting reads the image outside of any
+            // pipeline stage, after the semaphore it waits on, so nothing has to wait for the acquire here.
+            let barrier = self.transfer.acquire_image(
+                image,
+                vk::ImageLayout::PRESENT_SRC_KHR,
+                vk::ImageLayout::PRESENT_SRC_KHR,
+                vk::AccessFlags::empty(),
+            );
+            record(
+                device,
+                command_buffer,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                &[],
+                &[barrier],
+            );
+            unsafe {
+                device
+                    .end_command_buffer(command_buffer)
+                    .expect("Ending present acquire");
+            }
+        }
+    }
+
+    /// Records the release of the image at the end of the frame's command buffer, after every pass that writes
+    /// to it
+    pub fn release(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+    ) {
+        let barrier = self.transfer.release_image(
+            image,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+            vk::AccessFlags::MEMORY_WRITE,
+        );
+        record(
+            device,
+            command_buffer,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            &[],
+            &[barrier],
+        );
+    }
+
+    /// The image's acquire, to submit to the present queue once the frame's graphics work has released it
+    pub fn command_buffer(&self, image_index: usize) -> vk::CommandBuffer {
+        self.command_buffers[image_index]
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe { device.destroy_command_pool(self.command_pool, None) };
+    }
+}
@@ -0,0 +1,359 @@
+use std::mem::size_of;
+use std::path::Path;
+use std::time::Instant;
+
+use ash::vk;
+
+use crate::{billboard, ownership, postprocess, util, HelloTriangleApplication};
+
+pub const PARTICLE_COUNT: u32 = 2048;
+/// Particles updated per workgroup, matching `local_size_x` in `particles_comp.glsl`
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Matches `Particle` in `particles_comp.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Particle {
+    /// w is the particle's age in seconds
+    position: [f32; 4],
+    /// w is how long the particle lives for
+    velocity: [f32; 4],
+}
+
+/// Matches the push constants in `particles_comp.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Step {
+    delta_time: f32,
+    time: f32,
+    /// Non-zero on the first update, which spawns every particle
+    reset: u32,
+}
+
+impl Step {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+}
+
+/// A fountain of particles simulated in a compute shader, which writes them out as billboards for the
+/// graphics queue to draw. Updates are submitted to the compute queue, which is a dedicated compute family
+/// when the device has one so they overlap the graphics work of earlier frames. Each frame in flight has its
+/// own billboard buffer, which the frame's graphics work waits for through the frame's submission. The
+/// buffers belong to one family at a time: each update releases its buffer to the graphics family, which
+/// acquires it in the frame's command buffer. The compute family takes the buffer back without an acquire,
+/// since every update writes the whole of it.
+pub struct Particles {
+    queue: vk::Queue,
+    command_pool: vk::CommandPool,
+    command_buffers: Vec<vk::CommandBuffer>,
+    /// Only touched by the compute queue
+    state_buffer: vk::Buffer,
+    state_buffer_memory: vk::DeviceMemory,
+    billboard_buffers: Vec<(vk::Buffer, vk::DeviceMemory)>,
+    /// From the compute family to the graphics family, when they differ
+    transfer: Option<ownership::Transfer>,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+    start_time: Instant,
+    last_update: Option<Instant>,
+}
+
+impl Particles {
+    /// `queue` belongs to `compute_family`, and the billboard buffers are handed to `graphics_family`.
+    pub fn new(
+        device: &ash::Device,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        queue: vk::Queue,
+        compute_family: u32,
+        graphics_family: u32,
+        frames_in_flight: usize,
+    ) -> Self {
+        let command_pool = unsafe {
+            device
+                .create_command_pool(
+                    &vk::CommandPoolCreateInfo::builder()
+                        .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
+                        .queue_family_index(compute_family),
+                    None,
+                )
+                .expect("Particle command pool")
+        };
+        let command_buffers = unsafe {
+            device
+                .allocate_command_buffers(
+                    &vk::CommandBufferAllocateInfo::builder()
+                        .command_pool(command_pool)
+                        .level(vk::CommandBufferLevel::PRIMARY)
+                        .command_buffer_count(frames_in_flight as u32),
+                )
+                .expect("Particle command buffers")
+        };
+        let (state_buffer, state_buffer_memory) = HelloTriangleApplication::create_buffer(
+            device,
+            (PARTICLE_COUNT as usize * size_of::<Particle>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            device_memory_properties,
+        );
+        // Written on the compute queue and read on the graphics queue
+        let billboard_buffers: Vec<(vk::Buffer, vk::DeviceMemory)> = (0..frames_in_flight)
+            .map(|_| {
+                HelloTriangleApplication::create_buffer(
+                    device,
+                    (PARTICLE_COUNT as usize * size_of::<billboard::Billboard>()) as vk::DeviceSize,
+                    vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::VERTEX_BUFFER,
+                    vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                    device_memory_properties,
+                )
+            })
+            .collect();
+
+        let bindings: Vec<vk::DescriptorSetLayoutBinding> = (0..2)
+            .map(|binding| {
+                vk::DescriptorSetLayoutBinding::builder()
+                    .binding(binding)
+                    .descriptor_count(1)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                    .build()
+            })
+            .collect();
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+                    None,
+                )
+                .expect("Particle descriptor set layout")
+        };
+        let pool_sizes = [vk::DescriptorPoolSize::builder()
+            .ty(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(2 * frames_in_flight as u32)
+            .build()];
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::builder()
+                        .pool_sizes(&pool_sizes)
+                        .max_sets(frames_in_flight as u32),
+                    None,
+                )
+                .expect("Particle descriptor pool")
+        };
+        let set_layouts = vec![descriptor_set_layout; frames_in_flight];
+        let descriptor_sets = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::builder()
+                        .descriptor_pool(descriptor_pool)
+                        .set_layouts(&set_layouts),
+                )
+                .expect("Particle descriptor sets")
+        };
+        for (&set, &(billboard_buffer, _)) in descriptor_sets.iter().zip(billboard_buffers.iter()) {
+            let buffer_infos = [state_buffer, billboard_buffer].map(|buffer| {
+                [vk::DescriptorBufferInfo::builder()
+                    .buffer(buffer)
+                    .offset(0)
+                    .range(vk::WHOLE_SIZE)
+                    .build()]
+            });
+            let writes: Vec<vk::WriteDescriptorSet> = buffer_infos
+                .iter()
+                .enumerate()
+                .map(|(binding, buffer_info)| {
+                    vk::WriteDescriptorSet::builder()
+                        .dst_set(set)
+                        .dst_binding(binding as u32)
+                        .dst_array_element(0)
+                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                        .buffer_info(buffer_info)
+                        .build()
+                })
+                .collect();
+            unsafe { device.update_descriptor_sets(&writes, &[]) };
+        }
+
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(size_of::<Step>() as u32)
+            .build()];
+        let set_layouts = [descriptor_set_layout];
+        let layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::builder()
+                        .set_layouts(&set_layouts)
+                        .push_constant_ranges(&push_constant_ranges),
+                    None,
+                )
+                .expect("Particle pipeline layout")
+        };
+        let shader_path = Path::new(env!("OUT_DIR")).join("particles_comp.spv");
+        let pipeline = postprocess::create_compute_pipeline(
+            device,
+            layout,
+            &util::read_shader_code(&shader_path),
+        );
+
+        Self {
+            queue,
+            command_pool,
+            command_buffers,
+            state_buffer,
+            state_buffer_memory,
+            billboard_buffers,
+            transfer: ownership::Transfer::new(compute_family, graphics_family),
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_sets,
+            layout,
+            pipeline,
+            start_time: Instant::now(),
+            last_update: None,
+        }
+    }
+
+    /// Records the frame's update, returning the command buffer to submit to `queue` ahead of the frame's
+    /// graphics work. Must only be called once the frame's previous graphics submission has finished.
+    pub fn update(&mut self, device: &ash::Device, frame: usize) -> vk::CommandBuffer {
+        let now = Instant::now();
+        let step = Step {
+            // Long pauses, such as while the window is being dragged, would throw every particle at once
+            delta_time: self
+                .last_update
+                .map_or(0.0, |last| (now - last).as_secs_f32().min(0.1)),
+            time: (now - self.start_time).as_secs_f32(),
+            reset: self.last_update.is_none() as u32,
+        };
+        self.last_update = Some(now);
+
+        let command_buffer = self.command_buffers[frame];
+        unsafe {
+            device
+                .begin_command_buffer(
+                    command_buffer,
+                    &vk::CommandBufferBeginInfo::builder()
+                        .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+                )
+                .expect("Recording particle update");
+
+            // The previous update on this queue wrote the state this one advances
+            let barrier = vk::BufferMemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .buffer(self.state_buffer)
+                .offset(0)
+                .size(vk::WHOLE_SIZE)
+                .build();
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[barrier],
+                &[],
+            );
+
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.layout,
+                0,
+                &[self.descriptor_sets[frame]],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                self.layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                step.as_bytes(),
+            );
+            device.cmd_dispatch(
+                command_buffer,
+                PARTICLE_COUNT.div_ceil(WORKGROUP_SIZE),
+                1,
+                1,
+            );
+            if let Some(transfer) = self.transfer {
+                let release = transfer.release_buffer(
+                    self.billboard_buffers[frame].0,
+                    vk::AccessFlags::SHADER_WRITE,
+                );
+                ownership::record(
+                    device,
+                    command_buffer,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    &[release],
+                    &[],
+                );
+            }
+
+            device
+                .end_command_buffer(command_buffer)
+                .expect("Ending particle update");
+        }
+        command_buffer
+    }
+
+    /// The compute queue the updates are submitted to
+    pub fn queue(&self) -> vk::Queue {
+        self.queue
+    }
+
+    /// Records the graphics family's acquire of the frame's billboards, matching the release its update
+    /// recorded, before the render pass that draws them. Does nothing when the families are the same.
+    pub fn acquire(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, frame: usize) {
+        if let Some(transfer) = self.transfer {
+            let acquire = transfer.acquire_buffer(
+                self.billboard_buffers[frame].0,
+                vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+            );
+            ownership::record(
+                device,
+                command_buffer,
+                // Among the stages the frame's submission waits for the update in
+                vk::PipelineStageFlags::VERTEX_INPUT,
+                vk::PipelineStageFlags::VERTEX_INPUT,
+                &[acquire],
+                &[],
+            );
+        }
+    }
+
+    /// The buffer the frame's billboards are written to, drawn instanced with the billboard pipeline.
+    pub fn billboard_buffer(&self, frame: usize) -> vk::Buffer {
+        self.billboard_buffers[frame].0
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            for &(buffer, memory) in self.billboard_buffers.iter() {
+                device.destroy_buffer(buffer, None);
+                device.free_memory(memory, None);
+            }
+            device.destroy_buffer(self.state_buffer, None);
+            device.free_memory(self.state_buffer_memory, None);
+            device.destroy_command_pool(self.command_pool, None);
+        }
+    }
+}
@@ -0,0 +1,363 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use ash::vk;
+use cgmath::{Matrix4, Point3};
+use rapier3d::prelude::*;
+
+use crate::{bounds, draw, material, mesh, pipeline, raycast, HelloTriangleApplication, Vertex};
+
+/// Fixed simulation step. Frame time is accumulated and simulated in steps of this length so the simulation
+/// behaves the same at any frame rate.
+const TIME_STEP: f32 = 1.0 / 60.0;
+/// Limits how far the simulation catches up after a long frame such as a window drag
+const MAX_STEPS_PER_FRAME: u32 = 5;
+
+/// Debug drawn collider outlines are scaled up slightly to keep them from z-fighting with the surfaces they
+/// outline
+const DEBUG_OUTLINE_SCALE: f32 = 1.01;
+
+/// A cuboid collider and the draws that follow it.
+struct BoxCollider {
+    collider: ColliderHandle,
+    half_extents: [f32; 3],
+    /// The draw showing the cuboid. The ground is drawn by a separate mesh so it has none.
+    draw: Option<usize>,
+    /// The draw outlining the collider
+    debug_draw: usize,
+}
+
+/// A rigid body simulation driving the transforms of the draws attached to it. Every collider is a cuboid drawn
+/// with a unit cube mesh scaled to the collider's size.
+pub struct Simulation {
+    gravity: Vector<Real>,
+    integration_parameters: IntegrationParameters,
+    physics_pipeline: PhysicsPipeline,
+    islands: IslandManager,
+    broad_phase: BroadPhase,
+    narrow_phase: NarrowPhase,
+    bodies: RigidBodySet,
+    colliders: ColliderSet,
+    impulse_joints: ImpulseJointSet,
+    multibody_joints: MultibodyJointSet,
+    ccd_solver: CCDSolver,
+    cuboids: Vec<BoxCollider>,
+    last_update: Instant,
+    unsimulated: f32,
+}
+
+impl Simulation {
+    /// An empty simulation with gravity pulling down the Z axis, which is up in the renderer's scenes.
+    fn new() -> Self {
+        Self {
+            gravity: vector![0.0, 0.0, -9.81],
+            integration_parameters: IntegrationParameters {
+                dt: TIME_STEP,
+                ..IntegrationParameters::default()
+            },
+            physics_pipeline: PhysicsPipeline::new(),
+            islands: IslandManager::new(),
+            broad_phase: BroadPhase::new(),
+            narrow_phase: NarrowPhase::new(),
+            bodies: RigidBodySet::new(),
+            colliders: ColliderSet::new(),
+            impulse_joints: ImpulseJointSet::new(),
+            multibody_joints: MultibodyJointSet::new(),
+            ccd_solver: CCDSolver::new(),
+            cuboids: Vec::new(),
+            last_update: Instant::now(),
+            unsimulated: 0.0,
+        }
+    }
+
+    /// Adds a collider that never moves. `draw` is the index of the draw showing it, if it is to be moved to
+    /// match the collider.
+    pub fn add_fixed_cuboid(
+        &mut self,
+        center: [f32; 3],
+        half_extents: [f32; 3],
+        draw: Option<usize>,
+        debug_draw: usize,
+    ) {
+        let [hx, hy, hz] = half_extents;
+        let collider = ColliderBuilder::cuboid(hx, hy, hz)
+            .translation(center.into())
+            .build();
+        let collider = self.colliders.insert(collider);
+        self.cuboids.push(BoxCollider {
+            collider,
+            half_extents,
+            draw,
+            debug_draw,
+        });
+    }
+
+    /// Adds a box that falls under gravity. `rotation` is an axis-angle rotation in radians.
+    pub fn add_dynamic_cuboid(
+        &mut self,
+        center: [f32; 3],
+        rotation: [f32; 3],
+        half_extents: [f32; 3],
+        draw: usize,
+        debug_draw: usize,
+    ) {
+        let body = RigidBodyBuilder::dynamic()
+            .translation(center.into())
+            .rotation(rotation.into())
+            .build();
+        let body = self.bodies.insert(body);
+
+        let [hx, hy, hz] = half_extents;
+        let collider = ColliderBuilder::cuboid(hx, hy, hz).restitution(0.3).build();
+        let collider = self
+            .colliders
+            .insert_with_parent(collider, body, &mut self.bodies);
+        self.cuboids.push(BoxCollider {
+            collider,
+            half_extents,
+            draw: Some(draw),
+            debug_draw,
+        });
+    }
+
+    /// Advances the simulation by the time since the last update, in fixed steps.
+    pub fn update(&mut self) {
+        let now = Instant::now();
+        self.unsimulated += (now - self.last_update).as_secs_f32();
+        self.last_update = now;
+
+        let mut steps = 0;
+        while self.unsimulated >= TIME_STEP && steps < MAX_STEPS_PER_FRAME {
+            self.physics_pipeline.step(
+                &self.gravity,
+                &self.integration_parameters,
+                &mut self.islands,
+                &mut self.broad_phase,
+                &mut self.narrow_phase,
+                &mut self.bodies,
+                &mut self.colliders,
+                &mut self.impulse_joints,
+                &mut self.multibody_joints,
+                &mut self.ccd_solver,
+                &(),
+                &(),
+            );
+            self.unsimulated -= TIME_STEP;
+            steps += 1;
+        }
+        if steps == MAX_STEPS_PER_FRAME {
+            self.unsimulated = 0.0;
+        }
+    }
+
+    /// Moves the draws attached to each collider to the collider's current position.
+    pub fn sync(&self, draws: &mut [draw::Draw]) {
+        for cuboid in self.cuboids.iter() {
+            let columns: [[f32; 4]; 4] = self.colliders[cuboid.collider]
+                .position()
+                .to_homogeneous()
+                .into();
+            let position = Matrix4::from(columns);
+            let [hx, hy, hz] = cuboid.half_extents;
+
+            if let Some(draw) = cuboid.draw {
+                draws[draw].transform = position * Matrix4::from_nonuniform_scale(hx, hy, hz);
+            }
+            draws[cuboid.debug_draw].transform = position
+                * Matrix4::from_nonuniform_scale(
+                    hx * DEBUG_OUTLINE_SCALE,
+                    hy * DEBUG_OUTLINE_SCALE,
+                    hz * DEBUG_OUTLINE_SCALE,
+                );
+        }
+    }
+}
+
+/// A cube from -1 to 1 on each axis with outward facing normals and no texture coordinates.
+fn unit_cube(color: [f32; 3]) -> mesh::MeshData {
+    // Each face's normal followed by two tangents whose cross product is the normal, so that corners visited
+    // in the order below wind counter-clockwise around the normal
+    let faces: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+        ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+        ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+        ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0]),
+        ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+        ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ([0.0, 0.0, -1.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]),
+    ];
+    let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut indices = Vec::new();
+    for (normal, u, v) in faces.iter() {
+        let first = positions.len() as u16;
+        for (a, b) in corners.iter() {
+            positions.push([
+                normal[0] + u[0] * a + v[0] * b,
+                normal[1] + u[1] * a + v[1] * b,
+                normal[2] + u[2] * a + v[2] * b,
+            ]);
+            normals.push(*normal);
+        }
+        indices.extend_from_slice(&[first, first + 1, first + 2, first + 2, first + 3, first]);
+    }
+
+    mesh::MeshData {
+        colors: Some(vec![color; positions.len()]),
+        positions,
+        normals,
+        tex_coords: None,
+        lightmap_coords: None,
+        occlusion: None,
+        indices,
+    }
+}
+
+/// The 12 edges of a cube from -1 to 1 on each axis as a line list
+fn unit_cube_edges(color: [f32; 3]) -> Vec<Vertex> {
+    let vertex = |x: f32, y: f32, z: f32| Vertex {
+        pos: [x, y, z],
+        color,
+        tex_coord: [0.0, 0.0],
+        normal: [0.0, 0.0, 1.0],
+    };
+
+    let mut edges = Vec::new();
+    for &a in [-1.0, 1.0].iter() {
+        for &b in [-1.0, 1.0].iter() {
+            edges.extend_from_slice(&[vertex(-1.0, a, b), vertex(1.0, a, b)]);
+            edges.extend_from_slice(&[vertex(a, -1.0, b), vertex(a, 1.0, b)]);
+            edges.extend_from_slice(&[vertex(a, b, -1.0), vertex(a, b, 1.0)]);
+        }
+    }
+    edges
+}
+
+/// A demo scene of boxes falling onto the floor, with their colliders outlined.
+pub struct Demo {
+    simulation: Simulation,
+    buffers: Vec<(vk::Buffer, vk::DeviceMemory)>,
+}
+
+impl Demo {
+    /// Adds the boxes and collider outlines to `draws`. The ground is a thin slab whose top is the top of
+    /// `floor`, the bounds of the floor draw.
+    pub fn new(
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+        submit_queue: vk::Queue,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        draws: &mut Vec<draw::Draw>,
+        floor: &bounds::Aabb,
+    ) -> Self {
+        let cube = unit_cube([0.9, 0.5, 0.1]);
+        let cube_vertices = HelloTriangleApplication::create_device_local_buffer(
+            device,
+            &cube.interleave(),
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            command_pool,
+            submit_queue,
+            device_memory_properties,
+        );
+        let cube_indices = HelloTriangleApplication::create_device_local_buffer(
+            device,
+            &cube.indices,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            command_pool,
+            submit_queue,
+            device_memory_properties,
+        );
+        let edges = unit_cube_edges([0.0, 1.0, 0.0]);
+        let edge_vertices = HelloTriangleApplication::create_device_local_buffer(
+            device,
+            &edges,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            command_pool,
+            submit_queue,
+            device_memory_properties,
+        );
+
+        let cube_positions: Vec<Point3<f32>> = cube
+            .positions
+            .iter()
+            .map(|&position| Point3::from(position))
+            .collect();
+        let cube_collider = raycast::Bvh::new(&cube_positions, &cube.indices).map(Arc::new);
+        let cube_draw = draw::Draw {
+            bounds: cube.bounds().map(bounds::Bounds::new),
+            collider: cube_collider,
+            casts_ground_shadow: true,
+            ..draw::Draw::indexed(
+                material::Material {
+                    vertex_color: material::VertexColorMode::Multiply,
+                    ..material::Material::default()
+                }
+                .pipeline_config(cube.attributes()),
+                cube_vertices.0,
+                cube_indices.0,
+                cube.indices.len() as u32,
+            )
+        };
+        let outline_draw = draw::Draw::new(
+            pipeline::Config::unlit(vk::PrimitiveTopology::LINE_LIST),
+            edge_vertices.0,
+            edges.len() as u32,
+        );
+
+        let mut simulation = Simulation::new();
+        let ground_thickness = 0.05;
+        let center = floor.center();
+        draws.push(outline_draw.clone());
+        simulation.add_fixed_cuboid(
+            [center.x, center.y, floor.max.z - ground_thickness],
+            [
+                (floor.max.x - floor.min.x) / 2.0,
+                (floor.max.y - floor.min.y) / 2.0,
+                ground_thickness,
+            ],
+            None,
+            draws.len() - 1,
+        );
+
+        let boxes = [
+            ([0.0, 0.0, 1.0], [0.3, 0.2, 0.0]),
+            ([0.1, -0.05, 1.5], [0.0, 0.6, 0.4]),
+            ([-0.1, 0.1, 2.0], [0.7, 0.0, 0.2]),
+            ([0.5, 0.4, 1.2], [0.1, 0.1, 0.9]),
+            ([-0.5, -0.4, 1.8], [0.5, 0.5, 0.0]),
+        ];
+        for &(center, rotation) in boxes.iter() {
+            draws.push(cube_draw.clone());
+            draws.push(outline_draw.clone());
+            simulation.add_dynamic_cuboid(
+                center,
+                rotation,
+                [0.1, 0.1, 0.1],
+                draws.len() - 2,
+                draws.len() - 1,
+            );
+        }
+        simulation.sync(draws);
+
+        Self {
+            simulation,
+            buffers: vec![cube_vertices, cube_indices, edge_vertices],
+        }
+    }
+
+    /// Steps the simulation and moves the boxes and outlines to match.
+    pub fn update(&mut self, draws: &mut [draw::Draw]) {
+        self.simulation.update();
+        self.simulation.sync(draws);
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            for (buffer, memory) in self.buffers.drain(..) {
+                device.destroy_buffer(buffer, None);
+                device.free_memory(memory, None);
+            }
+        }
+    }
+}
@@ -0,0 +1,1346 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::mem::size_of;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use ash::vk;
+use cgmath::Matrix4;
+use memoffset::offset_of;
+
+use crate::{billboard, bounds, indirect, material, mesh, pointcloud, trails, util, Vertex};
+
+/// The shaders and vertex layout that a graphics pipeline is built from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Program {
+    /// Textured meshes. Their vertex layout is given by `Config::vertex_attributes`.
+    Mesh,
+    /// Camera facing quads expanded in the vertex shader from per-instance `billboard::Billboard` data
+    Billboard,
+    /// Untextured `Vertex` data coloured by its vertex colours. Used for lines and points.
+    Unlit,
+    /// Meshes drawn from the GPU generated draw stream of an `indirect::Scene`, or instanced from a buffer of
+    /// `indirect::Object`s. Each instance reads its transform from the objects rather than from push constants.
+    Indirect,
+    /// Meshes whose positions and normals are played back from a `vat::Texture`, bound as set 1. Their other
+    /// attributes come from the vertex buffer like `Mesh`.
+    Vat,
+    /// `pointcloud::Point`s drawn as round point sprites that shrink with distance
+    PointSprites,
+    /// `pointcloud::Point`s drawn instanced as discs facing along their normals, expanded in the vertex shader
+    Surfels,
+    /// Translucent ghosts of a mesh drawn instanced from a buffer of `trails::Ghost`s, each reading its transform
+    /// and opacity from the ghost. The mesh's vertex layout is given by `Config::vertex_attributes`.
+    Trail,
+    /// Meshes drawn with the user's shaders registered as the `CustomProgram` at the index, see
+    /// `shader_materials::Library`. Their vertex layout is given by `Config::vertex_attributes` like `Mesh`.
+    Custom(u16),
+}
+
+impl Program {
+    /// Whether vertices are transformed by the uniform buffer's model matrix. Billboards are positioned in world
+    /// space.
+    pub fn uses_model_transform(&self) -> bool {
+        match self {
+            Program::Mesh
+            | Program::Unlit
+            | Program::Indirect
+            | Program::Vat
+            | Program::PointSprites
+            | Program::Surfels
+            | Program::Trail
+            | Program::Custom(_) => true,
+            Program::Billboard => false,
+        }
+    }
+
+    /// The built in shaders, which are those a `CustomProgram` falls back on when it only has a fragment shader
+    fn shader_names(&self) -> (&'static str, &'static str) {
+        match self {
+            Program::Mesh | Program::Custom(_) => ("vert.spv", "frag.spv"),
+            Program::Billboard => ("billboard_vert.spv", "billboard_frag.spv"),
+            Program::Unlit => ("unlit_vert.spv", "unlit_frag.spv"),
+            Program::Indirect => ("indirect_vert.spv", "frag.spv"),
+            Program::Vat => ("vat_vert.spv", "frag.spv"),
+            Program::PointSprites => ("point_sprite_vert.spv", "point_sprite_frag.spv"),
+            Program::Surfels => ("surfel_vert.spv", "surfel_frag.spv"),
+            Program::Trail => ("trail_vert.spv", "trail_frag.spv"),
+        }
+    }
+
+    fn vertex_input(
+        &self,
+        attributes: &mesh::VertexAttributes,
+    ) -> (
+        Vec<vk::VertexInputBindingDescription>,
+        Vec<vk::VertexInputAttributeDescription>,
+    ) {
+        match self {
+            Program::Mesh | Program::Vat | Program::Custom(_) => (
+                attributes.binding_descriptions(),
+                attributes.attribute_descriptions(),
+            ),
+            Program::Unlit => (
+                vec![Vertex::get_binding_desription()],
+                Vertex::get_attribute_descriptions().to_vec(),
+            ),
+            Program::Billboard => (
+                vec![billboard::Billboard::get_binding_description()],
+                billboard::Billboard::get_attribute_descriptions().to_vec(),
+            ),
+            Program::PointSprites => (
+                vec![pointcloud::Point::get_binding_description(
+                    vk::VertexInputRate::VERTEX,
+                )],
+                pointcloud::Point::get_attribute_descriptions().to_vec(),
+            ),
+            Program::Surfels => (
+                vec![pointcloud::Point::get_binding_description(
+                    vk::VertexInputRate::INSTANCE,
+                )],
+                pointcloud::Point::get_attribute_descriptions().to_vec(),
+            ),
+            Program::Indirect => {
+                let mut bindings = attributes.binding_descriptions();
+                bindings.push(indirect::Object::get_binding_description());
+                let mut descriptions = attributes.attribute_descriptions();
+                descriptions.extend(indirect::Object::get_attribute_descriptions());
+                (bindings, descriptions)
+            }
+            Program::Trail => {
+                let mut bindings = attributes.binding_descriptions();
+                bindings.push(trails::Ghost::get_binding_description());
+                let mut descriptions = attributes.attribute_descriptions();
+                descriptions.extend(trails::Ghost::get_attribute_descriptions());
+                (bindings, descriptions)
+            }
+        }
+    }
+}
+
+/// The state that distinguishes one graphics pipeline from another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Config {
+    pub program: Program,
+    pub topology: vk::PrimitiveTopology,
+    pub cull_mode: vk::CullModeFlags,
+    /// The winding order of front facing triangles once projected
+    pub front_face: vk::FrontFace,
+    pub depth_write: bool,
+    /// How fragments' depths are compared with the depth already drawn. `ALWAYS` draws over everything.
+    pub depth_compare: vk::CompareOp,
+    /// Whether fragments are dropped where the depth already drawn is outside the draw's `DepthBounds`, before
+    /// they are shaded. The bounds are dynamic state. Left disabled on devices without the `depthBounds`
+    /// feature, so draws must still be correct without it.
+    pub depth_bounds: bool,
+    pub alpha_blend: bool,
+    /// Whether depth values are offset by the draw's `DepthBias`. The bias itself is dynamic state.
+    pub depth_bias: bool,
+    /// Flips the normals of back faces in the fragment shader. Only meaningful without back-face culling.
+    pub double_sided: bool,
+    /// Masks out fragments whose alpha is below `cutoff / 255`. Alpha-to-coverage is used instead of discarding
+    /// fragments when the pipeline is multisampled. Stored as an integer so that configs can be hashed.
+    pub alpha_cutoff: Option<u8>,
+    /// Which optional attributes the mesh's vertices carry. Only used by `Program::Mesh`, `Program::Indirect` and
+    /// `Program::Vat`.
+    pub vertex_attributes: mesh::VertexAttributes,
+    pub vertex_color: material::VertexColorMode,
+    /// How much a mesh's baked occlusion darkens its ambient light, as `strength / 255`. Stored as an integer so
+    /// that configs can be hashed.
+    pub occlusion_strength: u8,
+    /// Draws the back faces as caps over the mesh's cross section with `clipping::Clipping`'s planes, with
+    /// `clip_cap_frag.glsl` instead of the program's fragment shader. See `Config::clip_cap`.
+    pub clip_cap: bool,
+    /// Draws a shadow on the ground with `ground_shadow_frag.glsl` instead of the program's fragment shader. See
+    /// `ground_shadows::GroundShadows`.
+    pub ground_shadow: bool,
+    /// The row of the toon ramp atlas that lights the mesh in bands, or `None` for smooth lighting. See
+    /// `material::Toon`.
+    pub toon_ramp: Option<u8>,
+    /// How many pixels wide the mesh's inverted hull outline is, 0 for none. See `Config::outline`.
+    pub outline_width: u8,
+    /// Draws the outline with `outline_vert.glsl` and `outline_frag.glsl` instead of the program's shaders
+    pub outline: bool,
+    /// Whether the draw is drawn into the light's shadow map, see `Config::shadow_caster`
+    pub casts_shadows: bool,
+    /// Draws the mesh's depth from the light into `shadow_map::ShadowMap` with `shadow_frag.glsl` instead of
+    /// the program's fragment shader
+    pub shadow_caster: bool,
+}
+
+impl Config {
+    /// Opaque, back-face culled meshes. See `material::Material` for meshes with other face culling.
+    pub fn mesh() -> Self {
+        Self {
+            program: Program::Mesh,
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            cull_mode: vk::CullModeFlags::BACK,
+            front_face: vk::FrontFace::CLOCKWISE,
+            depth_write: true,
+            depth_compare: vk::CompareOp::LESS,
+            depth_bounds: false,
+            alpha_blend: false,
+            depth_bias: false,
+            double_sided: false,
+            alpha_cutoff: None,
+            vertex_attributes: mesh::VertexAttributes::ALL,
+            vertex_color: material::VertexColorMode::Ignore,
+            occlusion_strength: u8::MAX,
+            clip_cap: false,
+            ground_shadow: false,
+            toon_ramp: None,
+            outline_width: 0,
+            outline: false,
+            casts_shadows: true,
+            shadow_caster: false,
+        }
+    }
+
+    /// Billboards are blended over the opaque scene. They are depth tested but don't write depth so that
+    /// overlapping sprites don't clip each other, and both faces are drawn since they are always turned
+    /// towards the camera anyway.
+    pub fn billboard() -> Self {
+        Self {
+            program: Program::Billboard,
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            cull_mode: vk::CullModeFlags::NONE,
+            front_face: vk::FrontFace::CLOCKWISE,
+            depth_write: false,
+            depth_compare: vk::CompareOp::LESS,
+            depth_bounds: false,
+            alpha_blend: true,
+            depth_bias: false,
+            double_sided: false,
+            alpha_cutoff: None,
+            vertex_attributes: mesh::VertexAttributes::ALL,
+            vertex_color: material::VertexColorMode::Ignore,
+            occlusion_strength: u8::MAX,
+            clip_cap: false,
+            ground_shadow: false,
+            toon_ramp: None,
+            outline_width: 0,
+            outline: false,
+            casts_shadows: false,
+            shadow_caster: false,
+        }
+    }
+
+    /// Vertex coloured lines or points. `topology` should be one of the `LINE_*` or `POINT_LIST` topologies,
+    /// line width and point size are chosen per draw.
+    pub fn unlit(topology: vk::PrimitiveTopology) -> Self {
+        Self {
+            program: Program::Unlit,
+            topology,
+            cull_mode: vk::CullModeFlags::NONE,
+            front_face: vk::FrontFace::CLOCKWISE,
+            depth_write: true,
+            depth_compare: vk::CompareOp::LESS,
+            depth_bounds: false,
+            alpha_blend: false,
+            depth_bias: false,
+            double_sided: false,
+            alpha_cutoff: None,
+            vertex_attributes: mesh::VertexAttributes::ALL,
+            vertex_color: material::VertexColorMode::Ignore,
+            occlusion_strength: u8::MAX,
+            clip_cap: false,
+            ground_shadow: false,
+            toon_ramp: None,
+            outline_width: 0,
+            outline: false,
+            casts_shadows: false,
+            shadow_caster: false,
+        }
+    }
+
+    /// The variant that caps the cross section where clipping planes cut through a draw of this config, if it
+    /// can be capped. Only opaque meshes that cull their back faces can, since their back faces only show
+    /// through the cut.
+    pub fn clip_cap(&self) -> Option<Self> {
+        let mesh = matches!(
+            self.program,
+            Program::Mesh | Program::Indirect | Program::Vat
+        );
+        if !mesh || self.alpha_blend || self.cull_mode != vk::CullModeFlags::BACK {
+            return None;
+        }
+        Some(Self {
+            cull_mode: vk::CullModeFlags::FRONT,
+            clip_cap: true,
+            ..*self
+        })
+    }
+
+    /// The variant that outlines a draw of this config, if it has an outline. Only plain opaque meshes can be
+    /// outlined. The outline is the mesh's back faces pushed out along their normals, so it shows around the
+    /// silhouette and along creases where the front faces drawn over it let it through. Meshes need smooth
+    /// normals for an unbroken outline, since the faces either side of a hard edge are pushed apart.
+    pub fn outline(&self) -> Option<Self> {
+        if self.outline_width == 0 || self.program != Program::Mesh || self.alpha_blend {
+            return None;
+        }
+        Some(Self {
+            cull_mode: vk::CullModeFlags::FRONT,
+            double_sided: false,
+            alpha_cutoff: None,
+            vertex_color: material::VertexColorMode::Ignore,
+            clip_cap: false,
+            ground_shadow: false,
+            outline: true,
+            ..*self
+        })
+    }
+
+    /// The variant that flattens a draw of this config onto the ground as its planar shadow, if it can cast one.
+    /// Only meshes transformed by push constants can, since the shadow's projection is pushed as the draw's
+    /// transform. Shadows are blended over the ground without writing depth, and biased towards the camera so
+    /// that they win the depth test against the ground they lie on.
+    pub fn ground_shadow(&self) -> Option<Self> {
+        if !matches!(self.program, Program::Mesh | Program::Vat) {
+            return None;
+        }
+        Some(Self {
+            cull_mode: vk::CullModeFlags::NONE,
+            depth_write: false,
+            depth_compare: vk::CompareOp::LESS_OR_EQUAL,
+            depth_bounds: false,
+            alpha_blend: true,
+            depth_bias: true,
+            double_sided: false,
+            alpha_cutoff: None,
+            vertex_color: material::VertexColorMode::Ignore,
+            clip_cap: false,
+            ground_shadow: true,
+            outline_width: 0,
+            outline: false,
+            ..*self
+        })
+    }
+
+    /// The variant that draws the ghosts of a mesh of this config's trail, blended over the scene without
+    /// writing depth so that they don't hide each other. Only plain meshes can leave trails.
+    pub fn trail(&self) -> Option<Self> {
+        if self.program != Program::Mesh {
+            return None;
+        }
+        Some(Self {
+            program: Program::Trail,
+            depth_write: false,
+            depth_bounds: false,
+            alpha_blend: true,
+            depth_bias: false,
+            double_sided: false,
+            alpha_cutoff: None,
+            occlusion_strength: u8::MAX,
+            clip_cap: false,
+            ground_shadow: false,
+            toon_ramp: None,
+            outline_width: 0,
+            outline: false,
+            ..*self
+        })
+    }
+
+    /// The variant that draws a draw of this config into the light's shadow map, if it casts shadows. Only opaque
+    /// meshes can, masked or not, and shadows are cast by both faces so that open or single-sided meshes still
+    /// block the light. Depths are biased away from the light by the draw's `DepthBias` so that surfaces don't
+    /// shadow themselves.
+    pub fn shadow_caster(&self) -> Option<Self> {
+        let mesh = matches!(
+            self.program,
+            Program::Mesh | Program::Indirect | Program::Vat
+        );
+        if !mesh || !self.casts_shadows || self.alpha_blend {
+            return None;
+        }
+        Some(Self {
+            cull_mode: vk::CullModeFlags::NONE,
+            depth_write: true,
+            depth_compare: vk::CompareOp::LESS,
+            depth_bounds: false,
+            depth_bias: true,
+            double_sided: false,
+            vertex_color: material::VertexColorMode::Ignore,
+            occlusion_strength: u8::MAX,
+            clip_cap: false,
+            ground_shadow: false,
+            toon_ramp: None,
+            outline_width: 0,
+            outline: false,
+            shadow_caster: true,
+            ..*self
+        })
+    }
+
+    /// Soft discs blended over the ground under a caster, whose vertex colours' red is how much of the shadow
+    /// covers each vertex. See `ground_shadows::disc`.
+    pub fn blob_shadow() -> Self {
+        Self {
+            depth_write: false,
+            depth_compare: vk::CompareOp::LESS_OR_EQUAL,
+            alpha_blend: true,
+            depth_bias: true,
+            vertex_color: material::VertexColorMode::Multiply,
+            ground_shadow: true,
+            ..Self::unlit(vk::PrimitiveTopology::TRIANGLE_LIST)
+        }
+    }
+
+    /// Opaque point sprites. Their size is chosen per draw.
+    pub fn point_sprites() -> Self {
+        Self {
+            program: Program::PointSprites,
+            ..Self::unlit(vk::PrimitiveTopology::POINT_LIST)
+        }
+    }
+
+    /// Opaque surfels, drawn from both sides since they face along normals that may point away from the camera
+    pub fn surfels() -> Self {
+        Self {
+            program: Program::Surfels,
+            ..Self::unlit(vk::PrimitiveTopology::TRIANGLE_LIST)
+        }
+    }
+}
+
+/// A user's shaders registered as a material, drawn by `Program::Custom`. See `shader_materials::Library`.
+#[derive(Clone, Debug)]
+pub struct CustomProgram {
+    /// SPIR-V, or `None` to transform vertices like `Program::Mesh`
+    pub vertex: Option<Arc<Vec<u32>>>,
+    pub fragment: Arc<Vec<u32>>,
+    /// The first two sets are the same as the shared layout's and the push constants are too, so the frame's
+    /// descriptor sets stay bound when switching to the program
+    pub layout: vk::PipelineLayout,
+    /// The program's parameters, bound as `shader_materials::PARAMETER_SET` when it has any
+    pub parameters: Option<vk::DescriptorSet>,
+}
+
+/// Offsets the depth of polygons so that coplanar geometry such as decals and selection highlights can be drawn
+/// over a surface without z-fighting, and so that shadow maps don't shadow the surface they were rendered from.
+/// The offset is `constant_factor * r + slope_factor * max_slope`, where `r` is the smallest resolvable depth
+/// difference, clamped to `clamp` if it is non-zero. Negative factors move polygons towards the camera.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthBias {
+    pub constant_factor: f32,
+    pub slope_factor: f32,
+    pub clamp: f32,
+}
+
+impl DepthBias {
+    pub fn new(constant_factor: f32, slope_factor: f32) -> Self {
+        Self {
+            constant_factor,
+            slope_factor,
+            clamp: 0.0,
+        }
+    }
+
+    /// Moves ground shadows towards the camera so that they win the depth test against the ground they lie on
+    pub fn ground_shadow() -> Self {
+        Self::new(-1.0, -1.0)
+    }
+}
+
+/// The range of depths the depth already drawn must be within for a draw's fragments to be kept, from 0.0 at the
+/// near plane to 1.0 at the far plane. Light volumes and other passes that only affect what lies within a region
+/// use it to skip the pixels where the scene is nearer or further than the whole region without shading them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthBounds {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl DepthBounds {
+    pub fn new(min: f32, max: f32) -> Self {
+        Self { min, max }
+    }
+
+    /// The depths the box covers once transformed by `clip`, from its space to clip space. Boxes reaching behind
+    /// the camera can't be projected, so they cover every depth.
+    pub fn of(aabb: &bounds::Aabb, clip: &Matrix4<f32>) -> Self {
+        let mut covered = Self::new(1.0, 0.0);
+        for corner in aabb.corners().iter() {
+            let projected = clip * corner.to_homogeneous();
+            if projected.w <= 0.0 {
+                return Self::new(0.0, 1.0);
+            }
+            let depth = num::clamp(projected.z / projected.w, 0.0, 1.0);
+            covered.min = covered.min.min(depth);
+            covered.max = covered.max.max(depth);
+        }
+        covered
+    }
+}
+
+/// The range of rasterization state the device supports for line widths, point sizes and depth bias, and whether
+/// it can test depth bounds.
+#[derive(Clone, Copy, Debug)]
+pub struct RasterLimits {
+    wide_lines: bool,
+    large_points: bool,
+    depth_bias_clamp: bool,
+    depth_bounds: bool,
+    line_width_range: [f32; 2],
+    point_size_range: [f32; 2],
+}
+
+impl RasterLimits {
+    /// `features` should be the features enabled on the logical device rather than everything the physical
+    /// device supports, wide lines, large points, depth bias clamping and depth bounds are optional.
+    pub fn new(features: &vk::PhysicalDeviceFeatures, limits: &vk::PhysicalDeviceLimits) -> Self {
+        Self {
+            wide_lines: features.wide_lines == vk::TRUE,
+            large_points: features.large_points == vk::TRUE,
+            depth_bias_clamp: features.depth_bias_clamp == vk::TRUE,
+            depth_bounds: features.depth_bounds == vk::TRUE,
+            line_width_range: limits.line_width_range,
+            point_size_range: limits.point_size_range,
+        }
+    }
+
+    /// Clamps the requested line width to one the device can draw. Without the wide lines feature only 1.0 is
+    /// valid.
+    pub fn line_width(&self, requested: f32) -> f32 {
+        if self.wide_lines {
+            num::clamp(
+                requested,
+                self.line_width_range[0],
+                self.line_width_range[1],
+            )
+        } else {
+            1.0
+        }
+    }
+
+    /// Clamps the requested point size to one the device can draw. Without the large points feature only 1.0 is
+    /// valid.
+    pub fn point_size(&self, requested: f32) -> f32 {
+        if self.large_points {
+            num::clamp(
+                requested,
+                self.point_size_range[0],
+                self.point_size_range[1],
+            )
+        } else {
+            1.0
+        }
+    }
+
+    /// Whether pipelines test `Config::depth_bounds`. Without the depth bounds feature the test is left disabled.
+    pub fn depth_bounds(&self) -> bool {
+        self.depth_bounds
+    }
+
+    /// Without the depth bias clamp feature the clamp must be 0.0, which disables clamping.
+    pub fn depth_bias_clamp(&self, requested: f32) -> f32 {
+        if self.depth_bias_clamp {
+            requested
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Fragment shader specialization constants, matching the `constant_id`s declared by the fragment shaders.
+/// Shaders ignore any constants they don't declare.
+#[repr(C)]
+struct FragmentConstants {
+    double_sided: vk::Bool32,
+    alpha_mask: vk::Bool32,
+    alpha_cutoff: f32,
+    alpha_to_coverage: vk::Bool32,
+    encode_srgb: vk::Bool32,
+    vertex_color_mode: i32,
+    has_tex_coord: vk::Bool32,
+    has_lightmap: vk::Bool32,
+    occlusion_strength: f32,
+    toon_ramp: i32,
+}
+
+impl FragmentConstants {
+    fn map_entries() -> [vk::SpecializationMapEntry; 10] {
+        let entry = |constant_id: u32, offset: usize| {
+            vk::SpecializationMapEntry::builder()
+                .constant_id(constant_id)
+                .offset(offset as u32)
+                .size(4)
+                .build()
+        };
+
+        [
+            entry(0, offset_of!(Self, double_sided)),
+            entry(1, offset_of!(Self, alpha_mask)),
+            entry(2, offset_of!(Self, alpha_cutoff)),
+            entry(3, offset_of!(Self, alpha_to_coverage)),
+            entry(4, offset_of!(Self, encode_srgb)),
+            entry(5, offset_of!(Self, vertex_color_mode)),
+            entry(6, offset_of!(Self, has_tex_coord)),
+            entry(7, offset_of!(Self, has_lightmap)),
+            entry(8, offset_of!(Self, occlusion_strength)),
+            entry(9, offset_of!(Self, toon_ramp)),
+        ]
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+}
+
+/// Vertex shader specialization constants, matching the `constant_id`s declared by the vertex shaders. Only
+/// `outline_vert.glsl` declares any.
+#[repr(C)]
+struct VertexConstants {
+    outline_width: f32,
+}
+
+impl VertexConstants {
+    fn map_entries() -> [vk::SpecializationMapEntry; 1] {
+        [vk::SpecializationMapEntry::builder()
+            .constant_id(0)
+            .offset(offset_of!(Self, outline_width) as u32)
+            .size(4)
+            .build()]
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+}
+
+/// Push constants shared by every pipeline, read by the mesh and unlit vertex shaders.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct PushConstants {
+    /// The draw's transform, applied before the scene's model matrix
+    pub model: [[f32; 4]; 4],
+    /// Only read when drawing points, and as the radius of surfels
+    pub point_size: f32,
+    /// Only read by `Program::Vat`, see `vat::Playback::frame`
+    pub animation_frame: f32,
+}
+
+impl PushConstants {
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+}
+
+/// What every pipeline variant renders into.
+#[derive(Clone, Copy, Debug)]
+pub struct Target {
+    pub render_pass: vk::RenderPass,
+    pub samples: vk::SampleCountFlags,
+    pub extent: vk::Extent2D,
+    /// Set when the colour attachment is a UNORM format. Fragment shaders then encode their linear output to
+    /// sRGB themselves, which an sRGB attachment would otherwise do on write.
+    pub encode_srgb: bool,
+    /// Cleared for depth-only render passes, such as most shadow maps', whose pipelines blend no colour
+    pub color: bool,
+}
+
+/// Builds graphics pipelines on demand and caches them by their `Config` so each variant is only created once.
+/// Every variant shares a single pipeline layout. Pipelines depend on their `Target` so the whole set has to be
+/// destroyed and rebuilt along with the swapchain. They are created through a Vulkan pipeline cache that outlives
+/// the set, so rebuilding them is quicker than creating them the first time.
+pub struct Variants {
+    layout: vk::PipelineLayout,
+    /// Owned by the application
+    cache: vk::PipelineCache,
+    target: Target,
+    limits: RasterLimits,
+    pipelines: HashMap<Config, vk::Pipeline>,
+    clip_caps: bool,
+    /// Owned by the application, indexed by `Program::Custom`
+    custom: Vec<CustomProgram>,
+    /// The variants created since `take_compiled` was last called, with how long each took
+    compiled: Vec<(Program, Duration)>,
+}
+
+impl Variants {
+    pub fn new(
+        device: &ash::Device,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+        animation_set_layout: vk::DescriptorSetLayout,
+        target: Target,
+        limits: RasterLimits,
+        cache: vk::PipelineCache,
+    ) -> Self {
+        Self {
+            layout: create_layout(device, &[descriptor_set_layout, animation_set_layout]),
+            cache,
+            target,
+            limits,
+            pipelines: HashMap::new(),
+            clip_caps: false,
+            custom: Vec::new(),
+            compiled: Vec::new(),
+        }
+    }
+
+    pub fn layout(&self) -> vk::PipelineLayout {
+        self.layout
+    }
+
+    pub fn limits(&self) -> &RasterLimits {
+        &self.limits
+    }
+
+    /// Whether draws that can be capped are drawn a second time with their `Config::clip_cap` variant
+    pub fn clip_caps(&self) -> bool {
+        self.clip_caps
+    }
+
+    pub fn set_clip_caps(&mut self, clip_caps: bool) {
+        self.clip_caps = clip_caps;
+    }
+
+    pub fn custom_program(&self, index: u16) -> Option<&CustomProgram> {
+        self.custom.get(index as usize)
+    }
+
+    /// Sets the programs `Program::Custom` draws with. Programs are only ever added, so the index of each stays
+    /// the same.
+    pub fn set_custom_programs(&mut self, programs: Vec<CustomProgram>) {
+        self.custom = programs;
+    }
+
+    /// Returns the pipeline for the given config, creating it if this is the first time it has been requested.
+    pub fn get(&mut self, device: &ash::Device, config: &Config) -> vk::Pipeline {
+        let (layout, cache, target, limits) = (self.layout, self.cache, self.target, self.limits);
+        let (custom, compiled) = (&self.custom, &mut self.compiled);
+        *self.pipelines.entry(*config).or_insert_with(|| {
+            let start = Instant::now();
+            let pipeline = create(device, cache, &target, &limits, layout, custom, config);
+            compiled.push((config.program, start.elapsed()));
+            pipeline
+        })
+    }
+
+    /// The variants created since the last call, with how long each took to compile
+    pub fn take_compiled(&mut self) -> Vec<(Program, Duration)> {
+        std::mem::take(&mut self.compiled)
+    }
+
+    /// Creates the pipelines for the configs that haven't been created yet, so that drawing with them for the
+    /// first time doesn't stall a frame while they compile. `progress` is called with how many have been
+    /// created and how many there are to create, before the first and after each one.
+    pub fn prewarm(
+        &mut self,
+        device: &ash::Device,
+        configs: &[Config],
+        mut progress: impl FnMut(usize, usize),
+    ) {
+        let mut missing: Vec<Config> = Vec::new();
+        for config in configs {
+            if !self.pipelines.contains_key(config) && !missing.contains(config) {
+                missing.push(*config);
+            }
+        }
+        progress(0, missing.len());
+        for (index, config) in missing.iter().enumerate() {
+            self.get(device, config);
+            progress(index + 1, missing.len());
+        }
+    }
+
+    /// Returns the pipeline for the given config if it has already been created.
+    pub fn cached(&self, config: &Config) -> Option<vk::Pipeline> {
+        self.pipelines.get(config).copied()
+    }
+
+    /// Destroys every pipeline variant and the shared layout.
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            for (_, pipeline) in self.pipelines.drain() {
+                device.destroy_pipeline(pipeline, None);
+            }
+            device.destroy_pipeline_layout(self.layout, None);
+        }
+    }
+}
+
+/// An empty pipeline cache for `Variants` to create pipelines through
+pub fn create_cache(device: &ash::Device) -> vk::PipelineCache {
+    let create_info = vk::PipelineCacheCreateInfo::builder();
+    unsafe {
+        device
+            .create_pipeline_cache(&create_info, None)
+            .expect("Creating pipeline cache")
+    }
+}
+
+/// Creates the layout shared by every graphics pipeline from the frame's and the animation's set layouts. All
+/// pipelines bind the same descriptor set so that descriptor sets stay bound when switching between them. Set 1
+/// is only bound by `Program::Vat` draws. `CustomProgram`s' layouts add a set of parameters after those two.
+pub fn create_layout(
+    device: &ash::Device,
+    set_layouts: &[vk::DescriptorSetLayout],
+) -> vk::PipelineLayout {
+    let push_constant_ranges = [vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::VERTEX)
+        .offset(0)
+        .size(size_of::<PushConstants>() as u32)
+        .build()];
+    let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(set_layouts)
+        .push_constant_ranges(&push_constant_ranges);
+    unsafe {
+        device
+            .create_pipeline_layout(&pipeline_layout_info, None)
+            .expect("pipeline layout")
+    }
+}
+
+fn create(
+    device: &ash::Device,
+    cache: vk::PipelineCache,
+    target: &Target,
+    limits: &RasterLimits,
+    pipeline_layout: vk::PipelineLayout,
+    custom: &[CustomProgram],
+    config: &Config,
+) -> vk::Pipeline {
+    let swap_chain_extents = target.extent;
+    // Alpha-to-coverage turns alpha into a sample mask, so it only smooths edges when there are multiple samples
+    let alpha_to_coverage =
+        config.alpha_cutoff.is_some() && target.samples != vk::SampleCountFlags::TYPE_1;
+
+    let (vert_name, frag_name) = config.program.shader_names();
+    let vert_name = if config.outline {
+        "outline_vert.spv"
+    } else {
+        vert_name
+    };
+    let frag_name = if config.outline {
+        "outline_frag.spv"
+    } else if config.clip_cap {
+        "clip_cap_frag.spv"
+    } else if config.ground_shadow {
+        "ground_shadow_frag.spv"
+    } else if config.shadow_caster {
+        "shadow_frag.spv"
+    } else {
+        frag_name
+    };
+    let built_in = |name: &str| {
+        let path = Path::new(env!("OUT_DIR")).join(name);
+        util::read_shader_code(path.as_path())
+    };
+    let custom = match config.program {
+        Program::Custom(index) => Some(
+            custom
+                .get(index as usize)
+                .expect("Custom program registered before it is drawn"),
+        ),
+        _ => None,
+    };
+    let vert_shader_module = match custom.and_then(|custom| custom.vertex.as_ref()) {
+        Some(code) => create_shader_module(device, code),
+        None => create_shader_module(device, &built_in(vert_name)),
+    };
+    let frag_shader_module = match custom {
+        Some(custom) => create_shader_module(device, &custom.fragment),
+        None => create_shader_module(device, &built_in(frag_name)),
+    };
+    // Custom programs bind their parameters as an extra set
+    let pipeline_layout = custom.map_or(pipeline_layout, |custom| custom.layout);
+
+    let fragment_constants = FragmentConstants {
+        double_sided: config.double_sided.into(),
+        alpha_mask: config.alpha_cutoff.is_some().into(),
+        alpha_cutoff: config.alpha_cutoff.unwrap_or(0) as f32 / 255.0,
+        alpha_to_coverage: alpha_to_coverage.into(),
+        encode_srgb: target.encode_srgb.into(),
+        vertex_color_mode: config.vertex_color as i32,
+        has_tex_coord: config.vertex_attributes.tex_coord.into(),
+        has_lightmap: config.vertex_attributes.lightmap_coord.into(),
+        occlusion_strength: config.occlusion_strength as f32 / 255.0,
+        toon_ramp: config.toon_ramp.map_or(-1, i32::from),
+    };
+    let specialization_entries = FragmentConstants::map_entries();
+    let frag_specialization = vk::SpecializationInfo::builder()
+        .map_entries(&specialization_entries)
+        .data(fragment_constants.as_bytes());
+    let vertex_constants = VertexConstants {
+        outline_width: config.outline_width as f32,
+    };
+    let vertex_specialization_entries = VertexConstants::map_entries();
+    let vert_specialization = vk::SpecializationInfo::builder()
+        .map_entries(&vertex_specialization_entries)
+        .data(vertex_constants.as_bytes());
+
+    let main_fn_name = CString::new("main").unwrap();
+    let vert_stage_builder = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(main_fn_name.as_c_str())
+        .specialization_info(&vert_specialization);
+    let frag_stage_builder = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(frag_shader_module)
+        .name(main_fn_name.as_c_str())
+        .specialization_info(&frag_specialization);
+    let shader_stages = [vert_stage_builder.build(), frag_stage_builder.build()];
+
+    let (binding_descriptions, attribute_descriptions) =
+        config.program.vertex_input(&config.vertex_attributes);
+    // Describe our vertex layout, the input for the vertex shader
+    let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_binding_descriptions(&binding_descriptions)
+        .vertex_attribute_descriptions(&attribute_descriptions);
+
+    // Describe the primitives we are drawing with our vertices
+    let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(config.topology)
+        .primitive_restart_enable(false);
+
+    // Describe the region of the framebuffer that we want to render to
+    let viewport = vk::Viewport::builder()
+        .x(0.0)
+        .y(0.0)
+        .min_depth(0.0)
+        .max_depth(1.0)
+        .width(swap_chain_extents.width as f32)
+        .height(swap_chain_extents.height as f32);
+
+    // Clipping filter for frame buffer. We don't want to clip the frame buffer with this pipeline so we do the entire frame buffer.
+    let scissor = vk::Rect2D::builder()
+        .offset(vk::Offset2D { x: 0, y: 0 })
+        .extent(swap_chain_extents);
+
+    let viewports = [viewport.build()];
+    let scissors = [scissor.build()];
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
+        .viewports(&viewports)
+        .scissors(&scissors);
+
+    // Set up a rasterizer
+    let rasterizer = vk::PipelineRasterizationStateCreateInfo::builder()
+        .depth_clamp_enable(false) // Clip beyond near and far planes
+        .rasterizer_discard_enable(false) // Don't skip rasterization
+        .polygon_mode(vk::PolygonMode::FILL) // Rasterize entire polygon
+        .line_width(1.0) // Rasterization line width, overridden by dynamic state
+        .cull_mode(config.cull_mode) // Face culling
+        .front_face(config.front_face) // Vertex direction to determine if face is front or back
+        .depth_bias_enable(config.depth_bias); // Alter depth values with the dynamic depth bias
+
+    // MSAA config
+    let multisampling = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(target.samples)
+        .min_sample_shading(1.0)
+        .alpha_to_coverage_enable(alpha_to_coverage)
+        .alpha_to_one_enable(false);
+
+    let color_blend_attachment = if config.alpha_blend {
+        // Standard "over" blending: colour = src.rgb * src.a + dst.rgb * (1 - src.a)
+        vk::PipelineColorBlendAttachmentState::builder()
+            .color_write_mask(vk::ColorComponentFlags::all())
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ONE)
+            .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
+            .alpha_blend_op(vk::BlendOp::ADD)
+            .build()
+    } else {
+        vk::PipelineColorBlendAttachmentState::builder()
+            .color_write_mask(vk::ColorComponentFlags::all())
+            .blend_enable(false)
+            .build()
+    };
+    let color_blend_attachments = [color_blend_attachment];
+    let color_blend_attachments = match target.color {
+        true => &color_blend_attachments[..],
+        false => &[],
+    };
+    let global_blend = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .attachments(color_blend_attachments);
+
+    let depth_bounds = config.depth_bounds && limits.depth_bounds();
+    // Planar shadows only darken where the stencil is still clear and then mark it, so the ground under
+    // triangles that overlap once flattened, or under several casters, is only darkened once. Blobs are soft, so
+    // they darken together where they overlap instead. The stencil test passes without a stencil aspect.
+    let ground_stencil = config.ground_shadow && config.program != Program::Unlit;
+    let stencil = vk::StencilOpState::builder()
+        .fail_op(vk::StencilOp::KEEP)
+        .pass_op(vk::StencilOp::INCREMENT_AND_CLAMP)
+        .depth_fail_op(vk::StencilOp::KEEP)
+        .compare_op(vk::CompareOp::EQUAL)
+        .compare_mask(0xff)
+        .write_mask(0xff)
+        .reference(0)
+        .build();
+    let depth_stencil_attachment = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true)
+        .depth_write_enable(config.depth_write)
+        .depth_compare_op(config.depth_compare)
+        .depth_bounds_test_enable(depth_bounds)
+        .min_depth_bounds(0.0)
+        .max_depth_bounds(1.0)
+        .stencil_test_enable(ground_stencil)
+        .front(stencil)
+        .back(stencil);
+
+    // Line width and depth bias vary per draw, and the viewport and scissor are set when recording so the
+    // pipelines can draw into targets other than the swapchain's images. None of them are baked into the
+    // pipeline. Depth bounds vary per draw too, but are only dynamic on the pipelines that test them.
+    let mut dynamic_states = vec![
+        vk::DynamicState::VIEWPORT,
+        vk::DynamicState::SCISSOR,
+        vk::DynamicState::LINE_WIDTH,
+        vk::DynamicState::DEPTH_BIAS,
+    ];
+    if depth_bounds {
+        dynamic_states.push(vk::DynamicState::DEPTH_BOUNDS);
+    }
+    let dynamic_state =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+
+    let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&shader_stages[..])
+        .vertex_input_state(&vertex_input_info)
+        .input_assembly_state(&input_assembly_info)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterizer)
+        .multisample_state(&multisampling)
+        .color_blend_state(&global_blend)
+        .depth_stencil_state(&depth_stencil_attachment)
+        .dynamic_state(&dynamic_state)
+        .layout(pipeline_layout)
+        .render_pass(target.render_pass);
+
+    let pipelines = unsafe {
+        device
+            .create_graphics_pipelines(cache, &[pipeline_info.build()], None)
+            .expect("graphics pipeline")
+    };
+
+    unsafe { device.destroy_shader_module(vert_shader_module, None) };
+    unsafe { device.destroy_shader_module(frag_shader_module, None) };
+
+    pipelines[0]
+}
+
+pub fn create_shader_module(device: &ash::Device, code: &[u32]) -> vk::ShaderModule {
+    let builder = vk::ShaderModuleCreateInfo::builder().code(code);
+    unsafe {
+        device
+            .create_shader_module(&builder, None)
+            .expect("Shader module")
+    }
+}
+
+/// What a render pass does with what an attachment held before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Load {
+    /// Cleared, colour to the target's clear colour and depth to the far plane
+    Clear,
+    /// Kept, so the pass draws over what the target held when it was last drawn, for effects that accumulate
+    /// over frames. A target's first pass after it has been made clears it instead.
+    Preserve,
+    /// Left undefined, for passes that draw over every pixel
+    DontCare,
+}
+
+impl Load {
+    pub const ALL: [Self; 3] = [Self::Clear, Self::Preserve, Self::DontCare];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|load| load.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Load::Clear => "clear",
+            Load::Preserve => "preserve",
+            Load::DontCare => "dontcare",
+        }
+    }
+
+    pub fn op(&self) -> vk::AttachmentLoadOp {
+        match self {
+            Load::Clear => vk::AttachmentLoadOp::CLEAR,
+            Load::Preserve => vk::AttachmentLoadOp::LOAD,
+            Load::DontCare => vk::AttachmentLoadOp::DONT_CARE,
+        }
+    }
+}
+
+/// What a render pass does with one of its attachments.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Attachment {
+    pub load: Load,
+    /// Whether what was drawn is kept once the pass ends, for later passes to read or the next pass to preserve
+    pub store: bool,
+}
+
+impl Attachment {
+    pub fn store_op(&self) -> vk::AttachmentStoreOp {
+        match self.store {
+            true => vk::AttachmentStoreOp::STORE,
+            false => vk::AttachmentStoreOp::DONT_CARE,
+        }
+    }
+
+    /// The layout the attachment starts the pass in: `kept`, the layout it's left in between passes, when what it
+    /// holds is preserved
+    pub fn initial_layout(&self, kept: vk::ImageLayout) -> vk::ImageLayout {
+        match self.load {
+            Load::Preserve => kept,
+            Load::Clear | Load::DontCare => vk::ImageLayout::UNDEFINED,
+        }
+    }
+}
+
+/// How the passes drawing into a render target load and store its colour and depth, and what colour it's cleared
+/// to. Load and store operations don't affect render pass compatibility, so pipelines and framebuffers work with
+/// a target whatever its config. The stencil, which only the scene's planar ground shadows use, is always cleared.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PassConfig {
+    /// Linear RGB, or `None` for the renderer's background: black, or the sky's colour with the time of day
+    pub clear_color: Option<[f32; 3]>,
+    pub color: Attachment,
+    pub depth: Attachment,
+}
+
+impl Default for PassConfig {
+    fn default() -> Self {
+        Self {
+            clear_color: None,
+            color: Attachment {
+                load: Load::Clear,
+                store: true,
+            },
+            depth: Attachment {
+                load: Load::Clear,
+                store: false,
+            },
+        }
+    }
+}
+
+impl PassConfig {
+    /// Whether the passes keep something from before them, so the target needs a clearing render pass of
+    /// `first` too for the first time it's drawn, when there's nothing to keep
+    pub fn preserves(&self) -> bool {
+        self.color.load == Load::Preserve || self.depth.load == Load::Preserve
+    }
+
+    /// What the target is cleared to and what happens to its colour and depth, on one line
+    pub fn describe(&self) -> String {
+        let clear_color = match self.clear_color {
+            Some([red, green, blue]) => format!("{} {} {}", red, green, blue),
+            None => String::from("the background"),
+        };
+        let describe = |attachment: &Attachment| {
+            let store = match attachment.store {
+                true => "stored",
+                false => "discarded",
+            };
+            format!("{}, {}", attachment.load.name(), store)
+        };
+        format!(
+            "cleared to {}, colour {}, depth {}",
+            clear_color,
+            describe(&self.color),
+            describe(&self.depth)
+        )
+    }
+
+    /// The config with what it preserves cleared instead
+    pub fn first(&self) -> Self {
+        let clear = |attachment: Attachment| match attachment.load {
+            Load::Preserve => Attachment {
+                load: Load::Clear,
+                ..attachment
+            },
+            _ => attachment,
+        };
+        Self {
+            color: clear(self.color),
+            depth: clear(self.depth),
+            ..*self
+        }
+    }
+}
+
+/// The render targets whose passes can be configured with a `PassConfig`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderTarget {
+    /// The swapchain image and depth buffer the frame's render pass draws the scene into. Preserving its colour
+    /// keeps the image as it was last presented, after post processing and the overlays, which only the graphics
+    /// family can do when it presents the images too.
+    Scene,
+    Minimap,
+    /// Every reflection probe's cube. The faces share a depth buffer, so preserving depth keeps another face's.
+    Probes,
+}
+
+impl RenderTarget {
+    pub const ALL: [Self; 3] = [Self::Scene, Self::Minimap, Self::Probes];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|target| target.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RenderTarget::Scene => "scene",
+            RenderTarget::Minimap => "minimap",
+            RenderTarget::Probes => "probes",
+        }
+    }
+}
+
+/// The `PassConfig` of each `RenderTarget`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PassConfigs {
+    pub scene: PassConfig,
+    pub minimap: PassConfig,
+    pub probes: PassConfig,
+}
+
+impl Default for PassConfigs {
+    fn default() -> Self {
+        Self {
+            scene: PassConfig {
+                // Read after the render pass by Hi-Z culling, the fog and the pixel inspector
+                depth: Attachment {
+                    load: Load::Clear,
+                    store: true,
+                },
+                ..PassConfig::default()
+            },
+            minimap: PassConfig::default(),
+            probes: PassConfig::default(),
+        }
+    }
+}
+
+impl PassConfigs {
+    pub fn get(&self, target: RenderTarget) -> &PassConfig {
+        match target {
+            RenderTarget::Scene => &self.scene,
+            RenderTarget::Minimap => &self.minimap,
+            RenderTarget::Probes => &self.probes,
+        }
+    }
+
+    pub fn get_mut(&mut self, target: RenderTarget) -> &mut PassConfig {
+        match target {
+            RenderTarget::Scene => &mut self.scene,
+            RenderTarget::Minimap => &mut self.minimap,
+            RenderTarget::Probes => &mut self.probes,
+        }
+    }
+}
+
+/// A render pass compatible with the renderer's own, so pipelines created for it can draw into other images with
+/// the same attachment formats. The colour attachment is left in `final_layout` to be read from `reader_stage`
+/// with `reader_access`, and is loaded and stored as `config` says. A colour attachment that is preserved must be
+/// in `final_layout` before the pass.
+pub fn create_offscreen_render_pass(
+    device: &ash::Device,
+    color_format: vk::Format,
+    depth_format: vk::Format,
+    final_layout: vk::ImageLayout,
+    reader_stage: vk::PipelineStageFlags,
+    reader_access: vk::AccessFlags,
+    config: &PassConfig,
+) -> vk::RenderPass {
+    let attachments = [
+        vk::AttachmentDescription::builder()
+            .format(color_format)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(config.color.load.op())
+            .store_op(config.color.store_op())
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(config.color.initial_layout(final_layout))
+            .final_layout(final_layout)
+            .build(),
+        vk::AttachmentDescription::builder()
+            .format(depth_format)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(config.depth.load.op())
+            .store_op(config.depth.store_op())
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(
+                config
+                    .depth
+                    .initial_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL),
+            )
+            .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .build(),
+    ];
+    let color_attachment_refs = [vk::AttachmentReference::builder()
+        .attachment(0)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        .build()];
+    let depth_attachment_ref = vk::AttachmentReference::builder()
+        .attachment(1)
+        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .build();
+    let subpasses = [vk::SubpassDescription::builder()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .color_attachments(&color_attachment_refs)
+        .depth_stencil_attachment(&depth_attachment_ref)
+        .build()];
+
+    let dependencies = [
+        // Earlier reads of the colour have to finish before it is drawn over again, and earlier draws before
+        // what they drew is preserved
+        vk::SubpassDependency::builder()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .dst_subpass(0)
+            .src_stage_mask(
+                reader_stage
+                    | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            )
+            .src_access_mask(
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            )
+            .dst_stage_mask(
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+            )
+            .dst_access_mask(
+                vk::AccessFlags::COLOR_ATTACHMENT_READ
+                    | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            )
+            .build(),
+        vk::SubpassDependency::builder()
+            .src_subpass(0)
+            .dst_subpass(vk::SUBPASS_EXTERNAL)
+            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .dst_stage_mask(reader_stage)
+            .dst_access_mask(reader_access)
+            .build(),
+    ];
+
+    unsafe {
+        device
+            .create_render_pass(
+                &vk::RenderPassCreateInfo::builder()
+                    .attachments(&attachments)
+                    .subpasses(&subpasses)
+                    .dependencies(&dependencies),
+                None,
+            )
+            .expect("Offscreen render pass")
+    }
+}
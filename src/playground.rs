@@ -0,0 +1,546 @@
+use std::ffi::CString;
+use std::fs;
+use std::mem::size_of;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+
+use ash::vk;
+
+use crate::{memory, pipeline, present_thread, util, HelloTriangleApplication};
+
+/// Number of `iChannel` samplers available to sketches
+pub const CHANNEL_COUNT: usize = 4;
+
+/// Declarations put in front of a sketch so that it can be written as it would be on Shadertoy, by defining
+/// `mainImage`.
+const PRELUDE: &str = "#version 450
+
+layout(push_constant) uniform ShadertoyInputs {
+    vec4 iMouse;
+    vec3 iResolution;
+    float iTime;
+    float iTimeDelta;
+    int iFrame;
+} shadertoy;
+
+#define iMouse shadertoy.iMouse
+#define iResolution shadertoy.iResolution
+#define iTime shadertoy.iTime
+#define iTimeDelta shadertoy.iTimeDelta
+#define iFrame shadertoy.iFrame
+
+layout(binding = 0) uniform sampler2D iChannel0;
+layout(binding = 1) uniform sampler2D iChannel1;
+layout(binding = 2) uniform sampler2D iChannel2;
+layout(binding = 3) uniform sampler2D iChannel3;
+
+layout(location = 0) out vec4 shadertoyColor;
+";
+
+/// Calls the sketch's `mainImage` with Shadertoy's bottom-left origin for `fragCoord`
+const EPILOGUE: &str = "
+void main() {
+    vec2 fragCoord = vec2(gl_FragCoord.x, iResolution.y - gl_FragCoord.y);
+    mainImage(shadertoyColor, fragCoord);
+#ifdef SRGB_TARGET
+    // Shadertoy writes colours straight to the display, an sRGB attachment would encode them a second time
+    shadertoyColor.rgb = pow(max(shadertoyColor.rgb, vec3(0.0)), vec3(2.2));
+#endif
+    shadertoyColor.a = 1.0;
+}
+";
+
+/// Inputs shared with the sketch through push constants, laid out to match `ShadertoyInputs` in `PRELUDE`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Inputs {
+    /// xy is the cursor position while the left button is held, zw is where it was pressed. zw is negative
+    /// once the button is released.
+    mouse: [f32; 4],
+    resolution: [f32; 3],
+    time: f32,
+    time_delta: f32,
+    frame: i32,
+}
+
+impl Inputs {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+}
+
+/// What to run in playground mode: a Shadertoy style fragment shader and the images bound to its channels.
+#[derive(Clone, Debug)]
+pub struct Sketch {
+    pub source: PathBuf,
+    /// Up to `CHANNEL_COUNT` images. Unbound channels sample the renderer's default texture.
+    pub channels: Vec<String>,
+}
+
+/// How the sketch's channels are sampled: the texture unbound channels show and the sampler of every channel
+#[derive(Clone, Copy)]
+pub struct ChannelSampling {
+    pub default_texture: vk::ImageView,
+    pub sampler: vk::Sampler,
+}
+
+/// Renders a single full-screen fragment shader written for Shadertoy in place of the scene. The shader is
+/// compiled with `glslc` from the Vulkan SDK and recompiled whenever its file changes, keeping the last good
+/// version running when it fails to compile. Builds without the `runtime-shaders` feature can't compile it.
+pub struct Playground {
+    source: PathBuf,
+    modified: Option<SystemTime>,
+    /// Set when the sketch's file has changed since it was last compiled
+    needs_compile: bool,
+    fragment_code: Option<Vec<u32>>,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+    layout: vk::PipelineLayout,
+    /// Destroyed along with the swapchain and whenever the sketch is recompiled
+    pipeline: Option<vk::Pipeline>,
+    channels: Vec<(vk::Image, vk::DeviceMemory, vk::ImageView)>,
+    start_time: Instant,
+    last_frame: Instant,
+    frame: i32,
+    mouse: [f32; 4],
+    mouse_pressed: bool,
+}
+
+impl Playground {
+    pub fn new(
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        sketch: &Sketch,
+        sampling: ChannelSampling,
+        texture_budget: &mut memory::TextureBudget,
+    ) -> Self {
+        if sketch.channels.len() > CHANNEL_COUNT {
+            panic!("Sketches have at most {} channels", CHANNEL_COUNT)
+        }
+
+        let channels: Vec<(vk::Image, vk::DeviceMemory, vk::ImageView)> = sketch
+            .channels
+            .iter()
+            .map(|path| {
+                let (image, memory) = HelloTriangleApplication::create_texture_image(
+                    device,
+                    command_pool,
+                    queue,
+                    device_memory_properties,
+                    path.clone(),
+                    texture_budget,
+                );
+                let view = HelloTriangleApplication::create_texture_image_view(device, image);
+                (image, memory, view)
+            })
+            .collect();
+
+        let bindings: Vec<vk::DescriptorSetLayoutBinding> = (0..CHANNEL_COUNT)
+            .map(|binding| {
+                vk::DescriptorSetLayoutBinding::builder()
+                    .binding(binding as u32)
+                    .descriptor_count(1)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                    .build()
+            })
+            .collect();
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+                    None,
+                )
+                .expect("Playground descriptor set layout")
+        };
+
+        let pool_sizes = [vk::DescriptorPoolSize::builder()
+            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(CHANNEL_COUNT as u32)
+            .build()];
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::builder()
+                        .pool_sizes(&pool_sizes)
+                        .max_sets(1),
+                    None,
+                )
+                .expect("Playground descriptor pool")
+        };
+        let set_layouts = [descriptor_set_layout];
+        let descriptor_set = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::builder()
+                        .descriptor_pool(descriptor_pool)
+                        .set_layouts(&set_layouts),
+                )
+                .expect("Playground descriptor set")[0]
+        };
+
+        let image_infos: Vec<[vk::DescriptorImageInfo; 1]> = (0..CHANNEL_COUNT)
+            .map(|channel| {
+                [vk::DescriptorImageInfo::builder()
+                    .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                    .image_view(
+                        channels
+                            .get(channel)
+                            .map_or(sampling.default_texture, |&(_, _, view)| view),
+                    )
+                    .sampler(sampling.sampler)
+                    .build()]
+            })
+            .collect();
+        let writes: Vec<vk::WriteDescriptorSet> = image_infos
+            .iter()
+            .enumerate()
+            .map(|(channel, image_info)| {
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(channel as u32)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(image_info)
+                    .build()
+            })
+            .collect();
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .offset(0)
+            .size(size_of::<Inputs>() as u32)
+            .build()];
+        let layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::builder()
+                        .set_layouts(&set_layouts)
+                        .push_constant_ranges(&push_constant_ranges),
+                    None,
+                )
+                .expect("Playground pipeline layout")
+        };
+
+        println!("Running sketch {}", sketch.source.display());
+        Self {
+            source: sketch.source.clone(),
+            modified: None,
+            needs_compile: true,
+            fragment_code: None,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            layout,
+            pipeline: None,
+            channels,
+            start_time: Instant::now(),
+            last_frame: Instant::now(),
+            frame: 0,
+            mouse: [0.0, 0.0, -1.0, -1.0],
+            mouse_pressed: false,
+        }
+    }
+
+    /// Tracks the cursor for `iMouse`. Positions are in pixels from the top-left of a window `height` pixels
+    /// tall, `iMouse` has a bottom-left origin.
+    pub fn cursor_moved(&mut self, x: f32, y: f32, height: f32) {
+        if self.mouse_pressed {
+            self.mouse[0] = x;
+            self.mouse[1] = height - y;
+        }
+    }
+
+    /// Records presses and releases of the left mouse button for `iMouse`, at the given cursor position.
+    pub fn mouse_button(&mut self, pressed: bool, x: f32, y: f32, height: f32) {
+        self.mouse_pressed = pressed;
+        if pressed {
+            self.mouse = [x, height - y, x, height - y];
+        } else {
+            self.mouse[2] = -self.mouse[2].abs();
+            self.mouse[3] = -self.mouse[3].abs();
+        }
+    }
+
+    /// Recompiles the sketch if its file has changed, and creates the pipeline for the target if it doesn't
+    /// exist. Called before recording each frame.
+    pub fn prepare(&mut self, device: &ash::Device, target: &pipeline::Target) {
+        let modified = fs::metadata(&self.source)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if modified != self.modified {
+            self.modified = modified;
+            self.needs_compile = true;
+        }
+        if self.needs_compile {
+            self.needs_compile = false;
+            match self.compile(target) {
+                Ok(code) => {
+                    println!("Compiled {}", self.source.display());
+                    self.fragment_code = Some(code);
+                    // Earlier frames may still be drawing with the old pipeline
+                    present_thread::device_wait_idle(device).expect("Waiting for device idle");
+                    self.destroy_pipeline(device);
+                }
+                Err(e) => println!("Failed to compile {}:\n{}", self.source.display(), e),
+            }
+        }
+
+        if self.pipeline.is_none() {
+            if let Some(code) = &self.fragment_code {
+                self.pipeline = Some(create_pipeline(
+                    device,
+                    target,
+                    self.layout,
+                    code,
+                    false,
+                    false,
+                ));
+            }
+        }
+    }
+
+    /// Wraps the sketch so it is a complete fragment shader and compiles it to SPIR-V.
+    fn compile(&self, target: &pipeline::Target) -> Result<Vec<u32>, String> {
+        let sketch = fs::read_to_string(&self.source)
+            .map_err(|e| format!("Reading {}: {}", self.source.display(), e))?;
+        let srgb_target = if target.encode_srgb {
+            ""
+        } else {
+            "#define SRGB_TARGET\n"
+        };
+        // Reset line numbers so compile errors point at the sketch's own lines
+        let wrapped = format!(
+            "{}{}#line 1\n{}\n{}",
+            PRELUDE, srgb_target, sketch, EPILOGUE
+        );
+        glslc(wrapped, "frag")
+    }
+
+    /// Records the sketch into a command buffer inside the render pass, with the viewport already set.
+    pub fn record(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        extent: vk::Extent2D,
+    ) {
+        let now = Instant::now();
+        let inputs = Inputs {
+            mouse: self.mouse,
+            resolution: [extent.width as f32, extent.height as f32, 1.0],
+            time: (now - self.start_time).as_secs_f32(),
+            time_delta: (now - self.last_frame).as_secs_f32(),
+            frame: self.frame,
+        };
+        self.last_frame = now;
+        self.frame += 1;
+
+        let pipeline = match self.pipeline {
+            Some(pipeline) => pipeline,
+            // Nothing has compiled yet
+            None => return,
+        };
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                self.layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                inputs.as_bytes(),
+            );
+            // A single triangle covering the screen, generated by the vertex shader
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+
+    /// Destroys the pipeline so it is rebuilt by the next `prepare`. Called when the swapchain is recreated.
+    pub fn destroy_pipeline(&mut self, device: &ash::Device) {
+        if let Some(pipeline) = self.pipeline.take() {
+            unsafe { device.destroy_pipeline(pipeline, None) };
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        self.destroy_pipeline(device);
+        unsafe {
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            for (image, memory, view) in self.channels.drain(..) {
+                device.destroy_image_view(view, None);
+                device.destroy_image(image, None);
+                device.free_memory(memory, None);
+            }
+        }
+    }
+}
+
+/// Creates a pipeline that runs the fragment shader over the whole target. When `blend` is set the shader's
+/// output is blended over the attachment by its alpha, otherwise it replaces it. When `depth_bounds` is set
+/// pixels where the depth already drawn is outside the bounds set with `cmd_set_depth_bounds` are skipped, which
+/// needs the device's `depthBounds` feature.
+pub fn create_pipeline(
+    device: &ash::Device,
+    target: &pipeline::Target,
+    layout: vk::PipelineLayout,
+    fragment_code: &[u32],
+    blend: bool,
+    depth_bounds: bool,
+) -> vk::Pipeline {
+    create_masked_pipeline(
+        device,
+        target,
+        layout,
+        fragment_code,
+        blend,
+        depth_bounds,
+        vk::ColorComponentFlags::all(),
+    )
+}
+
+/// Creates a full-screen pipeline like `create_pipeline` that only writes the colour channels in `write_mask`.
+pub fn create_masked_pipeline(
+    device: &ash::Device,
+    target: &pipeline::Target,
+    layout: vk::PipelineLayout,
+    fragment_code: &[u32],
+    blend: bool,
+    depth_bounds: bool,
+    write_mask: vk::ColorComponentFlags,
+) -> vk::Pipeline {
+    let vert_path = Path::new(env!("OUT_DIR")).join("fullscreen_vert.spv");
+    let vert_shader_module =
+        pipeline::create_shader_module(device, &util::read_shader_code(&vert_path));
+    let frag_shader_module = pipeline::create_shader_module(device, fragment_code);
+
+    let main_fn_name = CString::new("main").unwrap();
+    let shader_stages = [
+        vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .module(vert_shader_module)
+            .name(main_fn_name.as_c_str())
+            .build(),
+        vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::FRAGMENT)
+            .module(frag_shader_module)
+            .name(main_fn_name.as_c_str())
+            .build(),
+    ];
+
+    // The full-screen triangle has no vertex data
+    let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder();
+    let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .primitive_restart_enable(false);
+
+    // The viewport is dynamic, set when recording
+    let viewports = [vk::Viewport::builder()
+        .width(target.extent.width as f32)
+        .height(target.extent.height as f32)
+        .max_depth(1.0)
+        .build()];
+    let scissors = [vk::Rect2D::builder().extent(target.extent).build()];
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
+        .viewports(&viewports)
+        .scissors(&scissors);
+
+    let rasterizer = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE);
+    let multisampling =
+        vk::PipelineMultisampleStateCreateInfo::builder().rasterization_samples(target.samples);
+    let color_blend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(write_mask)
+        .blend_enable(blend)
+        .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ONE)
+        .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
+        .alpha_blend_op(vk::BlendOp::ADD)
+        .build()];
+    let global_blend =
+        vk::PipelineColorBlendStateCreateInfo::builder().attachments(&color_blend_attachments);
+    // The shader covers the whole screen so there is nothing to depth test against
+    let depth_stencil_attachment = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(false)
+        .depth_write_enable(false)
+        .depth_bounds_test_enable(depth_bounds)
+        .max_depth_bounds(1.0);
+    let mut dynamic_states = vec![vk::DynamicState::VIEWPORT];
+    if depth_bounds {
+        dynamic_states.push(vk::DynamicState::DEPTH_BOUNDS);
+    }
+    let dynamic_state =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+
+    let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&shader_stages)
+        .vertex_input_state(&vertex_input_info)
+        .input_assembly_state(&input_assembly_info)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterizer)
+        .multisample_state(&multisampling)
+        .color_blend_state(&global_blend)
+        .depth_stencil_state(&depth_stencil_attachment)
+        .dynamic_state(&dynamic_state)
+        .layout(layout)
+        .render_pass(target.render_pass);
+
+    let pipelines = unsafe {
+        device
+            .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info.build()], None)
+            .expect("Full-screen pipeline")
+    };
+
+    unsafe {
+        device.destroy_shader_module(vert_shader_module, None);
+        device.destroy_shader_module(frag_shader_module, None);
+    }
+
+    pipelines[0]
+}
+
+/// Compiles a shader to SPIR-V with `glslc`. `stage` is the stage's file extension for `glslc`, such as `frag`
+/// or `vert`.
+#[cfg(feature = "runtime-shaders")]
+pub fn glslc(source: String, stage: &str) -> Result<Vec<u32>, String> {
+    let directory = std::env::temp_dir();
+    let wrapped_path = directory.join(format!("rust-renderer-vk-shader.{}", stage));
+    let spirv_path = directory.join(format!("rust-renderer-vk-shader-{}.spv", stage));
+    fs::write(&wrapped_path, source)
+        .map_err(|e| format!("Writing {}: {}", wrapped_path.display(), e))?;
+
+    let output = std::process::Command::new("glslc")
+        .arg(&wrapped_path)
+        .arg("-o")
+        .arg(&spirv_path)
+        .output()
+        .map_err(|e| format!("Running glslc from the Vulkan SDK: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+    }
+
+    Ok(util::read_shader_code(Path::new(&spirv_path)))
+}
+
+#[cfg(not(feature = "runtime-shaders"))]
+pub fn glslc(_source: String, _stage: &str) -> Result<Vec<u32>, String> {
+    Err(String::from(
+        "GLSL can't be compiled without the `runtime-shaders` feature",
+    ))
+}
@@ -0,0 +1,629 @@
+use std::fs;
+use std::mem::size_of;
+use std::path::Path;
+
+use ash::vk;
+use cgmath::{Matrix4, Point3, Vector3};
+use memoffset::offset_of;
+
+use crate::{bounds, draw, pipeline, units};
+
+/// Most points each chunk is aimed to hold. Chunks are culled against the view frustum one at a time, so smaller
+/// chunks cull more tightly at the cost of more draws.
+const CHUNK_POINTS: usize = 32 * 1024;
+/// Number of vertices drawn per surfel. The vertex shader expands them into two triangles covering the disc.
+pub const VERTICES_PER_SURFEL: u32 = 6;
+
+/// A point of a scan. Points without a colour are white, and points without a normal have a zero normal.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Point {
+    pub position: [f32; 3],
+    /// Linear colour
+    pub color: [f32; 3],
+    pub normal: [f32; 3],
+}
+
+impl Point {
+    /// Points are read once per vertex when drawn as sprites, and once per instance when drawn as surfels
+    pub fn get_binding_description(
+        input_rate: vk::VertexInputRate,
+    ) -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(size_of::<Self>() as u32)
+            .input_rate(input_rate)
+            .build()
+    }
+
+    pub fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescription; 3] {
+        let attribute = |location: u32, offset: usize| {
+            vk::VertexInputAttributeDescription::builder()
+                .binding(0)
+                .location(location)
+                .format(vk::Format::R32G32B32_SFLOAT)
+                .offset(offset as u32)
+                .build()
+        };
+        [
+            attribute(0, offset_of!(Self, position)),
+            attribute(1, offset_of!(Self, color)),
+            attribute(2, offset_of!(Self, normal)),
+        ]
+    }
+}
+
+/// How each point of a cloud is drawn
+#[derive(Clone, Copy, Debug)]
+pub enum Mode {
+    /// Round point sprites, `size` pixels across at one unit from the camera and smaller further away
+    Sprites { size: f32 },
+    /// Discs `radius` units across in the cloud's own space, turned to face along each point's normal. Points
+    /// without normals face the camera. Slower than sprites but closes the gaps between points up close.
+    Surfels { radius: f32 },
+}
+
+impl Mode {
+    /// Accepts sprites and surfels, sized to suit the cloud
+    pub fn parse(name: &str, cloud: &PointCloud) -> Option<Self> {
+        match name {
+            "sprites" => Some(Mode::Sprites { size: 4.0 }),
+            "surfels" => Some(Mode::Surfels {
+                radius: cloud.spacing(),
+            }),
+            _ => None,
+        }
+    }
+
+    fn pipeline(&self) -> pipeline::Config {
+        match self {
+            Mode::Sprites { .. } => pipeline::Config::point_sprites(),
+            Mode::Surfels { .. } => pipeline::Config::surfels(),
+        }
+    }
+}
+
+/// A run of the cloud's points that are near each other, drawn and culled together
+#[derive(Clone, Copy, Debug)]
+pub struct Chunk {
+    pub first: u32,
+    pub count: u32,
+    pub bounds: bounds::Aabb,
+}
+
+/// Points, such as those of a laser scan, ordered so that each chunk's points are next to each other
+pub struct PointCloud {
+    pub points: Vec<Point>,
+    pub chunks: Vec<Chunk>,
+    pub bounds: bounds::Aabb,
+    /// Which axis is up in the file. PLY has no convention, so it is taken to be Y, as in most scanned models,
+    /// and LAS is Z, as georeferenced coordinates are.
+    pub up: units::Up,
+}
+
+impl PointCloud {
+    /// Sorts the points into chunks on a grid over their bounds. Fails if there are no points.
+    pub fn new(mut points: Vec<Point>) -> Result<Self, String> {
+        let bounds =
+            bounds::Aabb::from_points(points.iter().map(|point| Point3::from(point.position)))
+                .ok_or("Point clouds need at least one point")?;
+
+        // Roughly CHUNK_POINTS in each cell if the points were spread evenly
+        let cells = points.len().div_ceil(CHUNK_POINTS);
+        let per_axis = (cells as f32).cbrt().ceil().max(1.0) as usize;
+        let size = bounds.max - bounds.min;
+        let cell = |point: &Point| {
+            let mut index = 0;
+            for axis in (0..3).rev() {
+                let along = if size[axis] > 0.0 {
+                    (point.position[axis] - bounds.min[axis]) / size[axis]
+                } else {
+                    0.0
+                };
+                let coordinate = ((along * per_axis as f32) as usize).min(per_axis - 1);
+                index = index * per_axis + coordinate;
+            }
+            index
+        };
+        points.sort_by_cached_key(cell);
+
+        let mut chunks: Vec<Chunk> = Vec::new();
+        let mut start = 0;
+        while start < points.len() {
+            let start_cell = cell(&points[start]);
+            let end = start
+                + points[start..]
+                    .iter()
+                    .position(|point| cell(point) != start_cell)
+                    .unwrap_or(points.len() - start);
+            // Clumped points can crowd one cell, which is split so no chunk is much larger than the rest
+            for first in (start..end).step_by(CHUNK_POINTS) {
+                let last = (first + CHUNK_POINTS).min(end);
+                chunks.push(Chunk {
+                    first: first as u32,
+                    count: (last - first) as u32,
+                    bounds: bounds::Aabb::from_points(
+                        points[first..last]
+                            .iter()
+                            .map(|point| Point3::from(point.position)),
+                    )
+                    .expect("chunks aren't empty"),
+                });
+            }
+            start = end;
+        }
+
+        Ok(Self {
+            points,
+            chunks,
+            bounds,
+            up: units::Up::Z,
+        })
+    }
+
+    /// Reads the vertices of an ASCII or binary PLY file, with their colours and normals if it has them. Any
+    /// faces are ignored.
+    pub fn load_ply(path: &Path) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("Reading {}: {}", path.display(), e))?;
+        Ok(Self {
+            up: units::Up::Y,
+            ..Self::new(ply::read(&bytes)?)?
+        })
+    }
+
+    /// Reads the points of a LAS file, coloured by their RGB if the point format has it and by their intensity
+    /// otherwise. Positions are relative to the file's offset, since georeferenced coordinates are too large
+    /// to keep their precision as single precision floats.
+    pub fn load_las(path: &Path) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("Reading {}: {}", path.display(), e))?;
+        Self::new(las::read(&bytes)?)
+    }
+
+    /// Loads a PLY or LAS file depending on its extension
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("ply") => Self::load_ply(path),
+            Some("las") => Self::load_las(path),
+            _ => Err(format!("{} isn't a PLY or LAS file", path.display())),
+        }
+    }
+
+    /// A guess at the distance between neighbouring points, assuming they sample surfaces through the bounds
+    pub fn spacing(&self) -> f32 {
+        let size = self.bounds.max - self.bounds.min;
+        let area = 2.0 * (size.x * size.y + size.y * size.z + size.z * size.x);
+        (area / self.points.len() as f32).sqrt().max(f32::EPSILON)
+    }
+
+    /// Scales the cloud so its longest side is one unit long and centres it on the origin. Scans are usually
+    /// in metres far from the origin, well outside the camera's range.
+    pub fn fit_transform(&self) -> Matrix4<f32> {
+        let size = self.bounds.max - self.bounds.min;
+        let longest = size.x.max(size.y).max(size.z).max(f32::EPSILON);
+        let center = self.bounds.center();
+        Matrix4::from_scale(1.0 / longest)
+            * Matrix4::from_translation(Point3::new(0.0, 0.0, 0.0) - center)
+    }
+
+    /// One draw of each chunk from `vertex_buffer`, which holds the cloud's points
+    pub fn draws(
+        &self,
+        vertex_buffer: vk::Buffer,
+        mode: Mode,
+        transform: Matrix4<f32>,
+    ) -> Vec<draw::Draw> {
+        self.chunks
+            .iter()
+            .map(|chunk| {
+                let draw = match mode {
+                    Mode::Sprites { size } => draw::Draw {
+                        vertex_offset: chunk.first as i32,
+                        point_size: size,
+                        ..draw::Draw::new(mode.pipeline(), vertex_buffer, chunk.count)
+                    },
+                    // Surfels are instanced, with the chunk's points selected by the first instance
+                    Mode::Surfels { radius } => draw::Draw {
+                        instance_count: chunk.count,
+                        first_instance: chunk.first,
+                        point_size: radius,
+                        ..draw::Draw::new(mode.pipeline(), vertex_buffer, VERTICES_PER_SURFEL)
+                    },
+                };
+                let bounds = match mode {
+                    Mode::Sprites { .. } => chunk.bounds,
+                    Mode::Surfels { radius } => {
+                        let padding = Vector3::new(radius, radius, radius);
+                        bounds::Aabb {
+                            min: chunk.bounds.min - padding,
+                            max: chunk.bounds.max + padding,
+                        }
+                    }
+                };
+                draw::Draw {
+                    transform,
+                    bounds: Some(bounds::Bounds::new(bounds)),
+                    ..draw
+                }
+            })
+            .collect()
+    }
+}
+
+/// The PLY format, see http://paulbourke.net/dataformats/ply/
+mod ply {
+    use super::Point;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum Format {
+        Ascii,
+        BinaryLittleEndian,
+        BinaryBigEndian,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum Scalar {
+        I8,
+        U8,
+        I16,
+        U16,
+        I32,
+        U32,
+        F32,
+        F64,
+    }
+
+    impl Scalar {
+        fn parse(name: &str) -> Result<Self, String> {
+            match name {
+                "char" | "int8" => Ok(Scalar::I8),
+                "uchar" | "uint8" => Ok(Scalar::U8),
+                "short" | "int16" => Ok(Scalar::I16),
+                "ushort" | "uint16" => Ok(Scalar::U16),
+                "int" | "int32" => Ok(Scalar::I32),
+                "uint" | "uint32" => Ok(Scalar::U32),
+                "float" | "float32" => Ok(Scalar::F32),
+                "double" | "float64" => Ok(Scalar::F64),
+                _ => Err(format!("Unknown PLY property type {}", name)),
+            }
+        }
+
+        fn size(&self) -> usize {
+            match self {
+                Scalar::I8 | Scalar::U8 => 1,
+                Scalar::I16 | Scalar::U16 => 2,
+                Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+                Scalar::F64 => 8,
+            }
+        }
+
+        /// What a colour channel of this type is scaled by to be from 0 to 1
+        fn color_scale(&self) -> f64 {
+            match self {
+                Scalar::U8 | Scalar::I8 => 1.0 / 255.0,
+                Scalar::U16 | Scalar::I16 => 1.0 / 65535.0,
+                _ => 1.0,
+            }
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    enum Property {
+        Scalar(String, Scalar),
+        /// Lists aren't read, only skipped
+        List(Scalar, Scalar),
+    }
+
+    struct Element {
+        name: String,
+        count: usize,
+        properties: Vec<Property>,
+    }
+
+    /// Reads values one after another from the body, in whichever format it is in
+    struct Reader<'a> {
+        format: Format,
+        bytes: &'a [u8],
+        offset: usize,
+        /// The body's words, for ASCII files
+        words: std::str::SplitAsciiWhitespace<'a>,
+    }
+
+    impl<'a> Reader<'a> {
+        fn read(&mut self, scalar: Scalar) -> Result<f64, String> {
+            if self.format == Format::Ascii {
+                return self
+                    .words
+                    .next()
+                    .ok_or("The PLY file ends early")?
+                    .parse()
+                    .map_err(|e| format!("PLY value: {}", e));
+            }
+            let size = scalar.size();
+            let bytes = self
+                .bytes
+                .get(self.offset..self.offset + size)
+                .ok_or("The PLY file ends early")?;
+            self.offset += size;
+            let mut a = [0; 8];
+            a[..size].copy_from_slice(bytes);
+            if self.format == Format::BinaryBigEndian {
+                a[..size].reverse();
+            }
+            Ok(match scalar {
+                Scalar::I8 => a[0] as i8 as f64,
+                Scalar::U8 => a[0] as f64,
+                Scalar::I16 => i16::from_le_bytes([a[0], a[1]]) as f64,
+                Scalar::U16 => u16::from_le_bytes([a[0], a[1]]) as f64,
+                Scalar::I32 => i32::from_le_bytes([a[0], a[1], a[2], a[3]]) as f64,
+                Scalar::U32 => u32::from_le_bytes([a[0], a[1], a[2], a[3]]) as f64,
+                Scalar::F32 => f32::from_le_bytes([a[0], a[1], a[2], a[3]]) as f64,
+                Scalar::F64 => f64::from_le_bytes(a),
+            })
+        }
+    }
+
+    pub fn read(bytes: &[u8]) -> Result<Vec<Point>, String> {
+        if !bytes.starts_with(b"ply") {
+            return Err("Not a PLY file".to_string());
+        }
+        let end_header = b"end_header";
+        let header_length = bytes
+            .windows(end_header.len())
+            .position(|window| window == end_header)
+            .ok_or("The PLY header has no end")?;
+        let body_start = bytes[header_length..]
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .map_or(bytes.len(), |newline| header_length + newline + 1);
+        let header = String::from_utf8_lossy(&bytes[..header_length]);
+
+        let mut format = None;
+        let mut elements: Vec<Element> = Vec::new();
+        for line in header.lines() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["format", "ascii", ..] => format = Some(Format::Ascii),
+                ["format", "binary_little_endian", ..] => format = Some(Format::BinaryLittleEndian),
+                ["format", "binary_big_endian", ..] => format = Some(Format::BinaryBigEndian),
+                ["element", name, count] => elements.push(Element {
+                    name: name.to_string(),
+                    count: count
+                        .parse()
+                        .map_err(|e| format!("PLY element count: {}", e))?,
+                    properties: Vec::new(),
+                }),
+                ["property", "list", count, item, _] => elements
+                    .last_mut()
+                    .ok_or("PLY property before any element")?
+                    .properties
+                    .push(Property::List(Scalar::parse(count)?, Scalar::parse(item)?)),
+                ["property", scalar, name] => elements
+                    .last_mut()
+                    .ok_or("PLY property before any element")?
+                    .properties
+                    .push(Property::Scalar(name.to_string(), Scalar::parse(scalar)?)),
+                _ => {}
+            }
+        }
+        let format = format.ok_or("The PLY header has no format")?;
+
+        let body = &bytes[body_start..];
+        let mut reader = Reader {
+            format,
+            bytes: body,
+            offset: 0,
+            words: if format == Format::Ascii {
+                std::str::from_utf8(body)
+                    .map_err(|e| format!("PLY body: {}", e))?
+                    .split_ascii_whitespace()
+            } else {
+                "".split_ascii_whitespace()
+            },
+        };
+        for element in elements.iter() {
+            if element.name == "vertex" {
+                return read_vertices(&mut reader, element);
+            }
+            // Elements before the vertices have to be read past
+            for _ in 0..element.count {
+                for property in element.properties.iter() {
+                    match property {
+                        Property::Scalar(_, scalar) => {
+                            reader.read(*scalar)?;
+                        }
+                        Property::List(count, item) => {
+                            for _ in 0..reader.read(*count)? as usize {
+                                reader.read(*item)?;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Err("The PLY file has no vertices".to_string())
+    }
+
+    fn read_vertices(reader: &mut Reader, element: &Element) -> Result<Vec<Point>, String> {
+        let find = |wanted: &[&str]| {
+            element
+                .properties
+                .iter()
+                .position(|property| match property {
+                    Property::Scalar(name, _) => wanted.contains(&name.as_str()),
+                    Property::List(..) => false,
+                })
+        };
+        let position = [find(&["x"]), find(&["y"]), find(&["z"])];
+        if position.iter().any(Option::is_none) {
+            return Err("PLY vertices need x, y and z".to_string());
+        }
+        let color = [
+            find(&["red", "r", "diffuse_red"]),
+            find(&["green", "g", "diffuse_green"]),
+            find(&["blue", "b", "diffuse_blue"]),
+        ];
+        let normal = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+
+        let mut values = vec![0.0; element.properties.len()];
+        let mut points = Vec::with_capacity(element.count);
+        for _ in 0..element.count {
+            for (value, property) in values.iter_mut().zip(element.properties.iter()) {
+                *value = match property {
+                    Property::Scalar(_, scalar) => reader.read(*scalar)?,
+                    Property::List(count, item) => {
+                        for _ in 0..reader.read(*count)? as usize {
+                            reader.read(*item)?;
+                        }
+                        0.0
+                    }
+                };
+            }
+            let get = |index: Option<usize>, default: f64| index.map_or(default, |i| values[i]);
+            let channel = |index: Option<usize>| match index {
+                Some(i) => match &element.properties[i] {
+                    Property::Scalar(_, scalar) => {
+                        // Colours are written in sRGB
+                        srgb_to_linear((values[i] * scalar.color_scale()) as f32)
+                    }
+                    Property::List(..) => 1.0,
+                },
+                None => 1.0,
+            };
+            points.push(Point {
+                position: [
+                    get(position[0], 0.0) as f32,
+                    get(position[1], 0.0) as f32,
+                    get(position[2], 0.0) as f32,
+                ],
+                color: [channel(color[0]), channel(color[1]), channel(color[2])],
+                normal: [
+                    get(normal[0], 0.0) as f32,
+                    get(normal[1], 0.0) as f32,
+                    get(normal[2], 0.0) as f32,
+                ],
+            });
+        }
+        Ok(points)
+    }
+
+    pub fn srgb_to_linear(value: f32) -> f32 {
+        if value <= 0.04045 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    }
+}
+
+/// The ASPRS LAS format, versions 1.0 to 1.4
+mod las {
+    use super::{ply::srgb_to_linear, Point};
+
+    fn field<const N: usize>(bytes: &[u8], offset: usize) -> Result<[u8; N], String> {
+        let mut field = [0; N];
+        field.copy_from_slice(
+            bytes
+                .get(offset..offset + N)
+                .ok_or("The LAS file ends early")?,
+        );
+        Ok(field)
+    }
+
+    pub fn read(bytes: &[u8]) -> Result<Vec<Point>, String> {
+        if !bytes.starts_with(b"LASF") {
+            return Err("Not a LAS file".to_string());
+        }
+        let version_minor = field::<1>(bytes, 25)?[0];
+        let point_offset = u32::from_le_bytes(field(bytes, 96)?) as usize;
+        // The top bits flag compression, which isn't supported
+        let point_format = field::<1>(bytes, 104)?[0];
+        if point_format & 0xc0 != 0 {
+            return Err("Compressed LAS (LAZ) files aren't supported".to_string());
+        }
+        let record_length = u16::from_le_bytes(field(bytes, 105)?) as usize;
+        let mut count = u32::from_le_bytes(field(bytes, 107)?) as usize;
+        if count == 0 && version_minor >= 4 {
+            count = u64::from_le_bytes(field(bytes, 247)?) as usize;
+        }
+        let mut scale = [0.0; 3];
+        for (axis, scale) in scale.iter_mut().enumerate() {
+            *scale = f64::from_le_bytes(field(bytes, 131 + 8 * axis)?);
+        }
+        // Where each point format keeps its colour, if it has one
+        let color_offset = match point_format {
+            2 => Some(20),
+            3 | 5 => Some(28),
+            7 | 8 | 10 => Some(30),
+            0 | 1 | 4 | 6 | 9 => None,
+            _ => return Err(format!("Unknown LAS point format {}", point_format)),
+        };
+        if record_length < color_offset.map_or(14, |offset| offset + 6) {
+            return Err(format!(
+                "LAS point records of {} bytes are too short for point format {}",
+                record_length, point_format
+            ));
+        }
+        let records = bytes
+            .get(point_offset..point_offset + count * record_length)
+            .ok_or("The LAS file ends before its last point")?;
+
+        let records: Vec<&[u8]> = records.chunks_exact(record_length).collect();
+        let u16_at =
+            |record: &[u8], offset: usize| u16::from_le_bytes([record[offset], record[offset + 1]]);
+        let max_intensity = records
+            .iter()
+            .map(|record| u16_at(record, 12))
+            .max()
+            .unwrap_or(0)
+            .max(1) as f32;
+        // Colours are sometimes stored in the low byte only, despite the format's 16 bits
+        let color_scale = match color_offset {
+            Some(color_offset)
+                if records.iter().all(|record| {
+                    (0..3).all(|channel| u16_at(record, color_offset + 2 * channel) < 256)
+                }) =>
+            {
+                1.0 / 255.0
+            }
+            _ => 1.0 / 65535.0,
+        };
+
+        Ok(records
+            .iter()
+            .map(|record| {
+                let coordinate = |axis: usize| {
+                    let raw = i32::from_le_bytes([
+                        record[4 * axis],
+                        record[4 * axis + 1],
+                        record[4 * axis + 2],
+                        record[4 * axis + 3],
+                    ]);
+                    (raw as f64 * scale[axis]) as f32
+                };
+                let color = match color_offset {
+                    Some(color_offset) => {
+                        let channel = |channel: usize| {
+                            srgb_to_linear(
+                                u16_at(record, color_offset + 2 * channel) as f32 * color_scale,
+                            )
+                        };
+                        [channel(0), channel(1), channel(2)]
+                    }
+                    None => {
+                        let intensity = srgb_to_linear(u16_at(record, 12) as f32 / max_intensity);
+                        [intensity, intensity, intensity]
+                    }
+                };
+                Point {
+                    position: [coordinate(0), coordinate(1), coordinate(2)],
+                    color,
+                    normal: [0.0; 3],
+                }
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,277 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Transform, Vector4};
+
+use crate::bounds;
+
+/// The first line of every portal file, naming the version of the format
+const HEADER: &str = "rust-renderer-vk portals 1";
+/// Portals followed from the camera's cell before giving up and treating the rest as hidden
+const MAX_DEPTH: usize = 16;
+/// How close in world units the camera can come to a portal's plane before it is looked through unnarrowed, since
+/// planes through the edges of a portal the camera is in are degenerate
+const PLANE_EPSILON: f32 = 1e-4;
+
+/// An opening between two cells, a convex polygon in the scene's space
+#[derive(Clone, Debug)]
+pub struct Portal {
+    pub cells: [usize; 2],
+    pub corners: Vec<Point3<f32>>,
+}
+
+/// Rooms of an indoor scene, as boxes in the scene's space, connected by the portals they can be seen through.
+/// The draws inside a cell are only drawn when the cell can be seen from the camera's cell through a chain of
+/// portals, clipping the view to each portal along the way. Draws outside every cell, and every draw when the
+/// camera is outside every cell, are frustum culled as usual.
+///
+/// Portal files are text, one cell or portal per line:
+///
+/// ```text
+/// rust-renderer-vk portals 1
+/// cell <min x> <y> <z> <max x> <y> <z>
+/// portal <cell> <cell> <corner x> <y> <z> <corner x> <y> <z> <corner x> <y> <z> [<corner x> <y> <z>...]
+/// ```
+///
+/// Cells are numbered from zero in the order they are listed, and a portal's corners go around its edge.
+/// Lines starting with `#` are ignored.
+#[derive(Clone, Debug, Default)]
+pub struct Portals {
+    pub cells: Vec<bounds::Aabb>,
+    pub portals: Vec<Portal>,
+}
+
+/// A convex volume the camera sees a cell through, as the planes bounding it, with normals pointing inwards.
+#[derive(Clone, Debug)]
+struct View {
+    planes: Vec<Vector4<f32>>,
+}
+
+impl View {
+    fn intersects(&self, aabb: &bounds::Aabb) -> bool {
+        self.planes.iter().all(|plane| aabb.in_front_of(plane))
+    }
+}
+
+/// Which cells can be seen from the camera in a frame, and through what.
+pub struct Visibility {
+    /// The cells in world space
+    cells: Vec<bounds::Aabb>,
+    /// The views the camera sees each cell through, none for cells it can't see
+    views: Vec<Vec<View>>,
+}
+
+impl Visibility {
+    /// Whether any of the box, in world space, may be seen. Boxes belong to the first cell that contains their
+    /// centre, and those outside every cell are left to frustum culling.
+    pub fn may_see(&self, aabb: &bounds::Aabb) -> bool {
+        let center = aabb.center();
+        match self.cells.iter().position(|cell| cell.contains(center)) {
+            Some(cell) => self.views[cell].iter().any(|view| view.intersects(aabb)),
+            None => true,
+        }
+    }
+}
+
+impl Portals {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text =
+            fs::read_to_string(path).map_err(|e| format!("Reading {}: {}", path.display(), e))?;
+        Self::from_text(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn from_text(text: &str) -> Result<Self, String> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(number, line)| (number + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+        match lines.next() {
+            Some((_, HEADER)) => {}
+            _ => return Err(format!("Portal files start with \"{}\"", HEADER)),
+        }
+
+        let mut portals = Self::default();
+        for (number, line) in lines {
+            let mut fields = line.split_whitespace();
+            let key = fields.next().unwrap_or_default();
+            let values = fields
+                .map(|value| parse(number, value))
+                .collect::<Result<Vec<f32>, String>>()?;
+            let point = |values: &[f32]| Point3::new(values[0], values[1], values[2]);
+            match key {
+                "cell" => {
+                    if values.len() != 6 {
+                        return Err(format!("Line {}: cells need two corners", number));
+                    }
+                    portals.cells.push(bounds::Aabb {
+                        min: point(&values[..3]),
+                        max: point(&values[3..]),
+                    });
+                }
+                "portal" => {
+                    if values.len() < 11 || (values.len() - 2) % 3 != 0 {
+                        return Err(format!(
+                            "Line {}: portals need two cells and at least three corners",
+                            number
+                        ));
+                    }
+                    let cells = [values[0] as usize, values[1] as usize];
+                    if cells.iter().any(|&cell| cell >= portals.cells.len()) {
+                        return Err(format!(
+                            "Line {}: portals must connect cells listed before them",
+                            number
+                        ));
+                    }
+                    portals.portals.push(Portal {
+                        cells,
+                        corners: values[2..].chunks_exact(3).map(point).collect(),
+                    });
+                }
+                other => return Err(format!("Line {}: unknown setting {}", number, other)),
+            }
+        }
+        Ok(portals)
+    }
+
+    /// Which cells the camera at `eye` sees through the `frustum`, from the cell it is in. The cells and portals
+    /// are placed in the world by the scene's `model` transform. Returns `None` when the camera is outside every
+    /// cell.
+    pub fn visibility(
+        &self,
+        model: &Matrix4<f32>,
+        eye: Point3<f32>,
+        frustum: &bounds::Frustum,
+    ) -> Option<Visibility> {
+        let cells: Vec<bounds::Aabb> = self
+            .cells
+            .iter()
+            .map(|cell| cell.transformed(model))
+            .collect();
+        let start = cells.iter().position(|cell| cell.contains(eye))?;
+        let portals: Vec<Portal> = self
+            .portals
+            .iter()
+            .map(|portal| Portal {
+                cells: portal.cells,
+                corners: portal
+                    .corners
+                    .iter()
+                    .map(|&corner| model.transform_point(corner))
+                    .collect(),
+            })
+            .collect();
+
+        let mut visibility = Visibility {
+            views: vec![Vec::new(); cells.len()],
+            cells,
+        };
+        let mut path = vec![start];
+        visit(
+            &portals,
+            eye,
+            View {
+                planes: frustum.planes.to_vec(),
+            },
+            &mut path,
+            &mut visibility.views,
+        );
+        Some(visibility)
+    }
+}
+
+/// Records that the last cell of the path is seen through the view, then looks through each of its portals to
+/// cells not already on the path, narrowing the view to the part of the portal it can see.
+fn visit(
+    portals: &[Portal],
+    eye: Point3<f32>,
+    view: View,
+    path: &mut Vec<usize>,
+    views: &mut [Vec<View>],
+) {
+    let cell = *path.last().expect("The path starts at the camera's cell");
+    if path.len() <= MAX_DEPTH {
+        for portal in portals.iter().filter(|portal| portal.cells.contains(&cell)) {
+            let next = portal.cells[(portal.cells[0] == cell) as usize];
+            if path.contains(&next) {
+                continue;
+            }
+            let visible = view
+                .planes
+                .iter()
+                .fold(portal.corners.clone(), |polygon, plane| {
+                    clip(&polygon, plane)
+                });
+            if visible.len() < 3 {
+                continue;
+            }
+            path.push(next);
+            visit(portals, eye, narrow(&view, &visible, eye), path, views);
+            path.pop();
+        }
+    }
+    views[cell].push(view);
+}
+
+/// The view through the visible part of a portal: bounded by the planes through the eye and each of its edges,
+/// and by the portal's own plane so that nothing between the eye and the portal is seen through it
+fn narrow(view: &View, polygon: &[Point3<f32>], eye: Point3<f32>) -> View {
+    let centroid = Point3::centroid(polygon);
+    let normal = (polygon[1] - polygon[0]).cross(polygon[2] - polygon[0]);
+    if normal.magnitude2() == 0.0 {
+        return view.clone();
+    }
+    let normal = normal.normalize();
+    // Facing away from the eye
+    let normal = if normal.dot(centroid - eye) < 0.0 {
+        -normal
+    } else {
+        normal
+    };
+    if normal.dot(centroid - eye) < PLANE_EPSILON {
+        return view.clone();
+    }
+
+    let mut planes = vec![normal.extend(-normal.dot(centroid.to_vec()))];
+    for (i, &a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+        let edge_normal = (a - eye).cross(b - eye);
+        if edge_normal.magnitude2() == 0.0 {
+            continue;
+        }
+        let edge_normal = edge_normal.normalize();
+        // Facing into the portal
+        let edge_normal = if edge_normal.dot(centroid - eye) < 0.0 {
+            -edge_normal
+        } else {
+            edge_normal
+        };
+        planes.push(edge_normal.extend(-edge_normal.dot(eye.to_vec())));
+    }
+    View { planes }
+}
+
+/// The part of the convex polygon on the side of the plane its normal points to
+fn clip(polygon: &[Point3<f32>], plane: &Vector4<f32>) -> Vec<Point3<f32>> {
+    let distance = |point: Point3<f32>| plane.truncate().dot(point.to_vec()) + plane.w;
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+    for (i, &a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+        let (a_distance, b_distance) = (distance(a), distance(b));
+        if a_distance >= 0.0 {
+            clipped.push(a);
+        }
+        if (a_distance >= 0.0) != (b_distance >= 0.0) {
+            let t = a_distance / (a_distance - b_distance);
+            clipped.push(a + (b - a) * t);
+        }
+    }
+    clipped
+}
+
+fn parse<T: FromStr>(number: usize, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Line {}: {} isn't a valid number", number, value))
+}
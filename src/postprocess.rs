@@ -0,0 +1,683 @@
+use std::any::Any;
+use std::ffi::CString;
+use std::path::Path;
+
+use ash::vk;
+
+use crate::features::{RenderFeature, SwapchainContext};
+use crate::{framegraph, hooks, scope, util, HelloTriangleApplication, ImageDescription};
+
+/// Filters are dispatched in 8x8 workgroups, matching `local_size_x` and `local_size_y` in their shaders
+pub const WORKGROUP_SIZE: u32 = 8;
+
+/// Format of the images filters read and write, declared as `rgba16f` in their shaders. Storage support for
+/// it is required by Vulkan, and it keeps the precision of linear colour read from an sRGB swapchain.
+const IMAGE_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+/// Swapchain image usage needed to copy the rendered frame to the filters and the result back
+pub const SWAPCHAIN_USAGE: vk::ImageUsageFlags = vk::ImageUsageFlags::from_raw(
+    vk::ImageUsageFlags::TRANSFER_SRC.as_raw() | vk::ImageUsageFlags::TRANSFER_DST.as_raw(),
+);
+
+/// A compute shader run over the whole frame. It reads the frame from `layout(binding = 0, rgba16f) uniform
+/// readonly image2D` and writes its result to `layout(binding = 1, rgba16f) uniform writeonly image2D`. The
+/// exposure in stops is pushed as a `float` constant, followed by the god rays' `float` strength and `vec2`
+/// light position, laid out like `Constants`, which filters can ignore.
+pub struct Filter {
+    pub name: String,
+    pub enabled: bool,
+    pipeline: vk::Pipeline,
+}
+
+/// A bright light in the frame, like the sun, that god rays streak out from
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Light {
+    /// Where the light is as a fraction of the frame's width and height, from the top left
+    pub position: [f32; 2],
+    /// How brightly it shines through the frame, already scaled by how much of it can be seen
+    pub strength: f32,
+}
+
+/// The push constants every post processing pipeline is given
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Constants {
+    exposure: f32,
+    /// The god rays' strength times the light's, zero when they are off
+    light_strength: f32,
+    light_position: [f32; 2],
+}
+
+impl Constants {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(
+                self as *const Self as *const u8,
+                std::mem::size_of::<Self>(),
+            )
+        }
+    }
+}
+
+/// The images filters ping-pong between, sized to the swapchain.
+struct Targets {
+    extent: vk::Extent2D,
+    images: [(vk::Image, vk::DeviceMemory, vk::ImageView); 2],
+    descriptor_pool: vk::DescriptorPool,
+    /// Set `i` reads image `i` and writes the other
+    descriptor_sets: [vk::DescriptorSet; 2],
+    /// Set `i` draws the scope over image `i`
+    scope_descriptor_sets: [vk::DescriptorSet; 2],
+    /// Whether the swapchain encodes the images' linear colour to sRGB
+    linear_frame: bool,
+}
+
+/// Compute filters applied in registration order to the rendered frame before it is presented, e.g. to
+/// sharpen, posterize or detect edges. The frame is blitted out of the swapchain image into a storage image,
+/// each enabled filter writes the input of the next and the last result is blitted back. A colour scope can
+/// be drawn over the result.
+pub struct PostProcess {
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    layout: vk::PipelineLayout,
+    filters: Vec<Filter>,
+    /// Brightens or darkens the frame by this many stops before the filters run, when it isn't zero
+    pub exposure: f32,
+    exposure_pipeline: vk::Pipeline,
+    /// How strongly `light` streaks out through the gaps in the scene after exposure, off when zero
+    pub god_rays: f32,
+    /// Turns the god rays off without losing their strength, for quality presets
+    pub god_rays_enabled: bool,
+    /// Set by the renderer each frame
+    pub light: Light,
+    god_rays_pipeline: vk::Pipeline,
+    pub scope: Option<scope::Mode>,
+    scopes: scope::Scopes,
+    /// Created with the swapchain, if its images can be copied
+    targets: Option<Targets>,
+}
+
+impl PostProcess {
+    pub fn new(
+        device: &ash::Device,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    ) -> Self {
+        let bindings: Vec<vk::DescriptorSetLayoutBinding> = (0..2)
+            .map(|binding| {
+                vk::DescriptorSetLayoutBinding::builder()
+                    .binding(binding)
+                    .descriptor_count(1)
+                    .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                    .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                    .build()
+            })
+            .collect();
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+                    None,
+                )
+                .expect("Post processing descriptor set layout")
+        };
+
+        let set_layouts = [descriptor_set_layout];
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(std::mem::size_of::<Constants>() as u32)
+            .build()];
+        let layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::builder()
+                        .set_layouts(&set_layouts)
+                        .push_constant_ranges(&push_constant_ranges),
+                    None,
+                )
+                .expect("Post processing pipeline layout")
+        };
+        let exposure_path = Path::new(env!("OUT_DIR")).join("exposure_comp.spv");
+        let exposure_pipeline =
+            create_compute_pipeline(device, layout, &util::read_shader_code(&exposure_path));
+        let god_rays_path = Path::new(env!("OUT_DIR")).join("god_rays_comp.spv");
+        let god_rays_pipeline =
+            create_compute_pipeline(device, layout, &util::read_shader_code(&god_rays_path));
+
+        Self {
+            descriptor_set_layout,
+            layout,
+            filters: Vec::new(),
+            exposure: 0.0,
+            exposure_pipeline,
+            god_rays: 0.0,
+            god_rays_enabled: true,
+            light: Light::default(),
+            god_rays_pipeline,
+            scope: None,
+            scopes: scope::Scopes::new(device, device_memory_properties),
+            targets: None,
+        }
+    }
+
+    /// Adds a filter from its compiled SPIR-V, after any already registered. Returns its index.
+    pub fn register(
+        &mut self,
+        device: &ash::Device,
+        name: &str,
+        code: &[u32],
+        enabled: bool,
+    ) -> usize {
+        let pipeline = create_compute_pipeline(device, self.layout, code);
+        self.filters.push(Filter {
+            name: String::from(name),
+            enabled,
+            pipeline,
+        });
+        self.filters.len() - 1
+    }
+
+    pub fn filters_mut(&mut self) -> &mut [Filter] {
+        &mut self.filters
+    }
+
+    fn is_active(&self) -> bool {
+        self.scope.is_some()
+            || self.exposure != 0.0
+            || self.god_rays_active()
+            || self.filters.iter().any(|filter| filter.enabled)
+    }
+
+    fn god_rays_active(&self) -> bool {
+        self.god_rays_enabled && self.god_rays > 0.0 && self.light.strength > 0.0
+    }
+
+    /// The pipelines run over the frame in order, exposure and god rays first
+    fn active_pipelines(&self) -> impl Iterator<Item = vk::Pipeline> + '_ {
+        let exposure = Some(self.exposure_pipeline).filter(|_| self.exposure != 0.0);
+        let god_rays = Some(self.god_rays_pipeline).filter(|_| self.god_rays_active());
+        exposure.into_iter().chain(god_rays).chain(
+            self.filters
+                .iter()
+                .filter(|filter| filter.enabled)
+                .map(|filter| filter.pipeline),
+        )
+    }
+
+    /// Creates the images filters run on for the swapchain.
+    fn create_targets(&mut self, context: &SwapchainContext) {
+        if !context.usage.contains(SWAPCHAIN_USAGE) {
+            return;
+        }
+        let device = context.device;
+        let extent = context.extent;
+        let create_target = || {
+            let (image, memory) = HelloTriangleApplication::create_image(
+                device,
+                &ImageDescription {
+                    width: extent.width,
+                    height: extent.height,
+                    format: IMAGE_FORMAT,
+                    tiling: vk::ImageTiling::OPTIMAL,
+                    usage: vk::ImageUsageFlags::STORAGE
+                        | vk::ImageUsageFlags::TRANSFER_SRC
+                        | vk::ImageUsageFlags::TRANSFER_DST,
+                    memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                },
+                context.device_memory_properties,
+            );
+            let view = HelloTriangleApplication::create_image_view(
+                device,
+                image,
+                IMAGE_FORMAT,
+                vk::ImageAspectFlags::COLOR,
+            );
+            (image, memory, view)
+        };
+        let images = [create_target(), create_target()];
+
+        // Two filter sets with two images each, and two scope sets with an image and the counts buffer
+        let pool_sizes = [
+            vk::DescriptorPoolSize::builder()
+                .ty(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(6)
+                .build(),
+            vk::DescriptorPoolSize::builder()
+                .ty(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(2)
+                .build(),
+        ];
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::builder()
+                        .pool_sizes(&pool_sizes)
+                        .max_sets(4),
+                    None,
+                )
+                .expect("Post processing descriptor pool")
+        };
+        let set_layouts = [self.descriptor_set_layout; 2];
+        let sets = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::builder()
+                        .descriptor_pool(descriptor_pool)
+                        .set_layouts(&set_layouts),
+                )
+                .expect("Post processing descriptor sets")
+        };
+        let descriptor_sets = [sets[0], sets[1]];
+
+        let image_infos: Vec<[vk::DescriptorImageInfo; 1]> = images
+            .iter()
+            .map(|&(_, _, view)| {
+                [vk::DescriptorImageInfo::builder()
+                    .image_layout(vk::ImageLayout::GENERAL)
+                    .image_view(view)
+                    .build()]
+            })
+            .collect();
+        let writes: Vec<vk::WriteDescriptorSet> = (0..2)
+            .flat_map(|set| {
+                [(0, set), (1, 1 - set)].map(|(binding, image)| {
+                    vk::WriteDescriptorSet::builder()
+                        .dst_set(descriptor_sets[set])
+                        .dst_binding(binding)
+                        .dst_array_element(0)
+                        .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                        .image_info(&image_infos[image])
+                        .build()
+                })
+            })
+            .collect();
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+
+        let scope_descriptor_sets = self.scopes.allocate_descriptor_sets(
+            device,
+            descriptor_pool,
+            [images[0].2, images[1].2],
+        );
+
+        self.targets = Some(Targets {
+            extent,
+            images,
+            descriptor_pool,
+            descriptor_sets,
+            scope_descriptor_sets,
+            linear_frame: util::is_srgb_format(context.format),
+        });
+    }
+
+    fn destroy_targets(&mut self, device: &ash::Device) {
+        if let Some(targets) = self.targets.take() {
+            unsafe {
+                device.destroy_descriptor_pool(targets.descriptor_pool, None);
+                for &(image, memory, view) in targets.images.iter() {
+                    device.destroy_image_view(view, None);
+                    device.destroy_image(image, None);
+                    device.free_memory(memory, None);
+                }
+            }
+        }
+    }
+
+    /// Records the enabled filters and the scope after the render pass, which leaves the swapchain image ready
+    /// to present. The image is ready to present again when they finish. Does nothing if neither is enabled.
+    fn record_filters(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        swapchain_image: vk::Image,
+    ) {
+        let targets = match &self.targets {
+            Some(targets) if self.is_active() => targets,
+            _ => return,
+        };
+        let extent = targets.extent;
+        let image_barrier = |image: vk::Image,
+                             old: vk::ImageLayout,
+                             new: vk::ImageLayout,
+                             src_access: vk::AccessFlags,
+                             dst_access: vk::AccessFlags| {
+            vk::ImageMemoryBarrier::builder()
+                .old_layout(old)
+                .new_layout(new)
+                .src_access_mask(src_access)
+                .dst_access_mask(dst_access)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(color_subresource_range())
+                .build()
+        };
+        let full_image = [
+            vk::Offset3D { x: 0, y: 0, z: 0 },
+            vk::Offset3D {
+                x: extent.width as i32,
+                y: extent.height as i32,
+                z: 1,
+            },
+        ];
+        let blit = vk::ImageBlit::builder()
+            .src_subresource(color_subresource_layers())
+            .src_offsets(full_image)
+            .dst_subresource(color_subresource_layers())
+            .dst_offsets(full_image)
+            .build();
+
+        let [(first, _, _), (second, _, _)] = targets.images;
+        unsafe {
+            // The previous frame may still be filtering in these images, so wait for it before overwriting them
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::COMPUTE_SHADER
+                    | vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[
+                    image_barrier(
+                        swapchain_image,
+                        vk::ImageLayout::PRESENT_SRC_KHR,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                        vk::AccessFlags::TRANSFER_READ,
+                    ),
+                    image_barrier(
+                        first,
+                        vk::ImageLayout::UNDEFINED,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::AccessFlags::empty(),
+                        vk::AccessFlags::TRANSFER_WRITE,
+                    ),
+                ],
+            );
+            device.cmd_blit_image(
+                command_buffer,
+                swapchain_image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                first,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[blit],
+                vk::Filter::NEAREST,
+            );
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[
+                    image_barrier(
+                        first,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::ImageLayout::GENERAL,
+                        vk::AccessFlags::TRANSFER_WRITE,
+                        vk::AccessFlags::SHADER_READ,
+                    ),
+                    image_barrier(
+                        second,
+                        vk::ImageLayout::UNDEFINED,
+                        vk::ImageLayout::GENERAL,
+                        vk::AccessFlags::empty(),
+                        vk::AccessFlags::SHADER_WRITE,
+                    ),
+                ],
+            );
+
+            let constants = Constants {
+                exposure: self.exposure,
+                light_strength: match self.god_rays_active() {
+                    true => self.god_rays * self.light.strength,
+                    false => 0.0,
+                },
+                light_position: self.light.position,
+            };
+            let mut input = 0;
+            for (i, pipeline) in self.active_pipelines().enumerate() {
+                if i > 0 {
+                    // The previous filter's output is this one's input, and its input is this one's output
+                    let barrier = vk::MemoryBarrier::builder()
+                        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                        .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                        .build();
+                    device.cmd_pipeline_barrier(
+                        command_buffer,
+                        vk::PipelineStageFlags::COMPUTE_SHADER,
+                        vk::PipelineStageFlags::COMPUTE_SHADER,
+                        vk::DependencyFlags::empty(),
+                        &[barrier],
+                        &[],
+                        &[],
+                    );
+                }
+                device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline);
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    self.layout,
+                    0,
+                    &[targets.descriptor_sets[input]],
+                    &[],
+                );
+                device.cmd_push_constants(
+                    command_buffer,
+                    self.layout,
+                    vk::ShaderStageFlags::COMPUTE,
+                    0,
+                    constants.as_bytes(),
+                );
+                device.cmd_dispatch(
+                    command_buffer,
+                    extent.width.div_ceil(WORKGROUP_SIZE),
+                    extent.height.div_ceil(WORKGROUP_SIZE),
+                    1,
+                );
+                input = 1 - input;
+            }
+
+            if let Some(mode) = self.scope {
+                let barrier = vk::MemoryBarrier::builder()
+                    .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+                    .build();
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::DependencyFlags::empty(),
+                    &[barrier],
+                    &[],
+                    &[],
+                );
+                self.scopes.record(
+                    device,
+                    command_buffer,
+                    targets.scope_descriptor_sets[input],
+                    mode,
+                    extent,
+                    targets.linear_frame,
+                );
+            }
+            let (result, _, _) = targets.images[input];
+
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[
+                    image_barrier(
+                        result,
+                        vk::ImageLayout::GENERAL,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        vk::AccessFlags::SHADER_WRITE,
+                        vk::AccessFlags::TRANSFER_READ,
+                    ),
+                    image_barrier(
+                        swapchain_image,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::AccessFlags::TRANSFER_READ,
+                        vk::AccessFlags::TRANSFER_WRITE,
+                    ),
+                ],
+            );
+            device.cmd_blit_image(
+                command_buffer,
+                result,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                swapchain_image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[blit],
+                vk::Filter::NEAREST,
+            );
+            // Presentation waits on the frame's semaphore, so no destination access is needed
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[image_barrier(
+                    swapchain_image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::PRESENT_SRC_KHR,
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    vk::AccessFlags::empty(),
+                )],
+            );
+        }
+    }
+}
+
+impl RenderFeature for PostProcess {
+    fn name(&self) -> &str {
+        "post processing"
+    }
+
+    fn init(&mut self, context: &SwapchainContext) {
+        if !context.usage.contains(SWAPCHAIN_USAGE) {
+            println!(
+                "The surface doesn't support copying swapchain images, post processing is disabled"
+            );
+        }
+        self.create_targets(context);
+    }
+
+    fn resize(&mut self, context: &SwapchainContext) {
+        self.destroy_targets(context.device);
+        self.create_targets(context);
+    }
+
+    fn record(&mut self, context: &hooks::FrameContext) {
+        if context.stage == hooks::Stage::PostProcess {
+            self.record_filters(
+                context.device,
+                context.command_buffer,
+                context.swapchain_image,
+            );
+        }
+    }
+
+    fn passes(&self) -> Vec<framegraph::Pass> {
+        let stage = framegraph::Position::Stage(hooks::Stage::PostProcess);
+        let exposure = Some("exposure").filter(|_| self.exposure != 0.0);
+        let god_rays = Some("god rays").filter(|_| self.god_rays_active());
+        let mut passes: Vec<framegraph::Pass> = exposure
+            .into_iter()
+            .chain(god_rays)
+            .chain(
+                self.filters
+                    .iter()
+                    .filter(|filter| filter.enabled)
+                    .map(|filter| filter.name.as_str()),
+            )
+            .map(|name| {
+                framegraph::Pass::new(
+                    name,
+                    stage,
+                    &[framegraph::SWAPCHAIN],
+                    &[framegraph::SWAPCHAIN],
+                )
+            })
+            .collect();
+        if self.scope.is_some() {
+            passes.push(framegraph::Pass::new(
+                "colour scope",
+                stage,
+                &[framegraph::SWAPCHAIN],
+                &[framegraph::SWAPCHAIN],
+            ));
+        }
+        passes
+    }
+
+    fn destroy(&mut self, device: &ash::Device) {
+        self.destroy_targets(device);
+        self.scopes.destroy(device);
+        unsafe {
+            for filter in self.filters.drain(..) {
+                device.destroy_pipeline(filter.pipeline, None);
+            }
+            device.destroy_pipeline(self.exposure_pipeline, None);
+            device.destroy_pipeline(self.god_rays_pipeline, None);
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+pub fn create_compute_pipeline(
+    device: &ash::Device,
+    layout: vk::PipelineLayout,
+    code: &[u32],
+) -> vk::Pipeline {
+    let module = crate::pipeline::create_shader_module(device, code);
+    let main_fn_name = CString::new("main").unwrap();
+    let stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::COMPUTE)
+        .module(module)
+        .name(main_fn_name.as_c_str());
+    let pipeline_info = vk::ComputePipelineCreateInfo::builder()
+        .stage(stage.build())
+        .layout(layout);
+
+    let pipeline = unsafe {
+        device
+            .create_compute_pipelines(vk::PipelineCache::null(), &[pipeline_info.build()], None)
+            .expect("Compute pipeline")[0]
+    };
+    unsafe { device.destroy_shader_module(module, None) };
+
+    pipeline
+}
+
+pub fn color_subresource_range() -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRange::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(0)
+        .layer_count(1)
+        .build()
+}
+
+pub fn color_subresource_layers() -> vk::ImageSubresourceLayers {
+    vk::ImageSubresourceLayers::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .mip_level(0)
+        .base_array_layer(0)
+        .layer_count(1)
+        .build()
+}
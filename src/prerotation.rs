@@ -0,0 +1,52 @@
+use ash::vk;
+use cgmath::{Deg, Matrix4, SquareMatrix};
+
+/// The transform to create the swapchain with. When the surface is rotated, as on a phone turned on its side, the
+/// renderer draws the frame already rotated and the swapchain says so, which saves the presentation engine
+/// rotating every frame. Other transforms fall back to the presentation engine doing the work, without
+/// transforming if the surface allows it.
+pub fn choose_transform(capabilities: &vk::SurfaceCapabilitiesKHR) -> vk::SurfaceTransformFlagsKHR {
+    let current = capabilities.current_transform;
+    let rotations = vk::SurfaceTransformFlagsKHR::IDENTITY
+        | vk::SurfaceTransformFlagsKHR::ROTATE_90
+        | vk::SurfaceTransformFlagsKHR::ROTATE_180
+        | vk::SurfaceTransformFlagsKHR::ROTATE_270;
+    if rotations.contains(current) {
+        current
+    } else if capabilities
+        .supported_transforms
+        .contains(vk::SurfaceTransformFlagsKHR::IDENTITY)
+    {
+        vk::SurfaceTransformFlagsKHR::IDENTITY
+    } else {
+        current
+    }
+}
+
+/// Rotates clip space to match the swapchain's transform. Applied after the camera's projection, so the scene is
+/// drawn into the swapchain's images the way the surface is turned.
+pub fn matrix(transform: vk::SurfaceTransformFlagsKHR) -> Matrix4<f32> {
+    match transform {
+        vk::SurfaceTransformFlagsKHR::ROTATE_90 => Matrix4::from_angle_z(Deg(90.0)),
+        vk::SurfaceTransformFlagsKHR::ROTATE_180 => Matrix4::from_angle_z(Deg(180.0)),
+        vk::SurfaceTransformFlagsKHR::ROTATE_270 => Matrix4::from_angle_z(Deg(270.0)),
+        _ => Matrix4::identity(),
+    }
+}
+
+/// The size of the frame as the user sees it. Swapchain images keep the surface's unrotated size, so a quarter
+/// turn swaps their width and height.
+pub fn display_extent(
+    extent: vk::Extent2D,
+    transform: vk::SurfaceTransformFlagsKHR,
+) -> vk::Extent2D {
+    match transform {
+        vk::SurfaceTransformFlagsKHR::ROTATE_90 | vk::SurfaceTransformFlagsKHR::ROTATE_270 => {
+            vk::Extent2D {
+                width: extent.height,
+                height: extent.width,
+            }
+        }
+        _ => extent,
+    }
+}
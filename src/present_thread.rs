@@ -0,0 +1,171 @@
+use std::sync::{mpsc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+use ash::extensions::khr;
+use ash::vk;
+
+/// Held while a queue is used, since the present thread shares the queues with the render thread and Vulkan
+/// queues can only be used from one thread at a time. Uploads and device idle waits take it as well as frame
+/// submissions, since the present queue is often the graphics queue.
+static QUEUES: Mutex<()> = Mutex::new(());
+
+/// Locks the queues until the guard is dropped
+pub fn lock_queues() -> MutexGuard<'static, ()> {
+    // Nothing behind the lock is left in a bad state by a panic while it was held
+    QUEUES.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Waits for the device to go idle with the queues locked
+pub fn device_wait_idle(device: &ash::Device) -> Result<(), vk::Result> {
+    let _queues = lock_queues();
+    unsafe { device.device_wait_idle() }
+}
+
+struct Request {
+    swapchain: vk::SwapchainKHR,
+    image_index: u32,
+    wait_semaphore: vk::Semaphore,
+}
+
+/// How far the present thread has got with its request
+enum Progress {
+    /// `queue_present` returned, so the swapchain is free again
+    Presented(Result<bool, vk::Result>),
+    /// The present queue has finished with the semaphores the present waited on
+    Idle,
+}
+
+/// Presents swapchain images from its own thread, so the render thread can start preparing the next frame
+/// instead of waiting for the present queue to go idle. The present queue is waited on after each present, as
+/// the render thread does without it, so that the frame's semaphores are free once the frame comes around again.
+/// Rather than waiting for the queue to go idle, which would keep it locked while the GPU drains, an empty
+/// submission signals a fence once everything before it on the queue has finished, and the fence is waited on
+/// with the queues unlocked, so frames can be submitted meanwhile.
+///
+/// At most one present is in flight. The render thread waits for it to be presented before touching the
+/// swapchain again, and for the present queue to have gone idle before sending the next present. Every other
+/// use of the queues goes through `lock_queues`.
+pub struct PresentThread {
+    sender: Option<mpsc::Sender<Request>>,
+    progress: mpsc::Receiver<Progress>,
+    /// Whether the last present is still to report it was presented, and then that its queue went idle
+    presenting: bool,
+    idling: bool,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl PresentThread {
+    pub fn spawn(device: ash::Device, loader: khr::Swapchain, queue: vk::Queue) -> Self {
+        let (sender, requests) = mpsc::channel::<Request>();
+        let (progress_sender, progress) = mpsc::channel();
+        let handle = thread::Builder::new()
+            .name(String::from("present"))
+            .spawn(move || {
+                let idle_fence = unsafe {
+                    device
+                        .create_fence(&vk::FenceCreateInfo::default(), None)
+                        .expect("Present thread fence")
+                };
+                for request in requests {
+                    let wait_semaphores = [request.wait_semaphore];
+                    let swapchains = [request.swapchain];
+                    let image_indices = [request.image_index];
+                    let present_info = vk::PresentInfoKHR::builder()
+                        .wait_semaphores(&wait_semaphores)
+                        .swapchains(&swapchains)
+                        .image_indices(&image_indices);
+                    let result = {
+                        let _queues = lock_queues();
+                        unsafe { loader.queue_present(queue, &present_info) }
+                    };
+                    if progress_sender.send(Progress::Presented(result)).is_err() {
+                        break;
+                    }
+
+                    let idle = {
+                        let _queues = lock_queues();
+                        unsafe { device.queue_submit(queue, &[], idle_fence) }
+                    }
+                    .and_then(|_| unsafe {
+                        device.wait_for_fences(&[idle_fence], true, u64::MAX)?;
+                        device.reset_fences(&[idle_fence])
+                    });
+                    if let Err(result) = idle {
+                        println!("Error waiting for present queue: {}", result)
+                    }
+                    if progress_sender.send(Progress::Idle).is_err() {
+                        break;
+                    }
+                }
+                unsafe { device.destroy_fence(idle_fence, None) };
+            })
+            .expect("Spawning present thread");
+
+        Self {
+            sender: Some(sender),
+            progress,
+            presenting: false,
+            idling: false,
+            handle: Some(handle),
+        }
+    }
+
+    /// Presents the image once `wait_semaphore` is signalled. Waits for the previous present's queue to go idle
+    /// first.
+    pub fn present(
+        &mut self,
+        swapchain: vk::SwapchainKHR,
+        image_index: u32,
+        wait_semaphore: vk::Semaphore,
+    ) {
+        self.wait_idle();
+        let request = Request {
+            swapchain,
+            image_index,
+            wait_semaphore,
+        };
+        let sender = self.sender.as_ref().expect("Present thread stopped");
+        sender.send(request).expect("Present thread stopped");
+        self.presenting = true;
+        self.idling = true;
+    }
+
+    /// Waits for the last present to reach the swapchain, returning what `queue_present` did or None when there
+    /// was nothing to wait for
+    pub fn wait_presented(&mut self) -> Option<Result<bool, vk::Result>> {
+        while self.presenting {
+            match self.progress.recv().expect("Present thread stopped") {
+                Progress::Presented(result) => {
+                    self.presenting = false;
+                    return Some(result);
+                }
+                Progress::Idle => self.idling = false,
+            }
+        }
+        None
+    }
+
+    /// Waits for the last present to finish on the present queue, returning what `queue_present` did if it
+    /// hadn't already been collected with `wait_presented`
+    pub fn wait_idle(&mut self) -> Option<Result<bool, vk::Result>> {
+        let presented = self.wait_presented();
+        while self.idling {
+            if let Progress::Idle = self.progress.recv().expect("Present thread stopped") {
+                self.idling = false;
+            }
+        }
+        presented
+    }
+}
+
+impl Drop for PresentThread {
+    /// Finishes the last present before the thread ends, so the swapchain can be destroyed after
+    fn drop(&mut self) {
+        self.sender = None;
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                println!("The present thread panicked");
+            }
+        }
+    }
+}
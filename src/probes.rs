@@ -0,0 +1,358 @@
+use ash::vk;
+use cgmath::{Matrix4, Point3};
+
+use crate::features::SwapchainContext;
+use crate::{capture, pipeline, schedule, HelloTriangleApplication, ImageDescription};
+
+/// Texels across each face of a probe's cube by default
+pub const DEFAULT_SIZE: u32 = 128;
+/// Frames between one drawing of each face of a probe and the next by default
+pub const DEFAULT_INTERVAL: u32 = 30;
+
+const FACE_COUNT: u32 = capture::FACE_NAMES.len() as u32;
+
+/// A reflection probe: the scene as seen from a point, drawn into the six faces of a cubemap while the renderer
+/// runs. Unlike an environment capture, which draws every face between two frames, the faces are drawn before
+/// the frame's render pass one at a time as the scheduler gets round to them, see `schedule::Scheduler`, so a
+/// probe costs at most one face's draws in a frame. The cube has the swapchain's formats, so the scene's
+/// pipelines draw into it unchanged, and is kept in the `SHADER_READ_ONLY_OPTIMAL` layout between faces.
+///
+/// Like the minimap, a probe sees the scene's draws only: GPU driven objects, the playground and the renderer's
+/// features aren't drawn into it.
+pub struct Probe {
+    pub position: Point3<f32>,
+    /// Texels across each face
+    pub size: u32,
+    pub interval: u32,
+    pub view: schedule::View,
+    /// The probe's task in the scheduler, with a part for each face
+    pub task: usize,
+    format: vk::Format,
+    render_pass: vk::RenderPass,
+    /// Clears what `render_pass` preserves, for the first face drawn
+    first_render_pass: Option<vk::RenderPass>,
+    cube: (vk::Image, vk::DeviceMemory),
+    /// Each face on its own, in face order, for drawing into
+    face_views: Vec<vk::ImageView>,
+    depth: (vk::Image, vk::DeviceMemory, vk::ImageView),
+    framebuffers: Vec<vk::Framebuffer>,
+    /// Whether the cube has been cleared out of the undefined layout it's created in
+    cleared: bool,
+    /// Whether a face has been drawn, leaving the depth buffer in the layout it's kept in
+    drawn: bool,
+}
+
+impl Probe {
+    pub fn new(
+        context: &SwapchainContext,
+        position: Point3<f32>,
+        size: u32,
+        interval: u32,
+        view: schedule::View,
+        task: usize,
+        passes: &pipeline::PassConfig,
+    ) -> Self {
+        let device = context.device;
+        let create_render_pass = |config: &pipeline::PassConfig| {
+            pipeline::create_offscreen_render_pass(
+                device,
+                context.format,
+                context.depth_format,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::SHADER_READ | vk::AccessFlags::TRANSFER_READ,
+                config,
+            )
+        };
+        let render_pass = create_render_pass(passes);
+        let first_render_pass = match passes.preserves() {
+            true => Some(create_render_pass(&passes.first())),
+            false => None,
+        };
+        let image = unsafe {
+            device
+                .create_image(
+                    &vk::ImageCreateInfo::builder()
+                        .image_type(vk::ImageType::TYPE_2D)
+                        .extent(vk::Extent3D {
+                            width: size,
+                            height: size,
+                            depth: 1,
+                        })
+                        .mip_levels(1)
+                        .array_layers(FACE_COUNT)
+                        .format(context.format)
+                        .tiling(vk::ImageTiling::OPTIMAL)
+                        .initial_layout(vk::ImageLayout::UNDEFINED)
+                        .usage(
+                            vk::ImageUsageFlags::COLOR_ATTACHMENT
+                                | vk::ImageUsageFlags::SAMPLED
+                                | vk::ImageUsageFlags::TRANSFER_SRC
+                                | vk::ImageUsageFlags::TRANSFER_DST,
+                        )
+                        .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                        .samples(vk::SampleCountFlags::TYPE_1)
+                        .flags(vk::ImageCreateFlags::CUBE_COMPATIBLE),
+                    None,
+                )
+                .expect("Reflection probe image")
+        };
+        let requirements = unsafe { device.get_image_memory_requirements(image) };
+        let memory = unsafe {
+            let memory = device
+                .allocate_memory(
+                    &vk::MemoryAllocateInfo::builder()
+                        .allocation_size(requirements.size)
+                        .memory_type_index(HelloTriangleApplication::find_memory_type(
+                            requirements.memory_type_bits,
+                            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                            context.device_memory_properties,
+                        )),
+                    None,
+                )
+                .expect("Reflection probe memory");
+            device
+                .bind_image_memory(image, memory, 0)
+                .expect("Binding reflection probe memory");
+            memory
+        };
+        let face_views: Vec<vk::ImageView> = (0..FACE_COUNT)
+            .map(|face| unsafe {
+                device
+                    .create_image_view(
+                        &vk::ImageViewCreateInfo::builder()
+                            .image(image)
+                            .view_type(vk::ImageViewType::TYPE_2D)
+                            .format(context.format)
+                            .subresource_range(vk::ImageSubresourceRange {
+                                aspect_mask: vk::ImageAspectFlags::COLOR,
+                                base_mip_level: 0,
+                                level_count: 1,
+                                base_array_layer: face,
+                                layer_count: 1,
+                            }),
+                        None,
+                    )
+                    .expect("Reflection probe face view")
+            })
+            .collect();
+
+        // Shared by the faces, which are never drawn at once
+        let (depth_image, depth_memory) = HelloTriangleApplication::create_image(
+            device,
+            &ImageDescription {
+                width: size,
+                height: size,
+                format: context.depth_format,
+                tiling: vk::ImageTiling::OPTIMAL,
+                usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+                memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            },
+            context.device_memory_properties,
+        );
+        let depth_view = HelloTriangleApplication::create_image_view(
+            device,
+            depth_image,
+            context.depth_format,
+            vk::ImageAspectFlags::DEPTH,
+        );
+        let framebuffers = face_views
+            .iter()
+            .map(|&face_view| unsafe {
+                let attachments = [face_view, depth_view];
+                device
+                    .create_framebuffer(
+                        &vk::FramebufferCreateInfo::builder()
+                            .render_pass(render_pass)
+                            .attachments(&attachments)
+                            .width(size)
+                            .height(size)
+                            .layers(1),
+                        None,
+                    )
+                    .expect("Reflection probe frame buffer")
+            })
+            .collect();
+
+        Self {
+            position,
+            size,
+            interval,
+            view,
+            task,
+            format: context.format,
+            render_pass,
+            first_render_pass,
+            cube: (image, memory),
+            face_views,
+            depth: (depth_image, depth_memory, depth_view),
+            framebuffers,
+            cleared: false,
+            drawn: false,
+        }
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        vk::Extent2D {
+            width: self.size,
+            height: self.size,
+        }
+    }
+
+    /// The view and projection looking out of the face
+    pub fn face_view_projection(&self, face: usize) -> (Matrix4<f32>, Matrix4<f32>) {
+        (
+            capture::face_views(self.position)[face],
+            capture::face_projection(),
+        )
+    }
+
+    /// Records clearing the cube to `color` the first time it's called, so every face can be read before it has
+    /// been drawn. Must be called outside a render pass.
+    pub fn record_clear(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        color: &vk::ClearColorValue,
+    ) {
+        if !self.cleared {
+            self.cleared = true;
+            schedule::record_clear(device, command_buffer, self.cube.0, FACE_COUNT, color);
+        }
+    }
+
+    /// Records a render pass that draws the face. `record` is given the command buffer inside the render pass and
+    /// records the scene's draws. Must be called outside a render pass, before the frame's render pass, once the
+    /// cube has been cleared.
+    pub fn record_face(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        face: usize,
+        clear_values: &[vk::ClearValue],
+        record: impl FnOnce(vk::CommandBuffer),
+    ) {
+        let render_pass = match (self.drawn, self.first_render_pass) {
+            (false, Some(first)) => first,
+            _ => self.render_pass,
+        };
+        self.drawn = true;
+        let render_pass_bi = vk::RenderPassBeginInfo::builder()
+            .render_pass(render_pass)
+            .framebuffer(self.framebuffers[face])
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: self.extent(),
+            })
+            .clear_values(clear_values);
+        unsafe {
+            device.cmd_begin_render_pass(
+                command_buffer,
+                &render_pass_bi,
+                vk::SubpassContents::INLINE,
+            )
+        };
+        record(command_buffer);
+        unsafe { device.cmd_end_render_pass(command_buffer) };
+    }
+
+    /// Records copying the face into a readback buffer made by `capture::create_readback`, leaving the cube
+    /// in the layout it's kept in. Must be called outside a render pass, once the cube has been cleared.
+    pub fn record_copy(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        face: usize,
+        readback: vk::Buffer,
+    ) {
+        let barrier = |old_layout, new_layout, src_access_mask, dst_access_mask| {
+            vk::ImageMemoryBarrier::builder()
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .src_access_mask(src_access_mask)
+                .dst_access_mask(dst_access_mask)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(self.cube.0)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: face as u32,
+                    layer_count: 1,
+                })
+                .build()
+        };
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier(
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                    vk::AccessFlags::TRANSFER_READ,
+                )],
+            );
+        }
+        capture::record_copy(
+            device,
+            command_buffer,
+            self.cube.0,
+            face as u32,
+            self.size,
+            readback,
+        );
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier(
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    vk::AccessFlags::TRANSFER_READ,
+                    vk::AccessFlags::SHADER_READ,
+                )],
+            );
+        }
+    }
+
+    /// Whether the cube has been cleared, so its faces can be read
+    pub fn cleared(&self) -> bool {
+        self.cleared
+    }
+
+    pub fn format(&self) -> vk::Format {
+        self.format
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            for &framebuffer in self.framebuffers.iter() {
+                device.destroy_framebuffer(framebuffer, None);
+            }
+            for &face_view in self.face_views.iter() {
+                device.destroy_image_view(face_view, None);
+            }
+            device.destroy_image(self.cube.0, None);
+            device.free_memory(self.cube.1, None);
+            let (image, memory, view) = self.depth;
+            device.destroy_image_view(view, None);
+            device.destroy_image(image, None);
+            device.free_memory(memory, None);
+            device.destroy_render_pass(self.render_pass, None);
+            if let Some(first) = self.first_render_pass {
+                device.destroy_render_pass(first, None);
+            }
+        }
+        self.view.destroy(device);
+    }
+}
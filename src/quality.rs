@@ -0,0 +1,147 @@
+use ash::vk;
+
+use crate::{ground_shadows, shadows};
+
+/// Devices with at least this much memory of their own can keep up with the ultra preset, and with half of it
+/// the high preset
+const ULTRA_MEMORY: vk::DeviceSize = 8 << 30;
+
+/// One switch for how much the renderer spends on making a frame look good, to match it to the hardware. The
+/// renderer has no multisampling or internal resolution to scale, so the presets cover what can be changed while
+/// it runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    Low,
+    Medium,
+    High,
+    Ultra,
+}
+
+impl Preset {
+    pub const ALL: [Self; 4] = [Self::Low, Self::Medium, Self::High, Self::Ultra];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|preset| preset.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Preset::Low => "low",
+            Preset::Medium => "medium",
+            Preset::High => "high",
+            Preset::Ultra => "ultra",
+        }
+    }
+
+    pub fn settings(&self) -> Settings {
+        match self {
+            Preset::Low => Settings {
+                anisotropy: 1.0,
+                fog: false,
+                lens_flare: false,
+                god_rays: false,
+                capsule_shadows: false,
+                ground_shadows: ground_shadows::Mode::Blob,
+                volume_samples_per_voxel: 1.0,
+                shadow_filter: shadows::Filter::Hard,
+                shadow_resolution: 1024,
+            },
+            Preset::Medium => Settings {
+                anisotropy: 4.0,
+                fog: true,
+                lens_flare: false,
+                god_rays: false,
+                capsule_shadows: true,
+                ground_shadows: ground_shadows::Mode::Planar,
+                volume_samples_per_voxel: 1.5,
+                shadow_filter: shadows::Filter::Pcf { kernel: 3 },
+                shadow_resolution: 2048,
+            },
+            Preset::High => Settings {
+                anisotropy: 8.0,
+                fog: true,
+                lens_flare: true,
+                god_rays: true,
+                capsule_shadows: true,
+                ground_shadows: ground_shadows::Mode::Planar,
+                volume_samples_per_voxel: 2.0,
+                shadow_filter: shadows::Filter::Pcf { kernel: 5 },
+                shadow_resolution: 2048,
+            },
+            Preset::Ultra => Settings {
+                anisotropy: 16.0,
+                fog: true,
+                lens_flare: true,
+                god_rays: true,
+                capsule_shadows: true,
+                ground_shadows: ground_shadows::Mode::Planar,
+                volume_samples_per_voxel: 4.0,
+                shadow_filter: shadows::Filter::Pcss {
+                    light_size: shadows::DEFAULT_LIGHT_SIZE,
+                },
+                shadow_resolution: 4096,
+            },
+        }
+    }
+}
+
+/// What a preset sets. Effects are only turned on if they have been added.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Settings {
+    /// The most texels textured meshes are filtered over along a slope, limited to what the device can do. 1
+    /// turns anisotropic filtering off.
+    pub anisotropy: f32,
+    pub fog: bool,
+    pub lens_flare: bool,
+    pub god_rays: bool,
+    /// Shadows and occlusion from draws' capsules, see `capsules::CapsuleShadows`
+    pub capsule_shadows: bool,
+    /// How draws cast shadows onto the ground, see `ground_shadows::GroundShadows`
+    pub ground_shadows: ground_shadows::Mode,
+    /// How finely volumes are ray marched, see `volume::Volume`
+    pub volume_samples_per_voxel: f32,
+    /// How the shadow map is filtered, see `shadows::Filter`
+    pub shadow_filter: shadows::Filter,
+    /// Texels across the shadow map
+    pub shadow_resolution: u32,
+}
+
+/// Guesses the preset a device can keep up with from what kind of device it is and how much memory it has of its
+/// own, along with why, for the renderer to start with when it isn't told one. Integrated GPUs share memory with
+/// the CPU, so only their type is taken into account.
+pub fn recommend(
+    properties: &vk::PhysicalDeviceProperties,
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+) -> (Preset, String) {
+    let device_memory = memory_properties.memory_heaps
+        [..memory_properties.memory_heap_count as usize]
+        .iter()
+        .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+        .map(|heap| heap.size)
+        .max()
+        .unwrap_or(0);
+    let gib = device_memory as f64 / (1u64 << 30) as f64;
+    match properties.device_type {
+        vk::PhysicalDeviceType::DISCRETE_GPU if device_memory >= ULTRA_MEMORY => (
+            Preset::Ultra,
+            format!("a discrete GPU with {:.1} GiB of memory", gib),
+        ),
+        vk::PhysicalDeviceType::DISCRETE_GPU if device_memory >= ULTRA_MEMORY / 2 => (
+            Preset::High,
+            format!("a discrete GPU with {:.1} GiB of memory", gib),
+        ),
+        vk::PhysicalDeviceType::DISCRETE_GPU => (
+            Preset::Medium,
+            format!("a discrete GPU with only {:.1} GiB of memory", gib),
+        ),
+        vk::PhysicalDeviceType::INTEGRATED_GPU => {
+            (Preset::Medium, String::from("an integrated GPU"))
+        }
+        vk::PhysicalDeviceType::CPU => (Preset::Low, String::from("a CPU")),
+        vk::PhysicalDeviceType::VIRTUAL_GPU => (Preset::Low, String::from("a virtual GPU")),
+        _ => (Preset::Low, String::from("a device of an unknown type")),
+    }
+}
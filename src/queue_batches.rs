@@ -0,0 +1,136 @@
+use ash::vk;
+
+/// The most batches a frame is submitted in, one for each of `framegraph::Queue`'s queues
+pub const MAX_BATCHES: usize = 3;
+
+/// One queue's part of a frame. The semaphores chaining it to the other batches come first in its lists.
+struct Batch {
+    handle: vk::Queue,
+    command_buffers: Vec<vk::CommandBuffer>,
+    wait_semaphores: Vec<vk::Semaphore>,
+    wait_stages: Vec<vk::PipelineStageFlags>,
+    signal_semaphores: Vec<vk::Semaphore>,
+    fence: vk::Fence,
+    /// How many of the waits and signals chain the batches, which `Batches::reset` keeps
+    chained_waits: usize,
+    chained_signals: usize,
+}
+
+/// A frame's batches of command buffers, one for each queue in the order they are submitted. The batches' lists
+/// are kept from one frame to the next and their submit infos are built in a fixed size array, so once the lists
+/// have grown to fit a frame, filling and submitting them doesn't allocate. Nothing here needs a device, which
+/// lets `benches/frame_allocations.rs` count what a frame's submission allocates.
+pub struct Batches {
+    batches: Vec<Batch>,
+}
+
+impl Batches {
+    /// A batch for each of the Vulkan queues, in the order they are submitted
+    pub fn new(handles: impl IntoIterator<Item = vk::Queue>) -> Self {
+        let batches: Vec<Batch> = handles
+            .into_iter()
+            .map(|handle| Batch {
+                handle,
+                command_buffers: Vec::new(),
+                wait_semaphores: Vec::new(),
+                wait_stages: Vec::new(),
+                signal_semaphores: Vec::new(),
+                fence: vk::Fence::null(),
+                chained_waits: 0,
+                chained_signals: 0,
+            })
+            .collect();
+        assert!(
+            batches.len() <= MAX_BATCHES,
+            "A frame has at most {} batches",
+            MAX_BATCHES
+        );
+        Self { batches }
+    }
+
+    /// Makes batch `to` wait at `stage` on a semaphore batch `from` signals, in every frame
+    pub fn chain(
+        &mut self,
+        from: usize,
+        to: usize,
+        semaphore: vk::Semaphore,
+        stage: vk::PipelineStageFlags,
+    ) {
+        assert!(from < to, "Batch {} waits on batch {} after it", to, from);
+        self.signal(from, semaphore);
+        self.wait(to, semaphore, stage);
+        let from = &mut self.batches[from];
+        from.chained_signals = from.signal_semaphores.len();
+        let to = &mut self.batches[to];
+        to.chained_waits = to.wait_semaphores.len();
+    }
+
+    /// Takes away the last frame's command buffers, fence, and waits and signals from outside the frame, keeping
+    /// the semaphores chaining the batches and the storage of the lists
+    pub fn reset(&mut self) {
+        for batch in self.batches.iter_mut() {
+            batch.command_buffers.clear();
+            batch.wait_semaphores.truncate(batch.chained_waits);
+            batch.wait_stages.truncate(batch.chained_waits);
+            batch.signal_semaphores.truncate(batch.chained_signals);
+            batch.fence = vk::Fence::null();
+        }
+    }
+
+    pub fn command_buffer(&mut self, batch: usize, command_buffer: vk::CommandBuffer) {
+        self.batches[batch].command_buffers.push(command_buffer);
+    }
+
+    pub fn wait(&mut self, batch: usize, semaphore: vk::Semaphore, stage: vk::PipelineStageFlags) {
+        let batch = &mut self.batches[batch];
+        batch.wait_semaphores.push(semaphore);
+        batch.wait_stages.push(stage);
+    }
+
+    pub fn signal(&mut self, batch: usize, semaphore: vk::Semaphore) {
+        self.batches[batch].signal_semaphores.push(semaphore);
+    }
+
+    /// Signals the fence once the batch has finished. Only one batch of each call can have a fence.
+    pub fn fence(&mut self, batch: usize, fence: vk::Fence) {
+        self.batches[batch].fence = fence;
+    }
+
+    /// Calls `submit` with each run of consecutive batches for the same Vulkan queue, in order, with their submit
+    /// infos and the fence the run signals
+    pub fn submit<F>(&self, mut submit: F) -> Result<(), vk::Result>
+    where
+        F: FnMut(vk::Queue, &[vk::SubmitInfo], vk::Fence) -> Result<(), vk::Result>,
+    {
+        let mut start = 0;
+        while start < self.batches.len() {
+            let handle = self.batches[start].handle;
+            let end = self.batches[start..]
+                .iter()
+                .position(|batch| batch.handle != handle)
+                .map_or(self.batches.len(), |offset| start + offset);
+            let group = &self.batches[start..end];
+            let mut submit_infos = [vk::SubmitInfo::default(); MAX_BATCHES];
+            for (submit_info, batch) in submit_infos.iter_mut().zip(group.iter()) {
+                *submit_info = vk::SubmitInfo::builder()
+                    .wait_semaphores(&batch.wait_semaphores)
+                    .wait_dst_stage_mask(&batch.wait_stages)
+                    .command_buffers(&batch.command_buffers)
+                    .signal_semaphores(&batch.signal_semaphores)
+                    .build();
+            }
+            let mut fences = group
+                .iter()
+                .map(|batch| batch.fence)
+                .filter(|&fence| fence != vk::Fence::null());
+            let fence = fences.next().unwrap_or_else(vk::Fence::null);
+            assert!(
+                fences.next().is_none(),
+                "Batches submitted together can only have one fence"
+            );
+            submit(handle, &submit_infos[..group.len()], fence)?;
+            start = end;
+        }
+        Ok(())
+    }
+}
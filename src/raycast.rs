@@ -0,0 +1,350 @@
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+
+use crate::bounds::Aabb;
+
+/// Triangles per BVH leaf. Below this, testing every triangle is cheaper than splitting further.
+const MAX_LEAF_TRIANGLES: usize = 4;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    /// Doesn't need to be normalized. Hit distances are measured in multiples of its length.
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn new(origin: Point3<f32>, direction: Vector3<f32>) -> Self {
+        Self { origin, direction }
+    }
+
+    pub fn at(&self, distance: f32) -> Point3<f32> {
+        self.origin + self.direction * distance
+    }
+
+    /// Distance along the ray to where it enters the box, or `None` if it misses the box or the box is
+    /// further than `max_distance`.
+    fn intersect_aabb(&self, aabb: &Aabb, max_distance: f32) -> Option<f32> {
+        let mut near = 0.0f32;
+        let mut far = max_distance;
+        for axis in 0..3 {
+            let inverse = 1.0 / self.direction[axis];
+            let mut t0 = (aabb.min[axis] - self.origin[axis]) * inverse;
+            let mut t1 = (aabb.max[axis] - self.origin[axis]) * inverse;
+            if inverse < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // NaNs from rays lying in a slab's plane are ignored by min/max
+            near = near.max(t0);
+            far = far.min(t1);
+            if far < near {
+                return None;
+            }
+        }
+        Some(near)
+    }
+
+    /// Möller–Trumbore ray/triangle intersection. Both faces of the triangle are hit.
+    fn intersect_triangle(&self, triangle: &Triangle) -> Option<f32> {
+        let [a, b, c] = triangle.vertices;
+        let ab = b - a;
+        let ac = c - a;
+        let p = self.direction.cross(ac);
+        let determinant = ab.dot(p);
+        if determinant.abs() < f32::EPSILON {
+            // The ray is parallel to the triangle
+            return None;
+        }
+
+        let inverse = 1.0 / determinant;
+        let to_origin = self.origin - a;
+        let u = to_origin.dot(p) * inverse;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = to_origin.cross(ab);
+        let v = self.direction.dot(q) * inverse;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let distance = ac.dot(q) * inverse;
+        if distance >= 0.0 {
+            Some(distance)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Triangle {
+    pub vertices: [Point3<f32>; 3],
+}
+
+impl Triangle {
+    /// Unit normal of the triangle's plane
+    pub fn normal(&self) -> Vector3<f32> {
+        let [a, b, c] = self.vertices;
+        (c - a).cross(b - a).normalize()
+    }
+
+    fn centroid(&self) -> Point3<f32> {
+        Point3::centroid(&self.vertices)
+    }
+}
+
+/// Where a ray hit a triangle.
+#[derive(Clone, Copy, Debug)]
+pub struct Hit {
+    /// Distance along the ray in multiples of the ray direction's length
+    pub distance: f32,
+    pub point: Point3<f32>,
+    /// Normal of the triangle that was hit, facing back towards the ray's origin
+    pub normal: Vector3<f32>,
+    /// Index of the triangle in the order it was given to `Bvh::new`
+    pub triangle: usize,
+}
+
+/// A hit on one of the scene's draws.
+#[derive(Clone, Copy, Debug)]
+pub struct SceneHit {
+    /// Index of the draw that was hit
+    pub draw: usize,
+    /// The hit in world space
+    pub hit: Hit,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Node {
+    /// `count` triangles from `start` in `Bvh::triangles`
+    Leaf {
+        bounds: Aabb,
+        start: usize,
+        count: usize,
+    },
+    Interior {
+        bounds: Aabb,
+        left: usize,
+        right: usize,
+    },
+}
+
+impl Node {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            Node::Leaf { bounds, .. } | Node::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+/// A bounding volume hierarchy over a set of triangles for ray casting on the CPU. The hierarchy doesn't
+/// change once built, so it should be built over a mesh in its own space and rays transformed into that space.
+#[derive(Clone, Debug)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    /// Triangles ordered so that each leaf's triangles are contiguous, along with their original index
+    triangles: Vec<(usize, Triangle)>,
+}
+
+impl Bvh {
+    /// Builds a hierarchy over an indexed triangle list. Returns `None` if there are no triangles.
+    pub fn new(positions: &[Point3<f32>], indices: &[u16]) -> Option<Self> {
+        let mut triangles: Vec<(usize, Triangle)> = indices
+            .chunks_exact(3)
+            .map(|face| Triangle {
+                vertices: [
+                    positions[face[0] as usize],
+                    positions[face[1] as usize],
+                    positions[face[2] as usize],
+                ],
+            })
+            .enumerate()
+            .collect();
+        if triangles.is_empty() {
+            return None;
+        }
+
+        let mut nodes = Vec::new();
+        Self::build(&mut nodes, &mut triangles, 0);
+        Some(Self { nodes, triangles })
+    }
+
+    /// Adds the node for `triangles`, which start at `offset` in the final triangle list, and its children.
+    /// Returns the node's index.
+    fn build(nodes: &mut Vec<Node>, triangles: &mut [(usize, Triangle)], offset: usize) -> usize {
+        let bounds = Aabb::from_points(
+            triangles
+                .iter()
+                .flat_map(|(_, triangle)| triangle.vertices.iter().copied()),
+        )
+        .expect("node has triangles");
+
+        let index = nodes.len();
+        if triangles.len() <= MAX_LEAF_TRIANGLES {
+            nodes.push(Node::Leaf {
+                bounds,
+                start: offset,
+                count: triangles.len(),
+            });
+            return index;
+        }
+
+        // Split at the median centroid along the axis the centroids are most spread out on
+        let centroid_bounds =
+            Aabb::from_points(triangles.iter().map(|(_, triangle)| triangle.centroid()))
+                .expect("node has triangles");
+        let spread = centroid_bounds.max - centroid_bounds.min;
+        let axis = if spread.x >= spread.y && spread.x >= spread.z {
+            0
+        } else if spread.y >= spread.z {
+            1
+        } else {
+            2
+        };
+        triangles.sort_by(|(_, a), (_, b)| {
+            a.centroid()[axis]
+                .partial_cmp(&b.centroid()[axis])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        // Reserve this node's slot before its children are added
+        nodes.push(Node::Leaf {
+            bounds,
+            start: offset,
+            count: 0,
+        });
+        let middle = triangles.len() / 2;
+        let (left_triangles, right_triangles) = triangles.split_at_mut(middle);
+        let left = Self::build(nodes, left_triangles, offset);
+        let right = Self::build(nodes, right_triangles, offset + middle);
+        nodes[index] = Node::Interior {
+            bounds,
+            left,
+            right,
+        };
+        index
+    }
+
+    /// The triangles in the order the hierarchy keeps them
+    pub fn triangles(&self) -> impl Iterator<Item = &Triangle> {
+        self.triangles.iter().map(|(_, triangle)| triangle)
+    }
+
+    /// The closest hit along the ray, or `None` if it doesn't hit any triangle.
+    pub fn raycast(&self, ray: &Ray) -> Option<Hit> {
+        let mut closest: Option<(f32, usize)> = None;
+        let mut stack = vec![0];
+
+        while let Some(node) = stack.pop() {
+            let max_distance = closest.map_or(f32::INFINITY, |(distance, _)| distance);
+            if ray
+                .intersect_aabb(self.nodes[node].bounds(), max_distance)
+                .is_none()
+            {
+                continue;
+            }
+
+            match self.nodes[node] {
+                Node::Leaf { start, count, .. } => {
+                    for i in start..start + count {
+                        if let Some(distance) = ray.intersect_triangle(&self.triangles[i].1) {
+                            if distance < max_distance
+                                && closest.is_none_or(|(closest, _)| distance < closest)
+                            {
+                                closest = Some((distance, i));
+                            }
+                        }
+                    }
+                }
+                Node::Interior { left, right, .. } => {
+                    stack.push(left);
+                    stack.push(right);
+                }
+            }
+        }
+
+        closest.map(|(distance, i)| {
+            let (triangle_index, triangle) = self.triangles[i];
+            let normal = triangle.normal();
+            Hit {
+                distance,
+                point: ray.at(distance),
+                normal: if normal.dot(ray.direction) > 0.0 {
+                    -normal
+                } else {
+                    normal
+                },
+                triangle: triangle_index,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A row of unit squares along x facing +z, each two triangles, enough of them for the hierarchy to split
+    fn squares(count: u16, z: f32) -> (Vec<Point3<f32>>, Vec<u16>) {
+        let mut positions = Vec::new();
+        let mut indices = Vec::new();
+        for square in 0..count {
+            let x = square as f32;
+            let first = square * 4;
+            positions.extend_from_slice(&[
+                Point3::new(x, 0.0, z),
+                Point3::new(x + 1.0, 0.0, z),
+                Point3::new(x + 1.0, 1.0, z),
+                Point3::new(x, 1.0, z),
+            ]);
+            indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+        }
+        (positions, indices)
+    }
+
+    #[test]
+    fn hits_the_closest_triangle() {
+        let (mut positions, mut indices) = squares(16, 0.0);
+        let (behind, behind_indices) = squares(16, -2.0);
+        let offset = positions.len() as u16;
+        positions.extend(behind);
+        indices.extend(behind_indices.iter().map(|index| index + offset));
+        let bvh = Bvh::new(&positions, &indices).unwrap();
+
+        let ray = Ray::new(Point3::new(5.25, 0.75, 3.0), Vector3::new(0.0, 0.0, -1.0));
+        let hit = bvh.raycast(&ray).unwrap();
+        assert!((hit.distance - 3.0).abs() < 1e-5);
+        assert!((hit.point - Point3::new(5.25, 0.75, 0.0)).magnitude() < 1e-5);
+        // The upper left triangle of the sixth square
+        assert_eq!(hit.triangle, 11);
+        assert!((hit.normal - Vector3::new(0.0, 0.0, 1.0)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn normals_face_the_ray() {
+        let (positions, indices) = squares(4, 0.0);
+        let bvh = Bvh::new(&positions, &indices).unwrap();
+        let ray = Ray::new(Point3::new(1.5, 0.5, -1.0), Vector3::new(0.0, 0.0, 2.0));
+        let hit = bvh.raycast(&ray).unwrap();
+        // Distances are in multiples of the direction's length
+        assert!((hit.distance - 0.5).abs() < 1e-5);
+        assert!((hit.normal - Vector3::new(0.0, 0.0, -1.0)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn misses() {
+        let (positions, indices) = squares(16, 0.0);
+        let bvh = Bvh::new(&positions, &indices).unwrap();
+        let past_the_end = Ray::new(Point3::new(17.0, 0.5, 1.0), Vector3::new(0.0, 0.0, -1.0));
+        assert!(bvh.raycast(&past_the_end).is_none());
+        let away = Ray::new(Point3::new(2.5, 0.5, 1.0), Vector3::new(0.0, 0.0, 1.0));
+        assert!(bvh.raycast(&away).is_none());
+        let parallel = Ray::new(Point3::new(-1.0, 0.5, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        assert!(bvh.raycast(&parallel).is_none());
+    }
+
+    #[test]
+    fn no_triangles() {
+        assert!(Bvh::new(&[Point3::new(0.0, 0.0, 0.0)], &[]).is_none());
+    }
+}
@@ -0,0 +1,178 @@
+use ash::vk;
+
+use crate::features::SwapchainContext;
+use crate::{postprocess, HelloTriangleApplication};
+
+/// Whether whole frames can be copied out of the swapchain's images and read as four bytes a pixel
+pub fn supported(context: &SwapchainContext) -> bool {
+    context.usage.contains(vk::ImageUsageFlags::TRANSFER_SRC)
+        && matches!(
+            context.format,
+            vk::Format::B8G8R8A8_SRGB
+                | vk::Format::B8G8R8A8_UNORM
+                | vk::Format::R8G8B8A8_SRGB
+                | vk::Format::R8G8B8A8_UNORM
+        )
+}
+
+/// A host visible buffer a whole frame is copied into by one swapchain image's command buffer. The frame is read
+/// once the image's commands have finished, a few frames after it was drawn.
+pub struct Readback {
+    buffer: vk::Buffer,
+    memory: vk::DeviceMemory,
+    /// Whether the commands last recorded for the image copied a frame
+    pending: bool,
+}
+
+impl Readback {
+    /// A buffer for each of the swapchain's images
+    pub fn create_all(context: &SwapchainContext) -> Vec<Self> {
+        let size = frame_size(context.extent);
+        (0..context.image_count)
+            .map(|_| {
+                let (buffer, memory) = HelloTriangleApplication::create_buffer(
+                    context.device,
+                    size,
+                    vk::BufferUsageFlags::TRANSFER_DST,
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                    context.device_memory_properties,
+                );
+                Self {
+                    buffer,
+                    memory,
+                    pending: false,
+                }
+            })
+            .collect()
+    }
+
+    /// The frame the image's previous commands copied, as red, green, blue and alpha bytes row by row from the
+    /// top. Must only be called once they have finished.
+    pub fn take(
+        &mut self,
+        device: &ash::Device,
+        extent: vk::Extent2D,
+        format: vk::Format,
+    ) -> Option<Vec<u8>> {
+        if !self.pending {
+            return None;
+        }
+        self.pending = false;
+
+        let size = frame_size(extent);
+        let mut pixels = vec![0u8; size as usize];
+        unsafe {
+            let data = device
+                .map_memory(self.memory, 0, size, vk::MemoryMapFlags::empty())
+                .expect("Mapping frame readback memory") as *const u8;
+            data.copy_to_nonoverlapping(pixels.as_mut_ptr(), pixels.len());
+            device.unmap_memory(self.memory);
+        }
+        if let vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM = format {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        Some(pixels)
+    }
+
+    /// Records copying the whole presented image into the buffer. The swapchain image is ready to present again
+    /// afterwards.
+    pub fn record_copy(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        swapchain_image: vk::Image,
+        extent: vk::Extent2D,
+    ) {
+        self.pending = true;
+
+        let image_barrier = |old: vk::ImageLayout,
+                             new: vk::ImageLayout,
+                             src_access: vk::AccessFlags,
+                             dst_access: vk::AccessFlags| {
+            vk::ImageMemoryBarrier::builder()
+                .old_layout(old)
+                .new_layout(new)
+                .src_access_mask(src_access)
+                .dst_access_mask(dst_access)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(swapchain_image)
+                .subresource_range(postprocess::color_subresource_range())
+                .build()
+        };
+        let copy = vk::BufferImageCopy::builder()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(postprocess::color_subresource_layers())
+            .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+            .image_extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            })
+            .build();
+        let host_barrier = vk::BufferMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(self.buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE)
+            .build();
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::COMPUTE_SHADER
+                    | vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[image_barrier(
+                    vk::ImageLayout::PRESENT_SRC_KHR,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::TRANSFER_WRITE,
+                    vk::AccessFlags::TRANSFER_READ,
+                )],
+            );
+            device.cmd_copy_image_to_buffer(
+                command_buffer,
+                swapchain_image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                self.buffer,
+                &[copy],
+            );
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::HOST | vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[host_barrier],
+                &[image_barrier(
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::ImageLayout::PRESENT_SRC_KHR,
+                    vk::AccessFlags::TRANSFER_READ,
+                    vk::AccessFlags::empty(),
+                )],
+            );
+        }
+    }
+
+    pub fn destroy(self, device: &ash::Device) {
+        unsafe {
+            device.destroy_buffer(self.buffer, None);
+            device.free_memory(self.memory, None);
+        }
+    }
+}
+
+fn frame_size(extent: vk::Extent2D) -> vk::DeviceSize {
+    extent.width as vk::DeviceSize * extent.height as vk::DeviceSize * 4
+}
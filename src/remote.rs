@@ -0,0 +1,412 @@
+use std::any::Any;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use ash::vk;
+use winit::dpi::PhysicalPosition;
+use winit::event::{ModifiersState, VirtualKeyCode};
+
+use crate::features::{RenderFeature, SwapchainContext};
+use crate::{framegraph, hooks, readback, render_thread};
+
+/// How long sending a frame can stall before the client is dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Quality frames are encoded with as JPEG, from 1 to 100
+#[cfg(feature = "images")]
+const JPEG_QUALITY: u8 = 80;
+
+/// Keys a client can press, by winit's name for them
+const KEYS: [VirtualKeyCode; 70] = {
+    use VirtualKeyCode::*;
+    [
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z, Key0, Key1,
+        Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10,
+        F11, F12, Escape, Tab, Back, Return, Space, Insert, Delete, Home, End, PageUp, PageDown,
+        Left, Up, Right, Down, LBracket, RBracket, Grave, Minus, Equals, Comma, Period,
+    ]
+};
+
+/// How streamed frames are encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// Red, green, blue and alpha bytes, row by row from the top
+    Raw,
+    #[cfg(feature = "images")]
+    Jpeg,
+}
+
+impl Encoding {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "raw" => Some(Encoding::Raw),
+            #[cfg(feature = "images")]
+            "jpeg" => Some(Encoding::Jpeg),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Encoding::Raw => "raw",
+            #[cfg(feature = "images")]
+            Encoding::Jpeg => "jpeg",
+        }
+    }
+}
+
+/// A frame read back from the swapchain, as red, green, blue and alpha bytes
+struct Captured {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+/// Streams every presented frame over TCP to a client, and passes the input the client sends back to the
+/// renderer as if it came from the window, for previewing a renderer running on a remote machine. One client is
+/// streamed to at a time, and a new connection replaces the last.
+///
+/// Each frame is sent as a line `frame <width> <height> <raw|jpeg> <bytes>` followed by that many bytes of the
+/// encoded frame. Clients send input as lines of text:
+///
+/// ```text
+/// cursor <x> <y>
+/// leave
+/// mouse <down|up>
+/// wheel <lines>
+/// key <name>
+/// modifiers [shift] [ctrl] [alt] [logo]
+/// ```
+///
+/// Cursor positions are in pixels from the top left of the frame, and keys are named as winit names them, such
+/// as `Space` or `Key1`. Frames are read back like the inspector's pixels, a few frames late, and are encoded and
+/// sent on a thread of their own. Frames arriving while the last is still being sent are dropped, so a slow
+/// connection lowers the frame rate streamed rather than the renderer's. Frames are only read back while a
+/// client is connected, from swapchain images that can be copied from with four bytes a pixel.
+pub struct Remote {
+    /// Encoded and sent on the streaming thread, which takes one frame at a time
+    frames: SyncSender<Captured>,
+    inputs: Receiver<render_thread::Input>,
+    /// What the listener and streaming threads, and the feature itself, have to report, see `take_reports`
+    reports: Sender<String>,
+    reported: Receiver<String>,
+    connected: Arc<AtomicBool>,
+    readbacks: Vec<readback::Readback>,
+    extent: vk::Extent2D,
+    format: vk::Format,
+    /// Whether the swapchain images can be copied from in a format frames can be sent in
+    copy: bool,
+}
+
+impl Remote {
+    /// Listens for a client on the address, such as `127.0.0.1:7878`. Clients aren't authenticated and their input
+    /// drives the renderer like its own window's, so only listen on other interfaces on a trusted network.
+    pub fn new(address: &str, encoding: Encoding) -> Result<Self, String> {
+        let listener =
+            TcpListener::bind(address).map_err(|e| format!("Listening on {}: {}", address, e))?;
+        let client = Arc::new(Mutex::new(None));
+        let connected = Arc::new(AtomicBool::new(false));
+        let (frames, captured) = mpsc::sync_channel(1);
+        let (input_sender, inputs) = mpsc::channel();
+        let (reports, reported) = mpsc::channel();
+        let _ = reports.send(format!(
+            "Streaming frames to clients connecting to {}",
+            address
+        ));
+        {
+            let client = client.clone();
+            let connected = connected.clone();
+            let reports = reports.clone();
+            thread::Builder::new()
+                .name(String::from("remote listener"))
+                .spawn(move || accept(listener, client, connected, input_sender, reports))
+                .map_err(|e| format!("Spawning the listener thread: {}", e))?;
+        }
+        {
+            let connected = connected.clone();
+            let reports = reports.clone();
+            thread::Builder::new()
+                .name(String::from("remote streaming"))
+                .spawn(move || send_frames(captured, client, connected, encoding, reports))
+                .map_err(|e| format!("Spawning the streaming thread: {}", e))?;
+        }
+        Ok(Self {
+            frames,
+            inputs,
+            reports,
+            reported,
+            connected,
+            readbacks: Vec::new(),
+            extent: vk::Extent2D::default(),
+            format: vk::Format::UNDEFINED,
+            copy: false,
+        })
+    }
+
+    /// The input clients have sent since it was last taken
+    pub fn take_inputs(&mut self) -> Vec<render_thread::Input> {
+        self.inputs.try_iter().collect()
+    }
+
+    /// What has happened to the stream since it was last taken, such as clients connecting, for the renderer to
+    /// emit as `events::Event::Streaming`
+    pub fn take_reports(&mut self) -> Vec<String> {
+        self.reported.try_iter().collect()
+    }
+
+    /// Passes the frame the image's previous commands copied to the streaming thread. Must only be called once
+    /// they have finished.
+    fn send_frame(&mut self, device: &ash::Device, image_index: usize) {
+        let pixels = match self.readbacks.get_mut(image_index) {
+            Some(readback) => readback.take(device, self.extent, self.format),
+            None => None,
+        };
+        let pixels = match pixels {
+            Some(pixels) => pixels,
+            None => return,
+        };
+        let frame = Captured {
+            width: self.extent.width,
+            height: self.extent.height,
+            pixels,
+        };
+        match self.frames.try_send(frame) {
+            // Still sending an earlier frame, so this one is dropped
+            Ok(()) | Err(TrySendError::Full(_)) => {}
+            Err(TrySendError::Disconnected(_)) => self.connected.store(false, Ordering::Relaxed),
+        }
+    }
+}
+
+impl RenderFeature for Remote {
+    fn name(&self) -> &str {
+        "remote streaming"
+    }
+
+    fn init(&mut self, context: &SwapchainContext) {
+        self.extent = context.extent;
+        self.format = context.format;
+        self.copy = readback::supported(context);
+        if !self.copy {
+            let _ = self.reports.send(format!(
+                "Frames can't be streamed from {:?} swapchain images",
+                context.format
+            ));
+            return;
+        }
+        self.readbacks = readback::Readback::create_all(context);
+    }
+
+    fn resize(&mut self, context: &SwapchainContext) {
+        self.destroy(context.device);
+        self.init(context);
+    }
+
+    fn prepare(&mut self, context: &SwapchainContext, image_index: usize) {
+        self.send_frame(context.device, image_index);
+    }
+
+    fn record(&mut self, context: &hooks::FrameContext) {
+        if context.stage == hooks::Stage::AfterUi
+            && self.copy
+            && self.connected.load(Ordering::Relaxed)
+        {
+            self.readbacks[context.image_index].record_copy(
+                context.device,
+                context.command_buffer,
+                context.swapchain_image,
+                self.extent,
+            );
+        }
+    }
+
+    fn passes(&self) -> Vec<framegraph::Pass> {
+        if !self.copy || !self.connected.load(Ordering::Relaxed) {
+            return Vec::new();
+        }
+        vec![framegraph::Pass::new(
+            self.name(),
+            framegraph::Position::Stage(hooks::Stage::AfterUi),
+            &[framegraph::SWAPCHAIN],
+            &["streamed frame"],
+        )]
+    }
+
+    fn destroy(&mut self, device: &ash::Device) {
+        for readback in self.readbacks.drain(..) {
+            readback.destroy(device);
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Accepts clients for as long as the renderer runs, each replacing the last, and reads their input
+fn accept(
+    listener: TcpListener,
+    client: Arc<Mutex<Option<TcpStream>>>,
+    connected: Arc<AtomicBool>,
+    inputs: Sender<render_thread::Input>,
+    reports: Sender<String>,
+) {
+    for stream in listener.incoming() {
+        let (stream, reader) = match stream.and_then(|stream| {
+            stream.set_nodelay(true)?;
+            stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+            let reader = stream.try_clone()?;
+            Ok((stream, reader))
+        }) {
+            Ok(streams) => streams,
+            Err(e) => {
+                let _ = reports.send(format!("Not accepting a streaming client: {}", e));
+                continue;
+            }
+        };
+        let peer = stream
+            .peer_addr()
+            .map_or(String::from("a client"), |address| address.to_string());
+        let _ = reports.send(format!("Streaming frames to {}", peer));
+        if let Some(last) = client.lock().expect("Locking the client").replace(stream) {
+            // Also ends the last client's input thread
+            let _ = last.shutdown(Shutdown::Both);
+        }
+        connected.store(true, Ordering::Relaxed);
+
+        let inputs = inputs.clone();
+        let input_reports = reports.clone();
+        let spawned = thread::Builder::new()
+            .name(String::from("remote input"))
+            .spawn(move || read_input(reader, inputs, input_reports));
+        if let Err(e) = spawned {
+            let _ = reports.send(format!("Not reading input from {}: {}", peer, e));
+        }
+    }
+}
+
+/// Passes each line of input the client sends to the renderer, until the client disconnects
+fn read_input(client: TcpStream, inputs: Sender<render_thread::Input>, reports: Sender<String>) {
+    for (number, line) in BufReader::new(client).lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        match parse_input(line.trim()) {
+            Ok(input) => {
+                if inputs.send(input).is_err() {
+                    return;
+                }
+            }
+            Err(e) => {
+                let _ = reports.send(format!("Remote input line {}: {}", number + 1, e));
+            }
+        }
+    }
+}
+
+fn parse_input(line: &str) -> Result<render_thread::Input, String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    match fields[..] {
+        ["cursor", x, y] => match (x.parse(), y.parse()) {
+            (Ok(x), Ok(y)) => Ok(render_thread::Input::CursorMoved(PhysicalPosition::new(
+                x, y,
+            ))),
+            _ => Err(format!("{} {} isn't a valid position", x, y)),
+        },
+        ["leave"] => Ok(render_thread::Input::CursorLeft),
+        ["mouse", "down"] => Ok(render_thread::Input::LeftMouseButton(true)),
+        ["mouse", "up"] => Ok(render_thread::Input::LeftMouseButton(false)),
+        ["wheel", lines] => lines
+            .parse()
+            .map(render_thread::Input::MouseWheel)
+            .map_err(|_| format!("{} isn't a number of lines", lines)),
+        ["key", name] => KEYS
+            .iter()
+            .find(|key| format!("{:?}", key) == name)
+            .map(|&key| render_thread::Input::KeyPressed(key))
+            .ok_or_else(|| format!("unknown key {}", name)),
+        ["modifiers", ref held @ ..] => {
+            let mut modifiers = ModifiersState::empty();
+            for &name in held {
+                modifiers |= match name {
+                    "shift" => ModifiersState::SHIFT,
+                    "ctrl" => ModifiersState::CTRL,
+                    "alt" => ModifiersState::ALT,
+                    "logo" => ModifiersState::LOGO,
+                    _ => return Err(format!("unknown modifier {}", name)),
+                };
+            }
+            Ok(render_thread::Input::Modifiers(modifiers))
+        }
+        _ => Err(format!("unknown input {}", line)),
+    }
+}
+
+/// Encodes each frame and sends it to the client, if there is one
+fn send_frames(
+    frames: Receiver<Captured>,
+    client: Arc<Mutex<Option<TcpStream>>>,
+    connected: Arc<AtomicBool>,
+    encoding: Encoding,
+    reports: Sender<String>,
+) {
+    for frame in frames {
+        let bytes = match encoding {
+            Encoding::Raw => frame.pixels,
+            #[cfg(feature = "images")]
+            Encoding::Jpeg => {
+                let rgb: Vec<u8> = frame
+                    .pixels
+                    .chunks_exact(4)
+                    .flat_map(|pixel| pixel[..3].iter().copied())
+                    .collect();
+                let mut jpeg = Vec::new();
+                let encoded =
+                    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY)
+                        .encode(&rgb, frame.width, frame.height, image::ColorType::Rgb8);
+                if let Err(e) = encoded {
+                    let _ = reports.send(format!("Not streaming a frame: {}", e));
+                    continue;
+                }
+                jpeg
+            }
+        };
+        // Sent outside the lock, so a stalled client doesn't keep a new one from replacing it
+        let stream = match client.lock().expect("Locking the client").as_ref() {
+            Some(stream) => stream.try_clone(),
+            None => continue,
+        };
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                let _ = reports.send(format!("Not streaming a frame: {}", e));
+                continue;
+            }
+        };
+        let sent = writeln!(
+            stream,
+            "frame {} {} {} {}",
+            frame.width,
+            frame.height,
+            encoding.name(),
+            bytes.len()
+        )
+        .and_then(|_| stream.write_all(&bytes));
+        if let Err(e) = sent {
+            let _ = reports.send(format!("Stopped streaming frames: {}", e));
+            let mut client = client.lock().expect("Locking the client");
+            // Unless a new client has replaced the one that failed in the meantime
+            let peer = stream.peer_addr().ok();
+            if client.as_ref().map(|current| current.peer_addr().ok()) == Some(peer) {
+                *client = None;
+                connected.store(false, Ordering::Relaxed);
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use winit::dpi::PhysicalPosition;
+use winit::event::{
+    ElementState, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta, VirtualKeyCode,
+    WindowEvent,
+};
+use winit::event_loop::EventLoopProxy;
+
+use crate::{statistics, validation, HelloTriangleApplication};
+
+/// How often the render thread reports its frame rate
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+/// Touchpads scroll by pixels rather than lines, this many to a line
+const PIXELS_PER_LINE: f64 = 20.0;
+
+/// Window input the renderer responds to.
+#[derive(Clone, Copy, Debug)]
+pub enum Input {
+    CursorMoved(PhysicalPosition<f64>),
+    CursorLeft,
+    Modifiers(ModifiersState),
+    /// Whether the left mouse button is now pressed
+    LeftMouseButton(bool),
+    /// Lines scrolled with the mouse wheel, positive away from the user
+    MouseWheel(f32),
+    KeyPressed(VirtualKeyCode),
+    /// Text typed, after the key presses that typed it
+    Character(char),
+}
+
+impl Input {
+    /// The input a window event carries, if the renderer uses it.
+    pub fn from_event(event: &WindowEvent) -> Option<Self> {
+        match *event {
+            WindowEvent::CursorMoved { position, .. } => Some(Input::CursorMoved(position)),
+            WindowEvent::CursorLeft { .. } => Some(Input::CursorLeft),
+            WindowEvent::ModifiersChanged(modifiers) => Some(Input::Modifiers(modifiers)),
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => Some(Input::LeftMouseButton(state == ElementState::Pressed)),
+            WindowEvent::MouseWheel { delta, .. } => Some(Input::MouseWheel(match delta {
+                MouseScrollDelta::LineDelta(_, lines) => lines,
+                MouseScrollDelta::PixelDelta(pixels) => (pixels.y / PIXELS_PER_LINE) as f32,
+            })),
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => Some(Input::KeyPressed(key)),
+            WindowEvent::ReceivedCharacter(character) => Some(Input::Character(character)),
+            _ => None,
+        }
+    }
+}
+
+/// Sent from the window's event thread to the render thread.
+#[derive(Clone, Copy, Debug)]
+pub enum Message {
+    Input(Input),
+    /// The window's size changed, so the swapchain has to be recreated
+    Resized,
+    /// The window moved to a display with a different scale factor, or the display's changed
+    ScaleFactorChanged(f64),
+    /// The window is closing. The renderer is destroyed and the thread ends.
+    Close,
+}
+
+/// How fast the renderer has been drawing since the last report.
+#[derive(Clone, Debug)]
+pub struct Stats {
+    pub frames: u32,
+    pub elapsed: Duration,
+    /// The average time each frame preparation system took per frame
+    pub systems: Vec<(&'static str, Duration)>,
+    /// The average work each pass did per frame, when pipeline statistics are enabled
+    pub pipeline: Vec<(&'static str, statistics::Counts)>,
+}
+
+impl Stats {
+    pub fn frames_per_second(&self) -> f32 {
+        self.frames as f32 / self.elapsed.as_secs_f32()
+    }
+}
+
+/// Sent from the render thread to wake the window's event loop.
+#[derive(Clone, Debug)]
+pub enum Notification {
+    Stats(Stats),
+    /// The render thread has ended, either after `Message::Close` or because it panicked
+    Stopped,
+}
+
+/// Sends `Notification::Stopped` when the render thread ends, however it ends.
+struct StoppedGuard(EventLoopProxy<Notification>);
+
+impl Drop for StoppedGuard {
+    fn drop(&mut self) {
+        // The event loop may already have exited
+        let _ = self.0.send_event(Notification::Stopped);
+    }
+}
+
+/// Renders on its own thread so the window's event thread never waits on the GPU, and the event thread being
+/// held up, such as while the window is dragged or resized on Windows, doesn't stall rendering.
+pub struct RenderThread {
+    sender: mpsc::Sender<Message>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl RenderThread {
+    /// Starts a thread that creates the renderer with `create` and then draws frames continuously, handling
+    /// the messages sent to it between frames. With a frame limit the thread closes the renderer and ends once
+    /// it has drawn that many.
+    pub fn spawn<F>(
+        create: F,
+        frame_limit: Option<u32>,
+        proxy: EventLoopProxy<Notification>,
+    ) -> Self
+    where
+        F: FnOnce() -> HelloTriangleApplication + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let handle = thread::Builder::new()
+            .name(String::from("render"))
+            .spawn(move || {
+                let _stopped = StoppedGuard(proxy.clone());
+                let app = create();
+                run(app, receiver, frame_limit, proxy);
+            })
+            .expect("Spawning render thread");
+
+        Self {
+            sender,
+            handle: Some(handle),
+        }
+    }
+
+    /// Passes the message to the render thread, which handles it before drawing its next frame.
+    pub fn send(&self, message: Message) {
+        // The thread has already stopped if this fails, which is reported with `Notification::Stopped`
+        let _ = self.sender.send(message);
+    }
+
+    /// Asks the render thread to close and waits for it to destroy the renderer.
+    pub fn close(&mut self) {
+        self.send(Message::Close);
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                println!("The render thread panicked");
+            }
+        }
+    }
+}
+
+fn run(
+    mut app: HelloTriangleApplication,
+    receiver: mpsc::Receiver<Message>,
+    frame_limit: Option<u32>,
+    proxy: EventLoopProxy<Notification>,
+) {
+    let mut frames = 0;
+    let mut frames_drawn = 0;
+    let mut last_report = Instant::now();
+    loop {
+        loop {
+            match receiver.try_recv() {
+                Ok(Message::Input(input)) => app.handle_input(input),
+                Ok(Message::Resized) => app.frame_buffer_resized = true,
+                Ok(Message::ScaleFactorChanged(scale_factor)) => app.set_scale_factor(scale_factor),
+                Ok(Message::Close) | Err(mpsc::TryRecvError::Disconnected) => {
+                    app.close();
+                    return;
+                }
+                Err(mpsc::TryRecvError::Empty) => break,
+            }
+        }
+
+        app.draw_frame();
+        validation::frame_drawn();
+        frames_drawn += 1;
+        if frame_limit == Some(frames_drawn) {
+            app.close();
+            return;
+        }
+
+        frames += 1;
+        let elapsed = last_report.elapsed();
+        if elapsed >= STATS_INTERVAL {
+            let stats = Stats {
+                frames,
+                elapsed,
+                systems: app.take_timings(),
+                pipeline: app.take_pipeline_statistics(),
+            };
+            if proxy.send_event(Notification::Stats(stats)).is_err() {
+                return;
+            }
+            frames = 0;
+            last_report = Instant::now();
+        }
+    }
+}
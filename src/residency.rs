@@ -0,0 +1,367 @@
+use std::any::Any;
+use std::time::{Duration, Instant};
+
+use ash::vk;
+
+use crate::features::{RenderFeature, SwapchainContext};
+use crate::font;
+use crate::{framegraph, hooks, host_memory, postprocess, HelloTriangleApplication};
+
+/// Characters across and lines down the panel
+const COLUMNS: usize = 36;
+const LINES: usize = 5;
+/// Logical pixels between the panel's edge and its text
+const MARGIN: u32 = 4;
+const BACKGROUND: [u8; 3] = [24, 24, 32];
+const TEXT_COLOR: [u8; 3] = [190, 230, 190];
+/// How long uploads are added up over for the bandwidth
+const BANDWIDTH_WINDOW: Duration = Duration::from_secs(1);
+
+/// Adds up the bytes uploaded to the device for meshes and textures, to work out how fast they are uploading.
+pub struct Uploads {
+    bytes: vk::DeviceSize,
+    since: Instant,
+    /// Bytes a second over the last whole window
+    bandwidth: f64,
+}
+
+impl Default for Uploads {
+    fn default() -> Self {
+        Self {
+            bytes: 0,
+            since: Instant::now(),
+            bandwidth: 0.0,
+        }
+    }
+}
+
+impl Uploads {
+    pub fn add(&mut self, bytes: vk::DeviceSize) {
+        self.bytes += bytes;
+    }
+
+    /// Bytes uploaded a second, over the last second that has passed
+    pub fn bandwidth(&mut self) -> f64 {
+        let elapsed = self.since.elapsed();
+        if elapsed >= BANDWIDTH_WINDOW {
+            self.bandwidth = self.bytes as f64 / elapsed.as_secs_f64();
+            self.bytes = 0;
+            self.since = Instant::now();
+        }
+        self.bandwidth
+    }
+}
+
+/// What the streaming systems are doing, shown by `Overlay`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Metrics {
+    /// Device memory textures take, and would take at their sources' sizes had they not been shrunk to fit
+    pub texture_resident: vk::DeviceSize,
+    pub texture_requested: vk::DeviceSize,
+    pub texture_budget: vk::DeviceSize,
+    /// World cells waiting to be loaded, if a world is streaming
+    pub queued_cells: Option<usize>,
+    /// Bytes a second
+    pub upload_bandwidth: f64,
+    /// The Vulkan implementation's own host memory, when it is tracked
+    pub host_memory: Option<host_memory::Usage>,
+}
+
+impl Metrics {
+    fn lines(&self) -> [String; LINES] {
+        let mib = |bytes: f64| bytes / (1u64 << 20) as f64;
+        let budget = match self.texture_budget {
+            vk::DeviceSize::MAX => String::from("none"),
+            budget => format!("{:.1} MiB", mib(budget as f64)),
+        };
+        [
+            format!(
+                "Textures {:.1}/{:.1} MiB",
+                mib(self.texture_resident as f64),
+                mib(self.texture_requested as f64)
+            ),
+            format!("Budget   {}", budget),
+            match self.queued_cells {
+                Some(cells) => format!("Queued   {} cells", cells),
+                None => String::from("Queued   not streaming"),
+            },
+            format!("Uploads  {:.2} MiB/s", mib(self.upload_bandwidth)),
+            match self.host_memory {
+                Some(usage) => format!(
+                    "Driver   {:.1} MiB host, {:.1} peak",
+                    mib((usage.allocated + usage.internal) as f64),
+                    mib(usage.peak as f64)
+                ),
+                None => String::from("Driver   host memory untracked"),
+            },
+        ]
+    }
+}
+
+/// A host visible buffer the panel is copied to the swapchain image from, one for each image so that it can be
+/// written while the others' commands are still reading theirs.
+struct Panel {
+    buffer: vk::Buffer,
+    memory: vk::DeviceMemory,
+}
+
+/// A panel in the top right corner of the frame showing the texture memory resident against what was requested,
+/// how many world cells are waiting to stream in, how fast meshes and textures are being uploaded and the host
+/// memory the Vulkan implementation is using, for tuning the texture budget and the world's load radius. Drawn on
+/// the CPU with the console's bitmap font and copied over the presented image like the console, so it only draws
+/// on the same swapchains.
+#[derive(Default)]
+pub struct Overlay {
+    pub visible: bool,
+    metrics: Option<Metrics>,
+    pixels: Vec<u8>,
+    dirty: bool,
+    panels: Vec<Panel>,
+    extent: vk::Extent2D,
+    /// The panel's size in physical pixels
+    size: (usize, usize),
+    font: Option<font::Font>,
+    margin: usize,
+    bgra: bool,
+    copy: bool,
+}
+
+impl Overlay {
+    /// Shows the metrics from now on, drawing the panel again if they changed
+    pub fn set_metrics(&mut self, metrics: Metrics) {
+        if self.metrics != Some(metrics) {
+            self.metrics = Some(metrics);
+            self.dirty = true;
+        }
+    }
+
+    fn draw(&mut self) {
+        let font = match self.font.as_ref() {
+            Some(font) => font,
+            None => return,
+        };
+        let (width, height) = self.size;
+        let background = self.color(BACKGROUND);
+        let text_color = self.color(TEXT_COLOR);
+        self.pixels.clear();
+        for _ in 0..width * height {
+            self.pixels.extend_from_slice(&background);
+        }
+
+        let lines = self.metrics.map(|metrics| metrics.lines());
+        for (row, line) in lines.iter().flatten().enumerate() {
+            let top = self.margin + row * font.glyph_height();
+            for (column, character) in line.chars().take(COLUMNS).enumerate() {
+                let left = self.margin + column * font.glyph_width();
+                let glyph = font.glyph(character);
+                for (y, glyph_row) in glyph.chunks_exact(font.glyph_width()).enumerate() {
+                    for (x, &covered) in glyph_row.iter().enumerate() {
+                        if covered {
+                            let pixel = ((top + y) * width + left + x) * 4;
+                            self.pixels[pixel..pixel + 4].copy_from_slice(&text_color);
+                        }
+                    }
+                }
+            }
+        }
+        self.dirty = false;
+    }
+
+    /// The colour's bytes in the swapchain's order
+    fn color(&self, [red, green, blue]: [u8; 3]) -> [u8; 4] {
+        match self.bgra {
+            true => [blue, green, red, 255],
+            false => [red, green, blue, 255],
+        }
+    }
+
+    fn record_copy(&self, context: &hooks::FrameContext) {
+        let image_barrier = |old: vk::ImageLayout,
+                             new: vk::ImageLayout,
+                             src_access: vk::AccessFlags,
+                             dst_access: vk::AccessFlags| {
+            vk::ImageMemoryBarrier::builder()
+                .old_layout(old)
+                .new_layout(new)
+                .src_access_mask(src_access)
+                .dst_access_mask(dst_access)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(context.swapchain_image)
+                .subresource_range(postprocess::color_subresource_range())
+                .build()
+        };
+        let (width, height) = self.size;
+        let copy = vk::BufferImageCopy::builder()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(postprocess::color_subresource_layers())
+            .image_offset(vk::Offset3D {
+                x: (self.extent.width as usize - width) as i32,
+                y: 0,
+                z: 0,
+            })
+            .image_extent(vk::Extent3D {
+                width: width as u32,
+                height: height as u32,
+                depth: 1,
+            })
+            .build();
+
+        let device = context.device;
+        unsafe {
+            device.cmd_pipeline_barrier(
+                context.command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::COMPUTE_SHADER
+                    | vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[image_barrier(
+                    vk::ImageLayout::PRESENT_SRC_KHR,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                        | vk::AccessFlags::TRANSFER_READ
+                        | vk::AccessFlags::TRANSFER_WRITE,
+                    vk::AccessFlags::TRANSFER_WRITE,
+                )],
+            );
+            device.cmd_copy_buffer_to_image(
+                context.command_buffer,
+                self.panels[context.image_index].buffer,
+                context.swapchain_image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[copy],
+            );
+            device.cmd_pipeline_barrier(
+                context.command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[image_barrier(
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::PRESENT_SRC_KHR,
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    vk::AccessFlags::TRANSFER_READ,
+                )],
+            );
+        }
+    }
+
+    fn drawn(&self) -> bool {
+        self.visible && self.copy && self.metrics.is_some()
+    }
+}
+
+impl RenderFeature for Overlay {
+    fn name(&self) -> &str {
+        "residency overlay"
+    }
+
+    fn init(&mut self, context: &SwapchainContext) {
+        self.extent = context.extent;
+        let font = font::Font::new(context.scale_factor);
+        self.margin = context.scaled(MARGIN) as usize;
+        self.size = (
+            COLUMNS * font.glyph_width() + 2 * self.margin,
+            LINES * font.glyph_height() + 2 * self.margin,
+        );
+        self.font = Some(font);
+        self.bgra = matches!(
+            context.format,
+            vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM
+        );
+        // The panel is left out of frames too small to hold it
+        self.copy = context.usage.contains(vk::ImageUsageFlags::TRANSFER_DST)
+            && matches!(
+                context.format,
+                vk::Format::B8G8R8A8_SRGB
+                    | vk::Format::B8G8R8A8_UNORM
+                    | vk::Format::R8G8B8A8_SRGB
+                    | vk::Format::R8G8B8A8_UNORM
+            )
+            && self.size.0 <= self.extent.width as usize
+            && self.size.1 <= self.extent.height as usize;
+        if !self.copy {
+            return;
+        }
+        let size = (self.size.0 * self.size.1 * 4) as vk::DeviceSize;
+        self.panels = (0..context.image_count)
+            .map(|_| {
+                let (buffer, memory) = HelloTriangleApplication::create_buffer(
+                    context.device,
+                    size,
+                    vk::BufferUsageFlags::TRANSFER_SRC,
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                    context.device_memory_properties,
+                );
+                Panel { buffer, memory }
+            })
+            .collect();
+        self.dirty = true;
+    }
+
+    fn resize(&mut self, context: &SwapchainContext) {
+        self.destroy(context.device);
+        self.init(context);
+    }
+
+    fn prepare(&mut self, context: &SwapchainContext, image_index: usize) {
+        if !self.drawn() {
+            return;
+        }
+        if self.dirty {
+            self.draw();
+        }
+        let memory = self.panels[image_index].memory;
+        unsafe {
+            let data = context
+                .device
+                .map_memory(
+                    memory,
+                    0,
+                    self.pixels.len() as vk::DeviceSize,
+                    vk::MemoryMapFlags::empty(),
+                )
+                .expect("Mapping residency overlay memory") as *mut u8;
+            data.copy_from_nonoverlapping(self.pixels.as_ptr(), self.pixels.len());
+            context.device.unmap_memory(memory);
+        }
+    }
+
+    fn record(&mut self, context: &hooks::FrameContext) {
+        if context.stage == hooks::Stage::AfterUi && self.drawn() {
+            self.record_copy(context);
+        }
+    }
+
+    fn passes(&self) -> Vec<framegraph::Pass> {
+        if !self.drawn() {
+            return Vec::new();
+        }
+        vec![framegraph::Pass::new(
+            self.name(),
+            framegraph::Position::Stage(hooks::Stage::AfterUi),
+            &[framegraph::SWAPCHAIN],
+            &[framegraph::SWAPCHAIN],
+        )]
+    }
+
+    fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            for panel in self.panels.drain(..) {
+                device.destroy_buffer(panel.buffer, None);
+                device.free_memory(panel.memory, None);
+            }
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
@@ -0,0 +1,267 @@
+use ash::vk;
+
+/// Renders each frame may do by default
+pub const DEFAULT_BUDGET: usize = 2;
+
+/// One render a task does in a frame: the part of it drawn again, such as a face of a reflection probe's cube
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Work {
+    pub task: usize,
+    pub part: usize,
+}
+
+struct Task {
+    name: String,
+    /// Frames a part is drawn for before it's due to be drawn again
+    interval: u32,
+    /// The frame each part was last drawn in, `None` until it has been drawn
+    drawn: Vec<Option<u64>>,
+}
+
+impl Task {
+    /// The part drawn longest ago, starting with those never drawn, and the frames since it was drawn
+    fn oldest(&self, frame: u64) -> (usize, Option<u64>) {
+        let (part, drawn) = self
+            .drawn
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, drawn)| drawn.map_or(0, |drawn| drawn + 1))
+            .expect("Tasks have parts");
+        (part, drawn.map(|drawn| frame - drawn))
+    }
+}
+
+/// Spreads expensive auxiliary renders, such as the minimap and reflection probes, across frames so that no
+/// frame does more than `budget` of them whatever has been added, keeping the cost of the frame stable.
+///
+/// Each task is split into parts drawn one at a time, like the six faces of a cube, and wants each part drawn
+/// again `interval` frames after it was last drawn. Every frame the scheduler picks the part of each task drawn
+/// longest ago, and draws those most overdue for their interval within the budget, never more than one part of
+/// a task in a frame. Parts never drawn come first, and the rest are drawn round-robin, so a task's parts and
+/// tasks that are equally overdue take turns. How stale a task is, the frames since the oldest of its parts was
+/// drawn, is kept for showing how far behind the budget leaves them.
+pub struct Scheduler {
+    /// The most parts drawn in a frame, at least 1
+    pub budget: usize,
+    frame: u64,
+    tasks: Vec<Option<Task>>,
+    /// What the current frame draws
+    scheduled: Vec<Work>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self {
+            budget: DEFAULT_BUDGET,
+            frame: 0,
+            tasks: Vec::new(),
+            scheduled: Vec::new(),
+        }
+    }
+}
+
+impl Scheduler {
+    /// Adds a task of `parts` parts, each drawn every `interval` frames, returning its index
+    pub fn add(&mut self, name: &str, parts: usize, interval: u32) -> usize {
+        let task = Task {
+            name: String::from(name),
+            interval: interval.max(1),
+            drawn: vec![None; parts.max(1)],
+        };
+        match self.tasks.iter().position(Option::is_none) {
+            Some(index) => {
+                self.tasks[index] = Some(task);
+                index
+            }
+            None => {
+                self.tasks.push(Some(task));
+                self.tasks.len() - 1
+            }
+        }
+    }
+
+    pub fn remove(&mut self, task: usize) {
+        if let Some(slot) = self.tasks.get_mut(task) {
+            *slot = None;
+        }
+        self.scheduled.retain(|work| work.task != task);
+    }
+
+    /// Treats every part of the task as never drawn, so they're drawn again first, such as after what they're
+    /// drawn into has been made again
+    pub fn invalidate(&mut self, task: usize) {
+        if let Some(task) = self.tasks.get_mut(task).and_then(Option::as_mut) {
+            task.drawn.iter_mut().for_each(|drawn| *drawn = None);
+        }
+    }
+
+    /// Moves on to the next frame and picks what it draws, which are then counted as drawn
+    pub fn next_frame(&mut self) -> &[Work] {
+        self.frame += 1;
+        let frame = self.frame;
+        let mut due: Vec<(f32, Work)> = self
+            .tasks
+            .iter()
+            .enumerate()
+            .filter_map(|(index, task)| {
+                let task = task.as_ref()?;
+                let (part, age) = task.oldest(frame);
+                let overdue = match age {
+                    Some(age) if age < task.interval as u64 => return None,
+                    Some(age) => age as f32 / task.interval as f32,
+                    None => f32::INFINITY,
+                };
+                Some((overdue, Work { task: index, part }))
+            })
+            .collect();
+        // Stable, so tasks as overdue as each other keep the order they were added in
+        due.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        self.scheduled.clear();
+        self.scheduled.extend(
+            due.into_iter()
+                .take(self.budget.max(1))
+                .map(|(_, work)| work),
+        );
+        for work in self.scheduled.iter() {
+            if let Some(task) = self.tasks[work.task].as_mut() {
+                task.drawn[work.part] = Some(frame);
+            }
+        }
+        &self.scheduled
+    }
+
+    /// The part of the task drawn in the current frame, if it's drawn in it
+    pub fn scheduled(&self, task: usize) -> Option<usize> {
+        self.scheduled
+            .iter()
+            .find(|work| work.task == task)
+            .map(|work| work.part)
+    }
+
+    /// Frames since the task's oldest part was drawn, or `None` if it has parts that haven't been drawn yet
+    pub fn staleness(&self, task: usize) -> Option<u64> {
+        let task = self.tasks.get(task)?.as_ref()?;
+        task.oldest(self.frame).1
+    }
+
+    /// A line for each task saying how stale it is against its interval
+    pub fn report(&self) -> Vec<String> {
+        self.tasks
+            .iter()
+            .enumerate()
+            .filter_map(|(index, task)| {
+                let task = task.as_ref()?;
+                let parts = match task.drawn.len() {
+                    1 => String::new(),
+                    parts => format!(", {} parts", parts),
+                };
+                let staleness = match self.staleness(index) {
+                    Some(frames) => format!("{} frames stale", frames),
+                    None => String::from("not drawn yet"),
+                };
+                Some(format!(
+                    "{}: every {} frames{}, {}",
+                    task.name, task.interval, parts, staleness
+                ))
+            })
+            .collect()
+    }
+}
+
+/// A scheduled render's own copies of the scene's uniform buffers and descriptor sets, one for each swapchain
+/// image, created by the renderer like its own. A render drawn at most once a frame can write its uniforms for
+/// the frame's image without waiting for the frames still in flight.
+pub struct View {
+    pub uniform_buffers: Vec<vk::Buffer>,
+    pub uniform_buffers_memory: Vec<vk::DeviceMemory>,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_sets: Vec<vk::DescriptorSet>,
+}
+
+impl View {
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            for (&buffer, &memory) in self
+                .uniform_buffers
+                .iter()
+                .zip(self.uniform_buffers_memory.iter())
+            {
+                device.destroy_buffer(buffer, None);
+                device.free_memory(memory, None);
+            }
+        }
+    }
+}
+
+/// Records clearing every layer of a colour image made for a scheduled render to `color`, and moving it into the
+/// `SHADER_READ_ONLY_OPTIMAL` layout, so it can be read before the scheduler gets round to drawing into it. The
+/// image must have been made with `TRANSFER_DST` usage and be outside a render pass.
+pub fn record_clear(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    layer_count: u32,
+    color: &vk::ClearColorValue,
+) {
+    let range = vk::ImageSubresourceRange {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        base_mip_level: 0,
+        level_count: 1,
+        base_array_layer: 0,
+        layer_count,
+    };
+    let barrier = |old_layout, new_layout, src_access_mask, dst_access_mask| {
+        vk::ImageMemoryBarrier::builder()
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_access_mask(src_access_mask)
+            .dst_access_mask(dst_access_mask)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(range)
+            .build()
+    };
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[barrier(
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::AccessFlags::empty(),
+                vk::AccessFlags::TRANSFER_WRITE,
+            )],
+        );
+        device.cmd_clear_color_image(
+            command_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            color,
+            &[range],
+        );
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER
+                | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[barrier(
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::SHADER_READ
+                    | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                    | vk::AccessFlags::TRANSFER_READ,
+            )],
+        );
+    }
+}
@@ -0,0 +1,315 @@
+use std::mem::size_of;
+use std::path::Path;
+
+use ash::vk;
+
+use crate::{postprocess, util, HelloTriangleApplication};
+
+/// Brightness levels counted per channel by the histogram
+const HISTOGRAM_LEVELS: usize = 256;
+/// Histogram channels: red, green, blue and luminance
+const HISTOGRAM_CHANNELS: usize = 4;
+/// Horizontal and vertical resolution of the waveform
+const WAVEFORM_COLUMNS: usize = 256;
+const WAVEFORM_LEVELS: usize = 128;
+
+/// Counters in the scope buffer after the peak, which is the largest of them
+const BIN_COUNT: usize =
+    if HISTOGRAM_LEVELS * HISTOGRAM_CHANNELS > WAVEFORM_COLUMNS * WAVEFORM_LEVELS {
+        HISTOGRAM_LEVELS * HISTOGRAM_CHANNELS
+    } else {
+        WAVEFORM_COLUMNS * WAVEFORM_LEVELS
+    };
+
+/// Size in pixels of the panel the scope is drawn in, in the frame's bottom left corner
+const PANEL_SIZE: [u32; 2] = [256, 128];
+
+/// Colour analysis drawn over the final image to diagnose exposure and grading.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// How many pixels have each level of red, green, blue and luminance, darkest on the left
+    Histogram = 0,
+    /// The spread of luminance in each column of the image, darkest at the bottom
+    Waveform = 1,
+}
+
+impl Mode {
+    /// The next mode shown when cycling through them, `None` turning the scope off.
+    pub fn next(mode: Option<Self>) -> Option<Self> {
+        match mode {
+            None => Some(Mode::Histogram),
+            Some(Mode::Histogram) => Some(Mode::Waveform),
+            Some(Mode::Waveform) => None,
+        }
+    }
+}
+
+/// Settings shared with both scope shaders through push constants.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Settings {
+    mode: i32,
+    /// Whether the frame holds linear colour that is encoded to sRGB when presented. Levels are measured on
+    /// the encoded values the display receives.
+    linear_frame: i32,
+}
+
+impl Settings {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+}
+
+/// Counts the levels of the final image into a storage buffer on the GPU and draws the counts back over the
+/// image, as a stage at the end of post processing.
+pub struct Scopes {
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    layout: vk::PipelineLayout,
+    accumulate: vk::Pipeline,
+    draw: vk::Pipeline,
+    /// The peak count followed by the bins, cleared every frame
+    buffer: vk::Buffer,
+    buffer_memory: vk::DeviceMemory,
+}
+
+impl Scopes {
+    pub fn new(
+        device: &ash::Device,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    ) -> Self {
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_count(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build(),
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(1)
+                .descriptor_count(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build(),
+        ];
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+                    None,
+                )
+                .expect("Scope descriptor set layout")
+        };
+
+        let set_layouts = [descriptor_set_layout];
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(size_of::<Settings>() as u32)
+            .build()];
+        let layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::builder()
+                        .set_layouts(&set_layouts)
+                        .push_constant_ranges(&push_constant_ranges),
+                    None,
+                )
+                .expect("Scope pipeline layout")
+        };
+
+        let load = |name: &str| {
+            let path = Path::new(env!("OUT_DIR")).join(name);
+            postprocess::create_compute_pipeline(device, layout, &util::read_shader_code(&path))
+        };
+        let accumulate = load("scope_accumulate_comp.spv");
+        let draw = load("scope_draw_comp.spv");
+
+        let (buffer, buffer_memory) = HelloTriangleApplication::create_buffer(
+            device,
+            ((1 + BIN_COUNT) * size_of::<u32>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            device_memory_properties,
+        );
+
+        Self {
+            descriptor_set_layout,
+            layout,
+            accumulate,
+            draw,
+            buffer,
+            buffer_memory,
+        }
+    }
+
+    /// Allocates a set from the pool for each of the post processing images.
+    pub fn allocate_descriptor_sets(
+        &self,
+        device: &ash::Device,
+        pool: vk::DescriptorPool,
+        image_views: [vk::ImageView; 2],
+    ) -> [vk::DescriptorSet; 2] {
+        let set_layouts = [self.descriptor_set_layout; 2];
+        let sets = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::builder()
+                        .descriptor_pool(pool)
+                        .set_layouts(&set_layouts),
+                )
+                .expect("Scope descriptor sets")
+        };
+
+        let buffer_info = [vk::DescriptorBufferInfo::builder()
+            .buffer(self.buffer)
+            .offset(0)
+            .range(vk::WHOLE_SIZE)
+            .build()];
+        let image_infos = image_views.map(|view| {
+            [vk::DescriptorImageInfo::builder()
+                .image_layout(vk::ImageLayout::GENERAL)
+                .image_view(view)
+                .build()]
+        });
+        let writes: Vec<vk::WriteDescriptorSet> = (0..2)
+            .flat_map(|i| {
+                [
+                    vk::WriteDescriptorSet::builder()
+                        .dst_set(sets[i])
+                        .dst_binding(0)
+                        .dst_array_element(0)
+                        .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                        .image_info(&image_infos[i])
+                        .build(),
+                    vk::WriteDescriptorSet::builder()
+                        .dst_set(sets[i])
+                        .dst_binding(1)
+                        .dst_array_element(0)
+                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                        .buffer_info(&buffer_info)
+                        .build(),
+                ]
+            })
+            .collect();
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+
+        [sets[0], sets[1]]
+    }
+
+    /// Records the scope over the image the set was allocated for, which must be in the `GENERAL` layout with
+    /// earlier compute writes to it complete.
+    pub fn record(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        descriptor_set: vk::DescriptorSet,
+        mode: Mode,
+        extent: vk::Extent2D,
+        linear_frame: bool,
+    ) {
+        let settings = Settings {
+            mode: mode as i32,
+            linear_frame: linear_frame as i32,
+        };
+        let buffer_barrier = |src_access: vk::AccessFlags, dst_access: vk::AccessFlags| {
+            vk::BufferMemoryBarrier::builder()
+                .src_access_mask(src_access)
+                .dst_access_mask(dst_access)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .buffer(self.buffer)
+                .offset(0)
+                .size(vk::WHOLE_SIZE)
+                .build()
+        };
+        let workgroups = |size: u32| size.div_ceil(postprocess::WORKGROUP_SIZE);
+
+        unsafe {
+            // The previous frame's scope may still be drawing from the counts
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[buffer_barrier(
+                    vk::AccessFlags::SHADER_READ,
+                    vk::AccessFlags::TRANSFER_WRITE,
+                )],
+                &[],
+            );
+            device.cmd_fill_buffer(command_buffer, self.buffer, 0, vk::WHOLE_SIZE, 0);
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[buffer_barrier(
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+                )],
+                &[],
+            );
+
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                self.layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                settings.as_bytes(),
+            );
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.accumulate,
+            );
+            device.cmd_dispatch(
+                command_buffer,
+                workgroups(extent.width),
+                workgroups(extent.height),
+                1,
+            );
+
+            // Drawing reads every count, and overwrites pixels the counting read
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[buffer_barrier(
+                    vk::AccessFlags::SHADER_WRITE,
+                    vk::AccessFlags::SHADER_READ,
+                )],
+                &[],
+            );
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, self.draw);
+            device.cmd_dispatch(
+                command_buffer,
+                workgroups(PANEL_SIZE[0]),
+                workgroups(PANEL_SIZE[1]),
+                1,
+            );
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            device.destroy_buffer(self.buffer, None);
+            device.free_memory(self.buffer_memory, None);
+            device.destroy_pipeline(self.accumulate, None);
+            device.destroy_pipeline(self.draw, None);
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::any::Any;
+use std::path::PathBuf;
+
+use ash::vk;
+
+use crate::features::{RenderFeature, SwapchainContext};
+use crate::{framegraph, hooks, images, readback};
+
+/// Saves the next presented frame to an image file, read back a few frames later like the inspector's pixels. It
+/// records before the overlays added after it, such as the console, so they aren't in the screenshot.
+#[derive(Default)]
+pub struct Screenshot {
+    /// Where the next frame is saved, until it is copied
+    requested: Option<PathBuf>,
+    /// Each image's readback buffer, with where the frame it copied is saved
+    readbacks: Vec<(readback::Readback, Option<PathBuf>)>,
+    extent: vk::Extent2D,
+    format: vk::Format,
+    /// Whether the swapchain images can be copied from in a format that can be saved
+    copy: bool,
+}
+
+impl Screenshot {
+    /// Saves the next frame to the path, in the format its extension names
+    pub fn request(&mut self, path: PathBuf) -> Result<(), String> {
+        if !self.copy {
+            return Err(format!(
+                "Screenshots can't be taken of {:?} swapchain images",
+                self.format
+            ));
+        }
+        self.requested = Some(path);
+        Ok(())
+    }
+
+    fn save(&mut self, device: &ash::Device, image_index: usize) {
+        let (readback, path) = match self.readbacks.get_mut(image_index) {
+            Some(readback) => readback,
+            None => return,
+        };
+        let pixels = match readback.take(device, self.extent, self.format) {
+            Some(pixels) => pixels,
+            None => return,
+        };
+        let path = path.take().expect("Frames are only copied to be saved");
+        let image = images::Image {
+            width: self.extent.width,
+            height: self.extent.height,
+            pixels,
+        };
+        match images::save(&image, &path) {
+            Ok(()) => println!("Saved a screenshot to {}", path.display()),
+            Err(e) => println!("Couldn't save a screenshot: {}", e),
+        }
+    }
+}
+
+impl RenderFeature for Screenshot {
+    fn name(&self) -> &str {
+        "screenshot"
+    }
+
+    fn init(&mut self, context: &SwapchainContext) {
+        self.extent = context.extent;
+        self.format = context.format;
+        self.copy = readback::supported(context);
+        if self.copy {
+            self.readbacks = readback::Readback::create_all(context)
+                .into_iter()
+                .map(|readback| (readback, None))
+                .collect();
+        }
+    }
+
+    fn resize(&mut self, context: &SwapchainContext) {
+        self.destroy(context.device);
+        self.init(context);
+    }
+
+    fn prepare(&mut self, context: &SwapchainContext, image_index: usize) {
+        self.save(context.device, image_index);
+    }
+
+    fn record(&mut self, context: &hooks::FrameContext) {
+        if context.stage != hooks::Stage::AfterUi || !self.copy {
+            return;
+        }
+        if let Some(path) = self.requested.take() {
+            let (readback, saved_to) = &mut self.readbacks[context.image_index];
+            readback.record_copy(
+                context.device,
+                context.command_buffer,
+                context.swapchain_image,
+                self.extent,
+            );
+            *saved_to = Some(path);
+        }
+    }
+
+    fn passes(&self) -> Vec<framegraph::Pass> {
+        if self.requested.is_none() {
+            return Vec::new();
+        }
+        vec![framegraph::Pass::new(
+            self.name(),
+            framegraph::Position::Stage(hooks::Stage::AfterUi),
+            &[framegraph::SWAPCHAIN],
+            &["screenshot"],
+        )]
+    }
+
+    fn destroy(&mut self, device: &ash::Device) {
+        for (readback, _) in self.readbacks.drain(..) {
+            readback.destroy(device);
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
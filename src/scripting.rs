@@ -0,0 +1,385 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use cgmath::{Deg, Matrix4, Vector3};
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST};
+use winit::event::VirtualKeyCode;
+
+use crate::{fog, material, pipeline, render_thread, timeline};
+
+/// Operations a script may run each frame before it is stopped, so that a script stuck in a loop can't hang the
+/// renderer
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// The input a script can react to.
+#[derive(Clone, Debug, Default)]
+pub struct Input {
+    /// The cursor's position in pixels from the top left of the window, while it is over the window
+    pub cursor: Option<(f32, f32)>,
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub left_mouse: bool,
+    /// Keys pressed since the script last ran
+    pub keys: Vec<VirtualKeyCode>,
+}
+
+impl Input {
+    pub fn handle(&mut self, input: &render_thread::Input) {
+        match *input {
+            render_thread::Input::CursorMoved(position) => {
+                self.cursor = Some((position.x as f32, position.y as f32))
+            }
+            render_thread::Input::CursorLeft => self.cursor = None,
+            render_thread::Input::Modifiers(modifiers) => {
+                self.shift = modifiers.shift();
+                self.ctrl = modifiers.ctrl();
+                self.alt = modifiers.alt();
+            }
+            render_thread::Input::LeftMouseButton(pressed) => self.left_mouse = pressed,
+            render_thread::Input::KeyPressed(key) => self.keys.push(key),
+            render_thread::Input::MouseWheel(_) | render_thread::Input::Character(_) => {}
+        }
+    }
+}
+
+/// The part of the renderer a script reads and changes, copied in before it runs and back out after.
+#[derive(Clone, Debug, Default)]
+pub struct State {
+    /// Seconds since the renderer started, or into the timeline while one plays
+    pub time: f32,
+    /// Each draw's transform and pipeline state, by its index
+    pub draws: Vec<(Matrix4<f32>, pipeline::Config)>,
+    pub scene: usize,
+    pub scene_count: usize,
+    /// Whether the fog is enabled, and its settings, when fog has been added
+    pub fog: Option<(bool, fog::Settings)>,
+    pub input: Input,
+    /// Calls the script made with draws or scenes that don't exist
+    pub errors: Vec<String>,
+}
+
+impl State {
+    /// The draw, or `None` once the call has been noted as an error if there is no such draw
+    fn draw(&mut self, call: &str, draw: i64) -> Option<&mut (Matrix4<f32>, pipeline::Config)> {
+        if draw < 0 || draw as usize >= self.draws.len() {
+            self.errors
+                .push(format!("{}: there's no draw {}", call, draw));
+            return None;
+        }
+        Some(&mut self.draws[draw as usize])
+    }
+
+    fn fog(&mut self, call: &str) -> Option<&mut (bool, fog::Settings)> {
+        if self.fog.is_none() {
+            self.errors.push(format!("{}: fog hasn't been added", call));
+        }
+        self.fog.as_mut()
+    }
+}
+
+/// A Rhai script run at the start of every frame, to prototype the scene's behaviour without recompiling the
+/// renderer. Scripts move and restyle draws, switch scenes, change the fog and react to input through the
+/// functions `engine` registers, and see the seconds since the renderer started and since their last frame as
+/// `time` and `delta`. Each run starts afresh, except for the `state` object map, which keeps whatever the
+/// script puts in it from one frame to the next.
+///
+/// The script is compiled again whenever its file changes, keeping `state`. A script that fails to run is not run
+/// again until its file changes.
+pub struct Script {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    /// Shared with the functions the script calls
+    state: Rc<RefCell<State>>,
+    /// The time the script last ran at
+    time: Option<f32>,
+    failed: bool,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let state = Rc::new(RefCell::new(State::default()));
+        let engine = engine(&state);
+        let modified = modified(path);
+        let ast = compile(&engine, path)?;
+        let mut scope = Scope::new();
+        scope.push("state", Map::new());
+        scope.push("time", 0.0_f64);
+        scope.push("delta", 0.0_f64);
+        Ok(Self {
+            path: path.to_path_buf(),
+            modified,
+            engine,
+            ast,
+            scope,
+            state,
+            time: None,
+            failed: false,
+        })
+    }
+
+    /// Runs the script over the state, returning the state as the script left it
+    pub fn run(&mut self, state: State) -> Result<State, String> {
+        self.compile_if_changed();
+        if self.failed {
+            return Ok(state);
+        }
+        let delta = self.time.map_or(0.0, |time| state.time - time);
+        self.time = Some(state.time);
+        self.scope.set_value("time", state.time as f64);
+        self.scope.set_value("delta", delta as f64);
+
+        *self.state.borrow_mut() = state;
+        let variables = self.scope.len();
+        let result = self.engine.run_ast_with_scope(&mut self.scope, &self.ast);
+        // Only the variables that were there before it ran last between frames
+        self.scope.rewind(variables);
+        let state = self.state.replace(State::default());
+        match result {
+            Ok(()) => Ok(state),
+            Err(e) => {
+                self.failed = true;
+                Err(format!("{}: {}", self.path.display(), e))
+            }
+        }
+    }
+
+    fn compile_if_changed(&mut self) {
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return;
+        }
+        self.modified = modified;
+        match compile(&self.engine, &self.path) {
+            Ok(ast) => {
+                println!("Compiled {}", self.path.display());
+                self.ast = ast;
+                self.failed = false;
+            }
+            Err(e) => println!("Failed to compile {}: {}", self.path.display(), e),
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn compile(engine: &Engine, path: &Path) -> Result<AST, String> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("Reading {}: {}", path.display(), e))?;
+    engine
+        .compile(&text)
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// The engine scripts run on, with the functions they call to read and change the state:
+///
+/// - `draw_count()`, `position(draw)` as `[x, y, z]`, `translate(draw, x, y, z)` in the scene's space,
+///   `rotate(draw, degrees)` about the draw's own Z axis, and `set_transform(draw, x, y, z, degrees, scale)`
+/// - `set_double_sided(draw, bool)`, `set_alpha_cutoff(draw, cutoff)`, `set_opaque(draw)`,
+///   `set_vertex_colors(draw, bool)` and `set_casts_shadows(draw, bool)`, which change the draw's material
+/// - `scene()`, `scene_count()` and `switch_scene(scene)`
+/// - `fog_enabled()`, `set_fog(bool)`, `set_fog_density(density)` and `set_fog_anisotropy(anisotropy)`
+/// - `key_pressed(name)` with winit's name for the key, such as `"Space"` or `"W"`, `shift()`, `ctrl()`,
+///   `alt()`, `mouse_down()` and `cursor()` as `[x, y]` in pixels, or `[]` outside the window
+///
+/// Draws and scenes are numbered from zero. Functions taking numbers other than draws and scenes take floating
+/// point numbers, written with a decimal point.
+fn engine(state: &Rc<RefCell<State>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+
+    let shared = state.clone();
+    engine.register_fn("draw_count", move || shared.borrow().draws.len() as i64);
+    let shared = state.clone();
+    engine.register_fn("position", move |draw: i64| -> Array {
+        match shared.borrow_mut().draw("position", draw) {
+            Some((transform, _)) => [transform.w.x, transform.w.y, transform.w.z]
+                .iter()
+                .map(|&value| Dynamic::from(value as f64))
+                .collect(),
+            None => Array::new(),
+        }
+    });
+    let shared = state.clone();
+    engine.register_fn("translate", move |draw: i64, x: f64, y: f64, z: f64| {
+        if let Some((transform, _)) = shared.borrow_mut().draw("translate", draw) {
+            let translation = Vector3::new(x as f32, y as f32, z as f32);
+            *transform = Matrix4::from_translation(translation) * *transform;
+        }
+    });
+    let shared = state.clone();
+    engine.register_fn("rotate", move |draw: i64, degrees: f64| {
+        if let Some((transform, _)) = shared.borrow_mut().draw("rotate", draw) {
+            *transform = *transform * Matrix4::from_angle_z(Deg(degrees as f32));
+        }
+    });
+    let shared = state.clone();
+    engine.register_fn(
+        "set_transform",
+        move |draw: i64, x: f64, y: f64, z: f64, degrees: f64, scale: f64| {
+            if let Some((transform, _)) = shared.borrow_mut().draw("set_transform", draw) {
+                *transform = timeline::Placement {
+                    translation: Vector3::new(x as f32, y as f32, z as f32),
+                    rotation: Deg(degrees as f32),
+                    scale: scale as f32,
+                }
+                .transform();
+            }
+        },
+    );
+
+    let shared = state.clone();
+    engine.register_fn("set_double_sided", move |draw: i64, double_sided: bool| {
+        edit_material(&shared, "set_double_sided", draw, |material| {
+            material.double_sided = double_sided
+        })
+    });
+    let shared = state.clone();
+    engine.register_fn("set_alpha_cutoff", move |draw: i64, cutoff: f64| {
+        edit_material(&shared, "set_alpha_cutoff", draw, |material| {
+            material.blend_mode = material::BlendMode::Masked {
+                cutoff: cutoff as f32,
+            }
+        })
+    });
+    let shared = state.clone();
+    engine.register_fn("set_opaque", move |draw: i64| {
+        edit_material(&shared, "set_opaque", draw, |material| {
+            material.blend_mode = material::BlendMode::Opaque
+        })
+    });
+    let shared = state.clone();
+    engine.register_fn("set_vertex_colors", move |draw: i64, colored: bool| {
+        edit_material(&shared, "set_vertex_colors", draw, |material| {
+            material.vertex_color = match colored {
+                true => material::VertexColorMode::Multiply,
+                false => material::VertexColorMode::Ignore,
+            }
+        })
+    });
+    let shared = state.clone();
+    engine.register_fn(
+        "set_casts_shadows",
+        move |draw: i64, casts_shadows: bool| {
+            edit_material(&shared, "set_casts_shadows", draw, |material| {
+                material.casts_shadows = casts_shadows
+            })
+        },
+    );
+    let shared = state.clone();
+    engine.register_fn("set_toon", move |draw: i64, ramp: i64, outline: f64| {
+        edit_material(&shared, "set_toon", draw, |material| {
+            material.toon = Some(material::Toon {
+                ramp: ramp.clamp(0, u8::MAX as i64) as u8,
+                outline: outline as f32,
+            })
+        })
+    });
+    let shared = state.clone();
+    engine.register_fn("set_smooth_shading", move |draw: i64| {
+        edit_material(&shared, "set_smooth_shading", draw, |material| {
+            material.toon = None
+        })
+    });
+
+    let shared = state.clone();
+    engine.register_fn("scene", move || shared.borrow().scene as i64);
+    let shared = state.clone();
+    engine.register_fn("scene_count", move || shared.borrow().scene_count as i64);
+    let shared = state.clone();
+    engine.register_fn("switch_scene", move |scene: i64| {
+        let mut state = shared.borrow_mut();
+        if scene < 0 || scene as usize >= state.scene_count {
+            state
+                .errors
+                .push(format!("switch_scene: there's no scene {}", scene));
+        } else {
+            state.scene = scene as usize;
+        }
+    });
+
+    let shared = state.clone();
+    engine.register_fn("fog_enabled", move || {
+        matches!(shared.borrow().fog, Some((true, _)))
+    });
+    let shared = state.clone();
+    engine.register_fn("set_fog", move |enabled: bool| {
+        if let Some(fog) = shared.borrow_mut().fog("set_fog") {
+            fog.0 = enabled;
+        }
+    });
+    let shared = state.clone();
+    engine.register_fn("set_fog_density", move |density: f64| {
+        if let Some(fog) = shared.borrow_mut().fog("set_fog_density") {
+            fog.1.density = density as f32;
+        }
+    });
+    let shared = state.clone();
+    engine.register_fn("set_fog_anisotropy", move |anisotropy: f64| {
+        if let Some(fog) = shared.borrow_mut().fog("set_fog_anisotropy") {
+            fog.1.anisotropy = anisotropy as f32;
+        }
+    });
+
+    let shared = state.clone();
+    engine.register_fn("key_pressed", move |name: &str| {
+        shared
+            .borrow()
+            .input
+            .keys
+            .iter()
+            .any(|key| format!("{:?}", key) == name)
+    });
+    let shared = state.clone();
+    engine.register_fn("shift", move || shared.borrow().input.shift);
+    let shared = state.clone();
+    engine.register_fn("ctrl", move || shared.borrow().input.ctrl);
+    let shared = state.clone();
+    engine.register_fn("alt", move || shared.borrow().input.alt);
+    let shared = state.clone();
+    engine.register_fn("mouse_down", move || shared.borrow().input.left_mouse);
+    let shared = state.clone();
+    engine.register_fn("cursor", move || -> Array {
+        match shared.borrow().input.cursor {
+            Some((x, y)) => vec![Dynamic::from(x as f64), Dynamic::from(y as f64)],
+            None => Array::new(),
+        }
+    });
+    engine
+}
+
+/// Changes the material the draw's pipeline state was made from, keeping the rest of its pipeline state
+fn edit_material(
+    state: &Rc<RefCell<State>>,
+    call: &str,
+    draw: i64,
+    edit: impl FnOnce(&mut material::Material),
+) {
+    if let Some((_, config)) = state.borrow_mut().draw(call, draw) {
+        let mut material = material::Material::from_config(config);
+        edit(&mut material);
+        let surface = material.pipeline_config(config.vertex_attributes);
+        *config = pipeline::Config {
+            cull_mode: surface.cull_mode,
+            front_face: surface.front_face,
+            double_sided: surface.double_sided,
+            alpha_cutoff: surface.alpha_cutoff,
+            vertex_color: surface.vertex_color,
+            occlusion_strength: surface.occlusion_strength,
+            toon_ramp: surface.toon_ramp,
+            outline_width: surface.outline_width,
+            casts_shadows: surface.casts_shadows,
+            ..*config
+        };
+    }
+}
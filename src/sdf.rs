@@ -0,0 +1,1171 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fmt::Debug;
+use std::mem::size_of;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+use ash::vk;
+use memoffset::offset_of;
+
+use crate::features::{RenderFeature, SwapchainContext};
+use crate::{font, framegraph, hooks, images, pipeline, util, HelloTriangleApplication};
+
+/// The characters an atlas has glyphs for, in order from its top left cell
+const FIRST: char = ' ';
+const LAST: char = '~';
+/// Cells across an atlas, and down it
+const COLUMNS: u32 = 16;
+const ROWS: u32 = 6;
+/// How many times larger than the bitmap font the built in atlas's glyphs are rasterized
+const UPSCALE: f32 = 4.0;
+/// How many texels either side of their edges the built in atlas's distances reach
+const SPREAD: f32 = 6.0;
+/// The most quads drawn in a frame, over every text and shape. Items past it are left out.
+pub const MAX_QUADS: usize = 4096;
+/// The most vertices of tessellated graphics drawn in a frame. Graphics past it are left out.
+pub const MAX_VERTICES: usize = 65536;
+/// How many images the layer can draw at once, see `Layer::add_image`
+pub const MAX_IMAGES: usize = 4;
+
+/// Signed distance fields of the glyphs of a monospaced font, one to a cell of a grid of `COLUMNS` by `ROWS`
+/// holding the printable ASCII characters from the top left. Each texel holds the distance from its centre to the
+/// nearest edge of its glyph, mapped from `-spread` to `spread` texels onto 1 down to 0, so that 0.5 is on the
+/// edge and texels inside the glyph are above it. Sampled with bilinear filtering, edges stay smooth however far
+/// the glyphs are scaled up, and glyphs can be outlined and glow out to `spread` texels without another atlas.
+#[derive(Clone, Debug)]
+pub struct Atlas {
+    pub width: u32,
+    pub height: u32,
+    /// A byte per texel, row by row from the top
+    distances: Vec<u8>,
+    /// How many texels each cell is across and down, including `spread` texels of padding around its glyph
+    cell: [u32; 2],
+    pub spread: f32,
+}
+
+impl Atlas {
+    /// The atlas of the renderer's bitmap font, `font::Font`, rasterized larger so its distances are finer
+    pub fn from_font() -> Self {
+        let font = font::Font::new(UPSCALE);
+        let padding = SPREAD.ceil() as usize;
+        let (glyph_width, glyph_height) = (font.glyph_width(), font.glyph_height());
+        let cell = [glyph_width + 2 * padding, glyph_height + 2 * padding];
+        let (width, height) = (cell[0] * COLUMNS as usize, cell[1] * ROWS as usize);
+        let mut distances = vec![0; width * height];
+        for (index, character) in (FIRST..=LAST).enumerate() {
+            let glyph = font.glyph(character);
+            let inside = |x: usize, y: usize| {
+                x >= padding
+                    && y >= padding
+                    && x - padding < glyph_width
+                    && y - padding < glyph_height
+                    && glyph[(y - padding) * glyph_width + x - padding]
+            };
+            let field = distance_field(cell[0], cell[1], inside);
+            let (left, top) = (
+                index % COLUMNS as usize * cell[0],
+                index / COLUMNS as usize * cell[1],
+            );
+            for (y, row) in field.chunks_exact(cell[0]).enumerate() {
+                for (x, distance) in row.iter().enumerate() {
+                    distances[(top + y) * width + left + x] = encode(*distance, SPREAD);
+                }
+            }
+        }
+        Self {
+            width: width as u32,
+            height: height as u32,
+            distances,
+            cell: [cell[0] as u32, cell[1] as u32],
+            spread: SPREAD,
+        }
+    }
+
+    /// An atlas made elsewhere, with the distances in the image's red channel. The image is split into the grid
+    /// of cells evenly, and each glyph must be padded by `spread` texels inside its cell.
+    pub fn from_image(image: &images::Image, spread: f32) -> Result<Self, String> {
+        let cell = [image.width / COLUMNS, image.height / ROWS];
+        let padding = spread.ceil() as u32;
+        if spread <= 0.0 || cell[0] <= 2 * padding || cell[1] <= 2 * padding {
+            return Err(format!(
+                "A {}x{} atlas's cells are too small for glyphs padded by {} texels",
+                image.width, image.height, spread
+            ));
+        }
+        Ok(Self {
+            width: image.width,
+            height: image.height,
+            distances: image.pixels.iter().step_by(4).copied().collect(),
+            cell,
+            spread,
+        })
+    }
+
+    /// The atlas as an image with the distances in every colour channel, for uploading as a UNORM texture
+    pub fn image(&self) -> images::Image {
+        images::Image::from_fn(self.width, self.height, |x, y| {
+            let distance = self.distances[(y * self.width + x) as usize];
+            [distance, distance, distance, 255]
+        })
+    }
+
+    fn padding(&self) -> f32 {
+        self.spread.ceil()
+    }
+
+    /// The quads drawing the text with its top left corner at `position`, in pixels. Each line is `size`
+    /// pixels tall, and characters the atlas doesn't have are drawn as '?'.
+    fn text_quads(&self, text: &Text, scale: f32, quads: &mut Vec<Quad>) {
+        let padding = self.padding();
+        let glyph_height = self.cell[1] as f32 - 2.0 * padding;
+        let pixels_per_texel = text.size * scale / glyph_height;
+        let advance = (self.cell[0] as f32 - 2.0 * padding) * pixels_per_texel;
+        let (cell_width, cell_height) = (
+            self.cell[0] as f32 * pixels_per_texel,
+            self.cell[1] as f32 * pixels_per_texel,
+        );
+        // Outlines and glows beyond the padding would show the neighbouring cells
+        let reach = self.spread * pixels_per_texel;
+        let outline_width = (text.style.outline_width * scale).min(reach);
+        let glow_width = (text.style.glow_width * scale).min(reach - outline_width);
+        let origin = [text.position[0] * scale, text.position[1] * scale];
+        for (line, characters) in text.text.lines().enumerate() {
+            let top = origin[1] + line as f32 * text.size * scale - padding * pixels_per_texel;
+            for (column, character) in characters.chars().enumerate() {
+                if character == ' ' {
+                    continue;
+                }
+                let index = match character {
+                    FIRST..=LAST => character as u32 - FIRST as u32,
+                    _ => '?' as u32 - FIRST as u32,
+                };
+                let cell = [
+                    (index % COLUMNS * self.cell[0]) as f32,
+                    (index / COLUMNS * self.cell[1]) as f32,
+                ];
+                let left = origin[0] + column as f32 * advance - padding * pixels_per_texel;
+                quads.push(Quad {
+                    rect: [left, top, cell_width, cell_height],
+                    shape: [cell[0], cell[1], self.cell[0] as f32, self.cell[1] as f32],
+                    fill: text.style.fill,
+                    outline: text.style.outline,
+                    glow: text.style.glow,
+                    parameters: [Kind::Glyph as u32 as f32, outline_width, glow_width, 0.0],
+                });
+            }
+        }
+    }
+}
+
+/// The signed distance from each texel of a `width` by `height` grid to the edge of the texels that are
+/// `inside`, negative inside, found with an exact Euclidean distance transform
+fn distance_field(width: usize, height: usize, inside: impl Fn(usize, usize) -> bool) -> Vec<f32> {
+    let to_inside = squared_distances(width, height, &inside);
+    let to_outside = squared_distances(width, height, |x, y| !inside(x, y));
+    (0..width * height)
+        .map(|index| {
+            // Edges lie halfway between texels either side of them
+            if to_inside[index] == 0.0 {
+                0.5 - to_outside[index].sqrt()
+            } else {
+                to_inside[index].sqrt() - 0.5
+            }
+        })
+        .collect()
+}
+
+/// The squared distance from each texel to the nearest texel that is `set`, by Felzenszwalb and Huttenlocher's
+/// transform along the columns and then along the rows
+fn squared_distances(width: usize, height: usize, set: impl Fn(usize, usize) -> bool) -> Vec<f32> {
+    // Further than any texel can be from another
+    let far = ((width * width + height * height) * 4) as f32;
+    let mut field: Vec<f32> = (0..width * height)
+        .map(|index| match set(index % width, index / width) {
+            true => 0.0,
+            false => far,
+        })
+        .collect();
+    let mut line = Vec::with_capacity(width.max(height));
+    for x in 0..width {
+        line.clear();
+        line.extend((0..height).map(|y| field[y * width + x]));
+        for (y, distance) in transform(&line).into_iter().enumerate() {
+            field[y * width + x] = distance;
+        }
+    }
+    for y in 0..height {
+        let row = &mut field[y * width..(y + 1) * width];
+        let transformed = transform(row);
+        row.copy_from_slice(&transformed);
+    }
+    field
+}
+
+/// One dimensional squared distance transform of `f`, the lower envelope of the parabolas rooted at each sample
+fn transform(f: &[f32]) -> Vec<f32> {
+    let n = f.len();
+    let mut distances = vec![0.0; n];
+    if n == 0 {
+        return distances;
+    }
+    // The parabolas on the envelope, and where each starts to be lowest
+    let mut roots = vec![0; n];
+    let mut starts = vec![0.0; n + 1];
+    let mut k = 0;
+    starts[0] = f32::NEG_INFINITY;
+    starts[1] = f32::INFINITY;
+    let intersection = |q: usize, p: usize| {
+        ((f[q] + (q * q) as f32) - (f[p] + (p * p) as f32)) / (2 * q - 2 * p) as f32
+    };
+    for q in 1..n {
+        let mut s = intersection(q, roots[k]);
+        while s <= starts[k] {
+            k -= 1;
+            s = intersection(q, roots[k]);
+        }
+        k += 1;
+        roots[k] = q;
+        starts[k] = s;
+        starts[k + 1] = f32::INFINITY;
+    }
+    k = 0;
+    for (q, distance) in distances.iter_mut().enumerate() {
+        while starts[k + 1] < q as f32 {
+            k += 1;
+        }
+        let offset = q as f32 - roots[k] as f32;
+        *distance = offset * offset + f[roots[k]];
+    }
+    distances
+}
+
+/// Maps a distance in texels onto a byte, see `Atlas`
+fn encode(distance: f32, spread: f32) -> u8 {
+    ((0.5 - distance / (2.0 * spread)).clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// How text and shapes are coloured. Colours are linear with straight alpha, and widths are in logical pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Style {
+    pub fill: [f32; 4],
+    /// Drawn in a band around the edge, outside it, `outline_width` wide
+    pub outline: [f32; 4],
+    pub outline_width: f32,
+    /// Fades out from the outline over `glow_width`
+    pub glow: [f32; 4],
+    pub glow_width: f32,
+}
+
+impl Default for Style {
+    /// White and unoutlined
+    fn default() -> Self {
+        Self {
+            fill: [1.0; 4],
+            outline: [0.0, 0.0, 0.0, 1.0],
+            outline_width: 0.0,
+            glow: [1.0, 1.0, 1.0, 0.0],
+            glow_width: 0.0,
+        }
+    }
+}
+
+/// Text drawn over the frame, in logical pixels from its top left corner
+#[derive(Clone, Debug, PartialEq)]
+pub struct Text {
+    pub text: String,
+    /// Where the top left corner of the first character is
+    pub position: [f32; 2],
+    /// How tall each line is
+    pub size: f32,
+    pub style: Style,
+}
+
+/// Vector shapes drawn over the frame from their distance functions, in logical pixels from its top left corner
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    Rectangle {
+        position: [f32; 2],
+        size: [f32; 2],
+        /// How far the corners are rounded, 0 for square corners
+        radius: f32,
+    },
+    Circle {
+        centre: [f32; 2],
+        radius: f32,
+    },
+    /// A line with rounded ends
+    Line {
+        from: [f32; 2],
+        to: [f32; 2],
+        width: f32,
+    },
+}
+
+/// Something the layer draws
+#[derive(Clone, Debug)]
+pub enum Item {
+    Text(Text),
+    Shape(Shape, Style),
+    /// One of the layer's images drawn into a rectangle, rounded by `radius`, tinted by the style's fill and
+    /// outlined and glowing like a shape
+    Image {
+        image: usize,
+        position: [f32; 2],
+        size: [f32; 2],
+        radius: f32,
+        style: Style,
+    },
+    /// A graphic made of triangles, fitted into the rectangle with its top left corner at `position`
+    // Only made by the vector feature's graphics, but drawn by the layer either way
+    #[cfg_attr(not(feature = "vector"), allow(dead_code))]
+    Vector {
+        graphic: Arc<dyn Tessellate>,
+        position: [f32; 2],
+        size: [f32; 2],
+    },
+}
+
+/// A graphic the layer draws as triangles, like an SVG icon, see `vector::Graphic`. It's tessellated again
+/// whenever it's drawn at another scale, so curves stay smooth at any size.
+pub trait Tessellate: Debug {
+    /// The triangles drawing the graphic fitted into the rectangle, as its left, top, width and height in
+    /// physical pixels, three vertices to a triangle
+    fn tessellate(&self, rect: [f32; 4]) -> Vec<Vertex>;
+}
+
+/// A corner of a tessellated graphic's triangle, read by `vector_vert.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vertex {
+    /// In physical pixels from the top left of the target
+    pub position: [f32; 2],
+    /// Linear colour with straight alpha
+    pub color: [f32; 4],
+}
+
+impl Vertex {
+    fn get_binding_description() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(size_of::<Self>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX)
+            .build()
+    }
+
+    fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescription; 2] {
+        [
+            vk::VertexInputAttributeDescription::builder()
+                .binding(0)
+                .location(0)
+                .format(vk::Format::R32G32_SFLOAT)
+                .offset(offset_of!(Self, position) as u32)
+                .build(),
+            vk::VertexInputAttributeDescription::builder()
+                .binding(0)
+                .location(1)
+                .format(vk::Format::R32G32B32A32_SFLOAT)
+                .offset(offset_of!(Self, color) as u32)
+                .build(),
+        ]
+    }
+}
+
+/// A run of items drawn with the same pipeline, as a range of a frame's quads or vertices
+#[derive(Clone, Debug, PartialEq)]
+enum Batch {
+    Quads(Range<u32>),
+    Triangles(Range<u32>),
+}
+
+impl Shape {
+    fn quad(&self, style: &Style, scale: f32) -> Quad {
+        let scaled = |point: [f32; 2]| [point[0] * scale, point[1] * scale];
+        let (outline_width, glow_width) = (style.outline_width * scale, style.glow_width * scale);
+        // Room for the outline and glow, and for anti-aliasing the outermost edge
+        let margin = outline_width + glow_width + 1.0;
+        let (kind, min, max, shape, radius) = match *self {
+            Shape::Rectangle {
+                position,
+                size,
+                radius,
+            } => {
+                let (corner, size) = (scaled(position), scaled(size));
+                let opposite = [corner[0] + size[0], corner[1] + size[1]];
+                let min = [corner[0].min(opposite[0]), corner[1].min(opposite[1])];
+                let max = [corner[0].max(opposite[0]), corner[1].max(opposite[1])];
+                let half = [(max[0] - min[0]) / 2.0, (max[1] - min[1]) / 2.0];
+                let radius = (radius * scale).clamp(0.0, half[0].min(half[1]));
+                (Kind::RoundedRectangle, min, max, half, radius)
+            }
+            Shape::Circle { centre, radius } => {
+                let (centre, radius) = (scaled(centre), (radius * scale).abs());
+                let min = [centre[0] - radius, centre[1] - radius];
+                let max = [centre[0] + radius, centre[1] + radius];
+                (Kind::RoundedRectangle, min, max, [radius; 2], radius)
+            }
+            Shape::Line { from, to, width } => {
+                let (from, to, radius) = (scaled(from), scaled(to), (width * scale).abs() / 2.0);
+                let min = [from[0].min(to[0]) - radius, from[1].min(to[1]) - radius];
+                let max = [from[0].max(to[0]) + radius, from[1].max(to[1]) + radius];
+                (Kind::Capsule, min, max, [from[0], from[1]], radius)
+            }
+        };
+        let corner = [min[0] - margin, min[1] - margin];
+        let shape = match *self {
+            Shape::Line { from, to, .. } => {
+                let (from, to) = (scaled(from), scaled(to));
+                [
+                    from[0] - corner[0],
+                    from[1] - corner[1],
+                    to[0] - corner[0],
+                    to[1] - corner[1],
+                ]
+            }
+            _ => [
+                (min[0] + max[0]) / 2.0 - corner[0],
+                (min[1] + max[1]) / 2.0 - corner[1],
+                shape[0],
+                shape[1],
+            ],
+        };
+        Quad {
+            rect: [
+                corner[0],
+                corner[1],
+                max[0] - min[0] + 2.0 * margin,
+                max[1] - min[1] + 2.0 * margin,
+            ],
+            shape,
+            fill: style.fill,
+            outline: style.outline,
+            glow: style.glow,
+            parameters: [kind as u32 as f32, outline_width, glow_width, radius],
+        }
+    }
+}
+
+/// Matches the kinds in `sdf_frag.glsl`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Glyph = 0,
+    RoundedRectangle = 1,
+    Capsule = 2,
+    /// Followed by the kinds of the layer's other images, `Kind::Image as u32 + image`
+    Image = 3,
+}
+
+/// A glyph or shape, read per instance by `sdf_vert.glsl`. Positions are in physical pixels.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Quad {
+    /// The top left corner and size
+    rect: [f32; 4],
+    /// The glyph's cell in the atlas in texels, or the shape's parameters, see `sdf_frag.glsl`
+    shape: [f32; 4],
+    fill: [f32; 4],
+    outline: [f32; 4],
+    glow: [f32; 4],
+    /// The kind, outline width, glow width and the shape's radius
+    parameters: [f32; 4],
+}
+
+/// An image the layer can draw, sampled in the `SHADER_READ_ONLY_OPTIMAL` layout
+#[derive(Clone, Debug)]
+struct Image {
+    name: String,
+    view: vk::ImageView,
+    /// Whether the image holds sRGB encoded colour in a UNORM format, so it is decoded when sampled
+    encoded: bool,
+}
+
+impl Quad {
+    fn get_binding_description() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(size_of::<Self>() as u32)
+            .input_rate(vk::VertexInputRate::INSTANCE)
+            .build()
+    }
+
+    fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescription; 6] {
+        let attribute = |location: u32, offset: usize| {
+            vk::VertexInputAttributeDescription::builder()
+                .binding(0)
+                .location(location)
+                .format(vk::Format::R32G32B32A32_SFLOAT)
+                .offset(offset as u32)
+                .build()
+        };
+        [
+            attribute(0, offset_of!(Self, rect)),
+            attribute(1, offset_of!(Self, shape)),
+            attribute(2, offset_of!(Self, fill)),
+            attribute(3, offset_of!(Self, outline)),
+            attribute(4, offset_of!(Self, glow)),
+            attribute(5, offset_of!(Self, parameters)),
+        ]
+    }
+}
+
+/// Inputs to `sdf_vert.glsl` and `sdf_frag.glsl`, laid out to match their `Panel` push constants
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Panel {
+    viewport: [f32; 2],
+    spread: f32,
+    encode_srgb: u32,
+    /// A bit for each image that is `Image::encoded`
+    decode_images: u32,
+}
+
+impl Panel {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+}
+
+/// A layer of text and vector shapes drawn over the scene in screen space, crisp at any size. Text samples the
+/// signed distance fields of an `Atlas`'s glyphs and shapes are measured from their distance functions, both
+/// anti-aliased over a pixel on screen whatever their scale, with an outline and a glow around their edges.
+/// Graphics that `Tessellate` are drawn as triangles instead, anti-aliased only by multisampling. Items are placed
+/// in logical pixels, so they are as large on high DPI displays as on others. Each frame the items' quads and
+/// vertices are written to host visible buffers for the swapchain image and drawn in the render pass after the
+/// scene, so post processing applies to them, with an instanced draw of quads or a draw of triangles for each run
+/// of items of the same kind so they stay in the order they were added.
+pub struct Layer {
+    items: Vec<Item>,
+    atlas: Atlas,
+    /// The atlas's texture, in a UNORM format so its distances are filtered linearly
+    texture: (vk::Image, vk::DeviceMemory, vk::ImageView),
+    images: [Option<Image>; MAX_IMAGES],
+    sampler: vk::Sampler,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+    layout: vk::PipelineLayout,
+    /// Created when first recorded after the swapchain has been (re)created, with the frame's render pass
+    pipeline: Option<vk::Pipeline>,
+    vector_pipeline: Option<vk::Pipeline>,
+    /// A buffer of quads and one of vertices for each swapchain image, and the batches written to them
+    buffers: Vec<(vk::Buffer, vk::DeviceMemory)>,
+    vertex_buffers: Vec<(vk::Buffer, vk::DeviceMemory)>,
+    batches: Vec<Vec<Batch>>,
+    /// The triangles of each tessellated item by its index, at the current scale factor
+    meshes: HashMap<usize, Vec<Vertex>>,
+    scale_factor: f32,
+}
+
+impl Layer {
+    /// The layer drawing text with the atlas, which has been uploaded to `texture`. The layer owns the texture.
+    pub fn new(
+        device: &ash::Device,
+        atlas: Atlas,
+        texture: (vk::Image, vk::DeviceMemory, vk::ImageView),
+    ) -> Self {
+        let sampler = unsafe {
+            device
+                .create_sampler(
+                    &vk::SamplerCreateInfo::builder()
+                        .mag_filter(vk::Filter::LINEAR)
+                        .min_filter(vk::Filter::LINEAR)
+                        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+                    None,
+                )
+                .expect("SDF sampler")
+        };
+
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_count(1)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build(),
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(1)
+                .descriptor_count(MAX_IMAGES as u32)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build(),
+        ];
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+                    None,
+                )
+                .expect("SDF descriptor set layout")
+        };
+        let pool_sizes = [vk::DescriptorPoolSize::builder()
+            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1 + MAX_IMAGES as u32)
+            .build()];
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::builder()
+                        .pool_sizes(&pool_sizes)
+                        .max_sets(1),
+                    None,
+                )
+                .expect("SDF descriptor pool")
+        };
+        let set_layouts = [descriptor_set_layout];
+        let descriptor_set = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::builder()
+                        .descriptor_pool(descriptor_pool)
+                        .set_layouts(&set_layouts),
+                )
+                .expect("SDF descriptor set")[0]
+        };
+
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+            .offset(0)
+            .size(size_of::<Panel>() as u32)
+            .build()];
+        let layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::builder()
+                        .set_layouts(&set_layouts)
+                        .push_constant_ranges(&push_constant_ranges),
+                    None,
+                )
+                .expect("SDF pipeline layout")
+        };
+
+        let layer = Self {
+            items: Vec::new(),
+            atlas,
+            texture,
+            images: Default::default(),
+            sampler,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            layout,
+            pipeline: None,
+            vector_pipeline: None,
+            buffers: Vec::new(),
+            vertex_buffers: Vec::new(),
+            batches: Vec::new(),
+            meshes: HashMap::new(),
+            scale_factor: 1.0,
+        };
+        layer.write_descriptor(device);
+        layer
+    }
+
+    fn write_descriptor(&self, device: &ash::Device) {
+        let image_info = |view: vk::ImageView| {
+            vk::DescriptorImageInfo::builder()
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .image_view(view)
+                .sampler(self.sampler)
+                .build()
+        };
+        let atlas_info = [image_info(self.texture.2)];
+        // Empty slots are never sampled, but must still hold an image
+        let images_info = self
+            .images
+            .clone()
+            .map(|image| image_info(image.map_or(self.texture.2, |image| image.view)));
+        let writes = [
+            vk::WriteDescriptorSet::builder()
+                .dst_set(self.descriptor_set)
+                .dst_binding(0)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&atlas_info)
+                .build(),
+            vk::WriteDescriptorSet::builder()
+                .dst_set(self.descriptor_set)
+                .dst_binding(1)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&images_info)
+                .build(),
+        ];
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+    }
+
+    /// Draws text with another atlas, destroying the old atlas's texture. The device must be idle.
+    pub fn set_atlas(
+        &mut self,
+        device: &ash::Device,
+        atlas: Atlas,
+        texture: (vk::Image, vk::DeviceMemory, vk::ImageView),
+    ) {
+        destroy_texture(device, self.texture);
+        self.atlas = atlas;
+        self.texture = texture;
+        self.write_descriptor(device);
+    }
+
+    /// Lets items draw the image, which is sampled in the `SHADER_READ_ONLY_OPTIMAL` layout, returning its index
+    /// for `Item::Image`. `encoded` is whether it holds sRGB encoded colour in a UNORM format, as the renderer's
+    /// own targets do with UNORM swapchains, so it's decoded when sampled. The view must stay valid while the
+    /// layer can draw the image. The device must be idle.
+    pub fn add_image(
+        &mut self,
+        device: &ash::Device,
+        name: &str,
+        view: vk::ImageView,
+        encoded: bool,
+    ) -> Result<usize, String> {
+        let index = self
+            .images
+            .iter()
+            .position(Option::is_none)
+            .ok_or_else(|| {
+                format!(
+                    "The layer can draw at most {} images, {} is one too many",
+                    MAX_IMAGES, name
+                )
+            })?;
+        self.set_image(device, index, name, view, encoded);
+        Ok(index)
+    }
+
+    /// Replaces the image at the index, which items drawing it then draw instead, such as after the image has
+    /// been made again. The device must be idle.
+    pub fn set_image(
+        &mut self,
+        device: &ash::Device,
+        index: usize,
+        name: &str,
+        view: vk::ImageView,
+        encoded: bool,
+    ) {
+        self.images[index] = Some(Image {
+            name: String::from(name),
+            view,
+            encoded,
+        });
+        self.write_descriptor(device);
+    }
+
+    /// Stops drawing the image, removing the items that draw it. The device must be idle.
+    pub fn remove_image(&mut self, device: &ash::Device, index: usize) {
+        self.images[index] = None;
+        self.items
+            .retain(|item| !matches!(item, Item::Image { image, .. } if *image == index));
+        // The items after those removed have moved
+        self.meshes.clear();
+        self.write_descriptor(device);
+    }
+
+    /// Adds an item drawn over those added before it, returning its index
+    pub fn add(&mut self, item: Item) -> usize {
+        self.items.push(item);
+        self.items.len() - 1
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.meshes.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn set_scale_factor(&mut self, scale_factor: f32) {
+        if scale_factor != self.scale_factor {
+            self.meshes.clear();
+        }
+        self.scale_factor = scale_factor;
+    }
+
+    /// The quads and vertices drawing the items, in physical pixels, and the batches drawing them in order
+    fn geometry(&mut self) -> (Vec<Quad>, Vec<Vertex>, Vec<Batch>) {
+        let scale = self.scale_factor;
+        let (mut quads, mut vertices, mut batches) = (Vec::new(), Vec::new(), Vec::new());
+        for (index, item) in self.items.iter().enumerate() {
+            let first = quads.len();
+            match item {
+                Item::Text(text) => self.atlas.text_quads(text, scale, &mut quads),
+                Item::Shape(shape, style) => quads.push(shape.quad(style, scale)),
+                Item::Image {
+                    image,
+                    position,
+                    size,
+                    radius,
+                    style,
+                } => {
+                    if !matches!(self.images.get(*image), Some(Some(_))) {
+                        continue;
+                    }
+                    let rectangle = Shape::Rectangle {
+                        position: *position,
+                        size: *size,
+                        radius: *radius,
+                    };
+                    let mut quad = rectangle.quad(style, scale);
+                    quad.parameters[0] = (Kind::Image as u32 + *image as u32) as f32;
+                    quads.push(quad);
+                }
+                Item::Vector {
+                    graphic,
+                    position,
+                    size,
+                } => {
+                    let mesh = self.meshes.entry(index).or_insert_with(|| {
+                        let rect = [position[0], position[1], size[0], size[1]];
+                        graphic.tessellate(rect.map(|value| value * scale))
+                    });
+                    // Whole graphics are left out, rather than leaving some of their triangles
+                    if vertices.len() + mesh.len() <= MAX_VERTICES {
+                        let first = vertices.len() as u32;
+                        vertices.extend_from_slice(mesh);
+                        push_batch(&mut batches, Batch::Triangles(first..vertices.len() as u32));
+                    }
+                    continue;
+                }
+            }
+            quads.truncate(MAX_QUADS);
+            if quads.len() > first {
+                push_batch(&mut batches, Batch::Quads(first as u32..quads.len() as u32));
+            }
+        }
+        (quads, vertices, batches)
+    }
+
+    /// The pipeline drawing with `<shader>_vert.spv` and `<shader>_frag.spv`
+    fn create_pipeline(
+        &self,
+        device: &ash::Device,
+        target: &pipeline::Target,
+        shader: &str,
+        vertex_input_info: &vk::PipelineVertexInputStateCreateInfo,
+        topology: vk::PrimitiveTopology,
+    ) -> vk::Pipeline {
+        let read = |name: String| {
+            let path = Path::new(env!("OUT_DIR")).join(name);
+            pipeline::create_shader_module(device, &util::read_shader_code(&path))
+        };
+        let vert_shader_module = read(format!("{}_vert.spv", shader));
+        let frag_shader_module = read(format!("{}_frag.spv", shader));
+        let main_fn_name = CString::new("main").unwrap();
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vert_shader_module)
+                .name(main_fn_name.as_c_str())
+                .build(),
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(frag_shader_module)
+                .name(main_fn_name.as_c_str())
+                .build(),
+        ];
+
+        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(topology)
+            .primitive_restart_enable(false);
+
+        let viewports = [vk::Viewport::builder()
+            .width(target.extent.width as f32)
+            .height(target.extent.height as f32)
+            .max_depth(1.0)
+            .build()];
+        let scissors = [vk::Rect2D::builder().extent(target.extent).build()];
+        let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
+            .viewports(&viewports)
+            .scissors(&scissors);
+        let rasterizer = vk::PipelineRasterizationStateCreateInfo::builder()
+            .polygon_mode(vk::PolygonMode::FILL)
+            .line_width(1.0)
+            .cull_mode(vk::CullModeFlags::NONE);
+        let multisampling =
+            vk::PipelineMultisampleStateCreateInfo::builder().rasterization_samples(target.samples);
+        let color_blend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .color_write_mask(vk::ColorComponentFlags::all())
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ONE)
+            .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
+            .alpha_blend_op(vk::BlendOp::ADD)
+            .build()];
+        let global_blend =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&color_blend_attachments);
+        // Drawn over everything in the scene
+        let depth_stencil_attachment = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .vertex_input_state(vertex_input_info)
+            .input_assembly_state(&input_assembly_info)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterizer)
+            .multisample_state(&multisampling)
+            .color_blend_state(&global_blend)
+            .depth_stencil_state(&depth_stencil_attachment)
+            .layout(self.layout)
+            .render_pass(target.render_pass);
+        let pipelines = unsafe {
+            device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    &[pipeline_info.build()],
+                    None,
+                )
+                .expect("2D layer pipeline")
+        };
+        unsafe {
+            device.destroy_shader_module(vert_shader_module, None);
+            device.destroy_shader_module(frag_shader_module, None);
+        }
+        pipelines[0]
+    }
+
+    fn create_pipelines(&mut self, device: &ash::Device, target: &pipeline::Target) {
+        if self.pipeline.is_none() {
+            let binding_descriptions = [Quad::get_binding_description()];
+            let attribute_descriptions = Quad::get_attribute_descriptions();
+            let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
+                .vertex_binding_descriptions(&binding_descriptions)
+                .vertex_attribute_descriptions(&attribute_descriptions);
+            self.pipeline = Some(self.create_pipeline(
+                device,
+                target,
+                "sdf",
+                &vertex_input_info,
+                vk::PrimitiveTopology::TRIANGLE_STRIP,
+            ));
+        }
+        if self.vector_pipeline.is_none() {
+            let binding_descriptions = [Vertex::get_binding_description()];
+            let attribute_descriptions = Vertex::get_attribute_descriptions();
+            let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
+                .vertex_binding_descriptions(&binding_descriptions)
+                .vertex_attribute_descriptions(&attribute_descriptions);
+            self.vector_pipeline = Some(self.create_pipeline(
+                device,
+                target,
+                "vector",
+                &vertex_input_info,
+                vk::PrimitiveTopology::TRIANGLE_LIST,
+            ));
+        }
+    }
+}
+
+/// Adds the batch, or extends the last batch if it draws the same kind of item
+fn push_batch(batches: &mut Vec<Batch>, batch: Batch) {
+    match (batches.last_mut(), batch) {
+        (Some(Batch::Quads(last)), Batch::Quads(next))
+        | (Some(Batch::Triangles(last)), Batch::Triangles(next))
+            if last.end == next.start =>
+        {
+            last.end = next.end
+        }
+        (_, batch) => batches.push(batch),
+    }
+}
+
+/// Copies the items into the host visible memory
+fn write<T: Copy>(device: &ash::Device, memory: vk::DeviceMemory, items: &[T]) {
+    if items.is_empty() {
+        return;
+    }
+    let size = std::mem::size_of_val(items) as vk::DeviceSize;
+    unsafe {
+        let data = device
+            .map_memory(memory, 0, size, vk::MemoryMapFlags::empty())
+            .expect("Mapping 2D layer memory") as *mut T;
+        data.copy_from_nonoverlapping(items.as_ptr(), items.len());
+        device.unmap_memory(memory);
+    }
+}
+
+fn destroy_texture(
+    device: &ash::Device,
+    (image, memory, view): (vk::Image, vk::DeviceMemory, vk::ImageView),
+) {
+    unsafe {
+        device.destroy_image_view(view, None);
+        device.destroy_image(image, None);
+        device.free_memory(memory, None);
+    }
+}
+
+impl RenderFeature for Layer {
+    fn name(&self) -> &str {
+        "text and shapes"
+    }
+
+    fn init(&mut self, context: &SwapchainContext) {
+        self.set_scale_factor(context.scale_factor);
+    }
+
+    fn resize(&mut self, context: &SwapchainContext) {
+        for pipeline in self
+            .pipeline
+            .take()
+            .into_iter()
+            .chain(self.vector_pipeline.take())
+        {
+            unsafe { context.device.destroy_pipeline(pipeline, None) };
+        }
+        self.set_scale_factor(context.scale_factor);
+    }
+
+    fn prepare(&mut self, context: &SwapchainContext, image_index: usize) {
+        // Created the first time each image draws items
+        while self.buffers.len() <= image_index {
+            let buffer = |size: usize| {
+                HelloTriangleApplication::create_buffer(
+                    context.device,
+                    size as vk::DeviceSize,
+                    vk::BufferUsageFlags::VERTEX_BUFFER,
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                    context.device_memory_properties,
+                )
+            };
+            self.buffers.push(buffer(MAX_QUADS * size_of::<Quad>()));
+            self.vertex_buffers
+                .push(buffer(MAX_VERTICES * size_of::<Vertex>()));
+            self.batches.push(Vec::new());
+        }
+        let (quads, vertices, batches) = self.geometry();
+        write(context.device, self.buffers[image_index].1, &quads);
+        write(
+            context.device,
+            self.vertex_buffers[image_index].1,
+            &vertices,
+        );
+        self.batches[image_index] = batches;
+    }
+
+    fn record(&mut self, context: &hooks::FrameContext) {
+        let drawing = self
+            .batches
+            .get(context.image_index)
+            .into_iter()
+            .any(|batches| !batches.is_empty());
+        if context.stage != hooks::Stage::AfterOpaque || !drawing {
+            return;
+        }
+        let device = context.device;
+        self.create_pipelines(device, &context.target);
+        let extent = context.target.extent;
+        let panel = Panel {
+            viewport: [extent.width as f32, extent.height as f32],
+            spread: self.atlas.spread,
+            encode_srgb: !util::is_srgb_format(context.swapchain_format) as u32,
+            decode_images: self
+                .images
+                .iter()
+                .enumerate()
+                .filter(|(_, image)| image.iter().any(|image| image.encoded))
+                .fold(0, |bits, (index, _)| bits | 1 << index),
+        };
+        let command_buffer = context.command_buffer;
+        unsafe {
+            // Both pipelines share the layout, so the set and constants stay bound between them
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                self.layout,
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                0,
+                panel.as_bytes(),
+            );
+            for batch in self.batches[context.image_index].iter() {
+                let (pipeline, buffer) = match batch {
+                    Batch::Quads(_) => (self.pipeline, self.buffers[context.image_index].0),
+                    Batch::Triangles(_) => (
+                        self.vector_pipeline,
+                        self.vertex_buffers[context.image_index].0,
+                    ),
+                };
+                let pipeline = pipeline.expect("2D layer pipelines created");
+                device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+                device.cmd_bind_vertex_buffers(command_buffer, 0, &[buffer], &[0]);
+                match batch {
+                    Batch::Quads(quads) => {
+                        device.cmd_draw(command_buffer, 4, quads.len() as u32, 0, quads.start)
+                    }
+                    Batch::Triangles(vertices) => {
+                        device.cmd_draw(command_buffer, vertices.len() as u32, 1, vertices.start, 0)
+                    }
+                }
+            }
+
+            // Left as it was found for the features and hooks recorded after this
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                context.pipeline_layout,
+                0,
+                &[context.descriptor_set],
+                &[],
+            );
+        }
+    }
+
+    fn passes(&self) -> Vec<framegraph::Pass> {
+        if self.items.is_empty() {
+            return Vec::new();
+        }
+        let mut reads = vec!["SDF atlas"];
+        reads.extend(
+            self.images
+                .iter()
+                .flatten()
+                .map(|image| image.name.as_str()),
+        );
+        vec![framegraph::Pass::new(
+            self.name(),
+            framegraph::Position::Stage(hooks::Stage::AfterOpaque),
+            &reads,
+            &[framegraph::SWAPCHAIN],
+        )]
+    }
+
+    fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            for pipeline in self
+                .pipeline
+                .take()
+                .into_iter()
+                .chain(self.vector_pipeline.take())
+            {
+                device.destroy_pipeline(pipeline, None);
+            }
+            for (buffer, memory) in self.buffers.drain(..).chain(self.vertex_buffers.drain(..)) {
+                device.destroy_buffer(buffer, None);
+                device.free_memory(memory, None);
+            }
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            device.destroy_sampler(self.sampler, None);
+        }
+        destroy_texture(device, self.texture);
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
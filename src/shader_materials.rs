@@ -0,0 +1,497 @@
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::Arc;
+
+use ash::vk;
+
+use crate::{assets, pipeline, playground, present_thread, spirv, HelloTriangleApplication};
+
+/// The descriptor set shader materials' parameters are declared in. Set 0 is the frame's, shared with every
+/// other pipeline, and set 1 is `pipeline::Program::Vat`'s animation.
+pub const PARAMETER_SET: u32 = 2;
+
+/// A uniform block of parameters and the host visible buffer it is read from
+struct Block {
+    block: spirv::Block,
+    buffer: vk::Buffer,
+    memory: vk::DeviceMemory,
+    /// What has been written to the buffer
+    data: Vec<u8>,
+}
+
+/// A sampler parameter and the texture it samples, or the scene's texture until one is set
+struct Texture {
+    sampler: spirv::Sampler,
+    texture: Option<assets::Texture>,
+}
+
+/// A user's vertex and fragment shaders drawn in place of the built in shading, with the uniform blocks and
+/// samplers they declare in `PARAMETER_SET` found by reflection and exposed as parameters
+pub struct ShaderMaterial {
+    pub name: String,
+    program: pipeline::CustomProgram,
+    set_layout: vk::DescriptorSetLayout,
+    pool: Option<vk::DescriptorPool>,
+    blocks: Vec<Block>,
+    textures: Vec<Texture>,
+}
+
+impl ShaderMaterial {
+    /// Each parameter's name and type, such as `tint vec4` or `pattern sampler2D`
+    pub fn parameters(&self) -> Vec<String> {
+        let uniforms = self.blocks.iter().flat_map(|block| {
+            block
+                .block
+                .members
+                .iter()
+                .map(|member| format!("{} {}", member.name, member.kind.name()))
+        });
+        let samplers = self
+            .textures
+            .iter()
+            .map(|texture| format!("{} sampler2D", texture.sampler.name));
+        uniforms.chain(samplers).collect()
+    }
+}
+
+/// The shader materials that have been loaded. Each one is drawn by `pipeline::Program::Custom` with its index,
+/// which a `material::Material` picks with `shader`. The frame's uniforms, texture, lightmap and toon ramps are
+/// bound to set 0 as for the built in shaders, so shader materials can declare any prefix of them, and meshes'
+/// vertices and push constants are the same as `vert.glsl`'s. Parameters start at zero, and materials that need
+/// different values of the same parameters are loaded once for each.
+#[derive(Default)]
+pub struct Library {
+    materials: Vec<ShaderMaterial>,
+}
+
+impl Library {
+    /// Loads the shaders as a material and returns its index. Each is SPIR-V if its file ends in `.spv` and GLSL
+    /// otherwise, which needs the `runtime-shaders` feature to compile. Materials without a vertex shader have
+    /// their vertices transformed like `vert.glsl`, with the same outputs. `frame_layout` and `animation_layout`
+    /// are the shared pipeline layout's first two sets, and samplers sample `default_texture` until they are set.
+    #[allow(clippy::too_many_arguments)]
+    pub fn load(
+        &mut self,
+        device: &ash::Device,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        name: &str,
+        vertex: Option<&Path>,
+        fragment: &Path,
+        frame_layout: vk::DescriptorSetLayout,
+        animation_layout: vk::DescriptorSetLayout,
+        default_texture: vk::ImageView,
+        sampler: vk::Sampler,
+    ) -> Result<u16, String> {
+        if self.find(name).is_some() {
+            return Err(format!("There's already a shader material called {}", name));
+        }
+        if self.materials.len() > u16::MAX as usize {
+            return Err(String::from("There are too many shader materials"));
+        }
+        let vertex = vertex.map(|path| load_code(path, "vert")).transpose()?;
+        let fragment = load_code(fragment, "frag")?;
+        let mut reflection = spirv::reflect(&fragment)?;
+        if let Some(vertex) = vertex.as_ref() {
+            merge(&mut reflection, spirv::reflect(vertex)?)?;
+        }
+        let blocks: Vec<spirv::Block> = reflection
+            .blocks
+            .into_iter()
+            .filter(|block| block.set == PARAMETER_SET)
+            .collect();
+        let samplers: Vec<spirv::Sampler> = reflection
+            .samplers
+            .into_iter()
+            .filter(|sampler| sampler.set == PARAMETER_SET)
+            .collect();
+
+        let stages = vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT;
+        let binding = |binding: u32, descriptor_type: vk::DescriptorType| {
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding)
+                .descriptor_count(1)
+                .descriptor_type(descriptor_type)
+                .stage_flags(stages)
+                .build()
+        };
+        let bindings: Vec<vk::DescriptorSetLayoutBinding> = blocks
+            .iter()
+            .map(|block| binding(block.binding, vk::DescriptorType::UNIFORM_BUFFER))
+            .chain(samplers.iter().map(|sampler| {
+                binding(sampler.binding, vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            }))
+            .collect();
+        let set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+                    None,
+                )
+                .expect("Shader material descriptor set layout")
+        };
+        let layout = pipeline::create_layout(device, &[frame_layout, animation_layout, set_layout]);
+
+        let blocks: Vec<Block> = blocks
+            .into_iter()
+            .map(|block| {
+                // Uniform buffers can't be empty
+                let size = block.size.max(16) as usize;
+                let (buffer, memory) = HelloTriangleApplication::create_buffer(
+                    device,
+                    size as vk::DeviceSize,
+                    vk::BufferUsageFlags::UNIFORM_BUFFER,
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                    device_memory_properties,
+                );
+                let block = Block {
+                    block,
+                    buffer,
+                    memory,
+                    data: vec![0; size],
+                };
+                write_block(device, &block);
+                block
+            })
+            .collect();
+        let textures: Vec<Texture> = samplers
+            .into_iter()
+            .map(|sampler| Texture {
+                sampler,
+                texture: None,
+            })
+            .collect();
+
+        let (pool, parameters) = match bindings.is_empty() {
+            true => (None, None),
+            false => {
+                let (pool, set) = allocate(device, set_layout, blocks.len(), textures.len());
+                (Some(pool), Some(set))
+            }
+        };
+        let material = ShaderMaterial {
+            name: String::from(name),
+            program: pipeline::CustomProgram {
+                vertex: vertex.map(Arc::new),
+                fragment: Arc::new(fragment),
+                layout,
+                parameters,
+            },
+            set_layout,
+            pool,
+            blocks,
+            textures,
+        };
+        if let Some(set) = parameters {
+            write_descriptors(device, &material, set, default_texture, sampler);
+        }
+        self.materials.push(material);
+        Ok((self.materials.len() - 1) as u16)
+    }
+
+    /// The index of the material with the name
+    pub fn find(&self, name: &str) -> Option<u16> {
+        self.materials
+            .iter()
+            .position(|material| material.name == name)
+            .map(|index| index as u16)
+    }
+
+    pub fn get(&self, index: u16) -> Option<&ShaderMaterial> {
+        self.materials.get(index as usize)
+    }
+
+    /// The materials as programs for `pipeline::Variants::set_custom_programs`, by index
+    pub fn programs(&self) -> Vec<pipeline::CustomProgram> {
+        self.materials
+            .iter()
+            .map(|material| material.program.clone())
+            .collect()
+    }
+
+    /// Sets a number, vector or matrix parameter of the material to the values, given column by column. A
+    /// parameter can be named by itself or after its block's name and a dot. Waits for the device to finish
+    /// with the parameters first, so setting parameters every frame stalls it.
+    pub fn set_parameter(
+        &mut self,
+        device: &ash::Device,
+        material: u16,
+        name: &str,
+        values: &[f32],
+    ) -> Result<(), String> {
+        let material = self
+            .materials
+            .get_mut(material as usize)
+            .ok_or_else(|| format!("There's no shader material {}", material))?;
+        let material_name = &material.name;
+        let (block, member) = material
+            .blocks
+            .iter_mut()
+            .find_map(|block| {
+                let member = block.block.members.iter().find(|member| {
+                    member.name == name || format!("{}.{}", block.block.name, member.name) == name
+                })?;
+                let member = member.clone();
+                Some((block, member))
+            })
+            .ok_or_else(|| format!("{} has no parameter {}", material_name, name))?;
+        if values.len() != member.kind.count() {
+            return Err(format!(
+                "{} is a {}, which takes {} values",
+                name,
+                member.kind.name(),
+                member.kind.count()
+            ));
+        }
+        for (offset, &value) in member.kind.offsets().zip(values) {
+            let bytes = match member.kind.scalar {
+                spirv::Scalar::Float => value.to_le_bytes(),
+                spirv::Scalar::Int => (value.round() as i32).to_le_bytes(),
+                spirv::Scalar::Uint => (value.max(0.0).round() as u32).to_le_bytes(),
+                spirv::Scalar::Bool => ((value != 0.0) as u32).to_le_bytes(),
+            };
+            let at = (member.offset + offset) as usize;
+            block.data[at..at + 4].copy_from_slice(&bytes);
+        }
+        // Frames in flight may still be reading the parameters
+        present_thread::device_wait_idle(device)
+            .map_err(|e| format!("Waiting for the device to set {}: {}", name, e))?;
+        write_block(device, block);
+        Ok(())
+    }
+
+    /// Makes a sampler parameter of the material sample the texture, which is kept loaded for as long as the
+    /// material samples it. Waits for the device to finish with the parameters first.
+    pub fn set_texture(
+        &mut self,
+        device: &ash::Device,
+        material: u16,
+        name: &str,
+        texture: assets::Texture,
+        sampler: vk::Sampler,
+    ) -> Result<(), String> {
+        let material = self
+            .materials
+            .get_mut(material as usize)
+            .ok_or_else(|| format!("There's no shader material {}", material))?;
+        let (set, material_name) = (material.program.parameters, &material.name);
+        let parameter = material
+            .textures
+            .iter_mut()
+            .find(|texture| texture.sampler.name == name)
+            .ok_or_else(|| format!("{} has no sampler {}", material_name, name))?;
+        let set = set.expect("Materials with samplers have parameters");
+        present_thread::device_wait_idle(device)
+            .map_err(|e| format!("Waiting for the device to set {}: {}", name, e))?;
+        let image_info = [vk::DescriptorImageInfo::builder()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(texture.view())
+            .sampler(sampler)
+            .build()];
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(set)
+            .dst_binding(parameter.sampler.binding)
+            .dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_info)
+            .build();
+        unsafe { device.update_descriptor_sets(&[write], &[]) };
+        parameter.texture = Some(texture);
+        Ok(())
+    }
+
+    /// Whether the material has a sampler parameter with the name
+    pub fn has_sampler(&self, material: u16, name: &str) -> bool {
+        self.get(material)
+            .into_iter()
+            .flat_map(|material| material.textures.iter())
+            .any(|texture| texture.sampler.name == name)
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            for material in self.materials.drain(..) {
+                for block in material.blocks {
+                    device.destroy_buffer(block.buffer, None);
+                    device.free_memory(block.memory, None);
+                }
+                if let Some(pool) = material.pool {
+                    device.destroy_descriptor_pool(pool, None);
+                }
+                device.destroy_pipeline_layout(material.program.layout, None);
+                device.destroy_descriptor_set_layout(material.set_layout, None);
+            }
+        }
+    }
+}
+
+/// Reads SPIR-V from a `.spv` file, or compiles the GLSL in any other file for the stage
+fn load_code(path: &Path, stage: &str) -> Result<Vec<u32>, String> {
+    let spirv = path
+        .extension()
+        .into_iter()
+        .any(|extension| extension.eq_ignore_ascii_case("spv"));
+    if spirv {
+        let bytes = fs::read(path).map_err(|e| format!("Reading {}: {}", path.display(), e))?;
+        ash::util::read_spv(&mut Cursor::new(bytes))
+            .map_err(|e| format!("Reading {}: {}", path.display(), e))
+    } else {
+        let source =
+            fs::read_to_string(path).map_err(|e| format!("Reading {}: {}", path.display(), e))?;
+        playground::glslc(source, stage)
+            .map_err(|e| format!("Compiling {}:\n{}", path.display(), e))
+    }
+}
+
+/// Adds the vertex shader's parameters to the fragment shader's. Parameters both declare must match.
+fn merge(reflection: &mut spirv::Reflection, other: spirv::Reflection) -> Result<(), String> {
+    for block in other.blocks {
+        match reflection
+            .blocks
+            .iter()
+            .find(|existing| (existing.set, existing.binding) == (block.set, block.binding))
+        {
+            Some(existing) if *existing != block => {
+                return Err(format!(
+                    "The shaders declare different blocks at set {} binding {}",
+                    block.set, block.binding
+                ))
+            }
+            Some(_) => {}
+            None => reflection.blocks.push(block),
+        }
+    }
+    for sampler in other.samplers {
+        if !reflection.samplers.contains(&sampler) {
+            reflection.samplers.push(sampler);
+        }
+    }
+    let mut bindings: Vec<(u32, u32)> = reflection
+        .blocks
+        .iter()
+        .map(|block| (block.set, block.binding))
+        .chain(
+            reflection
+                .samplers
+                .iter()
+                .map(|sampler| (sampler.set, sampler.binding)),
+        )
+        .collect();
+    let count = bindings.len();
+    bindings.sort_unstable();
+    bindings.dedup();
+    if bindings.len() != count {
+        return Err(String::from(
+            "The shaders declare a block and a sampler at the same binding",
+        ));
+    }
+    Ok(())
+}
+
+fn allocate(
+    device: &ash::Device,
+    set_layout: vk::DescriptorSetLayout,
+    blocks: usize,
+    samplers: usize,
+) -> (vk::DescriptorPool, vk::DescriptorSet) {
+    let pool_sizes: Vec<vk::DescriptorPoolSize> = [
+        (vk::DescriptorType::UNIFORM_BUFFER, blocks),
+        (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, samplers),
+    ]
+    .iter()
+    .filter(|(_, count)| *count > 0)
+    .map(|&(ty, count)| {
+        vk::DescriptorPoolSize::builder()
+            .ty(ty)
+            .descriptor_count(count as u32)
+            .build()
+    })
+    .collect();
+    let pool = unsafe {
+        device
+            .create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfo::builder()
+                    .pool_sizes(&pool_sizes)
+                    .max_sets(1),
+                None,
+            )
+            .expect("Shader material descriptor pool")
+    };
+    let set_layouts = [set_layout];
+    let set = unsafe {
+        device
+            .allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::builder()
+                    .descriptor_pool(pool)
+                    .set_layouts(&set_layouts),
+            )
+            .expect("Shader material descriptor set")[0]
+    };
+    (pool, set)
+}
+
+/// Points the material's descriptor set at its blocks' buffers, and its samplers at the default texture
+fn write_descriptors(
+    device: &ash::Device,
+    material: &ShaderMaterial,
+    set: vk::DescriptorSet,
+    default_texture: vk::ImageView,
+    sampler: vk::Sampler,
+) {
+    let buffer_infos: Vec<[vk::DescriptorBufferInfo; 1]> = material
+        .blocks
+        .iter()
+        .map(|block| {
+            [vk::DescriptorBufferInfo::builder()
+                .buffer(block.buffer)
+                .offset(0)
+                .range(vk::WHOLE_SIZE)
+                .build()]
+        })
+        .collect();
+    let image_info = [vk::DescriptorImageInfo::builder()
+        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .image_view(default_texture)
+        .sampler(sampler)
+        .build()];
+    let writes: Vec<vk::WriteDescriptorSet> = material
+        .blocks
+        .iter()
+        .zip(buffer_infos.iter())
+        .map(|(block, buffer_info)| {
+            vk::WriteDescriptorSet::builder()
+                .dst_set(set)
+                .dst_binding(block.block.binding)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .buffer_info(buffer_info)
+                .build()
+        })
+        .chain(material.textures.iter().map(|texture| {
+            vk::WriteDescriptorSet::builder()
+                .dst_set(set)
+                .dst_binding(texture.sampler.binding)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&image_info)
+                .build()
+        }))
+        .collect();
+    unsafe { device.update_descriptor_sets(&writes, &[]) };
+}
+
+/// Copies what has been written to the block to its buffer
+fn write_block(device: &ash::Device, block: &Block) {
+    unsafe {
+        let data = device
+            .map_memory(
+                block.memory,
+                0,
+                block.data.len() as vk::DeviceSize,
+                vk::MemoryMapFlags::empty(),
+            )
+            .expect("Mapping shader material parameters") as *mut u8;
+        data.copy_from_nonoverlapping(block.data.as_ptr(), block.data.len());
+        device.unmap_memory(block.memory);
+    }
+}
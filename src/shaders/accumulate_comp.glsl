@@ -0,0 +1,42 @@
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+// The rendered frame, replaced with the average
+layout(binding = 0, rgba16f) uniform image2D colorImage;
+// The average of the frames so far, in linear colour
+layout(binding = 1, rgba32f) uniform image2D accumulatedImage;
+
+// Matches `accumulation::Blend`
+layout(push_constant) uniform Blend {
+    // The frame's share of the average: 1 for the first frame, replacing what was there, 1/n for the nth, and 0
+    // once the average has converged
+    float weight;
+    // Set when the frame's colour is sRGB encoded
+    uint encoded;
+} blend;
+
+vec3 decodeSrgb(vec3 color) {
+    return mix(color / 12.92, pow((color + 0.055) / 1.055, vec3(2.4)), greaterThan(color, vec3(0.04045)));
+}
+
+vec3 encodeSrgb(vec3 color) {
+    return mix(color * 12.92, 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055, greaterThan(color, vec3(0.0031308)));
+}
+
+void main() {
+    ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+    if (any(greaterThanEqual(pixel, imageSize(colorImage)))) {
+        return;
+    }
+
+    vec4 color = imageLoad(colorImage, pixel);
+    vec4 frame = vec4(blend.encoded != 0u ? decodeSrgb(color.rgb) : color.rgb, color.a);
+    vec4 average = frame;
+    // The accumulated image is undefined before the first frame, which mustn't be mixed with it
+    if (blend.weight < 1.0) {
+        average = mix(imageLoad(accumulatedImage, pixel), frame, blend.weight);
+    }
+    imageStore(accumulatedImage, pixel, average);
+    imageStore(colorImage, pixel, vec4(blend.encoded != 0u ? encodeSrgb(average.rgb) : average.rgb, average.a));
+}
@@ -0,0 +1,31 @@
+#version 450
+
+vec3 encodeSrgb(vec3 linear) {
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+}
+
+// Widgets drawn into the layers of the UI atlas, see `atlas.rs`
+layout(binding = 0) uniform sampler2DArray atlas;
+
+layout(push_constant) uniform Placement {
+    // The widget's top left corner and size on screen in pixels, matching the viewport it is drawn with
+    vec2 offset;
+    vec2 size;
+    // The widget's slot in its layer, as the corner and size in texture coordinates
+    vec4 slot;
+    uint layer;
+    // Set when rendering to a UNORM swapchain, which doesn't encode linear colour to sRGB on write
+    uint encodeSrgb;
+} placement;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    vec2 uv = placement.slot.xy + (gl_FragCoord.xy - placement.offset) / placement.size * placement.slot.zw;
+    outColor = texture(atlas, vec3(uv, float(placement.layer)));
+    if (placement.encodeSrgb != 0u) {
+        outColor.rgb = encodeSrgb(outColor.rgb);
+    }
+}
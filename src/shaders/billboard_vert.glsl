@@ -0,0 +1,44 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+    // Captured audio levels from 0 to 1, lowest frequencies first
+    vec4 audioBands[4];
+} ubo;
+
+layout(location = 0) in vec3 inCenter;
+layout(location = 1) in vec2 inSize;
+layout(location = 2) in vec4 inAxis;
+
+layout(location = 0) out vec3 fragColor;
+layout(location = 1) out vec2 fragTexCoord;
+
+// Two triangles covering a unit quad centered on the origin
+const vec2 CORNERS[6] = vec2[](
+    vec2(-0.5, -0.5), vec2(0.5, -0.5), vec2(0.5, 0.5),
+    vec2(0.5, 0.5), vec2(-0.5, 0.5), vec2(-0.5, -0.5)
+);
+
+void main() {
+    vec2 corner = CORNERS[gl_VertexIndex];
+
+    // The rows of the view matrix's rotation are the camera's axes in world space
+    vec3 right = vec3(ubo.view[0][0], ubo.view[1][0], ubo.view[2][0]);
+    vec3 up = vec3(ubo.view[0][1], ubo.view[1][1], ubo.view[2][1]);
+
+    if (inAxis.w > 0.5) {
+        // Axis locked billboards keep the given axis as up and only turn around it towards the camera
+        vec3 cameraPosition = -transpose(mat3(ubo.view)) * ubo.view[3].xyz;
+        up = normalize(inAxis.xyz);
+        right = normalize(cross(up, cameraPosition - inCenter));
+    }
+
+    // Pulse with the bass
+    vec2 size = inSize * (1.0 + 0.5 * ubo.audioBands[0].x);
+    vec3 position = inCenter + right * corner.x * size.x + up * corner.y * size.y;
+    gl_Position = ubo.proj * ubo.view * vec4(position, 1.0);
+    fragColor = vec3(1.0, 1.0, 1.0);
+    fragTexCoord = corner + vec2(0.5, 0.5);
+}
@@ -0,0 +1,81 @@
+#version 450
+
+// Set when rendering to a UNORM swapchain, which doesn't encode linear colour to sRGB on write
+layout(constant_id = 4) const bool ENCODE_SRGB = false;
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+    vec4 audioBands[4];
+    // Matches `clipping::Uniforms`. Points where dot(xyz, position) < w are cut away, in the scene's space.
+    vec4 clipPlanes[4];
+    vec4 clipCapColor;
+    // x is how many planes are used
+    uvec4 clipFlags;
+    // Matches `sky::Uniforms`. xyz is the direction towards the light, and the colours are in rgb.
+    vec4 lightDirection;
+    vec4 lightColor;
+    vec4 ambientColor;
+} ubo;
+
+// The back face being capped, before the scene's model matrix
+layout(location = 5) in vec3 fragScenePosition;
+
+layout(location = 0) out vec4 outColor;
+
+vec3 encodeSrgb(vec3 linear) {
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+}
+
+// Back faces only show where the front of the mesh has been cut away. Each one is moved along the ray from the
+// camera onto the plane the ray crossed to reach it, so the cap fills the cut with the depth of the plane.
+void main() {
+    uint count = min(ubo.clipFlags.x, 4u);
+    mat4 sceneToView = ubo.view * ubo.model;
+    mat4 viewToScene = inverse(sceneToView);
+    // Away from the camera, in the scene's space. Orthographic projections look along parallel rays.
+    vec3 direction;
+    if (ubo.proj[3][3] == 1.0) {
+        direction = -viewToScene[2].xyz;
+    } else {
+        direction = fragScenePosition - viewToScene[3].xyz;
+    }
+
+    // Of the planes facing the camera, the one crossed last before the back face
+    int cap = -1;
+    float along = -1e30;
+    for (uint i = 0u; i < count; i++) {
+        vec4 plane = ubo.clipPlanes[i];
+        float facing = dot(plane.xyz, direction);
+        if (facing > 0.0) {
+            float crossing = (plane.w - dot(plane.xyz, fragScenePosition)) / facing;
+            if (crossing > along) {
+                along = crossing;
+                cap = int(i);
+            }
+        }
+    }
+    if (cap < 0) {
+        discard;
+    }
+    vec3 position = fragScenePosition + along * direction;
+    for (uint i = 0u; i < count; i++) {
+        if (int(i) != cap && dot(ubo.clipPlanes[i].xyz, position) < ubo.clipPlanes[i].w) {
+            discard;
+        }
+    }
+
+    vec4 clip = ubo.proj * sceneToView * vec4(position, 1.0);
+    gl_FragDepth = clip.z / clip.w;
+
+    // Caps face the side that was cut away
+    vec3 normal = normalize(transpose(inverse(mat3(ubo.model))) * -ubo.clipPlanes[cap].xyz);
+    float diffuse = max(dot(normal, ubo.lightDirection.xyz), 0.0);
+    outColor = vec4(ubo.clipCapColor.rgb * (ubo.ambientColor.rgb + ubo.lightColor.rgb * diffuse), 1.0);
+    if (ENCODE_SRGB) {
+        outColor.rgb = encodeSrgb(outColor.rgb);
+    }
+}
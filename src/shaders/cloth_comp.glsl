@@ -0,0 +1,89 @@
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+struct Particle {
+    // w is the particle's inverse mass, zero for pinned particles
+    vec4 position;
+    // Where the particle was a step ago, which gives its velocity
+    vec4 previous;
+};
+
+// Each step reads one state buffer and writes the other
+layout(std430, binding = 0) readonly buffer Source {
+    Particle source[];
+};
+layout(std430, binding = 1) writeonly buffer Destination {
+    Particle destination[];
+};
+
+layout(push_constant) uniform Step {
+    uint columns;
+    uint rows;
+    // Distance between neighbouring particles at rest
+    float spacing;
+    float deltaTime;
+    float time;
+} step;
+
+const vec3 GRAVITY = vec3(0.0, 0.0, -2.0);
+const float DAMPING = 0.01;
+// Springs to the particle's direct, diagonal and second neighbours. They keep the cloth from stretching,
+// shearing and folding sharply.
+const float STRUCTURAL = 500.0;
+const float SHEAR = 200.0;
+const float BEND = 50.0;
+
+ivec2 size() {
+    return ivec2(step.columns, step.rows);
+}
+
+vec3 positionAt(ivec2 grid) {
+    return source[grid.y * int(step.columns) + grid.x].position.xyz;
+}
+
+// The force pulling `position` towards its neighbour at `offset`, if the neighbour exists
+vec3 spring(vec3 position, ivec2 grid, ivec2 offset, float stiffness) {
+    ivec2 neighbour = grid + offset;
+    if (any(lessThan(neighbour, ivec2(0))) || any(greaterThanEqual(neighbour, size()))) {
+        return vec3(0.0);
+    }
+    vec3 towards = positionAt(neighbour) - position;
+    float distance = length(towards);
+    if (distance < 1e-6) {
+        return vec3(0.0);
+    }
+    float rest = step.spacing * length(vec2(offset));
+    return stiffness * (distance - rest) * towards / distance;
+}
+
+void main() {
+    ivec2 grid = ivec2(gl_GlobalInvocationID.xy);
+    if (any(greaterThanEqual(grid, size()))) {
+        return;
+    }
+    uint index = grid.y * step.columns + grid.x;
+    Particle particle = source[index];
+    float inverseMass = particle.position.w;
+    if (inverseMass == 0.0) {
+        destination[index] = particle;
+        return;
+    }
+
+    vec3 position = particle.position.xyz;
+    // Gusts that come and go, stronger across some parts of the cloth than others
+    float gust = 0.6 + 0.4 * sin(step.time * 1.3 + float(grid.x) * 0.25);
+    vec3 force = GRAVITY / inverseMass + vec3(0.0, 0.01 * gust, 0.0);
+    const ivec2 STRUCTURAL_OFFSETS[4] = ivec2[](ivec2(1, 0), ivec2(-1, 0), ivec2(0, 1), ivec2(0, -1));
+    const ivec2 SHEAR_OFFSETS[4] = ivec2[](ivec2(1, 1), ivec2(-1, 1), ivec2(1, -1), ivec2(-1, -1));
+    for (int i = 0; i < 4; i++) {
+        force += spring(position, grid, STRUCTURAL_OFFSETS[i], STRUCTURAL);
+        force += spring(position, grid, SHEAR_OFFSETS[i], SHEAR);
+        force += spring(position, grid, 2 * STRUCTURAL_OFFSETS[i], BEND);
+    }
+
+    // Verlet integration, so the velocity is how far the particle moved last step
+    vec3 velocity = (position - particle.previous.xyz) * (1.0 - DAMPING);
+    vec3 next = position + velocity + force * inverseMass * step.deltaTime * step.deltaTime;
+    destination[index] = Particle(vec4(next, inverseMass), vec4(position, 0.0));
+}
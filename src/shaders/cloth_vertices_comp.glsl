@@ -0,0 +1,54 @@
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+struct Particle {
+    vec4 position;
+    vec4 previous;
+};
+
+layout(std430, binding = 0) readonly buffer State {
+    Particle particles[];
+};
+
+// Tightly packed mesh vertices with a position, texture coordinate and normal, 8 floats each
+layout(std430, binding = 1) writeonly buffer Vertices {
+    float vertices[];
+};
+
+layout(push_constant) uniform Step {
+    uint columns;
+    uint rows;
+    float spacing;
+    float deltaTime;
+    float time;
+} step;
+
+vec3 positionAt(ivec2 grid) {
+    grid = clamp(grid, ivec2(0), ivec2(step.columns, step.rows) - 1);
+    return particles[grid.y * int(step.columns) + grid.x].position.xyz;
+}
+
+void main() {
+    ivec2 grid = ivec2(gl_GlobalInvocationID.xy);
+    if (grid.x >= int(step.columns) || grid.y >= int(step.rows)) {
+        return;
+    }
+
+    vec3 position = positionAt(grid);
+    // Rows run down the cloth, so this faces the same way as the triangles `cloth::Cloth` winds
+    vec3 across = positionAt(grid + ivec2(1, 0)) - positionAt(grid - ivec2(1, 0));
+    vec3 down = positionAt(grid + ivec2(0, 1)) - positionAt(grid - ivec2(0, 1));
+    vec3 normal = normalize(cross(across, down));
+    vec2 texCoord = vec2(grid) / vec2(step.columns - 1u, step.rows - 1u);
+
+    uint base = (grid.y * step.columns + grid.x) * 8u;
+    vertices[base + 0u] = position.x;
+    vertices[base + 1u] = position.y;
+    vertices[base + 2u] = position.z;
+    vertices[base + 3u] = texCoord.x;
+    vertices[base + 4u] = texCoord.y;
+    vertices[base + 5u] = normal.x;
+    vertices[base + 6u] = normal.y;
+    vertices[base + 7u] = normal.z;
+}
@@ -0,0 +1,142 @@
+#version 450
+
+layout(local_size_x = 64) in;
+
+struct Object {
+    mat4 transform;
+    // xyz is the centre of the object's bounding sphere in the scene, w its radius
+    vec4 sphere;
+    uint firstIndex;
+    uint indexCount;
+    int vertexOffset;
+    uint padding;
+};
+
+// Matches VkDrawIndexedIndirectCommand
+struct DrawCommand {
+    uint indexCount;
+    uint instanceCount;
+    uint firstIndex;
+    int vertexOffset;
+    uint firstInstance;
+};
+
+layout(std430, binding = 0) readonly buffer Objects {
+    Object objects[];
+};
+
+layout(std430, binding = 1) writeonly buffer Commands {
+    DrawCommand commands[];
+};
+
+// How many objects are visible, which is also the condition the draw is rendered with
+layout(std430, binding = 2) buffer Count {
+    uint drawCount;
+};
+
+// Each texel holds the farthest depth the previous frame drew over it, see `hiz.rs`
+layout(binding = 3) uniform sampler2D pyramid;
+
+layout(push_constant) uniform Cull {
+    // From the scene to clip space
+    mat4 clip;
+    uint objectCount;
+    // Set when the draw count is read from `drawCount`, so visible objects are packed at the start of
+    // `commands`. Otherwise every object keeps its own command and hidden ones draw no instances.
+    uint compact;
+    // Set when objects are tested against `pyramid` after the frustum
+    uint hiZ;
+} cull;
+
+vec4 clipRow(int i) {
+    return vec4(cull.clip[0][i], cull.clip[1][i], cull.clip[2][i], cull.clip[3][i]);
+}
+
+bool inFrustum(vec4 sphere) {
+    vec4 planes[6] = vec4[](
+        clipRow(3) + clipRow(0),
+        clipRow(3) - clipRow(0),
+        clipRow(3) + clipRow(1),
+        clipRow(3) - clipRow(1),
+        clipRow(3) + clipRow(2),
+        clipRow(3) - clipRow(2)
+    );
+    for (int i = 0; i < 6; i++) {
+        vec4 plane = planes[i] / length(planes[i].xyz);
+        if (dot(plane.xyz, sphere.xyz) + plane.w < -sphere.w) {
+            return false;
+        }
+    }
+    return true;
+}
+
+// Whether the box around the sphere is behind everything the pyramid saw drawn where it covers the screen
+bool occluded(vec4 sphere) {
+    vec2 minUv = vec2(1.0);
+    vec2 maxUv = vec2(0.0);
+    float nearest = 1.0;
+    for (int i = 0; i < 8; i++) {
+        vec3 corner = sphere.xyz + sphere.w * vec3(
+            (i & 1) != 0 ? 1.0 : -1.0,
+            (i & 2) != 0 ? 1.0 : -1.0,
+            (i & 4) != 0 ? 1.0 : -1.0
+        );
+        vec4 position = cull.clip * vec4(corner, 1.0);
+        // Boxes reaching past the near plane could cover the whole view
+        if (position.w <= 0.0 || position.z < -position.w) {
+            return false;
+        }
+        vec3 ndc = position.xyz / position.w;
+        minUv = min(minUv, ndc.xy * 0.5 + 0.5);
+        maxUv = max(maxUv, ndc.xy * 0.5 + 0.5);
+        nearest = min(nearest, ndc.z);
+    }
+    minUv = clamp(minUv, 0.0, 1.0);
+    maxUv = clamp(maxUv, 0.0, 1.0);
+
+    // The level at which the box covers at most two texels across, so four reads cover all of it
+    vec2 extent = (maxUv - minUv) * vec2(textureSize(pyramid, 0));
+    int level = clamp(
+        int(ceil(log2(max(max(extent.x, extent.y), 1.0)))),
+        0,
+        textureQueryLevels(pyramid) - 1
+    );
+    ivec2 size = textureSize(pyramid, level);
+    ivec2 low = min(ivec2(minUv * vec2(size)), size - 1);
+    ivec2 high = min(ivec2(maxUv * vec2(size)), size - 1);
+    float farthest = max(
+        max(texelFetch(pyramid, low, level).r, texelFetch(pyramid, ivec2(high.x, low.y), level).r),
+        max(texelFetch(pyramid, ivec2(low.x, high.y), level).r, texelFetch(pyramid, high, level).r)
+    );
+    return nearest > farthest;
+}
+
+void main() {
+    uint index = gl_GlobalInvocationID.x;
+    if (index >= cull.objectCount) {
+        return;
+    }
+
+    Object object = objects[index];
+    bool visible = inFrustum(object.sphere);
+    if (visible && cull.hiZ != 0u) {
+        visible = !occluded(object.sphere);
+    }
+
+    DrawCommand command;
+    command.indexCount = object.indexCount;
+    command.instanceCount = visible ? 1u : 0u;
+    command.firstIndex = object.firstIndex;
+    command.vertexOffset = object.vertexOffset;
+    // Selects the object's transform from the per-instance vertex attributes
+    command.firstInstance = index;
+
+    if (cull.compact == 0u) {
+        commands[index] = command;
+        if (visible) {
+            atomicAdd(drawCount, 1u);
+        }
+    } else if (visible) {
+        commands[atomicAdd(drawCount, 1u)] = command;
+    }
+}
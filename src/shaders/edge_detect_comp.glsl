@@ -0,0 +1,32 @@
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(binding = 0, rgba16f) uniform readonly image2D inputImage;
+layout(binding = 1, rgba16f) uniform writeonly image2D outputImage;
+
+float luminance(ivec2 coord, ivec2 size) {
+    vec3 color = imageLoad(inputImage, clamp(coord, ivec2(0), size - 1)).rgb;
+    return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
+
+void main() {
+    ivec2 coord = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 size = imageSize(inputImage);
+    if (any(greaterThanEqual(coord, size))) {
+        return;
+    }
+
+    // Sobel operator over the luminance of the 3x3 neighbourhood
+    float samples[9];
+    for (int y = -1; y <= 1; y++) {
+        for (int x = -1; x <= 1; x++) {
+            samples[(y + 1) * 3 + x + 1] = luminance(coord + ivec2(x, y), size);
+        }
+    }
+    float horizontal = samples[2] + 2.0 * samples[5] + samples[8] - samples[0] - 2.0 * samples[3] - samples[6];
+    float vertical = samples[6] + 2.0 * samples[7] + samples[8] - samples[0] - 2.0 * samples[1] - samples[2];
+    float edge = clamp(length(vec2(horizontal, vertical)), 0.0, 1.0);
+
+    imageStore(outputImage, coord, vec4(vec3(edge), 1.0));
+}
@@ -0,0 +1,21 @@
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(binding = 0, rgba16f) uniform readonly image2D inputImage;
+layout(binding = 1, rgba16f) uniform writeonly image2D outputImage;
+
+layout(push_constant) uniform Exposure {
+    // In stops, each doubling the frame's brightness
+    float stops;
+} exposure;
+
+void main() {
+    ivec2 coord = ivec2(gl_GlobalInvocationID.xy);
+    if (any(greaterThanEqual(coord, imageSize(inputImage)))) {
+        return;
+    }
+
+    vec3 color = imageLoad(inputImage, coord).rgb;
+    imageStore(outputImage, coord, vec4(color * exp2(exposure.stops), 1.0));
+}
@@ -0,0 +1,48 @@
+#version 450
+
+vec3 encodeSrgb(vec3 linear) {
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+}
+
+layout(push_constant) uniform Sprite {
+    // The sprite's top left corner and size in pixels, matching the viewport it is drawn with
+    vec2 offset;
+    vec2 size;
+    // Linear colour, and how opaque the sprite is at its strongest
+    vec4 color;
+    // 0 is the glow over the light, 1 a disc and 2 a ring, matching `flare::Shape`
+    uint shape;
+    // Set when rendering to a UNORM swapchain, which doesn't encode linear colour to sRGB on write
+    uint encodeSrgb;
+} sprite;
+
+layout(location = 0) out vec4 outColor;
+
+// How many streaks cross the glow
+const float STREAKS = 6.0;
+
+void main() {
+    // From -1 to 1 across the sprite
+    vec2 position = (gl_FragCoord.xy - sprite.offset) / sprite.size * 2.0 - 1.0;
+    float radius = length(position);
+
+    float coverage;
+    if (sprite.shape == 0u) {
+        float core = pow(max(1.0 - radius, 0.0), 4.0);
+        float angle = atan(position.y, position.x);
+        float streaks = pow(abs(cos(angle * STREAKS * 0.5)), 40.0) * max(1.0 - radius, 0.0);
+        coverage = core + 0.5 * streaks;
+    } else if (sprite.shape == 1u) {
+        coverage = 1.0 - smoothstep(0.7, 1.0, radius);
+    } else {
+        coverage = smoothstep(0.75, 0.88, radius) * (1.0 - smoothstep(0.9, 1.0, radius));
+    }
+
+    vec3 color = sprite.color.rgb;
+    if (sprite.encodeSrgb != 0u) {
+        color = encodeSrgb(color);
+    }
+    outColor = vec4(color, clamp(coverage * sprite.color.a, 0.0, 1.0));
+}
@@ -0,0 +1,97 @@
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+// Matches `fog::Frame`
+layout(binding = 0) uniform Frame {
+    mat4 viewProjection;
+    mat4 inverseViewProjection;
+    // xyz is the camera's position
+    vec4 camera;
+    // xyz is the direction towards the light, w how much light scatters forwards rather than back
+    vec4 light;
+    // x is the density, y how quickly it thins out above z, the height it starts thinning out from
+    vec4 medium;
+    // x and y are the distances the froxel slices start and end at
+    vec4 range;
+    // Maps depth to view depth, see `camera::DepthMapping`
+    vec4 depthMapping;
+    // x and y are the frame's size, z is set when depth is 24 bit unorm rather than float, w when the frame's
+    // colour is sRGB encoded
+    uvec4 extent;
+} frame;
+
+layout(std430, binding = 1) readonly buffer Depth {
+    uint depth[];
+};
+layout(std430, binding = 3) readonly buffer Integrated {
+    vec4 integrated[];
+};
+// The rendered frame, fogged in place
+layout(binding = 4, rgba16f) uniform image2D colorImage;
+
+// Matches `fog::GRID`
+const uvec3 GRID = uvec3(160, 90, 64);
+
+float sceneDepth(ivec2 pixel) {
+    uint raw = depth[pixel.y * int(frame.extent.x) + pixel.x];
+    return frame.extent.z != 0u ? float(raw & 0xffffffu) / 16777215.0 : uintBitsToFloat(raw);
+}
+
+vec3 unproject(vec2 ndc, float z) {
+    vec4 position = frame.inverseViewProjection * vec4(ndc, z, 1.0);
+    return position.xyz / position.w;
+}
+
+float viewDepth(float depth) {
+    vec4 mapping = frame.depthMapping;
+    return mapping.z != 0.0 ? -mapping.y / (-mapping.x - depth) : (mapping.y - depth) / mapping.x;
+}
+
+// Distance from the camera to the surface the depth buffer holds at a point on screen. Perspective views scale
+// the distance to a point part way along the ray by their view depths, so that where an infinite far plane is,
+// which has no position, is infinitely far away.
+float surfaceDistance(vec2 ndc, float depth) {
+    if (frame.depthMapping.z == 0.0) {
+        return distance(unproject(ndc, depth), frame.camera.xyz);
+    }
+    return distance(unproject(ndc, 0.5), frame.camera.xyz) * viewDepth(depth) / viewDepth(0.5);
+}
+
+vec3 decodeSrgb(vec3 color) {
+    return mix(color / 12.92, pow((color + 0.055) / 1.055, vec3(2.4)), greaterThan(color, vec3(0.04045)));
+}
+
+vec3 encodeSrgb(vec3 color) {
+    return mix(color * 12.92, 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055, greaterThan(color, vec3(0.0031308)));
+}
+
+// The fog between the camera and the far edge of a slice, with no fog in front of the first
+vec4 fogUpTo(uvec2 column, int slice) {
+    if (slice < 0) {
+        return vec4(0.0, 0.0, 0.0, 1.0);
+    }
+    return integrated[(uint(slice) * GRID.y + column.y) * GRID.x + column.x];
+}
+
+void main() {
+    ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+    if (any(greaterThanEqual(uvec2(pixel), frame.extent.xy))) {
+        return;
+    }
+    vec2 uv = (vec2(pixel) + 0.5) / vec2(frame.extent.xy);
+    float surface = surfaceDistance(uv * 2.0 - 1.0, sceneDepth(pixel));
+
+    // Where the surface falls between the slices' edges, blending between the fog up to the edges either side
+    float ratio = frame.range.y / frame.range.x;
+    float edge = log(max(surface, frame.range.x) / frame.range.x) / log(ratio) * float(GRID.z);
+    edge = clamp(edge, 0.0, float(GRID.z));
+    int below = int(floor(edge));
+    uvec2 column = min(uvec2(uv * vec2(GRID.xy)), GRID.xy - 1u);
+    vec4 fog = mix(fogUpTo(column, below - 1), fogUpTo(column, min(below, int(GRID.z) - 1)), edge - float(below));
+
+    vec4 color = imageLoad(colorImage, pixel);
+    vec3 linear = frame.extent.w != 0u ? decodeSrgb(color.rgb) : color.rgb;
+    linear = linear * fog.a + fog.rgb;
+    imageStore(colorImage, pixel, vec4(frame.extent.w != 0u ? encodeSrgb(linear) : linear, color.a));
+}
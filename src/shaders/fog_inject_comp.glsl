@@ -0,0 +1,122 @@
+#version 450
+
+layout(local_size_x = 4, local_size_y = 4, local_size_z = 4) in;
+
+// Matches `fog::Frame`
+layout(binding = 0) uniform Frame {
+    mat4 viewProjection;
+    mat4 inverseViewProjection;
+    // xyz is the camera's position
+    vec4 camera;
+    // xyz is the direction towards the light, w how much light scatters forwards rather than back
+    vec4 light;
+    // x is the density, y how quickly it thins out above z, the height it starts thinning out from
+    vec4 medium;
+    // x and y are the distances the froxel slices start and end at
+    vec4 range;
+    // Maps depth to view depth, see `camera::DepthMapping`
+    vec4 depthMapping;
+    // x and y are the frame's size, z is set when depth is 24 bit unorm rather than float, w when the frame's
+    // colour is sRGB encoded
+    uvec4 extent;
+    // The light's colour, and the ambient light the fog scatters whichever way it faces, in rgb
+    vec4 lightColor;
+    vec4 ambient;
+} frame;
+
+// The depth buffer copied out after the render pass
+layout(std430, binding = 1) readonly buffer Depth {
+    uint depth[];
+};
+// rgb is the light each froxel scatters towards the camera and w its density
+layout(std430, binding = 2) writeonly buffer Scattering {
+    vec4 scattering[];
+};
+
+// Matches `fog::GRID`
+const uvec3 GRID = uvec3(160, 90, 64);
+const float PI = 3.14159265;
+// Froxels are shadowed where the depth buffer shows something between them and the light within SHADOW_LENGTH
+const int SHADOW_STEPS = 8;
+const float SHADOW_LENGTH = 1.0;
+// How far behind a surface in the depth buffer a point is still taken to be inside it
+const float SHADOW_THICKNESS = 0.3;
+
+float sceneDepth(ivec2 pixel) {
+    uint raw = depth[pixel.y * int(frame.extent.x) + pixel.x];
+    return frame.extent.z != 0u ? float(raw & 0xffffffu) / 16777215.0 : uintBitsToFloat(raw);
+}
+
+vec3 unproject(vec2 ndc, float z) {
+    vec4 position = frame.inverseViewProjection * vec4(ndc, z, 1.0);
+    return position.xyz / position.w;
+}
+
+float viewDepth(float depth) {
+    vec4 mapping = frame.depthMapping;
+    return mapping.z != 0.0 ? -mapping.y / (-mapping.x - depth) : (mapping.y - depth) / mapping.x;
+}
+
+// Distance from the camera to the surface the depth buffer holds at a point on screen. Perspective views scale
+// the distance to a point part way along the ray by their view depths, so that where an infinite far plane is,
+// which has no position, is infinitely far away.
+float surfaceDistance(vec2 ndc, float depth) {
+    if (frame.depthMapping.z == 0.0) {
+        return distance(unproject(ndc, depth), frame.camera.xyz);
+    }
+    return distance(unproject(ndc, 0.5), frame.camera.xyz) * viewDepth(depth) / viewDepth(0.5);
+}
+
+// Distance along the view ray to a slice, spaced exponentially so nearer slices are thinner
+float sliceDistance(float slice) {
+    return frame.range.x * pow(frame.range.y / frame.range.x, slice / float(GRID.z));
+}
+
+// The Henyey-Greenstein phase function, scaled so that fog scattering equally in every direction is as bright
+// as a white surface facing the light
+float phase(float cosTheta, float anisotropy) {
+    float g2 = anisotropy * anisotropy;
+    return (1.0 - g2) / pow(1.0 + g2 - 2.0 * anisotropy * cosTheta, 1.5);
+}
+
+// Marches from the point towards the light through the depth buffer. Only what the camera can see casts
+// shadows, and nothing off screen does.
+float visibility(vec3 position) {
+    float cameraDistance = distance(position, frame.camera.xyz);
+    for (int i = 1; i <= SHADOW_STEPS; i++) {
+        vec3 point = position + frame.light.xyz * (SHADOW_LENGTH * float(i) / float(SHADOW_STEPS));
+        vec4 clip = frame.viewProjection * vec4(point, 1.0);
+        if (clip.w <= 0.0) {
+            break;
+        }
+        vec2 ndc = clip.xy / clip.w;
+        if (any(greaterThan(abs(ndc), vec2(1.0)))) {
+            break;
+        }
+        ivec2 pixel = clamp(ivec2((ndc * 0.5 + 0.5) * vec2(frame.extent.xy)), ivec2(0), ivec2(frame.extent.xy) - 1);
+        float behind = distance(point, frame.camera.xyz) - surfaceDistance(ndc, sceneDepth(pixel));
+        if (behind > 0.0 && behind < SHADOW_THICKNESS) {
+            return 0.0;
+        }
+    }
+    return 1.0;
+}
+
+void main() {
+    uvec3 froxel = gl_GlobalInvocationID;
+    if (any(greaterThanEqual(froxel, GRID))) {
+        return;
+    }
+    vec2 ndc = (vec2(froxel.xy) + 0.5) / vec2(GRID.xy) * 2.0 - 1.0;
+    // Towards a point part way along the ray in perspective views, since an infinite far plane has no position
+    float along = frame.depthMapping.z != 0.0 ? 0.5 : 1.0;
+    vec3 direction = normalize(unproject(ndc, along) - frame.camera.xyz);
+    vec3 position = frame.camera.xyz + direction * sliceDistance(float(froxel.z) + 0.5);
+
+    float density = frame.medium.x * exp(-frame.medium.y * max(position.z - frame.medium.z, 0.0));
+    // Light arriving along the view ray, travelling towards the camera, is scattered the most when the fog
+    // scatters forwards
+    vec3 direct = frame.lightColor.rgb * phase(dot(direction, frame.light.xyz), frame.light.w) * visibility(position);
+    uint index = (froxel.z * GRID.y + froxel.y) * GRID.x + froxel.x;
+    scattering[index] = vec4(frame.ambient.rgb + direct, density);
+}
@@ -0,0 +1,58 @@
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+// Matches `fog::Frame`
+layout(binding = 0) uniform Frame {
+    mat4 viewProjection;
+    mat4 inverseViewProjection;
+    // xyz is the camera's position
+    vec4 camera;
+    // xyz is the direction towards the light, w how much light scatters forwards rather than back
+    vec4 light;
+    // x is the density, y how quickly it thins out above z, the height it starts thinning out from
+    vec4 medium;
+    // x and y are the distances the froxel slices start and end at
+    vec4 range;
+    // Maps depth to view depth, see `camera::DepthMapping`
+    vec4 depthMapping;
+    // x and y are the frame's size, z is set when depth is 24 bit unorm rather than float, w when the frame's
+    // colour is sRGB encoded
+    uvec4 extent;
+} frame;
+
+layout(std430, binding = 2) readonly buffer Scattering {
+    vec4 scattering[];
+};
+// rgb is the light scattered towards the camera up to the far edge of each froxel and w how much of the light
+// from behind the froxel reaches the camera
+layout(std430, binding = 3) writeonly buffer Integrated {
+    vec4 integrated[];
+};
+
+// Matches `fog::GRID`
+const uvec3 GRID = uvec3(160, 90, 64);
+
+float sliceDistance(float slice) {
+    return frame.range.x * pow(frame.range.y / frame.range.x, slice / float(GRID.z));
+}
+
+// Walks each column of froxels away from the camera, accumulating the light they scatter and absorb
+void main() {
+    uvec2 column = gl_GlobalInvocationID.xy;
+    if (any(greaterThanEqual(column, GRID.xy))) {
+        return;
+    }
+    vec3 scattered = vec3(0.0);
+    float transmittance = 1.0;
+    for (uint slice = 0u; slice < GRID.z; slice++) {
+        uint index = (slice * GRID.y + column.y) * GRID.x + column.x;
+        vec4 froxel = scattering[index];
+        float thickness = sliceDistance(float(slice + 1u)) - sliceDistance(float(slice));
+        float sliceTransmittance = exp(-froxel.w * thickness);
+        // The froxel's light integrated over its thickness, for fog that scatters all the light it absorbs
+        scattered += transmittance * froxel.rgb * (1.0 - sliceTransmittance);
+        transmittance *= sliceTransmittance;
+        integrated[index] = vec4(scattered, transmittance);
+    }
+}
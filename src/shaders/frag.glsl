@@ -1,12 +1,272 @@
 #version 450
 
+// Set for double-sided materials, which are drawn without back-face culling
+layout(constant_id = 0) const bool DOUBLE_SIDED = false;
+// Set for masked materials. Fragments with alpha below the cutoff are discarded, or with alpha-to-coverage
+// enabled they cover fewer samples instead.
+layout(constant_id = 1) const bool ALPHA_MASK = false;
+layout(constant_id = 2) const float ALPHA_CUTOFF = 0.5;
+layout(constant_id = 3) const bool ALPHA_TO_COVERAGE = false;
+
+// Set when rendering to a UNORM swapchain, which doesn't encode linear colour to sRGB on write
+layout(constant_id = 4) const bool ENCODE_SRGB = false;
+
+// How vertex colours combine with the texture: 0 ignores them and 1 multiplies the texture by them
+layout(constant_id = 5) const int VERTEX_COLOR_MODE = 0;
+// Cleared for meshes without texture coordinates, which are drawn untextured
+layout(constant_id = 6) const bool HAS_TEX_COORD = true;
+// Set for meshes with lightmap coordinates, which are lit by the baked lightmap instead of the fixed light
+layout(constant_id = 7) const bool HAS_LIGHTMAP = false;
+// How much baked occlusion darkens the ambient light, from 0 to 1. Lightmaps already include occlusion.
+layout(constant_id = 8) const float OCCLUSION_STRENGTH = 1.0;
+// The row of the toon ramp atlas the light is looked up in for toon shaded materials, or -1 for smooth lighting
+layout(constant_id = 9) const int TOON_RAMP = -1;
+
+vec3 encodeSrgb(vec3 linear) {
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+}
+
+// Matches `capsules::MAX_CAPSULES`
+const uint MAX_CAPSULES = 16u;
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+    vec4 audioBands[4];
+    // Matches `clipping::Uniforms`. Points where dot(xyz, position) < w are cut away, in the scene's space.
+    vec4 clipPlanes[4];
+    vec4 clipCapColor;
+    // x is how many planes are used
+    uvec4 clipFlags;
+    // Matches `sky::Uniforms`. xyz is the direction towards the light, and the colours are in rgb.
+    vec4 lightDirection;
+    vec4 lightColor;
+    vec4 ambientColor;
+    // Matches `capsules::Uniforms`, in world space. The capsules run between the starts and ends, and w of the
+    // starts is their radius.
+    vec4 capsuleStarts[MAX_CAPSULES];
+    vec4 capsuleEnds[MAX_CAPSULES];
+    // x is how many capsules are used
+    uvec4 capsuleFlags;
+    // The shadow strength, occlusion strength and penumbra
+    vec4 capsuleShading;
+    vec4 groundShadow;
+    vec4 outlineColor;
+    vec4 outlineViewport;
+    // Matches `shadows::Uniforms`. The matrix is from world space to the shadow map's clip space. x of the flags
+    // is the filter, 0 without a shadow map, 1 hard, 2 PCF, 3 PCSS and 4 VSM, and y is PCF's kernel width. The
+    // params are the strength, a texel's width across the map, how far PCSS penumbrae widen for each unit of
+    // depth, and how much of VSM's shadow is cut off.
+    mat4 shadowMatrix;
+    uvec4 shadowFlags;
+    vec4 shadowParams;
+} ubo;
+
 layout(binding = 1) uniform sampler2D texSampler;
+// Lighting baked by `lightmap::bake`, sampled in linear colour
+layout(binding = 2) uniform sampler2D lightmapSampler;
+// The depth the light sees, fetched without filtering, and VSM's blurred depth and depth squared in rg
+layout(binding = 3) uniform sampler2D shadowDepth;
+layout(binding = 4) uniform sampler2D shadowMoments;
+// Rows of `toon::Ramp`s, fetched without filtering so that their bands stay hard
+layout(binding = 5) uniform sampler2D toonRamps;
 
 layout(location = 0) in vec3 fragColor;
 layout(location = 1) in vec2 fragTexCoord;
+layout(location = 2) in vec3 fragNormal;
+layout(location = 3) in vec2 fragLightmapCoord;
+layout(location = 4) in float fragOcclusion;
+layout(location = 5) in vec3 fragScenePosition;
 
 layout(location = 0) out vec4 outColor;
 
+// How much of the light reaches the point past the capsule along the ray towards it, from 0 to 1. The soft edge
+// widens with how far along the ray it passes the capsule, so the shadow is sharp where the capsule is close.
+float capsuleShadow(vec3 point, vec3 towardsLight, vec3 start, vec3 end, float radius) {
+    vec3 axis = end - start;
+    float axisLength = dot(axis, axis);
+    float along = dot(towardsLight, axis);
+    // Where on the axis the ray passes closest. Rays parallel to the axis, and spheres, are taken at the start.
+    float denominator = axisLength - along * along;
+    float closest = 0.0;
+    if (denominator > 0.0001) {
+        vec3 fromStart = point - start;
+        closest = clamp((dot(fromStart, axis) - along * dot(fromStart, towardsLight)) / denominator, 0.0, 1.0);
+    }
+    vec3 axisPoint = start + axis * closest;
+    float travelled = dot(axisPoint - point, towardsLight);
+    // The capsule is on the side of the point away from the light, which lights its own surfaces facing it
+    if (travelled <= 0.0) {
+        return 1.0;
+    }
+    float clearance = length(point + towardsLight * travelled - axisPoint) - radius;
+    return smoothstep(-1.0, 1.0, clearance / (ubo.capsuleShading.z * travelled));
+}
+
+// How much of the ambient light reaches the point past the capsule, from 0 to 1, as if it were a sphere at the
+// nearest point on its axis
+float capsuleOcclusion(vec3 point, vec3 normal, vec3 start, vec3 end, float radius) {
+    vec3 axis = end - start;
+    float closest = clamp(dot(point - start, axis) / max(dot(axis, axis), 0.0001), 0.0, 1.0);
+    vec3 towardsAxis = start + axis * closest - point;
+    float away = max(length(towardsAxis), radius);
+    float facing = max(dot(normal, towardsAxis / away), 0.0);
+    // A sphere's occlusion falls off with the square of its distance
+    float size = radius / away;
+    return 1.0 - facing * size * size;
+}
+
+// 1 where the point at the depth is in front of what the light sees at uv, or 0 behind it
+float shadowCompare(vec2 uv, float depth) {
+    return depth <= texture(shadowDepth, uv).r ? 1.0 : 0.0;
+}
+
+// The comparisons over a square kernel `kernel` samples across, `spacing` apart in the map, averaged
+float shadowPcf(vec2 uv, float depth, int kernel, float spacing) {
+    int reach = kernel / 2;
+    float lit = 0.0;
+    for (int y = -reach; y <= reach; y++) {
+        for (int x = -reach; x <= reach; x++) {
+            lit += shadowCompare(uv + vec2(x, y) * spacing, depth);
+        }
+    }
+    return lit / float(kernel * kernel);
+}
+
+// Filters with a penumbra as wide as the light, seen from the point, is across the gap to the blockers found
+// between it and the light
+float shadowPcss(vec2 uv, float depth) {
+    float texel = ubo.shadowParams.y;
+    float search = max(ubo.shadowParams.z * depth, texel) / 2.0;
+    float blockers = 0.0;
+    float blockerDepth = 0.0;
+    for (int y = -2; y <= 2; y++) {
+        for (int x = -2; x <= 2; x++) {
+            float sampled = texture(shadowDepth, uv + vec2(x, y) * search).r;
+            if (sampled < depth) {
+                blockers += 1.0;
+                blockerDepth += sampled;
+            }
+        }
+    }
+    if (blockers == 0.0) {
+        return 1.0;
+    }
+    float penumbra = (depth - blockerDepth / blockers) * ubo.shadowParams.z;
+    return shadowPcf(uv, depth, 7, max(penumbra / 6.0, texel));
+}
+
+// Chebyshev's upper bound on how much of the light reaches the point, from the moments' mean and variance, with
+// the lowest of it cut off where overlapping shadows would let light bleed through
+float shadowVsm(vec2 uv, float depth) {
+    vec2 moments = texture(shadowMoments, uv).rg;
+    if (depth <= moments.x) {
+        return 1.0;
+    }
+    float variance = max(moments.y - moments.x * moments.x, 0.00001);
+    float gap = depth - moments.x;
+    float lit = variance / (variance + gap * gap);
+    float bleed = ubo.shadowParams.w;
+    return clamp((lit - bleed) / (1.0 - bleed), 0.0, 1.0);
+}
+
+// How much of the light the shadow map lets reach the point, from 0 to 1. Points outside the map are lit.
+float shadowMap(vec3 point) {
+    uint mode = ubo.shadowFlags.x;
+    if (mode == 0u) {
+        return 1.0;
+    }
+    vec3 projected = (ubo.shadowMatrix * vec4(point, 1.0)).xyz;
+    vec2 uv = projected.xy * 0.5 + 0.5;
+    float depth = projected.z;
+    if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0))) || depth > 1.0) {
+        return 1.0;
+    }
+    float lit;
+    if (mode == 2u) {
+        lit = shadowPcf(uv, depth, int(ubo.shadowFlags.y), ubo.shadowParams.y);
+    } else if (mode == 3u) {
+        lit = shadowPcss(uv, depth);
+    } else if (mode == 4u) {
+        lit = shadowVsm(uv, depth);
+    } else {
+        lit = shadowCompare(uv, depth);
+    }
+    return mix(1.0, lit, ubo.shadowParams.x);
+}
+
 void main() {
-    outColor = texture(texSampler, fragTexCoord);
-}
\ No newline at end of file
+    for (uint i = 0u; i < min(ubo.clipFlags.x, 4u); i++) {
+        if (dot(ubo.clipPlanes[i].xyz, fragScenePosition) < ubo.clipPlanes[i].w) {
+            discard;
+        }
+    }
+
+    vec3 normal = normalize(fragNormal);
+    if (DOUBLE_SIDED && !gl_FrontFacing) {
+        // Back faces of double-sided materials are lit as if they were the front
+        normal = -normal;
+    }
+
+    vec4 texel = vec4(1.0);
+    if (HAS_TEX_COORD) {
+        texel = texture(texSampler, fragTexCoord);
+    }
+    if (VERTEX_COLOR_MODE == 1) {
+        texel.rgb *= fragColor;
+    }
+    float alpha = texel.a;
+    if (ALPHA_MASK) {
+        if (ALPHA_TO_COVERAGE) {
+            // Sharpen alpha around the cutoff to roughly a pixel wide transition so the coverage mask gives
+            // crisp but anti-aliased edges
+            alpha = clamp((alpha - ALPHA_CUTOFF) / max(fwidth(alpha), 0.0001) + 0.5, 0.0, 1.0);
+        } else if (alpha < ALPHA_CUTOFF) {
+            discard;
+        } else {
+            alpha = 1.0;
+        }
+    }
+
+    vec3 worldPosition = (ubo.model * vec4(fragScenePosition, 1.0)).xyz;
+    float capsuleLight = 1.0;
+    float capsuleAmbient = 1.0;
+    for (uint i = 0u; i < min(ubo.capsuleFlags.x, MAX_CAPSULES); i++) {
+        vec3 start = ubo.capsuleStarts[i].xyz;
+        vec3 end = ubo.capsuleEnds[i].xyz;
+        float radius = ubo.capsuleStarts[i].w;
+        capsuleLight = min(capsuleLight, capsuleShadow(worldPosition, ubo.lightDirection.xyz, start, end, radius));
+        capsuleAmbient *= capsuleOcclusion(worldPosition, normal, start, end, radius);
+    }
+    capsuleLight = mix(1.0, capsuleLight, ubo.capsuleShading.x);
+    capsuleAmbient = mix(1.0, capsuleAmbient, ubo.capsuleShading.y);
+
+    vec3 lighting;
+    if (HAS_LIGHTMAP) {
+        // The lightmap holds the light and the ambient light together, so both darken all of it
+        lighting = texture(lightmapSampler, fragLightmapCoord).rgb * capsuleLight * capsuleAmbient;
+    } else {
+        // Lightmaps have their shadows baked in, so only the fixed light is shadowed by the map
+        float shadowed = capsuleLight * shadowMap(worldPosition);
+        vec3 diffuse = vec3(max(dot(normal, ubo.lightDirection.xyz), 0.0) * shadowed);
+        if (TOON_RAMP >= 0) {
+            // Across the ramp from facing away from the light to facing it, with shadows moving fragments into
+            // the darker bands
+            float lit = (dot(normal, ubo.lightDirection.xyz) * 0.5 + 0.5) * shadowed;
+            ivec2 size = textureSize(toonRamps, 0);
+            int x = clamp(int(lit * float(size.x)), 0, size.x - 1);
+            // Images are flipped vertically when they are uploaded, so the atlas's top row is its last
+            int y = clamp(size.y - 1 - TOON_RAMP, 0, size.y - 1);
+            diffuse = texelFetch(toonRamps, ivec2(x, y), 0).rgb;
+        }
+        float occlusion = mix(1.0, fragOcclusion, OCCLUSION_STRENGTH) * capsuleAmbient;
+        lighting = ubo.ambientColor.rgb * occlusion + ubo.lightColor.rgb * diffuse;
+    }
+    outColor = vec4(texel.rgb * lighting, alpha);
+    if (ENCODE_SRGB) {
+        outColor.rgb = encodeSrgb(outColor.rgb);
+    }
+}
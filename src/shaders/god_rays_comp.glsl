@@ -0,0 +1,49 @@
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(binding = 0, rgba16f) uniform readonly image2D inputImage;
+layout(binding = 1, rgba16f) uniform writeonly image2D outputImage;
+
+layout(push_constant) uniform Constants {
+    float exposure;
+    // How brightly the light shines through the frame, scaled by how much of it can be seen
+    float lightStrength;
+    // Where the light is as a fraction of the frame's size, from the top left
+    vec2 lightPosition;
+} constants;
+
+// Samples taken on the way from each pixel towards the light
+const int SAMPLES = 48;
+// How much of the way to the light the samples cover
+const float REACH = 0.8;
+// How much less each sample counts than the one before, so the rays fade out away from what they shine through
+const float DECAY = 0.96;
+// Only pixels brighter than this, like the sky around the sun, let light through
+const float THRESHOLD = 0.5;
+
+void main() {
+    ivec2 coord = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 size = imageSize(inputImage);
+    if (any(greaterThanEqual(coord, size))) {
+        return;
+    }
+
+    vec3 color = imageLoad(inputImage, coord).rgb;
+    // Blur the bright parts of the frame radially towards the light, so they streak past whatever is darker
+    vec2 position = (vec2(coord) + 0.5) / vec2(size);
+    vec2 stride = (constants.lightPosition - position) * REACH / float(SAMPLES);
+    vec3 rays = vec3(0.0);
+    float weight = 1.0;
+    for (int i = 0; i < SAMPLES; i++) {
+        position += stride;
+        ivec2 sampled = clamp(ivec2(position * vec2(size)), ivec2(0), size - 1);
+        vec3 light = imageLoad(inputImage, sampled).rgb;
+        float luminance = dot(light, vec3(0.2126, 0.7152, 0.0722));
+        rays += light * smoothstep(THRESHOLD, 1.0, luminance) * weight;
+        weight *= DECAY;
+    }
+
+    color += rays / float(SAMPLES) * constants.lightStrength;
+    imageStore(outputImage, coord, vec4(color, 1.0));
+}
@@ -0,0 +1,37 @@
+#version 450
+
+// Set for blob shadows, whose vertex colours' red is how much of the shadow covers them
+layout(constant_id = 5) const int VERTEX_COLOR_MODE = 0;
+
+// Matches `capsules::MAX_CAPSULES`
+const uint MAX_CAPSULES = 16u;
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+    vec4 audioBands[4];
+    vec4 clipPlanes[4];
+    vec4 clipCapColor;
+    uvec4 clipFlags;
+    vec4 lightDirection;
+    vec4 lightColor;
+    vec4 ambientColor;
+    vec4 capsuleStarts[MAX_CAPSULES];
+    vec4 capsuleEnds[MAX_CAPSULES];
+    uvec4 capsuleFlags;
+    vec4 capsuleShading;
+    // Matches `ground_shadows::Uniforms`. x is how much light a shadow blocks.
+    vec4 groundShadow;
+} ubo;
+
+layout(location = 0) in vec3 fragColor;
+
+layout(location = 0) out vec4 outColor;
+
+// Shadows are black, blended over the ground so that they darken it by their opacity. Black needs no sRGB
+// encoding.
+void main() {
+    float coverage = VERTEX_COLOR_MODE == 1 ? fragColor.r : 1.0;
+    outColor = vec4(0.0, 0.0, 0.0, coverage * ubo.groundShadow.x);
+}
@@ -0,0 +1,27 @@
+#version 450
+
+// Builds one level of the depth pyramid from the level before it, or from the depth attachment for level zero
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(binding = 0) uniform sampler2D source;
+layout(binding = 1, r32f) uniform writeonly image2D destination;
+
+void main() {
+    ivec2 texel = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 size = imageSize(destination);
+    if (any(greaterThanEqual(texel, size))) {
+        return;
+    }
+
+    // The source texels this one covers, rounded outwards when the sizes don't divide so none are missed
+    ivec2 sourceSize = textureSize(source, 0);
+    ivec2 first = texel * sourceSize / size;
+    ivec2 last = ((texel + 1) * sourceSize + size - 1) / size - 1;
+    float farthest = 0.0;
+    for (int y = first.y; y <= last.y; y++) {
+        for (int x = first.x; x <= last.x; x++) {
+            farthest = max(farthest, texelFetch(source, ivec2(x, y), 0).r);
+        }
+    }
+    imageStore(destination, texel, vec4(farthest));
+}
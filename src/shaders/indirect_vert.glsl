@@ -0,0 +1,36 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+} ubo;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inColor;
+layout(location = 2) in vec2 inTexCoord;
+layout(location = 3) in vec3 inNormal;
+// The object's transform, advanced per instance and selected by the draw command's first instance. Instanced
+// draws such as a `swarm::Swarm` read one per instance.
+layout(location = 4) in mat4 inTransform;
+
+layout(location = 0) out vec3 fragColor;
+layout(location = 1) out vec2 fragTexCoord;
+layout(location = 2) out vec3 fragNormal;
+// Objects drawn indirectly aren't lightmapped or occluded
+layout(location = 3) out vec2 fragLightmapCoord;
+layout(location = 4) out float fragOcclusion;
+// Before the scene's model matrix, where clipping planes are placed
+layout(location = 5) out vec3 fragScenePosition;
+
+void main() {
+    mat4 model = ubo.model * inTransform;
+    gl_Position = ubo.proj * ubo.view * model * vec4(inPosition, 1.0);
+    fragScenePosition = (inTransform * vec4(inPosition, 1.0)).xyz;
+    fragColor = inColor;
+    fragTexCoord = inTexCoord;
+    fragLightmapCoord = vec2(0.0);
+    fragOcclusion = 1.0;
+    // The inverse transpose keeps normals perpendicular to the surface under non-uniform scaling
+    fragNormal = transpose(inverse(mat3(model))) * inNormal;
+}
@@ -0,0 +1,163 @@
+#version 450
+
+layout(local_size_x = 4, local_size_y = 4, local_size_z = 4) in;
+
+// One RGBA8 texel per invocation, copied into the texture once every texel is written
+layout(std430, binding = 0) writeonly buffer Texels {
+    uint texels[];
+};
+
+layout(push_constant) uniform Generate {
+    uvec3 size;
+    // Matches `noise::Kind`
+    uint kind;
+    // Cells across the texture in the first octave of the red channel
+    uint frequency;
+    uint octaves;
+    // How much each octave's amplitude is scaled by relative to the one before
+    float persistence;
+    uint seed;
+} generate;
+
+const uint PERLIN = 0u;
+const uint SIMPLEX = 1u;
+const uint WORLEY = 2u;
+
+// Three well mixed numbers for each cell, see "Hash Functions for GPU Rendering" by Jarzynski and Olano
+uvec3 pcg3d(uvec3 v) {
+    v = v * 1664525u + 1013904223u;
+    v.x += v.y * v.z;
+    v.y += v.z * v.x;
+    v.z += v.x * v.y;
+    v ^= v >> 16u;
+    v.x += v.y * v.z;
+    v.y += v.z * v.x;
+    v.z += v.x * v.y;
+    return v;
+}
+
+// Cells wrap around every `period` cells, which is what makes the noise tile
+vec3 random3(ivec3 cell, ivec3 period) {
+    // Integer modulo is undefined for negative cells
+    uvec3 wrapped = uvec3(cell - period * ivec3(floor(vec3(cell) / vec3(period))));
+    uint seed = generate.seed;
+    return vec3(pcg3d(wrapped + uvec3(seed, seed * 7u, seed * 13u))) / 4294967295.0;
+}
+
+vec3 gradient(ivec3 cell, ivec3 period) {
+    return normalize(random3(cell, period) * 2.0 - 1.0 + 1e-4);
+}
+
+float perlin(vec3 p, ivec3 period) {
+    ivec3 cell = ivec3(floor(p));
+    vec3 f = fract(p);
+    vec3 u = f * f * f * (f * (f * 6.0 - 15.0) + 10.0);
+    float corners[8];
+    for (int i = 0; i < 8; i++) {
+        ivec3 corner = ivec3(i & 1, (i >> 1) & 1, (i >> 2) & 1);
+        corners[i] = dot(gradient(cell + corner, period), f - vec3(corner));
+    }
+    float y0 = mix(mix(corners[0], corners[1], u.x), mix(corners[2], corners[3], u.x), u.y);
+    float y1 = mix(mix(corners[4], corners[5], u.x), mix(corners[6], corners[7], u.x), u.y);
+    return mix(y0, y1, u.z);
+}
+
+// Simplex noise over the cube grid split into six tetrahedra per cell, rather than over a skewed grid, so that
+// it tiles like the other kinds. Every corner's falloff reaches zero before the faces opposite it.
+float simplex(vec3 p, ivec3 period) {
+    ivec3 cell = ivec3(floor(p));
+    vec3 f = fract(p);
+    // The tetrahedron containing the point steps along the axes in order of the point's largest offset
+    ivec3 first;
+    ivec3 second;
+    if (f.x >= f.y) {
+        if (f.y >= f.z) {
+            first = ivec3(1, 0, 0);
+            second = ivec3(1, 1, 0);
+        } else if (f.x >= f.z) {
+            first = ivec3(1, 0, 0);
+            second = ivec3(1, 0, 1);
+        } else {
+            first = ivec3(0, 0, 1);
+            second = ivec3(1, 0, 1);
+        }
+    } else {
+        if (f.y < f.z) {
+            first = ivec3(0, 0, 1);
+            second = ivec3(0, 1, 1);
+        } else if (f.x < f.z) {
+            first = ivec3(0, 1, 0);
+            second = ivec3(0, 1, 1);
+        } else {
+            first = ivec3(0, 1, 0);
+            second = ivec3(1, 1, 0);
+        }
+    }
+    ivec3 corners[4] = ivec3[](ivec3(0), first, second, ivec3(1));
+    float result = 0.0;
+    for (int i = 0; i < 4; i++) {
+        vec3 offset = f - vec3(corners[i]);
+        float falloff = max(0.5 - dot(offset, offset), 0.0);
+        falloff *= falloff;
+        result += falloff * falloff * dot(gradient(cell + corners[i], period), offset);
+    }
+    return 40.0 * result;
+}
+
+// Distance to the nearest of one random point per cell, inverted so the points are bright
+float worley(vec3 p, ivec3 period) {
+    ivec3 cell = ivec3(floor(p));
+    vec3 f = fract(p);
+    float nearest = 1.0;
+    for (int z = -1; z <= 1; z++) {
+        for (int y = -1; y <= 1; y++) {
+            for (int x = -1; x <= 1; x++) {
+                ivec3 neighbour = ivec3(x, y, z);
+                vec3 feature = vec3(neighbour) + random3(cell + neighbour, period);
+                nearest = min(nearest, length(feature - f));
+            }
+        }
+    }
+    return 1.0 - nearest;
+}
+
+// Octaves of the noise at doubling frequencies, in [0, 1]. `uv` is the texel's position in the texture from 0
+// to 1, and 2D textures only have one cell along z.
+float fractal(vec3 uv, uint frequency) {
+    float value = 0.0;
+    float amplitude = 1.0;
+    float total = 0.0;
+    for (uint octave = 0u; octave < generate.octaves; octave++) {
+        uint octaveFrequency = frequency << octave;
+        ivec3 period = ivec3(octaveFrequency, octaveFrequency, generate.size.z > 1u ? octaveFrequency : 1u);
+        vec3 p = uv * vec3(period);
+        float noise;
+        if (generate.kind == PERLIN) {
+            noise = 0.5 + 0.5 * perlin(p, period);
+        } else if (generate.kind == SIMPLEX) {
+            noise = 0.5 + 0.5 * simplex(p, period);
+        } else {
+            noise = worley(p, period);
+        }
+        value += amplitude * noise;
+        total += amplitude;
+        amplitude *= generate.persistence;
+    }
+    return clamp(value / max(total, 1e-6), 0.0, 1.0);
+}
+
+void main() {
+    uvec3 texel = gl_GlobalInvocationID;
+    if (any(greaterThanEqual(texel, generate.size))) {
+        return;
+    }
+    vec3 uv = (vec3(texel) + 0.5) / vec3(generate.size);
+    // Each channel starts an octave higher than the one before, the usual layout for cloud noise
+    vec4 color = vec4(
+        fractal(uv, generate.frequency),
+        fractal(uv, generate.frequency * 2u),
+        fractal(uv, generate.frequency * 4u),
+        fractal(uv, generate.frequency * 8u));
+    uint index = (texel.z * generate.size.y + texel.y) * generate.size.x + texel.x;
+    texels[index] = packUnorm4x8(color);
+}
@@ -0,0 +1,52 @@
+#version 450
+
+// Set when rendering to a UNORM swapchain, which doesn't encode linear colour to sRGB on write
+layout(constant_id = 4) const bool ENCODE_SRGB = false;
+
+// Matches `capsules::MAX_CAPSULES`
+const uint MAX_CAPSULES = 16u;
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+    vec4 audioBands[4];
+    // Matches `clipping::Uniforms`. Points where dot(xyz, position) < w are cut away, in the scene's space.
+    vec4 clipPlanes[4];
+    vec4 clipCapColor;
+    // x is how many planes are used
+    uvec4 clipFlags;
+    vec4 lightDirection;
+    vec4 lightColor;
+    vec4 ambientColor;
+    vec4 capsuleStarts[MAX_CAPSULES];
+    vec4 capsuleEnds[MAX_CAPSULES];
+    uvec4 capsuleFlags;
+    vec4 capsuleShading;
+    vec4 groundShadow;
+    // Matches `toon::Uniforms`, in linear colour
+    vec4 outlineColor;
+} ubo;
+
+layout(location = 5) in vec3 fragScenePosition;
+
+layout(location = 0) out vec4 outColor;
+
+vec3 encodeSrgb(vec3 linear) {
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+}
+
+void main() {
+    // Outlines are cut away with the mesh they outline
+    for (uint i = 0u; i < min(ubo.clipFlags.x, 4u); i++) {
+        if (dot(ubo.clipPlanes[i].xyz, fragScenePosition) < ubo.clipPlanes[i].w) {
+            discard;
+        }
+    }
+    outColor = vec4(ubo.outlineColor.rgb, 1.0);
+    if (ENCODE_SRGB) {
+        outColor.rgb = encodeSrgb(outColor.rgb);
+    }
+}
@@ -0,0 +1,57 @@
+#version 450
+
+// How wide the outline is in pixels, see `material::Toon::outline`
+layout(constant_id = 0) const float OUTLINE_WIDTH = 1.0;
+
+// Matches `capsules::MAX_CAPSULES`
+const uint MAX_CAPSULES = 16u;
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+    vec4 audioBands[4];
+    vec4 clipPlanes[4];
+    vec4 clipCapColor;
+    uvec4 clipFlags;
+    vec4 lightDirection;
+    vec4 lightColor;
+    vec4 ambientColor;
+    vec4 capsuleStarts[MAX_CAPSULES];
+    vec4 capsuleEnds[MAX_CAPSULES];
+    uvec4 capsuleFlags;
+    vec4 capsuleShading;
+    vec4 groundShadow;
+    // Matches `toon::Uniforms`. The viewport's width and height in pixels are in xy.
+    vec4 outlineColor;
+    vec4 outlineViewport;
+} ubo;
+
+layout(push_constant) uniform PushConstants {
+    // The draw's own transform, applied before the scene's
+    mat4 model;
+    float pointSize;
+} pc;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 3) in vec3 inNormal;
+
+// Before the scene's model matrix, where clipping planes are placed
+layout(location = 5) out vec3 fragScenePosition;
+
+// The mesh's back faces pushed out along their normals by the same number of pixels however far away they are,
+// so that they show as a rim around the front faces drawn over them
+void main() {
+    mat4 model = ubo.model * pc.model;
+    gl_Position = ubo.proj * ubo.view * model * vec4(inPosition, 1.0);
+    fragScenePosition = (pc.model * vec4(inPosition, 1.0)).xyz;
+
+    vec3 viewNormal = mat3(ubo.view) * transpose(inverse(mat3(model))) * inNormal;
+    vec2 screenNormal = (ubo.proj * vec4(viewNormal, 0.0)).xy * ubo.outlineViewport.xy;
+    if (dot(screenNormal, screenNormal) > 0.0) {
+        // A pixel is 2 / viewport across in normalized device coordinates, and scaling by w undoes the
+        // perspective divide
+        vec2 offset = normalize(screenNormal) * OUTLINE_WIDTH * 2.0 / ubo.outlineViewport.xy;
+        gl_Position.xy += offset * gl_Position.w;
+    }
+}
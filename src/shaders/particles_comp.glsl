@@ -0,0 +1,87 @@
+#version 450
+
+layout(local_size_x = 64) in;
+
+struct Particle {
+    // w is the particle's age in seconds
+    vec4 position;
+    // w is how long the particle lives for
+    vec4 velocity;
+};
+
+layout(std430, binding = 0) buffer State {
+    Particle particles[];
+};
+
+// Tightly packed `Billboard`s: center, size and axis, 9 floats each
+layout(std430, binding = 1) writeonly buffer Billboards {
+    float billboards[];
+};
+
+layout(push_constant) uniform Update {
+    float deltaTime;
+    float time;
+    // Non-zero on the first update, which spawns every particle with a random age so they don't all launch
+    // together
+    uint reset;
+} update;
+
+const vec3 EMITTER = vec3(0.75, 0.75, -0.75);
+const vec3 GRAVITY = vec3(0.0, 0.0, -2.0);
+const float SIZE = 0.03;
+
+float hash(uint n) {
+    n = (n << 13u) ^ n;
+    n = n * (n * n * 15731u + 789221u) + 1376312589u;
+    return float(n & 0x7fffffffu) / float(0x7fffffff);
+}
+
+Particle spawn(uint index, float seed) {
+    uint n = index * 4u + uint(seed * 1000.0) * 7919u;
+    float angle = hash(n) * 6.2831853;
+    float spread = 0.3 * hash(n + 1u);
+    Particle particle;
+    particle.position = vec4(EMITTER, 0.0);
+    vec3 velocity = vec3(spread * cos(angle), spread * sin(angle), 1.6 + 0.4 * hash(n + 2u));
+    particle.velocity = vec4(velocity, 2.0 + hash(n + 3u));
+    return particle;
+}
+
+void main() {
+    uint index = gl_GlobalInvocationID.x;
+    if (index >= particles.length()) {
+        return;
+    }
+
+    Particle particle = particles[index];
+    if (update.reset != 0u) {
+        particle = spawn(index, 0.0);
+        particle.position.w = hash(index) * particle.velocity.w;
+        // Start where the particle would be at its age
+        float age = particle.position.w;
+        particle.position.xyz += particle.velocity.xyz * age + 0.5 * GRAVITY * age * age;
+        particle.velocity.xyz += GRAVITY * age;
+    } else {
+        particle.velocity.xyz += GRAVITY * update.deltaTime;
+        particle.position.xyz += particle.velocity.xyz * update.deltaTime;
+        particle.position.w += update.deltaTime;
+        if (particle.position.w > particle.velocity.w || particle.position.z < EMITTER.z) {
+            particle = spawn(index, update.time);
+        }
+    }
+    particles[index] = particle;
+
+    // Particles below the floor are hidden until they respawn
+    float size = particle.position.z < EMITTER.z ? 0.0 : SIZE;
+    uint base = index * 9u;
+    billboards[base + 0u] = particle.position.x;
+    billboards[base + 1u] = particle.position.y;
+    billboards[base + 2u] = particle.position.z;
+    billboards[base + 3u] = size;
+    billboards[base + 4u] = size;
+    // Spherical, with no locking axis
+    billboards[base + 5u] = 0.0;
+    billboards[base + 6u] = 0.0;
+    billboards[base + 7u] = 0.0;
+    billboards[base + 8u] = 0.0;
+}
@@ -0,0 +1,26 @@
+#version 450
+
+// Set when rendering to a UNORM swapchain, which doesn't encode linear colour to sRGB on write
+layout(constant_id = 4) const bool ENCODE_SRGB = false;
+
+vec3 encodeSrgb(vec3 linear) {
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+}
+
+layout(location = 0) in vec3 fragColor;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    // Points are rasterized as squares, cut down to the circle inside
+    vec2 offset = gl_PointCoord * 2.0 - 1.0;
+    if (dot(offset, offset) > 1.0) {
+        discard;
+    }
+    outColor = vec4(fragColor, 1.0);
+    if (ENCODE_SRGB) {
+        outColor.rgb = encodeSrgb(outColor.rgb);
+    }
+}
@@ -0,0 +1,27 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+} ubo;
+
+layout(push_constant) uniform PushConstants {
+    // The draw's own transform, applied before the scene's
+    mat4 model;
+    // Pixels across one unit from the camera
+    float pointSize;
+} pc;
+
+// Matches `pointcloud::Point`. Sprites always face the camera so the normal isn't read.
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inColor;
+
+layout(location = 0) out vec3 fragColor;
+
+void main() {
+    gl_Position = ubo.proj * ubo.view * ubo.model * pc.model * vec4(inPosition, 1.0);
+    // w is the distance from the camera, so sprites shrink with distance like the surfaces they sample
+    gl_PointSize = max(pc.pointSize / gl_Position.w, 1.0);
+    fragColor = inColor;
+}
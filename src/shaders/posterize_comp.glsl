@@ -0,0 +1,22 @@
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(binding = 0, rgba16f) uniform readonly image2D inputImage;
+layout(binding = 1, rgba16f) uniform writeonly image2D outputImage;
+
+const float LEVELS = 4.0;
+
+void main() {
+    ivec2 coord = ivec2(gl_GlobalInvocationID.xy);
+    if (any(greaterThanEqual(coord, imageSize(inputImage)))) {
+        return;
+    }
+
+    vec3 color = clamp(imageLoad(inputImage, coord).rgb, 0.0, 1.0);
+    // Quantise perceptually so the bands look evenly spaced rather than crowding the highlights
+    vec3 perceptual = pow(color, vec3(1.0 / 2.2));
+    vec3 banded = floor(perceptual * LEVELS + 0.5) / LEVELS;
+
+    imageStore(outputImage, coord, vec4(pow(banded, vec3(2.2)), 1.0));
+}
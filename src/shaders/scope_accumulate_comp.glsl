@@ -0,0 +1,58 @@
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(binding = 0, rgba16f) uniform readonly image2D frame;
+
+// Must match the sizes in scope.rs
+const int HISTOGRAM_LEVELS = 256;
+const int WAVEFORM_COLUMNS = 256;
+const int WAVEFORM_LEVELS = 128;
+
+layout(std430, binding = 1) buffer Counts {
+    uint peak;
+    uint bins[];
+} counts;
+
+layout(push_constant) uniform Settings {
+    int mode;
+    bool linearFrame;
+} settings;
+
+const int HISTOGRAM = 0;
+
+vec3 encodeSrgb(vec3 linear) {
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+}
+
+void count(int bin) {
+    uint previous = atomicAdd(counts.bins[bin], 1);
+    atomicMax(counts.peak, previous + 1);
+}
+
+void main() {
+    ivec2 coord = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 size = imageSize(frame);
+    if (any(greaterThanEqual(coord, size))) {
+        return;
+    }
+
+    vec3 color = clamp(imageLoad(frame, coord).rgb, 0.0, 1.0);
+    if (settings.linearFrame) {
+        color = encodeSrgb(color);
+    }
+    float luminance = dot(color, vec3(0.2126, 0.7152, 0.0722));
+
+    if (settings.mode == HISTOGRAM) {
+        ivec4 levels = ivec4(vec4(color, luminance) * float(HISTOGRAM_LEVELS - 1) + 0.5);
+        for (int channel = 0; channel < 4; channel++) {
+            count(channel * HISTOGRAM_LEVELS + levels[channel]);
+        }
+    } else {
+        int column = coord.x * WAVEFORM_COLUMNS / size.x;
+        int level = int(luminance * float(WAVEFORM_LEVELS - 1) + 0.5);
+        count(column * WAVEFORM_LEVELS + level);
+    }
+}
@@ -0,0 +1,65 @@
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(binding = 0, rgba16f) uniform image2D frame;
+
+// Must match the sizes in scope.rs
+const int HISTOGRAM_LEVELS = 256;
+const int WAVEFORM_LEVELS = 128;
+const ivec2 PANEL_SIZE = ivec2(256, 128);
+const int MARGIN = 16;
+
+layout(std430, binding = 1) readonly buffer Counts {
+    uint peak;
+    uint bins[];
+} counts;
+
+layout(push_constant) uniform Settings {
+    int mode;
+    bool linearFrame;
+} settings;
+
+const int HISTOGRAM = 0;
+
+// Log scale so that sparse levels still show up next to a large flat background
+float scaled(uint count) {
+    return log(1.0 + float(count)) / log(1.0 + float(max(counts.peak, 1)));
+}
+
+void main() {
+    ivec2 panelCoord = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 size = imageSize(frame);
+    ivec2 coord = ivec2(MARGIN, size.y - MARGIN - PANEL_SIZE.y) + panelCoord;
+    if (any(greaterThanEqual(panelCoord, PANEL_SIZE)) || any(lessThan(coord, ivec2(0)))
+            || any(greaterThanEqual(coord, size))) {
+        return;
+    }
+
+    // Height of this pixel above the bottom of the panel, from 0 to 1
+    float height = float(PANEL_SIZE.y - 1 - panelCoord.y) / float(PANEL_SIZE.y - 1);
+    vec3 trace = vec3(0.0);
+    if (settings.mode == HISTOGRAM) {
+        int level = panelCoord.x * HISTOGRAM_LEVELS / PANEL_SIZE.x;
+        for (int channel = 0; channel < 3; channel++) {
+            if (scaled(counts.bins[channel * HISTOGRAM_LEVELS + level]) >= height) {
+                trace[channel] = 0.6;
+            }
+        }
+        if (scaled(counts.bins[3 * HISTOGRAM_LEVELS + level]) >= height) {
+            trace += vec3(0.3);
+        }
+    } else {
+        int level = int(height * float(WAVEFORM_LEVELS - 1) + 0.5);
+        float intensity = scaled(counts.bins[panelCoord.x * WAVEFORM_LEVELS + level]);
+        trace = vec3(0.3, 1.0, 0.4) * intensity;
+    }
+
+    // Darken what's behind the panel so the trace stays readable
+    vec3 background = imageLoad(frame, coord).rgb * 0.25;
+    vec3 color = clamp(trace, 0.0, 1.0);
+    if (settings.linearFrame) {
+        color = pow(color, vec3(2.2));
+    }
+    imageStore(frame, coord, vec4(background + color, 1.0));
+}
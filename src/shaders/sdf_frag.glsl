@@ -0,0 +1,115 @@
+#version 450
+
+vec3 encodeSrgb(vec3 linear) {
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+}
+
+vec3 decodeSrgb(vec3 encoded) {
+    vec3 low = encoded / 12.92;
+    vec3 high = pow((encoded + 0.055) / 1.055, vec3(2.4));
+    return mix(high, low, lessThanEqual(encoded, vec3(0.04045)));
+}
+
+// Matches `sdf::Kind`
+const int KIND_GLYPH = 0;
+const int KIND_ROUNDED_RECTANGLE = 1;
+const int KIND_CAPSULE = 2;
+// Followed by the kinds of the other images, `KIND_IMAGE + image`
+const int KIND_IMAGE = 3;
+// Matches `sdf::MAX_IMAGES`
+const int MAX_IMAGES = 4;
+
+layout(push_constant) uniform Panel {
+    vec2 viewport;
+    float spread;
+    uint encodeSrgb;
+    // A bit for each image that holds sRGB encoded colour in a UNORM format
+    uint decodeImages;
+} panel;
+
+// Signed distances to the glyphs' edges in red, 0.5 on the edge and higher inside, see `sdf::Atlas`
+layout(binding = 0) uniform sampler2D atlas;
+// Images drawn in rounded rectangles, see `sdf::Layer::add_image`
+layout(binding = 1) uniform sampler2D images[MAX_IMAGES];
+
+layout(location = 0) in vec2 fragLocal;
+layout(location = 1) in vec2 fragTexel;
+// For glyphs the texels of the glyph's cell. For rounded rectangles and images the rectangle's centre in xy and
+// half size in zw, and for capsules the ends of the line, in pixels from the quad's corner.
+layout(location = 2) flat in vec4 fragShape;
+layout(location = 3) flat in vec4 fragFill;
+layout(location = 4) flat in vec4 fragOutline;
+layout(location = 5) flat in vec4 fragGlow;
+layout(location = 6) flat in vec4 fragParameters;
+
+layout(location = 0) out vec4 outColor;
+
+// The distance in pixels from the fragment to the edge of what it is part of, negative inside
+float edgeDistance() {
+    int kind = int(fragParameters.x);
+    float radius = fragParameters.w;
+    if (kind == KIND_ROUNDED_RECTANGLE || kind >= KIND_IMAGE) {
+        vec2 q = abs(fragLocal - fragShape.xy) - fragShape.zw + radius;
+        return length(max(q, 0.0)) + min(max(q.x, q.y), 0.0) - radius;
+    }
+    if (kind == KIND_CAPSULE) {
+        vec2 along = fragShape.zw - fragShape.xy;
+        float t = clamp(dot(fragLocal - fragShape.xy, along) / max(dot(along, along), 1e-6), 0.0, 1.0);
+        return length(fragLocal - fragShape.xy - along * t) - radius;
+    }
+    float distance = (0.5 - texture(atlas, fragTexel / vec2(textureSize(atlas, 0))).r) * 2.0 * panel.spread;
+    // How many texels a pixel spans, measured in screen space so glyphs stay sharp however they are scaled
+    float texelsPerPixel = max(length(fwidth(fragTexel)) * 0.70710678, 1e-4);
+    return distance / texelsPerPixel;
+}
+
+// The image's linear colour where the fragment is in its rectangle
+vec4 imageColor(int image) {
+    vec2 uv = (fragLocal - fragShape.xy + fragShape.zw) / max(2.0 * fragShape.zw, vec2(1e-4));
+    vec4 color = vec4(0.0);
+    // Indexed with constants, since the index isn't uniform across the draw
+    for (int i = 0; i < MAX_IMAGES; i++) {
+        if (i == image) {
+            color = textureLod(images[i], uv, 0.0);
+        }
+    }
+    if ((panel.decodeImages & (1u << uint(image))) != 0u) {
+        color.rgb = decodeSrgb(color.rgb);
+    }
+    return color;
+}
+
+// Straight alpha `top` over `bottom`
+vec4 over(vec4 top, vec4 bottom) {
+    float alpha = top.a + bottom.a * (1.0 - top.a);
+    vec3 color = (top.rgb * top.a + bottom.rgb * bottom.a * (1.0 - top.a)) / max(alpha, 1e-6);
+    return vec4(color, alpha);
+}
+
+void main() {
+    float distance = edgeDistance();
+    float outlineWidth = fragParameters.y;
+    float glowWidth = fragParameters.z;
+
+    // Each layer covers what is within half a pixel of its edge, for anti-aliasing
+    float fill = clamp(0.5 - distance, 0.0, 1.0);
+    float outline = clamp(0.5 - (distance - outlineWidth), 0.0, 1.0);
+    float glow = glowWidth > 0.0 ? 1.0 - smoothstep(outlineWidth, outlineWidth + glowWidth, distance) : 0.0;
+
+    // Images are tinted by the fill
+    int kind = int(fragParameters.x);
+    vec4 fillColor = kind >= KIND_IMAGE ? fragFill * imageColor(kind - KIND_IMAGE) : fragFill;
+
+    vec4 color = vec4(fragGlow.rgb, fragGlow.a * glow);
+    color = over(vec4(fragOutline.rgb, fragOutline.a * outline), color);
+    color = over(vec4(fillColor.rgb, fillColor.a * fill), color);
+    if (color.a <= 0.0) {
+        discard;
+    }
+    outColor = color;
+    if (panel.encodeSrgb != 0u) {
+        outColor.rgb = encodeSrgb(outColor.rgb);
+    }
+}
@@ -0,0 +1,45 @@
+#version 450
+
+// Matches `sdf::Panel`
+layout(push_constant) uniform Panel {
+    // The target's size in pixels
+    vec2 viewport;
+    // How many texels either side of a glyph's edge its distances reach
+    float spread;
+    // Set when rendering to a UNORM target, which doesn't encode linear colour to sRGB on write
+    uint encodeSrgb;
+    uint decodeImages;
+} panel;
+
+// Matches `sdf::Quad`, one per instance. The quad's top left corner and size in pixels, what its fragments
+// sample or measure, their colours, and x: how the quad is shaded, y: the outline's width, z: the glow's width and
+// w: the shape's radius.
+layout(location = 0) in vec4 inRect;
+layout(location = 1) in vec4 inShape;
+layout(location = 2) in vec4 inFill;
+layout(location = 3) in vec4 inOutline;
+layout(location = 4) in vec4 inGlow;
+layout(location = 5) in vec4 inParameters;
+
+// Where the fragment is in the quad in pixels, and in the atlas in texels for glyphs
+layout(location = 0) out vec2 fragLocal;
+layout(location = 1) out vec2 fragTexel;
+layout(location = 2) flat out vec4 fragShape;
+layout(location = 3) flat out vec4 fragFill;
+layout(location = 4) flat out vec4 fragOutline;
+layout(location = 5) flat out vec4 fragGlow;
+layout(location = 6) flat out vec4 fragParameters;
+
+void main() {
+    // Drawn as a strip of four vertices
+    vec2 corner = vec2(gl_VertexIndex & 1, (gl_VertexIndex >> 1) & 1);
+    vec2 pixel = inRect.xy + corner * inRect.zw;
+    gl_Position = vec4(pixel / panel.viewport * 2.0 - 1.0, 0.0, 1.0);
+    fragLocal = corner * inRect.zw;
+    fragTexel = inShape.xy + corner * inShape.zw;
+    fragShape = inShape;
+    fragFill = inFill;
+    fragOutline = inOutline;
+    fragGlow = inGlow;
+    fragParameters = inParameters;
+}
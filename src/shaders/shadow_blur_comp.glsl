@@ -0,0 +1,30 @@
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+// VSM's moments, blurred along one direction into the destination
+layout(binding = 0) uniform sampler2D source;
+layout(binding = 1, rgba32f) writeonly uniform image2D destination;
+
+// Matches `shadows::Blur`
+layout(push_constant) uniform Blur {
+    // A texel across or down
+    ivec2 direction;
+    // Texels either side averaged in
+    int radius;
+} blur;
+
+void main() {
+    ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 size = textureSize(source, 0);
+    if (any(greaterThanEqual(pixel, size))) {
+        return;
+    }
+
+    vec4 sum = vec4(0.0);
+    for (int i = -blur.radius; i <= blur.radius; i++) {
+        ivec2 texel = clamp(pixel + blur.direction * i, ivec2(0), size - 1);
+        sum += texelFetch(source, texel, 0);
+    }
+    imageStore(destination, pixel, sum / float(2 * blur.radius + 1));
+}
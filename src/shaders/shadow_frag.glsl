@@ -0,0 +1,44 @@
+#version 450
+
+// As in `frag.glsl`, so that masked materials' holes let the light through
+layout(constant_id = 1) const bool ALPHA_MASK = false;
+layout(constant_id = 2) const float ALPHA_CUTOFF = 0.5;
+layout(constant_id = 6) const bool HAS_TEX_COORD = true;
+
+// Matches the start of `UniformBufferObject` in `frag.glsl`, through the clipping planes, with the light's view
+// and projection
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+    vec4 audioBands[4];
+    vec4 clipPlanes[4];
+    vec4 clipCapColor;
+    uvec4 clipFlags;
+} ubo;
+
+layout(binding = 1) uniform sampler2D texSampler;
+
+layout(location = 1) in vec2 fragTexCoord;
+layout(location = 5) in vec3 fragScenePosition;
+
+// Only written for VSM, whose render pass has a colour target
+layout(location = 0) out vec4 outMoments;
+
+// Casters only write depth, except for VSM's moments: the depth and its square, widened by how steeply the depth
+// changes across the texel so that sloped surfaces don't shadow themselves
+void main() {
+    for (uint i = 0u; i < min(ubo.clipFlags.x, 4u); i++) {
+        if (dot(ubo.clipPlanes[i].xyz, fragScenePosition) < ubo.clipPlanes[i].w) {
+            discard;
+        }
+    }
+    if (ALPHA_MASK && HAS_TEX_COORD && texture(texSampler, fragTexCoord).a < ALPHA_CUTOFF) {
+        discard;
+    }
+
+    float depth = gl_FragCoord.z;
+    float dx = dFdx(depth);
+    float dy = dFdy(depth);
+    outMoments = vec4(depth, depth * depth + 0.25 * (dx * dx + dy * dy), 0.0, 0.0);
+}
@@ -0,0 +1,28 @@
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(binding = 0, rgba16f) uniform readonly image2D inputImage;
+layout(binding = 1, rgba16f) uniform writeonly image2D outputImage;
+
+const float STRENGTH = 0.5;
+
+vec3 load(ivec2 coord, ivec2 size) {
+    return imageLoad(inputImage, clamp(coord, ivec2(0), size - 1)).rgb;
+}
+
+void main() {
+    ivec2 coord = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 size = imageSize(inputImage);
+    if (any(greaterThanEqual(coord, size))) {
+        return;
+    }
+
+    // Unsharp mask: push each pixel away from the average of its neighbours
+    vec3 center = load(coord, size);
+    vec3 neighbours = load(coord + ivec2(1, 0), size) + load(coord - ivec2(1, 0), size)
+        + load(coord + ivec2(0, 1), size) + load(coord - ivec2(0, 1), size);
+    vec3 sharpened = center + STRENGTH * (4.0 * center - neighbours);
+
+    imageStore(outputImage, coord, vec4(max(sharpened, vec3(0.0)), 1.0));
+}
@@ -0,0 +1,11 @@
+#version 450
+
+// The left eye, rendered before the frame's render pass
+layout(binding = 0) uniform sampler2D leftEye;
+
+layout(location = 0) out vec4 outColor;
+
+// Anaglyphs only write the red channel, leaving green and blue to the right eye
+void main() {
+    outColor = vec4(texelFetch(leftEye, ivec2(gl_FragCoord.xy), 0).rgb, 1.0);
+}
@@ -0,0 +1,36 @@
+#version 450
+
+// Set when rendering to a UNORM swapchain, which doesn't encode linear colour to sRGB on write
+layout(constant_id = 4) const bool ENCODE_SRGB = false;
+
+vec3 encodeSrgb(vec3 linear) {
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+}
+
+layout(location = 0) in vec3 fragColor;
+layout(location = 1) in vec3 fragNormal;
+layout(location = 2) in vec2 fragCorner;
+
+layout(location = 0) out vec4 outColor;
+
+// Match frag.glsl
+const vec3 LIGHT_DIRECTION = normalize(vec3(0.5, 0.5, 1.0));
+const float AMBIENT = 0.2;
+
+void main() {
+    if (dot(fragCorner, fragCorner) > 1.0) {
+        discard;
+    }
+    vec3 lighting = vec3(1.0);
+    if (dot(fragNormal, fragNormal) > 1e-6) {
+        // Scans rarely agree on which way normals face, so both sides are lit
+        float diffuse = abs(dot(normalize(fragNormal), LIGHT_DIRECTION));
+        lighting = vec3(AMBIENT + (1.0 - AMBIENT) * diffuse);
+    }
+    outColor = vec4(fragColor * lighting, 1.0);
+    if (ENCODE_SRGB) {
+        outColor.rgb = encodeSrgb(outColor.rgb);
+    }
+}
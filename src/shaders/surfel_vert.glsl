@@ -0,0 +1,56 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+} ubo;
+
+layout(push_constant) uniform PushConstants {
+    // The draw's own transform, applied before the scene's
+    mat4 model;
+    // The radius of each disc in the draw's own space
+    float pointSize;
+} pc;
+
+// Matches `pointcloud::Point`, advanced once per surfel
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inColor;
+layout(location = 2) in vec3 inNormal;
+
+layout(location = 0) out vec3 fragColor;
+// Zero for surfels without a normal, which aren't lit
+layout(location = 1) out vec3 fragNormal;
+// Where the fragment is on the disc, from -1 to 1 across it
+layout(location = 2) out vec2 fragCorner;
+
+// Two triangles covering the square around the disc
+const vec2 CORNERS[6] = vec2[](
+    vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(1.0, 1.0),
+    vec2(1.0, 1.0), vec2(-1.0, 1.0), vec2(-1.0, -1.0)
+);
+
+void main() {
+    vec2 corner = CORNERS[gl_VertexIndex];
+    mat4 model = ubo.model * pc.model;
+    vec3 center = (model * vec4(inPosition, 1.0)).xyz;
+    // The transforms only scale uniformly, so any axis gives the scale
+    float radius = pc.pointSize * length(model[0].xyz);
+
+    vec3 normal = vec3(0.0);
+    // The rows of the view matrix's rotation are the camera's axes in world space
+    vec3 right = vec3(ubo.view[0][0], ubo.view[1][0], ubo.view[2][0]);
+    vec3 up = vec3(ubo.view[0][1], ubo.view[1][1], ubo.view[2][1]);
+    if (dot(inNormal, inNormal) > 1e-12) {
+        normal = normalize(mat3(model) * inNormal);
+        vec3 reference = abs(normal.z) < 0.99 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+        right = normalize(cross(normal, reference));
+        up = cross(normal, right);
+    }
+
+    vec3 position = center + (right * corner.x + up * corner.y) * radius;
+    gl_Position = ubo.proj * ubo.view * vec4(position, 1.0);
+    fragColor = inColor;
+    fragNormal = normal;
+    fragCorner = corner;
+}
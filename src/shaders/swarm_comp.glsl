@@ -0,0 +1,138 @@
+#version 450
+
+layout(local_size_x = 64) in;
+
+struct Agent {
+    // w is the agent's mass
+    vec4 position;
+    vec4 velocity;
+};
+
+// Matches `indirect::Object`. Only the transform is read, as per-instance vertex attributes.
+struct Instance {
+    mat4 transform;
+    vec4 sphere;
+    uvec4 command;
+};
+
+// Each step reads one state buffer and writes the other
+layout(std430, binding = 0) readonly buffer Source {
+    Agent source[];
+};
+layout(std430, binding = 1) writeonly buffer Destination {
+    Agent destination[];
+};
+layout(std430, binding = 2) writeonly buffer Instances {
+    Instance instances[];
+};
+
+layout(push_constant) uniform Step {
+    // xyz is the centre of the space the agents keep to, w its radius
+    vec4 region;
+    uint count;
+    uint behaviour;
+    float deltaTime;
+    // Length of each agent's mesh
+    float scale;
+} step;
+
+// Match `swarm::Behaviour`
+const uint FLOCKING = 0u;
+const uint GRAVITY = 1u;
+
+// Flocking agents steer towards the heading and centre of the agents within NEIGHBOUR_RADIUS, and away from
+// any closer than SEPARATION_RADIUS
+const float NEIGHBOUR_RADIUS = 0.1;
+const float SEPARATION_RADIUS = 0.03;
+const float ALIGNMENT = 1.0;
+const float COHESION = 2.0;
+const float SEPARATION = 0.01;
+// How hard agents that have left the region turn back into it
+const float BOUNDARY = 4.0;
+const float MIN_SPEED = 0.2;
+const float MAX_SPEED = 0.5;
+
+// Matches `swarm::GRAVITATIONAL_CONSTANT`
+const float G = 0.05;
+// Keeps agents that pass through each other from being flung out at huge speeds
+const float SOFTENING = 0.02;
+
+// Every invocation loads one agent of each tile, which the whole workgroup then reads from shared memory
+shared vec4 tilePositions[64];
+shared vec4 tileVelocities[64];
+
+void main() {
+    uint index = gl_GlobalInvocationID.x;
+    // Invocations past the end still load tiles for the rest of their workgroup
+    bool active = index < step.count;
+    Agent agent = active ? source[index] : Agent(vec4(0.0), vec4(0.0));
+    vec3 position = agent.position.xyz;
+    vec3 velocity = agent.velocity.xyz;
+
+    vec3 acceleration = vec3(0.0);
+    vec3 alignment = vec3(0.0);
+    vec3 cohesion = vec3(0.0);
+    vec3 separation = vec3(0.0);
+    float neighbours = 0.0;
+    for (uint tile = 0u; tile < step.count; tile += 64u) {
+        uint other = tile + gl_LocalInvocationID.x;
+        // Agents past the end load with no mass, so they pull on nothing
+        tilePositions[gl_LocalInvocationID.x] = other < step.count ? source[other].position : vec4(0.0);
+        tileVelocities[gl_LocalInvocationID.x] = other < step.count ? source[other].velocity : vec4(0.0);
+        barrier();
+
+        uint tileSize = min(64u, step.count - tile);
+        for (uint i = 0u; i < tileSize; i++) {
+            vec3 offset = tilePositions[i].xyz - position;
+            float distanceSquared = dot(offset, offset);
+            if (step.behaviour == GRAVITY) {
+                // An agent's pull on itself is zero since the offset is
+                float softened = distanceSquared + SOFTENING * SOFTENING;
+                acceleration += G * tilePositions[i].w * offset * inversesqrt(softened * softened * softened);
+            } else if (tile + i != index && distanceSquared < NEIGHBOUR_RADIUS * NEIGHBOUR_RADIUS) {
+                neighbours += 1.0;
+                alignment += tileVelocities[i].xyz;
+                cohesion += offset;
+                if (distanceSquared < SEPARATION_RADIUS * SEPARATION_RADIUS) {
+                    separation -= offset / max(distanceSquared, 1e-6);
+                }
+            }
+        }
+        barrier();
+    }
+
+    if (!active) {
+        return;
+    }
+
+    if (step.behaviour == FLOCKING) {
+        if (neighbours > 0.0) {
+            acceleration += ALIGNMENT * (alignment / neighbours - velocity);
+            acceleration += COHESION * cohesion / neighbours;
+            acceleration += SEPARATION * separation;
+        }
+        vec3 fromCentre = position - step.region.xyz;
+        float outside = length(fromCentre) - step.region.w;
+        if (outside > 0.0) {
+            acceleration -= BOUNDARY * outside * normalize(fromCentre);
+        }
+    }
+    velocity += acceleration * step.deltaTime;
+    float speed = length(velocity);
+    if (step.behaviour == FLOCKING && speed > 1e-6) {
+        velocity *= clamp(speed, MIN_SPEED, MAX_SPEED) / speed;
+    }
+    position += velocity * step.deltaTime;
+    destination[index] = Agent(vec4(position, agent.position.w), vec4(velocity, 0.0));
+
+    // Agents' meshes point along +X with +Z up, turned here to face the way they are heading
+    vec3 forward = speed > 1e-6 ? normalize(velocity) : vec3(1.0, 0.0, 0.0);
+    vec3 up = abs(forward.z) > 0.99 ? vec3(1.0, 0.0, 0.0) : vec3(0.0, 0.0, 1.0);
+    vec3 side = normalize(cross(up, forward));
+    vec3 top = cross(forward, side);
+    instances[index].transform = mat4(
+        vec4(forward * step.scale, 0.0),
+        vec4(side * step.scale, 0.0),
+        vec4(top * step.scale, 0.0),
+        vec4(position, 1.0));
+}
@@ -0,0 +1,28 @@
+#version 450
+
+vec3 encodeSrgb(vec3 linear) {
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+}
+
+// The target shown in the thumbnail
+layout(binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform Panel {
+    // The thumbnail's top left corner and size in pixels, matching the viewport it is drawn with
+    vec2 offset;
+    vec2 size;
+    // Set when rendering to a UNORM swapchain, which doesn't encode linear colour to sRGB on write
+    uint encodeSrgb;
+} panel;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    vec2 uv = (gl_FragCoord.xy - panel.offset) / panel.size;
+    outColor = vec4(texture(source, uv).rgb, 1.0);
+    if (panel.encodeSrgb != 0u) {
+        outColor.rgb = encodeSrgb(outColor.rgb);
+    }
+}
@@ -0,0 +1,64 @@
+#version 450
+
+// Set when rendering to a UNORM swapchain, which doesn't encode linear colour to sRGB on write
+layout(constant_id = 4) const bool ENCODE_SRGB = false;
+// How vertex colours combine with the texture: 0 ignores them and 1 multiplies the texture by them
+layout(constant_id = 5) const int VERTEX_COLOR_MODE = 0;
+// Cleared for meshes without texture coordinates, which are drawn untextured
+layout(constant_id = 6) const bool HAS_TEX_COORD = true;
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+    vec4 audioBands[4];
+    // Matches `clipping::Uniforms`. Points where dot(xyz, position) < w are cut away, in the scene's space.
+    vec4 clipPlanes[4];
+    vec4 clipCapColor;
+    // x is how many planes are used
+    uvec4 clipFlags;
+    // Matches `sky::Uniforms`. xyz is the direction towards the light, and the colours are in rgb.
+    vec4 lightDirection;
+    vec4 lightColor;
+    vec4 ambientColor;
+} ubo;
+
+layout(binding = 1) uniform sampler2D texSampler;
+
+layout(location = 0) in vec3 fragColor;
+layout(location = 1) in vec2 fragTexCoord;
+layout(location = 2) in vec3 fragNormal;
+layout(location = 5) in vec3 fragScenePosition;
+layout(location = 6) in float fragOpacity;
+
+layout(location = 0) out vec4 outColor;
+
+vec3 encodeSrgb(vec3 linear) {
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+}
+
+// Ghosts are lit by the light and the ambient light alone, without the lightmap, occlusion or capsules, since
+// they are faint and gone a moment later
+void main() {
+    for (uint i = 0u; i < min(ubo.clipFlags.x, 4u); i++) {
+        if (dot(ubo.clipPlanes[i].xyz, fragScenePosition) < ubo.clipPlanes[i].w) {
+            discard;
+        }
+    }
+
+    vec4 texel = vec4(1.0);
+    if (HAS_TEX_COORD) {
+        texel = texture(texSampler, fragTexCoord);
+    }
+    if (VERTEX_COLOR_MODE == 1) {
+        texel.rgb *= fragColor;
+    }
+    float diffuse = max(dot(normalize(fragNormal), ubo.lightDirection.xyz), 0.0);
+    vec3 lighting = ubo.ambientColor.rgb + ubo.lightColor.rgb * diffuse;
+    outColor = vec4(texel.rgb * lighting, texel.a * fragOpacity);
+    if (ENCODE_SRGB) {
+        outColor.rgb = encodeSrgb(outColor.rgb);
+    }
+}
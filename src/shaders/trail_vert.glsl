@@ -0,0 +1,33 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+} ubo;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inColor;
+layout(location = 2) in vec2 inTexCoord;
+layout(location = 3) in vec3 inNormal;
+// Where the draw was when the ghost was left behind, before the scene's model matrix, and how opaque the ghost
+// is. Matches `trails::Ghost`.
+layout(location = 4) in mat4 inTransform;
+layout(location = 10) in float inOpacity;
+
+layout(location = 0) out vec3 fragColor;
+layout(location = 1) out vec2 fragTexCoord;
+layout(location = 2) out vec3 fragNormal;
+layout(location = 5) out vec3 fragScenePosition;
+layout(location = 6) out float fragOpacity;
+
+void main() {
+    mat4 model = ubo.model * inTransform;
+    gl_Position = ubo.proj * ubo.view * model * vec4(inPosition, 1.0);
+    fragScenePosition = (inTransform * vec4(inPosition, 1.0)).xyz;
+    fragColor = inColor;
+    fragTexCoord = inTexCoord;
+    fragOpacity = inOpacity;
+    // The inverse transpose keeps normals perpendicular to the surface under non-uniform scaling
+    fragNormal = transpose(inverse(mat3(model))) * inNormal;
+}
@@ -0,0 +1,37 @@
+#version 450
+
+// The outgoing scene, rendered before the frame's render pass
+layout(binding = 0) uniform sampler2D outgoing;
+
+layout(push_constant) uniform Blend {
+    vec2 resolution;
+    // How far through the transition it is, from 0 to 1
+    float progress;
+    // 0 fades through black, 1 crossfades and 2 wipes, matching `transition::Effect`
+    uint effect;
+} blend;
+
+layout(location = 0) out vec4 outColor;
+
+// Half the width of the wipe's soft edge, as a fraction of the screen's width
+const float WIPE_EDGE = 0.02;
+
+void main() {
+    vec2 uv = gl_FragCoord.xy / blend.resolution;
+    vec3 color = texelFetch(outgoing, ivec2(gl_FragCoord.xy), 0).rgb;
+    float progress = blend.progress;
+
+    // Alpha is how much of the outgoing scene covers the incoming one
+    if (blend.effect == 0u) {
+        if (progress < 0.5) {
+            outColor = vec4(color * (1.0 - 2.0 * progress), 1.0);
+        } else {
+            outColor = vec4(0.0, 0.0, 0.0, 2.0 - 2.0 * progress);
+        }
+    } else if (blend.effect == 1u) {
+        outColor = vec4(color, 1.0 - progress);
+    } else {
+        float edge = mix(-WIPE_EDGE, 1.0 + WIPE_EDGE, progress);
+        outColor = vec4(color, smoothstep(edge - WIPE_EDGE, edge + WIPE_EDGE, uv.x));
+    }
+}
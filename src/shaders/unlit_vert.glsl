@@ -0,0 +1,25 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+} ubo;
+
+layout(push_constant) uniform PushConstants {
+    // The draw's own transform, applied before the scene's
+    mat4 model;
+    float pointSize;
+} pc;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inColor;
+
+layout(location = 0) out vec3 fragColor;
+
+void main() {
+    gl_Position = ubo.proj * ubo.view * ubo.model * pc.model * vec4(inPosition, 1.0);
+    // Only read when drawing with the point list topology
+    gl_PointSize = pc.pointSize;
+    fragColor = inColor;
+}
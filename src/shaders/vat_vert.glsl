@@ -0,0 +1,60 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+} ubo;
+
+layout(push_constant) uniform PushConstants {
+    // The draw's own transform, applied before the scene's
+    mat4 model;
+    float pointSize;
+    // The frame to show, blending towards the next by its fraction
+    float animationFrame;
+} pc;
+
+// A column per vertex. The top half of the rows holds each frame's positions and the bottom half its normals,
+// as laid out by `vat::Animation`.
+layout(set = 1, binding = 0) uniform sampler2D animation;
+
+// Positions and normals come from the animation, the rest of the attributes from the vertex buffer
+layout(location = 1) in vec3 inColor;
+layout(location = 2) in vec2 inTexCoord;
+layout(location = 8) in vec2 inLightmapCoord;
+layout(location = 9) in float inOcclusion;
+
+layout(location = 0) out vec3 fragColor;
+layout(location = 1) out vec2 fragTexCoord;
+layout(location = 2) out vec3 fragNormal;
+layout(location = 3) out vec2 fragLightmapCoord;
+layout(location = 4) out float fragOcclusion;
+// Before the scene's model matrix, where clipping planes are placed
+layout(location = 5) out vec3 fragScenePosition;
+
+void main() {
+    int frameCount = textureSize(animation, 0).y / 2;
+    int first = int(floor(pc.animationFrame)) % frameCount;
+    // Looping animations blend their last frame back into the first
+    int second = (first + 1) % frameCount;
+    float blend = fract(pc.animationFrame);
+
+    vec3 position = mix(
+        texelFetch(animation, ivec2(gl_VertexIndex, first), 0).xyz,
+        texelFetch(animation, ivec2(gl_VertexIndex, second), 0).xyz,
+        blend);
+    vec3 normal = mix(
+        texelFetch(animation, ivec2(gl_VertexIndex, frameCount + first), 0).xyz,
+        texelFetch(animation, ivec2(gl_VertexIndex, frameCount + second), 0).xyz,
+        blend);
+
+    mat4 model = ubo.model * pc.model;
+    gl_Position = ubo.proj * ubo.view * model * vec4(position, 1.0);
+    fragScenePosition = (pc.model * vec4(position, 1.0)).xyz;
+    fragColor = inColor;
+    fragTexCoord = inTexCoord;
+    fragLightmapCoord = inLightmapCoord;
+    fragOcclusion = inOcclusion;
+    // The inverse transpose keeps normals perpendicular to the surface under non-uniform scaling
+    fragNormal = transpose(inverse(mat3(model))) * normal;
+}
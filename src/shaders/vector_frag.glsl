@@ -0,0 +1,27 @@
+#version 450
+
+vec3 encodeSrgb(vec3 linear) {
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+}
+
+// Matches `sdf::Panel`
+layout(push_constant) uniform Panel {
+    vec2 viewport;
+    float spread;
+    uint encodeSrgb;
+    uint decodeImages;
+} panel;
+
+// Linear with straight alpha
+layout(location = 0) in vec4 fragColor;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    outColor = fragColor;
+    if (panel.encodeSrgb != 0u) {
+        outColor.rgb = encodeSrgb(outColor.rgb);
+    }
+}
@@ -0,0 +1,22 @@
+#version 450
+
+// Matches `sdf::Panel`
+layout(push_constant) uniform Panel {
+    // The target's size in pixels
+    vec2 viewport;
+    float spread;
+    // Set when rendering to a UNORM target, which doesn't encode linear colour to sRGB on write
+    uint encodeSrgb;
+    uint decodeImages;
+} panel;
+
+// Matches `sdf::Vertex`, in pixels from the target's top left
+layout(location = 0) in vec2 inPosition;
+layout(location = 1) in vec4 inColor;
+
+layout(location = 0) out vec4 fragColor;
+
+void main() {
+    gl_Position = vec4(inPosition / panel.viewport * 2.0 - 1.0, 0.0, 1.0);
+    fragColor = inColor;
+}
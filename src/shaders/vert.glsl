@@ -6,15 +6,37 @@ layout(binding = 0) uniform UniformBufferObject {
     mat4 proj;
 } ubo;
 
+layout(push_constant) uniform PushConstants {
+    // The draw's own transform, applied before the scene's
+    mat4 model;
+    float pointSize;
+} pc;
+
 layout(location = 0) in vec3 inPosition;
 layout(location = 1) in vec3 inColor;
 layout(location = 2) in vec2 inTexCoord;
+layout(location = 3) in vec3 inNormal;
+// Where the vertex is in the scene's lightmap, zero for meshes that aren't lightmapped
+layout(location = 8) in vec2 inLightmapCoord;
+// How much ambient light reaches the vertex, one for meshes without baked occlusion
+layout(location = 9) in float inOcclusion;
 
 layout(location = 0) out vec3 fragColor;
 layout(location = 1) out vec2 fragTexCoord;
+layout(location = 2) out vec3 fragNormal;
+layout(location = 3) out vec2 fragLightmapCoord;
+layout(location = 4) out float fragOcclusion;
+// Before the scene's model matrix, where clipping planes are placed
+layout(location = 5) out vec3 fragScenePosition;
 
 void main() {
-    gl_Position = ubo.proj * ubo.view * ubo.model * vec4(inPosition, 1.0);
+    mat4 model = ubo.model * pc.model;
+    gl_Position = ubo.proj * ubo.view * model * vec4(inPosition, 1.0);
+    fragScenePosition = (pc.model * vec4(inPosition, 1.0)).xyz;
     fragColor = inColor;
     fragTexCoord = inTexCoord;
-}
\ No newline at end of file
+    fragLightmapCoord = inLightmapCoord;
+    fragOcclusion = inOcclusion;
+    // The inverse transpose keeps normals perpendicular to the surface under non-uniform scaling
+    fragNormal = transpose(inverse(mat3(model))) * inNormal;
+}
@@ -0,0 +1,109 @@
+#version 450
+
+// Matches `volume::Uniforms`
+layout(std140, binding = 0) uniform Volume {
+    mat4 inverseViewProjection;
+    // Maps world space to the unit cube the volume fills
+    mat4 worldToVolume;
+    // xy is the size of the target in pixels
+    vec4 viewport;
+    // x is the distance between samples and y the distance the transfer function's opacities are for, both in the
+    // volume's space. zw are the values mapped to the ends of the transfer function.
+    vec4 sampling;
+    // Points where dot(xyz, point) < w are cut away
+    vec4 planes[4];
+    // x is how many planes are used, y is set when the attachment is UNORM and colours must be sRGB encoded
+    uvec4 flags;
+    vec4 transfer[256];
+} volume;
+
+layout(binding = 1) uniform sampler3D data;
+
+layout(location = 0) out vec4 outColor;
+
+// Keeps rays through huge volumes from stalling the GPU
+const int MAX_STEPS = 4096;
+// Rays stop once they are this opaque, since nothing behind would show
+const float OPAQUE = 0.99;
+// Keeps the slab test away from dividing by zero for rays along the volume's faces
+const float EPSILON = 1e-7;
+// Rays end just short of the far plane, which has no position when it is infinitely far away
+const float FAR_DEPTH = 0.999999;
+
+vec3 encodeSrgb(vec3 linear) {
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+}
+
+vec4 transferFunction(float value) {
+    float position = clamp(value, 0.0, 1.0) * 255.0;
+    int below = int(floor(position));
+    int above = min(below + 1, 255);
+    return mix(volume.transfer[below], volume.transfer[above], position - float(below));
+}
+
+void main() {
+    // The ray through the pixel from the near plane to the far plane, in the volume's space, at t from 0 to 1.
+    // Starting on the near plane rather than at the camera keeps the rays parallel in orthographic views.
+    vec2 ndc = gl_FragCoord.xy / volume.viewport.xy * 2.0 - 1.0;
+    vec4 near = volume.inverseViewProjection * vec4(ndc, 0.0, 1.0);
+    vec4 far = volume.inverseViewProjection * vec4(ndc, FAR_DEPTH, 1.0);
+    vec3 origin = (volume.worldToVolume * vec4(near.xyz / near.w, 1.0)).xyz;
+    vec3 end = (volume.worldToVolume * vec4(far.xyz / far.w, 1.0)).xyz;
+    vec3 direction = end - origin;
+    direction = mix(direction, vec3(EPSILON), lessThan(abs(direction), vec3(EPSILON)));
+
+    vec3 toLow = -origin / direction;
+    vec3 toHigh = (vec3(1.0) - origin) / direction;
+    vec3 entries = min(toLow, toHigh);
+    vec3 exits = max(toLow, toHigh);
+    float enter = max(max(entries.x, entries.y), max(entries.z, 0.0));
+    float exit = min(min(exits.x, exits.y), min(exits.z, 1.0));
+
+    // Slicing planes shorten the ray rather than rejecting samples, so no steps are spent on the parts cut away
+    for (uint i = 0u; i < min(volume.flags.x, 4u); i++) {
+        vec4 plane = volume.planes[i];
+        float along = dot(plane.xyz, direction);
+        float inside = dot(plane.xyz, origin) - plane.w;
+        if (abs(along) < EPSILON) {
+            if (inside < 0.0) {
+                discard;
+            }
+        } else if (along > 0.0) {
+            enter = max(enter, -inside / along);
+        } else {
+            exit = min(exit, -inside / along);
+        }
+    }
+    if (enter >= exit) {
+        discard;
+    }
+
+    float stepLength = volume.sampling.x / length(direction);
+    // Opacity correction keeps the volume looking the same however finely it is sampled
+    float correction = volume.sampling.x / volume.sampling.y;
+    float window = max(volume.sampling.w - volume.sampling.z, 1e-6);
+    vec3 color = vec3(0.0);
+    float alpha = 0.0;
+    float t = enter + 0.5 * stepLength;
+    for (int i = 0; i < MAX_STEPS && t < exit && alpha < OPAQUE; i++) {
+        float value = texture(data, origin + t * direction).r;
+        vec4 sampled = transferFunction((value - volume.sampling.z) / window);
+        float opacity = 1.0 - pow(1.0 - clamp(sampled.a, 0.0, 0.9999), correction);
+        // Composited front to back, each sample showing through what is in front of it
+        color += (1.0 - alpha) * opacity * sampled.rgb;
+        alpha += (1.0 - alpha) * opacity;
+        t += stepLength;
+    }
+    if (alpha <= 0.0) {
+        discard;
+    }
+
+    // Blended over the scene by alpha, so the colour isn't premultiplied
+    color /= alpha;
+    if (volume.flags.y != 0u) {
+        color = encodeSrgb(color);
+    }
+    outColor = vec4(color, alpha);
+}
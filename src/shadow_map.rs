@@ -0,0 +1,642 @@
+use std::mem::size_of;
+use std::path::Path;
+
+use ash::vk;
+
+use crate::features::SwapchainContext;
+use crate::{
+    pipeline, postprocess, schedule, shadows, util, HelloTriangleApplication, ImageDescription,
+};
+
+/// The views the scene's descriptor sets sample the shadow map through, the depth at binding 3 and VSM's
+/// moments at binding 4. Without a shadow map both are some other texture, which the shaders don't read.
+#[derive(Clone, Copy, Debug)]
+pub struct Descriptors {
+    pub depth: (vk::ImageView, vk::Sampler),
+    pub moments: (vk::ImageView, vk::Sampler),
+}
+
+impl Descriptors {
+    /// Both bindings pointing at `view`, for descriptor sets drawn without a shadow map
+    pub fn none(view: vk::ImageView, sampler: vk::Sampler) -> Self {
+        Self {
+            depth: (view, sampler),
+            moments: (view, sampler),
+        }
+    }
+}
+
+/// The formats the map is made with, from `formats::Request::shadow_depth` and `formats::Request::shadow_moments`
+#[derive(Clone, Copy, Debug)]
+pub struct Formats {
+    pub depth: vk::Format,
+    /// Only for `shadows::Filter::Vsm`
+    pub moments: Option<vk::Format>,
+    /// Whether the moments' format can be filtered linearly, without which they are fetched like the depth
+    pub filter_moments: bool,
+}
+
+/// Matches the push constants in `shadow_blur_comp.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Blur {
+    direction: [i32; 2],
+    radius: i32,
+}
+
+impl Blur {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+}
+
+/// VSM's colour target and the separable blur over it, a horizontal pass into `blurred` and a vertical pass back
+struct Moments {
+    image: (vk::Image, vk::DeviceMemory, vk::ImageView),
+    blurred: (vk::Image, vk::DeviceMemory, vk::ImageView),
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    /// Reading the moments and writing the blurred image, then the other way round
+    descriptor_sets: [vk::DescriptorSet; 2],
+    layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+}
+
+/// A shadow map for the scene's directional light, `sky::Light`. Every frame, before the minimap and the
+/// frame's render pass, the draws that cast shadows, see `pipeline::Config::shadow_caster`, are drawn from the
+/// light through an orthographic projection covering `shadows::Settings::extent` around the camera's target into
+/// a depth target of their own. `frag.glsl` then darkens the light on surfaces the map shows behind something,
+/// filtered as `shadows::Settings::filter` says, the way capsule shadows darken it. Lightmapped meshes keep their
+/// baked shadows.
+///
+/// The map's render pass has no colour unless it's VSM's, so it can't draw with the scene's pipelines. It has
+/// its own `pipeline::Variants`, which share the renderer's layouts and pipeline cache.
+pub struct ShadowMap {
+    pub settings: shadows::Settings,
+    /// Uniform buffers and descriptor sets for each swapchain image, with the light's view and projection
+    pub view: schedule::View,
+    pub pipelines: pipeline::Variants,
+    render_pass: vk::RenderPass,
+    depth: (vk::Image, vk::DeviceMemory, vk::ImageView),
+    moments: Option<Moments>,
+    framebuffer: vk::Framebuffer,
+    /// Depths are fetched without filtering, since they can't be filtered before they are compared
+    nearest_sampler: vk::Sampler,
+    moments_sampler: vk::Sampler,
+}
+
+impl ShadowMap {
+    /// `pipelines` makes the casters' pipeline variants for the map's target. `formats.moments` must be given
+    /// for `shadows::Filter::Vsm`.
+    pub fn new(
+        context: &SwapchainContext,
+        settings: shadows::Settings,
+        view: schedule::View,
+        formats: Formats,
+        pipelines: impl FnOnce(pipeline::Target) -> pipeline::Variants,
+    ) -> Self {
+        let device = context.device;
+        let resolution = settings.resolution.max(1);
+        let moments_format = match settings.filter.uses_moments() {
+            true => Some(formats.moments.expect("A format for VSM's moments")),
+            false => None,
+        };
+        let render_pass = create_render_pass(device, formats.depth, moments_format);
+        let create_attachment = |format, usage, aspect| {
+            let (image, memory) = HelloTriangleApplication::create_image(
+                device,
+                &ImageDescription {
+                    width: resolution,
+                    height: resolution,
+                    format,
+                    tiling: vk::ImageTiling::OPTIMAL,
+                    usage,
+                    memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                },
+                context.device_memory_properties,
+            );
+            let view = HelloTriangleApplication::create_image_view(device, image, format, aspect);
+            (image, memory, view)
+        };
+        let depth = create_attachment(
+            formats.depth,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            vk::ImageAspectFlags::DEPTH,
+        );
+        let nearest_sampler = create_sampler(device, vk::Filter::NEAREST);
+        let moments_sampler = create_sampler(
+            device,
+            match formats.filter_moments {
+                true => vk::Filter::LINEAR,
+                false => vk::Filter::NEAREST,
+            },
+        );
+        let moments = moments_format.map(|format| {
+            let image = create_attachment(
+                format,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | vk::ImageUsageFlags::SAMPLED
+                    | vk::ImageUsageFlags::STORAGE,
+                vk::ImageAspectFlags::COLOR,
+            );
+            let blurred = create_attachment(
+                format,
+                vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::STORAGE,
+                vk::ImageAspectFlags::COLOR,
+            );
+            create_blur(device, image, blurred, nearest_sampler)
+        });
+
+        let attachments: Vec<vk::ImageView> = moments
+            .iter()
+            .map(|moments| moments.image.2)
+            .chain(std::iter::once(depth.2))
+            .collect();
+        let framebuffer = unsafe {
+            device
+                .create_framebuffer(
+                    &vk::FramebufferCreateInfo::builder()
+                        .render_pass(render_pass)
+                        .attachments(&attachments)
+                        .width(resolution)
+                        .height(resolution)
+                        .layers(1),
+                    None,
+                )
+                .expect("Shadow map frame buffer")
+        };
+        let pipelines = pipelines(pipeline::Target {
+            render_pass,
+            samples: vk::SampleCountFlags::TYPE_1,
+            extent: vk::Extent2D {
+                width: resolution,
+                height: resolution,
+            },
+            encode_srgb: false,
+            color: moments.is_some(),
+        });
+
+        Self {
+            settings,
+            view,
+            pipelines,
+            render_pass,
+            depth,
+            moments,
+            framebuffer,
+            nearest_sampler,
+            moments_sampler,
+        }
+    }
+
+    /// The bias casters are drawn with
+    pub fn depth_bias(&self) -> pipeline::DepthBias {
+        pipeline::DepthBias::new(self.settings.constant_bias, self.settings.slope_bias)
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        let resolution = self.settings.resolution.max(1);
+        vk::Extent2D {
+            width: resolution,
+            height: resolution,
+        }
+    }
+
+    /// The map's depth and moments for the scene's descriptor sets, in the `SHADER_READ_ONLY_OPTIMAL` layout
+    /// once the map has been drawn. Without moments the depth stands in for them.
+    pub fn descriptors(&self) -> Descriptors {
+        let depth = (self.depth.2, self.nearest_sampler);
+        Descriptors {
+            depth,
+            moments: self
+                .moments
+                .as_ref()
+                .map_or(depth, |moments| (moments.image.2, self.moments_sampler)),
+        }
+    }
+
+    /// Records a render pass that draws the map, followed by VSM's blur. `record` is given the command buffer
+    /// inside the render pass and records the casters' draws. Must be called outside a render pass, before
+    /// anything that shades with the map.
+    pub fn record(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        record: impl FnOnce(vk::CommandBuffer),
+    ) {
+        let depth = vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            },
+        };
+        // Nothing in front of the far plane, whose depth and its square are both 1
+        let moments = vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: [1.0, 1.0, 0.0, 0.0],
+            },
+        };
+        let clear_values = [moments, depth];
+        let clear_values = match self.moments {
+            Some(_) => &clear_values[..],
+            None => &clear_values[1..],
+        };
+        let render_pass_bi = vk::RenderPassBeginInfo::builder()
+            .render_pass(self.render_pass)
+            .framebuffer(self.framebuffer)
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: self.extent(),
+            })
+            .clear_values(clear_values);
+        unsafe {
+            device.cmd_begin_render_pass(
+                command_buffer,
+                &render_pass_bi,
+                vk::SubpassContents::INLINE,
+            )
+        };
+        record(command_buffer);
+        unsafe { device.cmd_end_render_pass(command_buffer) };
+        if let (Some(moments), shadows::Filter::Vsm { blur }) =
+            (self.moments.as_ref(), self.settings.filter)
+        {
+            if blur > 0 {
+                self.record_blur(device, command_buffer, moments, blur);
+            }
+        }
+    }
+
+    /// Records blurring the moments `radius` texels either side, across and then down
+    fn record_blur(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        moments: &Moments,
+        radius: u32,
+    ) {
+        let barrier = |image: vk::Image,
+                       old: vk::ImageLayout,
+                       new: vk::ImageLayout,
+                       src_access: vk::AccessFlags,
+                       dst_access: vk::AccessFlags| {
+            vk::ImageMemoryBarrier::builder()
+                .old_layout(old)
+                .new_layout(new)
+                .src_access_mask(src_access)
+                .dst_access_mask(dst_access)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(postprocess::color_subresource_range())
+                .build()
+        };
+        let groups = self
+            .settings
+            .resolution
+            .max(1)
+            .div_ceil(postprocess::WORKGROUP_SIZE);
+        let dispatch = |descriptor_set: vk::DescriptorSet, direction: [i32; 2]| unsafe {
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                moments.layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            let blur = Blur {
+                direction,
+                radius: radius as i32,
+            };
+            device.cmd_push_constants(
+                command_buffer,
+                moments.layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                blur.as_bytes(),
+            );
+            device.cmd_dispatch(command_buffer, groups, groups, 1);
+        };
+        let (image, blurred) = (moments.image.0, moments.blurred.0);
+
+        unsafe {
+            // The render pass leaves the moments ready to read, and last frame's vertical pass has finished
+            // reading the blurred image
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier(
+                    blurred,
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::GENERAL,
+                    vk::AccessFlags::empty(),
+                    vk::AccessFlags::SHADER_WRITE,
+                )],
+            );
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                moments.pipeline,
+            );
+            dispatch(moments.descriptor_sets[0], [1, 0]);
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[
+                    barrier(
+                        blurred,
+                        vk::ImageLayout::GENERAL,
+                        vk::ImageLayout::GENERAL,
+                        vk::AccessFlags::SHADER_WRITE,
+                        vk::AccessFlags::SHADER_READ,
+                    ),
+                    barrier(
+                        image,
+                        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                        vk::ImageLayout::GENERAL,
+                        vk::AccessFlags::SHADER_READ,
+                        vk::AccessFlags::SHADER_WRITE,
+                    ),
+                ],
+            );
+            dispatch(moments.descriptor_sets[1], [0, 1]);
+            // Shaded with next
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier(
+                    image,
+                    vk::ImageLayout::GENERAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    vk::AccessFlags::SHADER_WRITE,
+                    vk::AccessFlags::SHADER_READ,
+                )],
+            );
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            device.destroy_framebuffer(self.framebuffer, None);
+            let mut images = vec![self.depth];
+            if let Some(moments) = self.moments.take() {
+                images.extend([moments.image, moments.blurred]);
+                device.destroy_pipeline(moments.pipeline, None);
+                device.destroy_pipeline_layout(moments.layout, None);
+                device.destroy_descriptor_pool(moments.descriptor_pool, None);
+                device.destroy_descriptor_set_layout(moments.descriptor_set_layout, None);
+            }
+            for &(image, memory, view) in images.iter() {
+                device.destroy_image_view(view, None);
+                device.destroy_image(image, None);
+                device.free_memory(memory, None);
+            }
+            device.destroy_sampler(self.nearest_sampler, None);
+            device.destroy_sampler(self.moments_sampler, None);
+            device.destroy_render_pass(self.render_pass, None);
+        }
+        self.pipelines.destroy(device);
+        self.view.destroy(device);
+    }
+}
+
+/// The map's render pass, cleared and drawn into every frame, with the moments as its colour attachment before
+/// the depth for VSM. Both are left to be sampled.
+fn create_render_pass(
+    device: &ash::Device,
+    depth_format: vk::Format,
+    moments_format: Option<vk::Format>,
+) -> vk::RenderPass {
+    let attachment = |format: vk::Format| {
+        vk::AttachmentDescription::builder()
+            .format(format)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .build()
+    };
+    let attachments: Vec<vk::AttachmentDescription> = moments_format
+        .into_iter()
+        .chain(std::iter::once(depth_format))
+        .map(attachment)
+        .collect();
+    let color_attachment_refs: Vec<vk::AttachmentReference> = moments_format
+        .iter()
+        .map(|_| {
+            vk::AttachmentReference::builder()
+                .attachment(0)
+                .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .build()
+        })
+        .collect();
+    let depth_attachment_ref = vk::AttachmentReference::builder()
+        .attachment(attachments.len() as u32 - 1)
+        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .build();
+    let subpasses = [vk::SubpassDescription::builder()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .color_attachments(&color_attachment_refs)
+        .depth_stencil_attachment(&depth_attachment_ref)
+        .build()];
+
+    let readers = vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER;
+    let writers = vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+        | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+        | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT;
+    let writes =
+        vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE | vk::AccessFlags::COLOR_ATTACHMENT_WRITE;
+    let dependencies = [
+        // The last frame's shading and blur have to finish reading the map before it's drawn over again
+        vk::SubpassDependency::builder()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .dst_subpass(0)
+            .src_stage_mask(readers)
+            .src_access_mask(vk::AccessFlags::empty())
+            .dst_stage_mask(writers)
+            .dst_access_mask(writes | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ)
+            .build(),
+        vk::SubpassDependency::builder()
+            .src_subpass(0)
+            .dst_subpass(vk::SUBPASS_EXTERNAL)
+            .src_stage_mask(writers)
+            .src_access_mask(writes)
+            .dst_stage_mask(readers)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+            .build(),
+    ];
+
+    unsafe {
+        device
+            .create_render_pass(
+                &vk::RenderPassCreateInfo::builder()
+                    .attachments(&attachments)
+                    .subpasses(&subpasses)
+                    .dependencies(&dependencies),
+                None,
+            )
+            .expect("Shadow map render pass")
+    }
+}
+
+/// Clamped to the map's edges, which are only reached from outside it where nothing is shadowed anyway
+fn create_sampler(device: &ash::Device, filter: vk::Filter) -> vk::Sampler {
+    let create_info = vk::SamplerCreateInfo::builder()
+        .mag_filter(filter)
+        .min_filter(filter)
+        .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .compare_enable(false)
+        .max_lod(0.0);
+    unsafe {
+        device
+            .create_sampler(&create_info, None)
+            .expect("Shadow map sampler")
+    }
+}
+
+/// The pipeline and descriptor sets that blur `image` through `blurred` and back
+fn create_blur(
+    device: &ash::Device,
+    image: (vk::Image, vk::DeviceMemory, vk::ImageView),
+    blurred: (vk::Image, vk::DeviceMemory, vk::ImageView),
+    sampler: vk::Sampler,
+) -> Moments {
+    let bindings = [
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .build(),
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(1)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .build(),
+    ];
+    let descriptor_set_layout = unsafe {
+        device
+            .create_descriptor_set_layout(
+                &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+                None,
+            )
+            .expect("Shadow blur descriptor set layout")
+    };
+    let push_constant_ranges = [vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        .offset(0)
+        .size(size_of::<Blur>() as u32)
+        .build()];
+    let set_layouts = [descriptor_set_layout; 2];
+    let layout = unsafe {
+        device
+            .create_pipeline_layout(
+                &vk::PipelineLayoutCreateInfo::builder()
+                    .set_layouts(&set_layouts[..1])
+                    .push_constant_ranges(&push_constant_ranges),
+                None,
+            )
+            .expect("Shadow blur pipeline layout")
+    };
+    let path = Path::new(env!("OUT_DIR")).join("shadow_blur_comp.spv");
+    let pipeline =
+        postprocess::create_compute_pipeline(device, layout, &util::read_shader_code(&path));
+
+    let pool_sizes = [
+        vk::DescriptorPoolSize::builder()
+            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(2)
+            .build(),
+        vk::DescriptorPoolSize::builder()
+            .ty(vk::DescriptorType::STORAGE_IMAGE)
+            .descriptor_count(2)
+            .build(),
+    ];
+    let descriptor_pool = unsafe {
+        device
+            .create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfo::builder()
+                    .pool_sizes(&pool_sizes)
+                    .max_sets(2),
+                None,
+            )
+            .expect("Shadow blur descriptor pool")
+    };
+    let allocated = unsafe {
+        device
+            .allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::builder()
+                    .descriptor_pool(descriptor_pool)
+                    .set_layouts(&set_layouts),
+            )
+            .expect("Shadow blur descriptor sets")
+    };
+    let descriptor_sets = [allocated[0], allocated[1]];
+    // Across reads the moments as the render pass left them, and down reads the blurred image it wrote
+    let passes = [
+        (
+            image.2,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            blurred.2,
+        ),
+        (blurred.2, vk::ImageLayout::GENERAL, image.2),
+    ];
+    for (&descriptor_set, &(source, source_layout, destination)) in
+        descriptor_sets.iter().zip(passes.iter())
+    {
+        let source_info = [vk::DescriptorImageInfo::builder()
+            .image_layout(source_layout)
+            .image_view(source)
+            .sampler(sampler)
+            .build()];
+        let destination_info = [vk::DescriptorImageInfo::builder()
+            .image_layout(vk::ImageLayout::GENERAL)
+            .image_view(destination)
+            .build()];
+        let writes = [
+            vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&source_info)
+                .build(),
+            vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .image_info(&destination_info)
+                .build(),
+        ];
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+    }
+
+    Moments {
+        image,
+        blurred,
+        descriptor_set_layout,
+        descriptor_pool,
+        descriptor_sets,
+        layout,
+        pipeline,
+    }
+}
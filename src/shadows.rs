@@ -0,0 +1,335 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Transform, Vector3};
+
+use crate::bounds;
+
+/// How far beyond the scene's bounds the map's near and far planes are, in world units
+const CLEARANCE: f32 = 1.0;
+/// The widths of the square kernels `Filter::Pcf` can filter over, in texels
+pub const PCF_KERNELS: [u32; 3] = [3, 5, 7];
+/// The widest `Filter::Vsm` blurs either side of a texel, in texels
+pub const MAX_BLUR: u32 = 8;
+/// `Filter::Pcss`'s light size when none is given, a little wider than the sun for penumbrae that show
+pub const DEFAULT_LIGHT_SIZE: f32 = 0.02;
+/// How much of the shadow `Filter::Vsm` cuts off, so that the light doesn't bleed through where shadows overlap
+const LIGHT_BLEED_REDUCTION: f32 = 0.3;
+
+/// cgmath's projections keep OpenGL's depth from -1 to 1, which Vulkan clips at 0, so the map's projection is
+/// moved onto Vulkan's 0 to 1 to use all of its depth
+#[rustfmt::skip]
+const DEPTH_RANGE: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
+
+/// How the shadow map is filtered where surfaces are shaded. Each costs more than the one before it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    /// One comparison with the depth the light sees, with hard, aliased edges
+    Hard,
+    /// Percentage-closer filtering: the comparisons over a square kernel `kernel` texels across, one of
+    /// `PCF_KERNELS`, averaged into edges as soft as the kernel is wide
+    Pcf { kernel: u32 },
+    /// Percentage-closer soft shadows: the blockers around a point are searched for first, and the edge widens
+    /// with how far the point is beyond them, `light_size` world units for each unit, so shadows are sharp
+    /// where casters touch what they shadow and soft further away
+    Pcss { light_size: f32 },
+    /// Variance shadow maps: the depth and its square are drawn into a colour target and blurred `blur` texels
+    /// either side, and the light is estimated from their mean and variance, so the edges are filtered by the
+    /// texture sampler and the blur rather than by many comparisons
+    Vsm { blur: u32 },
+}
+
+impl Filter {
+    pub const NAMES: [&'static str; 4] = ["hard", "pcf", "pcss", "vsm"];
+
+    /// The filter by name, with its setting if it has one: the kernel for PCF, the light size for PCSS and the
+    /// blur for VSM
+    pub fn parse(name: &str, setting: Option<&str>) -> Result<Self, String> {
+        let number = |default: f32| match setting {
+            Some(word) => word
+                .parse::<f32>()
+                .map_err(|_| format!("{} isn't a number", word)),
+            None => Ok(default),
+        };
+        match name {
+            "hard" => Ok(Filter::Hard),
+            "pcf" => {
+                let kernel = number(3.0)?;
+                PCF_KERNELS
+                    .iter()
+                    .copied()
+                    .find(|&width| width as f32 == kernel)
+                    .map(|kernel| Filter::Pcf { kernel })
+                    .ok_or_else(|| {
+                        format!("PCF kernels are 3, 5 or 7 texels across, not {}", kernel)
+                    })
+            }
+            "pcss" => match number(DEFAULT_LIGHT_SIZE)? {
+                light_size if light_size > 0.0 => Ok(Filter::Pcss { light_size }),
+                light_size => Err(format!(
+                    "The light's size must be above 0, not {}",
+                    light_size
+                )),
+            },
+            "vsm" => {
+                let blur = number(2.0)?;
+                if blur < 0.0 || blur > MAX_BLUR as f32 || blur.fract() != 0.0 {
+                    return Err(format!(
+                        "VSM blurs 0 to {} whole texels either side, not {}",
+                        MAX_BLUR, blur
+                    ));
+                }
+                Ok(Filter::Vsm { blur: blur as u32 })
+            }
+            _ => Err(format!(
+                "{} isn't a shadow filter, which are {}",
+                name,
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+
+    /// The filter as `parse` reads it
+    pub fn describe(&self) -> String {
+        match self {
+            Filter::Hard => String::from("hard"),
+            Filter::Pcf { kernel } => format!("pcf {}", kernel),
+            Filter::Pcss { light_size } => format!("pcss {}", light_size),
+            Filter::Vsm { blur } => format!("vsm {}", blur),
+        }
+    }
+
+    /// Whether the filter reads moments from a colour target rather than the depth
+    pub fn uses_moments(&self) -> bool {
+        matches!(self, Filter::Vsm { .. })
+    }
+
+    /// Matches the filters in `frag.glsl`'s `shadowLight`, where 0 is no shadow map
+    fn code(&self) -> u32 {
+        match self {
+            Filter::Hard => 1,
+            Filter::Pcf { .. } => 2,
+            Filter::Pcss { .. } => 3,
+            Filter::Vsm { .. } => 4,
+        }
+    }
+}
+
+/// The light's shadow map, see `shadow_map::ShadowMap`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Settings {
+    pub filter: Filter,
+    /// Texels across the square map
+    pub resolution: u32,
+    /// How much of the world the map covers across, in world units, centred on the camera's target. Nothing
+    /// further away is shadowed.
+    pub extent: f32,
+    /// How much of the light shadows block, from 0 to 1
+    pub strength: f32,
+    /// Push casters' depths away from the light so that surfaces don't shadow themselves, as
+    /// `pipeline::DepthBias`'s factors
+    pub constant_bias: f32,
+    pub slope_bias: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            filter: Filter::Pcf { kernel: 3 },
+            resolution: 2048,
+            extent: 30.0,
+            strength: 1.0,
+            constant_bias: 1.25,
+            slope_bias: 1.75,
+        }
+    }
+}
+
+/// What the light sees the map through
+#[derive(Clone, Copy, Debug)]
+pub struct LightView {
+    pub view: Matrix4<f32>,
+    pub projection: Matrix4<f32>,
+    /// World units from the map's near plane to its far plane
+    pub depth: f32,
+}
+
+impl Settings {
+    /// The light's view of the map, looking from `towards_light` onto `target` through an orthographic
+    /// projection from in front of everything in `bounds`. The target is moved by whole texels in the light's
+    /// view, so that shadows' edges stay where they are as it moves rather than crawling across the texels.
+    pub fn light_view(
+        &self,
+        towards_light: Vector3<f32>,
+        target: Point3<f32>,
+        bounds: Option<bounds::Aabb>,
+    ) -> LightView {
+        let towards = towards_light.normalize();
+        let half = self.extent / 2.0;
+        // The scene's nearest and furthest points from the light, or as far as the map is wide either way
+        let (above, below) = match bounds {
+            Some(bounds) => {
+                bounds
+                    .corners()
+                    .iter()
+                    .fold((f32::MIN, f32::MAX), |(above, below), corner| {
+                        let height = (corner - target).dot(towards);
+                        (above.max(height), below.min(height))
+                    })
+            }
+            None => (half, -half),
+        };
+        // Any up will do that isn't along the light's direction
+        let up = match towards.z.abs() < 0.99 {
+            true => Vector3::unit_z(),
+            false => Vector3::unit_y(),
+        };
+        let rotation = Matrix4::look_to_rh(Point3::origin(), -towards, up);
+        let texel = self.extent / self.resolution.max(1) as f32;
+        let centre = rotation.transform_point(target);
+        let snapped = Vector3::new(
+            (centre.x / texel).round() * texel,
+            (centre.y / texel).round() * texel,
+            centre.z,
+        );
+        let height = above.max(0.0) + CLEARANCE;
+        let view = Matrix4::from_translation(-snapped - Vector3::unit_z() * height) * rotation;
+        let depth = height - below.min(0.0) + CLEARANCE;
+        let projection = DEPTH_RANGE * cgmath::ortho(-half, half, -half, half, 0.0, depth);
+        LightView {
+            view,
+            projection,
+            depth,
+        }
+    }
+
+    /// The uniforms shading surfaces with the map `light` sees
+    pub fn uniforms(&self, light: &LightView) -> Uniforms {
+        let kernel = match self.filter {
+            Filter::Pcf { kernel } => kernel,
+            _ => 1,
+        };
+        let penumbra = match self.filter {
+            Filter::Pcss { light_size } => light_size * light.depth / self.extent,
+            _ => 0.0,
+        };
+        Uniforms {
+            matrix: (light.projection * light.view).into(),
+            flags: [self.filter.code(), kernel, 0, 0],
+            params: [
+                self.strength.clamp(0.0, 1.0),
+                1.0 / self.resolution.max(1) as f32,
+                penumbra,
+                LIGHT_BLEED_REDUCTION,
+            ],
+        }
+    }
+}
+
+/// Matches the `shadow` members of `UniformBufferObject` in `frag.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Uniforms {
+    /// From world space to the map's clip space
+    matrix: [[f32; 4]; 4],
+    /// x is the filter, see `Filter::code`, and y how many texels across PCF's kernel is
+    flags: [u32; 4],
+    /// The strength, how much of the map a texel covers, how far PCSS penumbrae widen across the map for each
+    /// unit of the map's depth, and how much of VSM's shadow is cut off
+    params: [f32; 4],
+}
+
+impl Uniforms {
+    /// Without a shadow map
+    pub fn off() -> Self {
+        Self {
+            matrix: Matrix4::from_scale(1.0).into(),
+            flags: [0; 4],
+            params: [0.0; 4],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(light: &LightView, point: Point3<f32>) -> Vector3<f32> {
+        let clip = light.projection * light.view * point.to_homogeneous();
+        clip.truncate() / clip.w
+    }
+
+    #[test]
+    fn filters_parse_back_from_their_descriptions() {
+        for filter in [
+            Filter::Hard,
+            Filter::Pcf { kernel: 5 },
+            Filter::Pcss { light_size: 0.05 },
+            Filter::Vsm { blur: 3 },
+        ] {
+            let description = filter.describe();
+            let mut words = description.split_whitespace();
+            let name = words.next().unwrap();
+            assert_eq!(Filter::parse(name, words.next()), Ok(filter));
+        }
+        assert_eq!(Filter::parse("pcf", None), Ok(Filter::Pcf { kernel: 3 }));
+        assert!(Filter::parse("pcf", Some("4")).is_err());
+        assert!(Filter::parse("vsm", Some("1.5")).is_err());
+        assert!(Filter::parse("pcss", Some("0")).is_err());
+        assert!(Filter::parse("soft", None).is_err());
+    }
+
+    #[test]
+    fn the_target_is_in_the_middle_of_the_map_and_the_light_sees_over_the_scene() {
+        let settings = Settings::default();
+        let target = Point3::new(3.0, -2.0, 0.0);
+        let bounds = bounds::Aabb {
+            min: Point3::new(-10.0, -10.0, -1.0),
+            max: Point3::new(10.0, 10.0, 4.0),
+        };
+        let towards_light = Vector3::new(1.0, 1.0, 2.0);
+        let light = settings.light_view(towards_light, target, Some(bounds));
+
+        let centre = project(&light, target);
+        let texel = 2.0 / settings.resolution as f32;
+        assert!(
+            centre.x.abs() <= texel && centre.y.abs() <= texel,
+            "{:?}",
+            centre
+        );
+        // Everything in the bounds is between the near and far planes
+        for corner in bounds.corners().iter() {
+            let depth = project(&light, *corner).z;
+            assert!((0.0..=1.0).contains(&depth), "{:?} is at {}", corner, depth);
+        }
+        // Further from the light is deeper
+        let nearer = target + towards_light.normalize();
+        assert!(project(&light, nearer).z < centre.z);
+    }
+
+    #[test]
+    fn the_target_moves_across_the_map_by_whole_texels() {
+        let settings = Settings::default();
+        let towards_light = Vector3::new(0.3, -0.2, 1.0);
+        let light = settings.light_view(towards_light, Point3::new(0.0, 0.0, 0.0), None);
+        let moved = settings.light_view(towards_light, Point3::new(0.001, 0.0, 0.0), None);
+        // A small move leaves the map where it was, so the same point lands on the same texel
+        let point = Point3::new(2.0, 1.0, 0.5);
+        let (before, after) = (project(&light, point), project(&moved, point));
+        assert!(
+            (before - after).magnitude() < 1e-5,
+            "{:?} {:?}",
+            before,
+            after
+        );
+    }
+
+    #[test]
+    fn a_light_straight_overhead_still_has_a_view() {
+        let light = Settings::default().light_view(Vector3::unit_z(), Point3::origin(), None);
+        let centre = project(&light, Point3::origin());
+        assert!(centre.x.is_finite() && centre.y.is_finite() && centre.z.is_finite());
+    }
+}
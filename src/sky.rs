@@ -0,0 +1,295 @@
+use cgmath::{Deg, InnerSpace, Rad, Vector3};
+
+use crate::units;
+
+/// The renderer's fixed light, which shines down from above and to one side of the scene whatever the time
+pub const FIXED_DIRECTION: [f32; 3] = [0.5, 0.5, 1.0];
+pub const FIXED_AMBIENT: f32 = 0.2;
+/// How far the Earth's axis is tilted, which the sun's declination swings through over the year
+const AXIAL_TILT: Deg<f32> = Deg(23.44);
+/// The sun's colour temperature in Kelvin as it sets, and once it is high enough above the horizon to shine
+/// through little atmosphere
+const HORIZON_TEMPERATURE: f32 = 1900.0;
+const NOON_TEMPERATURE: f32 = 5800.0;
+
+/// Sky colours in linear colour, at the zenith and horizon
+const DAY_SKY: ([f32; 3], [f32; 3]) = ([0.15, 0.35, 0.8], [0.6, 0.75, 0.95]);
+const TWILIGHT_SKY: ([f32; 3], [f32; 3]) = ([0.1, 0.12, 0.3], [0.9, 0.45, 0.2]);
+const NIGHT_SKY: ([f32; 3], [f32; 3]) = ([0.005, 0.008, 0.02], [0.015, 0.02, 0.04]);
+/// How much of the sky's light reaches surfaces facing away from the sun
+const SKY_AMBIENT: f32 = 0.3;
+
+/// The directional light every lit draw and the fog are shaded with, in linear colour
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Light {
+    /// Towards the light, in the world's space
+    pub direction: Vector3<f32>,
+    /// Added to surfaces facing the light, scaled by how squarely they face it
+    pub color: [f32; 3],
+    /// Added to every surface whichever way it faces, before baked occlusion
+    pub ambient: [f32; 3],
+}
+
+impl Default for Light {
+    /// The fixed light the renderer is lit by without a time of day
+    fn default() -> Self {
+        Self {
+            direction: Vector3::from(FIXED_DIRECTION).normalize(),
+            color: [1.0 - FIXED_AMBIENT; 3],
+            ambient: [FIXED_AMBIENT; 3],
+        }
+    }
+}
+
+impl Light {
+    pub fn uniforms(&self) -> Uniforms {
+        let [r, g, b] = self.color;
+        let [ambient_r, ambient_g, ambient_b] = self.ambient;
+        Uniforms {
+            direction: self.direction.extend(0.0).into(),
+            color: [r, g, b, 0.0],
+            ambient: [ambient_r, ambient_g, ambient_b, 0.0],
+        }
+    }
+}
+
+/// Matches the `light` members of `UniformBufferObject` in `frag.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Uniforms {
+    direction: [f32; 4],
+    color: [f32; 4],
+    ambient: [f32; 4],
+}
+
+/// Where the sun is over a day at a latitude, and the light and sky it gives. The day runs on by itself at
+/// `speed`, and the sun rises in the east and crosses the sky to the south in the northern hemisphere, with Y
+/// north and X east in worlds with Z up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeOfDay {
+    /// Hours into the day, from 0 to 24, solar noon at 12
+    pub hour: f32,
+    /// Hours that pass each second. Zero holds the time.
+    pub speed: f32,
+    /// Positive in the northern hemisphere
+    pub latitude: Deg<f32>,
+    /// Days into the year, from 0 at the March equinox, which sets how high the sun climbs
+    pub day: f32,
+}
+
+impl Default for TimeOfDay {
+    /// The middle of the morning at the equinox, halfway to the North Pole, with an hour passing every minute
+    fn default() -> Self {
+        Self {
+            hour: 9.0,
+            speed: 1.0 / 60.0,
+            latitude: Deg(45.0),
+            day: 0.0,
+        }
+    }
+}
+
+impl TimeOfDay {
+    /// Moves the time on by the seconds passed, into the next day after midnight
+    pub fn update(&mut self, seconds: f32) {
+        self.hour += self.speed * seconds;
+        let days = (self.hour / 24.0).floor();
+        self.hour -= days * 24.0;
+        self.day = (self.day + days).rem_euclid(365.0);
+    }
+
+    /// Towards the sun in the world's space, below the horizon at night
+    pub fn sun_direction(&self, up: units::Up) -> Vector3<f32> {
+        let declination =
+            Rad::from(AXIAL_TILT).0 * (2.0 * std::f32::consts::PI * self.day / 365.0).sin();
+        let hour_angle = Rad::from(Deg(15.0 * (self.hour - 12.0))).0;
+        let latitude = Rad::from(self.latitude).0;
+        // East, north and up
+        let direction = Vector3::new(
+            -declination.cos() * hour_angle.sin(),
+            latitude.cos() * declination.sin()
+                - latitude.sin() * declination.cos() * hour_angle.cos(),
+            latitude.sin() * declination.sin()
+                + latitude.cos() * declination.cos() * hour_angle.cos(),
+        );
+        up.rotation() * direction
+    }
+
+    /// The sine of the sun's height above the horizon
+    fn elevation(&self) -> f32 {
+        self.sun_direction(units::Up::Z).z
+    }
+
+    /// Sunlight, reddened as the sun gets lower and fading out after it sets, and the sky's light. Only the
+    /// sky lights the scene at night, from the sun's direction so the fog still has a direction to scatter.
+    pub fn light(&self, up: units::Up) -> Light {
+        let elevation = self.elevation();
+        let temperature = HORIZON_TEMPERATURE
+            + (NOON_TEMPERATURE - HORIZON_TEMPERATURE) * smoothstep(0.0, 0.5, elevation);
+        let strength = (1.0 - FIXED_AMBIENT) * smoothstep(-0.02, 0.15, elevation);
+        let sky = self.sky();
+        Light {
+            direction: self.sun_direction(up),
+            color: blackbody(temperature).map(|channel| channel * strength),
+            ambient: mix(sky.zenith, sky.horizon, 0.5).map(|channel| channel * SKY_AMBIENT),
+        }
+    }
+
+    pub fn sky(&self) -> Sky {
+        let elevation = self.elevation();
+        let ((zenith, horizon), towards, amount) = match elevation >= 0.0 {
+            true => (TWILIGHT_SKY, DAY_SKY, smoothstep(0.0, 0.3, elevation)),
+            false => (TWILIGHT_SKY, NIGHT_SKY, smoothstep(0.0, 0.2, -elevation)),
+        };
+        Sky {
+            zenith: mix(zenith, towards.0, amount),
+            horizon: mix(horizon, towards.1, amount),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let minutes = (self.hour * 60.0) as u32;
+        format!(
+            "{:02}:{:02} on day {:.0}, latitude {:.1}, {:.3} hours a second, sun {:.1} degrees up",
+            minutes / 60,
+            minutes % 60,
+            self.day,
+            self.latitude.0,
+            self.speed,
+            Deg::from(Rad(self.elevation().asin())).0
+        )
+    }
+}
+
+/// The sky's colours for the time of day, in linear colour
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sky {
+    pub zenith: [f32; 3],
+    pub horizon: [f32; 3],
+}
+
+impl Sky {
+    /// The colour the scene is drawn over, the sky's partway up from the horizon
+    pub fn background(&self) -> [f32; 3] {
+        mix(self.horizon, self.zenith, 0.4)
+    }
+}
+
+/// The linear colour of a black body at the temperature, brightest channel one. Fitted to the blackbody curve
+/// from 1000K to 40000K, after Tanner Helland.
+fn blackbody(kelvin: f32) -> [f32; 3] {
+    let t = kelvin / 100.0;
+    let red = match t <= 66.0 {
+        true => 1.0,
+        false => 1.292_936 * (t - 60.0).powf(-0.133_204_76),
+    };
+    let green = match t <= 66.0 {
+        true => 0.390_081_58 * t.ln() - 0.631_841_4,
+        false => 1.129_890_9 * (t - 60.0).powf(-0.075_514_85),
+    };
+    let blue = match t {
+        t if t >= 66.0 => 1.0,
+        t if t <= 19.0 => 0.0,
+        t => 0.543_206_8 * (t - 10.0).ln() - 1.196_254_1,
+    };
+    // The fit is of sRGB encoded colour
+    [red, green, blue].map(|channel| channel.clamp(0.0, 1.0).powf(2.2))
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn mix(a: [f32; 3], b: [f32; 3], amount: f32) -> [f32; 3] {
+    [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * amount)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: f32, latitude: f32, day: f32) -> TimeOfDay {
+        TimeOfDay {
+            hour,
+            speed: 0.0,
+            latitude: Deg(latitude),
+            day,
+        }
+    }
+
+    fn assert_near(actual: Vector3<f32>, expected: Vector3<f32>) {
+        assert!(
+            (actual - expected).magnitude() < 1e-4,
+            "{:?} isn't {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn noon_sun_is_due_south_at_the_equinox() {
+        let latitude = Rad::from(Deg(45.0f32)).0;
+        assert_near(
+            at(12.0, 45.0, 0.0).sun_direction(units::Up::Z),
+            Vector3::new(0.0, -latitude.sin(), latitude.cos()),
+        );
+        // And overhead at the equator
+        assert_near(
+            at(12.0, 0.0, 0.0).sun_direction(units::Up::Z),
+            Vector3::unit_z(),
+        );
+        // North of it in the southern hemisphere
+        assert!(at(12.0, -30.0, 0.0).sun_direction(units::Up::Z).y > 0.0);
+    }
+
+    #[test]
+    fn rises_in_the_east_and_sets_in_the_west() {
+        assert_near(
+            at(6.0, 45.0, 0.0).sun_direction(units::Up::Z),
+            Vector3::unit_x(),
+        );
+        assert_near(
+            at(18.0, 45.0, 0.0).sun_direction(units::Up::Z),
+            -Vector3::unit_x(),
+        );
+        assert!(at(0.0, 45.0, 0.0).sun_direction(units::Up::Z).z < 0.0);
+    }
+
+    #[test]
+    fn overhead_at_the_tropic_on_the_solstice() {
+        let solstice = 365.0 / 4.0;
+        assert_near(
+            at(12.0, AXIAL_TILT.0, solstice).sun_direction(units::Up::Z),
+            Vector3::unit_z(),
+        );
+    }
+
+    #[test]
+    fn follows_the_world_up() {
+        let time = at(10.0, 52.0, 40.0);
+        let z_up = time.sun_direction(units::Up::Z);
+        let y_up = time.sun_direction(units::Up::Y);
+        assert!((y_up.dot(units::Up::Y.vector()) - z_up.z).abs() < 1e-5);
+        assert!((y_up.magnitude() - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn update_wraps_into_the_next_day_and_year() {
+        let mut time = TimeOfDay {
+            speed: 1.0,
+            ..at(23.0, 45.0, 364.0)
+        };
+        time.update(2.0);
+        assert!((time.hour - 1.0).abs() < 1e-5);
+        assert!(time.day.abs() < 1e-5);
+    }
+
+    #[test]
+    fn night_is_unlit_by_the_sun() {
+        let night = at(0.0, 45.0, 0.0).light(units::Up::Z);
+        assert_eq!(night.color, [0.0; 3]);
+        let noon = at(12.0, 45.0, 0.0).light(units::Up::Z);
+        assert!(noon.color.iter().all(|&channel| channel > 0.5));
+    }
+}
@@ -0,0 +1,480 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use ash::vk;
+use cgmath::{Deg, Matrix4, Point3, Vector3};
+
+use crate::{camera, clipping, draw, material, quality, sky, HelloTriangleApplication};
+
+/// The first line of every scene file, naming the version of the format
+const HEADER: &str = "rust-renderer-vk scene 2";
+/// The first line of scene files saved before the light was, which still load, lit by the fixed light
+const HEADER_1: &str = "rust-renderer-vk scene 1";
+
+/// What can be changed about the renderer's scenes while it runs, saved in a scene file so it can be restored.
+/// Draws are saved by their index, so a scene file only loads back into a renderer started with the same
+/// arguments, which builds the same draws in the same order. Geometry and textures aren't saved. The light is
+/// saved as it was, along with the time of day that moves it if there is one.
+///
+/// Scene files are text, one setting per line:
+///
+/// ```text
+/// rust-renderer-vk scene 2
+/// scene <index>
+/// camera <perspective|orthographic> <front|top|right|isometric> <target x> <y> <z> <distance> [<yaw> <pitch>]
+/// camera_parameter <fov|near|far|move_speed|dolly_speed|smoothing> <value>
+/// grid <0|1>
+/// quality <low|medium|high|ultra>
+/// clip_caps <0|1> <red> <green> <blue>
+/// clip <normal x> <y> <z> <distance>
+/// light <direction x> <y> <z> <red> <green> <blue> <ambient red> <green> <blue>
+/// time_of_day <hour> <hours a second> <latitude> <day of year>
+/// draw <index> <none|front|back|both> <cw|ccw> <double sided 0|1> <alpha cutoff|-> <ignore|multiply>
+///     <occlusion strength> <transform, 16 numbers by column>
+/// ```
+///
+/// with a `camera_parameter` line for each of the camera's parameters, a `quality` line if a quality preset has been
+/// applied, a `clip` line for each plane, a `time_of_day` line if the sun moves over the day and a `draw` line for
+/// each draw. The light's direction is towards it in the world's space, and its colours are linear. The camera's
+/// yaw and pitch from the view are in degrees. Lines starting with `#` are ignored.
+pub struct Snapshot {
+    pub scene: usize,
+    pub camera: camera::Camera,
+    pub grid_visible: bool,
+    pub clipping: clipping::Clipping,
+    pub draws: Vec<DrawState>,
+    pub quality: Option<quality::Preset>,
+    pub light: sky::Light,
+    pub time_of_day: Option<sky::TimeOfDay>,
+}
+
+/// How a draw's material is set up and where it is placed
+#[derive(Clone, Copy, Debug)]
+pub struct DrawState {
+    pub transform: Matrix4<f32>,
+    pub cull_mode: vk::CullModeFlags,
+    pub front_face: vk::FrontFace,
+    pub double_sided: bool,
+    pub alpha_cutoff: Option<u8>,
+    pub vertex_color: material::VertexColorMode,
+    pub occlusion_strength: u8,
+}
+
+impl DrawState {
+    pub fn of(draw: &draw::Draw) -> Self {
+        Self {
+            transform: draw.transform,
+            cull_mode: draw.pipeline.cull_mode,
+            front_face: draw.pipeline.front_face,
+            double_sided: draw.pipeline.double_sided,
+            alpha_cutoff: draw.pipeline.alpha_cutoff,
+            vertex_color: draw.pipeline.vertex_color,
+            occlusion_strength: draw.pipeline.occlusion_strength,
+        }
+    }
+
+    pub fn apply(&self, draw: &mut draw::Draw) {
+        draw.transform = self.transform;
+        draw.pipeline.cull_mode = self.cull_mode;
+        draw.pipeline.front_face = self.front_face;
+        draw.pipeline.double_sided = self.double_sided;
+        draw.pipeline.alpha_cutoff = self.alpha_cutoff;
+        draw.pipeline.vertex_color = self.vertex_color;
+        draw.pipeline.occlusion_strength = self.occlusion_strength;
+    }
+}
+
+impl Snapshot {
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_text()).map_err(|e| format!("Writing {}: {}", path.display(), e))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text =
+            fs::read_to_string(path).map_err(|e| format!("Reading {}: {}", path.display(), e))?;
+        Self::from_text(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = String::new();
+        let camera = &self.camera;
+        let clipping = &self.clipping;
+        let [red, green, blue] = clipping.cap_color;
+        // Writing to a string can't fail
+        let _ = writeln!(text, "{}", HEADER);
+        let _ = writeln!(text, "scene {}", self.scene);
+        let _ = writeln!(
+            text,
+            "camera {} {} {} {} {} {} {} {}",
+            camera.projection.name(),
+            camera.preset.name(),
+            camera.target.x,
+            camera.target.y,
+            camera.target.z,
+            camera.distance,
+            Deg::from(camera.yaw).0,
+            Deg::from(camera.pitch).0
+        );
+        for parameter in camera::Parameter::ALL {
+            let _ = writeln!(
+                text,
+                "camera_parameter {} {}",
+                parameter.name(),
+                parameter.get(camera)
+            );
+        }
+        let _ = writeln!(text, "grid {}", self.grid_visible as u8);
+        if let Some(preset) = self.quality {
+            let _ = writeln!(text, "quality {}", preset.name());
+        }
+        let _ = writeln!(
+            text,
+            "clip_caps {} {} {} {}",
+            clipping.capped as u8, red, green, blue
+        );
+        for plane in clipping.planes.iter() {
+            let _ = writeln!(
+                text,
+                "clip {} {} {} {}",
+                plane.normal.x, plane.normal.y, plane.normal.z, plane.distance
+            );
+        }
+        let light = &self.light;
+        let _ = writeln!(
+            text,
+            "light {} {} {} {} {} {} {} {} {}",
+            light.direction.x,
+            light.direction.y,
+            light.direction.z,
+            light.color[0],
+            light.color[1],
+            light.color[2],
+            light.ambient[0],
+            light.ambient[1],
+            light.ambient[2]
+        );
+        if let Some(time_of_day) = self.time_of_day {
+            let _ = writeln!(
+                text,
+                "time_of_day {} {} {} {}",
+                time_of_day.hour, time_of_day.speed, time_of_day.latitude.0, time_of_day.day
+            );
+        }
+        for (index, draw) in self.draws.iter().enumerate() {
+            let cull_mode = match draw.cull_mode {
+                vk::CullModeFlags::NONE => "none",
+                vk::CullModeFlags::FRONT => "front",
+                vk::CullModeFlags::BACK => "back",
+                _ => "both",
+            };
+            let front_face = match draw.front_face {
+                vk::FrontFace::COUNTER_CLOCKWISE => "ccw",
+                _ => "cw",
+            };
+            let alpha_cutoff = draw
+                .alpha_cutoff
+                .map_or_else(|| String::from("-"), |cutoff| cutoff.to_string());
+            let vertex_color = match draw.vertex_color {
+                material::VertexColorMode::Ignore => "ignore",
+                material::VertexColorMode::Multiply => "multiply",
+            };
+            let transform: &[f32; 16] = draw.transform.as_ref();
+            let transform: Vec<String> = transform.iter().map(|value| value.to_string()).collect();
+            let _ = writeln!(
+                text,
+                "draw {} {} {} {} {} {} {} {}",
+                index,
+                cull_mode,
+                front_face,
+                draw.double_sided as u8,
+                alpha_cutoff,
+                vertex_color,
+                draw.occlusion_strength,
+                transform.join(" ")
+            );
+        }
+        text
+    }
+
+    fn from_text(text: &str) -> Result<Self, String> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(number, line)| (number + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+        match lines.next() {
+            Some((_, HEADER)) | Some((_, HEADER_1)) => {}
+            _ => return Err(format!("Scene files start with \"{}\"", HEADER)),
+        }
+
+        let mut snapshot = Self {
+            scene: 0,
+            camera: camera::Camera::new(HelloTriangleApplication::FIELD_OF_VIEW),
+            grid_visible: false,
+            clipping: clipping::Clipping::default(),
+            draws: Vec::new(),
+            quality: None,
+            light: sky::Light::default(),
+            time_of_day: None,
+        };
+        for (number, line) in lines {
+            let mut fields = line.split_whitespace();
+            let key = fields.next().unwrap_or_default();
+            let mut field = |name: &str| {
+                fields
+                    .next()
+                    .ok_or_else(|| format!("Line {} is missing the {}", number, name))
+            };
+            match key {
+                "scene" => snapshot.scene = parse(number, field("scene")?)?,
+                "camera" => {
+                    let camera = &mut snapshot.camera;
+                    let projection = field("projection")?;
+                    camera.projection = camera::Projection::parse(projection).ok_or_else(|| {
+                        format!("Line {}: unknown projection {}", number, projection)
+                    })?;
+                    let preset = field("view")?;
+                    camera.preset = camera::Preset::parse(preset)
+                        .ok_or_else(|| format!("Line {}: unknown view {}", number, preset))?;
+                    camera.target = Point3::new(
+                        parse(number, field("target")?)?,
+                        parse(number, field("target")?)?,
+                        parse(number, field("target")?)?,
+                    );
+                    camera.distance = parse(number, field("distance")?)?;
+                    // Files saved before the camera could be turned from the view leave them out
+                    if let Ok(yaw) = field("yaw") {
+                        camera.yaw = Deg(parse(number, yaw)?).into();
+                        camera.pitch = Deg(parse(number, field("pitch")?)?).into();
+                    }
+                }
+                "camera_parameter" => {
+                    let name = field("camera parameter")?;
+                    let parameter = camera::Parameter::parse(name).ok_or_else(|| {
+                        format!("Line {}: unknown camera parameter {}", number, name)
+                    })?;
+                    // Checked once they have all been read, since the clip planes are checked against each other
+                    parameter.assign(&mut snapshot.camera, parse(number, field("value")?)?);
+                }
+                "grid" => snapshot.grid_visible = parse::<u8>(number, field("visibility")?)? != 0,
+                "quality" => {
+                    let name = field("quality preset")?;
+                    snapshot.quality = Some(quality::Preset::parse(name).ok_or_else(|| {
+                        format!("Line {}: unknown quality preset {}", number, name)
+                    })?);
+                }
+                "clip_caps" => {
+                    snapshot.clipping.capped = parse::<u8>(number, field("capping")?)? != 0;
+                    for channel in snapshot.clipping.cap_color.iter_mut() {
+                        *channel = parse(number, field("cap colour")?)?;
+                    }
+                }
+                "clip" => {
+                    let normal = Vector3::new(
+                        parse(number, field("normal")?)?,
+                        parse(number, field("normal")?)?,
+                        parse(number, field("normal")?)?,
+                    );
+                    let distance = parse(number, field("distance")?)?;
+                    snapshot
+                        .clipping
+                        .planes
+                        .push(clipping::Plane { normal, distance });
+                }
+                "light" => {
+                    let light = &mut snapshot.light;
+                    light.direction = Vector3::new(
+                        parse(number, field("light direction")?)?,
+                        parse(number, field("light direction")?)?,
+                        parse(number, field("light direction")?)?,
+                    );
+                    for channel in light.color.iter_mut() {
+                        *channel = parse(number, field("light colour")?)?;
+                    }
+                    for channel in light.ambient.iter_mut() {
+                        *channel = parse(number, field("ambient colour")?)?;
+                    }
+                }
+                "time_of_day" => {
+                    snapshot.time_of_day = Some(sky::TimeOfDay {
+                        hour: parse(number, field("hour")?)?,
+                        speed: parse(number, field("speed")?)?,
+                        latitude: Deg(parse(number, field("latitude")?)?),
+                        day: parse(number, field("day")?)?,
+                    });
+                }
+                "draw" => {
+                    let index: usize = parse(number, field("index")?)?;
+                    if index != snapshot.draws.len() {
+                        return Err(format!("Line {}: draws must be in order", number));
+                    }
+                    let cull_mode = match field("cull mode")? {
+                        "none" => vk::CullModeFlags::NONE,
+                        "front" => vk::CullModeFlags::FRONT,
+                        "back" => vk::CullModeFlags::BACK,
+                        "both" => vk::CullModeFlags::FRONT_AND_BACK,
+                        other => {
+                            return Err(format!("Line {}: unknown cull mode {}", number, other))
+                        }
+                    };
+                    let front_face = match field("front face")? {
+                        "cw" => vk::FrontFace::CLOCKWISE,
+                        "ccw" => vk::FrontFace::COUNTER_CLOCKWISE,
+                        other => {
+                            return Err(format!("Line {}: unknown front face {}", number, other))
+                        }
+                    };
+                    let double_sided = parse::<u8>(number, field("double sidedness")?)? != 0;
+                    let alpha_cutoff = match field("alpha cutoff")? {
+                        "-" => None,
+                        cutoff => Some(parse(number, cutoff)?),
+                    };
+                    let vertex_color = match field("vertex colour mode")? {
+                        "ignore" => material::VertexColorMode::Ignore,
+                        "multiply" => material::VertexColorMode::Multiply,
+                        other => {
+                            return Err(format!(
+                                "Line {}: unknown vertex colour mode {}",
+                                number, other
+                            ))
+                        }
+                    };
+                    let occlusion_strength = parse(number, field("occlusion strength")?)?;
+                    let mut transform = [0.0; 16];
+                    for value in transform.iter_mut() {
+                        *value = parse(number, field("transform")?)?;
+                    }
+                    let transform: &Matrix4<f32> = (&transform).into();
+                    snapshot.draws.push(DrawState {
+                        transform: *transform,
+                        cull_mode,
+                        front_face,
+                        double_sided,
+                        alpha_cutoff,
+                        vertex_color,
+                        occlusion_strength,
+                    });
+                }
+                other => return Err(format!("Line {}: unknown setting {}", number, other)),
+            }
+        }
+        snapshot.camera.check()?;
+        Ok(snapshot)
+    }
+}
+
+fn parse<T: FromStr>(number: usize, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Line {}: {} isn't a valid number", number, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        let mut camera = camera::Camera::new(HelloTriangleApplication::FIELD_OF_VIEW);
+        camera.projection = camera::Projection::Orthographic;
+        camera.target = Point3::new(1.0, -2.5, 0.25);
+        camera.distance = 12.0;
+        let mut clipping = clipping::Clipping::default();
+        clipping.capped = true;
+        clipping.cap_color = [0.5, 0.25, 1.0];
+        clipping.planes.push(clipping::Plane {
+            normal: Vector3::new(0.0, 0.0, 1.0),
+            distance: -0.5,
+        });
+        let draw = DrawState {
+            transform: Matrix4::from_translation(Vector3::new(3.0, 4.0, 5.0)),
+            cull_mode: vk::CullModeFlags::FRONT,
+            front_face: vk::FrontFace::CLOCKWISE,
+            double_sided: true,
+            alpha_cutoff: Some(128),
+            vertex_color: material::VertexColorMode::Multiply,
+            occlusion_strength: 200,
+        };
+        Snapshot {
+            scene: 1,
+            camera,
+            grid_visible: true,
+            clipping,
+            draws: vec![
+                draw,
+                DrawState {
+                    alpha_cutoff: None,
+                    ..draw
+                },
+            ],
+            quality: Some(quality::Preset::High),
+            light: sky::Light {
+                direction: Vector3::new(0.0, 0.6, 0.8),
+                color: [1.0, 0.9, 0.75],
+                ambient: [0.1, 0.1, 0.2],
+            },
+            time_of_day: Some(sky::TimeOfDay {
+                hour: 17.5,
+                speed: 0.0,
+                latitude: Deg(-33.0),
+                day: 90.0,
+            }),
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let text = snapshot().to_text();
+        let loaded = Snapshot::from_text(&text).unwrap();
+        assert_eq!(loaded.to_text(), text);
+        assert_eq!(loaded.scene, 1);
+        assert_eq!(loaded.camera.projection, camera::Projection::Orthographic);
+        assert_eq!(loaded.camera.target, Point3::new(1.0, -2.5, 0.25));
+        assert!(loaded.grid_visible);
+        assert_eq!(loaded.clipping.planes.len(), 1);
+        assert_eq!(loaded.draws.len(), 2);
+        assert_eq!(loaded.draws[0].alpha_cutoff, Some(128));
+        assert_eq!(loaded.draws[1].alpha_cutoff, None);
+        assert_eq!(loaded.draws[0].front_face, vk::FrontFace::CLOCKWISE);
+        assert_eq!(loaded.light, snapshot().light);
+        assert_eq!(loaded.time_of_day, snapshot().time_of_day);
+    }
+
+    #[test]
+    fn reads_the_first_version() {
+        let text = format!("{}\nscene 2\n# a comment\n\ngrid 1\n", HEADER_1);
+        let loaded = Snapshot::from_text(&text).unwrap();
+        assert_eq!(loaded.scene, 2);
+        assert!(loaded.grid_visible);
+        // Scenes saved before the light was are lit by the fixed light
+        assert_eq!(loaded.light, sky::Light::default());
+        assert_eq!(loaded.time_of_day, None);
+    }
+
+    #[test]
+    fn reports_the_line_of_errors() {
+        let error = |body: &str| Snapshot::from_text(&format!("{}\n{}", HEADER, body)).err();
+        assert!(Snapshot::from_text("scene 0\n").is_err());
+        assert_eq!(
+            error("scene 0\n\nbrightness 2\n").as_deref(),
+            Some("Line 4: unknown setting brightness")
+        );
+        assert_eq!(
+            error("scene x\n").as_deref(),
+            Some("Line 2: x isn't a valid number")
+        );
+        assert_eq!(
+            error("light 0 0 1 1 1\n").as_deref(),
+            Some("Line 2 is missing the light colour")
+        );
+        assert_eq!(
+            error("draw 1 none ccw 0 - ignore 255\n").as_deref(),
+            Some("Line 2: draws must be in order")
+        );
+        assert_eq!(
+            error("quality extreme\n").as_deref(),
+            Some("Line 2: unknown quality preset extreme")
+        );
+    }
+}
@@ -0,0 +1,367 @@
+use std::collections::HashMap;
+
+const MAGIC: u32 = 0x0723_0203;
+
+// Opcodes
+const OP_NAME: u32 = 5;
+const OP_MEMBER_NAME: u32 = 6;
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+// Decorations
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+// Storage classes
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_UNIFORM: u32 = 2;
+
+/// The numbers a parameter is made of
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scalar {
+    Float,
+    Int,
+    Uint,
+    /// Stored as a 32 bit integer in uniform blocks
+    Bool,
+}
+
+/// The type of a member of a uniform block: a scalar, vector or matrix, or an array of them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Kind {
+    pub scalar: Scalar,
+    /// 1 for scalars, or how many components each column has
+    pub components: u32,
+    /// 1 for scalars and vectors
+    pub columns: u32,
+    /// Bytes between the start of one column and the next, for matrices
+    pub matrix_stride: u32,
+    /// 1 for anything that isn't an array
+    pub elements: u32,
+    /// Bytes between the start of one element and the next, for arrays
+    pub array_stride: u32,
+}
+
+impl Kind {
+    /// How many numbers it holds
+    pub fn count(&self) -> usize {
+        (self.components * self.columns * self.elements) as usize
+    }
+
+    /// The byte offset of each of its numbers from its start, in the order they are given, column by column
+    pub fn offsets(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..self.elements).flat_map(move |element| {
+            (0..self.columns).flat_map(move |column| {
+                (0..self.components).map(move |component| {
+                    element * self.array_stride + column * self.matrix_stride + component * 4
+                })
+            })
+        })
+    }
+
+    /// How many bytes it covers from its start
+    fn size(&self) -> u32 {
+        self.offsets().max().map_or(0, |last| last + 4)
+    }
+
+    /// Its name in GLSL, such as `vec4` or `mat4`
+    pub fn name(&self) -> String {
+        let prefix = match self.scalar {
+            Scalar::Float => "",
+            Scalar::Int => "i",
+            Scalar::Uint => "u",
+            Scalar::Bool => "b",
+        };
+        let base = match (self.components, self.columns) {
+            (1, _) => match self.scalar {
+                Scalar::Float => String::from("float"),
+                Scalar::Int => String::from("int"),
+                Scalar::Uint => String::from("uint"),
+                Scalar::Bool => String::from("bool"),
+            },
+            (components, 1) => format!("{}vec{}", prefix, components),
+            (components, columns) if components == columns => format!("mat{}", columns),
+            (components, columns) => format!("mat{}x{}", columns, components),
+        };
+        match self.elements {
+            1 => base,
+            elements => format!("{}[{}]", base, elements),
+        }
+    }
+}
+
+/// A member of a uniform block
+#[derive(Clone, Debug, PartialEq)]
+pub struct Member {
+    pub name: String,
+    /// Bytes from the start of the block
+    pub offset: u32,
+    pub kind: Kind,
+}
+
+/// A uniform block a shader declares
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    /// The block's instance name, or its type name when it has none
+    pub name: String,
+    pub set: u32,
+    pub binding: u32,
+    /// How many bytes its members cover
+    pub size: u32,
+    /// Its members that are numbers, vectors, matrices or arrays of them. Nested structs are left out.
+    pub members: Vec<Member>,
+}
+
+/// A combined image sampler a shader declares
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sampler {
+    pub name: String,
+    pub set: u32,
+    pub binding: u32,
+}
+
+/// The uniform blocks and samplers a SPIR-V module declares, found from its types and decorations. Shaders need
+/// to be compiled with their names kept, which `glslc` does unless told to strip them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Reflection {
+    pub blocks: Vec<Block>,
+    pub samplers: Vec<Sampler>,
+}
+
+#[derive(Clone, Debug)]
+enum Type {
+    Scalar(Scalar),
+    Vector(u32, u32),
+    Matrix(u32, u32),
+    Array(u32, u32),
+    Struct(Vec<u32>),
+    SampledImage,
+    Pointer(u32),
+    Other,
+}
+
+/// Reads the uniform blocks and samplers the module declares
+pub fn reflect(code: &[u32]) -> Result<Reflection, String> {
+    if code.len() < 5 || code[0] != MAGIC {
+        return Err(String::from("Not SPIR-V"));
+    }
+
+    let mut names: HashMap<u32, String> = HashMap::new();
+    let mut member_names: HashMap<(u32, u32), String> = HashMap::new();
+    let mut decorations: HashMap<(u32, u32), u32> = HashMap::new();
+    let mut member_decorations: HashMap<(u32, u32, u32), u32> = HashMap::new();
+    let mut types: HashMap<u32, Type> = HashMap::new();
+    let mut constants: HashMap<u32, u32> = HashMap::new();
+    // The type and storage class of each variable
+    let mut variables: Vec<(u32, u32, u32)> = Vec::new();
+
+    let mut at = 5;
+    while at < code.len() {
+        let word_count = (code[at] >> 16) as usize;
+        let opcode = code[at] & 0xffff;
+        if word_count == 0 || at + word_count > code.len() {
+            return Err(format!(
+                "Instruction {} runs past the end of the module",
+                at
+            ));
+        }
+        let operands = &code[at + 1..at + word_count];
+        let operand = |index: usize| operands.get(index).copied().unwrap_or(0);
+        match opcode {
+            OP_NAME if !operands.is_empty() => {
+                names.insert(operand(0), string(&operands[1..]));
+            }
+            OP_MEMBER_NAME if operands.len() > 1 => {
+                member_names.insert((operand(0), operand(1)), string(&operands[2..]));
+            }
+            OP_DECORATE if operands.len() > 1 => {
+                decorations.insert((operand(0), operand(1)), operand(2));
+            }
+            OP_MEMBER_DECORATE if operands.len() > 2 => {
+                member_decorations.insert((operand(0), operand(1), operand(2)), operand(3));
+            }
+            OP_TYPE_BOOL => {
+                types.insert(operand(0), Type::Scalar(Scalar::Bool));
+            }
+            OP_TYPE_INT => {
+                let scalar = if operand(2) == 1 {
+                    Scalar::Int
+                } else {
+                    Scalar::Uint
+                };
+                types.insert(operand(0), Type::Scalar(scalar));
+            }
+            OP_TYPE_FLOAT => {
+                types.insert(operand(0), Type::Scalar(Scalar::Float));
+            }
+            OP_TYPE_VECTOR => {
+                types.insert(operand(0), Type::Vector(operand(1), operand(2)));
+            }
+            OP_TYPE_MATRIX => {
+                types.insert(operand(0), Type::Matrix(operand(1), operand(2)));
+            }
+            OP_TYPE_IMAGE => {
+                types.insert(operand(0), Type::Other);
+            }
+            OP_TYPE_SAMPLED_IMAGE => {
+                types.insert(operand(0), Type::SampledImage);
+            }
+            OP_TYPE_ARRAY => {
+                types.insert(operand(0), Type::Array(operand(1), operand(2)));
+            }
+            OP_TYPE_STRUCT if !operands.is_empty() => {
+                types.insert(operand(0), Type::Struct(operands[1..].to_vec()));
+            }
+            OP_TYPE_POINTER => {
+                types.insert(operand(0), Type::Pointer(operand(2)));
+            }
+            OP_CONSTANT => {
+                constants.insert(operand(1), operand(2));
+            }
+            OP_VARIABLE => variables.push((operand(0), operand(1), operand(2))),
+            _ => {}
+        }
+        at += word_count;
+    }
+
+    let mut reflection = Reflection::default();
+    for (pointer, variable, storage) in variables {
+        let pointee = match types.get(&pointer) {
+            Some(Type::Pointer(pointee)) => *pointee,
+            _ => continue,
+        };
+        let (set, binding) = match (
+            decorations.get(&(variable, DECORATION_DESCRIPTOR_SET)),
+            decorations.get(&(variable, DECORATION_BINDING)),
+        ) {
+            (Some(&set), Some(&binding)) => (set, binding),
+            _ => continue,
+        };
+        let name = names
+            .get(&variable)
+            .filter(|name| !name.is_empty())
+            .or_else(|| names.get(&pointee))
+            .cloned()
+            .unwrap_or_else(|| format!("binding {}", binding));
+        match (storage, types.get(&pointee)) {
+            (STORAGE_UNIFORM_CONSTANT, Some(Type::SampledImage)) => {
+                reflection.samplers.push(Sampler { name, set, binding })
+            }
+            (STORAGE_UNIFORM, Some(Type::Struct(member_types)))
+                if decorations.contains_key(&(pointee, DECORATION_BLOCK)) =>
+            {
+                let members: Vec<Member> = member_types
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, &member_type)| {
+                        let index = index as u32;
+                        let matrix_stride = member_decorations
+                            .get(&(pointee, index, DECORATION_MATRIX_STRIDE))
+                            .copied()
+                            .unwrap_or(16);
+                        let kind =
+                            kind(&types, &constants, &decorations, member_type, matrix_stride)?;
+                        Some(Member {
+                            name: member_names
+                                .get(&(pointee, index))
+                                .cloned()
+                                .unwrap_or_else(|| format!("member {}", index)),
+                            offset: member_decorations
+                                .get(&(pointee, index, DECORATION_OFFSET))
+                                .copied()?,
+                            kind,
+                        })
+                    })
+                    .collect();
+                let size = members
+                    .iter()
+                    .map(|member| member.offset + member.kind.size())
+                    .max()
+                    .unwrap_or(0);
+                reflection.blocks.push(Block {
+                    name,
+                    set,
+                    binding,
+                    size,
+                    members,
+                });
+            }
+            _ => {}
+        }
+    }
+    reflection
+        .blocks
+        .sort_by_key(|block| (block.set, block.binding));
+    reflection
+        .samplers
+        .sort_by_key(|sampler| (sampler.set, sampler.binding));
+    Ok(reflection)
+}
+
+/// The kind of a block member's type, or `None` if it isn't made of numbers, like a nested struct
+fn kind(
+    types: &HashMap<u32, Type>,
+    constants: &HashMap<u32, u32>,
+    decorations: &HashMap<(u32, u32), u32>,
+    id: u32,
+    matrix_stride: u32,
+) -> Option<Kind> {
+    let scalar = |id: u32| match types.get(&id) {
+        Some(Type::Scalar(scalar)) => Some(*scalar),
+        _ => None,
+    };
+    let single = |scalar, components, columns| Kind {
+        scalar,
+        components,
+        columns,
+        matrix_stride,
+        elements: 1,
+        array_stride: 0,
+    };
+    match types.get(&id)? {
+        Type::Scalar(scalar) => Some(single(*scalar, 1, 1)),
+        Type::Vector(component, count) => Some(single(scalar(*component)?, *count, 1)),
+        Type::Matrix(column, columns) => match types.get(column)? {
+            Type::Vector(component, count) => Some(single(scalar(*component)?, *count, *columns)),
+            _ => None,
+        },
+        Type::Array(element, length) => {
+            let element = kind(types, constants, decorations, *element, matrix_stride)?;
+            if element.elements != 1 {
+                return None;
+            }
+            Some(Kind {
+                elements: *constants.get(length)?,
+                array_stride: *decorations.get(&(id, DECORATION_ARRAY_STRIDE))?,
+                ..element
+            })
+        }
+        _ => None,
+    }
+}
+
+/// A nul terminated string packed four bytes to a word
+fn string(words: &[u32]) -> String {
+    let bytes: Vec<u8> = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .take_while(|&byte| byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
@@ -0,0 +1,213 @@
+use ash::vk;
+
+/// The counters each query collects, in the order Vulkan writes them: by their bit in the flags
+pub const FLAGS: vk::QueryPipelineStatisticFlags = vk::QueryPipelineStatisticFlags::from_raw(
+    vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_VERTICES.as_raw()
+        | vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_PRIMITIVES.as_raw()
+        | vk::QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS.as_raw()
+        | vk::QueryPipelineStatisticFlags::CLIPPING_INVOCATIONS.as_raw()
+        | vk::QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES.as_raw()
+        | vk::QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS.as_raw()
+        | vk::QueryPipelineStatisticFlags::COMPUTE_SHADER_INVOCATIONS.as_raw(),
+);
+const COUNTERS: usize = 7;
+const COUNTER_NAMES: [&str; COUNTERS] = [
+    "vertices",
+    "primitives",
+    "vertex shader invocations",
+    "primitives reaching clipping",
+    "primitives after clipping",
+    "fragment shader invocations",
+    "compute shader invocations",
+];
+
+/// The parts of a frame counted separately, each from the start of one to the start of the next
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pass {
+    /// Compute work recorded before the render pass, culling the object grid and simulating cloth and swarms
+    Compute,
+    /// The render pass drawing the scene, including the features and hooks drawn after opaque geometry
+    Scene,
+    BeforePost,
+    PostProcess,
+    Ui,
+}
+
+impl Pass {
+    pub const ALL: [Pass; 5] = [
+        Pass::Compute,
+        Pass::Scene,
+        Pass::BeforePost,
+        Pass::PostProcess,
+        Pass::Ui,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pass::Compute => "compute",
+            Pass::Scene => "scene",
+            Pass::BeforePost => "before post-processing",
+            Pass::PostProcess => "post-processing",
+            Pass::Ui => "ui",
+        }
+    }
+
+    /// The pass's place in the frame, which is also its query's index in pools with a query per pass
+    pub fn query(&self) -> u32 {
+        Pass::ALL
+            .iter()
+            .position(|pass| pass == self)
+            .expect("Every pass is listed") as u32
+    }
+}
+
+/// The number of vertices, primitives and shader invocations a pass processed
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Counts([u64; COUNTERS]);
+
+impl Counts {
+    pub fn describe(&self) -> String {
+        let counts: Vec<String> = self
+            .0
+            .iter()
+            .zip(COUNTER_NAMES.iter())
+            .map(|(count, name)| format!("{} {}", count, name))
+            .collect();
+        counts.join(", ")
+    }
+}
+
+/// Pipeline statistics queries around each pass of the frame, averaged over the frames since they were last
+/// taken. Each swapchain image has a pool of its own, read back once the image's previous frame has finished so
+/// reading never waits on the GPU.
+pub struct PipelineStatistics {
+    pools: Vec<vk::QueryPool>,
+    /// Whether each image's queries have been recorded since the pool was created, so have results to read
+    recorded: Vec<bool>,
+    totals: [Counts; Pass::ALL.len()],
+    frames: u64,
+}
+
+impl PipelineStatistics {
+    pub fn supported(features: &vk::PhysicalDeviceFeatures) -> bool {
+        features.pipeline_statistics_query == vk::TRUE
+    }
+
+    pub fn new(device: &ash::Device, image_count: usize) -> Self {
+        let create_info = vk::QueryPoolCreateInfo::builder()
+            .query_type(vk::QueryType::PIPELINE_STATISTICS)
+            .query_count(Pass::ALL.len() as u32)
+            .pipeline_statistics(FLAGS);
+        let pools = (0..image_count)
+            .map(|_| unsafe {
+                device
+                    .create_query_pool(&create_info, None)
+                    .expect("Creating pipeline statistics query pool")
+            })
+            .collect();
+
+        Self {
+            pools,
+            recorded: vec![false; image_count],
+            totals: Default::default(),
+            frames: 0,
+        }
+    }
+
+    /// Adds the results of the image's previous frame to the totals. Must only be called once the image's
+    /// previous commands have finished, and before they are recorded again.
+    pub fn collect(&mut self, device: &ash::Device, image_index: usize) {
+        if !self.recorded[image_index] {
+            return;
+        }
+        let mut results = [[0u64; COUNTERS]; Pass::ALL.len()];
+        let read = unsafe {
+            device.get_query_pool_results(
+                self.pools[image_index],
+                0,
+                Pass::ALL.len() as u32,
+                &mut results,
+                vk::QueryResultFlags::TYPE_64,
+            )
+        };
+        // Results that aren't ready are left out rather than waited for
+        if read.is_err() {
+            return;
+        }
+        for (total, counts) in self.totals.iter_mut().zip(results.iter()) {
+            for (total, count) in total.0.iter_mut().zip(counts.iter()) {
+                *total += count;
+            }
+        }
+        self.frames += 1;
+    }
+
+    /// Resets the image's queries, ready for its passes to be recorded. Must be recorded outside a render pass.
+    pub fn begin_frame(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+    ) {
+        unsafe {
+            device.cmd_reset_query_pool(
+                command_buffer,
+                self.pools[image_index],
+                0,
+                Pass::ALL.len() as u32,
+            )
+        };
+        self.recorded[image_index] = true;
+    }
+
+    pub fn begin(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+        pass: Pass,
+    ) {
+        unsafe {
+            device.cmd_begin_query(
+                command_buffer,
+                self.pools[image_index],
+                pass.query(),
+                vk::QueryControlFlags::empty(),
+            )
+        };
+    }
+
+    pub fn end(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+        pass: Pass,
+    ) {
+        unsafe { device.cmd_end_query(command_buffer, self.pools[image_index], pass.query()) };
+    }
+
+    /// The average counts of each pass per frame since they were last taken
+    pub fn take_averages(&mut self) -> Vec<(&'static str, Counts)> {
+        let frames = self.frames.max(1);
+        self.frames = 0;
+        let totals = std::mem::take(&mut self.totals);
+        Pass::ALL
+            .iter()
+            .zip(totals.iter())
+            .map(|(pass, total)| {
+                let mut average = *total;
+                for count in average.0.iter_mut() {
+                    *count /= frames;
+                }
+                (pass.name(), average)
+            })
+            .collect()
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        for pool in self.pools.drain(..) {
+            unsafe { device.destroy_query_pool(pool, None) };
+        }
+    }
+}
@@ -0,0 +1,374 @@
+use std::path::Path;
+
+use ash::vk;
+use cgmath::{Matrix4, Vector3};
+
+use crate::features::SwapchainContext;
+use crate::{pipeline, playground, transition, util};
+
+/// How the two eyes' views are put together into the frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// The left eye's red over the right eye's green and blue, for red/cyan glasses
+    Anaglyph,
+    /// The left eye in the left half of the frame and the right eye in the right half, for displays and viewers
+    /// that show each half to one eye
+    SideBySide,
+}
+
+impl Mode {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "anaglyph" => Some(Mode::Anaglyph),
+            "side-by-side" => Some(Mode::SideBySide),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Settings {
+    pub mode: Mode,
+    /// Distance between the eyes in world units
+    pub separation: f32,
+}
+
+impl Settings {
+    /// The part of the frame each eye is drawn into
+    pub fn area(&self, eye: Eye, extent: vk::Extent2D) -> vk::Rect2D {
+        let half = extent.width / 2;
+        match (self.mode, eye) {
+            (Mode::Anaglyph, _) => vk::Rect2D::builder().extent(extent).build(),
+            (Mode::SideBySide, Eye::Left) => vk::Rect2D::builder()
+                .extent(vk::Extent2D {
+                    width: half,
+                    height: extent.height,
+                })
+                .build(),
+            (Mode::SideBySide, Eye::Right) => vk::Rect2D {
+                offset: vk::Offset2D {
+                    x: half as i32,
+                    y: 0,
+                },
+                extent: vk::Extent2D {
+                    width: extent.width - half,
+                    height: extent.height,
+                },
+            },
+        }
+    }
+
+    /// How much narrower each eye's view is than the frame's
+    pub fn aspect_scale(&self) -> f32 {
+        match self.mode {
+            Mode::Anaglyph => 1.0,
+            Mode::SideBySide => 0.5,
+        }
+    }
+
+    /// The eye's view and projection from the camera's. The eyes sit `separation` apart along the camera's x axis
+    /// and look the same way, and with a perspective projection each eye's frustum is shifted sideways so that
+    /// the two meet `convergence` in front of the camera, which then appears at the depth of the screen. Both
+    /// eyes see the camera's own view through an orthographic projection, which has no parallax to show.
+    pub fn eye(
+        &self,
+        eye: Eye,
+        view: Matrix4<f32>,
+        projection: Matrix4<f32>,
+        convergence: f32,
+        perspective: bool,
+    ) -> (Matrix4<f32>, Matrix4<f32>) {
+        if !perspective {
+            return (view, projection);
+        }
+        // Points are moved the opposite way to the eye
+        let offset = match eye {
+            Eye::Left => self.separation / 2.0,
+            Eye::Right => -self.separation / 2.0,
+        };
+        let eye_view = Matrix4::from_translation(Vector3::new(offset, 0.0, 0.0)) * view;
+        // Adds a multiple of w to clip space x, which moves a point at the convergence distance back to the middle
+        let shift = -projection.x.x * offset / convergence.max(f32::EPSILON);
+        let eye_projection = Matrix4::from_translation(Vector3::new(shift, 0.0, 0.0)) * projection;
+        (eye_view, eye_projection)
+    }
+}
+
+/// The left eye's own copies of the scene's uniform buffers and descriptor sets, one for each swapchain image,
+/// created by the renderer like its own.
+pub struct LeftEye {
+    pub uniform_buffers: Vec<vk::Buffer>,
+    pub uniform_buffers_memory: Vec<vk::DeviceMemory>,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_sets: Vec<vk::DescriptorSet>,
+}
+
+/// Stereo output without VR hardware. The right eye is drawn by the frame's render pass as the scene usually is,
+/// and the left eye is drawn before it into a texture of its own with a render pass compatible with the
+/// renderer's, so the scene's pipelines draw into it unchanged. The left eye is then copied over the frame
+/// after the scene's draws: into the red channel for anaglyphs, or into the left half side by side.
+///
+/// The left eye is the scene's draws only, as GPU driven objects, the playground and the renderer's features
+/// are drawn once into the frame.
+pub struct Stereo {
+    pub settings: Settings,
+    pub left: LeftEye,
+    extent: vk::Extent2D,
+    render_pass: vk::RenderPass,
+    color: (vk::Image, vk::DeviceMemory, vk::ImageView),
+    depth: (vk::Image, vk::DeviceMemory, vk::ImageView),
+    framebuffer: vk::Framebuffer,
+    sampler: vk::Sampler,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+    layout: vk::PipelineLayout,
+    /// Copies the left eye over the frame, made for the frame's render pass `target`
+    pipeline: vk::Pipeline,
+}
+
+impl Stereo {
+    pub fn new(
+        context: &SwapchainContext,
+        target: &pipeline::Target,
+        settings: Settings,
+        left: LeftEye,
+    ) -> Self {
+        let device = context.device;
+        let extent = context.extent;
+        let render_pass = pipeline::create_offscreen_render_pass(
+            device,
+            context.format,
+            context.depth_format,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            // The copy samples the texture in the frame's render pass
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::AccessFlags::SHADER_READ,
+            &pipeline::PassConfig::default(),
+        );
+        let color = transition::create_attachment(
+            context,
+            context.format,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            vk::ImageAspectFlags::COLOR,
+        );
+        let depth = transition::create_attachment(
+            context,
+            context.depth_format,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            vk::ImageAspectFlags::DEPTH,
+        );
+        let attachments = [color.2, depth.2];
+        let framebuffer = unsafe {
+            device
+                .create_framebuffer(
+                    &vk::FramebufferCreateInfo::builder()
+                        .render_pass(render_pass)
+                        .attachments(&attachments)
+                        .width(extent.width)
+                        .height(extent.height)
+                        .layers(1),
+                    None,
+                )
+                .expect("Stereo frame buffer")
+        };
+
+        let sampler = unsafe {
+            device
+                .create_sampler(
+                    &vk::SamplerCreateInfo::builder()
+                        .mag_filter(vk::Filter::NEAREST)
+                        .min_filter(vk::Filter::NEAREST)
+                        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+                    None,
+                )
+                .expect("Stereo sampler")
+        };
+        let bindings = [vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build()];
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+                    None,
+                )
+                .expect("Stereo descriptor set layout")
+        };
+        let pool_sizes = [vk::DescriptorPoolSize::builder()
+            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .build()];
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::builder()
+                        .pool_sizes(&pool_sizes)
+                        .max_sets(1),
+                    None,
+                )
+                .expect("Stereo descriptor pool")
+        };
+        let set_layouts = [descriptor_set_layout];
+        let descriptor_set = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::builder()
+                        .descriptor_pool(descriptor_pool)
+                        .set_layouts(&set_layouts),
+                )
+                .expect("Stereo descriptor set")[0]
+        };
+        let image_info = [vk::DescriptorImageInfo::builder()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(color.2)
+            .sampler(sampler)
+            .build()];
+        let writes = [vk::WriteDescriptorSet::builder()
+            .dst_set(descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_info)
+            .build()];
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+
+        let layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::builder().set_layouts(&set_layouts),
+                    None,
+                )
+                .expect("Stereo pipeline layout")
+        };
+        let write_mask = match settings.mode {
+            Mode::Anaglyph => vk::ColorComponentFlags::R,
+            Mode::SideBySide => vk::ColorComponentFlags::all(),
+        };
+        let path = Path::new(env!("OUT_DIR")).join("stereo_frag.spv");
+        let pipeline = playground::create_masked_pipeline(
+            device,
+            target,
+            layout,
+            &util::read_shader_code(&path),
+            false,
+            false,
+            write_mask,
+        );
+
+        Self {
+            settings,
+            left,
+            extent,
+            render_pass,
+            color,
+            depth,
+            framebuffer,
+            sampler,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            layout,
+            pipeline,
+        }
+    }
+
+    /// Records a render pass that draws the left eye into its texture. `record` is given the command buffer
+    /// inside the render pass and records the scene's draws. Must be called outside a render pass, before the
+    /// frame's render pass.
+    pub fn record_left(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        clear_values: &[vk::ClearValue],
+        record: impl FnOnce(vk::CommandBuffer),
+    ) {
+        let render_pass_bi = vk::RenderPassBeginInfo::builder()
+            .render_pass(self.render_pass)
+            .framebuffer(self.framebuffer)
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: self.extent,
+            })
+            .clear_values(clear_values);
+        unsafe {
+            device.cmd_begin_render_pass(
+                command_buffer,
+                &render_pass_bi,
+                vk::SubpassContents::INLINE,
+            )
+        };
+        record(command_buffer);
+        unsafe { device.cmd_end_render_pass(command_buffer) };
+    }
+
+    /// Records copying the left eye over the frame, inside the frame's render pass. Leaves the viewport set to the
+    /// left eye's area and the copy's descriptor set bound.
+    pub fn record_composite(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        let area = self.settings.area(Eye::Left, self.extent);
+        // The full-screen triangle covers the viewport
+        let viewports = [vk::Viewport::builder()
+            .x(area.offset.x as f32)
+            .y(area.offset.y as f32)
+            .width(area.extent.width as f32)
+            .height(area.extent.height as f32)
+            .min_depth(0.0)
+            .max_depth(1.0)
+            .build()];
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            device.cmd_set_viewport(command_buffer, 0, &viewports);
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            device.destroy_sampler(self.sampler, None);
+            device.destroy_framebuffer(self.framebuffer, None);
+            for &(image, memory, view) in [self.color, self.depth].iter() {
+                device.destroy_image_view(view, None);
+                device.destroy_image(image, None);
+                device.free_memory(memory, None);
+            }
+            device.destroy_render_pass(self.render_pass, None);
+
+            device.destroy_descriptor_pool(self.left.descriptor_pool, None);
+            for (&buffer, &memory) in self
+                .left
+                .uniform_buffers
+                .iter()
+                .zip(self.left.uniform_buffers_memory.iter())
+            {
+                device.destroy_buffer(buffer, None);
+                device.free_memory(memory, None);
+            }
+        }
+    }
+}
@@ -0,0 +1,384 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::thread;
+
+use cgmath::{Matrix4, Point3, Vector3};
+
+use crate::{batch, material, mesh, HelloTriangleApplication};
+
+/// The first line of every world file, naming the version of the format
+const HEADER: &str = "rust-renderer-vk world 1";
+/// Boxes merged into each of a cell's meshes, few enough that their vertices fit `u16` indices
+const BOXES_PER_MESH: usize = 2048;
+
+/// A square of the ground plane, counted in cells from the origin along x and y
+pub type Cell = (i32, i32);
+
+/// Where each of a cell's boxes start in the world file, and the line it is on
+type BoxOffsets = Vec<(u64, usize)>;
+
+/// A cell's meshes once the loader thread has read them, or why it couldn't
+type LoadedCell = (Cell, Result<Vec<mesh::MeshData>, String>);
+
+/// A box of the world, centred on a point with a size along each axis
+#[derive(Clone, Copy, Debug)]
+struct WorldBox {
+    center: Point3<f32>,
+    size: [f32; 3],
+}
+
+/// A world too large to load at once, streamed in around the camera. The world's file is indexed when it is
+/// opened, noting where each cell's boxes are without reading them, and the cells near the camera are loaded on
+/// a thread of their own. Cells that get too far away are unloaded by removing their draws, which frees their
+/// meshes through the asset garbage collection pass.
+///
+/// World files are text, one box per line, laid out on the ground plane in square cells:
+///
+/// ```text
+/// rust-renderer-vk world 1
+/// cell_size <size>
+/// box <centre x> <y> <z> <width> <depth> <height>
+/// ```
+///
+/// Boxes belong to the cell their centre is in. Lines starting with `#` are ignored.
+pub struct World {
+    cell_size: f32,
+    /// Where each of the cell's boxes start in the file, and the line it is on
+    index: HashMap<Cell, BoxOffsets>,
+    /// Cells are loaded when their centre is this close to the camera, and unloaded a cell further out so that
+    /// cells at the edge don't load and unload over and over
+    pub load_radius: f32,
+    /// The scene streamed cells are drawn in
+    pub scene: usize,
+    /// Cells waiting to be loaded by the thread
+    loading: HashSet<Cell>,
+    /// Draws of the cells that are loaded
+    loaded: HashMap<Cell, Vec<usize>>,
+    /// Draws of unloaded cells, which are empty and can be reused by cells loaded later
+    free_draws: Vec<usize>,
+    requests: Option<mpsc::Sender<(Cell, BoxOffsets)>>,
+    results: mpsc::Receiver<LoadedCell>,
+    loader: Option<thread::JoinHandle<()>>,
+}
+
+impl World {
+    /// Indexes the world file's cells, ready to stream them into the scene
+    pub fn open(path: &Path, load_radius: f32, scene: usize) -> Result<Self, String> {
+        let file =
+            fs::File::open(path).map_err(|e| format!("Opening {}: {}", path.display(), e))?;
+        let (cell_size, index) =
+            index(BufReader::new(file)).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        let (requests, received) = mpsc::channel();
+        let (finished, results) = mpsc::channel();
+        let path = PathBuf::from(path);
+        let loader = thread::spawn(move || load_cells(&path, received, finished));
+
+        Ok(Self {
+            cell_size,
+            index,
+            load_radius,
+            scene,
+            loading: HashSet::new(),
+            loaded: HashMap::new(),
+            free_draws: Vec::new(),
+            requests: Some(requests),
+            results,
+            loader: Some(loader),
+        })
+    }
+
+    /// How many cells are waiting for the thread to load them
+    pub fn queued(&self) -> usize {
+        self.loading.len()
+    }
+
+    /// How many cells are loaded, and how many the world has
+    pub fn cell_counts(&self) -> (usize, usize) {
+        (self.loaded.len(), self.index.len())
+    }
+
+    /// Starts loading the cells around `center`, a point on the world's ground plane, and unloads those that have
+    /// got too far from it. Returns the draws of the unloaded cells, which should be removed.
+    pub fn update(&mut self, center: Point3<f32>) -> Vec<usize> {
+        let cell_size = self.cell_size;
+        let cell_center = |&(x, y): &Cell| {
+            Point3::new(
+                (x as f32 + 0.5) * cell_size,
+                (y as f32 + 0.5) * cell_size,
+                center.z,
+            )
+        };
+        let distance = |cell: &Cell| {
+            let offset = cell_center(cell) - center;
+            (offset.x * offset.x + offset.y * offset.y).sqrt()
+        };
+
+        let unload_radius = self.load_radius + self.cell_size;
+        let far: Vec<Cell> = self
+            .loaded
+            .keys()
+            .chain(self.loading.iter())
+            .filter(|cell| distance(cell) > unload_radius)
+            .copied()
+            .collect();
+        let mut unloaded = Vec::new();
+        for cell in far {
+            // Cells still loading are dropped when they finish
+            self.loading.remove(&cell);
+            if let Some(draws) = self.loaded.remove(&cell) {
+                unloaded.extend(draws);
+            }
+        }
+        self.free_draws.extend(unloaded.iter().copied());
+
+        let reach = (self.load_radius / self.cell_size).ceil() as i32 + 1;
+        let (center_x, center_y) = self.cell_at(center);
+        for x in center_x - reach..=center_x + reach {
+            for y in center_y - reach..=center_y + reach {
+                let cell = (x, y);
+                if distance(&cell) > self.load_radius
+                    || self.loaded.contains_key(&cell)
+                    || self.loading.contains(&cell)
+                {
+                    continue;
+                }
+                if let (Some(boxes), Some(requests)) = (self.index.get(&cell), &self.requests) {
+                    if requests.send((cell, boxes.clone())).is_ok() {
+                        self.loading.insert(cell);
+                    }
+                }
+            }
+        }
+
+        unloaded
+    }
+
+    /// The meshes of the cells the thread has finished loading since this was last called, leaving out cells
+    /// that were unloaded before they finished. Each cell must be given its draws with `placed`.
+    pub fn finished(&mut self) -> Vec<LoadedCell> {
+        let mut finished = Vec::new();
+        while let Ok((cell, meshes)) = self.results.try_recv() {
+            if self.loading.remove(&cell) {
+                finished.push((cell, meshes));
+            }
+        }
+        finished
+    }
+
+    /// Records the draws a loaded cell's meshes were added as, so they can be removed when it is unloaded
+    pub fn placed(&mut self, cell: Cell, draws: Vec<usize>) {
+        self.loaded.insert(cell, draws);
+    }
+
+    /// An empty draw left by an unloaded cell, to reuse rather than adding another
+    pub fn take_free_draw(&mut self) -> Option<usize> {
+        self.free_draws.pop()
+    }
+
+    fn cell_at(&self, point: Point3<f32>) -> Cell {
+        cell_at(self.cell_size, point)
+    }
+}
+
+impl Drop for World {
+    fn drop(&mut self) {
+        // Closing the channel stops the thread once it has finished the cell it is loading
+        self.requests = None;
+        if let Some(loader) = self.loader.take() {
+            let _ = loader.join();
+        }
+    }
+}
+
+/// The material every streamed box is drawn with
+pub fn material() -> material::Material {
+    material::Material {
+        vertex_color: material::VertexColorMode::Multiply,
+        ..material::Material::default()
+    }
+}
+
+fn cell_at(cell_size: f32, point: Point3<f32>) -> Cell {
+    (
+        (point.x / cell_size).floor() as i32,
+        (point.y / cell_size).floor() as i32,
+    )
+}
+
+/// Reads the world file's header and the cells of its boxes, returning the cell size and where each cell's boxes
+/// are in the file
+fn index(mut reader: impl BufRead) -> Result<(f32, HashMap<Cell, BoxOffsets>), String> {
+    let mut header = false;
+    let mut cell_size = None;
+    let mut index: HashMap<Cell, BoxOffsets> = HashMap::new();
+    let mut line = String::new();
+    let (mut offset, mut number) = (0, 0);
+    loop {
+        line.clear();
+        let read = reader
+            .read_line(&mut line)
+            .map_err(|e| format!("Reading line {}: {}", number + 1, e))?;
+        if read == 0 {
+            break;
+        }
+        let start = offset;
+        offset += read as u64;
+        number += 1;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if !header {
+            if trimmed != HEADER {
+                return Err(format!("World files start with \"{}\"", HEADER));
+            }
+            header = true;
+            continue;
+        }
+        let mut fields = trimmed.split_whitespace();
+        match fields.next().unwrap_or_default() {
+            "cell_size" => {
+                let size: f32 = parse(number, fields.next().unwrap_or_default())?;
+                if size <= 0.0 {
+                    return Err(format!("Line {}: cells must have a size", number));
+                }
+                cell_size = Some(size);
+            }
+            "box" => {
+                let cell_size = cell_size
+                    .ok_or_else(|| format!("Line {}: the cell size must come first", number))?;
+                let world_box = parse_box(number, trimmed)?;
+                index
+                    .entry(cell_at(cell_size, world_box.center))
+                    .or_default()
+                    .push((start, number));
+            }
+            other => return Err(format!("Line {}: unknown setting {}", number, other)),
+        }
+    }
+    match cell_size {
+        Some(cell_size) => Ok((cell_size, index)),
+        None => Err(String::from("The world has no cell size")),
+    }
+}
+
+fn parse_box(number: usize, line: &str) -> Result<WorldBox, String> {
+    let mut fields = line.split_whitespace().skip(1);
+    let mut values = [0.0; 6];
+    for value in values.iter_mut() {
+        let field = fields
+            .next()
+            .ok_or_else(|| format!("Line {} is missing part of the box", number))?;
+        *value = parse(number, field)?;
+    }
+    Ok(WorldBox {
+        center: Point3::new(values[0], values[1], values[2]),
+        size: [values[3], values[4], values[5]],
+    })
+}
+
+fn parse<T: FromStr>(number: usize, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Line {}: {} isn't a valid number", number, value))
+}
+
+/// Loads the cells requested until the world is dropped, reading each cell's boxes from the file and merging
+/// them into meshes
+fn load_cells(
+    path: &Path,
+    requests: mpsc::Receiver<(Cell, BoxOffsets)>,
+    finished: mpsc::Sender<LoadedCell>,
+) {
+    let mut reader = match fs::File::open(path) {
+        Ok(file) => BufReader::new(file),
+        Err(e) => {
+            println!("Not streaming {}: {}", path.display(), e);
+            return;
+        }
+    };
+    for (cell, boxes) in requests {
+        let meshes = load_cell(&mut reader, &boxes);
+        if finished.send((cell, meshes)).is_err() {
+            return;
+        }
+    }
+}
+
+fn load_cell(
+    reader: &mut BufReader<fs::File>,
+    boxes: &[(u64, usize)],
+) -> Result<Vec<mesh::MeshData>, String> {
+    let mut line = String::new();
+    let boxes = boxes
+        .iter()
+        .map(|&(offset, number)| {
+            reader
+                .seek(SeekFrom::Start(offset))
+                .and_then(|_| {
+                    line.clear();
+                    reader.read_line(&mut line)
+                })
+                .map_err(|e| format!("Reading line {}: {}", number, e))?;
+            parse_box(number, &line)
+        })
+        .collect::<Result<Vec<WorldBox>, String>>()?;
+
+    let meshes = boxes
+        .chunks(BOXES_PER_MESH)
+        .flat_map(|chunk| {
+            let static_meshes = chunk
+                .iter()
+                .map(|world_box| batch::StaticMesh {
+                    mesh: HelloTriangleApplication::cuboid_mesh(world_box.size),
+                    material: material(),
+                    transform: Matrix4::from_translation(Vector3::new(
+                        world_box.center.x,
+                        world_box.center.y,
+                        world_box.center.z,
+                    )),
+                })
+                .collect();
+            // The boxes share a material and fit one batch, so each chunk bakes into a single mesh
+            batch::bake(static_meshes)
+                .into_iter()
+                .map(|baked| baked.mesh)
+        })
+        .collect();
+    Ok(meshes)
+}
+
+/// Writes a world of boxes to the path, `cells` cells along each side with a building on every `spacing` units
+pub fn write_example(path: &Path, cells: u32, cell_size: f32, spacing: f32) -> Result<(), String> {
+    let mut text = String::new();
+    // Writing to a string can't fail
+    let _ = writeln!(text, "{}", HEADER);
+    let _ = writeln!(text, "cell_size {}", cell_size);
+    let half = cells as f32 * cell_size / 2.0;
+    let across = (cells as f32 * cell_size / spacing) as u32;
+    for i in 0..across {
+        for j in 0..across {
+            // A cheap hash of the position varies the buildings' heights without a random number generator
+            let hash = (i.wrapping_mul(73_856_093) ^ j.wrapping_mul(19_349_663)) % 1000;
+            let height = spacing * (0.5 + 3.0 * hash as f32 / 1000.0);
+            let width = spacing * 0.6;
+            let _ = writeln!(
+                text,
+                "box {} {} {} {} {} {}",
+                -half + (i as f32 + 0.5) * spacing,
+                -half + (j as f32 + 0.5) * spacing,
+                -0.75 + height / 2.0,
+                width,
+                width,
+                height
+            );
+        }
+    }
+    fs::write(path, text).map_err(|e| format!("Writing {}: {}", path.display(), e))
+}
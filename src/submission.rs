@@ -0,0 +1,154 @@
+use ash::vk;
+
+use crate::{framegraph, present_thread, queue_batches};
+
+/// The batches a frame graph's passes are submitted in and the dependencies chaining them, worked out once for
+/// the graph rather than every frame
+pub struct Plan {
+    /// The queues the passes are on, in the order of the first pass on each
+    queues: Vec<framegraph::Queue>,
+    dependencies: Vec<framegraph::Dependency>,
+}
+
+impl Plan {
+    pub fn new(graph: &framegraph::Graph) -> Self {
+        let queues = graph.queues();
+        let dependencies = graph.dependencies();
+        // Binary semaphores have to be signalled by an earlier submission than the one waiting on them
+        let order = |queue| queues.iter().position(|&planned| planned == queue);
+        for dependency in dependencies.iter() {
+            assert!(
+                order(dependency.from) < order(dependency.to),
+                "{:?} work depends on {:?} work after it",
+                dependency.to,
+                dependency.from
+            );
+        }
+        Self {
+            queues,
+            dependencies,
+        }
+    }
+}
+
+/// A frame's command buffers, submitted with as few `vkQueueSubmit` calls as its queues allow. Each queue the
+/// frame graph has passes on gets one batch of command buffers, in the order of its first pass, and the batches
+/// are chained by a semaphore for each of the graph's dependencies between queues. Consecutive batches for the
+/// same Vulkan queue, such as compute and graphics on a device without a separate compute family, share a call.
+///
+/// Each frame in flight keeps its submission from one frame to the next, and `reset` empties it for the frame's
+/// command buffers, so that submitting doesn't allocate the batches every frame. See `queue_batches::Batches`.
+pub struct Submission {
+    /// The queue of each batch
+    queues: Vec<framegraph::Queue>,
+    batches: queue_batches::Batches,
+}
+
+impl Submission {
+    /// Batches for the plan's queues, each submitted to the Vulkan queue `handle` gives for it. The semaphores
+    /// chaining them are the frame's from `semaphores`.
+    pub fn new(
+        device: &ash::Device,
+        plan: &Plan,
+        handle: impl Fn(framegraph::Queue) -> vk::Queue,
+        semaphores: &mut Semaphores,
+        frame: usize,
+    ) -> Self {
+        let mut submission = Self {
+            queues: plan.queues.clone(),
+            batches: queue_batches::Batches::new(plan.queues.iter().map(|&queue| handle(queue))),
+        };
+        for (index, dependency) in plan.dependencies.iter().enumerate() {
+            let semaphore = semaphores.get(device, frame, index);
+            let (from, to) = (
+                submission.position(dependency.from),
+                submission.position(dependency.to),
+            );
+            submission
+                .batches
+                .chain(from, to, semaphore, dependency.stage);
+        }
+        submission
+    }
+
+    /// Takes away the last frame's command buffers, fence, and waits and signals from outside the frame, keeping
+    /// the semaphores chaining the batches
+    pub fn reset(&mut self) {
+        self.batches.reset();
+    }
+
+    pub fn command_buffer(&mut self, queue: framegraph::Queue, command_buffer: vk::CommandBuffer) {
+        let position = self.position(queue);
+        self.batches.command_buffer(position, command_buffer);
+    }
+
+    /// Makes the queue's batch wait on a semaphore from outside the frame, such as the swapchain image's
+    pub fn wait(
+        &mut self,
+        queue: framegraph::Queue,
+        semaphore: vk::Semaphore,
+        stage: vk::PipelineStageFlags,
+    ) {
+        let position = self.position(queue);
+        self.batches.wait(position, semaphore, stage);
+    }
+
+    pub fn signal(&mut self, queue: framegraph::Queue, semaphore: vk::Semaphore) {
+        let position = self.position(queue);
+        self.batches.signal(position, semaphore);
+    }
+
+    /// Signals the fence once the queue's batch has finished. Only one batch of each call can have a fence.
+    pub fn fence(&mut self, queue: framegraph::Queue, fence: vk::Fence) {
+        let position = self.position(queue);
+        self.batches.fence(position, fence);
+    }
+
+    /// Submits the batches in order, grouping consecutive batches for the same Vulkan queue into one call
+    pub fn submit(&self, device: &ash::Device) -> Result<(), vk::Result> {
+        self.batches.submit(|handle, submit_infos, fence| {
+            let _queues = present_thread::lock_queues();
+            unsafe { device.queue_submit(handle, submit_infos, fence) }
+        })
+    }
+
+    fn position(&self, queue: framegraph::Queue) -> usize {
+        self.queues
+            .iter()
+            .position(|&batch| batch == queue)
+            .unwrap_or_else(|| panic!("The frame graph has no passes on the {:?} queue", queue))
+    }
+}
+
+/// The semaphores chaining each frame in flight's batches, made as the frame graph needs them. A frame's
+/// semaphores are reused once its fence has been waited on and the present queue is idle, as `draw_frame` does
+/// before recording the frame again, so every wait on them has happened.
+pub struct Semaphores {
+    frames: Vec<Vec<vk::Semaphore>>,
+}
+
+impl Semaphores {
+    pub fn new(frames_in_flight: usize) -> Self {
+        Self {
+            frames: vec![Vec::new(); frames_in_flight],
+        }
+    }
+
+    fn get(&mut self, device: &ash::Device, frame: usize, index: usize) -> vk::Semaphore {
+        let semaphores = &mut self.frames[frame];
+        while semaphores.len() <= index {
+            semaphores.push(unsafe {
+                device
+                    .create_semaphore(&vk::SemaphoreCreateInfo::builder(), None)
+                    .expect("Submission semaphore")
+            });
+        }
+        semaphores[index]
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        for &semaphore in self.frames.iter().flatten() {
+            unsafe { device.destroy_semaphore(semaphore, None) };
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use ash::vk;
+
+/// Surface formats, colour spaces and present modes to use instead of the ones the renderer would choose, for
+/// reproducing flicker, gamma or stutter that only shows up with a particular driver's choices. Each is the
+/// Vulkan name without its prefix and in any case, such as `B8G8R8A8_UNORM`, `SRGB_NONLINEAR` or `fifo_relaxed`,
+/// and is only used if the surface supports it. An override the surface doesn't support is reported and dropped,
+/// and the renderer's own choice is used instead.
+#[derive(Clone, Debug, Default)]
+pub struct Overrides {
+    pub format: Option<String>,
+    pub color_space: Option<String>,
+    pub present_mode: Option<String>,
+}
+
+/// Whether the value's Vulkan name is the one asked for
+fn named<T: std::fmt::Debug>(value: &T, name: &str) -> bool {
+    format!("{:?}", value).eq_ignore_ascii_case(name)
+}
+
+fn names<T: std::fmt::Debug>(values: impl Iterator<Item = T>) -> String {
+    values
+        .map(|value| format!("{:?}", value))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl Overrides {
+    /// The surface format matching the format and colour space overrides, when there are any. Either override
+    /// alone picks the first format the surface lists with it.
+    pub fn surface_format(
+        &mut self,
+        available: &[vk::SurfaceFormatKHR],
+    ) -> Option<vk::SurfaceFormatKHR> {
+        if self.format.is_none() && self.color_space.is_none() {
+            return None;
+        }
+        let found = available.iter().copied().find(|available| {
+            let format = self.format.as_ref();
+            let color_space = self.color_space.as_ref();
+            format.is_none_or(|name| named(&available.format, name))
+                && color_space.is_none_or(|name| named(&available.color_space, name))
+        });
+        if found.is_none() {
+            println!(
+                "The surface has no {} {} format, choosing one instead of [{}]",
+                self.format.take().unwrap_or_default(),
+                self.color_space.take().unwrap_or_default(),
+                names(
+                    available
+                        .iter()
+                        .map(|available| (available.format, available.color_space))
+                )
+            );
+        }
+        found
+    }
+
+    /// The present mode override, if there is one and the surface supports it
+    pub fn present_mode(&mut self, available: &[vk::PresentModeKHR]) -> Option<vk::PresentModeKHR> {
+        let name = self.present_mode.as_ref()?;
+        let found = available.iter().copied().find(|mode| named(mode, name));
+        if found.is_none() {
+            println!(
+                "The surface can't present with {}, choosing a present mode instead of [{}]",
+                name,
+                names(available.iter())
+            );
+            self.present_mode = None;
+        }
+        found
+    }
+}
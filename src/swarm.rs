@@ -0,0 +1,455 @@
+use std::f32::consts::PI;
+use std::mem::size_of;
+use std::path::Path;
+use std::time::Instant;
+
+use ash::vk;
+use cgmath::{Point3, Vector3};
+
+use crate::{
+    bounds, draw, indirect, lightmap, material, mesh, pipeline, postprocess, util,
+    HelloTriangleApplication,
+};
+
+/// Agents updated per workgroup, matching `local_size_x` in `swarm_comp.glsl`
+const WORKGROUP_SIZE: u32 = 64;
+/// The longest time simulated in a frame, so a slow frame doesn't send agents flying out of the region
+const MAX_FRAME_TIME: f32 = 1.0 / 30.0;
+/// Matches `G` in `swarm_comp.glsl`
+const GRAVITATIONAL_CONSTANT: f32 = 0.05;
+
+/// How the agents of a `Swarm` move. The values match the constants in `swarm_comp.glsl`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Behaviour {
+    /// Boids that steer to keep with their neighbours without crowding them, inside a sphere
+    Flocking = 0,
+    /// Bodies pulled towards every other, starting as a spinning disk
+    Gravity = 1,
+}
+
+impl Behaviour {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "flocking" => Some(Behaviour::Flocking),
+            "gravity" => Some(Behaviour::Gravity),
+            _ => None,
+        }
+    }
+
+    /// Length of each agent's mesh
+    fn scale(&self) -> f32 {
+        match self {
+            Behaviour::Flocking => 0.03,
+            Behaviour::Gravity => 0.015,
+        }
+    }
+
+    fn color(&self) -> [f32; 3] {
+        match self {
+            Behaviour::Flocking => [0.9, 0.5, 0.2],
+            Behaviour::Gravity => [0.6, 0.7, 1.0],
+        }
+    }
+}
+
+/// Matches `Agent` in `swarm_comp.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Agent {
+    /// w is the agent's mass
+    position: [f32; 4],
+    velocity: [f32; 4],
+}
+
+/// Matches the push constants in `swarm_comp.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Step {
+    region: [f32; 4],
+    count: u32,
+    behaviour: u32,
+    delta_time: f32,
+    scale: f32,
+}
+
+impl Step {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+}
+
+/// Thousands of agents moved by a compute shader and drawn with one instanced draw, as a demo and a GPU stress
+/// test. Every agent is affected by every other, so the cost grows with the square of the count. Each step
+/// reads the agents from one state buffer and writes them to the other, along with a transform per agent in
+/// the layout of `indirect::Object` so that `pipeline::Program::Indirect` can draw them. Updates are recorded
+/// into the frame's command buffer ahead of the render pass.
+pub struct Swarm {
+    behaviour: Behaviour,
+    count: u32,
+    center: Point3<f32>,
+    radius: f32,
+    state_buffers: [(vk::Buffer, vk::DeviceMemory); 2],
+    instance_buffer: (vk::Buffer, vk::DeviceMemory),
+    vertex_buffer: (vk::Buffer, vk::DeviceMemory),
+    vertex_count: u32,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    /// Sets stepping from the first state buffer to the second and back
+    step_sets: [vk::DescriptorSet; 2],
+    /// Which of the step sets the next update uses
+    next_set: usize,
+    layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+    last_update: Option<Instant>,
+}
+
+impl Swarm {
+    /// `count` agents starting within `radius` of `center`
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        behaviour: Behaviour,
+        count: u32,
+        center: Point3<f32>,
+        radius: f32,
+    ) -> Self {
+        let count = count.max(1);
+        let agents = Self::initial_agents(behaviour, count, center, radius);
+        let state_buffers = [0, 1].map(|_| {
+            HelloTriangleApplication::create_device_local_buffer(
+                device,
+                &agents,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+                command_pool,
+                queue,
+                device_memory_properties,
+            )
+        });
+        // Written by the simulation before it is first drawn
+        let instance_buffer = HelloTriangleApplication::create_buffer(
+            device,
+            (count as usize * size_of::<indirect::Object>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::VERTEX_BUFFER,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            device_memory_properties,
+        );
+        let mesh = Self::agent_mesh(behaviour.color());
+        let vertex_buffer = HelloTriangleApplication::create_device_local_buffer(
+            device,
+            &mesh.interleave(),
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            command_pool,
+            queue,
+            device_memory_properties,
+        );
+
+        let bindings: Vec<vk::DescriptorSetLayoutBinding> = (0..3)
+            .map(|binding| {
+                vk::DescriptorSetLayoutBinding::builder()
+                    .binding(binding)
+                    .descriptor_count(1)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                    .build()
+            })
+            .collect();
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+                    None,
+                )
+                .expect("Swarm descriptor set layout")
+        };
+        let pool_sizes = [vk::DescriptorPoolSize::builder()
+            .ty(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(6)
+            .build()];
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::builder()
+                        .pool_sizes(&pool_sizes)
+                        .max_sets(2),
+                    None,
+                )
+                .expect("Swarm descriptor pool")
+        };
+        let set_layouts = [descriptor_set_layout; 2];
+        let sets = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::builder()
+                        .descriptor_pool(descriptor_pool)
+                        .set_layouts(&set_layouts),
+                )
+                .expect("Swarm descriptor sets")
+        };
+        let (first, second) = (state_buffers[0].0, state_buffers[1].0);
+        for (&set, buffers) in sets.iter().zip([
+            [first, second, instance_buffer.0],
+            [second, first, instance_buffer.0],
+        ]) {
+            let buffer_infos = buffers.map(|buffer| {
+                [vk::DescriptorBufferInfo::builder()
+                    .buffer(buffer)
+                    .offset(0)
+                    .range(vk::WHOLE_SIZE)
+                    .build()]
+            });
+            let writes: Vec<vk::WriteDescriptorSet> = buffer_infos
+                .iter()
+                .enumerate()
+                .map(|(binding, buffer_info)| {
+                    vk::WriteDescriptorSet::builder()
+                        .dst_set(set)
+                        .dst_binding(binding as u32)
+                        .dst_array_element(0)
+                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                        .buffer_info(buffer_info)
+                        .build()
+                })
+                .collect();
+            unsafe { device.update_descriptor_sets(&writes, &[]) };
+        }
+
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(size_of::<Step>() as u32)
+            .build()];
+        let set_layouts = [descriptor_set_layout];
+        let layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::builder()
+                        .set_layouts(&set_layouts)
+                        .push_constant_ranges(&push_constant_ranges),
+                    None,
+                )
+                .expect("Swarm pipeline layout")
+        };
+        let shader_path = Path::new(env!("OUT_DIR")).join("swarm_comp.spv");
+        let pipeline = postprocess::create_compute_pipeline(
+            device,
+            layout,
+            &util::read_shader_code(&shader_path),
+        );
+
+        Self {
+            behaviour,
+            count,
+            center,
+            radius,
+            state_buffers,
+            instance_buffer,
+            vertex_buffer,
+            vertex_count: mesh.positions.len() as u32,
+            descriptor_set_layout,
+            descriptor_pool,
+            step_sets: [sets[0], sets[1]],
+            next_set: 0,
+            layout,
+            pipeline,
+            last_update: None,
+        }
+    }
+
+    /// Flocking agents start scattered through the sphere heading every which way. Gravity starts from a disk
+    /// with each body moving fast enough to orbit the mass nearer the centre than it.
+    fn initial_agents(
+        behaviour: Behaviour,
+        count: u32,
+        center: Point3<f32>,
+        radius: f32,
+    ) -> Vec<Agent> {
+        let mass = 1.0 / count as f32;
+        (0..count)
+            .map(|i| {
+                let seed = i.wrapping_mul(4);
+                let [a, b, c, d] = [0, 1, 2, 3].map(|j| lightmap::random(seed.wrapping_add(j)));
+                let (offset, velocity) = match behaviour {
+                    Behaviour::Flocking => {
+                        let direction = |u: f32, v: f32| {
+                            let z = 2.0 * u - 1.0;
+                            let around = (1.0 - z * z).max(0.0).sqrt();
+                            let angle = 2.0 * PI * v;
+                            Vector3::new(around * angle.cos(), around * angle.sin(), z)
+                        };
+                        (
+                            direction(a, b) * (0.8 * radius * c.cbrt()),
+                            direction(d, c) * 0.3,
+                        )
+                    }
+                    Behaviour::Gravity => {
+                        let distance = radius * a.sqrt();
+                        let angle = 2.0 * PI * b;
+                        let (sin, cos) = angle.sin_cos();
+                        // A disk of even density holds the square of the distance's share of the mass
+                        let enclosed = (distance / radius).powi(2);
+                        let speed = (GRAVITATIONAL_CONSTANT * enclosed
+                            / distance.max(0.01 * radius))
+                        .sqrt();
+                        (
+                            Vector3::new(distance * cos, distance * sin, 0.02 * radius * (c - 0.5)),
+                            Vector3::new(-sin, cos, 0.0) * speed,
+                        )
+                    }
+                };
+                let position = center + offset;
+                Agent {
+                    position: [position.x, position.y, position.z, mass],
+                    velocity: [velocity.x, velocity.y, velocity.z, 0.0],
+                }
+            })
+            .collect()
+    }
+
+    /// A dart pointing along +X with a fin along +Z, a unit long
+    fn agent_mesh(color: [f32; 3]) -> mesh::MeshData {
+        let nose = [0.5, 0.0, 0.0];
+        let tail = [-0.3, 0.0, 0.0];
+        let positions = vec![
+            nose,
+            [-0.5, 0.35, 0.0],
+            tail,
+            nose,
+            tail,
+            [-0.5, -0.35, 0.0],
+            nose,
+            [-0.5, 0.0, 0.25],
+            tail,
+        ];
+        let mut normals = vec![[0.0, 0.0, 1.0]; 6];
+        normals.extend([[0.0, 1.0, 0.0]; 3].iter());
+        mesh::MeshData {
+            colors: Some(vec![color; positions.len()]),
+            tex_coords: None,
+            lightmap_coords: None,
+            occlusion: None,
+            indices: (0..positions.len() as u16).collect(),
+            positions,
+            normals,
+        }
+    }
+
+    /// A draw of every agent, vertex coloured and shaded on both sides since the darts are flat
+    pub fn draw(&self) -> draw::Draw {
+        let attributes = mesh::VertexAttributes {
+            color: true,
+            tex_coord: false,
+            lightmap_coord: false,
+            occlusion: false,
+        };
+        let config = material::Material {
+            double_sided: true,
+            vertex_color: material::VertexColorMode::Multiply,
+            ..material::Material::default()
+        }
+        .pipeline_config(attributes);
+        // Flocking agents turn back soon after leaving the region and most bodies stay near their orbits
+        let reach = Vector3::new(1.0, 1.0, 1.0) * (2.0 * self.radius);
+        let bounds = bounds::Aabb::from_points(vec![self.center - reach, self.center + reach]);
+        draw::Draw {
+            instance_count: self.count,
+            instances: Some(self.instance_buffer.0),
+            bounds: bounds.map(bounds::Bounds::new),
+            ..draw::Draw::new(
+                pipeline::Config {
+                    program: pipeline::Program::Indirect,
+                    ..config
+                },
+                self.vertex_buffer.0,
+                self.vertex_count,
+            )
+        }
+    }
+
+    /// Records one step of the simulation. Must be recorded outside of a render pass, before the agents are
+    /// drawn.
+    pub fn record_update(&mut self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        let now = Instant::now();
+        let frame_time = self
+            .last_update
+            .map_or(0.0, |last| (now - last).as_secs_f32().min(MAX_FRAME_TIME));
+        self.last_update = Some(now);
+        let step = Step {
+            region: [self.center.x, self.center.y, self.center.z, self.radius],
+            count: self.count,
+            behaviour: self.behaviour as u32,
+            delta_time: frame_time,
+            scale: self.behaviour.scale(),
+        };
+
+        unsafe {
+            // The previous step wrote the state, and the previous frame's draw read the transforms this step
+            // overwrites
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::VERTEX_INPUT,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[vk::MemoryBarrier::builder()
+                    .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+                    .build()],
+                &[],
+                &[],
+            );
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.layout,
+                0,
+                &[self.step_sets[self.next_set]],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                self.layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                step.as_bytes(),
+            );
+            device.cmd_dispatch(command_buffer, self.count.div_ceil(WORKGROUP_SIZE), 1, 1);
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::VERTEX_INPUT,
+                vk::DependencyFlags::empty(),
+                &[vk::MemoryBarrier::builder()
+                    .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::VERTEX_ATTRIBUTE_READ)
+                    .build()],
+                &[],
+                &[],
+            );
+        }
+        self.next_set = 1 - self.next_set;
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            for &(buffer, memory) in self
+                .state_buffers
+                .iter()
+                .chain([self.instance_buffer, self.vertex_buffer].iter())
+            {
+                device.destroy_buffer(buffer, None);
+                device.free_memory(memory, None);
+            }
+        }
+    }
+}
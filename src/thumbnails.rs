@@ -0,0 +1,523 @@
+use std::any::Any;
+use std::mem::size_of;
+use std::path::Path;
+
+use ash::vk;
+
+use crate::features::{RenderFeature, SwapchainContext};
+use crate::{assets, atlas, framegraph, hooks, playground, util};
+
+/// At most as many sources as there are number keys to toggle them with
+pub const MAX_SOURCES: usize = 9;
+/// Size in logical pixels of each thumbnail, and the gap around them
+const SIZE: u32 = 160;
+const MARGIN: u32 = 8;
+
+/// Inputs to `thumbnail_frag.glsl`, laid out to match its `Panel` push constants.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Panel {
+    offset: [f32; 2],
+    size: [f32; 2],
+    encode_srgb: u32,
+}
+
+impl Panel {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+}
+
+/// An image the thumbnails can show, sampled in the `SHADER_READ_ONLY_OPTIMAL` layout.
+struct Source {
+    name: String,
+    descriptor_set: vk::DescriptorSet,
+    visible: bool,
+    /// Where the thumbnail is kept in the UI atlas when thumbnails are cached
+    slot: Option<atlas::Slot>,
+    /// Whether the thumbnail has been drawn into its slot since it was last shown
+    cached: bool,
+    /// Keeps a loaded texture alive for as long as its thumbnail can be shown
+    _texture: Option<assets::Texture>,
+}
+
+/// Draws intermediate images the renderer keeps, such as its textures and lightmap, as thumbnails along the
+/// top of the frame for debugging what passes produce. Thumbnails are drawn over the scene in the render pass,
+/// so images the render pass draws into, like the depth buffer, can't be shown.
+///
+/// When cached, each thumbnail is drawn into a slot of a UI atlas after the frame it is first shown in, and
+/// copied from there in the frames after. Since sources are only drawn again when they are shown again, a
+/// cached thumbnail of an image that changes shows it as it was when it was shown.
+pub struct Thumbnails {
+    sources: Vec<Source>,
+    sampler: vk::Sampler,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    layout: vk::PipelineLayout,
+    /// Created when first recorded after the swapchain has been (re)created, with the frame's render pass
+    pipeline: Option<vk::Pipeline>,
+    /// The atlas cached thumbnails are kept in, and the pipeline drawing them into it
+    atlas: Option<(atlas::Atlas, vk::Pipeline)>,
+    /// `SIZE` and `MARGIN` in physical pixels
+    size: u32,
+    margin: u32,
+}
+
+impl Thumbnails {
+    pub fn new(device: &ash::Device) -> Self {
+        let sampler = unsafe {
+            device
+                .create_sampler(
+                    &vk::SamplerCreateInfo::builder()
+                        .mag_filter(vk::Filter::LINEAR)
+                        .min_filter(vk::Filter::LINEAR)
+                        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+                    None,
+                )
+                .expect("Thumbnail sampler")
+        };
+
+        let bindings = [vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build()];
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+                    None,
+                )
+                .expect("Thumbnail descriptor set layout")
+        };
+
+        let pool_sizes = [vk::DescriptorPoolSize::builder()
+            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(MAX_SOURCES as u32)
+            .build()];
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::builder()
+                        .pool_sizes(&pool_sizes)
+                        .max_sets(MAX_SOURCES as u32),
+                    None,
+                )
+                .expect("Thumbnail descriptor pool")
+        };
+
+        let set_layouts = [descriptor_set_layout];
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .offset(0)
+            .size(size_of::<Panel>() as u32)
+            .build()];
+        let layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::builder()
+                        .set_layouts(&set_layouts)
+                        .push_constant_ranges(&push_constant_ranges),
+                    None,
+                )
+                .expect("Thumbnail pipeline layout")
+        };
+
+        Self {
+            sources: Vec::new(),
+            sampler,
+            descriptor_set_layout,
+            descriptor_pool,
+            layout,
+            pipeline: None,
+            atlas: None,
+            size: SIZE,
+            margin: MARGIN,
+        }
+    }
+
+    /// Adds an image that can be shown as a thumbnail, hidden until toggled. The view must stay valid, and the
+    /// image in the `SHADER_READ_ONLY_OPTIMAL` layout, for as long as the thumbnails are drawn.
+    pub fn add_source(
+        &mut self,
+        device: &ash::Device,
+        name: &str,
+        view: vk::ImageView,
+    ) -> Result<usize, String> {
+        self.add(device, name, view, None)
+    }
+
+    /// Adds a loaded texture that can be shown as a thumbnail, keeping it alive while the thumbnails are drawn
+    pub fn add_texture(
+        &mut self,
+        device: &ash::Device,
+        name: &str,
+        texture: &assets::Texture,
+    ) -> Result<usize, String> {
+        self.add(device, name, texture.view(), Some(texture.clone()))
+    }
+
+    fn add(
+        &mut self,
+        device: &ash::Device,
+        name: &str,
+        view: vk::ImageView,
+        texture: Option<assets::Texture>,
+    ) -> Result<usize, String> {
+        if self.sources.len() == MAX_SOURCES {
+            return Err(format!(
+                "Thumbnails can show at most {} images, {} is one too many",
+                MAX_SOURCES, name
+            ));
+        }
+        let set_layouts = [self.descriptor_set_layout];
+        let descriptor_set = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::builder()
+                        .descriptor_pool(self.descriptor_pool)
+                        .set_layouts(&set_layouts),
+                )
+                .expect("Thumbnail descriptor set")[0]
+        };
+        let image_info = [vk::DescriptorImageInfo::builder()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(view)
+            .sampler(self.sampler)
+            .build()];
+        let writes = [vk::WriteDescriptorSet::builder()
+            .dst_set(descriptor_set)
+            .dst_binding(0)
+            .dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_info)
+            .build()];
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+
+        let slot = match self.atlas.as_mut() {
+            Some((atlas, _)) => Some(atlas.allocate(self.size, self.size)?),
+            None => None,
+        };
+        self.sources.push(Source {
+            name: String::from(name),
+            descriptor_set,
+            visible: false,
+            slot,
+            cached: false,
+            _texture: texture,
+        });
+        Ok(self.sources.len() - 1)
+    }
+
+    /// Shows or hides the source's thumbnail, returning its name and whether it is now shown, or `None` if there
+    /// is no such source
+    pub fn toggle(&mut self, index: usize) -> Option<(&str, bool)> {
+        let source = self.sources.get_mut(index)?;
+        source.visible = !source.visible;
+        source.cached = false;
+        Some((&source.name, source.visible))
+    }
+
+    /// Starts or stops keeping the thumbnails in a UI atlas, so each is drawn once when it is shown rather than
+    /// every frame. The device must be idle when caching stops.
+    pub fn set_cached(
+        &mut self,
+        device: &ash::Device,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        cached: bool,
+    ) -> Result<(), String> {
+        if cached == self.atlas.is_some() {
+            return Ok(());
+        }
+        if let Some((mut atlas, pipeline)) = self.atlas.take() {
+            unsafe { device.destroy_pipeline(pipeline, None) };
+            atlas.destroy(device);
+        }
+        for source in self.sources.iter_mut() {
+            source.slot = None;
+            source.cached = false;
+        }
+        if !cached {
+            return Ok(());
+        }
+
+        let mut atlas = atlas::Atlas::new(device, device_memory_properties);
+        for source in self.sources.iter_mut() {
+            source.slot = Some(atlas.allocate(self.size, self.size)?);
+        }
+        let path = Path::new(env!("OUT_DIR")).join("thumbnail_frag.spv");
+        let pipeline = playground::create_pipeline(
+            device,
+            &atlas.target(),
+            self.layout,
+            &util::read_shader_code(&path),
+            false,
+            false,
+        );
+        self.atlas = Some((atlas, pipeline));
+        Ok(())
+    }
+
+    /// Records drawing the shown thumbnails that aren't in the atlas yet into their slots
+    fn record_atlas(&mut self, context: &hooks::FrameContext) {
+        let (atlas, pipeline) = match self.atlas.as_mut() {
+            Some((atlas, pipeline)) => (atlas, *pipeline),
+            None => return,
+        };
+        let (device, layout) = (context.device, self.layout);
+        for source in self.sources.iter_mut() {
+            let slot = match source.slot {
+                Some(slot) if source.visible && !source.cached => slot,
+                _ => continue,
+            };
+            let panel = Panel {
+                offset: [slot.x as f32, slot.y as f32],
+                size: [slot.width as f32, slot.height as f32],
+                // The atlas is sRGB, and encodes on write
+                encode_srgb: 0,
+            };
+            let descriptor_set = source.descriptor_set;
+            atlas.record_update(
+                device,
+                context.command_buffer,
+                &slot,
+                |command_buffer| unsafe {
+                    device.cmd_bind_pipeline(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        pipeline,
+                    );
+                    device.cmd_bind_descriptor_sets(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        layout,
+                        0,
+                        &[descriptor_set],
+                        &[],
+                    );
+                    device.cmd_push_constants(
+                        command_buffer,
+                        layout,
+                        vk::ShaderStageFlags::FRAGMENT,
+                        0,
+                        panel.as_bytes(),
+                    );
+                    device.cmd_draw(command_buffer, 3, 1, 0, 0);
+                },
+            );
+            source.cached = true;
+        }
+    }
+
+    pub fn source_names(&self) -> Vec<&str> {
+        self.sources
+            .iter()
+            .map(|source| source.name.as_str())
+            .collect()
+    }
+}
+
+impl RenderFeature for Thumbnails {
+    fn name(&self) -> &str {
+        "thumbnails"
+    }
+
+    fn init(&mut self, context: &SwapchainContext) {
+        self.size = context.scaled(SIZE);
+        self.margin = context.scaled(MARGIN);
+    }
+
+    fn resize(&mut self, context: &SwapchainContext) {
+        if let Some(pipeline) = self.pipeline.take() {
+            unsafe { context.device.destroy_pipeline(pipeline, None) };
+        }
+        if let Some((atlas, _)) = self.atlas.as_mut() {
+            atlas.resize(context.device);
+        }
+        let size = context.scaled(SIZE);
+        self.margin = context.scaled(MARGIN);
+        if size == self.size {
+            return;
+        }
+        self.size = size;
+        // The atlas's slots are made again at the new size
+        if self.atlas.is_some() {
+            let cached = self
+                .set_cached(context.device, context.device_memory_properties, false)
+                .and_then(|_| {
+                    self.set_cached(context.device, context.device_memory_properties, true)
+                });
+            if let Err(e) = cached {
+                println!("Stopped caching thumbnails: {}", e);
+            }
+        }
+    }
+
+    fn record(&mut self, context: &hooks::FrameContext) {
+        if context.stage == hooks::Stage::BeforePost {
+            self.record_atlas(context);
+        }
+        if context.stage != hooks::Stage::AfterOpaque
+            || !self.sources.iter().any(|source| source.visible)
+        {
+            return;
+        }
+
+        let device = context.device;
+        let layout = self.layout;
+        let pipeline = *self.pipeline.get_or_insert_with(|| {
+            let path = Path::new(env!("OUT_DIR")).join("thumbnail_frag.spv");
+            playground::create_pipeline(
+                device,
+                &context.target,
+                layout,
+                &util::read_shader_code(&path),
+                false,
+                false,
+            )
+        });
+        let extent = context.target.extent;
+        let command_buffer = context.command_buffer;
+        let visible = self.sources.iter().filter(|source| source.visible);
+        let (size, margin) = (self.size, self.margin);
+        for (slot, source) in visible.enumerate() {
+            let x = margin + slot as u32 * (size + margin);
+            // Thumbnails that don't fit across the frame are left out
+            if x + size > extent.width || margin + size > extent.height {
+                break;
+            }
+            if let (Some((atlas, _)), Some(atlas_slot), true) =
+                (self.atlas.as_mut(), source.slot, source.cached)
+            {
+                atlas.record_composite(
+                    device,
+                    command_buffer,
+                    &context.target,
+                    context.swapchain_format,
+                    &atlas_slot,
+                    (x, margin),
+                );
+                continue;
+            }
+            let panel = Panel {
+                offset: [x as f32, margin as f32],
+                size: [size as f32, size as f32],
+                encode_srgb: !util::is_srgb_format(context.swapchain_format) as u32,
+            };
+            // The full-screen triangle covers the viewport, which is narrowed to the thumbnail
+            let viewports = [vk::Viewport::builder()
+                .x(panel.offset[0])
+                .y(panel.offset[1])
+                .width(panel.size[0])
+                .height(panel.size[1])
+                .min_depth(0.0)
+                .max_depth(1.0)
+                .build()];
+            unsafe {
+                device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+                device.cmd_set_viewport(command_buffer, 0, &viewports);
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    layout,
+                    0,
+                    &[source.descriptor_set],
+                    &[],
+                );
+                device.cmd_push_constants(
+                    command_buffer,
+                    layout,
+                    vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    panel.as_bytes(),
+                );
+                device.cmd_draw(command_buffer, 3, 1, 0, 0);
+            }
+        }
+
+        // Left as it was found for the features and hooks recorded after this
+        let viewports = [vk::Viewport::builder()
+            .x(0.0)
+            .y(0.0)
+            .width(extent.width as f32)
+            .height(extent.height as f32)
+            .min_depth(0.0)
+            .max_depth(1.0)
+            .build()];
+        unsafe {
+            device.cmd_set_viewport(command_buffer, 0, &viewports);
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                context.pipeline_layout,
+                0,
+                &[context.descriptor_set],
+                &[],
+            );
+        }
+    }
+
+    fn passes(&self) -> Vec<framegraph::Pass> {
+        let shown: Vec<&str> = self
+            .sources
+            .iter()
+            .filter(|source| source.visible)
+            .map(|source| source.name.as_str())
+            .collect();
+        if shown.is_empty() {
+            return Vec::new();
+        }
+        if self.atlas.is_none() {
+            return vec![framegraph::Pass::new(
+                self.name(),
+                framegraph::Position::Stage(hooks::Stage::AfterOpaque),
+                &shown,
+                &[framegraph::SWAPCHAIN],
+            )];
+        }
+        let mut passes = vec![framegraph::Pass::new(
+            self.name(),
+            framegraph::Position::Stage(hooks::Stage::AfterOpaque),
+            &[shown.as_slice(), &["UI atlas"]].concat(),
+            &[framegraph::SWAPCHAIN],
+        )];
+        let stale: Vec<&str> = self
+            .sources
+            .iter()
+            .filter(|source| source.visible && !source.cached)
+            .map(|source| source.name.as_str())
+            .collect();
+        if !stale.is_empty() {
+            passes.push(framegraph::Pass::new(
+                "thumbnail atlas",
+                framegraph::Position::Stage(hooks::Stage::BeforePost),
+                &stale,
+                &["UI atlas"],
+            ));
+        }
+        passes
+    }
+
+    fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            if let Some(pipeline) = self.pipeline.take() {
+                device.destroy_pipeline(pipeline, None);
+            }
+            if let Some((mut atlas, pipeline)) = self.atlas.take() {
+                device.destroy_pipeline(pipeline, None);
+                atlas.destroy(device);
+            }
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            device.destroy_sampler(self.sampler, None);
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
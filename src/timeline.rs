@@ -0,0 +1,335 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use cgmath::{Deg, Matrix4, Point3, Vector3};
+
+use crate::flythrough;
+
+/// The first line of every timeline file, naming the version of the format
+const HEADER: &str = "rust-renderer-vk timeline 1";
+
+/// Renderer settings a timeline can turn on and off.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Setting {
+    Grid,
+    Fog,
+    Magnifier,
+    Orthographic,
+    Baked,
+}
+
+impl Setting {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "grid" => Some(Setting::Grid),
+            "fog" => Some(Setting::Fog),
+            "magnifier" => Some(Setting::Magnifier),
+            "orthographic" => Some(Setting::Orthographic),
+            "baked" => Some(Setting::Baked),
+            _ => None,
+        }
+    }
+}
+
+/// Something a timeline does once, when its time comes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Set(Setting, bool),
+    /// Switches to the scene, with the current transition
+    Scene(usize),
+}
+
+/// Where a draw is at one of its keys, placed in the scene by a translation, a turn about Z and a uniform scale.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Placement {
+    pub translation: Vector3<f32>,
+    pub rotation: Deg<f32>,
+    pub scale: f32,
+}
+
+impl Placement {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        Self {
+            translation: self.translation + (other.translation - self.translation) * t,
+            rotation: self.rotation + (other.rotation - self.rotation) * t,
+            scale: self.scale + (other.scale - self.scale) * t,
+        }
+    }
+
+    pub fn transform(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from_angle_z(self.rotation)
+            * Matrix4::from_scale(self.scale)
+    }
+}
+
+/// A draw's keys, in time order.
+#[derive(Clone, Debug)]
+pub struct Track {
+    pub draw: usize,
+    pub keys: Vec<(f32, Placement)>,
+}
+
+/// Camera keys, draw transform keys, setting toggles and scene switches at times in seconds, played back a fixed
+/// step per frame so every playback renders the same frames. The camera moves along Catmull-Rom splines through
+/// its keys, draws move in straight lines between theirs, and actions are done on the first frame at or after
+/// their time. Keys and actions may be listed in any order.
+///
+/// Timeline files are text, one key or action per line:
+///
+/// ```text
+/// rust-renderer-vk timeline 1
+/// camera <time> <eye x> <y> <z> <target x> <y> <z>
+/// transform <time> <draw> <x> <y> <z> <degrees about z> <scale>
+/// set <time> <grid|fog|magnifier|orthographic|baked> <on|off>
+/// scene <time> <index>
+/// end <time>
+/// ```
+///
+/// The timeline ends at its `end` line, or at its last key or action without one. Draws are given by their
+/// index, as in scene files. Lines starting with `#` are ignored.
+#[derive(Clone, Debug, Default)]
+pub struct Timeline {
+    pub camera: Vec<(f32, flythrough::Key)>,
+    pub tracks: Vec<Track>,
+    pub actions: Vec<(f32, Action)>,
+    /// Seconds from the start to the end
+    pub end: f32,
+}
+
+/// What a timeline shows in one frame.
+pub struct Frame {
+    pub camera: Option<flythrough::Key>,
+    pub transforms: Vec<(usize, Matrix4<f32>)>,
+    pub actions: Vec<Action>,
+}
+
+impl Timeline {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text =
+            fs::read_to_string(path).map_err(|e| format!("Reading {}: {}", path.display(), e))?;
+        Self::from_text(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn from_text(text: &str) -> Result<Self, String> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(number, line)| (number + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+        match lines.next() {
+            Some((_, HEADER)) => {}
+            _ => return Err(format!("Timeline files start with \"{}\"", HEADER)),
+        }
+
+        let mut timeline = Self::default();
+        let mut end = None;
+        for (number, line) in lines {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let time: f32 = match fields.get(1) {
+                Some(time) => parse(number, time)?,
+                None => return Err(format!("Line {}: needs a time", number)),
+            };
+            let values = || {
+                fields[2..]
+                    .iter()
+                    .map(|value| parse(number, value))
+                    .collect::<Result<Vec<f32>, String>>()
+            };
+            match fields[0] {
+                "camera" => match values()?[..] {
+                    [ex, ey, ez, tx, ty, tz] => timeline.camera.push((
+                        time,
+                        flythrough::Key {
+                            eye: Point3::new(ex, ey, ez),
+                            target: Point3::new(tx, ty, tz),
+                        },
+                    )),
+                    _ => {
+                        return Err(format!(
+                            "Line {}: camera keys need an eye and a target",
+                            number
+                        ))
+                    }
+                },
+                "transform" => match values()?[..] {
+                    [draw, x, y, z, degrees, scale] => {
+                        let draw = draw as usize;
+                        let key = (
+                            time,
+                            Placement {
+                                translation: Vector3::new(x, y, z),
+                                rotation: Deg(degrees),
+                                scale,
+                            },
+                        );
+                        match timeline.tracks.iter_mut().find(|track| track.draw == draw) {
+                            Some(track) => track.keys.push(key),
+                            None => timeline.tracks.push(Track {
+                                draw,
+                                keys: vec![key],
+                            }),
+                        }
+                    }
+                    _ => {
+                        return Err(format!(
+                        "Line {}: transform keys need a draw, a translation, an angle and a scale",
+                        number
+                    ))
+                    }
+                },
+                "set" => {
+                    let setting = fields.get(2).and_then(|name| Setting::parse(name));
+                    let on = match fields.get(3) {
+                        Some(&"on") => Some(true),
+                        Some(&"off") => Some(false),
+                        _ => None,
+                    };
+                    match (setting, on, fields.len()) {
+                        (Some(setting), Some(on), 4) => {
+                            timeline.actions.push((time, Action::Set(setting, on)))
+                        }
+                        _ => {
+                            return Err(format!(
+                                "Line {}: set needs a setting and on or off",
+                                number
+                            ))
+                        }
+                    }
+                }
+                "scene" => match fields[2..] {
+                    [scene] => timeline
+                        .actions
+                        .push((time, Action::Scene(parse(number, scene)?))),
+                    _ => return Err(format!("Line {}: scene needs a scene index", number)),
+                },
+                "end" => end = Some(time),
+                other => return Err(format!("Line {}: unknown setting {}", number, other)),
+            }
+        }
+
+        let by_time = |a: &f32, b: &f32| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal);
+        // Stable, so actions at the same time are done in the order they are listed
+        timeline.camera.sort_by(|a, b| by_time(&a.0, &b.0));
+        timeline.actions.sort_by(|a, b| by_time(&a.0, &b.0));
+        for track in timeline.tracks.iter_mut() {
+            track.keys.sort_by(|a, b| by_time(&a.0, &b.0));
+        }
+        timeline.end = end.unwrap_or_else(|| {
+            let times = timeline
+                .camera
+                .iter()
+                .map(|(time, _)| *time)
+                .chain(timeline.actions.iter().map(|(time, _)| *time))
+                .chain(
+                    timeline
+                        .tracks
+                        .iter()
+                        .flat_map(|track| track.keys.iter().map(|(time, _)| *time)),
+                );
+            times.fold(0.0, f32::max)
+        });
+        Ok(timeline)
+    }
+
+    /// Where the camera is at the time, clamped to its first and last keys. `None` without camera keys.
+    fn camera_at(&self, time: f32) -> Option<flythrough::Key> {
+        let (index, t) = segment(self.camera.iter().map(|(time, _)| *time), time)?;
+        let last = self.camera.len() - 1;
+        // The ends are repeated so the curve passes through the first and last keys
+        let key = |offset: isize| {
+            self.camera[(index as isize + offset).clamp(0, last as isize) as usize].1
+        };
+        let [k0, k1, k2, k3] = [key(-1), key(0), key(1), key(2)];
+        Some(flythrough::Key {
+            eye: flythrough::catmull_rom(k0.eye, k1.eye, k2.eye, k3.eye, t),
+            target: flythrough::catmull_rom(k0.target, k1.target, k2.target, k3.target, t),
+        })
+    }
+}
+
+impl Track {
+    fn transform_at(&self, time: f32) -> Option<Matrix4<f32>> {
+        let (index, t) = segment(self.keys.iter().map(|(time, _)| *time), time)?;
+        let from = self.keys[index].1;
+        let to = self.keys[(index + 1).min(self.keys.len() - 1)].1;
+        Some(from.lerp(to, t).transform())
+    }
+}
+
+/// Plays a timeline back a fixed step of it per frame.
+pub struct Playback {
+    timeline: Timeline,
+    time: f32,
+    /// The first action not done yet
+    next_action: usize,
+}
+
+impl Playback {
+    pub fn new(timeline: Timeline) -> Self {
+        Self {
+            timeline,
+            time: 0.0,
+            next_action: 0,
+        }
+    }
+
+    /// Seconds into the timeline of the frame last returned by `advance`
+    pub fn time(&self) -> f32 {
+        (self.time - flythrough::PLAYBACK_STEP).max(0.0)
+    }
+
+    /// What this frame shows, moving on to the next. `None` once the end of the timeline has been played.
+    pub fn advance(&mut self) -> Option<Frame> {
+        if self.time > self.timeline.end {
+            return None;
+        }
+        let time = self.time;
+        let actions = &self.timeline.actions[self.next_action..];
+        let due = actions
+            .iter()
+            .take_while(|(at, _)| *at <= time)
+            .map(|(_, action)| *action)
+            .collect::<Vec<_>>();
+        self.next_action += due.len();
+        self.time += flythrough::PLAYBACK_STEP;
+        Some(Frame {
+            camera: self.timeline.camera_at(time),
+            transforms: self
+                .timeline
+                .tracks
+                .iter()
+                .filter_map(|track| Some((track.draw, track.transform_at(time)?)))
+                .collect(),
+            actions: due,
+        })
+    }
+}
+
+/// The index of the key starting the segment the time is in, and how far through the segment it is. Times
+/// before the first key are at its start, and times after the last key are at the end of the last segment.
+fn segment(times: impl Iterator<Item = f32>, time: f32) -> Option<(usize, f32)> {
+    let times: Vec<f32> = times.collect();
+    let last = times.len().checked_sub(1)?;
+    if last == 0 || time <= times[0] {
+        return Some((0, 0.0));
+    }
+    let index = times
+        .windows(2)
+        .position(|pair| time < pair[1])
+        .unwrap_or(last - 1);
+    let span = times[index + 1] - times[index];
+    let t = if span > 0.0 {
+        ((time - times[index]) / span).clamp(0.0, 1.0)
+    } else {
+        1.0
+    };
+    Some((index, t))
+}
+
+fn parse<T: FromStr>(number: usize, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Line {}: {} isn't a valid number", number, value))
+}
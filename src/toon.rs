@@ -0,0 +1,112 @@
+/// Texels across each ramp of the built in atlas
+pub const RAMP_WIDTH: u32 = 64;
+
+/// A row of a toon ramp atlas, which the light reaching a toon shaded fragment is looked up in. Left to right is
+/// from facing away from the light to facing it, as half of the dot product of the normal and the direction
+/// towards the light plus a half, so the terminator is in the middle. Each band lights what falls in it evenly.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ramp {
+    pub name: &'static str,
+    /// Where each band starts, from 0 to 1, and the linear colour the light is scaled by in it, in order
+    bands: &'static [(f32, [f32; 3])],
+    /// How far either side of a band's start it blends with the band before it, for softer terminators
+    softness: f32,
+}
+
+/// The ramps of the built in atlas, in its rows from the top. A material picks its ramp by row, see
+/// `material::Toon::ramp`.
+pub const RAMPS: [Ramp; 5] = [
+    Ramp {
+        name: "two",
+        bands: &[(0.0, [0.15; 3]), (0.5, [1.0; 3])],
+        softness: 0.01,
+    },
+    Ramp {
+        name: "three",
+        bands: &[(0.0, [0.1; 3]), (0.45, [0.45; 3]), (0.7, [1.0; 3])],
+        softness: 0.01,
+    },
+    Ramp {
+        name: "four",
+        bands: &[
+            (0.0, [0.08; 3]),
+            (0.4, [0.3; 3]),
+            (0.6, [0.65; 3]),
+            (0.8, [1.0; 3]),
+        ],
+        softness: 0.01,
+    },
+    Ramp {
+        name: "soft",
+        bands: &[(0.0, [0.15; 3]), (0.5, [1.0; 3])],
+        softness: 0.15,
+    },
+    // Cool shadows and warm light, as in hand painted cel shading
+    Ramp {
+        name: "warm",
+        bands: &[(0.0, [0.12, 0.14, 0.3]), (0.5, [1.0, 0.9, 0.75])],
+        softness: 0.02,
+    },
+];
+
+/// The row of the built in ramp with the name
+pub fn ramp(name: &str) -> Option<usize> {
+    RAMPS.iter().position(|ramp| ramp.name == name)
+}
+
+impl Ramp {
+    /// The linear colour at texel `x` of the ramp's row
+    pub fn texel(&self, x: u32) -> [f32; 3] {
+        let at = (x as f32 + 0.5) / RAMP_WIDTH as f32;
+        let mut colour = self.bands.first().map_or([1.0; 3], |&(_, colour)| colour);
+        for &(start, band) in self.bands.iter().skip(1) {
+            let blend = if self.softness > 0.0 {
+                ((at - start) / (2.0 * self.softness) + 0.5).clamp(0.0, 1.0)
+            } else if at >= start {
+                1.0
+            } else {
+                0.0
+            };
+            for (channel, band) in colour.iter_mut().zip(band.iter()) {
+                *channel += (band - *channel) * blend;
+            }
+        }
+        colour
+    }
+}
+
+/// How toon shaded draws' inverted hull outlines are drawn, see `pipeline::Config::outline`. Their widths are
+/// chosen per material, see `material::Toon::outline`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Outlines {
+    /// Linear colour
+    pub color: [f32; 3],
+}
+
+impl Default for Outlines {
+    fn default() -> Self {
+        Self {
+            color: [0.02, 0.02, 0.03],
+        }
+    }
+}
+
+impl Outlines {
+    /// The uniforms for drawing outlines into a `width` by `height` pixel target
+    pub fn uniforms(&self, width: u32, height: u32) -> Uniforms {
+        let [r, g, b] = self.color;
+        Uniforms {
+            color: [r, g, b, 1.0],
+            viewport: [width.max(1) as f32, height.max(1) as f32, 0.0, 0.0],
+        }
+    }
+}
+
+/// Matches the `outline` members of `UniformBufferObject` in `outline_vert.glsl` and `outline_frag.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Uniforms {
+    color: [f32; 4],
+    /// The target's width and height in pixels in x and y
+    viewport: [f32; 4],
+}
@@ -0,0 +1,204 @@
+use std::collections::VecDeque;
+use std::mem::size_of;
+
+use ash::vk;
+use cgmath::{Matrix4, SquareMatrix};
+use memoffset::offset_of;
+
+use crate::{draw, indirect, HelloTriangleApplication};
+
+/// The most ghosts drawn in a frame, over every trail. The ghosts of trails past it are left out.
+pub const MAX_GHOSTS: usize = 1024;
+/// Matches the location of `inOpacity` in `trail_vert.glsl`, after the mesh's own attributes
+const OPACITY_LOCATION: u32 = 10;
+
+/// Translucent copies of a draw left behind where it has been on the last few frames, fading out with age, to
+/// show how it moves. Kept on the draw in `Draw::trail`, which samples the draw's world space transform into a
+/// ring buffer every `interval` frames.
+#[derive(Clone, Debug)]
+pub struct Trail {
+    /// How many ghosts follow the draw
+    pub length: usize,
+    /// Frames between one ghost and the next, 1 for a ghost every frame
+    pub interval: u32,
+    /// How opaque the newest ghost is, from 0 to 1. Older ones fade out evenly to nothing.
+    pub opacity: f32,
+    /// The newest transform first
+    history: VecDeque<Matrix4<f32>>,
+    frames: u32,
+}
+
+impl Trail {
+    pub fn new(length: usize, interval: u32, opacity: f32) -> Self {
+        Self {
+            length,
+            interval: interval.max(1),
+            opacity,
+            history: VecDeque::with_capacity(length),
+            frames: 0,
+        }
+    }
+
+    /// Called once a frame with the draw's world space transform
+    pub fn update(&mut self, transform: Matrix4<f32>) {
+        self.frames += 1;
+        if self.frames < self.interval && !self.history.is_empty() {
+            return;
+        }
+        self.frames = 0;
+        if self.history.len() >= self.length {
+            self.history.pop_back();
+        }
+        if self.length > 0 {
+            self.history.push_front(transform);
+        }
+    }
+
+    /// The trail's ghosts, before the scene's model matrix whose inverse is `from_world`. Ghosts where the draw
+    /// still is are left out, since they would only fight with the draw's own depth.
+    fn ghosts<'a>(
+        &'a self,
+        current: &'a Matrix4<f32>,
+        from_world: &'a Matrix4<f32>,
+    ) -> impl Iterator<Item = Ghost> + 'a {
+        let length = self.length as f32;
+        self.history
+            .iter()
+            .enumerate()
+            .filter(move |(_, transform)| !same(transform, current))
+            .map(move |(age, transform)| Ghost {
+                transform: (from_world * transform).into(),
+                opacity: self.opacity * (1.0 - age as f32 / length),
+                _padding: [0.0; 3],
+            })
+    }
+}
+
+/// Whether the transforms are the same, give or take rounding
+fn same(a: &Matrix4<f32>, b: &Matrix4<f32>) -> bool {
+    let (a, b): (&[f32; 16], &[f32; 16]) = (a.as_ref(), b.as_ref());
+    a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() <= 1e-5)
+}
+
+/// One ghost of a trail, read per instance by `pipeline::Program::Trail`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Ghost {
+    /// Where the draw was, applied before the scene's model matrix
+    transform: [[f32; 4]; 4],
+    opacity: f32,
+    _padding: [f32; 3],
+}
+
+impl Ghost {
+    pub fn get_binding_description() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::builder()
+            .binding(indirect::INSTANCE_BINDING)
+            .stride(size_of::<Self>() as u32)
+            .input_rate(vk::VertexInputRate::INSTANCE)
+            .build()
+    }
+
+    pub fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescription; 5] {
+        let column = size_of::<[f32; 4]>() as u32;
+        let attribute = |location: u32, format: vk::Format, offset: u32| {
+            vk::VertexInputAttributeDescription::builder()
+                .binding(indirect::INSTANCE_BINDING)
+                .location(location)
+                .format(format)
+                .offset(offset)
+                .build()
+        };
+        let transform = offset_of!(Self, transform) as u32;
+        [
+            attribute(4, vk::Format::R32G32B32A32_SFLOAT, transform),
+            attribute(5, vk::Format::R32G32B32A32_SFLOAT, transform + column),
+            attribute(6, vk::Format::R32G32B32A32_SFLOAT, transform + 2 * column),
+            attribute(7, vk::Format::R32G32B32A32_SFLOAT, transform + 3 * column),
+            attribute(
+                OPACITY_LOCATION,
+                vk::Format::R32_SFLOAT,
+                offset_of!(Self, opacity) as u32,
+            ),
+        ]
+    }
+}
+
+/// The ghosts of every trail in a frame, written to a host visible buffer for each swapchain image so that one
+/// image's can be written while the others' commands may still read theirs. Each trail is drawn with a single
+/// instanced draw of its ghosts.
+#[derive(Default)]
+pub struct Ghosts {
+    buffers: Vec<(vk::Buffer, vk::DeviceMemory)>,
+    written: Vec<Ghost>,
+}
+
+impl Ghosts {
+    /// Writes the ghosts of the casters' trails for the swapchain image, and replaces `trails` with the draws
+    /// that draw them. `model` is the scene's model matrix.
+    pub fn write<'a, I: IntoIterator<Item = &'a draw::Draw>>(
+        &mut self,
+        device: &ash::Device,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        image_index: usize,
+        draws: I,
+        model: &Matrix4<f32>,
+        trails: &mut Vec<draw::Draw>,
+    ) {
+        trails.clear();
+        self.written.clear();
+        let from_world = match model.invert() {
+            Some(inverse) => inverse,
+            None => return,
+        };
+        // Created the first time each image draws trails
+        while self.buffers.len() <= image_index {
+            self.buffers.push(HelloTriangleApplication::create_buffer(
+                device,
+                (MAX_GHOSTS * size_of::<Ghost>()) as vk::DeviceSize,
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                device_memory_properties,
+            ));
+        }
+        let buffer = self.buffers[image_index].0;
+        for draw in draws {
+            let trail = match draw.trail.as_ref() {
+                Some(trail) => trail,
+                None => continue,
+            };
+            let first = self.written.len();
+            let current = draw.world_transform(model);
+            let room = MAX_GHOSTS - first;
+            self.written
+                .extend(trail.ghosts(&current, &from_world).take(room));
+            let count = self.written.len() - first;
+            match draw.trail_draw(buffer, first as u32, count as u32) {
+                Some(ghosts) => trails.push(ghosts),
+                None => self.written.truncate(first),
+            }
+        }
+        if self.written.is_empty() {
+            return;
+        }
+
+        let memory = self.buffers[image_index].1;
+        let size = (self.written.len() * size_of::<Ghost>()) as vk::DeviceSize;
+        unsafe {
+            let data = device
+                .map_memory(memory, 0, size, vk::MemoryMapFlags::empty())
+                .expect("Mapping trail memory") as *mut Ghost;
+            data.copy_from_nonoverlapping(self.written.as_ptr(), self.written.len());
+            device.unmap_memory(memory);
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            for (buffer, memory) in self.buffers.drain(..) {
+                device.destroy_buffer(buffer, None);
+                device.free_memory(memory, None);
+            }
+        }
+    }
+}
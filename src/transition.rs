@@ -0,0 +1,469 @@
+use std::any::Any;
+use std::mem::size_of;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use ash::vk;
+
+use crate::features::{RenderFeature, SwapchainContext};
+use crate::{
+    framegraph, hooks, pipeline, playground, util, HelloTriangleApplication, ImageDescription,
+};
+
+/// How the outgoing scene gives way to the incoming one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Effect {
+    /// The outgoing scene fades to black, then the incoming scene fades in
+    Fade,
+    /// The outgoing scene fades out over the incoming scene
+    Crossfade,
+    /// The incoming scene is uncovered from left to right
+    Wipe,
+}
+
+impl Effect {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "fade" => Some(Effect::Fade),
+            "crossfade" => Some(Effect::Crossfade),
+            "wipe" => Some(Effect::Wipe),
+            _ => None,
+        }
+    }
+
+    /// Cycles through the effects
+    pub fn next(self) -> Self {
+        match self {
+            Effect::Fade => Effect::Crossfade,
+            Effect::Crossfade => Effect::Wipe,
+            Effect::Wipe => Effect::Fade,
+        }
+    }
+}
+
+/// Inputs to `transition_frag.glsl`, laid out to match its `Blend` push constants.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Blend {
+    resolution: [f32; 2],
+    progress: f32,
+    effect: u32,
+}
+
+impl Blend {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+}
+
+struct Active {
+    /// The scene being transitioned away from
+    from: usize,
+    effect: Effect,
+    duration: Duration,
+    started: Instant,
+}
+
+impl Active {
+    /// How far through the transition it is, from 0 to 1
+    fn progress(&self) -> f32 {
+        if self.duration.is_zero() {
+            return 1.0;
+        }
+        (self.started.elapsed().as_secs_f32() / self.duration.as_secs_f32()).min(1.0)
+    }
+}
+
+/// The image the outgoing scene is rendered into, sized to the swapchain.
+struct Target {
+    extent: vk::Extent2D,
+    render_pass: vk::RenderPass,
+    color: (vk::Image, vk::DeviceMemory, vk::ImageView),
+    depth: (vk::Image, vk::DeviceMemory, vk::ImageView),
+    framebuffer: vk::Framebuffer,
+}
+
+/// Transitions between the renderer's scenes. While a transition runs, the outgoing scene is rendered into a
+/// texture of its own before the frame's render pass, and is blended over the incoming scene at the end of
+/// `Stage::AfterOpaque`. The texture is rendered with a render pass compatible with the renderer's, so the
+/// scene's pipelines draw into it unchanged.
+pub struct Transitions {
+    pub effect: Effect,
+    pub duration: Duration,
+    active: Option<Active>,
+    sampler: vk::Sampler,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+    layout: vk::PipelineLayout,
+    /// Created when first recorded after the swapchain has been (re)created, with the frame's render pass
+    pipeline: Option<vk::Pipeline>,
+    target: Option<Target>,
+}
+
+impl Transitions {
+    pub fn new(device: &ash::Device) -> Self {
+        let sampler = unsafe {
+            device
+                .create_sampler(
+                    &vk::SamplerCreateInfo::builder()
+                        .mag_filter(vk::Filter::NEAREST)
+                        .min_filter(vk::Filter::NEAREST)
+                        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+                    None,
+                )
+                .expect("Transition sampler")
+        };
+
+        let bindings = [vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build()];
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+                    None,
+                )
+                .expect("Transition descriptor set layout")
+        };
+
+        let pool_sizes = [vk::DescriptorPoolSize::builder()
+            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .build()];
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::builder()
+                        .pool_sizes(&pool_sizes)
+                        .max_sets(1),
+                    None,
+                )
+                .expect("Transition descriptor pool")
+        };
+        let set_layouts = [descriptor_set_layout];
+        let descriptor_set = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::builder()
+                        .descriptor_pool(descriptor_pool)
+                        .set_layouts(&set_layouts),
+                )
+                .expect("Transition descriptor set")[0]
+        };
+
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .offset(0)
+            .size(size_of::<Blend>() as u32)
+            .build()];
+        let layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::builder()
+                        .set_layouts(&set_layouts)
+                        .push_constant_ranges(&push_constant_ranges),
+                    None,
+                )
+                .expect("Transition pipeline layout")
+        };
+
+        Self {
+            effect: Effect::Crossfade,
+            duration: Duration::from_secs_f32(1.5),
+            active: None,
+            sampler,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            layout,
+            pipeline: None,
+            target: None,
+        }
+    }
+
+    /// Starts transitioning away from the scene with the current effect and duration. Replaces any transition
+    /// already running.
+    pub fn start(&mut self, from: usize) {
+        self.active = Some(Active {
+            from,
+            effect: self.effect,
+            duration: self.duration,
+            started: Instant::now(),
+        });
+    }
+
+    /// The scene being transitioned away from, if a transition is running. Ends the transition once it has
+    /// finished.
+    pub fn outgoing_scene(&mut self) -> Option<usize> {
+        if self.active.as_ref()?.progress() >= 1.0 {
+            self.active = None;
+        }
+        self.active.as_ref().map(|active| active.from)
+    }
+
+    /// Records a render pass that draws the outgoing scene into the transition's texture. `record` is given
+    /// the command buffer inside the render pass and records the scene's draws. Must be called outside a render
+    /// pass, before the frame's render pass.
+    pub fn record_outgoing(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        record: impl FnOnce(vk::CommandBuffer),
+    ) {
+        let target = match self.target.as_ref() {
+            Some(target) => target,
+            None => return,
+        };
+        let clear_values = [
+            vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: [0.0, 0.0, 0.0, 1.0],
+                },
+            },
+            vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            },
+        ];
+        let render_pass_bi = vk::RenderPassBeginInfo::builder()
+            .render_pass(target.render_pass)
+            .framebuffer(target.framebuffer)
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: target.extent,
+            })
+            .clear_values(&clear_values);
+
+        unsafe {
+            device.cmd_begin_render_pass(
+                command_buffer,
+                &render_pass_bi,
+                vk::SubpassContents::INLINE,
+            )
+        };
+        record(command_buffer);
+        unsafe { device.cmd_end_render_pass(command_buffer) };
+    }
+
+    fn create_target(&mut self, context: &SwapchainContext) {
+        let device = context.device;
+        let extent = context.extent;
+        let render_pass = pipeline::create_offscreen_render_pass(
+            device,
+            context.format,
+            context.depth_format,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            // The blend samples the texture in the frame's render pass
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::AccessFlags::SHADER_READ,
+            &pipeline::PassConfig::default(),
+        );
+
+        let color = create_attachment(
+            context,
+            context.format,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            vk::ImageAspectFlags::COLOR,
+        );
+        let depth = create_attachment(
+            context,
+            context.depth_format,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            vk::ImageAspectFlags::DEPTH,
+        );
+
+        let attachments = [color.2, depth.2];
+        let framebuffer = unsafe {
+            device
+                .create_framebuffer(
+                    &vk::FramebufferCreateInfo::builder()
+                        .render_pass(render_pass)
+                        .attachments(&attachments)
+                        .width(extent.width)
+                        .height(extent.height)
+                        .layers(1),
+                    None,
+                )
+                .expect("Transition frame buffer")
+        };
+
+        let image_info = [vk::DescriptorImageInfo::builder()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(color.2)
+            .sampler(self.sampler)
+            .build()];
+        let writes = [vk::WriteDescriptorSet::builder()
+            .dst_set(self.descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_info)
+            .build()];
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+
+        self.target = Some(Target {
+            extent,
+            render_pass,
+            color,
+            depth,
+            framebuffer,
+        });
+    }
+
+    fn destroy_target(&mut self, device: &ash::Device) {
+        if let Some(pipeline) = self.pipeline.take() {
+            unsafe { device.destroy_pipeline(pipeline, None) };
+        }
+        if let Some(target) = self.target.take() {
+            unsafe {
+                device.destroy_framebuffer(target.framebuffer, None);
+                for (image, memory, view) in [target.color, target.depth] {
+                    device.destroy_image_view(view, None);
+                    device.destroy_image(image, None);
+                    device.free_memory(memory, None);
+                }
+                device.destroy_render_pass(target.render_pass, None);
+            }
+        }
+    }
+}
+
+impl RenderFeature for Transitions {
+    fn name(&self) -> &str {
+        "transitions"
+    }
+
+    fn init(&mut self, context: &SwapchainContext) {
+        self.create_target(context);
+    }
+
+    fn resize(&mut self, context: &SwapchainContext) {
+        self.destroy_target(context.device);
+        self.create_target(context);
+    }
+
+    fn record(&mut self, context: &hooks::FrameContext) {
+        if context.stage != hooks::Stage::AfterOpaque {
+            return;
+        }
+        let active = match self.active.as_ref() {
+            Some(active) => active,
+            None => return,
+        };
+        let blend = Blend {
+            resolution: [
+                context.target.extent.width as f32,
+                context.target.extent.height as f32,
+            ],
+            progress: active.progress(),
+            effect: active.effect as u32,
+        };
+
+        let device = context.device;
+        let layout = self.layout;
+        let pipeline = *self.pipeline.get_or_insert_with(|| {
+            let path = Path::new(env!("OUT_DIR")).join("transition_frag.spv");
+            playground::create_pipeline(
+                device,
+                &context.target,
+                layout,
+                &util::read_shader_code(&path),
+                true,
+                false,
+            )
+        });
+        let command_buffer = context.command_buffer;
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                blend.as_bytes(),
+            );
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+
+            // Left as it was found for the features and hooks recorded after this
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                context.pipeline_layout,
+                0,
+                &[context.descriptor_set],
+                &[],
+            );
+        }
+    }
+
+    fn passes(&self) -> Vec<framegraph::Pass> {
+        if self.active.is_none() {
+            return Vec::new();
+        }
+        vec![
+            framegraph::Pass::new(
+                "outgoing scene",
+                framegraph::Position::BeforeRenderPass,
+                &[framegraph::UNIFORMS],
+                &["outgoing scene"],
+            ),
+            framegraph::Pass::new(
+                "scene transition",
+                framegraph::Position::Stage(hooks::Stage::AfterOpaque),
+                &["outgoing scene"],
+                &[framegraph::SWAPCHAIN],
+            ),
+        ]
+    }
+
+    fn destroy(&mut self, device: &ash::Device) {
+        self.destroy_target(device);
+        unsafe {
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            device.destroy_sampler(self.sampler, None);
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+pub fn create_attachment(
+    context: &SwapchainContext,
+    format: vk::Format,
+    usage: vk::ImageUsageFlags,
+    aspect: vk::ImageAspectFlags,
+) -> (vk::Image, vk::DeviceMemory, vk::ImageView) {
+    let (image, memory) = HelloTriangleApplication::create_image(
+        context.device,
+        &ImageDescription {
+            width: context.extent.width,
+            height: context.extent.height,
+            format,
+            tiling: vk::ImageTiling::OPTIMAL,
+            usage,
+            memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        },
+        context.device_memory_properties,
+    );
+    let view = HelloTriangleApplication::create_image_view(context.device, image, format, aspect);
+    (image, memory, view)
+}
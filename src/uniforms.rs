@@ -0,0 +1,30 @@
+use std::ffi::c_void;
+
+use cgmath::Matrix4;
+
+use crate::{audio, capsules, clipping, ground_shadows, shadows, sky, toon};
+
+/// The uniforms every draw of a frame reads. `repr(C)` keeps its layout the same as `UniformBufferObject` in
+/// the shaders.
+#[repr(C)]
+#[derive(Clone, Debug, Copy)]
+pub struct UniformBufferObject {
+    pub model: Matrix4<f32>,
+    pub view: Matrix4<f32>,
+    pub perspective: Matrix4<f32>,
+    pub audio_bands: [f32; audio::BAND_COUNT],
+    pub clipping: clipping::Uniforms,
+    pub light: sky::Uniforms,
+    pub capsules: capsules::Uniforms,
+    pub ground_shadows: ground_shadows::Uniforms,
+    pub outlines: toon::Uniforms,
+    pub shadows: shadows::Uniforms,
+}
+
+impl UniformBufferObject {
+    /// Copies the uniforms to `mapped`, which must point to at least `size_of::<UniformBufferObject>()` bytes
+    /// of mapped memory, aligned for it.
+    pub unsafe fn write(&self, mapped: *mut c_void) {
+        (mapped as *mut Self).copy_from_nonoverlapping(self, 1);
+    }
+}
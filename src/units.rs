@@ -0,0 +1,116 @@
+use cgmath::{Matrix, Matrix3, Matrix4, SquareMatrix, Vector3};
+
+/// Which axis points up, in the world or in an asset imported into it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Up {
+    Y,
+    Z,
+}
+
+impl Up {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "y" => Some(Up::Y),
+            "z" => Some(Up::Z),
+            _ => None,
+        }
+    }
+
+    /// Turns directions laid out with Z up, as the camera's presets and the grid are, to have this axis up
+    /// instead. Y up has +Z towards the front view's camera and -Z up the screen in the top view.
+    pub fn rotation(&self) -> Matrix3<f32> {
+        match self {
+            Up::Z => Matrix3::identity(),
+            Up::Y => Matrix3::new(1.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 1.0, 0.0),
+        }
+    }
+
+    pub fn vector(&self) -> Vector3<f32> {
+        self.rotation() * Vector3::unit_z()
+    }
+
+    /// Turns positions in an asset with `source` up so that its up is this one
+    pub fn convert(&self, source: Up) -> Matrix4<f32> {
+        Matrix4::from(self.rotation() * source.rotation().transpose())
+    }
+}
+
+/// How the world is laid out: which axis is up and how long its unit is. The camera orbits and walks about the
+/// up axis, the grid lies across it and imported assets are turned to match it. Only the demo scene's own
+/// geometry and physics are built with Z up whatever the world's up is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Convention {
+    pub up: Up,
+    /// Metres in one of the world's units, which the grid's spacing is a power of ten of
+    pub unit: f32,
+}
+
+impl Default for Convention {
+    fn default() -> Self {
+        Self {
+            up: Up::Z,
+            unit: 1.0,
+        }
+    }
+}
+
+impl Convention {
+    /// World units in `metres`
+    pub fn units(&self, metres: f32) -> f32 {
+        metres / self.unit
+    }
+
+    pub fn metres(&self, units: f32) -> f32 {
+        units * self.unit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector4};
+
+    use super::*;
+
+    #[test]
+    fn rotations_put_up_on_the_axis() {
+        assert_eq!(Up::Z.vector(), Vector3::unit_z());
+        assert_eq!(Up::Y.vector(), Vector3::unit_y());
+        // The front view's camera is towards +Z with Y up
+        assert_eq!(Up::Y.rotation() * -Vector3::unit_y(), Vector3::unit_z());
+        assert_eq!(Up::Y.rotation() * Vector3::unit_x(), Vector3::unit_x());
+    }
+
+    #[test]
+    fn converts_between_ups() {
+        let point = Vector4::new(1.0, 2.0, 3.0, 1.0);
+        assert_eq!(Up::Y.convert(Up::Y) * point, point);
+        assert_eq!(
+            Up::Z.convert(Up::Y) * point,
+            Vector4::new(1.0, -3.0, 2.0, 1.0)
+        );
+        let there_and_back = Up::Y.convert(Up::Z) * Up::Z.convert(Up::Y) * point;
+        assert!((there_and_back - point).magnitude() < 1e-6);
+        // An asset's up ends up along the world's
+        let asset_up = Up::Y.vector().extend(0.0);
+        assert_eq!(Up::Z.convert(Up::Y) * asset_up, Up::Z.vector().extend(0.0));
+    }
+
+    #[test]
+    fn parses_axis_names() {
+        assert_eq!(Up::parse("y"), Some(Up::Y));
+        assert_eq!(Up::parse("z"), Some(Up::Z));
+        assert_eq!(Up::parse("x"), None);
+    }
+
+    #[test]
+    fn converts_units_and_metres() {
+        let centimetres = Convention {
+            unit: 0.01,
+            ..Convention::default()
+        };
+        assert!((centimetres.units(1.5) - 150.0).abs() < 1e-4);
+        assert!((centimetres.metres(250.0) - 2.5).abs() < 1e-6);
+        assert!((centimetres.metres(centimetres.units(3.7)) - 3.7).abs() < 1e-6);
+        assert_eq!(Convention::default().units(4.0), 4.0);
+    }
+}
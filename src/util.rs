@@ -1,4 +1,6 @@
-use std::{ffi, fs, os::raw, path, string};
+use std::{ffi, fs, os::raw, path, str};
+
+use ash::vk;
 
 pub fn read_shader_code(shader_path: &path::Path) -> Vec<u32> {
     let mut spv_file = fs::File::open(shader_path)
@@ -7,19 +9,106 @@ pub fn read_shader_code(shader_path: &path::Path) -> Vec<u32> {
     ash::util::read_spv(&mut spv_file).expect("spv file")
 }
 
-pub fn read_vk_string(chars: &[raw::c_char]) -> Result<String, string::FromUtf8Error> {
-    let terminator = '\0' as u8;
-    let mut content: Vec<u8> = vec![];
-
-    for raw in chars.iter() {
-        let ch = (*raw) as u8;
+/// Reads a nul terminated string from a fixed size Vulkan array, ending at the end of the array if it has no
+/// terminator. Vulkan's names are UTF-8, but those of drivers and layers don't always keep to it, so see
+/// `utf8_lossless` for how other bytes are read.
+pub fn read_vk_string(chars: &[raw::c_char]) -> String {
+    let content: Vec<u8> = chars
+        .iter()
+        .map(|&ch| ch as u8)
+        .take_while(|&ch| ch != 0)
+        .collect();
+    utf8_lossless(&content)
+}
 
-        if ch != terminator {
-            content.push(ch);
-        } else {
-            break;
+/// The bytes as text, with each byte that isn't part of valid UTF-8 kept as a `\xNN` escape rather than
+/// replaced, so that names differing only in those bytes stay different. Backslashes are doubled, so an escape
+/// can't be mistaken for the same characters in the name.
+pub fn utf8_lossless(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len());
+    let mut rest = bytes;
+    loop {
+        match str::from_utf8(rest) {
+            Ok(valid) => {
+                text.push_str(&valid.replace('\\', "\\\\"));
+                return text;
+            }
+            Err(e) => {
+                let (valid, invalid) = rest.split_at(e.valid_up_to());
+                let valid = str::from_utf8(valid).expect("Checked to be valid");
+                text.push_str(&valid.replace('\\', "\\\\"));
+                let length = e.error_len().unwrap_or(invalid.len());
+                for byte in &invalid[..length] {
+                    text.push_str(&format!("\\x{:02x}", byte));
+                }
+                rest = &invalid[length..];
+            }
         }
     }
+}
+
+/// The text as a nul terminated string to pass to Vulkan. Nuls within it are written as `\0` so that the rest
+/// isn't cut off, and backslashes are doubled first so that a `\0` in the text stays apart from a nul.
+pub fn vk_string(text: &str) -> ffi::CString {
+    let escaped = text.replace('\\', "\\\\").replace('\0', "\\0");
+    ffi::CString::new(escaped).expect("Nuls have been replaced")
+}
+
+/// Encodes a linear colour channel from 0 to 1 to sRGB
+pub fn encode_srgb(linear: f32) -> f32 {
+    if linear <= 0.003_130_8 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
 
-    String::from_utf8(content)
+/// Whether the format encodes linear colour values to sRGB when written and decodes them when read.
+pub fn is_srgb_format(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::R8_SRGB
+            | vk::Format::R8G8_SRGB
+            | vk::Format::R8G8B8_SRGB
+            | vk::Format::B8G8R8_SRGB
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::B8G8R8A8_SRGB
+            | vk::Format::A8B8G8R8_SRGB_PACK32
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_utf8_is_escaped() {
+        assert_eq!(utf8_lossless(b"layer"), "layer");
+        assert_eq!(utf8_lossless(b"bad\xff\xfename"), "bad\\xff\\xfename");
+        // Cut off in the middle of a character
+        assert_eq!(utf8_lossless(b"caf\xc3"), "caf\\xc3");
+    }
+
+    #[test]
+    fn backslashes_stay_apart_from_escapes() {
+        assert_eq!(utf8_lossless(b"a\\b"), "a\\\\b");
+        assert_ne!(utf8_lossless(b"\\xff"), utf8_lossless(b"\xff"));
+    }
+
+    #[test]
+    fn nuls_are_escaped_for_vulkan() {
+        assert_eq!(vk_string("name").as_bytes(), b"name");
+        assert_eq!(vk_string("a\0b").as_bytes(), b"a\\0b");
+        assert_eq!(vk_string("a\\0b").as_bytes(), b"a\\\\0b");
+        assert_ne!(vk_string("a\0b"), vk_string("a\\0b"));
+    }
+
+    #[test]
+    fn vulkan_strings_end_at_their_nul() {
+        let chars: Vec<raw::c_char> = b"VK_KHR_swapchain\0junk"
+            .iter()
+            .map(|&byte| byte as raw::c_char)
+            .collect();
+        assert_eq!(read_vk_string(&chars), "VK_KHR_swapchain");
+    }
 }
@@ -0,0 +1,103 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Mutex;
+
+use ash::vk;
+
+/// Whether the debug callback's warnings and errors are being collected for a report, see `fail_on_messages`
+static COLLECTING: AtomicBool = AtomicBool::new(false);
+/// Each different message collected, with how many times it arrived
+static MESSAGES: Mutex<Vec<(String, usize)>> = Mutex::new(Vec::new());
+static FRAMES_DRAWN: AtomicU32 = AtomicU32::new(0);
+
+/// Collects every warning and error the debug callback receives from now on, so that a run drawing a fixed
+/// number of frames can fail if it wasn't validation clean. Must be called before the instance is created to
+/// catch what creating it reports.
+pub fn fail_on_messages() {
+    COLLECTING.store(true, Ordering::Relaxed);
+}
+
+/// Called by the debug callback with each message it prints
+pub fn record(severity: vk::DebugUtilsMessageSeverityFlagsEXT, message: &str) {
+    let failure = vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+        | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR;
+    if COLLECTING.load(Ordering::Relaxed) && failure.intersects(severity) {
+        collect(message);
+    }
+}
+
+/// Fails the run with a message that didn't come from the debug callback, such as the validation layers being
+/// missing, which would otherwise leave nothing to report
+pub fn record_failure(message: &str) {
+    if COLLECTING.load(Ordering::Relaxed) {
+        collect(message);
+    }
+}
+
+fn collect(message: &str) {
+    // A message is still collected after a thread panicked holding the lock
+    let mut messages = MESSAGES.lock().unwrap_or_else(|e| e.into_inner());
+    match messages
+        .iter_mut()
+        .find(|(collected, _)| collected == message)
+    {
+        Some((_, count)) => *count += 1,
+        None => messages.push((String::from(message), 1)),
+    }
+}
+
+/// Called by the render thread each time it has drawn a frame
+pub fn frame_drawn() {
+    FRAMES_DRAWN.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Whether a run meant to draw `frames` frames drew them all without any warnings or errors.
+pub struct Report {
+    pub frames: u32,
+    pub frames_drawn: u32,
+    /// Every different message, in the order they first arrived, with how many times each arrived
+    pub messages: Vec<(String, usize)>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.frames_drawn >= self.frames && self.messages.is_empty()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.passed() {
+            return write!(f, "Validation clean over {} frames", self.frames_drawn);
+        }
+        write!(f, "Validation failed")?;
+        if self.frames_drawn < self.frames {
+            write!(
+                f,
+                ", the renderer stopped after {} of {} frames",
+                self.frames_drawn, self.frames
+            )?;
+        }
+        let total: usize = self.messages.iter().map(|(_, count)| count).sum();
+        write!(
+            f,
+            "\n{} messages, {} different:",
+            total,
+            self.messages.len()
+        )?;
+        for (message, count) in &self.messages {
+            write!(f, "\n{:>6}x {}", count, message)?;
+        }
+        Ok(())
+    }
+}
+
+/// What has been collected since `fail_on_messages`, for a run meant to draw `frames` frames
+pub fn report(frames: u32) -> Report {
+    let messages = MESSAGES.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    Report {
+        frames,
+        frames_drawn: FRAMES_DRAWN.load(Ordering::Relaxed),
+        messages,
+    }
+}
@@ -0,0 +1,525 @@
+use std::path::Path;
+use std::time::Duration;
+
+use ash::vk;
+use ash::vk::MemoryMapFlags;
+use cgmath::{InnerSpace, Point3, Vector3};
+
+use crate::{
+    begin_single_time_commands, bounds, end_single_time_commands, HelloTriangleApplication,
+    ImageDescription,
+};
+
+/// The largest image width and height every device supports. Textures are a vertex wide and two rows per frame.
+const MAX_TEXTURE_SIZE: u32 = 4096;
+const FORMAT: vk::Format = vk::Format::R32G32B32A32_SFLOAT;
+
+/// The positions and normals of every vertex of a mesh at one moment of an animation.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+}
+
+/// A pre-simulated animation of a mesh, one `Frame` per sample. Every frame has a value for each of the mesh's
+/// vertices, in the same order as the mesh's vertex buffer.
+#[derive(Clone, Debug)]
+pub struct Animation {
+    pub frames: Vec<Frame>,
+    /// Frames per second the animation was sampled at
+    pub fps: f32,
+}
+
+impl Animation {
+    /// An animation of the mesh with the given indices, from the positions of its vertices in each frame. Normals
+    /// are worked out from the triangles around each vertex, taking them to wind counter-clockwise around their
+    /// front like the renderer's built in geometry.
+    pub fn from_positions(
+        positions: Vec<Vec<[f32; 3]>>,
+        indices: &[u16],
+        fps: f32,
+    ) -> Result<Self, String> {
+        let frames = positions
+            .into_iter()
+            .map(|positions| Frame {
+                normals: vertex_normals(&positions, indices),
+                positions,
+            })
+            .collect();
+        let animation = Self { frames, fps };
+        animation.validate()?;
+        Ok(animation)
+    }
+
+    /// Reads an animation saved by `save`. Any 32 bit float image laid out the same way can be loaded, such as
+    /// one exported from a simulation package.
+    #[cfg(feature = "images")]
+    pub fn load(path: &Path, fps: f32) -> Result<Self, String> {
+        let image = image::open(path)
+            .map_err(|e| format!("Opening {}: {}", path.display(), e))?
+            .into_rgba32f();
+        let (vertex_count, rows) = image.dimensions();
+        if rows % 2 != 0 {
+            return Err(format!(
+                "{} has {} rows, it needs a row of positions and one of normals per frame",
+                path.display(),
+                rows
+            ));
+        }
+
+        let frame_count = rows / 2;
+        let row = |row: u32| -> Vec<[f32; 3]> {
+            (0..vertex_count)
+                .map(|vertex| {
+                    let [x, y, z, _] = image.get_pixel(vertex, row).0;
+                    [x, y, z]
+                })
+                .collect()
+        };
+        let frames = (0..frame_count)
+            .map(|frame| Frame {
+                positions: row(frame),
+                normals: row(frame_count + frame),
+            })
+            .collect();
+        let animation = Self { frames, fps };
+        animation.validate()?;
+        Ok(animation)
+    }
+
+    /// Saves the animation as it is laid out in its texture, as a 32 bit float image such as OpenEXR.
+    #[cfg(feature = "images")]
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let (width, height, texels) = self.texels();
+        image::Rgba32FImage::from_raw(width, height, texels)
+            .expect("Texture has every texel")
+            .save(path)
+            .map_err(|e| format!("Saving {}: {}", path.display(), e))
+    }
+
+    /// Vertex animation textures are only read and written with the `images` feature
+    #[cfg(not(feature = "images"))]
+    pub fn load(path: &Path, _fps: f32) -> Result<Self, String> {
+        Err(format!(
+            "Opening {}: vertex animations can't be read without the `images` feature",
+            path.display()
+        ))
+    }
+
+    #[cfg(not(feature = "images"))]
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        Err(format!(
+            "Saving {}: vertex animations can't be written without the `images` feature",
+            path.display()
+        ))
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.frames.first().map_or(0, |frame| frame.positions.len())
+    }
+
+    /// Bounds of every position the animation's vertices pass through
+    pub fn bounds(&self) -> Option<bounds::Aabb> {
+        bounds::Aabb::from_points(
+            self.frames
+                .iter()
+                .flat_map(|frame| frame.positions.iter())
+                .map(|&position| Point3::from(position)),
+        )
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let vertex_count = self.vertex_count();
+        if self.frames.is_empty() || vertex_count == 0 {
+            return Err(String::from(
+                "A vertex animation needs at least one frame and vertex",
+            ));
+        }
+        if self.frames.iter().any(|frame| {
+            frame.positions.len() != vertex_count || frame.normals.len() != vertex_count
+        }) {
+            return Err(String::from(
+                "Every frame of a vertex animation needs a position and normal for each vertex",
+            ));
+        }
+        if vertex_count as u32 > MAX_TEXTURE_SIZE || 2 * self.frames.len() as u32 > MAX_TEXTURE_SIZE
+        {
+            return Err(format!(
+                "Vertex animations can have at most {} vertices and {} frames",
+                MAX_TEXTURE_SIZE,
+                MAX_TEXTURE_SIZE / 2
+            ));
+        }
+        if self.fps.is_nan() || self.fps <= 0.0 {
+            return Err(String::from(
+                "A vertex animation needs a positive frame rate",
+            ));
+        }
+        Ok(())
+    }
+
+    /// The texture's width, height and contents: a column per vertex, with each frame's positions in the top
+    /// half of the rows and its normals in the bottom half, as red, green, blue and alpha floats row by row.
+    fn texels(&self) -> (u32, u32, Vec<f32>) {
+        let frame_count = self.frames.len();
+        let texels = (0..2 * frame_count)
+            .flat_map(|row| {
+                let frame = &self.frames[row % frame_count];
+                if row < frame_count {
+                    &frame.positions
+                } else {
+                    &frame.normals
+                }
+            })
+            .flat_map(|&[x, y, z]| [x, y, z, 1.0])
+            .collect();
+        (self.vertex_count() as u32, 2 * frame_count as u32, texels)
+    }
+}
+
+/// Area weighted normals of each vertex of the triangles
+fn vertex_normals(positions: &[[f32; 3]], indices: &[u16]) -> Vec<[f32; 3]> {
+    let mut normals = vec![Vector3::new(0.0, 0.0, 0.0); positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| Vector3::from(positions[triangle[i] as usize]));
+        let normal = (b - a).cross(c - a);
+        for &index in triangle {
+            normals[index as usize] += normal;
+        }
+    }
+    normals
+        .into_iter()
+        .map(|normal| {
+            if normal.magnitude2() > 0.0 {
+                normal.normalize().into()
+            } else {
+                [0.0, 0.0, 1.0]
+            }
+        })
+        .collect()
+}
+
+/// Where an animation is up to. Advance it each frame and draw with its `frame`.
+#[derive(Clone, Copy, Debug)]
+pub struct Playback {
+    /// How fast the animation plays, 1 being the rate it was sampled at. Negative speeds play it backwards.
+    pub speed: f32,
+    /// Whether it starts over once it reaches the end, blending the last frame back into the first. Otherwise
+    /// it stops on the last frame.
+    pub looping: bool,
+    pub playing: bool,
+    fps: f32,
+    frame_count: u32,
+    /// Seconds into the animation
+    time: f32,
+}
+
+impl Playback {
+    /// Looping at the animation's own rate, from its first frame
+    pub fn new(animation: &Animation) -> Self {
+        Self {
+            speed: 1.0,
+            looping: true,
+            playing: true,
+            fps: animation.fps,
+            frame_count: animation.frames.len() as u32,
+            time: 0.0,
+        }
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    /// Jumps to `seconds` into the animation
+    pub fn seek(&mut self, seconds: f32) {
+        self.time = seconds;
+        self.wrap();
+    }
+
+    pub fn advance(&mut self, elapsed: Duration) {
+        if self.playing {
+            self.time += elapsed.as_secs_f32() * self.speed;
+            self.wrap();
+        }
+    }
+
+    /// The frame to show, with the fraction of the way to the next frame. The vertex shader blends between them.
+    pub fn frame(&self) -> f32 {
+        self.time * self.fps
+    }
+
+    fn last_frame_time(&self) -> f32 {
+        (self.frame_count - 1) as f32 / self.fps
+    }
+
+    fn wrap(&mut self) {
+        self.time = if self.looping {
+            self.time.rem_euclid(self.frame_count as f32 / self.fps)
+        } else {
+            num::clamp(self.time, 0.0, self.last_frame_time())
+        };
+    }
+}
+
+/// What a draw of `pipeline::Program::Vat` binds to show its animation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Binding {
+    pub descriptor_set: vk::DescriptorSet,
+    /// See `Playback::frame`
+    pub frame: f32,
+}
+
+/// The layout of the descriptor set holding an animation's texture, bound as set 1.
+pub fn create_descriptor_set_layout(device: &ash::Device) -> vk::DescriptorSetLayout {
+    let bindings = [vk::DescriptorSetLayoutBinding::builder()
+        .binding(0)
+        .descriptor_count(1)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .stage_flags(vk::ShaderStageFlags::VERTEX)
+        .build()];
+    let ci = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+    unsafe {
+        device
+            .create_descriptor_set_layout(&ci, None)
+            .expect("Vertex animation descriptor set layout")
+    }
+}
+
+/// An animation uploaded to a texture, and the descriptor set that binds it.
+pub struct Texture {
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    view: vk::ImageView,
+    sampler: vk::Sampler,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+}
+
+impl Texture {
+    pub fn new(
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        layout: vk::DescriptorSetLayout,
+        animation: &Animation,
+    ) -> Self {
+        let (width, height, data) = animation.texels();
+        let size = (data.len() * std::mem::size_of::<f32>()) as vk::DeviceSize;
+
+        let (staging_buffer, staging_memory) = HelloTriangleApplication::create_buffer(
+            device,
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            device_memory_properties,
+        );
+        unsafe {
+            let mapped = device
+                .map_memory(staging_memory, 0, size, MemoryMapFlags::empty())
+                .expect("Mapping vertex animation staging buffer")
+                as *mut f32;
+            mapped.copy_from_nonoverlapping(data.as_ptr(), data.len());
+            device.unmap_memory(staging_memory);
+        }
+
+        let (image, memory) = HelloTriangleApplication::create_image(
+            device,
+            &ImageDescription {
+                width,
+                height,
+                format: FORMAT,
+                tiling: vk::ImageTiling::OPTIMAL,
+                usage: vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+                memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            },
+            device_memory_properties,
+        );
+        let command_buffer = begin_single_time_commands(device, command_pool);
+        let range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let barrier = |old, new, src_access, dst_access| {
+            vk::ImageMemoryBarrier::builder()
+                .old_layout(old)
+                .new_layout(new)
+                .src_access_mask(src_access)
+                .dst_access_mask(dst_access)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(range)
+                .build()
+        };
+        let region = vk::BufferImageCopy::builder()
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .image_extent(vk::Extent3D {
+                width,
+                height,
+                depth: 1,
+            })
+            .build();
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier(
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::AccessFlags::empty(),
+                    vk::AccessFlags::TRANSFER_WRITE,
+                )],
+            );
+            device.cmd_copy_buffer_to_image(
+                command_buffer,
+                staging_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region],
+            );
+            // Read by the vertex shader, unlike other textures
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::VERTEX_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier(
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    vk::AccessFlags::SHADER_READ,
+                )],
+            );
+        }
+        end_single_time_commands(device, command_pool, command_buffer, queue);
+        unsafe {
+            device.destroy_buffer(staging_buffer, None);
+            device.free_memory(staging_memory, None);
+        }
+
+        let view = HelloTriangleApplication::create_image_view(
+            device,
+            image,
+            FORMAT,
+            vk::ImageAspectFlags::COLOR,
+        );
+        // Texels are fetched directly, float formats don't have to support filtering
+        let sampler = unsafe {
+            device
+                .create_sampler(
+                    &vk::SamplerCreateInfo::builder()
+                        .mag_filter(vk::Filter::NEAREST)
+                        .min_filter(vk::Filter::NEAREST)
+                        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                        .max_lod(0.0),
+                    None,
+                )
+                .expect("Vertex animation sampler")
+        };
+
+        let pool_sizes = [vk::DescriptorPoolSize::builder()
+            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .build()];
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::builder()
+                        .pool_sizes(&pool_sizes)
+                        .max_sets(1),
+                    None,
+                )
+                .expect("Vertex animation descriptor pool")
+        };
+        let layouts = [layout];
+        let descriptor_set = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::builder()
+                        .descriptor_pool(descriptor_pool)
+                        .set_layouts(&layouts),
+                )
+                .expect("Allocating vertex animation descriptor set")[0]
+        };
+        let image_info = [vk::DescriptorImageInfo::builder()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(view)
+            .sampler(sampler)
+            .build()];
+        let write = [vk::WriteDescriptorSet::builder()
+            .dst_set(descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_info)
+            .build()];
+        unsafe { device.update_descriptor_sets(&write, &[]) };
+
+        Self {
+            image,
+            memory,
+            view,
+            sampler,
+            descriptor_pool,
+            descriptor_set,
+        }
+    }
+
+    pub fn binding(&self, playback: &Playback) -> Binding {
+        Binding {
+            descriptor_set: self.descriptor_set,
+            frame: playback.frame(),
+        }
+    }
+
+    pub fn destroy(self, device: &ash::Device) {
+        unsafe {
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            device.destroy_sampler(self.sampler, None);
+            device.destroy_image_view(self.view, None);
+            device.destroy_image(self.image, None);
+            device.free_memory(self.memory, None);
+        }
+    }
+}
+
+/// An animated draw, where its animation is up to and the buffers it owns.
+pub struct Animated {
+    pub draw: usize,
+    pub texture: Texture,
+    pub playback: Playback,
+    pub buffers: Vec<(vk::Buffer, vk::DeviceMemory)>,
+}
+
+impl Animated {
+    pub fn destroy(self, device: &ash::Device) {
+        self.texture.destroy(device);
+        unsafe {
+            for (buffer, memory) in self.buffers {
+                device.destroy_buffer(buffer, None);
+                device.free_memory(memory, None);
+            }
+        }
+    }
+}
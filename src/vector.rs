@@ -0,0 +1,736 @@
+use std::fmt;
+use std::path::Path as FilePath;
+
+use lyon::math::{point, vector, Angle, Point};
+use lyon::path::builder::SvgPathBuilder;
+use lyon::path::{ArcFlags, Path};
+use lyon::tessellation::{
+    BuffersBuilder, FillOptions, FillRule, FillTessellator, FillVertex, StrokeOptions,
+    StrokeTessellator, StrokeVertex, VertexBuffers,
+};
+
+use crate::sdf;
+
+/// How far the tessellated curves may stray from the true ones, in physical pixels
+const TOLERANCE: f32 = 0.25;
+
+/// Elements whose children aren't drawn where they are
+const HIDDEN: [&str; 6] = ["defs", "clipPath", "mask", "symbol", "pattern", "marker"];
+
+/// An SVG icon, flattened into its filled and stroked paths in the order they are painted. Only what HUD icons
+/// tend to use is read: `path`, `rect`, `circle`, `ellipse`, `line`, `polyline` and `polygon` elements, grouped in
+/// `g` elements, painted with `fill`, `stroke`, `stroke-width`, `fill-rule` and the opacities, as attributes or in
+/// a `style`. Transforms, gradients, text and references to other elements, as in `use`, are ignored. Group opacity multiplies
+/// into each element's paint instead of compositing the group as a whole.
+pub struct Graphic {
+    /// The area of the document's coordinates drawn, as the left, top, width and height
+    view_box: [f32; 4],
+    shapes: Vec<Shape>,
+}
+
+struct Shape {
+    path: Path,
+    /// Linear colours with straight alpha
+    fill: Option<([f32; 4], FillRule)>,
+    stroke: Option<([f32; 4], f32)>,
+}
+
+impl fmt::Debug for Graphic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Graphic")
+            .field("view_box", &self.view_box)
+            .field("shapes", &self.shapes.len())
+            .finish()
+    }
+}
+
+impl Graphic {
+    pub fn load(path: &FilePath) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Reading {}: {}", path.display(), e))?;
+        Self::parse(&source).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut view_box = None;
+        let mut shapes = Vec::new();
+        // The paint of each open group, innermost last
+        let mut paints = vec![Paint::default()];
+        // How deep in elements whose children are only drawn where they're referenced the scanner is
+        let mut hidden = 0;
+        for tag in tags(source) {
+            let tag = tag?;
+            if HIDDEN.contains(&tag.name) {
+                match (tag.closing, tag.self_closing) {
+                    (true, _) => hidden -= 1,
+                    (false, false) => hidden += 1,
+                    (false, true) => (),
+                }
+                continue;
+            }
+            if hidden > 0 {
+                continue;
+            }
+            if tag.closing {
+                if tag.name == "g" || tag.name == "svg" {
+                    // The document's own paint is never popped
+                    if paints.len() > 1 {
+                        paints.pop();
+                    }
+                }
+                continue;
+            }
+            let paint = paints.last().expect("The document's paint").inherit(&tag);
+            let data = match tag.name {
+                "svg" => {
+                    view_box = view_box.or_else(|| document_view_box(&tag));
+                    None
+                }
+                "path" => tag.attribute("d").map(String::from),
+                "rect" => rect_data(&tag),
+                "circle" | "ellipse" => ellipse_data(&tag),
+                "line" => Some(format!(
+                    "M {} {} L {} {}",
+                    tag.number("x1"),
+                    tag.number("y1"),
+                    tag.number("x2"),
+                    tag.number("y2")
+                )),
+                "polyline" => tag
+                    .attribute("points")
+                    .map(|points| format!("M {}", points)),
+                "polygon" => tag
+                    .attribute("points")
+                    .map(|points| format!("M {} Z", points)),
+                _ => None,
+            };
+            if (tag.name == "g" || tag.name == "svg") && !tag.self_closing {
+                paints.push(paint);
+            } else if let Some(data) = data {
+                let fill = paint
+                    .fill
+                    .map(|color| (paint.color(color, paint.fill_opacity), paint.fill_rule));
+                let stroke = match paint.stroke {
+                    Some(color) if paint.stroke_width > 0.0 => {
+                        Some((paint.color(color, paint.stroke_opacity), paint.stroke_width))
+                    }
+                    _ => None,
+                };
+                if fill.is_some() || stroke.is_some() {
+                    shapes.push(Shape {
+                        path: parse_path(&data)?,
+                        fill,
+                        stroke,
+                    });
+                }
+            }
+        }
+        Ok(Self {
+            view_box: view_box.ok_or_else(|| String::from("Not an SVG document"))?,
+            shapes,
+        })
+    }
+}
+
+impl sdf::Tessellate for Graphic {
+    /// Fits the view box into the rectangle, keeping its aspect ratio and centring it, as SVG does by default
+    fn tessellate(&self, rect: [f32; 4]) -> Vec<sdf::Vertex> {
+        let [left, top, width, height] = self.view_box;
+        let scale = (rect[2] / width).min(rect[3] / height);
+        if scale.is_nan() || scale <= 0.0 {
+            return Vec::new();
+        }
+        let offset = [
+            rect[0] + (rect[2] - width * scale) / 2.0 - left * scale,
+            rect[1] + (rect[3] - height * scale) / 2.0 - top * scale,
+        ];
+        let to_pixels = |position: Point| {
+            [
+                offset[0] + position.x * scale,
+                offset[1] + position.y * scale,
+            ]
+        };
+        // Tessellated in the document's coordinates
+        let tolerance = TOLERANCE / scale;
+
+        let mut vertices = Vec::new();
+        let mut fills = FillTessellator::new();
+        let mut strokes = StrokeTessellator::new();
+        let mut buffers: VertexBuffers<Point, u32> = VertexBuffers::new();
+        for shape in self.shapes.iter() {
+            if let Some((color, rule)) = shape.fill {
+                buffers.vertices.clear();
+                buffers.indices.clear();
+                let options = FillOptions::tolerance(tolerance).with_fill_rule(rule);
+                let mut builder =
+                    BuffersBuilder::new(&mut buffers, |vertex: FillVertex| vertex.position());
+                if fills
+                    .tessellate_path(&shape.path, &options, &mut builder)
+                    .is_ok()
+                {
+                    triangles(&buffers, color, to_pixels, &mut vertices);
+                }
+            }
+            if let Some((color, width)) = shape.stroke {
+                buffers.vertices.clear();
+                buffers.indices.clear();
+                let options = StrokeOptions::tolerance(tolerance).with_line_width(width);
+                let mut builder =
+                    BuffersBuilder::new(&mut buffers, |vertex: StrokeVertex| vertex.position());
+                if strokes
+                    .tessellate_path(&shape.path, &options, &mut builder)
+                    .is_ok()
+                {
+                    triangles(&buffers, color, to_pixels, &mut vertices);
+                }
+            }
+        }
+        vertices
+    }
+}
+
+/// Appends the indexed triangles as a list of vertices, the way the layer draws them
+fn triangles(
+    buffers: &VertexBuffers<Point, u32>,
+    color: [f32; 4],
+    to_pixels: impl Fn(Point) -> [f32; 2],
+    vertices: &mut Vec<sdf::Vertex>,
+) {
+    vertices.extend(buffers.indices.iter().map(|&index| sdf::Vertex {
+        position: to_pixels(buffers.vertices[index as usize]),
+        color,
+    }));
+}
+
+/// What an element is painted with, inherited from the groups around it
+#[derive(Clone, Copy, Debug)]
+struct Paint {
+    /// sRGB colours, without alpha
+    fill: Option<[f32; 3]>,
+    stroke: Option<[f32; 3]>,
+    stroke_width: f32,
+    fill_rule: FillRule,
+    opacity: f32,
+    fill_opacity: f32,
+    stroke_opacity: f32,
+}
+
+impl Default for Paint {
+    /// Filled black and unstroked, as SVG paints by default
+    fn default() -> Self {
+        Self {
+            fill: Some([0.0; 3]),
+            stroke: None,
+            stroke_width: 1.0,
+            fill_rule: FillRule::NonZero,
+            opacity: 1.0,
+            fill_opacity: 1.0,
+            stroke_opacity: 1.0,
+        }
+    }
+}
+
+impl Paint {
+    /// The paint of an element with the tag inside this paint's element. Properties it can't read are inherited.
+    fn inherit(&self, tag: &Tag) -> Self {
+        let mut paint = *self;
+        paint.opacity = 1.0;
+        for (name, value) in tag.properties() {
+            match name {
+                "fill" => paint.fill = parse_paint(value).unwrap_or(paint.fill),
+                "stroke" => paint.stroke = parse_paint(value).unwrap_or(paint.stroke),
+                "stroke-width" => {
+                    paint.stroke_width = parse_length(value).unwrap_or(paint.stroke_width)
+                }
+                "fill-rule" => {
+                    paint.fill_rule = match value {
+                        "evenodd" => FillRule::EvenOdd,
+                        _ => FillRule::NonZero,
+                    }
+                }
+                "opacity" => paint.opacity = parse_length(value).unwrap_or(1.0),
+                "fill-opacity" => {
+                    paint.fill_opacity = parse_length(value).unwrap_or(paint.fill_opacity)
+                }
+                "stroke-opacity" => {
+                    paint.stroke_opacity = parse_length(value).unwrap_or(paint.stroke_opacity)
+                }
+                _ => (),
+            }
+        }
+        // Opacity isn't inherited, but the groups' multiply into their elements'
+        paint.opacity *= self.opacity;
+        paint
+    }
+
+    fn color(&self, srgb: [f32; 3], opacity: f32) -> [f32; 4] {
+        let [r, g, b] = srgb;
+        [
+            decode_srgb(r),
+            decode_srgb(g),
+            decode_srgb(b),
+            (opacity * self.opacity).clamp(0.0, 1.0),
+        ]
+    }
+}
+
+fn decode_srgb(encoded: f32) -> f32 {
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// A paint's colour, `Some(None)` for `none`, or `None` if it can't be read
+fn parse_paint(value: &str) -> Option<Option<[f32; 3]>> {
+    let value = value.trim();
+    if value == "none" || value == "transparent" {
+        return Some(None);
+    }
+    if let Some(hex) = value.strip_prefix('#') {
+        let digits: Vec<u32> = hex.chars().map(|c| c.to_digit(16)).collect::<Option<_>>()?;
+        let channels = match digits[..] {
+            [r, g, b] => [r * 17, g * 17, b * 17],
+            [r1, r0, g1, g0, b1, b0] => [r1 * 16 + r0, g1 * 16 + g0, b1 * 16 + b0],
+            _ => return None,
+        };
+        return Some(Some(channels.map(|channel| channel as f32 / 255.0)));
+    }
+    if let Some(arguments) = value
+        .strip_prefix("rgb(")
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        let channels: Vec<f32> = arguments
+            .split(',')
+            .map(|channel| match channel.trim().strip_suffix('%') {
+                Some(percent) => percent.parse::<f32>().map(|percent| percent / 100.0),
+                None => channel.trim().parse::<f32>().map(|channel| channel / 255.0),
+            })
+            .collect::<Result<_, _>>()
+            .ok()?;
+        return match channels[..] {
+            [r, g, b] => Some(Some([r, g, b].map(|channel| channel.clamp(0.0, 1.0)))),
+            _ => None,
+        };
+    }
+    let named = match value {
+        "black" => [0, 0, 0],
+        "white" => [255, 255, 255],
+        "red" => [255, 0, 0],
+        "lime" => [0, 255, 0],
+        "green" => [0, 128, 0],
+        "blue" => [0, 0, 255],
+        "yellow" => [255, 255, 0],
+        "cyan" | "aqua" => [0, 255, 255],
+        "magenta" | "fuchsia" => [255, 0, 255],
+        "orange" => [255, 165, 0],
+        "gray" | "grey" => [128, 128, 128],
+        // Icons drawn to be tinted are tinted white, so they take the colour of whatever is drawn beneath them
+        "currentColor" => [255, 255, 255],
+        _ => return None,
+    };
+    Some(Some(named.map(|channel| channel as f32 / 255.0)))
+}
+
+/// A number with an optional `px` unit
+fn parse_length(value: &str) -> Option<f32> {
+    let value = value.trim();
+    value
+        .strip_suffix("px")
+        .unwrap_or(value)
+        .trim()
+        .parse()
+        .ok()
+}
+
+fn document_view_box(tag: &Tag) -> Option<[f32; 4]> {
+    if let Some(view_box) = tag.attribute("viewBox") {
+        let numbers = Numbers::new(view_box).collect::<Result<Vec<_>, _>>().ok()?;
+        return match numbers[..] {
+            [left, top, width, height] if width > 0.0 && height > 0.0 => {
+                Some([left, top, width, height])
+            }
+            _ => None,
+        };
+    }
+    let width = tag.attribute("width").and_then(parse_length)?;
+    let height = tag.attribute("height").and_then(parse_length)?;
+    Some([0.0, 0.0, width, height])
+}
+
+fn rect_data(tag: &Tag) -> Option<String> {
+    let (x, y, width, height) = (
+        tag.number("x"),
+        tag.number("y"),
+        tag.number("width"),
+        tag.number("height"),
+    );
+    if width <= 0.0 || height <= 0.0 {
+        return None;
+    }
+    // A missing radius takes the other's, as in SVG
+    let (rx, ry) = match (
+        tag.attribute("rx").and_then(parse_length),
+        tag.attribute("ry").and_then(parse_length),
+    ) {
+        (Some(rx), Some(ry)) => (rx, ry),
+        (Some(r), None) | (None, Some(r)) => (r, r),
+        (None, None) => (0.0, 0.0),
+    };
+    let (rx, ry) = (rx.clamp(0.0, width / 2.0), ry.clamp(0.0, height / 2.0));
+    if rx <= 0.0 || ry <= 0.0 {
+        return Some(format!(
+            "M {} {} h {} v {} h {} Z",
+            x, y, width, height, -width
+        ));
+    }
+    Some(format!(
+        "M {x0} {y} H {x1} A {rx} {ry} 0 0 1 {x2} {y0} V {y1} A {rx} {ry} 0 0 1 {x1} {y2} H {x0} \
+         A {rx} {ry} 0 0 1 {x} {y1} V {y0} A {rx} {ry} 0 0 1 {x0} {y} Z",
+        x = x,
+        y = y,
+        x0 = x + rx,
+        x1 = x + width - rx,
+        x2 = x + width,
+        y0 = y + ry,
+        y1 = y + height - ry,
+        y2 = y + height,
+        rx = rx,
+        ry = ry,
+    ))
+}
+
+fn ellipse_data(tag: &Tag) -> Option<String> {
+    let (cx, cy) = (tag.number("cx"), tag.number("cy"));
+    let (rx, ry) = match tag.attribute("r").and_then(parse_length) {
+        Some(r) => (r, r),
+        None => (tag.number("rx"), tag.number("ry")),
+    };
+    if rx <= 0.0 || ry <= 0.0 {
+        return None;
+    }
+    Some(format!(
+        "M {} {} A {rx} {ry} 0 1 1 {} {} A {rx} {ry} 0 1 1 {} {} Z",
+        cx - rx,
+        cy,
+        cx + rx,
+        cy,
+        cx - rx,
+        cy,
+        rx = rx,
+        ry = ry,
+    ))
+}
+
+/// An element's start or end tag
+struct Tag<'a> {
+    name: &'a str,
+    attributes: Vec<(&'a str, &'a str)>,
+    closing: bool,
+    self_closing: bool,
+}
+
+impl<'a> Tag<'a> {
+    fn attribute(&self, name: &str) -> Option<&'a str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| *attribute == name)
+            .map(|(_, value)| *value)
+    }
+
+    fn number(&self, name: &str) -> f32 {
+        self.attribute(name).and_then(parse_length).unwrap_or(0.0)
+    }
+
+    /// The presentation attributes, and then the declarations of the `style`, which take precedence over them
+    fn properties(&self) -> impl Iterator<Item = (&'a str, &'a str)> + '_ {
+        let style = self.attribute("style").unwrap_or("");
+        self.attributes
+            .iter()
+            .copied()
+            .filter(|(name, _)| *name != "style")
+            .chain(style.split(';').filter_map(|declaration| {
+                let (name, value) = declaration.split_once(':')?;
+                Some((name.trim(), value.trim()))
+            }))
+    }
+}
+
+/// The document's start and end tags in order, skipping its text, comments, declarations and processing
+/// instructions
+fn tags(source: &str) -> impl Iterator<Item = Result<Tag<'_>, String>> {
+    let mut rest = source;
+    std::iter::from_fn(move || loop {
+        let start = rest.find('<')?;
+        rest = &rest[start..];
+        let skip_to = |rest: &mut &str, end: &str| match rest.find(end) {
+            Some(at) => {
+                *rest = &rest[at + end.len()..];
+                Ok(())
+            }
+            None => Err(format!("Unterminated {}", &rest[..rest.len().min(16)])),
+        };
+        if rest.starts_with("<!--") {
+            if let Err(e) = skip_to(&mut rest, "-->") {
+                rest = "";
+                return Some(Err(e));
+            }
+            continue;
+        }
+        if rest.starts_with("<?") || rest.starts_with("<!") {
+            if let Err(e) = skip_to(&mut rest, ">") {
+                rest = "";
+                return Some(Err(e));
+            }
+            continue;
+        }
+        return Some(match parse_tag(rest) {
+            Ok((tag, after)) => {
+                rest = after;
+                Ok(tag)
+            }
+            Err(e) => {
+                rest = "";
+                Err(e)
+            }
+        });
+    })
+}
+
+/// The tag at the start of `source`, and what follows it
+fn parse_tag(source: &str) -> Result<(Tag<'_>, &str), String> {
+    let mut rest = &source[1..];
+    let closing = rest.starts_with('/');
+    if closing {
+        rest = &rest[1..];
+    }
+    let name_end = rest
+        .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .ok_or("Unterminated tag")?;
+    // Namespaced elements like `svg:path` are read as their local names
+    let qualified = &rest[..name_end];
+    let name = qualified.rsplit(':').next().unwrap_or(qualified);
+    rest = &rest[name_end..];
+    let mut attributes = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix("/>") {
+            let tag = Tag {
+                name,
+                attributes,
+                closing,
+                self_closing: true,
+            };
+            return Ok((tag, after));
+        }
+        if let Some(after) = rest.strip_prefix('>') {
+            let tag = Tag {
+                name,
+                attributes,
+                closing,
+                self_closing: false,
+            };
+            return Ok((tag, after));
+        }
+        let equals = rest
+            .find('=')
+            .ok_or_else(|| format!("Unterminated <{}>", name))?;
+        let attribute = rest[..equals].trim();
+        rest = rest[equals + 1..].trim_start();
+        let quote = rest
+            .chars()
+            .next()
+            .filter(|&c| c == '"' || c == '\'')
+            .ok_or_else(|| format!("Unquoted {} in <{}>", attribute, name))?;
+        let end = rest[1..]
+            .find(quote)
+            .ok_or_else(|| format!("Unterminated {} in <{}>", attribute, name))?;
+        attributes.push((attribute, &rest[1..end + 1]));
+        rest = &rest[end + 2..];
+    }
+}
+
+/// The numbers of SVG path data or a list of points, separated by whitespace and commas or by their own signs
+/// and decimal points, as in `M10-5.5.5`
+struct Numbers<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Numbers<'a> {
+    fn new(source: &'a str) -> Self {
+        Self { rest: source }
+    }
+
+    fn skip_separators(&mut self) {
+        self.rest = self
+            .rest
+            .trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+    }
+
+    /// Whether a number is next rather than a command
+    fn at_number(&mut self) -> bool {
+        self.skip_separators();
+        self.rest
+            .starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '+' || c == '.')
+    }
+
+    /// An arc's flags, which may be written without separators, as in `a1 1 0 01 1 1`
+    fn flag(&mut self) -> Result<bool, String> {
+        self.skip_separators();
+        let flag = match self.rest.chars().next() {
+            Some('0') => false,
+            Some('1') => true,
+            _ => return Err(format!("Expected an arc flag at '{}'", self.excerpt())),
+        };
+        self.rest = &self.rest[1..];
+        Ok(flag)
+    }
+
+    fn number(&mut self) -> Result<f32, String> {
+        self.next()
+            .unwrap_or_else(|| Err(String::from("Missing a number at the end of the path")))
+    }
+
+    fn point(&mut self) -> Result<Point, String> {
+        Ok(point(self.number()?, self.number()?))
+    }
+
+    fn vector(&mut self) -> Result<lyon::math::Vector, String> {
+        Ok(vector(self.number()?, self.number()?))
+    }
+
+    fn excerpt(&self) -> &str {
+        let end = self
+            .rest
+            .char_indices()
+            .nth(12)
+            .map_or(self.rest.len(), |(at, _)| at);
+        &self.rest[..end]
+    }
+}
+
+impl Iterator for Numbers<'_> {
+    type Item = Result<f32, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.skip_separators();
+        if self.rest.is_empty() {
+            return None;
+        }
+        let bytes = self.rest.as_bytes();
+        let mut end = 0;
+        if matches!(bytes.first(), Some(b'-') | Some(b'+')) {
+            end += 1;
+        }
+        let mut seen_point = false;
+        let mut seen_exponent = false;
+        while end < bytes.len() {
+            match bytes[end] {
+                b'0'..=b'9' => (),
+                b'.' if !seen_point && !seen_exponent => seen_point = true,
+                b'e' | b'E' if !seen_exponent && end > 0 => {
+                    seen_exponent = true;
+                    if matches!(bytes.get(end + 1), Some(b'-') | Some(b'+')) {
+                        end += 1;
+                    }
+                }
+                _ => break,
+            }
+            end += 1;
+        }
+        let number = &self.rest[..end];
+        match number.parse() {
+            Ok(number) => {
+                self.rest = &self.rest[end..];
+                Some(Ok(number))
+            }
+            Err(_) => {
+                let error = format!("Expected a number at '{}'", self.excerpt());
+                self.rest = "";
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+/// Builds a path from SVG path data
+fn parse_path(data: &str) -> Result<Path, String> {
+    let mut builder = Path::svg_builder();
+    let mut numbers = Numbers::new(data);
+    let mut current = None;
+    loop {
+        numbers.skip_separators();
+        // Commands repeat while numbers follow them
+        let command = match numbers.rest.chars().next() {
+            None => break,
+            Some(c) if c.is_ascii_alphabetic() => {
+                numbers.rest = &numbers.rest[c.len_utf8()..];
+                c
+            }
+            Some(_) => match current {
+                Some(command) if numbers.at_number() => command,
+                _ => return Err(format!("Expected a command at '{}'", numbers.excerpt())),
+            },
+        };
+        match command {
+            'M' => builder.move_to(numbers.point()?),
+            'm' => builder.relative_move_to(numbers.vector()?),
+            'L' => builder.line_to(numbers.point()?),
+            'l' => builder.relative_line_to(numbers.vector()?),
+            'H' => builder.horizontal_line_to(numbers.number()?),
+            'h' => builder.relative_horizontal_line_to(numbers.number()?),
+            'V' => builder.vertical_line_to(numbers.number()?),
+            'v' => builder.relative_vertical_line_to(numbers.number()?),
+            'C' => {
+                let (ctrl1, ctrl2) = (numbers.point()?, numbers.point()?);
+                builder.cubic_bezier_to(ctrl1, ctrl2, numbers.point()?);
+            }
+            'c' => {
+                let (ctrl1, ctrl2) = (numbers.vector()?, numbers.vector()?);
+                builder.relative_cubic_bezier_to(ctrl1, ctrl2, numbers.vector()?);
+            }
+            'S' => {
+                let ctrl2 = numbers.point()?;
+                builder.smooth_cubic_bezier_to(ctrl2, numbers.point()?);
+            }
+            's' => {
+                let ctrl2 = numbers.vector()?;
+                builder.smooth_relative_cubic_bezier_to(ctrl2, numbers.vector()?);
+            }
+            'Q' => {
+                let ctrl = numbers.point()?;
+                builder.quadratic_bezier_to(ctrl, numbers.point()?);
+            }
+            'q' => {
+                let ctrl = numbers.vector()?;
+                builder.relative_quadratic_bezier_to(ctrl, numbers.vector()?);
+            }
+            'T' => builder.smooth_quadratic_bezier_to(numbers.point()?),
+            't' => builder.smooth_relative_quadratic_bezier_to(numbers.vector()?),
+            'A' | 'a' => {
+                let radii = numbers.vector()?;
+                let rotation = Angle::degrees(numbers.number()?);
+                let flags = ArcFlags {
+                    large_arc: numbers.flag()?,
+                    sweep: numbers.flag()?,
+                };
+                match command {
+                    'A' => builder.arc_to(radii, rotation, flags, numbers.point()?),
+                    _ => builder.relative_arc_to(radii, rotation, flags, numbers.vector()?),
+                }
+            }
+            'Z' | 'z' => builder.close(),
+            _ => return Err(format!("Unknown path command {}", command)),
+        }
+        current = match command {
+            // A move's numbers after its first pair are lines
+            'M' => Some('L'),
+            'm' => Some('l'),
+            // Nothing follows a close but another command
+            'Z' | 'z' => None,
+            _ => Some(command),
+        };
+    }
+    Ok(builder.build())
+}
@@ -0,0 +1,295 @@
+use std::any::Any;
+
+use ash::vk;
+
+use crate::features::{RenderFeature, SwapchainContext};
+use crate::{framegraph, hooks, HelloTriangleApplication};
+
+/// Width and height in logical pixels of the square of the depth buffer read back around the light
+const SIZE: u32 = 24;
+/// Bytes read back per depth texel
+const TEXEL_SIZE: vk::DeviceSize = 4;
+
+/// A host visible buffer the square around the light is copied into by one swapchain image's command buffer.
+struct Readback {
+    buffer: vk::Buffer,
+    memory: vk::DeviceMemory,
+    /// How many texels the commands last recorded for the image copied, if they copied any
+    pending: Option<usize>,
+}
+
+/// Estimates how much of a small bright light, such as the sun, can be seen, from the depth buffer around where
+/// it is on screen. After the render pass a square of depth texels centred on the light is copied out, and the
+/// fraction of them still at the far plane, where nothing was drawn, is how visible the light is. Unlike rays
+/// cast on the CPU this sees everything drawn, including the GPU driven grid, point clouds and the volume's
+/// bounding geometry. Each swapchain image copies into its own buffer, read the next time the image's commands
+/// are recorded, so the estimate lags a few frames behind.
+#[derive(Default)]
+pub struct LightVisibility {
+    /// Where the light is in normalized device coordinates, for frames recorded from now on. None when it is
+    /// behind the camera.
+    pub position: Option<[f32; 2]>,
+    /// The last estimate read back, from 0 when the light is hidden to 1
+    visibility: f32,
+    readbacks: Vec<Readback>,
+    extent: vk::Extent2D,
+    depth_format: vk::Format,
+    /// `SIZE` in physical pixels
+    size: u32,
+}
+
+impl LightVisibility {
+    /// How much of the light could be seen a few frames ago, from 0 to 1. A light outside the frame can't be
+    /// seen.
+    pub fn visibility(&self) -> f32 {
+        self.visibility
+    }
+
+    /// The part of the frame around the light, if any of it is on screen
+    fn region(&self) -> Option<vk::Rect2D> {
+        let [x, y] = self.position?;
+        // Normalized device Y points down the frame like the depth image's rows
+        let centre = [
+            (x + 1.0) / 2.0 * self.extent.width as f32,
+            (y + 1.0) / 2.0 * self.extent.height as f32,
+        ];
+        let half = self.size as f32 / 2.0;
+        let left = (centre[0] - half).max(0.0);
+        let top = (centre[1] - half).max(0.0);
+        let right = (centre[0] + half).min(self.extent.width as f32);
+        let bottom = (centre[1] + half).min(self.extent.height as f32);
+        (right - left >= 1.0 && bottom - top >= 1.0).then_some(vk::Rect2D {
+            offset: vk::Offset2D {
+                x: left as i32,
+                y: top as i32,
+            },
+            extent: vk::Extent2D {
+                width: (right - left) as u32,
+                height: (bottom - top) as u32,
+            },
+        })
+    }
+
+    /// The fraction of the texels the image's previous commands copied that are at the far plane. Must only
+    /// be called once they have finished.
+    fn read(&mut self, device: &ash::Device, image_index: usize) -> Option<f32> {
+        let readback = &mut self.readbacks[image_index];
+        let count = readback.pending.take()?;
+
+        let mut texels = vec![0u32; count];
+        unsafe {
+            let data = device
+                .map_memory(
+                    readback.memory,
+                    0,
+                    count as vk::DeviceSize * TEXEL_SIZE,
+                    vk::MemoryMapFlags::empty(),
+                )
+                .expect("Mapping light visibility memory") as *const u32;
+            data.copy_to_nonoverlapping(texels.as_mut_ptr(), count);
+            device.unmap_memory(readback.memory);
+        }
+
+        let far = |texel: &&u32| match self.depth_format {
+            // Depth is copied out of packed depth stencil formats in the low 24 bits
+            vk::Format::D24_UNORM_S8_UINT => **texel & 0x00ff_ffff == 0x00ff_ffff,
+            _ => f32::from_bits(**texel) >= 1.0,
+        };
+        Some(texels.iter().filter(far).count() as f32 / count as f32)
+    }
+
+    /// Records copying the square around the light out of the depth buffer, which is left ready for the next
+    /// frame's depth test
+    fn record_copy(&mut self, context: &hooks::FrameContext) {
+        let region = self.region();
+        let readback = &mut self.readbacks[context.image_index];
+        readback.pending = None;
+        let region = match region {
+            Some(region) => region,
+            None => return,
+        };
+        readback.pending = Some((region.extent.width * region.extent.height) as usize);
+
+        let device = context.device;
+        let command_buffer = context.command_buffer;
+        let mut depth_aspect = vk::ImageAspectFlags::DEPTH;
+        if HelloTriangleApplication::has_stencil_component(self.depth_format) {
+            depth_aspect |= vk::ImageAspectFlags::STENCIL;
+        }
+        let barrier = |old: vk::ImageLayout,
+                       new: vk::ImageLayout,
+                       src_access: vk::AccessFlags,
+                       dst_access: vk::AccessFlags| {
+            vk::ImageMemoryBarrier::builder()
+                .old_layout(old)
+                .new_layout(new)
+                .src_access_mask(src_access)
+                .dst_access_mask(dst_access)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(context.depth_image)
+                .subresource_range(
+                    vk::ImageSubresourceRange::builder()
+                        .aspect_mask(depth_aspect)
+                        .base_mip_level(0)
+                        .level_count(1)
+                        .base_array_layer(0)
+                        .layer_count(1)
+                        .build(),
+                )
+                .build()
+        };
+        let copy = vk::BufferImageCopy::builder()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(
+                vk::ImageSubresourceLayers::builder()
+                    .aspect_mask(vk::ImageAspectFlags::DEPTH)
+                    .mip_level(0)
+                    .base_array_layer(0)
+                    .layer_count(1)
+                    .build(),
+            )
+            .image_offset(vk::Offset3D {
+                x: region.offset.x,
+                y: region.offset.y,
+                z: 0,
+            })
+            .image_extent(vk::Extent3D {
+                width: region.extent.width,
+                height: region.extent.height,
+                depth: 1,
+            })
+            .build();
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::LATE_FRAGMENT_TESTS | vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier(
+                    vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                    vk::AccessFlags::TRANSFER_READ,
+                )],
+            );
+            device.cmd_copy_image_to_buffer(
+                command_buffer,
+                context.depth_image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                readback.buffer,
+                &[copy],
+            );
+            let host_barrier = vk::BufferMemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::HOST_READ)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .buffer(readback.buffer)
+                .offset(0)
+                .size(vk::WHOLE_SIZE)
+                .build();
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[host_barrier],
+                &[],
+            );
+            // The next frame's depth test must not start until the copy has read the depth buffer
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier(
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                    vk::AccessFlags::empty(),
+                    vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                )],
+            );
+        }
+    }
+}
+
+impl RenderFeature for LightVisibility {
+    fn name(&self) -> &str {
+        "light visibility"
+    }
+
+    fn init(&mut self, context: &SwapchainContext) {
+        self.size = context.scaled(SIZE);
+        let buffer_size = (self.size * self.size) as vk::DeviceSize * TEXEL_SIZE;
+        self.readbacks = (0..context.image_count)
+            .map(|_| {
+                let (buffer, memory) = HelloTriangleApplication::create_buffer(
+                    context.device,
+                    buffer_size,
+                    vk::BufferUsageFlags::TRANSFER_DST,
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                    context.device_memory_properties,
+                );
+                Readback {
+                    buffer,
+                    memory,
+                    pending: None,
+                }
+            })
+            .collect();
+        self.extent = context.extent;
+        self.depth_format = context.depth_format;
+    }
+
+    fn resize(&mut self, context: &SwapchainContext) {
+        self.destroy(context.device);
+        self.init(context);
+    }
+
+    fn prepare(&mut self, context: &SwapchainContext, image_index: usize) {
+        // A frame that copied nothing had the light out of view
+        self.visibility = self.read(context.device, image_index).unwrap_or(0.0);
+    }
+
+    fn record(&mut self, context: &hooks::FrameContext) {
+        if context.stage == hooks::Stage::BeforePost {
+            self.record_copy(context);
+        }
+    }
+
+    fn passes(&self) -> Vec<framegraph::Pass> {
+        if self.region().is_none() {
+            return Vec::new();
+        }
+        vec![framegraph::Pass::new(
+            self.name(),
+            framegraph::Position::Stage(hooks::Stage::BeforePost),
+            &[framegraph::DEPTH],
+            &["light visibility readback"],
+        )]
+    }
+
+    fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            for readback in self.readbacks.drain(..) {
+                device.destroy_buffer(readback.buffer, None);
+                device.free_memory(readback.memory, None);
+            }
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
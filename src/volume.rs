@@ -0,0 +1,993 @@
+use std::any::Any;
+use std::fs;
+use std::mem::{size_of, size_of_val};
+use std::path::Path;
+
+use ash::vk;
+use cgmath::{Matrix4, Point3, SquareMatrix, Vector3};
+
+use crate::features::{RenderFeature, SwapchainContext};
+use crate::{
+    begin_single_time_commands, bounds, end_single_time_commands, framegraph, hooks, pipeline,
+    playground, units, util, HelloTriangleApplication,
+};
+
+/// Entries in the baked transfer function. Matches the size of `transfer` in `volume_frag.glsl`.
+const TRANSFER_SIZE: usize = 256;
+/// Matches the size of `planes` in `volume_frag.glsl`
+pub const MAX_PLANES: usize = 4;
+/// Linear filtering of 3D textures is supported for it on every device, unlike the 16 bit UNORM formats
+const FORMAT: vk::Format = vk::Format::R16_SFLOAT;
+
+/// How each sample of a raw volume is stored
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl SampleType {
+    /// Accepts the names NRRD headers use for each type, and their shorter Rust names
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "i8" | "int8" | "int8_t" | "char" | "signed char" => Some(SampleType::I8),
+            "u8" | "uint8" | "uint8_t" | "uchar" | "unsigned char" => Some(SampleType::U8),
+            "i16" | "int16" | "int16_t" | "short" | "short int" | "signed short"
+            | "signed short int" => Some(SampleType::I16),
+            "u16" | "uint16" | "uint16_t" | "ushort" | "unsigned short" | "unsigned short int" => {
+                Some(SampleType::U16)
+            }
+            "i32" | "int32" | "int32_t" | "int" | "signed int" => Some(SampleType::I32),
+            "u32" | "uint32" | "uint32_t" | "uint" | "unsigned int" => Some(SampleType::U32),
+            "f32" | "float" => Some(SampleType::F32),
+            "f64" | "double" => Some(SampleType::F64),
+            _ => None,
+        }
+    }
+
+    /// Bytes per sample
+    pub fn size(&self) -> usize {
+        match self {
+            SampleType::I8 | SampleType::U8 => 1,
+            SampleType::I16 | SampleType::U16 => 2,
+            SampleType::I32 | SampleType::U32 | SampleType::F32 => 4,
+            SampleType::F64 => 8,
+        }
+    }
+
+    fn decode(&self, bytes: &[u8], little_endian: bool) -> f32 {
+        let mut a = [0; 8];
+        a[..bytes.len()].copy_from_slice(bytes);
+        if !little_endian {
+            a[..bytes.len()].reverse();
+        }
+        match self {
+            SampleType::I8 => a[0] as i8 as f32,
+            SampleType::U8 => a[0] as f32,
+            SampleType::I16 => i16::from_le_bytes([a[0], a[1]]) as f32,
+            SampleType::U16 => u16::from_le_bytes([a[0], a[1]]) as f32,
+            SampleType::I32 => i32::from_le_bytes([a[0], a[1], a[2], a[3]]) as f32,
+            SampleType::U32 => u32::from_le_bytes([a[0], a[1], a[2], a[3]]) as f32,
+            SampleType::F32 => f32::from_le_bytes([a[0], a[1], a[2], a[3]]),
+            SampleType::F64 => f64::from_le_bytes(a) as f32,
+        }
+    }
+}
+
+/// A 3D grid of scalar samples, such as a CT scan or a simulation's density field
+#[derive(Clone, Debug)]
+pub struct Dataset {
+    /// Samples along x, y and z
+    pub size: [u32; 3],
+    /// Distance between neighbouring samples along each axis, in whatever units the data was recorded in
+    pub spacing: [f32; 3],
+    /// x varies fastest, then y, then z
+    pub values: Vec<f32>,
+    /// Which axis is up in the data, taken to be Z, as it is in the patient spaces of medical scans
+    pub up: units::Up,
+}
+
+impl Dataset {
+    /// Reads a headerless volume of little-endian samples, x varying fastest
+    pub fn load_raw(path: &Path, size: [u32; 3], sample_type: SampleType) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("Reading {}: {}", path.display(), e))?;
+        Self::decode(&bytes, size, [1.0; 3], sample_type, true)
+    }
+
+    /// Reads a 3D NRRD volume, with its data either after the header or in the detached file the header names.
+    /// Only raw and ASCII encodings are supported.
+    pub fn load_nrrd(path: &Path) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("Reading {}: {}", path.display(), e))?;
+        if !bytes.starts_with(b"NRRD") {
+            return Err(format!("{} isn't a NRRD file", path.display()));
+        }
+        // The header ends at the first blank line
+        let header_end = bytes
+            .windows(2)
+            .position(|pair| pair == b"\n\n")
+            .map(|position| (position + 1, position + 2))
+            .or_else(|| {
+                bytes
+                    .windows(4)
+                    .position(|quad| quad == b"\r\n\r\n")
+                    .map(|position| (position + 2, position + 4))
+            });
+        let (header_length, data_start) = match header_end {
+            Some(header_end) => header_end,
+            // A detached header doesn't need the blank line
+            None => (bytes.len(), bytes.len()),
+        };
+        let header = String::from_utf8_lossy(&bytes[..header_length]);
+
+        let mut sample_type = None;
+        let mut size = None;
+        let mut spacing = [1.0; 3];
+        let mut encoding = String::from("raw");
+        let mut little_endian = true;
+        let mut data_file = None;
+        let mut byte_skip: i64 = 0;
+        for line in header.lines().skip(1) {
+            if line.starts_with('#') {
+                continue;
+            }
+            let (field, value) = match line.split_once(':') {
+                // Key/value pairs use `:=` and are ignored
+                Some((field, value)) if !value.starts_with('=') => (field.trim(), value.trim()),
+                _ => continue,
+            };
+            match field {
+                "type" => {
+                    sample_type = Some(
+                        SampleType::parse(value)
+                            .ok_or_else(|| format!("Unsupported NRRD type {}", value))?,
+                    )
+                }
+                "dimension" if value != "3" => {
+                    return Err(format!("Only 3D volumes are supported, not {}D", value))
+                }
+                "sizes" => {
+                    let sizes = value
+                        .split_whitespace()
+                        .map(|size| size.parse::<u32>())
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|e| format!("NRRD sizes {}: {}", value, e))?;
+                    if sizes.len() != 3 {
+                        return Err(format!("Expected 3 NRRD sizes, got {}", value));
+                    }
+                    size = Some([sizes[0], sizes[1], sizes[2]]);
+                }
+                "spacings" => {
+                    for (spacing, value) in spacing.iter_mut().zip(value.split_whitespace()) {
+                        *spacing = value.parse().unwrap_or(1.0);
+                    }
+                }
+                // Each axis's spacing is the length of its direction vector, the directions themselves are
+                // assumed to be the axes
+                "space directions" => {
+                    let directions = value
+                        .split(')')
+                        .filter(|direction| direction.contains('('))
+                        .map(|direction| {
+                            direction
+                                .trim()
+                                .trim_start_matches('(')
+                                .split(',')
+                                .filter_map(|component| component.trim().parse::<f32>().ok())
+                                .map(|component| component * component)
+                                .sum::<f32>()
+                                .sqrt()
+                        });
+                    for (spacing, length) in spacing.iter_mut().zip(directions) {
+                        if length > 0.0 {
+                            *spacing = length;
+                        }
+                    }
+                }
+                "encoding" => encoding = value.to_string(),
+                "endian" => little_endian = value == "little",
+                "data file" | "datafile" => data_file = Some(value.to_string()),
+                "byte skip" | "byteskip" => {
+                    byte_skip = value
+                        .parse()
+                        .map_err(|e| format!("NRRD byte skip {}: {}", value, e))?
+                }
+                _ => {}
+            }
+        }
+        let sample_type = sample_type.ok_or("The NRRD header has no type")?;
+        let size = size.ok_or("The NRRD header has no sizes")?;
+
+        let detached;
+        let data = match data_file {
+            Some(data_file) => {
+                let data_path = path.with_file_name(&data_file);
+                detached = fs::read(&data_path)
+                    .map_err(|e| format!("Reading {}: {}", data_path.display(), e))?;
+                &detached[..]
+            }
+            None => &bytes[data_start..],
+        };
+
+        match encoding.as_str() {
+            "raw" => {
+                let expected = size.iter().product::<u32>() as usize * sample_type.size();
+                // A byte skip of -1 means the data is at the end of the file
+                let data = if byte_skip < 0 {
+                    &data[data.len().saturating_sub(expected)..]
+                } else {
+                    data.get(byte_skip as usize..).unwrap_or(&[])
+                };
+                Self::decode(data, size, spacing, sample_type, little_endian)
+            }
+            "ascii" | "text" | "txt" => {
+                let values = String::from_utf8_lossy(data)
+                    .split_whitespace()
+                    .map(|value| value.parse::<f32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| format!("NRRD ASCII data: {}", e))?;
+                Self::new(size, spacing, values)
+            }
+            _ => Err(format!(
+                "NRRD {} encoding isn't supported, resave it with raw encoding",
+                encoding
+            )),
+        }
+    }
+
+    /// Fails if there aren't exactly as many values as the size has samples
+    pub fn new(size: [u32; 3], spacing: [f32; 3], values: Vec<f32>) -> Result<Self, String> {
+        let count = size.iter().map(|&size| size as usize).product::<usize>();
+        if count == 0 {
+            return Err("Volumes need at least one sample".to_string());
+        }
+        if values.len() != count {
+            return Err(format!(
+                "A {}x{}x{} volume needs {} samples, not {}",
+                size[0],
+                size[1],
+                size[2],
+                count,
+                values.len()
+            ));
+        }
+        Ok(Self {
+            size,
+            spacing,
+            values,
+            up: units::Up::Z,
+        })
+    }
+
+    fn decode(
+        bytes: &[u8],
+        size: [u32; 3],
+        spacing: [f32; 3],
+        sample_type: SampleType,
+        little_endian: bool,
+    ) -> Result<Self, String> {
+        let count = size.iter().map(|&size| size as usize).product::<usize>();
+        let expected = count * sample_type.size();
+        if bytes.len() < expected {
+            return Err(format!(
+                "A {}x{}x{} volume of {:?} needs {} bytes, only {} were found",
+                size[0],
+                size[1],
+                size[2],
+                sample_type,
+                expected,
+                bytes.len()
+            ));
+        }
+        let values = bytes[..expected]
+            .chunks_exact(sample_type.size())
+            .map(|sample| sample_type.decode(sample, little_endian))
+            .collect();
+        Self::new(size, spacing, values)
+    }
+
+    /// The smallest and largest values
+    pub fn range(&self) -> (f32, f32) {
+        self.values
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(min, max), &value| {
+                (min.min(value), max.max(value))
+            })
+    }
+
+    /// Maps the unit cube the volume is sampled over to a box centred on the origin, with its longest side one
+    /// unit long and its sides in proportion to the dataset's physical size
+    pub fn default_transform(&self) -> Matrix4<f32> {
+        let lengths: Vec<f32> = self
+            .size
+            .iter()
+            .zip(self.spacing.iter())
+            .map(|(&size, &spacing)| size as f32 * spacing.abs())
+            .collect();
+        let longest = lengths.iter().cloned().fold(f32::MIN_POSITIVE, f32::max);
+        let scale = Vector3::new(lengths[0], lengths[1], lengths[2]) / longest;
+        Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z)
+            * Matrix4::from_translation(Vector3::new(-0.5, -0.5, -0.5))
+    }
+}
+
+/// Maps the volume's normalised values to colour and opacity. Opacity is how much of the light a sample absorbs
+/// over the distance between neighbouring samples of the dataset. Marching with smaller steps corrects for it.
+#[derive(Clone, Debug)]
+pub struct TransferFunction {
+    /// Values from 0 to 1 and their colour and opacity, in order. Colours are linear.
+    points: Vec<(f32, [f32; 4])>,
+}
+
+impl TransferFunction {
+    /// Interpolates linearly between the points, which are sorted by their value. Values outside of the points
+    /// take the colour of the nearest one.
+    pub fn new(mut points: Vec<(f32, [f32; 4])>) -> Self {
+        points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        Self { points }
+    }
+
+    /// Accepts grayscale, fire and bone
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "grayscale" => Some(Self::grayscale()),
+            // Faint red wisps through to bright yellow in the densest parts, for simulations
+            "fire" => Some(Self::new(vec![
+                (0.0, [0.0, 0.0, 0.0, 0.0]),
+                (0.3, [0.5, 0.0, 0.0, 0.02]),
+                (0.6, [1.0, 0.4, 0.0, 0.15]),
+                (1.0, [1.0, 1.0, 0.7, 0.6]),
+            ])),
+            // Hides air and fades soft tissue so bone stands out, for CT scans
+            "bone" => Some(Self::new(vec![
+                (0.0, [0.0, 0.0, 0.0, 0.0]),
+                (0.25, [0.0, 0.0, 0.0, 0.0]),
+                (0.35, [0.8, 0.3, 0.2, 0.01]),
+                (0.6, [0.9, 0.85, 0.7, 0.3]),
+                (1.0, [1.0, 1.0, 1.0, 0.9]),
+            ])),
+            _ => None,
+        }
+    }
+
+    /// Transparent black through to opaque white
+    pub fn grayscale() -> Self {
+        Self::new(vec![
+            (0.0, [0.0, 0.0, 0.0, 0.0]),
+            (1.0, [1.0, 1.0, 1.0, 1.0]),
+        ])
+    }
+
+    fn sample(&self, value: f32) -> [f32; 4] {
+        let first = match self.points.first() {
+            Some(first) => first,
+            None => return [0.0; 4],
+        };
+        let next = match self.points.iter().position(|&(point, _)| point > value) {
+            Some(0) => return first.1,
+            Some(next) => next,
+            None => return self.points[self.points.len() - 1].1,
+        };
+        let (from, from_color) = self.points[next - 1];
+        let (to, to_color) = self.points[next];
+        let t = (value - from) / (to - from).max(1e-6);
+        let mut color = [0.0; 4];
+        for ((color, from), to) in color.iter_mut().zip(from_color).zip(to_color) {
+            *color = from + (to - from) * t;
+        }
+        color
+    }
+
+    fn bake(&self) -> [[f32; 4]; TRANSFER_SIZE] {
+        let mut table = [[0.0; 4]; TRANSFER_SIZE];
+        for (i, entry) in table.iter_mut().enumerate() {
+            *entry = self.sample(i as f32 / (TRANSFER_SIZE - 1) as f32);
+        }
+        table
+    }
+}
+
+/// Cuts away the part of the volume behind it
+#[derive(Clone, Copy, Debug)]
+pub struct Plane {
+    /// In the volume's own space, where it fills the unit cube
+    pub normal: Vector3<f32>,
+    /// Points whose dot product with the normal is less than this are cut away
+    pub distance: f32,
+}
+
+/// Matches `Volume` in `volume_frag.glsl`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Uniforms {
+    inverse_view_projection: [[f32; 4]; 4],
+    world_to_volume: [[f32; 4]; 4],
+    viewport: [f32; 4],
+    sampling: [f32; 4],
+    planes: [[f32; 4]; MAX_PLANES],
+    flags: [u32; 4],
+    transfer: [[f32; 4]; TRANSFER_SIZE],
+}
+
+/// One uniform buffer for each swapchain image, since frames in flight each write their own
+struct Frames {
+    uniform_buffers: Vec<(vk::Buffer, vk::DeviceMemory)>,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+}
+
+/// A dataset rendered by marching rays through it in a full-screen pass, blended over the opaque scene. Each
+/// sample is coloured by the transfer function and composited front to back, stopping once the ray is almost
+/// opaque. The volume is drawn over everything, it isn't hidden by the scene's geometry in front of it.
+pub struct Volume {
+    /// Places the unit cube the volume is sampled over in the world
+    pub transform: Matrix4<f32>,
+    pub transfer_function: TransferFunction,
+    /// The values mapped to the ends of the transfer function, where the dataset's smallest value is 0 and its
+    /// largest 1. Values outside are clamped.
+    pub window: [f32; 2],
+    /// Samples marched per voxel along each ray
+    pub samples_per_voxel: f32,
+    /// Only the first `MAX_PLANES` are used
+    pub planes: Vec<Plane>,
+    pub enabled: bool,
+    size: [u32; 3],
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    view: vk::ImageView,
+    sampler: vk::Sampler,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    layout: vk::PipelineLayout,
+    /// Created when first recorded after the swapchain has been (re)created, with the frame's render pass
+    pipeline: Option<vk::Pipeline>,
+    frames: Option<Frames>,
+    /// Which axis `cycle_slice` last cut along
+    slice: Option<usize>,
+}
+
+impl Volume {
+    /// Uploads the dataset as a 3D texture, waiting for the GPU to finish
+    pub fn new(
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        dataset: &Dataset,
+        transfer_function: TransferFunction,
+    ) -> Self {
+        let range = dataset.range();
+        let scale = 1.0 / (range.1 - range.0).max(f32::MIN_POSITIVE);
+        let texels: Vec<u16> = dataset
+            .values
+            .iter()
+            .map(|&value| to_half((value - range.0) * scale))
+            .collect();
+        let extent = vk::Extent3D {
+            width: dataset.size[0],
+            height: dataset.size[1],
+            depth: dataset.size[2],
+        };
+        let (image, memory) = upload(
+            device,
+            command_pool,
+            queue,
+            device_memory_properties,
+            extent,
+            &texels,
+        );
+        let view = unsafe {
+            device
+                .create_image_view(
+                    &vk::ImageViewCreateInfo::builder()
+                        .image(image)
+                        .view_type(vk::ImageViewType::TYPE_3D)
+                        .format(FORMAT)
+                        .subresource_range(vk::ImageSubresourceRange {
+                            aspect_mask: vk::ImageAspectFlags::COLOR,
+                            base_mip_level: 0,
+                            level_count: 1,
+                            base_array_layer: 0,
+                            layer_count: 1,
+                        }),
+                    None,
+                )
+                .expect("Volume image view")
+        };
+        let sampler = unsafe {
+            device
+                .create_sampler(
+                    &vk::SamplerCreateInfo::builder()
+                        .mag_filter(vk::Filter::LINEAR)
+                        .min_filter(vk::Filter::LINEAR)
+                        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+                    None,
+                )
+                .expect("Volume sampler")
+        };
+
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_count(1)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build(),
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(1)
+                .descriptor_count(1)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build(),
+        ];
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+                    None,
+                )
+                .expect("Volume descriptor set layout")
+        };
+        let set_layouts = [descriptor_set_layout];
+        let layout = unsafe {
+            device
+                .create_pipeline_layout(
+                    &vk::PipelineLayoutCreateInfo::builder().set_layouts(&set_layouts),
+                    None,
+                )
+                .expect("Volume pipeline layout")
+        };
+
+        Self {
+            transform: dataset.default_transform(),
+            transfer_function,
+            window: [0.0, 1.0],
+            samples_per_voxel: 2.0,
+            planes: Vec::new(),
+            enabled: true,
+            size: dataset.size,
+            image,
+            memory,
+            view,
+            sampler,
+            descriptor_set_layout,
+            layout,
+            pipeline: None,
+            frames: None,
+            slice: None,
+        }
+    }
+
+    /// Replaces the slicing planes with one cutting the volume in half along the next axis, or with none after
+    /// the z axis. Returns the axis cut along.
+    pub fn cycle_slice(&mut self) -> Option<usize> {
+        self.slice = match self.slice {
+            None => Some(0),
+            Some(axis) if axis < 2 => Some(axis + 1),
+            Some(_) => None,
+        };
+        self.planes.clear();
+        if let Some(axis) = self.slice {
+            let mut normal = Vector3::new(0.0, 0.0, 0.0);
+            normal[axis] = 1.0;
+            self.planes.push(Plane {
+                normal,
+                distance: 0.5,
+            });
+        }
+        self.slice
+    }
+
+    fn create_frames(&mut self, context: &SwapchainContext) {
+        let device = context.device;
+        let uniform_buffers: Vec<(vk::Buffer, vk::DeviceMemory)> = (0..context.image_count)
+            .map(|_| {
+                HelloTriangleApplication::create_buffer(
+                    device,
+                    size_of::<Uniforms>() as vk::DeviceSize,
+                    vk::BufferUsageFlags::UNIFORM_BUFFER,
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                    context.device_memory_properties,
+                )
+            })
+            .collect();
+
+        let count = context.image_count as u32;
+        let pool_sizes = [
+            vk::DescriptorPoolSize::builder()
+                .ty(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(count)
+                .build(),
+            vk::DescriptorPoolSize::builder()
+                .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(count)
+                .build(),
+        ];
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(
+                    &vk::DescriptorPoolCreateInfo::builder()
+                        .pool_sizes(&pool_sizes)
+                        .max_sets(count),
+                    None,
+                )
+                .expect("Volume descriptor pool")
+        };
+        let set_layouts = vec![self.descriptor_set_layout; context.image_count];
+        let descriptor_sets = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::builder()
+                        .descriptor_pool(descriptor_pool)
+                        .set_layouts(&set_layouts),
+                )
+                .expect("Volume descriptor sets")
+        };
+        let image_info = [vk::DescriptorImageInfo::builder()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(self.view)
+            .sampler(self.sampler)
+            .build()];
+        for (&set, &(uniform_buffer, _)) in descriptor_sets.iter().zip(uniform_buffers.iter()) {
+            let buffer_info = [vk::DescriptorBufferInfo::builder()
+                .buffer(uniform_buffer)
+                .offset(0)
+                .range(vk::WHOLE_SIZE)
+                .build()];
+            let writes = [
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(set)
+                    .dst_binding(0)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(&buffer_info)
+                    .build(),
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(set)
+                    .dst_binding(1)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&image_info)
+                    .build(),
+            ];
+            unsafe { device.update_descriptor_sets(&writes, &[]) };
+        }
+
+        self.frames = Some(Frames {
+            uniform_buffers,
+            descriptor_pool,
+            descriptor_sets,
+        });
+    }
+
+    fn destroy_frames(&mut self, device: &ash::Device) {
+        unsafe {
+            if let Some(pipeline) = self.pipeline.take() {
+                device.destroy_pipeline(pipeline, None);
+            }
+            if let Some(frames) = self.frames.take() {
+                device.destroy_descriptor_pool(frames.descriptor_pool, None);
+                for (buffer, memory) in frames.uniform_buffers {
+                    device.destroy_buffer(buffer, None);
+                    device.free_memory(memory, None);
+                }
+            }
+        }
+    }
+
+    fn uniforms(&self, context: &hooks::FrameContext) -> Uniforms {
+        let inverse_view_projection = (context.projection * context.view)
+            .invert()
+            .expect("view projection is invertible");
+        let world_to_volume = self
+            .transform
+            .invert()
+            .expect("volume transform is invertible");
+        // Opacities are for the distance between samples along the longest axis, in the volume's own space
+        let voxel = 1.0 / *self.size.iter().max().expect("volumes have three axes") as f32;
+        let mut planes = [[0.0; 4]; MAX_PLANES];
+        for (plane, slice) in planes.iter_mut().zip(self.planes.iter()) {
+            *plane = [
+                slice.normal.x,
+                slice.normal.y,
+                slice.normal.z,
+                slice.distance,
+            ];
+        }
+        let extent = context.target.extent;
+        Uniforms {
+            inverse_view_projection: inverse_view_projection.into(),
+            world_to_volume: world_to_volume.into(),
+            viewport: [extent.width as f32, extent.height as f32, 0.0, 0.0],
+            sampling: [
+                voxel / self.samples_per_voxel.max(0.1),
+                voxel,
+                self.window[0],
+                self.window[1],
+            ],
+            planes,
+            flags: [
+                self.planes.len().min(MAX_PLANES) as u32,
+                context.target.encode_srgb as u32,
+                0,
+                0,
+            ],
+            transfer: self.transfer_function.bake(),
+        }
+    }
+}
+
+impl RenderFeature for Volume {
+    fn name(&self) -> &str {
+        "volume"
+    }
+
+    fn init(&mut self, context: &SwapchainContext) {
+        self.create_frames(context);
+    }
+
+    fn resize(&mut self, context: &SwapchainContext) {
+        self.destroy_frames(context.device);
+        self.create_frames(context);
+    }
+
+    fn record(&mut self, context: &hooks::FrameContext) {
+        if context.stage != hooks::Stage::AfterOpaque || !self.enabled {
+            return;
+        }
+        let uniforms = self.uniforms(context);
+        let device = context.device;
+        let layout = self.layout;
+        // Pixels where the scene is nearer than the whole volume can't see any of it
+        let depth_bounds = context.limits.depth_bounds();
+        let pipeline = *self.pipeline.get_or_insert_with(|| {
+            let path = Path::new(env!("OUT_DIR")).join("volume_frag.spv");
+            playground::create_pipeline(
+                device,
+                &context.target,
+                layout,
+                &util::read_shader_code(&path),
+                true,
+                depth_bounds,
+            )
+        });
+        let frames = match self.frames.as_ref() {
+            Some(frames) => frames,
+            None => return,
+        };
+        let memory = frames.uniform_buffers[context.image_index].1;
+        unsafe {
+            let mapped = device
+                .map_memory(
+                    memory,
+                    0,
+                    size_of::<Uniforms>() as vk::DeviceSize,
+                    vk::MemoryMapFlags::empty(),
+                )
+                .expect("Mapping volume uniform buffer") as *mut Uniforms;
+            mapped.copy_from_nonoverlapping(&uniforms, 1);
+            device.unmap_memory(memory);
+        }
+
+        let command_buffer = context.command_buffer;
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            if depth_bounds {
+                let cube = bounds::Aabb {
+                    min: Point3::new(0.0, 0.0, 0.0),
+                    max: Point3::new(1.0, 1.0, 1.0),
+                };
+                let covered = pipeline::DepthBounds::of(
+                    &cube,
+                    &(context.projection * context.view * self.transform),
+                );
+                device.cmd_set_depth_bounds(command_buffer, covered.min, 1.0);
+            }
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                layout,
+                0,
+                &[frames.descriptor_sets[context.image_index]],
+                &[],
+            );
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+
+            // Left as it was found for the features and hooks recorded after this
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                context.pipeline_layout,
+                0,
+                &[context.descriptor_set],
+                &[],
+            );
+        }
+    }
+
+    fn passes(&self) -> Vec<framegraph::Pass> {
+        if !self.enabled {
+            return Vec::new();
+        }
+        vec![framegraph::Pass::new(
+            "volume",
+            framegraph::Position::Stage(hooks::Stage::AfterOpaque),
+            &["volume texture"],
+            &[framegraph::SWAPCHAIN],
+        )]
+    }
+
+    fn destroy(&mut self, device: &ash::Device) {
+        self.destroy_frames(device);
+        unsafe {
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            device.destroy_sampler(self.sampler, None);
+            device.destroy_image_view(self.view, None);
+            device.destroy_image(self.image, None);
+            device.free_memory(self.memory, None);
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Converts a value from 0 to 1 to a half float, rounding to the nearest. Values too small for a normal half
+/// float become zero.
+fn to_half(value: f32) -> u16 {
+    let bits = value.max(0.0).to_bits();
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    if exponent <= 0 {
+        return 0;
+    }
+    let mantissa = bits & 0x7f_ffff;
+    // Rounding up can carry into the exponent, which is still the nearest half float
+    (((exponent as u32) << 10 | mantissa >> 13) + ((mantissa >> 12) & 1)) as u16
+}
+
+/// Creates a 3D image of `FORMAT` texels and copies them into it through a staging buffer, leaving it ready to
+/// be sampled by fragment shaders
+fn upload(
+    device: &ash::Device,
+    command_pool: vk::CommandPool,
+    queue: vk::Queue,
+    device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    extent: vk::Extent3D,
+    texels: &[u16],
+) -> (vk::Image, vk::DeviceMemory) {
+    let size = size_of_val(texels) as vk::DeviceSize;
+    let (staging_buffer, staging_memory) = HelloTriangleApplication::create_buffer(
+        device,
+        size,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        device_memory_properties,
+    );
+    unsafe {
+        let mapped = device
+            .map_memory(staging_memory, 0, size, vk::MemoryMapFlags::empty())
+            .expect("Mapping volume staging buffer") as *mut u16;
+        mapped.copy_from_nonoverlapping(texels.as_ptr(), texels.len());
+        device.unmap_memory(staging_memory);
+    }
+
+    let image = unsafe {
+        device
+            .create_image(
+                &vk::ImageCreateInfo::builder()
+                    .image_type(vk::ImageType::TYPE_3D)
+                    .extent(extent)
+                    .mip_levels(1)
+                    .array_layers(1)
+                    .format(FORMAT)
+                    .tiling(vk::ImageTiling::OPTIMAL)
+                    .initial_layout(vk::ImageLayout::UNDEFINED)
+                    .usage(vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED)
+                    .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                    .samples(vk::SampleCountFlags::TYPE_1),
+                None,
+            )
+            .expect("Volume image")
+    };
+    let requirements = unsafe { device.get_image_memory_requirements(image) };
+    let memory = unsafe {
+        let memory = device
+            .allocate_memory(
+                &vk::MemoryAllocateInfo::builder()
+                    .allocation_size(requirements.size)
+                    .memory_type_index(HelloTriangleApplication::find_memory_type(
+                        requirements.memory_type_bits,
+                        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                        device_memory_properties,
+                    )),
+                None,
+            )
+            .expect("Volume image memory");
+        device
+            .bind_image_memory(image, memory, 0)
+            .expect("Binding volume image memory");
+        memory
+    };
+
+    let image_barrier = |old, new, src_access, dst_access| {
+        vk::ImageMemoryBarrier::builder()
+            .old_layout(old)
+            .new_layout(new)
+            .src_access_mask(src_access)
+            .dst_access_mask(dst_access)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .build()
+    };
+    let region = vk::BufferImageCopy::builder()
+        .image_subresource(vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        })
+        .image_extent(extent)
+        .build();
+
+    let command_buffer = begin_single_time_commands(device, command_pool);
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[image_barrier(
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::AccessFlags::empty(),
+                vk::AccessFlags::TRANSFER_WRITE,
+            )],
+        );
+        device.cmd_copy_buffer_to_image(
+            command_buffer,
+            staging_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[region],
+        );
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[image_barrier(
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::SHADER_READ,
+            )],
+        );
+    }
+    end_single_time_commands(device, command_pool, command_buffer, queue);
+
+    unsafe {
+        device.destroy_buffer(staging_buffer, None);
+        device.free_memory(staging_memory, None);
+    }
+    (image, memory)
+}